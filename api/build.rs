@@ -0,0 +1,147 @@
+use std::{
+    fmt::Write as _,
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Libraries whose versions are reported by the `/version` endpoint.
+const REPORTED_LIBRARIES: &[&str] = &[
+    "image",
+    "libavif",
+    "libavif-sys",
+    "libheif-rs",
+    "libheif-sys",
+    "libwebp-sys",
+    "ravif",
+    "webp",
+];
+
+fn git(workspace_dir: &Path, args: &[&str]) -> Option<String> {
+    Command::new("git")
+        .args(args)
+        .current_dir(workspace_dir)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|output| output.trim().to_string())
+}
+
+fn git_sha(workspace_dir: &Path) -> String {
+    git(workspace_dir, &["rev-parse", "HEAD"]).unwrap_or_else(|| "unknown".to_string())
+}
+
+/// The time of the build, or `SOURCE_DATE_EPOCH` when it is set so that builds are reproducible.
+fn build_timestamp() -> i64 {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or_default()
+        })
+}
+
+/// The time of the HEAD commit.
+fn commit_timestamp(workspace_dir: &Path) -> Option<i64> {
+    git(workspace_dir, &["log", "-1", "--format=%ct", "HEAD"])?
+        .parse()
+        .ok()
+}
+
+/// Read the resolved versions of the reported libraries out of the lockfile.
+fn library_versions(workspace_dir: &Path) -> Vec<(String, String)> {
+    let lockfile = std::fs::read_to_string(workspace_dir.join("Cargo.lock")).unwrap_or_default();
+
+    let mut versions = Vec::new();
+    let mut name = None;
+    for line in lockfile.lines() {
+        if line == "[[package]]" {
+            name = None;
+        } else if let Some(value) = line.strip_prefix("name = ") {
+            name = Some(value.trim_matches('"').to_string());
+        } else if let Some(value) = line.strip_prefix("version = ") {
+            if let Some(name) = name.take() {
+                if REPORTED_LIBRARIES.contains(&name.as_str()) {
+                    versions.push((name, value.trim_matches('"').to_string()));
+                }
+            }
+        }
+    }
+
+    versions.sort();
+    versions
+}
+
+fn enabled_features() -> Vec<String> {
+    let mut features = std::env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .collect::<Vec<_>>();
+    features.sort();
+    features
+}
+
+fn main() {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let workspace_dir = Path::new(&manifest_dir).parent().unwrap();
+
+    let mut output = String::new();
+    writeln!(
+        output,
+        "pub const GIT_SHA: &str = {:?};",
+        git_sha(workspace_dir)
+    )
+    .unwrap();
+    writeln!(
+        output,
+        "pub const BUILD_TIMESTAMP: i64 = {};",
+        build_timestamp()
+    )
+    .unwrap();
+    writeln!(
+        output,
+        "pub const COMMIT_TIMESTAMP: Option<i64> = {:?};",
+        commit_timestamp(workspace_dir)
+    )
+    .unwrap();
+    writeln!(
+        output,
+        "pub const FEATURES: &[&str] = &{:?};",
+        enabled_features()
+    )
+    .unwrap();
+    writeln!(
+        output,
+        "pub const LIBRARY_VERSIONS: &[(&str, &str)] = &{:?};",
+        library_versions(workspace_dir)
+    )
+    .unwrap();
+
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    std::fs::write(Path::new(&out_dir).join("build_info.rs"), output).unwrap();
+
+    // Rerun on any change to the crate's sources as well as to the git state, so that the build
+    // time is refreshed whenever the crate is rebuilt. Changes to the enabled features rerun the
+    // script on their own.
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let git_dir = workspace_dir.join(".git");
+    println!("cargo:rerun-if-changed={}", git_dir.join("HEAD").display());
+    println!("cargo:rerun-if-changed={}", git_dir.join("refs").display());
+    println!(
+        "cargo:rerun-if-changed={}",
+        git_dir.join("packed-refs").display()
+    );
+    println!(
+        "cargo:rerun-if-changed={}",
+        workspace_dir.join("Cargo.lock").display()
+    );
+}
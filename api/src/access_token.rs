@@ -0,0 +1,99 @@
+//! Access tokens for private projects. Images in a private project can not be fetched from the
+//! serve route directly, so that a CDN can't hand them out to anyone. Instead the request must
+//! carry a token created for the project, or a signed URL.
+
+use std::{fmt::Display, str::FromStr};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use base64::Engine;
+use pic_store_db::object_id::ProjectAccessTokenId;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::Error;
+
+pub const ACCESS_TOKEN_HEADER: &str = "x-access-token";
+
+pub type Hash = blake3::Hash;
+
+/// Create the secret for a new token. The returned hash is the only part that should be saved.
+/// Share links use the same format, with their own ID.
+pub fn generate(id: impl Display) -> (String, Hash) {
+    let random = Uuid::new_v4();
+    let random = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(random.as_bytes());
+    let token = format!("{id}.{random}");
+    let hash = blake3::hash(token.as_bytes());
+    (token, hash)
+}
+
+/// Split a token into its ID and the hash to look up.
+pub fn decode(token: &str) -> Result<(ProjectAccessTokenId, Hash), Error> {
+    split(token).ok_or(Error::InvalidAccessToken)
+}
+
+/// Split a token with any kind of ID into the ID and the hash to look up.
+pub fn split<T: FromStr>(token: &str) -> Option<(T, Hash)> {
+    let (id, _) = token.split_once('.')?;
+    let id = id.parse::<T>().ok()?;
+    Some((id, blake3::hash(token.as_bytes())))
+}
+
+#[derive(Debug, Deserialize)]
+struct AccessTokenQuery {
+    access_token: Option<String>,
+}
+
+/// An access token sent with a request, either in the `access_token` query parameter or the
+/// `X-Access-Token` header. The token is only decoded here, and must still be checked against
+/// the project being accessed.
+#[derive(Debug)]
+pub struct AccessToken(pub Option<(ProjectAccessTokenId, Hash)>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AccessToken
+where
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Error> {
+        let Query(query) = Query::<AccessTokenQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| Error::InvalidAccessToken)?;
+
+        let token = query.access_token.or_else(|| {
+            parts
+                .headers
+                .get(ACCESS_TOKEN_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        });
+
+        token.as_deref().map(decode).transpose().map(AccessToken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let id = ProjectAccessTokenId::new();
+        let (token, hash) = generate(id);
+        let (decoded_id, decoded_hash) = decode(&token).unwrap();
+        assert_eq!(decoded_id, id);
+        assert_eq!(decoded_hash, hash);
+    }
+
+    #[test]
+    fn bad_tokens() {
+        assert!(decode("").is_err());
+        assert!(decode("abc.def").is_err());
+        assert!(decode(&ProjectAccessTokenId::new().to_string()).is_err());
+    }
+}
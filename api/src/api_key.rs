@@ -2,7 +2,7 @@ use chrono::{DateTime, TimeZone, Utc};
 use diesel::{prelude::*, PgConnection};
 use eyre::Result;
 use pic_store_auth::api_key::ApiKeyData;
-use pic_store_db::object_id::UserId;
+use pic_store_db::object_id::{UploadProfileId, UserId};
 use uuid::Uuid;
 
 pub fn make_key(
@@ -11,6 +11,7 @@ pub fn make_key(
     no_inherit_user_permissions: bool,
     description: Option<&str>,
     expires: Option<DateTime<Utc>>,
+    bound_upload_profile_id: Option<UploadProfileId>,
 ) -> Result<ApiKeyData> {
     let default_date = Utc.ymd(3000, 1, 1).and_hms(0, 0, 0);
     let key = ApiKeyData::from_params(
@@ -24,6 +25,16 @@ pub fn make_key(
         .find(user_id)
         .first::<pic_store_db::users::User>(conn)?;
 
+    if let Some(profile_id) = bound_upload_profile_id {
+        let profile_team_id = pic_store_db::upload_profiles::table
+            .find(profile_id)
+            .select(pic_store_db::upload_profiles::team_id)
+            .first::<pic_store_db::object_id::TeamId>(conn)?;
+        if profile_team_id != user.team_id {
+            eyre::bail!("Upload profile {profile_id} does not belong to the user's team");
+        }
+    }
+
     let new_key = pic_store_db::api_keys::ApiKey {
         id: key.id,
         prefix: key.prefix.clone(),
@@ -34,6 +45,7 @@ pub fn make_key(
         inherits_user_permissions: !no_inherit_user_permissions,
         expires: key.expires,
         created: Utc::now(),
+        bound_upload_profile_id,
     };
 
     diesel::insert_into(pic_store_db::api_keys::table)
@@ -0,0 +1,276 @@
+//! Counts of API requests and errors for each project and API key, so that integrators can
+//! monitor their own usage. Requests are counted in memory and periodically added to hourly
+//! rollups in the database, which keeps the database out of the request path.
+//!
+//! A request is counted against a project when its path contains the project or one of the
+//! project's images, or when the handler adds a [UsageProject] to the response.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    extract::State,
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, DurationRound, Utc};
+use db::{
+    api_usage::{self, ApiUsage, SESSION_KEY_ID},
+    base_images,
+    object_id::{BaseImageId, ProjectId, TeamId},
+    PoolExt,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use tracing::{event, Level};
+use uuid::Uuid;
+
+use crate::{auth::UserInfo, shared_state::AppState, Error};
+
+/// How often the counts are written to the database.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Set as a response extension by handlers whose path doesn't identify the project.
+#[derive(Clone, Copy, Debug)]
+pub struct UsageProject(pub ProjectId);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum UsageTarget {
+    Project(ProjectId),
+    Image(BaseImageId),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct UsageKey {
+    team_id: TeamId,
+    target: UsageTarget,
+    api_key_id: Uuid,
+    hour: DateTime<Utc>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct UsageCounts {
+    requests: i64,
+    client_errors: i64,
+    server_errors: i64,
+}
+
+impl UsageCounts {
+    fn add(&mut self, other: &UsageCounts) {
+        self.requests += other.requests;
+        self.client_errors += other.client_errors;
+        self.server_errors += other.server_errors;
+    }
+}
+
+/// Collects request counts until they are flushed to the database.
+#[derive(Clone, Debug, Default)]
+pub struct UsageRecorder {
+    pending: Arc<Mutex<HashMap<UsageKey, UsageCounts>>>,
+}
+
+impl UsageRecorder {
+    fn record(&self, key: UsageKey, status: StatusCode) {
+        let mut pending = self.pending.lock().unwrap();
+        let counts = pending.entry(key).or_default();
+        counts.requests += 1;
+        if status.is_client_error() {
+            counts.client_errors += 1;
+        } else if status.is_server_error() {
+            counts.server_errors += 1;
+        }
+    }
+
+    /// Write the pending counts to the database.
+    pub async fn flush(&self, pool: &db::Pool) -> Result<(), Error> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        pool.interact(move |conn| {
+            let image_ids = pending
+                .keys()
+                .filter_map(|key| match key.target {
+                    UsageTarget::Image(id) => Some(id),
+                    UsageTarget::Project(_) => None,
+                })
+                .collect::<Vec<_>>();
+            let image_projects = if image_ids.is_empty() {
+                HashMap::new()
+            } else {
+                base_images::table
+                    .filter(base_images::id.eq_any(image_ids))
+                    .select((base_images::id, base_images::project_id.assume_not_null()))
+                    .load::<(BaseImageId, ProjectId)>(conn)?
+                    .into_iter()
+                    .collect::<HashMap<_, _>>()
+            };
+
+            // Several images can belong to the same project, so merge them before writing.
+            let mut rows: HashMap<(TeamId, ProjectId, Uuid, DateTime<Utc>), UsageCounts> =
+                HashMap::new();
+            for (key, counts) in pending {
+                let project_id = match key.target {
+                    UsageTarget::Project(id) => id,
+                    UsageTarget::Image(id) => match image_projects.get(&id) {
+                        Some(id) => *id,
+                        None => continue,
+                    },
+                };
+
+                rows.entry((key.team_id, project_id, key.api_key_id, key.hour))
+                    .or_default()
+                    .add(&counts);
+            }
+
+            let rows = rows
+                .into_iter()
+                .map(
+                    |((team_id, project_id, api_key_id, hour), counts)| ApiUsage {
+                        team_id,
+                        project_id,
+                        api_key_id,
+                        hour,
+                        requests: counts.requests,
+                        client_errors: counts.client_errors,
+                        server_errors: counts.server_errors,
+                    },
+                )
+                .collect::<Vec<_>>();
+
+            // Project IDs in the path come from the client, so skip any that don't belong to
+            // the team instead of failing the whole batch.
+            let team_projects = db::projects::table
+                .filter(db::projects::id.eq_any(rows.iter().map(|r| r.project_id)))
+                .select((db::projects::id, db::projects::team_id))
+                .load::<(ProjectId, TeamId)>(conn)?
+                .into_iter()
+                .collect::<HashMap<_, _>>();
+            let rows = rows
+                .into_iter()
+                .filter(|row| team_projects.get(&row.project_id) == Some(&row.team_id))
+                .collect::<Vec<_>>();
+
+            if !rows.is_empty() {
+                api_usage::add_usage(conn, &rows)?;
+            }
+            Ok::<_, Error>(())
+        })
+        .await
+    }
+
+    /// Flush the counts to the database periodically.
+    pub fn start_flush_task(&self, pool: db::Pool) -> tokio::task::JoinHandle<()> {
+        let recorder = self.clone();
+        tokio::task::spawn(async move {
+            let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if let Err(e) = recorder.flush(&pool).await {
+                    event!(Level::ERROR, error = ?e, "Failed to save API usage");
+                }
+            }
+        })
+    }
+}
+
+/// Find the project or image that a request path refers to.
+fn path_target(path: &str) -> Option<UsageTarget> {
+    let mut segments = path
+        .strip_prefix("/api/")?
+        .split('/')
+        .filter(|s| !s.is_empty());
+
+    match (segments.next(), segments.next()) {
+        (Some("projects"), Some(id)) => id.parse().ok().map(UsageTarget::Project),
+        (Some("images"), Some(id)) => id.parse().ok().map(UsageTarget::Image),
+        _ => None,
+    }
+}
+
+/// Count authenticated API requests against the project that they use.
+pub async fn record_usage<B>(
+    State(state): State<AppState>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let user = req
+        .extensions()
+        .get::<UserInfo>()
+        .map(|user| (user.team_id, user.api_key_id));
+    let target = path_target(req.uri().path());
+
+    let response = next.run(req).await;
+
+    let target = response
+        .extensions()
+        .get::<UsageProject>()
+        .map(|p| UsageTarget::Project(p.0))
+        .or(target);
+
+    if let (Some((team_id, api_key_id)), Some(target)) = (user, target) {
+        let now = Utc::now();
+        state.api_usage.record(
+            UsageKey {
+                team_id,
+                target,
+                api_key_id: api_key_id.unwrap_or(SESSION_KEY_ID),
+                hour: now
+                    .duration_trunc(chrono::Duration::hours(1))
+                    .unwrap_or(now),
+            },
+            response.status(),
+        );
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_from_path() {
+        let project_id = ProjectId::new();
+        let image_id = BaseImageId::new();
+
+        assert_eq!(
+            path_target(&format!("/api/projects/{project_id}/upload_profiles")),
+            Some(UsageTarget::Project(project_id))
+        );
+        assert_eq!(
+            path_target(&format!("/api/images/{image_id}/upload")),
+            Some(UsageTarget::Image(image_id))
+        );
+        assert_eq!(path_target("/api/projects/global/storage_locations"), None);
+        assert_eq!(path_target("/api/images"), None);
+        assert_eq!(path_target(&format!("/serve/{image_id}")), None);
+    }
+
+    #[test]
+    fn counts_errors() {
+        let recorder = UsageRecorder::default();
+        let key = UsageKey {
+            team_id: TeamId::new(),
+            target: UsageTarget::Project(ProjectId::new()),
+            api_key_id: SESSION_KEY_ID,
+            hour: Utc::now(),
+        };
+        recorder.record(key, StatusCode::OK);
+        recorder.record(key, StatusCode::NOT_FOUND);
+        recorder.record(key, StatusCode::INTERNAL_SERVER_ERROR);
+
+        let pending = recorder.pending.lock().unwrap();
+        let counts = pending.get(&key).unwrap();
+        assert_eq!(counts.requests, 3);
+        assert_eq!(counts.client_errors, 1);
+        assert_eq!(counts.server_errors, 1);
+    }
+}
@@ -7,8 +7,10 @@ use axum::extract::FromRequestParts;
 use base64::Engine;
 use chrono::{DateTime, Utc};
 use db::{
-    object_id::{ProjectId, RoleId, TeamId, UploadProfileId, UserId},
-    PoolExt,
+    object_id::{
+        ImpersonationId, OrganizationId, ProjectId, RoleId, TeamId, UploadProfileId, UserId,
+    },
+    OrganizationRole, PoolExt, TeamStatus,
 };
 use diesel::{dsl::sql, prelude::*};
 use http::request::Parts;
@@ -30,6 +32,8 @@ pub struct ApiKeyData {
     pub roles: Vec<RoleId>,
     pub inherits_user_permissions: bool,
     pub default_upload_profile_id: Option<UploadProfileId>,
+    pub bound_upload_profile_id: Option<UploadProfileId>,
+    pub team_status: TeamStatus,
 }
 
 pub struct ApiKeyNewData {
@@ -38,6 +42,7 @@ pub struct ApiKeyNewData {
     pub name: String,
     pub inherits_user_permissions: bool,
     pub default_upload_profile_id: Option<UploadProfileId>,
+    pub bound_upload_profile_id: Option<UploadProfileId>,
 }
 
 #[derive(Clone)]
@@ -64,7 +69,9 @@ impl auth::api_key::ApiKeyStore for ApiKeyStore {
             pub roles: Vec<RoleId>,
             pub inherits_user_permissions: bool,
             pub api_key_default_upload_profile_id: Option<UploadProfileId>,
+            pub bound_upload_profile_id: Option<UploadProfileId>,
             pub user_default_upload_profile_id: Option<UploadProfileId>,
+            pub team_status: Option<TeamStatus>,
         }
 
         let info = self.db
@@ -86,10 +93,15 @@ impl auth::api_key::ApiKeyStore for ApiKeyStore {
                         ),
                         db::bool_or(db::api_keys::inherits_user_permissions),
                         db::api_keys::default_upload_profile_id,
+                        db::api_keys::bound_upload_profile_id,
                         db::users::table
                             .select(db::users::default_upload_profile_id)
                             .filter(db::users::id.eq(db::api_keys::user_id))
                             .single_value(),
+                        db::teams::table
+                            .select(db::teams::status)
+                            .filter(db::teams::id.eq(db::api_keys::team_id))
+                            .single_value(),
                     ))
                     .first::<ApiKeyLookupResult>(conn)
                     .optional()
@@ -105,9 +117,13 @@ impl auth::api_key::ApiKeyStore for ApiKeyStore {
             team_id: info.team_id,
             roles: info.roles,
             inherits_user_permissions: info.inherits_user_permissions,
+            // A key bound to an upload profile always uploads there.
             default_upload_profile_id: info
-                .api_key_default_upload_profile_id
+                .bound_upload_profile_id
+                .or(info.api_key_default_upload_profile_id)
                 .or(info.user_default_upload_profile_id),
+            bound_upload_profile_id: info.bound_upload_profile_id,
+            team_status: info.team_status.unwrap_or_default(),
         })
     }
 
@@ -126,6 +142,7 @@ impl auth::api_key::ApiKeyStore for ApiKeyStore {
             inherits_user_permissions: data.inherits_user_permissions,
             expires: key.expires,
             created: Utc::now(),
+            bound_upload_profile_id: data.bound_upload_profile_id,
         };
 
         let conn = self.db.get().await?;
@@ -167,6 +184,7 @@ pub struct SessionData {
     team_id: TeamId,
     roles: Vec<RoleId>,
     default_upload_profile_id: Option<UploadProfileId>,
+    team_status: Option<TeamStatus>,
 }
 
 #[async_trait]
@@ -214,6 +232,10 @@ impl auth::session::SessionStore for SessionStore {
                     db::users::team_id,
                     db::array_agg(db::user_roles::role_id),
                     db::users::default_upload_profile_id,
+                    db::teams::table
+                        .select(db::teams::status)
+                        .filter(db::teams::id.eq(db::users::team_id))
+                        .single_value(),
                 ))
                 .first::<SessionData>(conn)
         })
@@ -241,6 +263,13 @@ pub struct UserInfo {
     pub team_id: TeamId,
     pub roles: Vec<RoleId>,
     pub default_upload_profile_id: Option<UploadProfileId>,
+    /// Set when using an API key that may only upload through this profile.
+    pub bound_upload_profile_id: Option<UploadProfileId>,
+    pub team_status: TeamStatus,
+    /// Set when an instance admin is acting as a member of `team_id`.
+    pub impersonation_id: Option<ImpersonationId>,
+    /// Set when the request was authenticated with an API key.
+    pub api_key_id: Option<Uuid>,
 }
 
 impl From<RequestUser<ApiKeyData, SessionData>> for UserInfo {
@@ -251,12 +280,20 @@ impl From<RequestUser<ApiKeyData, SessionData>> for UserInfo {
                 team_id: key.team_id,
                 roles: key.roles,
                 default_upload_profile_id: key.default_upload_profile_id,
+                bound_upload_profile_id: key.bound_upload_profile_id,
+                team_status: key.team_status,
+                impersonation_id: None,
+                api_key_id: Some(key.api_key_id),
             },
             RequestUser::Session(s) => UserInfo {
                 user_id: s.user_id,
                 team_id: s.team_id,
                 roles: s.roles,
                 default_upload_profile_id: s.default_upload_profile_id,
+                bound_upload_profile_id: None,
+                team_status: s.team_status.unwrap_or_default(),
+                impersonation_id: None,
+                api_key_id: None,
             },
         }
     }
@@ -325,3 +362,74 @@ pub fn must_have_permission_on_project(
         Err(Error::MissingPermission(permission.into()))
     }
 }
+
+pub fn must_have_global_permission(
+    conn: &mut PgConnection,
+    user: &UserInfo,
+    permission: db::permissions::GlobalPermission,
+) -> Result<(), crate::Error> {
+    if db::permissions::has_global_permission(conn, user.team_id, &user.roles, permission)? {
+        Ok(())
+    } else {
+        Err(Error::MissingPermission(permission.into()))
+    }
+}
+
+/// Make sure that the project belongs to the user's team, and that the user has the given
+/// permission on it.
+pub fn must_own_project(
+    conn: &mut PgConnection,
+    user: &UserInfo,
+    project_id: ProjectId,
+    permission: db::permissions::ProjectPermission,
+) -> Result<(), crate::Error> {
+    let exists = diesel::select(diesel::dsl::exists(
+        db::projects::table
+            .filter(db::projects::id.eq(project_id))
+            .filter(db::projects::team_id.eq(user.team_id))
+            .filter(db::projects::deleted.is_null()),
+    ))
+    .get_result::<bool>(conn)?;
+    if !exists {
+        return Err(Error::ObjectNotFound("project"));
+    }
+
+    must_have_permission_on_project(conn, user, project_id, permission)
+}
+
+/// Make sure that the user is a member of the organization with at least the given role, and
+/// return their role.
+pub fn must_have_organization_role(
+    conn: &mut PgConnection,
+    user: &UserInfo,
+    organization_id: OrganizationId,
+    required: OrganizationRole,
+) -> Result<OrganizationRole, crate::Error> {
+    let role = db::organizations::member_role(conn, organization_id, user.user_id)?
+        .ok_or(Error::ObjectNotFound("organization"))?;
+
+    if role.includes(required) {
+        Ok(role)
+    } else {
+        Err(Error::MissingOrganizationRole(required))
+    }
+}
+
+pub fn must_be_instance_admin(
+    conn: &mut PgConnection,
+    user: &UserInfo,
+) -> Result<(), crate::Error> {
+    let instance_admin = db::users::table
+        .filter(db::users::id.eq(user.user_id))
+        .filter(db::users::deleted.is_null())
+        .select(db::users::instance_admin)
+        .first::<bool>(conn)
+        .optional()?
+        .unwrap_or(false);
+
+    if instance_admin {
+        Ok(())
+    } else {
+        Err(Error::InstanceAdminRequired)
+    }
+}
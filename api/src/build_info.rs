@@ -0,0 +1,3 @@
+//! Information about the build, generated by the build script.
+
+include!(concat!(env!("OUT_DIR"), "/build_info.rs"));
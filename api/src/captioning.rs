@@ -0,0 +1,132 @@
+//! Generate alt text for images with an external captioning service.
+//!
+//! The service receives a `POST` with a downscaled JPEG of the image as the body, and should
+//! respond with JSON like `{ "caption": "A dog running on a beach" }`. Generated alt text is
+//! flagged as machine-generated until someone replaces or confirms it.
+
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageOutputFormat};
+use serde::Deserialize;
+
+/// Images are scaled to fit within this size before they are sent to the service.
+const MAX_DIMENSION: u32 = 1024;
+
+#[derive(Deserialize)]
+struct CaptionResponse {
+    caption: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Captioner {
+    client: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+}
+
+impl Captioner {
+    pub fn new(url: String, api_key: Option<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+            api_key,
+        }
+    }
+
+    pub async fn caption(&self, image: &DynamicImage) -> Result<String, eyre::Report> {
+        let body = encode_preview(image)?;
+
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "image/jpeg")
+            .body(body);
+        if let Some(api_key) = self.api_key.as_deref() {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<CaptionResponse>()
+            .await?;
+
+        let caption = response.caption.trim();
+        if caption.is_empty() {
+            return Err(eyre::eyre!("Captioning service returned an empty caption"));
+        }
+
+        Ok(caption.to_string())
+    }
+}
+
+fn encode_preview(image: &DynamicImage) -> Result<Vec<u8>, image::ImageError> {
+    let preview = if image.width() > MAX_DIMENSION || image.height() > MAX_DIMENSION {
+        image.thumbnail(MAX_DIMENSION, MAX_DIMENSION)
+    } else {
+        image.clone()
+    };
+
+    // JPEG has no alpha channel.
+    let preview = DynamicImage::ImageRgb8(preview.to_rgb8());
+    let mut output = Cursor::new(Vec::new());
+    preview.write_to(&mut output, ImageOutputFormat::Jpeg(85))?;
+    Ok(output.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn captions_image() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/caption"))
+            .and(header("authorization", "Bearer caption-key"))
+            .and(header("content-type", "image/jpeg"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "caption": " A blank image \n" })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let captioner = Captioner::new(
+            format!("{}/caption", server.uri()),
+            Some("caption-key".to_string()),
+        );
+        let image = DynamicImage::new_rgba8(2000, 1000);
+        let caption = captioner.caption(&image).await.unwrap();
+        assert_eq!(caption, "A blank image");
+    }
+
+    #[tokio::test]
+    async fn rejects_empty_captions() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "caption": "" })),
+            )
+            .mount(&server)
+            .await;
+
+        let captioner = Captioner::new(server.uri(), None);
+        let result = captioner.caption(&DynamicImage::new_rgb8(10, 10)).await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn preview_size() {
+        let image = DynamicImage::new_rgba8(4000, 2000);
+        let preview = image::load_from_memory(&encode_preview(&image).unwrap()).unwrap();
+        assert_eq!((preview.width(), preview.height()), (1024, 512));
+    }
+}
@@ -0,0 +1,131 @@
+//! Purge images from a CDN when they are replaced or deleted, so that clients don't keep seeing
+//! the old image until the cache TTL expires.
+//!
+//! Cloudflare and CloudFront purge the image's output URLs under a storage location's
+//! `public_url_base`. Resized variants from the serve route can't be enumerated, so those still
+//! expire on their own. Fastly purges by surrogate key instead, and the serve route tags every
+//! response with the image ID, so Fastly purges the variants too.
+
+mod cloudflare;
+mod cloudfront;
+mod fastly;
+
+use async_trait::async_trait;
+use db::{object_id::BaseImageId, storage_locations::CdnPurge};
+use pic_store_db as db;
+use tracing::{event, Level};
+
+pub use cloudflare::Cloudflare;
+pub use cloudfront::CloudFront;
+pub use fastly::Fastly;
+
+/// The header on serve route responses that Fastly uses to purge all of an image's variants.
+pub const SURROGATE_KEY_HEADER: &str = "surrogate-key";
+
+/// The cached content to invalidate for an image.
+#[derive(Debug)]
+pub struct PurgeTarget<'a> {
+    pub image_id: BaseImageId,
+    /// The public URLs of the image's output files.
+    pub urls: &'a [String],
+}
+
+/// A CDN that can invalidate its cached copies of an image.
+#[async_trait]
+pub trait CdnInvalidator: Send + Sync {
+    async fn invalidate(&self, target: &PurgeTarget<'_>) -> Result<(), eyre::Report>;
+}
+
+/// The API base URLs for each CDN. These only need to change for testing.
+#[derive(Clone, Debug)]
+pub struct CdnEndpoints {
+    pub cloudflare: String,
+    pub fastly: String,
+    pub cloudfront: String,
+}
+
+impl Default for CdnEndpoints {
+    fn default() -> Self {
+        Self {
+            cloudflare: cloudflare::API_BASE.to_string(),
+            fastly: fastly::API_BASE.to_string(),
+            cloudfront: cloudfront::API_BASE.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CdnPurger {
+    client: reqwest::Client,
+    endpoints: CdnEndpoints,
+}
+
+impl CdnPurger {
+    pub fn new(endpoints: CdnEndpoints) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            endpoints,
+        }
+    }
+
+    /// Create the invalidator for a storage location's CDN configuration.
+    pub fn invalidator<'a>(&'a self, cdn: &'a CdnPurge) -> Box<dyn CdnInvalidator + 'a> {
+        match cdn {
+            CdnPurge::Cloudflare { zone_id, api_token } => Box::new(Cloudflare {
+                client: &self.client,
+                api_base: &self.endpoints.cloudflare,
+                zone_id,
+                api_token,
+            }),
+            CdnPurge::Fastly {
+                service_id,
+                api_token,
+            } => Box::new(Fastly {
+                client: &self.client,
+                api_base: &self.endpoints.fastly,
+                service_id,
+                api_token,
+            }),
+            CdnPurge::CloudFront {
+                distribution_id,
+                access_key_id,
+                secret_key,
+            } => Box::new(CloudFront {
+                client: &self.client,
+                api_base: &self.endpoints.cloudfront,
+                distribution_id,
+                access_key_id,
+                secret_key,
+            }),
+        }
+    }
+
+    /// Invalidate the image in the CDN. The CDN configuration is read from the database, so its
+    /// credentials are decrypted first.
+    pub async fn purge(
+        &self,
+        cdn: &CdnPurge,
+        target: &PurgeTarget<'_>,
+    ) -> Result<(), eyre::Report> {
+        let cdn = cdn.clone().decrypt_credentials()?;
+        let invalidator = self.invalidator(&cdn);
+        invalidator.invalidate(target).await
+    }
+
+    /// Invalidate the image, logging any failure instead of returning it. The image has already
+    /// changed by the time this is called, so a failed purge only means that the CDN serves the
+    /// old version until it expires.
+    pub async fn purge_best_effort(&self, cdn: &Option<CdnPurge>, target: &PurgeTarget<'_>) {
+        let Some(cdn) = cdn else {
+            return;
+        };
+
+        if target.urls.is_empty() {
+            return;
+        }
+
+        if let Err(e) = self.purge(cdn, target).await {
+            event!(Level::WARN, error=?e, ?cdn, image_id=%target.image_id, "Failed to purge CDN cache");
+        }
+    }
+}
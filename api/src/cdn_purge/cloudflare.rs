@@ -0,0 +1,113 @@
+use async_trait::async_trait;
+use serde::Serialize;
+
+use super::{CdnInvalidator, PurgeTarget};
+
+pub(super) const API_BASE: &str = "https://api.cloudflare.com/client/v4";
+
+/// Cloudflare accepts at most this many URLs in a single purge request.
+const MAX_FILES: usize = 30;
+
+#[derive(Serialize)]
+struct PurgeBody<'a> {
+    files: &'a [String],
+}
+
+/// Purges URLs from a Cloudflare zone.
+pub struct Cloudflare<'a> {
+    pub client: &'a reqwest::Client,
+    pub api_base: &'a str,
+    pub zone_id: &'a str,
+    pub api_token: &'a str,
+}
+
+#[async_trait]
+impl<'a> CdnInvalidator for Cloudflare<'a> {
+    async fn invalidate(&self, target: &PurgeTarget<'_>) -> Result<(), eyre::Report> {
+        let endpoint = format!("{}/zones/{}/purge_cache", self.api_base, self.zone_id);
+        for files in target.urls.chunks(MAX_FILES) {
+            self.client
+                .post(&endpoint)
+                .bearer_auth(self.api_token)
+                .json(&PurgeBody { files })
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pic_store_db::object_id::BaseImageId;
+    use wiremock::{
+        matchers::{body_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn cloudflare<'a>(client: &'a reqwest::Client, api_base: &'a str) -> Cloudflare<'a> {
+        Cloudflare {
+            client,
+            api_base,
+            zone_id: "zone1",
+            api_token: "cf-token",
+        }
+    }
+
+    #[tokio::test]
+    async fn purges_in_chunks() {
+        let server = MockServer::start().await;
+        let urls = (0..35)
+            .map(|i| format!("https://cdn.example.com/image-{i}.webp"))
+            .collect::<Vec<_>>();
+
+        Mock::given(method("POST"))
+            .and(path("/zones/zone1/purge_cache"))
+            .and(header("authorization", "Bearer cf-token"))
+            .and(body_json(serde_json::json!({ "files": &urls[0..30] })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/zones/zone1/purge_cache"))
+            .and(body_json(serde_json::json!({ "files": &urls[30..] })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = reqwest::Client::new();
+        let uri = server.uri();
+        cloudflare(&client, &uri)
+            .invalidate(&PurgeTarget {
+                image_id: BaseImageId::new(),
+                urls: &urls,
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn returns_api_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&server)
+            .await;
+
+        let client = reqwest::Client::new();
+        let uri = server.uri();
+        let result = cloudflare(&client, &uri)
+            .invalidate(&PurgeTarget {
+                image_id: BaseImageId::new(),
+                urls: &["https://cdn.example.com/a.png".to_string()],
+            })
+            .await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use super::{CdnInvalidator, PurgeTarget};
+
+pub(super) const API_BASE: &str = "https://cloudfront.amazonaws.com";
+
+const API_VERSION: &str = "2020-05-31";
+
+/// CloudFront is a global service, but requests are signed for this region.
+const REGION: &str = "us-east-1";
+const SERVICE: &str = "cloudfront";
+const CONTENT_TYPE: &str = "text/xml";
+
+/// CloudFront accepts at most this many paths in one invalidation.
+const MAX_PATHS: usize = 3000;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Creates invalidations for the image's URLs in a CloudFront distribution.
+pub struct CloudFront<'a> {
+    pub client: &'a reqwest::Client,
+    pub api_base: &'a str,
+    pub distribution_id: &'a str,
+    pub access_key_id: &'a str,
+    pub secret_key: &'a str,
+}
+
+#[async_trait]
+impl<'a> CdnInvalidator for CloudFront<'a> {
+    async fn invalidate(&self, target: &PurgeTarget<'_>) -> Result<(), eyre::Report> {
+        let paths = target
+            .urls
+            .iter()
+            .map(|url| Ok(reqwest::Url::parse(url)?.path().to_string()))
+            .collect::<Result<Vec<_>, eyre::Report>>()?;
+
+        let path = format!(
+            "/{API_VERSION}/distribution/{}/invalidation",
+            self.distribution_id
+        );
+        let url = reqwest::Url::parse(self.api_base)?.join(&path)?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(eyre::eyre!("CloudFront API URL has no host")),
+        };
+
+        for paths in paths.chunks(MAX_PATHS) {
+            let body = invalidation_batch(paths, &ulid::Ulid::new().to_string());
+            let now = Utc::now();
+            let authorization = self.authorization(&host, &path, body.as_bytes(), now);
+
+            self.client
+                .post(url.clone())
+                .header(reqwest::header::CONTENT_TYPE, CONTENT_TYPE)
+                .header("x-amz-date", amz_date(now))
+                .header(reqwest::header::AUTHORIZATION, authorization)
+                .body(body)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> CloudFront<'a> {
+    /// Build the AWS Signature Version 4 `Authorization` header for a POST request.
+    fn authorization(&self, host: &str, path: &str, body: &[u8], now: DateTime<Utc>) -> String {
+        let amz_date = amz_date(now);
+        let date = now.format("%Y%m%d").to_string();
+        let signed_headers = "content-type;host;x-amz-date";
+
+        let canonical_request = format!(
+            "POST\n{path}\n\ncontent-type:{CONTENT_TYPE}\nhost:{host}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{}",
+            hex(&Sha256::digest(body))
+        );
+
+        let scope = format!("{date}/{REGION}/{SERVICE}/aws4_request");
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let key = signing_key(self.secret_key, &date, REGION, SERVICE);
+        let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.access_key_id
+        )
+    }
+}
+
+fn amz_date(now: DateTime<Utc>) -> String {
+    now.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn signing_key(secret_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac(format!("AWS4{secret_key}").as_bytes(), date.as_bytes());
+    let key = hmac(&key, region.as_bytes());
+    let key = hmac(&key, service.as_bytes());
+    hmac(&key, b"aws4_request")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn invalidation_batch(paths: &[String], caller_reference: &str) -> String {
+    let items = paths
+        .iter()
+        .map(|path| format!("<Path>{}</Path>", escape_xml(path)))
+        .collect::<String>();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><InvalidationBatch xmlns="http://cloudfront.amazonaws.com/doc/{API_VERSION}/"><Paths><Quantity>{}</Quantity><Items>{items}</Items></Paths><CallerReference>{caller_reference}</CallerReference></InvalidationBatch>"#,
+        paths.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pic_store_db::object_id::BaseImageId;
+    use wiremock::{
+        matchers::{body_string_contains, header, header_regex, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    #[test]
+    fn derives_signing_key() {
+        // From the AWS Signature Version 4 documentation.
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20150830",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex(&key),
+            "c4afb1cc5771d871763a393e44b703571b55cc28424d1a5e86da6ed3c154a4b9"
+        );
+    }
+
+    #[test]
+    fn batch_escapes_paths() {
+        let batch = invalidation_batch(&["/a&b.png".to_string()], "ref1");
+        assert!(batch.contains("<Quantity>1</Quantity>"));
+        assert!(batch.contains("<Path>/a&amp;b.png</Path>"));
+        assert!(batch.contains("<CallerReference>ref1</CallerReference>"));
+    }
+
+    #[tokio::test]
+    async fn creates_invalidation() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/2020-05-31/distribution/dist1/invalidation"))
+            .and(header("content-type", "text/xml"))
+            // The mock server splits header values at commas, so each part is matched separately.
+            .and(header_regex(
+                "authorization",
+                r"^\s*(AWS4-HMAC-SHA256 Credential=AKID/\d{8}/us-east-1/cloudfront/aws4_request|SignedHeaders=content-type;host;x-amz-date|Signature=[0-9a-f]{64})$",
+            ))
+            .and(body_string_contains("<Path>/project/image-300.webp</Path>"))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = reqwest::Client::new();
+        let uri = server.uri();
+        CloudFront {
+            client: &client,
+            api_base: &uri,
+            distribution_id: "dist1",
+            access_key_id: "AKID",
+            secret_key: "secret",
+        }
+        .invalidate(&PurgeTarget {
+            image_id: BaseImageId::new(),
+            urls: &["https://cdn.example.com/project/image-300.webp".to_string()],
+        })
+        .await
+        .unwrap();
+    }
+}
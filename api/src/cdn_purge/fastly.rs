@@ -0,0 +1,72 @@
+use async_trait::async_trait;
+
+use super::{CdnInvalidator, PurgeTarget, SURROGATE_KEY_HEADER};
+
+pub(super) const API_BASE: &str = "https://api.fastly.com";
+
+/// Purges everything tagged with the image's surrogate key from a Fastly service.
+pub struct Fastly<'a> {
+    pub client: &'a reqwest::Client,
+    pub api_base: &'a str,
+    pub service_id: &'a str,
+    pub api_token: &'a str,
+}
+
+#[async_trait]
+impl<'a> CdnInvalidator for Fastly<'a> {
+    async fn invalidate(&self, target: &PurgeTarget<'_>) -> Result<(), eyre::Report> {
+        self.client
+            .post(format!(
+                "{}/service/{}/purge",
+                self.api_base, self.service_id
+            ))
+            .header("fastly-key", self.api_token)
+            .header(SURROGATE_KEY_HEADER, target.image_id.to_string())
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pic_store_db::object_id::BaseImageId;
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn purges_surrogate_key() {
+        let server = MockServer::start().await;
+        let image_id = BaseImageId::new();
+
+        Mock::given(method("POST"))
+            .and(path("/service/svc1/purge"))
+            .and(header("fastly-key", "fastly-token"))
+            .and(header("surrogate-key", image_id.to_string().as_str()))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = reqwest::Client::new();
+        let uri = server.uri();
+        Fastly {
+            client: &client,
+            api_base: &uri,
+            service_id: "svc1",
+            api_token: "fastly-token",
+        }
+        .invalidate(&PurgeTarget {
+            image_id,
+            urls: &["https://cdn.example.com/a.png".to_string()],
+        })
+        .await
+        .unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+//! SHA-256 checksums of the stored originals and output images. Each file is read back after it
+//! is written and checked against its checksum, and the checksum is returned by the API so that
+//! other systems can check the files they download.
+
+use bytes::Bytes;
+use futures::TryStreamExt;
+use pic_store_storage::{self as storage, Operator};
+use sha2::{Digest, Sha256};
+
+use crate::Error;
+
+/// Format a digest as lowercase hex.
+pub fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The hex SHA-256 checksum of some data.
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+/// Check that data matches a checksum.
+pub fn check(data: &[u8], expected: &str, location: &str) -> Result<(), Error> {
+    if sha256_hex(data) != expected {
+        return Err(Error::ChecksumMismatch(location.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Read back a stored file and check that it matches its checksum.
+pub async fn verify_stored(
+    operator: &Operator,
+    location: &str,
+    expected: &str,
+) -> Result<(), Error> {
+    let mut stream = operator.get(location).await?.into_stream();
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = stream.try_next().await.map_err(storage::Error::from)? {
+        hasher.update(&chunk);
+    }
+
+    if to_hex(&hasher.finalize()) != expected {
+        return Err(Error::ChecksumMismatch(location.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Write a file and make sure that storage returns the same contents. Returns the file's
+/// checksum.
+pub async fn put_verified(
+    operator: &Operator,
+    location: &str,
+    contents: Bytes,
+) -> Result<String, Error> {
+    let checksum = sha256_hex(&contents);
+    operator.put(location, contents).await?;
+    verify_stored(operator, location, &checksum).await?;
+    Ok(checksum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn check_mismatch() {
+        let checksum = sha256_hex(b"abc");
+        assert!(check(b"abc", &checksum, "a.png").is_ok());
+        assert!(matches!(
+            check(b"abd", &checksum, "a.png"),
+            Err(Error::ChecksumMismatch(location)) if location == "a.png"
+        ));
+    }
+}
@@ -0,0 +1,169 @@
+//! Client hints that let browsers ask for an image at the size that it will be displayed,
+//! without the page putting the size in the URL. Browsers only send these headers to sites that
+//! opt in with `Accept-CH`.
+
+use axum::http::{HeaderMap, HeaderName};
+
+pub const SEC_CH_WIDTH: HeaderName = HeaderName::from_static("sec-ch-width");
+pub const WIDTH: HeaderName = HeaderName::from_static("width");
+pub const SEC_CH_DPR: HeaderName = HeaderName::from_static("sec-ch-dpr");
+pub const DPR: HeaderName = HeaderName::from_static("dpr");
+pub const SAVE_DATA: HeaderName = HeaderName::from_static("save-data");
+pub const ECT: HeaderName = HeaderName::from_static("ect");
+
+/// Higher ratios don't make a visible difference, and would only make images larger.
+const MAX_DPR: f32 = 4.0;
+
+/// Widths from hints that don't match a stored output are rounded up to a multiple of this, so
+/// that each image gets a limited number of variants.
+const WIDTH_STEP: u32 = 100;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ClientHints {
+    /// The width the image will be displayed at, in physical pixels.
+    pub width: Option<u32>,
+    /// The ratio of physical pixels to CSS pixels.
+    pub dpr: Option<f32>,
+    /// The user asked to use less data.
+    pub save_data: bool,
+    /// The effective connection type is 3G or slower.
+    pub slow_connection: bool,
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &HeaderName) -> Option<&'a str> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim())
+}
+
+impl ClientHints {
+    pub fn from_headers(headers: &HeaderMap) -> ClientHints {
+        let width = header_str(headers, &SEC_CH_WIDTH)
+            .or_else(|| header_str(headers, &WIDTH))
+            .and_then(|value| value.parse::<f32>().ok())
+            .filter(|width| width.is_finite() && *width >= 1.0)
+            .map(|width| width.ceil() as u32);
+
+        let dpr = header_str(headers, &SEC_CH_DPR)
+            .or_else(|| header_str(headers, &DPR))
+            .and_then(|value| value.parse::<f32>().ok())
+            .filter(|dpr| dpr.is_finite() && *dpr > 0.0)
+            .map(|dpr| dpr.min(MAX_DPR));
+
+        let save_data = header_str(headers, &SAVE_DATA)
+            .map(|value| value.eq_ignore_ascii_case("on"))
+            .unwrap_or(false);
+
+        let slow_connection = header_str(headers, &ECT)
+            .map(|value| matches!(value, "slow-2g" | "2g" | "3g"))
+            .unwrap_or(false);
+
+        ClientHints {
+            width,
+            dpr,
+            save_data,
+            slow_connection,
+        }
+    }
+
+    /// Whether the client would be better served by a lower quality image.
+    pub fn wants_less_data(&self) -> bool {
+        self.save_data || self.slow_connection
+    }
+
+    /// The width to serve, given the width from the query string. A width in the query is
+    /// treated as CSS pixels and scaled by the DPR. Without one, the Width hint is used.
+    /// Returns `None` when the hints don't change the width.
+    pub fn requested_width(&self, query_width: Option<u32>) -> Option<u32> {
+        match (query_width, self.dpr) {
+            (Some(width), Some(dpr)) if dpr != 1.0 => Some((width as f32 * dpr).ceil() as u32),
+            (Some(_), _) => None,
+            (None, _) => self.width,
+        }
+    }
+
+    /// The hint headers that decide the width, which the response should vary on.
+    pub fn width_vary(query_width: Option<u32>) -> Vec<HeaderName> {
+        if query_width.is_some() {
+            vec![SEC_CH_DPR, DPR]
+        } else {
+            vec![SEC_CH_WIDTH, WIDTH]
+        }
+    }
+}
+
+/// Pick the smallest stored width that is at least as wide as the requested width, so that
+/// requests reuse the outputs that already exist. Returns `None` if they are all too small.
+pub fn closest_stored_width(requested: u32, stored: &[u32]) -> Option<u32> {
+    stored.iter().copied().filter(|w| *w >= requested).min()
+}
+
+/// Round a width from a hint up to the next step.
+pub fn round_width(width: u32) -> u32 {
+    width.div_ceil(WIDTH_STEP) * WIDTH_STEP
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn hints(values: &[(HeaderName, &'static str)]) -> ClientHints {
+        let headers = values
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+            .collect();
+        ClientHints::from_headers(&headers)
+    }
+
+    #[test]
+    fn parse_headers() {
+        assert_eq!(hints(&[]), ClientHints::default());
+        assert_eq!(
+            hints(&[(WIDTH, "412.5"), (SEC_CH_DPR, "2.625"), (SAVE_DATA, "on")]),
+            ClientHints {
+                width: Some(413),
+                dpr: Some(2.625),
+                save_data: true,
+                slow_connection: false,
+            }
+        );
+        assert!(hints(&[(ECT, "2g")]).wants_less_data());
+        assert!(!hints(&[(ECT, "4g")]).wants_less_data());
+        assert_eq!(hints(&[(DPR, "9")]).dpr, Some(MAX_DPR));
+        // The standard header takes precedence over the legacy one.
+        assert_eq!(
+            hints(&[(SEC_CH_WIDTH, "800"), (WIDTH, "400")]).width,
+            Some(800)
+        );
+        assert_eq!(
+            hints(&[(DPR, "-1"), (WIDTH, "wide")]),
+            ClientHints::default()
+        );
+    }
+
+    #[test]
+    fn requested_widths() {
+        let h = hints(&[(WIDTH, "640"), (SEC_CH_DPR, "2")]);
+        // Explicit widths are scaled by the DPR, and the Width hint is ignored.
+        assert_eq!(h.requested_width(Some(300)), Some(600));
+        assert_eq!(h.requested_width(None), Some(640));
+
+        let no_dpr = hints(&[(DPR, "1")]);
+        assert_eq!(no_dpr.requested_width(Some(300)), None);
+        assert_eq!(no_dpr.requested_width(None), None);
+    }
+
+    #[test]
+    fn closest_width() {
+        let stored = [320, 640, 1280];
+        assert_eq!(closest_stored_width(500, &stored), Some(640));
+        assert_eq!(closest_stored_width(640, &stored), Some(640));
+        assert_eq!(closest_stored_width(2000, &stored), None);
+
+        assert_eq!(round_width(413), 500);
+        assert_eq!(round_width(500), 500);
+    }
+}
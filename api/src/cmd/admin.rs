@@ -5,11 +5,25 @@ use pic_store_api::auth::API_KEY_PREFIX;
 use pic_store_db::object_id;
 use uuid::Uuid;
 
-use self::make_api_key::MakeApiKeyArgs;
+use self::{
+    doctor::DoctorArgs,
+    gc::GcArgs,
+    make_api_key::MakeApiKeyArgs,
+    migrate_storage::MigrateStorageArgs,
+    pause_conversions::{PauseConversionsArgs, ResumeConversionsArgs},
+    profile_template::ProfileTemplateArgs,
+    reencrypt_credentials::ReencryptCredentialsArgs,
+};
 
 #[cfg(feature = "bootstrap")]
 mod bootstrap;
+mod doctor;
+mod gc;
 mod make_api_key;
+mod migrate_storage;
+mod pause_conversions;
+mod profile_template;
+mod reencrypt_credentials;
 
 #[derive(Debug, Args)]
 pub struct AdminArgs {
@@ -33,6 +47,39 @@ pub enum Commands {
     HashPassword(HashPassword),
     /// Add an API key for a particular user and insert it into the database.
     AddApiKey(MakeApiKeyArgs),
+    /// Check the server configuration, database, storage locations, and image encoders.
+    ///
+    /// This takes the same options as the server command, and prints a report of which checks
+    /// passed and failed.
+    Doctor(DoctorArgs),
+    /// Create a conversion profile from one of the built-in templates.
+    ///
+    /// Run without a template name to list the templates.
+    ProfileTemplate(ProfileTemplateArgs),
+    /// Encrypt the credentials in every storage location with a new key.
+    ///
+    /// Run this after setting a credentials key for the first time to encrypt the existing
+    /// credentials, or with --old-key to rotate keys. A key can be generated with
+    /// `openssl rand -base64 32`.
+    ReencryptCredentials(ReencryptCredentialsArgs),
+    /// Move the images stored in one storage location to another.
+    ///
+    /// The original and output images of every upload profile that uses the --from location are
+    /// copied and verified, and then the upload profiles are changed to use the --to location.
+    /// Pass --delete-source to remove the old copies afterwards.
+    MigrateStorage(MigrateStorageArgs),
+    /// Stop running image conversions, for every team or for one team.
+    ///
+    /// New conversion jobs are held until conversions are resumed, and then the server queues
+    /// them again.
+    PauseConversions(PauseConversionsArgs),
+    /// Resume image conversions that were paused with pause-conversions.
+    ResumeConversions(ResumeConversionsArgs),
+    /// Find objects in storage that no image refers to, and images whose objects are missing.
+    ///
+    /// Orphaned objects can be left behind when the server crashes partway through writing or
+    /// deleting an image. Pass --delete to remove them.
+    Gc(GcArgs),
 }
 
 #[derive(Debug, Args)]
@@ -48,6 +95,7 @@ enum IdType {
     User,
     Project,
     ConversionProfile,
+    TransformationPreset,
     StorageLocation,
     UploadProfile,
     BaseImage,
@@ -61,13 +109,20 @@ pub struct HashPassword {
     password: String,
 }
 
-pub fn admin_commands(cmd: AdminArgs) -> Result<(), eyre::Report> {
+pub async fn admin_commands(cmd: AdminArgs) -> Result<(), eyre::Report> {
     match cmd.commands {
         #[cfg(feature = "bootstrap")]
         Commands::Bootstrap(args) => bootstrap::bootstrap(args)?,
         Commands::MakeId(MakeId { command }) => make_id(command),
         Commands::AddApiKey(args) => make_api_key::main(args)?,
         Commands::HashPassword(HashPassword { password }) => hash_password(password)?,
+        Commands::Doctor(args) => doctor::main(args).await?,
+        Commands::ProfileTemplate(args) => profile_template::main(args)?,
+        Commands::ReencryptCredentials(args) => reencrypt_credentials::main(args)?,
+        Commands::MigrateStorage(args) => migrate_storage::main(args).await?,
+        Commands::PauseConversions(args) => pause_conversions::pause(args)?,
+        Commands::ResumeConversions(args) => pause_conversions::resume(args)?,
+        Commands::Gc(args) => gc::main(args).await?,
     }
 
     Ok(())
@@ -80,6 +135,7 @@ fn make_id(id: IdType) {
         IdType::Role => object_id::RoleId::new().to_string(),
         IdType::Project => object_id::ProjectId::new().to_string(),
         IdType::ConversionProfile => object_id::ConversionProfileId::new().to_string(),
+        IdType::TransformationPreset => object_id::TransformationPresetId::new().to_string(),
         IdType::StorageLocation => object_id::StorageLocationId::new().to_string(),
         IdType::UploadProfile => object_id::UploadProfileId::new().to_string(),
         IdType::BaseImage => object_id::BaseImageId::new().to_string(),
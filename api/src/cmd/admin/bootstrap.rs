@@ -89,6 +89,8 @@ pub struct ApiKeyInput {
     user_id: UserId,
     inherits_user_permissions: bool,
     expires: DateTime<Utc>,
+    #[serde(default)]
+    bound_upload_profile_id: Option<UploadProfileId>,
 }
 
 fn apply_object(
@@ -119,6 +121,12 @@ fn apply_object(
             conn,
             obj
         ),
+        "transformation_preset" | "transformation_presets" => insert_object!(
+            db::transformation_presets::table,
+            db::transformation_presets::NewTransformationPreset,
+            conn,
+            obj
+        ),
         "storage_location" | "storage_locations" => insert_object!(
             db::storage_locations::table,
             db::storage_locations::NewStorageLocation,
@@ -174,6 +182,7 @@ fn apply_object(
                 inherits_user_permissions: input.inherits_user_permissions,
                 created: Utc::now(),
                 expires: input.expires,
+                bound_upload_profile_id: input.bound_upload_profile_id,
             };
 
             diesel::insert_into(db::api_keys::table)
@@ -0,0 +1,267 @@
+use std::{fmt::Display, path::Path};
+
+use base64::Engine;
+use clap::Args;
+use diesel::{prelude::*, PgConnection};
+use eyre::{eyre, Result};
+use image::{DynamicImage, RgbImage};
+use pic_store_api::{config::Config, routes::imgproxy::ImgproxyKey};
+use pic_store_convert as convert;
+use pic_store_db::{self as db, storage_locations::StorageLocation};
+use pic_store_storage as storage;
+
+/// The key used to check that a storage location can be written to.
+const STORAGE_PROBE_KEY: &str = ".pic-store-doctor";
+
+#[derive(Debug, Args)]
+pub struct DoctorArgs {
+    #[clap(flatten)]
+    config: Config,
+}
+
+#[derive(Default)]
+struct Report {
+    failed: usize,
+}
+
+impl Report {
+    fn check(&mut self, name: impl Display, result: Result<()>) {
+        match result {
+            Ok(()) => println!("PASS  {name}"),
+            Err(e) => {
+                self.failed += 1;
+                println!("FAIL  {name}: {e:#}");
+            }
+        }
+    }
+}
+
+/// Check that the server's configuration and the services it depends on work, and print a
+/// report of the results.
+pub async fn main(args: DoctorArgs) -> Result<()> {
+    let config = args.config;
+    let mut report = Report::default();
+
+    check_config(&mut report, &config);
+
+    if let Some(key) = config.credentials_key.as_deref() {
+        match db::credentials::MasterKey::from_base64(key) {
+            Ok(key) => {
+                db::credentials::set_master_key(key);
+                report.check("credentials key", Ok(()));
+            }
+            Err(e) => report.check("credentials key", Err(e.into())),
+        }
+    }
+
+    match PgConnection::establish(&config.database_url) {
+        Ok(mut conn) => {
+            report.check("database connection", Ok(()));
+            report.check("database schema", check_schema(&mut conn));
+            check_storage(&mut report, &mut conn).await;
+        }
+        Err(e) => {
+            report.check("database connection", Err(e.into()));
+            println!("Skipping storage locations since the database is unavailable");
+        }
+    }
+
+    check_encoders(&mut report, &config);
+
+    if report.failed > 0 {
+        return Err(eyre!("{} checks failed", report.failed));
+    }
+
+    println!("All checks passed");
+    Ok(())
+}
+
+fn check_config(report: &mut Report, config: &Config) {
+    report.check("cookie key", check_cookie_key(&config.cookie_key));
+
+    if let Some(key) = config.url_signing_key.as_deref() {
+        report.check("URL signing key", check_url_signing_key(key));
+    }
+
+    match (
+        config.imgproxy_key.as_deref(),
+        config.imgproxy_salt.as_deref(),
+    ) {
+        (Some(key), salt) => report.check(
+            "imgproxy key",
+            check_imgproxy_key(key, salt.unwrap_or_default()),
+        ),
+        (None, Some(_)) => report.check(
+            "imgproxy key",
+            Err(eyre!("imgproxy salt is set without an imgproxy key")),
+        ),
+        (None, None) => {}
+    }
+
+    report.check(
+        "trace sample rate",
+        if (0.0..=1.0).contains(&config.trace_sample_rate) {
+            Ok(())
+        } else {
+            Err(eyre!("must be between 0.0 and 1.0"))
+        },
+    );
+
+    report.check(
+        "geo country header",
+        http::HeaderName::try_from(config.geo_country_header.as_str())
+            .map(|_| ())
+            .map_err(eyre::Report::new),
+    );
+
+    report.check(
+        "queue database path",
+        check_queue_path(Path::new(&config.queue_db_path)),
+    );
+}
+
+fn check_cookie_key(key: &str) -> Result<()> {
+    let key = base64::engine::general_purpose::STANDARD
+        .decode(key)
+        .map_err(|e| eyre!("not valid base64: {e}"))?;
+    if key.len() < 64 {
+        return Err(eyre!("must be at least 64 bytes, found {}", key.len()));
+    }
+
+    Ok(())
+}
+
+fn check_url_signing_key(key: &str) -> Result<()> {
+    if key.len() < 32 {
+        return Err(eyre!(
+            "is {} characters, use at least 32 random characters",
+            key.len()
+        ));
+    }
+
+    Ok(())
+}
+
+fn check_imgproxy_key(key: &str, salt: &str) -> Result<()> {
+    let key = ImgproxyKey::new(key, salt)?;
+    // Make sure that signing works with the decoded key.
+    key.sign("/plain/doctor");
+    Ok(())
+}
+
+fn check_queue_path(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let metadata = std::fs::metadata(dir).map_err(|e| eyre!("{}: {e}", dir.display()))?;
+    if !metadata.is_dir() {
+        return Err(eyre!("{} is not a directory", dir.display()));
+    }
+    if metadata.permissions().readonly() {
+        return Err(eyre!("{} is read-only", dir.display()));
+    }
+
+    Ok(())
+}
+
+fn check_schema(conn: &mut PgConnection) -> Result<()> {
+    let pending = db::pending_migrations(conn)?;
+    if !pending.is_empty() {
+        return Err(eyre!("migrations not applied: {}", pending.join(", ")));
+    }
+
+    Ok(())
+}
+
+async fn check_storage(report: &mut Report, conn: &mut PgConnection) {
+    let locations = db::storage_locations::table
+        .filter(db::storage_locations::deleted.is_null())
+        .load::<StorageLocation>(conn);
+
+    let locations = match locations {
+        Ok(locations) => locations,
+        Err(e) => {
+            report.check("storage locations", Err(e.into()));
+            return;
+        }
+    };
+
+    if locations.is_empty() {
+        println!("No storage locations to check");
+    }
+
+    for location in locations {
+        let name = format!("storage location {} ({})", location.name, location.id);
+        report.check(
+            name,
+            check_storage_location(location.provider, &location.base_location).await,
+        );
+    }
+}
+
+/// Write, read, and delete a small file to make sure that the location is usable.
+async fn check_storage_location(
+    provider: db::storage_locations::Provider,
+    base_location: &str,
+) -> Result<()> {
+    let operator = storage::Provider::from_db(provider)?
+        .create_operator(base_location)
+        .await?;
+
+    match operator.health_check(STORAGE_PROBE_KEY).await.error() {
+        Some(error) => Err(eyre!(error)),
+        None => Ok(()),
+    }
+}
+
+fn check_encoders(report: &mut Report, config: &Config) {
+    let limits = convert::DecodeLimits {
+        max_width: Some(config.max_image_width),
+        max_height: Some(config.max_image_height),
+        max_pixels: Some(config.max_image_pixels),
+        max_decoded_bytes: Some(config.max_decoded_image_bytes),
+    };
+
+    let image = DynamicImage::ImageRgb8(RgbImage::from_fn(16, 16, |x, y| {
+        image::Rgb([(x * 16) as u8, (y * 16) as u8, 128])
+    }));
+
+    for format in [
+        db::ImageFormat::Png,
+        db::ImageFormat::Jpg,
+        db::ImageFormat::Webp,
+        db::ImageFormat::Avif,
+    ] {
+        report.check(
+            format!("{format:?} encoder"),
+            check_encoder(&image, format, &limits),
+        );
+    }
+}
+
+/// Encode an image and decode it again, which exercises the native libraries for the format.
+fn check_encoder(
+    image: &DynamicImage,
+    format: db::ImageFormat,
+    limits: &convert::DecodeLimits,
+) -> Result<()> {
+    let size = convert::ImageSizeTransform {
+        width: Some(8),
+        height: None,
+        preserve_aspect_ratio: true,
+    };
+
+    let output = convert::convert(image, format.into(), None, &size, &[])?;
+    let decoded = convert::image_from_bytes(&output.image, limits)?;
+    if decoded.width() != 8 || decoded.height() != 8 {
+        return Err(eyre!(
+            "decoded image was {}x{}, expected 8x8",
+            decoded.width(),
+            decoded.height()
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,258 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Duration, Utc};
+use clap::Args;
+use diesel::{prelude::*, PgConnection};
+use eyre::{eyre, Result};
+use pic_store_db::{
+    self as db, object_id::StorageLocationId, storage_locations::StorageLocation, BaseImageStatus,
+    OutputImageStatus, OutputMirrorStatus,
+};
+use pic_store_storage as storage;
+
+#[derive(Debug, Args)]
+pub struct GcArgs {
+    #[clap(short, long, help = "Database connection string", env = "DATABASE_URL")]
+    database: String,
+    #[clap(
+        long,
+        help = "The base64-encoded key that the storage credentials are encrypted with",
+        env = "CREDENTIALS_KEY"
+    )]
+    credentials_key: Option<String>,
+    #[clap(long, help = "Only check this storage location")]
+    location: Option<StorageLocationId>,
+    #[clap(long, help = "Delete the orphaned objects")]
+    delete: bool,
+    #[clap(
+        long,
+        default_value_t = 24,
+        help = "Objects modified more recently than this many hours ago are not treated as orphans, since an upload or conversion may still be writing them"
+    )]
+    min_age_hours: i64,
+}
+
+/// The objects that the database expects a storage location to have.
+#[derive(Default)]
+struct ExpectedObjects {
+    /// Every object that belongs to an image, including ones that may not have been written yet.
+    known: BTreeSet<String>,
+    /// Objects that belong to a finished image, and so should exist.
+    required: BTreeSet<String>,
+}
+
+impl ExpectedObjects {
+    fn add(&mut self, key: String, required: bool) {
+        if required {
+            self.required.insert(key.clone());
+        }
+        self.known.insert(key);
+    }
+}
+
+/// List the objects in each storage location and compare them to the images in the database.
+/// Objects that no image refers to are orphans, which can be left behind when the server
+/// crashes partway through writing or deleting an image. Orphans are only deleted with
+/// `--delete`.
+pub async fn main(args: GcArgs) -> Result<()> {
+    if let Some(key) = args.credentials_key.as_deref() {
+        db::credentials::set_master_key(db::credentials::MasterKey::from_base64(key)?);
+    }
+
+    let mut conn = PgConnection::establish(args.database.as_str())?;
+    let mut query = db::storage_locations::table
+        .filter(db::storage_locations::deleted.is_null())
+        .into_boxed();
+    if let Some(id) = args.location {
+        query = query.filter(db::storage_locations::id.eq(id));
+    }
+    let locations = query.load::<StorageLocation>(&mut conn)?;
+    if locations.is_empty() {
+        return Err(eyre!("No storage locations found"));
+    }
+
+    let cutoff = Utc::now() - Duration::hours(args.min_age_hours);
+    let mut total_orphans = 0;
+    let mut total_missing = 0;
+    let mut total_deleted = 0;
+    for location in locations {
+        println!(
+            "Checking storage location {} ({})",
+            location.name, location.id
+        );
+
+        let expected = expected_objects(&mut conn, location.id)?;
+        let operator = storage::Provider::from_db(location.provider.clone())?
+            .create_operator(&location.base_location)
+            .await?;
+        let stored = operator
+            .list()
+            .await
+            .map_err(|e| eyre!("Listing {}: {e}", location.name))?
+            .into_iter()
+            .map(|(key, meta)| (normalize_key(&key), (key, meta)))
+            .collect::<BTreeMap<_, _>>();
+
+        let orphans = stored
+            .iter()
+            .filter(|(key, (_, meta))| {
+                !expected.known.contains(*key) && meta.last_modified < cutoff
+            })
+            .map(|(_, (key, meta))| (key.as_str(), meta.size))
+            .collect::<Vec<_>>();
+        let missing = expected
+            .required
+            .iter()
+            .filter(|key| !stored.contains_key(*key))
+            .collect::<Vec<_>>();
+
+        for (key, size) in &orphans {
+            println!("  orphan: {key} ({size} bytes)");
+        }
+        for key in &missing {
+            println!("  missing: {key}");
+        }
+        println!(
+            "  {} objects, {} orphans ({} bytes), {} missing",
+            stored.len(),
+            orphans.len(),
+            orphans.iter().map(|(_, size)| size).sum::<usize>(),
+            missing.len()
+        );
+
+        if args.delete {
+            for (key, _) in &orphans {
+                operator
+                    .delete(key)
+                    .await
+                    .map_err(|e| eyre!("Deleting {key}: {e}"))?;
+                total_deleted += 1;
+            }
+        }
+
+        total_orphans += orphans.len();
+        total_missing += missing.len();
+    }
+
+    println!("Found {total_orphans} orphaned objects and {total_missing} missing objects");
+    if args.delete {
+        println!("Deleted {total_deleted} orphaned objects");
+    } else if total_orphans > 0 {
+        println!("Run again with --delete to remove the orphaned objects");
+    }
+
+    Ok(())
+}
+
+/// Find the originals, archived originals, output images, and mirror copies that should be in a
+/// storage location, keyed by their path under the location's base.
+fn expected_objects(
+    conn: &mut PgConnection,
+    location_id: StorageLocationId,
+) -> Result<ExpectedObjects> {
+    let mut expected = ExpectedObjects::default();
+
+    let originals = db::base_images::table
+        .inner_join(db::upload_profiles::table)
+        .inner_join(db::projects::table.on(db::projects::id.eq(db::base_images::project_id)))
+        .filter(db::upload_profiles::base_storage_location_id.eq(location_id))
+        .filter(db::base_images::original_removed.is_null())
+        .filter(db::base_images::status.ne(BaseImageStatus::Deleted))
+        .select((
+            db::projects::base_location,
+            db::upload_profiles::base_storage_location_path,
+            db::base_images::location,
+            db::base_images::status,
+        ))
+        .load::<(String, Option<String>, String, BaseImageStatus)>(conn)?;
+    for (project_base, profile_path, location, status) in originals {
+        let required = matches!(status, BaseImageStatus::Converting | BaseImageStatus::Ready);
+        expected.add(
+            object_key(&project_base, &profile_path, &location),
+            required,
+        );
+    }
+
+    // Archived originals keep the same path in the archive location.
+    let archived = db::base_images::table
+        .inner_join(db::upload_profiles::table)
+        .inner_join(db::projects::table.on(db::projects::id.eq(db::base_images::project_id)))
+        .filter(db::base_images::original_archive_location_id.eq(location_id))
+        .filter(db::base_images::original_removed.is_not_null())
+        .filter(db::base_images::status.ne(BaseImageStatus::Deleted))
+        .select((
+            db::projects::base_location,
+            db::upload_profiles::base_storage_location_path,
+            db::base_images::location,
+        ))
+        .load::<(String, Option<String>, String)>(conn)?;
+    for (project_base, profile_path, location) in archived {
+        expected.add(object_key(&project_base, &profile_path, &location), true);
+    }
+
+    let outputs = db::output_images::table
+        .inner_join(db::base_images::table)
+        .inner_join(
+            db::upload_profiles::table
+                .on(db::upload_profiles::id.eq(db::base_images::upload_profile_id)),
+        )
+        .inner_join(db::projects::table.on(db::projects::id.eq(db::base_images::project_id)))
+        .filter(db::upload_profiles::output_storage_location_id.eq(location_id))
+        .filter(db::output_images::status.ne(OutputImageStatus::Deleted))
+        .select((
+            db::projects::base_location,
+            db::upload_profiles::output_storage_location_path,
+            db::output_images::location,
+            db::output_images::status,
+        ))
+        .load::<(String, Option<String>, String, OutputImageStatus)>(conn)?;
+    for (project_base, profile_path, location, status) in outputs {
+        let required = status == OutputImageStatus::Ready;
+        expected.add(
+            object_key(&project_base, &profile_path, &location),
+            required,
+        );
+    }
+
+    // Mirror copies use the same paths as the output images. Only copies that were verified
+    // after they were written are required.
+    let mirrored = db::output_image_mirrors::table
+        .inner_join(db::output_images::table.inner_join(db::base_images::table))
+        .inner_join(
+            db::upload_profiles::table
+                .on(db::upload_profiles::id.eq(db::base_images::upload_profile_id)),
+        )
+        .inner_join(db::projects::table.on(db::projects::id.eq(db::base_images::project_id)))
+        .filter(db::output_image_mirrors::storage_location_id.eq(location_id))
+        .filter(db::output_images::status.ne(OutputImageStatus::Deleted))
+        .select((
+            db::projects::base_location,
+            db::upload_profiles::output_storage_location_path,
+            db::output_images::location,
+            db::output_image_mirrors::status,
+        ))
+        .load::<(String, Option<String>, String, OutputMirrorStatus)>(conn)?;
+    for (project_base, profile_path, location, status) in mirrored {
+        expected.add(
+            object_key(&project_base, &profile_path, &location),
+            status == OutputMirrorStatus::Ready,
+        );
+    }
+
+    Ok(expected)
+}
+
+/// The path of an image under its storage location's base, matching the operators created with
+/// [db::image_base_location].
+fn object_key(project_base: &str, profile_path: &Option<String>, location: &str) -> String {
+    let base = db::image_base_location("", project_base, profile_path);
+    normalize_key(&format!("{base}/{location}"))
+}
+
+/// Remove empty path segments, which the storage providers ignore.
+fn normalize_key(key: &str) -> String {
+    key.split('/')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
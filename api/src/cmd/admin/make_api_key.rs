@@ -18,6 +18,11 @@ pub struct MakeApiKeyArgs {
     no_inherit_user_permissions: bool,
     #[clap(name = "desc", long, help = "A description for the API key")]
     description: Option<String>,
+    #[clap(
+        long,
+        help = "Only allow the key to upload images through this upload profile"
+    )]
+    upload_profile: Option<UploadProfileId>,
 }
 
 pub fn main(args: MakeApiKeyArgs) -> Result<()> {
@@ -28,6 +33,7 @@ pub fn main(args: MakeApiKeyArgs) -> Result<()> {
         args.no_inherit_user_permissions,
         args.description.as_deref(),
         args.expires,
+        args.upload_profile,
     )?;
 
     println!("Key ID: {}", key.id);
@@ -0,0 +1,288 @@
+use clap::Args;
+use diesel::{prelude::*, Connection, PgConnection};
+use eyre::{eyre, Result};
+use pic_store_db::{
+    self as db, image_base_location,
+    object_id::{StorageLocationId, UploadProfileId},
+    storage_locations::StorageLocation,
+    BaseImageStatus, OutputImageStatus,
+};
+use pic_store_storage::{self as storage, Operator, ParallelGet};
+
+#[derive(Debug, Args)]
+pub struct MigrateStorageArgs {
+    #[clap(short, long, help = "Database connection string", env = "DATABASE_URL")]
+    database: String,
+    #[clap(
+        long,
+        help = "The base64-encoded key that the storage credentials are encrypted with",
+        env = "CREDENTIALS_KEY"
+    )]
+    credentials_key: Option<String>,
+    #[clap(long, help = "The storage location to move the images out of")]
+    from: StorageLocationId,
+    #[clap(long, help = "The storage location to move the images to")]
+    to: StorageLocationId,
+    #[clap(
+        long,
+        help = "Delete the objects in the old storage location once the upload profiles have been updated"
+    )]
+    delete_source: bool,
+}
+
+/// The part of an upload profile that uses the storage location being migrated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Base,
+    Output,
+}
+
+struct ProfileMigration {
+    upload_profile_id: UploadProfileId,
+    role: Role,
+    source: Operator,
+    destination: Operator,
+    /// The object locations, relative to the profile's path in the storage location, and the
+    /// checksum recorded for each one.
+    objects: Vec<(String, Option<String>)>,
+}
+
+#[derive(Default)]
+struct Totals {
+    copied: usize,
+    missing: usize,
+    bytes: usize,
+}
+
+/// Copy the original and output images for every upload profile that uses one storage location
+/// to another, and then point the upload profiles at the new location.
+///
+/// Objects keep the same keys in the new location, even if it has a different key prefix
+/// template. The upload profiles are only updated after every object has been copied and
+/// verified, so a failed migration can be run again.
+pub async fn main(args: MigrateStorageArgs) -> Result<()> {
+    if args.from == args.to {
+        return Err(eyre!("--from and --to must be different storage locations"));
+    }
+
+    if let Some(key) = args.credentials_key.as_deref() {
+        db::credentials::set_master_key(db::credentials::MasterKey::from_base64(key)?);
+    }
+
+    let mut conn = PgConnection::establish(args.database.as_str())?;
+    let from = load_location(&mut conn, args.from)?;
+    let to = load_location(&mut conn, args.to)?;
+    if from.team_id != to.team_id {
+        return Err(eyre!("The storage locations belong to different teams"));
+    }
+
+    let migrations = plan(&mut conn, &from, &to).await?;
+    if migrations.is_empty() {
+        println!("No upload profiles use storage location {}", from.name);
+        return Ok(());
+    }
+
+    let settings = ParallelGet::default();
+    let mut totals = Totals::default();
+    for migration in &migrations {
+        println!(
+            "Copying {} {} images for upload profile {}",
+            migration.objects.len(),
+            match migration.role {
+                Role::Base => "original",
+                Role::Output => "output",
+            },
+            migration.upload_profile_id
+        );
+
+        for (location, checksum) in &migration.objects {
+            copy_object(
+                migration,
+                location,
+                checksum.as_deref(),
+                &settings,
+                &mut totals,
+            )
+            .await?;
+        }
+    }
+
+    conn.transaction(|conn| {
+        for migration in &migrations {
+            let profile = db::upload_profiles::table
+                .filter(db::upload_profiles::id.eq(migration.upload_profile_id));
+            match migration.role {
+                Role::Base => diesel::update(profile)
+                    .set(db::upload_profiles::base_storage_location_id.eq(to.id))
+                    .execute(conn)?,
+                Role::Output => diesel::update(profile)
+                    .set(db::upload_profiles::output_storage_location_id.eq(to.id))
+                    .execute(conn)?,
+            };
+        }
+
+        Ok::<_, eyre::Report>(())
+    })?;
+
+    println!(
+        "Copied {} objects ({} bytes) from {} to {}",
+        totals.copied, totals.bytes, from.name, to.name
+    );
+    if totals.missing > 0 {
+        println!(
+            "{} objects were missing from {} and were skipped",
+            totals.missing, from.name
+        );
+    }
+
+    if args.delete_source {
+        let mut deleted = 0;
+        for migration in &migrations {
+            for (location, _) in &migration.objects {
+                migration.source.delete(location).await?;
+                deleted += 1;
+            }
+        }
+
+        println!("Deleted {deleted} objects from {}", from.name);
+    }
+
+    Ok(())
+}
+
+fn load_location(conn: &mut PgConnection, id: StorageLocationId) -> Result<StorageLocation> {
+    db::storage_locations::table
+        .filter(db::storage_locations::id.eq(id))
+        .filter(db::storage_locations::deleted.is_null())
+        .first::<StorageLocation>(conn)
+        .optional()?
+        .ok_or_else(|| eyre!("Storage location {id} not found"))
+}
+
+/// Find the upload profiles that use the `from` location and the objects to copy for each one.
+async fn plan(
+    conn: &mut PgConnection,
+    from: &StorageLocation,
+    to: &StorageLocation,
+) -> Result<Vec<ProfileMigration>> {
+    let profiles = db::upload_profiles::table
+        .inner_join(db::projects::table.on(db::projects::id.eq(db::upload_profiles::project_id)))
+        .filter(
+            db::upload_profiles::base_storage_location_id
+                .eq(from.id)
+                .or(db::upload_profiles::output_storage_location_id.eq(from.id)),
+        )
+        .select((
+            db::upload_profiles::id,
+            db::projects::base_location,
+            db::upload_profiles::base_storage_location_id,
+            db::upload_profiles::base_storage_location_path,
+            db::upload_profiles::output_storage_location_id,
+            db::upload_profiles::output_storage_location_path,
+        ))
+        .load::<(
+            UploadProfileId,
+            String,
+            StorageLocationId,
+            Option<String>,
+            StorageLocationId,
+            Option<String>,
+        )>(conn)?;
+
+    let source_provider = storage::Provider::from_db(from.provider.clone())?;
+    let destination_provider = storage::Provider::from_db(to.provider.clone())?;
+
+    let mut migrations = Vec::new();
+    for (id, project_base_location, base_id, base_path, output_id, output_path) in profiles {
+        for (role, location_id, profile_path) in [
+            (Role::Base, base_id, base_path),
+            (Role::Output, output_id, output_path),
+        ] {
+            if location_id != from.id {
+                continue;
+            }
+
+            let objects = match role {
+                Role::Base => db::base_images::table
+                    .filter(db::base_images::upload_profile_id.eq(id))
+                    .filter(db::base_images::status.ne_all(vec![
+                        BaseImageStatus::AwaitingUpload,
+                        BaseImageStatus::Deleted,
+                    ]))
+                    .filter(db::base_images::original_removed.is_null())
+                    .select((db::base_images::location, db::base_images::hash))
+                    .load::<(String, Option<String>)>(conn)?,
+                Role::Output => db::output_images::table
+                    .inner_join(db::base_images::table)
+                    .filter(db::base_images::upload_profile_id.eq(id))
+                    .filter(db::output_images::status.eq(OutputImageStatus::Ready))
+                    .select((db::output_images::location, db::output_images::etag))
+                    .load::<(String, Option<String>)>(conn)?,
+            };
+
+            let source_location =
+                image_base_location(&from.base_location, &project_base_location, &profile_path);
+            let destination_location =
+                image_base_location(&to.base_location, &project_base_location, &profile_path);
+
+            migrations.push(ProfileMigration {
+                upload_profile_id: id,
+                role,
+                source: source_provider.create_operator(&source_location).await?,
+                destination: destination_provider
+                    .create_operator(&destination_location)
+                    .await?,
+                objects,
+            });
+        }
+    }
+
+    Ok(migrations)
+}
+
+/// Copy an object and make sure that the new copy matches the original.
+async fn copy_object(
+    migration: &ProfileMigration,
+    location: &str,
+    checksum: Option<&str>,
+    settings: &ParallelGet,
+    totals: &mut Totals,
+) -> Result<()> {
+    let contents = match migration.source.get_parallel(location, settings).await {
+        Ok(contents) => contents,
+        Err(e) if e.is_not_found() => {
+            println!("Skipping {location}, which is missing from the source");
+            totals.missing += 1;
+            return Ok(());
+        }
+        Err(e) => return Err(eyre!("Reading {location}: {e}")),
+    };
+
+    let hash = blake3::hash(&contents);
+    if let Some(checksum) = checksum {
+        if hash.to_hex().as_str() != checksum {
+            return Err(eyre!(
+                "{location} does not match its recorded checksum, so it was not copied"
+            ));
+        }
+    }
+
+    migration
+        .destination
+        .put(location, contents.clone())
+        .await
+        .map_err(|e| eyre!("Writing {location}: {e}"))?;
+
+    let copied = migration
+        .destination
+        .get_parallel(location, settings)
+        .await
+        .map_err(|e| eyre!("Reading back {location}: {e}"))?;
+    if blake3::hash(&copied) != hash {
+        return Err(eyre!("The copy of {location} does not match the original"));
+    }
+
+    totals.copied += 1;
+    totals.bytes += contents.len();
+    Ok(())
+}
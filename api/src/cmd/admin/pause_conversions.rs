@@ -0,0 +1,49 @@
+use clap::Args;
+use diesel::{Connection, PgConnection};
+use eyre::Result;
+use pic_store_db::{conversion_pauses, object_id::TeamId};
+
+#[derive(Debug, Args)]
+pub struct PauseConversionsArgs {
+    #[clap(short, long, help = "Database connection string", env = "DATABASE_URL")]
+    database: String,
+    #[clap(long, help = "Only pause conversions for this team")]
+    team: Option<TeamId>,
+    #[clap(long, help = "Why conversions are paused")]
+    reason: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ResumeConversionsArgs {
+    #[clap(short, long, help = "Database connection string", env = "DATABASE_URL")]
+    database: String,
+    #[clap(
+        long,
+        help = "The team to resume conversions for, if it was paused on its own"
+    )]
+    team: Option<TeamId>,
+}
+
+pub fn pause(args: PauseConversionsArgs) -> Result<()> {
+    let mut conn = PgConnection::establish(args.database.as_str())?;
+    conversion_pauses::pause(&mut conn, args.team, args.reason)?;
+
+    match args.team {
+        Some(team) => println!("Paused conversions for team {team}"),
+        None => println!("Paused conversions for every team"),
+    }
+
+    Ok(())
+}
+
+pub fn resume(args: ResumeConversionsArgs) -> Result<()> {
+    let mut conn = PgConnection::establish(args.database.as_str())?;
+    if !conversion_pauses::resume(&mut conn, args.team)? {
+        println!("Conversions were not paused");
+        return Ok(());
+    }
+
+    println!("Resumed conversions. The server will queue any held conversions within a minute.");
+
+    Ok(())
+}
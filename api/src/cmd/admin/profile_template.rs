@@ -0,0 +1,74 @@
+use clap::Args;
+use diesel::{prelude::*, Connection, PgConnection};
+use eyre::{eyre, Result};
+use pic_store_api::profile_templates;
+use pic_store_db::{
+    conversion_profiles::{self, NewConversionProfile},
+    object_id::*,
+};
+
+#[derive(Debug, Args)]
+pub struct ProfileTemplateArgs {
+    #[clap(help = "The template to install. Lists the templates if omitted")]
+    template: Option<String>,
+    #[clap(
+        short,
+        long,
+        help = "The team that will own the profile",
+        env = "TEAM_ID"
+    )]
+    team: Option<TeamId>,
+    #[clap(
+        short,
+        long,
+        help = "The project for the profile. The profile is available to every project in the team if omitted"
+    )]
+    project: Option<ProjectId>,
+    #[clap(
+        short,
+        long,
+        help = "The name of the profile. Defaults to the template name"
+    )]
+    name: Option<String>,
+    #[clap(short, long, help = "Database connection string", env = "DATABASE_URL")]
+    database: Option<String>,
+}
+
+pub fn main(args: ProfileTemplateArgs) -> Result<()> {
+    let Some(template_name) = args.template else {
+        for template in profile_templates::TEMPLATES {
+            println!("{:12} {}", template.name, template.description);
+        }
+        return Ok(());
+    };
+
+    let template = profile_templates::find(&template_name)
+        .ok_or_else(|| eyre!("Unknown profile template {template_name}"))?;
+    let team_id = args.team.ok_or_else(|| eyre!("--team is required"))?;
+    let database = args
+        .database
+        .ok_or_else(|| eyre!("--database is required"))?;
+
+    let mut conn = PgConnection::establish(database.as_str())?;
+    let id = ConversionProfileId::new();
+    conn.transaction(|conn| {
+        diesel::insert_into(conversion_profiles::table)
+            .values(NewConversionProfile {
+                id,
+                name: args.name.unwrap_or_else(|| template.name.to_string()),
+                team_id,
+                project_id: args.project,
+                output: template.output(),
+                output_key_template: None,
+                extends: None,
+                overrides: None,
+            })
+            .execute(conn)?;
+        conversion_profiles::record_version(conn, id)?;
+        Ok::<_, eyre::Report>(())
+    })?;
+
+    println!("Conversion profile ID: {id}");
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use clap::Args;
+use diesel::{prelude::*, Connection, PgConnection};
+use eyre::Result;
+use pic_store_db::{
+    credentials::MasterKey,
+    object_id::StorageLocationId,
+    storage_locations::{self, CdnPurge, Provider},
+};
+
+#[derive(Debug, Args)]
+pub struct ReencryptCredentialsArgs {
+    #[clap(short, long, help = "Database connection string", env = "DATABASE_URL")]
+    database: String,
+    #[clap(
+        long,
+        help = "The base64-encoded key to encrypt the credentials with",
+        env = "CREDENTIALS_KEY"
+    )]
+    key: String,
+    #[clap(
+        long,
+        help = "The previous key, when rotating keys. Credentials that are already encrypted with the new key can't be read with this set"
+    )]
+    old_key: Option<String>,
+}
+
+pub fn main(args: ReencryptCredentialsArgs) -> Result<()> {
+    let key = MasterKey::from_base64(&args.key)?;
+    let old_key = args
+        .old_key
+        .as_deref()
+        .map(MasterKey::from_base64)
+        .transpose()?;
+    let decrypt_key = old_key.as_ref().unwrap_or(&key);
+
+    let mut conn = PgConnection::establish(args.database.as_str())?;
+    let count = conn.transaction(|conn| {
+        let locations = storage_locations::table
+            .select((
+                storage_locations::id,
+                storage_locations::provider,
+                storage_locations::cdn_purge,
+            ))
+            .for_update()
+            .load::<(StorageLocationId, Provider, Option<CdnPurge>)>(conn)?;
+
+        for (id, provider, cdn_purge) in &locations {
+            let provider = provider
+                .clone()
+                .map_credentials(|value| decrypt_key.decrypt(&value))?
+                .map_credentials(|value| key.encrypt(&value))?;
+            let cdn_purge = cdn_purge
+                .clone()
+                .map(|cdn| {
+                    cdn.map_credentials(|value| decrypt_key.decrypt(&value))?
+                        .map_credentials(|value| key.encrypt(&value))
+                })
+                .transpose()?;
+
+            diesel::update(storage_locations::table)
+                .filter(storage_locations::id.eq(id))
+                .set((
+                    storage_locations::provider.eq(provider),
+                    storage_locations::cdn_purge.eq(cdn_purge),
+                ))
+                .execute(conn)?;
+        }
+
+        Ok::<_, eyre::Report>(locations.len())
+    })?;
+
+    println!("Encrypted the credentials for {count} storage locations");
+
+    Ok(())
+}
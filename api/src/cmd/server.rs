@@ -1,4 +1,4 @@
-use pic_store_api::tracing_config::{self, HoneycombConfig, TracingExportConfig};
+use pic_store_api::tracing_config::{self, HoneycombConfig, SamplingConfig, TracingExportConfig};
 
 pub async fn run(
     mut config: pic_store_api::config::Config,
@@ -14,7 +14,15 @@ pub async fn run(
         TracingExportConfig::None
     };
 
-    tracing_config::configure(tracing_export_config)?;
+    let sampling = SamplingConfig {
+        sample_rate: config.trace_sample_rate,
+        always_sample_errors: config.trace_sample_errors,
+        slow_threshold: config
+            .trace_slow_threshold_ms
+            .map(std::time::Duration::from_millis),
+    };
+
+    tracing_config::configure(tracing_export_config, sampling)?;
 
     let server = pic_store_api::create_server(config).await?;
     let result = server.run().await;
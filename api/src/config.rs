@@ -24,6 +24,30 @@ pub struct Config {
     #[clap(long, env)]
     pub jaeger_endpoint: Option<String>,
 
+    #[clap(
+        long,
+        env,
+        help = "The fraction of traces to export, from 0.0 to 1.0",
+        default_value_t = 1.0
+    )]
+    pub trace_sample_rate: f64,
+
+    #[clap(
+        long,
+        env,
+        help = "Export traces containing errors even when they are not sampled",
+        default_value_t = true,
+        action = clap::ArgAction::Set
+    )]
+    pub trace_sample_errors: bool,
+
+    #[clap(
+        long,
+        env,
+        help = "Export traces for requests taking at least this many milliseconds, even when they are not sampled"
+    )]
+    pub trace_slow_threshold_ms: Option<u64>,
+
     #[clap(
         long,
         env,
@@ -34,6 +58,35 @@ pub struct Config {
     #[clap(long, env, help = "The name of the session cookie", default_value_t = String::from("sid"))]
     pub session_cookie_name: String,
 
+    #[clap(
+        long,
+        env,
+        help = "A secret key for signing image URLs. Signed URLs are disabled if not set"
+    )]
+    pub url_signing_key: Option<String>,
+
+    #[clap(
+        long,
+        env,
+        help = "The hex-encoded key for imgproxy-style URL signatures. Signatures are not checked if not set"
+    )]
+    pub imgproxy_key: Option<String>,
+
+    #[clap(
+        long,
+        env,
+        help = "The hex-encoded salt for imgproxy-style URL signatures"
+    )]
+    pub imgproxy_salt: Option<String>,
+
+    #[clap(
+        long,
+        env,
+        help = "The header that the CDN uses to pass the viewer's country code",
+        default_value_t = String::from("cf-ipcountry")
+    )]
+    pub geo_country_header: String,
+
     #[clap(
         long,
         env,
@@ -41,4 +94,275 @@ pub struct Config {
         default_value_t = false
     )]
     pub allow_local_fs: bool,
+
+    #[clap(
+        long,
+        env,
+        help = "The directory for local filesystem storage locations. Relative base locations are placed inside it, and other base locations must be inside it too"
+    )]
+    pub local_storage_dir: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        env,
+        help = "A Cedar policy file that every authenticated request must be permitted by, in addition to the normal permission checks"
+    )]
+    pub authz_policy_file: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        env,
+        help = "A base64-encoded 32 byte key for encrypting storage location credentials. Credentials are stored unencrypted if not set"
+    )]
+    pub credentials_key: Option<String>,
+
+    #[clap(
+        long,
+        env,
+        help = "A captioning service that generates alt text for images uploaded without any. Alt text is not generated if not set"
+    )]
+    pub captioning_url: Option<String>,
+
+    #[clap(long, env, help = "A bearer token for the captioning service")]
+    pub captioning_api_key: Option<String>,
+
+    #[clap(
+        long,
+        env,
+        help = "Extract text from uploaded images with tesseract, using this language such as eng. Requires the ocr feature"
+    )]
+    pub ocr_language: Option<String>,
+
+    #[clap(
+        long,
+        env,
+        help = "Decode QR codes and barcodes in uploaded images. Requires the barcodes feature",
+        default_value_t = false
+    )]
+    pub detect_codes: bool,
+
+    #[clap(
+        long,
+        env,
+        help = "Accept imgix query parameters on the serve route",
+        default_value_t = false
+    )]
+    pub imgix_compat: bool,
+
+    #[clap(
+        long,
+        env,
+        help = "Add Link preload headers to served images, which CDNs can send as 103 Early Hints",
+        default_value_t = false
+    )]
+    pub early_hints: bool,
+
+    #[clap(
+        long,
+        env,
+        help = "The most image variants that the serve route renders at once. Other requests for new variants wait for a slot",
+        default_value_t = 4
+    )]
+    pub max_concurrent_renders: usize,
+
+    #[clap(
+        long,
+        env,
+        help = "The rate limit for each team's uploads, as requests per minute, optionally followed by `:` and the burst size, like `60:20`"
+    )]
+    pub rate_limit_uploads: Option<crate::rate_limit::RateLimit>,
+
+    #[clap(
+        long,
+        env,
+        help = "The rate limit for rendering images, such as the serve routes and conversion previews. Unauthenticated requests are limited per client address"
+    )]
+    pub rate_limit_transforms: Option<crate::rate_limit::RateLimit>,
+
+    #[clap(
+        long,
+        env,
+        help = "The rate limit for each client address sending abuse reports",
+        default_value = "10:5"
+    )]
+    pub rate_limit_reports: Option<crate::rate_limit::RateLimit>,
+
+    #[clap(long, env, help = "The rate limit for each team's other read requests")]
+    pub rate_limit_reads: Option<crate::rate_limit::RateLimit>,
+
+    #[clap(
+        long,
+        env,
+        help = "The rate limit for each team's other write requests"
+    )]
+    pub rate_limit_writes: Option<crate::rate_limit::RateLimit>,
+
+    #[clap(
+        long,
+        env,
+        help = "A header that a trusted proxy sets to the client's address, such as `x-forwarded-for`. The address of the connection is used when this is not set"
+    )]
+    pub client_ip_header: Option<String>,
+
+    #[clap(
+        long,
+        env,
+        help = "Allow projects to crawl their site's sitemap to find the pages that use each image. The server fetches the URLs that users provide, so only enable this where it can't reach internal services",
+        default_value_t = false
+    )]
+    pub reference_crawler: bool,
+
+    #[clap(
+        long,
+        env,
+        help = "Check a sample of each project's delivery URLs through their public path every this many hours, and report the ones that fail. Disabled when not set"
+    )]
+    pub link_check_interval_hours: Option<u64>,
+
+    #[clap(
+        long,
+        env,
+        help = "The number of images to check in each project on each link check",
+        default_value_t = 20
+    )]
+    pub link_check_sample_size: i64,
+
+    #[clap(
+        long,
+        env,
+        help = "Measure the conversion queue against its objectives every this many seconds",
+        default_value_t = 60
+    )]
+    pub slo_check_interval_seconds: u64,
+
+    #[clap(
+        long,
+        env,
+        help = "Alert when more than this many images are waiting for conversion"
+    )]
+    pub slo_max_queue_depth: Option<i64>,
+
+    #[clap(
+        long,
+        env,
+        help = "Alert when the oldest image waiting for conversion has waited longer than this many seconds"
+    )]
+    pub slo_max_worker_lag_seconds: Option<u64>,
+
+    #[clap(
+        long,
+        env,
+        help = "Alert when the 95th percentile of the time that conversions waited in the queue over the last 15 minutes is longer than this many seconds"
+    )]
+    pub slo_max_p95_latency_seconds: Option<u64>,
+
+    #[clap(
+        long,
+        env,
+        help = "POST a JSON notification to this URL when a queue objective is breached or recovers"
+    )]
+    pub slo_alert_webhook_url: Option<String>,
+
+    #[clap(
+        long,
+        env,
+        help = "Save request and response pairs to this directory for debugging. Ignored in production"
+    )]
+    pub record_requests_dir: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        env,
+        help = "The fraction of requests to record, from 0.0 to 1.0. Requests with the X-Record-Request header are always recorded",
+        default_value_t = 0.0
+    )]
+    pub record_requests_sample_rate: f64,
+
+    #[clap(
+        long,
+        env,
+        help = "Reject API requests with unknown JSON fields. Clients can also opt in with the X-Strict-Json header",
+        default_value_t = false
+    )]
+    pub strict_json: bool,
+
+    #[clap(
+        long,
+        env,
+        help = "The maximum width of an image that will be converted",
+        default_value_t = 16384
+    )]
+    pub max_image_width: u32,
+
+    #[clap(
+        long,
+        env,
+        help = "The maximum height of an image that will be converted",
+        default_value_t = 16384
+    )]
+    pub max_image_height: u32,
+
+    #[clap(
+        long,
+        env,
+        help = "The maximum number of pixels in an image that will be converted",
+        default_value_t = 100_000_000
+    )]
+    pub max_image_pixels: u64,
+
+    #[clap(
+        long,
+        env,
+        help = "The maximum memory, in bytes, that a decoded image may use",
+        default_value_t = 512 * 1024 * 1024
+    )]
+    pub max_decoded_image_bytes: u64,
+
+    #[clap(
+        long,
+        env,
+        help = "Originals larger than this many bytes are downloaded by workers as concurrent range requests of this size",
+        default_value_t = 8 * 1024 * 1024
+    )]
+    pub download_part_size: usize,
+
+    #[clap(
+        long,
+        env,
+        help = "The maximum number of concurrent range requests when downloading an original",
+        default_value_t = 8
+    )]
+    pub download_concurrency: usize,
+
+    #[clap(
+        long,
+        env,
+        help = "The maximum size of an uploaded original, in bytes",
+        default_value_t = 1024 * 1024 * 1024
+    )]
+    pub max_upload_size: usize,
+
+    #[clap(
+        long,
+        env,
+        help = "Originals are uploaded to storage in parts of about this many bytes. S3 requires at least 5MiB",
+        default_value_t = 16 * 1024 * 1024
+    )]
+    pub upload_part_size: usize,
+
+    #[clap(
+        long,
+        env,
+        help = "How long fetching an image for an upload from a URL may take, in seconds",
+        default_value_t = 30
+    )]
+    pub url_upload_timeout_seconds: u64,
+
+    #[clap(
+        long,
+        env,
+        help = "Allow uploads from URLs, sitemap crawls, delivery URL checks, and post-processing callbacks to reach loopback, private, and other internal addresses. Only enable this where those addresses can't reach internal services",
+        default_value_t = false
+    )]
+    pub url_upload_allow_private_networks: bool,
 }
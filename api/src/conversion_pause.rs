@@ -0,0 +1,135 @@
+//! Pausing conversions, for every team or for one team, so that storage incidents or migrations
+//! don't cause a pile of failed jobs. Conversion jobs for a paused team are held in the database
+//! instead of running, and are queued again once the pause is lifted.
+
+use std::time::Duration;
+
+use db::{conversion_pauses, object_id::TeamId, PoolExt};
+use diesel::PgConnection;
+use pic_store_db as db;
+use tracing::{event, Level};
+
+use crate::{
+    jobs::{CreateOutputImagesJobPayload, CREATE_OUTPUT_IMAGES},
+    shared_state::AppState,
+    Error,
+};
+
+/// How often to check for held conversions that can run again. Conversions are also released
+/// right away when a pause is lifted through the API.
+const RELEASE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The number of held conversions to release in each database query.
+const RELEASE_BATCH_SIZE: i64 = 100;
+
+/// Hold a conversion job if conversions are paused for the team. Returns true if it was held.
+pub fn hold_if_paused(
+    conn: &mut PgConnection,
+    team_id: TeamId,
+    payload: &CreateOutputImagesJobPayload,
+) -> Result<bool, Error> {
+    if !conversion_pauses::is_paused(conn, team_id)? {
+        return Ok(false);
+    }
+
+    let value = serde_json::to_value(payload).map_err(eyre::Report::new)?;
+    conversion_pauses::hold(conn, payload.base_image, team_id, value)?;
+    Ok(true)
+}
+
+/// Queue a conversion job, or hold it if conversions are paused for the team.
+pub async fn queue_conversion(
+    state: &AppState,
+    team_id: TeamId,
+    payload: CreateOutputImagesJobPayload,
+) -> Result<(), Error> {
+    let held = {
+        let payload = payload.clone();
+        state
+            .db
+            .interact(move |conn| hold_if_paused(conn, team_id, &payload))
+            .await?
+    };
+
+    if held {
+        event!(Level::INFO, base_image = %payload.base_image, "Conversions are paused, holding conversion job");
+        return Ok(());
+    }
+
+    let job_id = effectum::Job::builder(CREATE_OUTPUT_IMAGES)
+        .json_payload(&payload)?
+        .add_to(&state.queue)
+        .await?;
+    event!(Level::INFO, %job_id, "enqueued image conversion job");
+
+    Ok(())
+}
+
+/// Queue the held conversions of teams that are no longer paused, and return how many were
+/// queued.
+pub async fn release_held_conversions(state: &AppState) -> Result<usize, Error> {
+    let mut count = 0;
+    loop {
+        let held = state
+            .db
+            .interact(|conn| {
+                conversion_pauses::take_released(conn, RELEASE_BATCH_SIZE).map_err(Error::from)
+            })
+            .await?;
+
+        let done = (held.len() as i64) < RELEASE_BATCH_SIZE;
+        for conversion in held {
+            let queued = async {
+                effectum::Job::builder(CREATE_OUTPUT_IMAGES)
+                    .json_payload(&conversion.payload)?
+                    .add_to(&state.queue)
+                    .await
+            }
+            .await;
+
+            if let Err(e) = queued {
+                // Put it back so that it isn't lost.
+                state
+                    .db
+                    .interact(move |conn| {
+                        conversion_pauses::hold(
+                            conn,
+                            conversion.base_image_id,
+                            conversion.team_id,
+                            conversion.payload,
+                        )
+                        .map_err(Error::from)
+                    })
+                    .await?;
+                return Err(e.into());
+            }
+
+            count += 1;
+        }
+
+        if done {
+            break;
+        }
+    }
+
+    if count > 0 {
+        event!(Level::INFO, count, "Released held conversion jobs");
+    }
+
+    Ok(count)
+}
+
+/// Periodically release held conversions, which picks up pauses that were lifted from the
+/// command line.
+pub fn start_release_task(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(RELEASE_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = release_held_conversions(&state).await {
+                event!(Level::ERROR, error = ?e, "Failed to release held conversions");
+            }
+        }
+    })
+}
@@ -0,0 +1,380 @@
+//! Per-project CORS headers for the serve and upload routes, so that browser apps can fetch and
+//! upload images from the origins that the project trusts. Projects without CORS settings don't
+//! get any CORS headers.
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use db::{base_images, object_id::BaseImageId, projects, projects::CorsSettings, PoolExt};
+use diesel::prelude::*;
+use pic_store_db as db;
+
+use crate::{shared_state::AppState, Error};
+
+/// Find the image that a CORS-enabled route refers to.
+fn cors_image_id(method: &Method, path: &str) -> Option<BaseImageId> {
+    let segments = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+
+    let is_preflight = method == Method::OPTIONS;
+    let image_id = match segments.as_slice() {
+        ["serve", image_id] if is_preflight || method == Method::GET || method == Method::HEAD => {
+            image_id
+        }
+        ["api", "images", image_id, "upload"] if is_preflight || method == Method::POST => image_id,
+        _ => return None,
+    };
+
+    image_id.parse().ok()
+}
+
+fn origin_allowed(settings: &CorsSettings, origin: &str) -> bool {
+    settings
+        .allowed_origins
+        .iter()
+        .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(origin))
+}
+
+/// Check a preflight request's method and headers against the settings.
+fn preflight_allowed(settings: &CorsSettings, headers: &HeaderMap) -> bool {
+    let method_allowed = headers
+        .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|value| value.to_str().ok())
+        .map(|method| {
+            matches!(method, "GET" | "HEAD" | "POST")
+                || settings.allowed_methods.iter().any(|m| m == method)
+        })
+        .unwrap_or(false);
+
+    let headers_allowed = headers
+        .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+        .and_then(|value| value.to_str().ok())
+        .map(|requested| {
+            requested
+                .split(',')
+                .map(|h| h.trim())
+                .filter(|h| !h.is_empty())
+                .all(|h| {
+                    settings
+                        .allowed_headers
+                        .iter()
+                        .any(|allowed| allowed.eq_ignore_ascii_case(h))
+                })
+        })
+        .unwrap_or(true);
+
+    method_allowed && headers_allowed
+}
+
+/// Credentials are only allowed for origins that are listed by name, so a wildcard can never let
+/// every site make credentialed requests.
+fn credentials_allowed(settings: &CorsSettings, origin: &HeaderValue) -> bool {
+    settings.allow_credentials
+        && origin
+            .to_str()
+            .map(|origin| {
+                settings
+                    .allowed_origins
+                    .iter()
+                    .any(|allowed| allowed != "*" && allowed.eq_ignore_ascii_case(origin))
+            })
+            .unwrap_or(false)
+}
+
+fn add_cors_headers(headers: &mut HeaderMap, settings: &CorsSettings, origin: &HeaderValue) {
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+    if credentials_allowed(settings, origin) {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+    }
+}
+
+fn add_preflight_headers(headers: &mut HeaderMap, settings: &CorsSettings) {
+    let methods = ["GET", "HEAD", "POST"]
+        .into_iter()
+        .chain(settings.allowed_methods.iter().map(|m| m.as_str()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if let Ok(value) = HeaderValue::from_str(&methods) {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, value);
+    }
+
+    if !settings.allowed_headers.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(&settings.allowed_headers.join(", ")) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, value);
+        }
+    }
+
+    if let Some(max_age) = settings.max_age {
+        headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from(max_age));
+    }
+}
+
+/// Add CORS headers for the image's project, and answer preflight requests.
+pub async fn apply_cors<B>(
+    State(state): State<AppState>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, Error> {
+    let origin = req.headers().get(header::ORIGIN).cloned();
+    let image_id = cors_image_id(req.method(), req.uri().path());
+    let (Some(origin), Some(image_id)) = (origin, image_id) else {
+        return Ok(next.run(req).await);
+    };
+
+    let settings = state
+        .db
+        .interact(move |conn| {
+            base_images::table
+                .inner_join(projects::table.on(projects::id.eq(base_images::project_id)))
+                .filter(base_images::id.eq(image_id))
+                .select(projects::cors)
+                .first::<Option<CorsSettings>>(conn)
+                .optional()
+                .map_err(Error::from)
+        })
+        .await?
+        .flatten()
+        .filter(|settings| {
+            origin
+                .to_str()
+                .map(|origin| origin_allowed(settings, origin))
+                .unwrap_or(false)
+        });
+
+    let is_preflight = req.method() == Method::OPTIONS
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    if is_preflight {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        headers.insert(header::VARY, HeaderValue::from_static("origin"));
+        if let Some(settings) = settings.filter(|s| preflight_allowed(s, req.headers())) {
+            add_cors_headers(headers, &settings, &origin);
+            add_preflight_headers(headers, &settings);
+        }
+        return Ok(response);
+    }
+
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+    headers.append(header::VARY, HeaderValue::from_static("origin"));
+    if let Some(settings) = settings {
+        add_cors_headers(headers, &settings, &origin);
+    }
+
+    Ok(response)
+}
+
+/// Check and normalize CORS settings before they are saved.
+pub fn normalize_settings(mut settings: CorsSettings) -> Result<CorsSettings, Error> {
+    for origin in settings.allowed_origins.iter_mut() {
+        let trimmed = origin.trim().trim_end_matches('/').to_ascii_lowercase();
+        let valid = trimmed == "*"
+            || trimmed
+                .split_once("://")
+                .map(|(scheme, host)| {
+                    matches!(scheme, "http" | "https")
+                        && !host.is_empty()
+                        && !host.contains(['/', '?', '#', '*'])
+                })
+                .unwrap_or(false);
+        if !valid {
+            return Err(Error::InvalidCorsSettings(format!(
+                "invalid origin {origin}"
+            )));
+        }
+        *origin = trimmed;
+    }
+
+    if settings.allow_credentials && settings.allowed_origins.iter().any(|o| o == "*") {
+        return Err(Error::InvalidCorsSettings(
+            "allow_credentials can not be used with the * origin".to_string(),
+        ));
+    }
+
+    for method in settings.allowed_methods.iter_mut() {
+        let upper = method.trim().to_ascii_uppercase();
+        if Method::from_bytes(upper.as_bytes()).is_err() {
+            return Err(Error::InvalidCorsSettings(format!(
+                "invalid method {method}"
+            )));
+        }
+        *method = upper;
+    }
+    // The simple methods are always allowed.
+    settings
+        .allowed_methods
+        .retain(|m| !matches!(m.as_str(), "GET" | "HEAD" | "POST"));
+
+    for name in settings.allowed_headers.iter_mut() {
+        let lower = name.trim().to_ascii_lowercase();
+        if HeaderName::from_bytes(lower.as_bytes()).is_err() {
+            return Err(Error::InvalidCorsSettings(format!("invalid header {name}")));
+        }
+        *name = lower;
+    }
+
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> CorsSettings {
+        CorsSettings {
+            allowed_origins: vec!["https://app.example.com".to_string()],
+            allowed_methods: vec!["PUT".to_string()],
+            allowed_headers: vec!["content-type".to_string(), "x-api-key".to_string()],
+            max_age: Some(600),
+            allow_credentials: false,
+        }
+    }
+
+    #[test]
+    fn cors_routes() {
+        let id = BaseImageId::new();
+        assert_eq!(
+            cors_image_id(&Method::GET, &format!("/serve/{id}")),
+            Some(id)
+        );
+        assert_eq!(
+            cors_image_id(&Method::OPTIONS, &format!("/api/images/{id}/upload")),
+            Some(id)
+        );
+        assert_eq!(
+            cors_image_id(&Method::POST, &format!("/api/images/{id}/upload")),
+            Some(id)
+        );
+        assert_eq!(
+            cors_image_id(&Method::GET, &format!("/api/images/{id}")),
+            None
+        );
+        assert_eq!(
+            cors_image_id(&Method::DELETE, &format!("/serve/{id}")),
+            None
+        );
+    }
+
+    #[test]
+    fn origins() {
+        let s = settings();
+        assert!(origin_allowed(&s, "https://app.example.com"));
+        assert!(!origin_allowed(&s, "https://evil.com"));
+        assert!(!origin_allowed(&s, "http://app.example.com"));
+
+        let any = CorsSettings {
+            allowed_origins: vec!["*".to_string()],
+            ..Default::default()
+        };
+        assert!(origin_allowed(&any, "https://evil.com"));
+    }
+
+    #[test]
+    fn credentials() {
+        let origin = HeaderValue::from_static("https://app.example.com");
+        let s = CorsSettings {
+            allow_credentials: true,
+            ..settings()
+        };
+        let mut headers = HeaderMap::new();
+        add_cors_headers(&mut headers, &s, &origin);
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS),
+            Some(&HeaderValue::from_static("true"))
+        );
+
+        // Settings saved before the wildcard check still never allow credentials for a
+        // wildcard match.
+        let any = CorsSettings {
+            allowed_origins: vec!["*".to_string()],
+            allow_credentials: true,
+            ..Default::default()
+        };
+        let mut headers = HeaderMap::new();
+        add_cors_headers(&mut headers, &any, &origin);
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some(&origin)
+        );
+        assert!(headers
+            .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+            .is_none());
+
+        assert!(matches!(
+            normalize_settings(any),
+            Err(Error::InvalidCorsSettings(_))
+        ));
+        assert!(normalize_settings(s).is_ok());
+    }
+
+    fn preflight(method: &str, headers: Option<&str>) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        map.insert(
+            header::ACCESS_CONTROL_REQUEST_METHOD,
+            HeaderValue::from_str(method).unwrap(),
+        );
+        if let Some(headers) = headers {
+            map.insert(
+                header::ACCESS_CONTROL_REQUEST_HEADERS,
+                HeaderValue::from_str(headers).unwrap(),
+            );
+        }
+        map
+    }
+
+    #[test]
+    fn preflight_requests() {
+        let s = settings();
+        assert!(preflight_allowed(&s, &preflight("POST", None)));
+        assert!(preflight_allowed(
+            &s,
+            &preflight("PUT", Some("Content-Type, X-Api-Key"))
+        ));
+        assert!(!preflight_allowed(&s, &preflight("DELETE", None)));
+        assert!(!preflight_allowed(
+            &s,
+            &preflight("POST", Some("authorization"))
+        ));
+        assert!(!preflight_allowed(&s, &HeaderMap::new()));
+    }
+
+    #[test]
+    fn normalize() {
+        let normalized = normalize_settings(CorsSettings {
+            allowed_origins: vec!["HTTPS://App.Example.com/".to_string(), "*".to_string()],
+            allowed_methods: vec!["put".to_string(), "GET".to_string()],
+            allowed_headers: vec!["X-Api-Key".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            normalized.allowed_origins,
+            vec!["https://app.example.com", "*"]
+        );
+        assert_eq!(normalized.allowed_methods, vec!["PUT"]);
+        assert_eq!(normalized.allowed_headers, vec!["x-api-key"]);
+
+        let invalid_origin = CorsSettings {
+            allowed_origins: vec!["example.com".to_string()],
+            ..Default::default()
+        };
+        assert!(normalize_settings(invalid_origin).is_err());
+
+        let invalid_header = CorsSettings {
+            allowed_origins: vec!["*".to_string()],
+            allowed_headers: vec!["bad header".to_string()],
+            ..Default::default()
+        };
+        assert!(normalize_settings(invalid_header).is_err());
+    }
+}
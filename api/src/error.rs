@@ -6,7 +6,7 @@ use axum::{
 use pic_store_db::Permission;
 use thiserror::Error;
 
-use pic_store_http_errors::ErrorResponseData;
+use pic_store_http_errors::{ErrorResponseData, FieldError};
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
@@ -39,6 +39,9 @@ pub enum Error {
     #[error("Storage error: {0}")]
     StorageError(#[from] pic_store_storage::Error),
 
+    #[error(transparent)]
+    CredentialError(#[from] pic_store_db::credentials::CredentialError),
+
     #[error("Not found")]
     NotFound,
 
@@ -54,6 +57,9 @@ pub enum Error {
     #[error("Failed to decode image information: {0}")]
     ImageHeaderDecode(#[from] imageinfo::ImageInfoError),
 
+    #[error(transparent)]
+    ImageConversion(#[from] pic_store_convert::Error),
+
     #[error("Unsupported image type: {0:?}")]
     UnsupportedImageType(imageinfo::ImageFormat),
 
@@ -74,6 +80,180 @@ pub enum Error {
 
     #[error("Queue error: {0}")]
     Queue(#[from] effectum::Error),
+
+    #[error(transparent)]
+    InvalidKeyTemplate(#[from] crate::key_template::KeyTemplateError),
+
+    #[error("Instance admin permission required")]
+    InstanceAdminRequired,
+
+    #[error("Impersonation is not active")]
+    InvalidImpersonation,
+
+    #[error("Team is suspended")]
+    TeamSuspended,
+
+    #[error("Team is read-only")]
+    TeamReadOnly,
+
+    #[error("Too many {0} requests")]
+    RateLimited(&'static str),
+
+    #[error("Invalid abuse report: {0}")]
+    InvalidAbuseReport(&'static str),
+
+    #[error("Invalid conversion profile: {0}")]
+    InvalidConversionProfile(String),
+
+    #[error("Invalid transformation: {0}")]
+    InvalidTransformation(&'static str),
+
+    #[error("Invalid signed URL: {0}")]
+    InvalidSignedUrl(&'static str),
+
+    #[error("Signed URLs are not enabled on this server")]
+    SignedUrlsDisabled,
+
+    #[error("Missing or invalid access token")]
+    InvalidAccessToken,
+
+    #[error("Invalid country code {0}")]
+    InvalidCountryCode(String),
+
+    #[error("This image is not available in your region")]
+    GeoRestricted,
+
+    #[error("Invalid Cache-Control value")]
+    InvalidCacheControl,
+
+    #[error("save_data_quality must be between 1 and 100")]
+    InvalidSaveDataQuality,
+
+    #[error("Invalid format fallbacks: {0}")]
+    InvalidFormatFallbacks(&'static str),
+
+    #[error("This API key can only be used to upload images")]
+    ApiKeyRestricted,
+
+    #[error("Invalid imgproxy URL: {0}")]
+    InvalidImgproxyUrl(&'static str),
+
+    #[error("The image's storage location does not have a CDN to purge")]
+    CdnPurgeNotConfigured,
+
+    #[error("Failed to purge the CDN: {0}")]
+    CdnPurge(eyre::Report),
+
+    #[error("Invalid hostname")]
+    InvalidHostname,
+
+    #[error("This domain is already in use")]
+    DeliveryDomainTaken,
+
+    #[error("Invalid referer {0}, expected a hostname or a *. wildcard")]
+    InvalidReferer(String),
+
+    #[error("This image can not be embedded on this site")]
+    HotlinkForbidden,
+
+    #[error("This requires the {0} role in the organization")]
+    MissingOrganizationRole(pic_store_db::OrganizationRole),
+
+    #[error("The team already belongs to an organization")]
+    TeamInOrganization,
+
+    #[error("An organization must have at least one admin")]
+    LastOrganizationAdmin,
+
+    #[error("Invalid storage location: {0}")]
+    InvalidStorageLocation(&'static str),
+
+    #[error("Invalid CORS settings: {0}")]
+    InvalidCorsSettings(String),
+
+    #[error("Invalid JSON body: {0}")]
+    InvalidJson(String),
+
+    #[error("Unknown fields in request: {}", .0.join(", "))]
+    UnknownFields(Vec<String>),
+
+    #[error("Invalid label: {0}")]
+    InvalidLabel(&'static str),
+
+    #[error("Invalid label policy: {0}")]
+    InvalidLabelPolicy(&'static str),
+
+    #[error("Invalid webhook: {0}")]
+    InvalidWebhook(&'static str),
+
+    #[error("The webhook's host is not on the team's allowlist")]
+    WebhookHostNotAllowed,
+
+    #[error("Invalid webhook payload template: {0}")]
+    InvalidWebhookTemplate(String),
+
+    #[error("The upload is not allowed by the policy for the label {0}")]
+    LabelPolicyViolation(String),
+
+    #[error("This request is not allowed by the authorization policy")]
+    PolicyDenied,
+
+    #[error("Invalid image constraints: {0}")]
+    InvalidImageConstraints(&'static str),
+
+    #[error("The image does not meet the upload profile's constraints")]
+    ImageConstraintViolation(Vec<pic_store_db::upload_profiles::ConstraintViolation>),
+
+    #[error("Invalid original retention: {0}")]
+    InvalidOriginalRetention(&'static str),
+
+    #[error("Invalid output mirrors: {0}")]
+    InvalidOutputMirrors(&'static str),
+
+    #[error("The original image was removed by the upload profile's retention rules")]
+    OriginalUnavailable,
+
+    #[error("Invalid bulk deletion: {0}")]
+    InvalidBulkDeletion(&'static str),
+
+    #[error("The confirmation token is invalid or has expired")]
+    InvalidConfirmationToken,
+
+    #[error("{0} does not match its checksum after it was stored")]
+    ChecksumMismatch(String),
+
+    #[error("Invalid image references: {0}")]
+    InvalidImageReferences(&'static str),
+
+    #[error("The image's original has already been uploaded")]
+    ImageAlreadyUploaded,
+
+    #[error("The storage location does not support direct uploads")]
+    DirectUploadUnsupported,
+
+    #[error("Unknown export column {0}")]
+    InvalidExportColumn(String),
+
+    #[error("Invalid upload chunk: {0}")]
+    InvalidUploadChunk(String),
+
+    #[error("The upload is missing {0} chunks")]
+    MissingUploadChunks(usize),
+
+    #[error("Invalid post-processing callback: {0}")]
+    InvalidPostProcessCallback(&'static str),
+
+    #[error("Invalid image URL: {0}")]
+    InvalidRemoteUrl(String),
+
+    #[error("The image URL leads to an address that can not be fetched")]
+    ForbiddenRemoteAddress,
+
+    #[error("Failed to fetch the image: {0}")]
+    RemoteFetchFailed(String),
+
+    #[error("The image URL returned {0} instead of an image")]
+    UnsupportedRemoteContentType(String),
 }
 
 impl Error {
@@ -88,11 +268,14 @@ impl Error {
             Error::AuthError(_) => "authz",
             Error::ApiKeyNotFound => "authn",
             Error::StorageError(_) => "storage",
+            Error::CredentialError(_) => "credentials",
             Error::NotFound => "not_found",
             Error::ObjectNotFound(_) => "not_found",
             Error::IoError(_) => "internal_server_error",
             Error::AxumError(_) => "bad_request",
             Error::ImageHeaderDecode(_) => "image_decode",
+            Error::ImageConversion(pic_store_convert::Error::Encode(_)) => "image_encode",
+            Error::ImageConversion(_) => "image_decode",
             Error::UnsupportedImageType(_) => "unsupported_image_type",
             Error::ContentLengthRequired => "bad_request",
             Error::RequestTooLarge => "bad_request",
@@ -100,6 +283,64 @@ impl Error {
             Error::InvalidSessionId => "authn",
             Error::NoUploadProfile => "no_upload_profile",
             Error::Queue(_) => "job_queue",
+            Error::InvalidKeyTemplate(_) => "invalid_key_template",
+            Error::InstanceAdminRequired => "missing_permission",
+            Error::InvalidImpersonation => "invalid_impersonation",
+            Error::TeamSuspended => "team_suspended",
+            Error::RateLimited(_) => "rate_limited",
+            Error::TeamReadOnly => "team_read_only",
+            Error::InvalidAbuseReport(_) => "invalid_abuse_report",
+            Error::InvalidConversionProfile(_) => "invalid_conversion_profile",
+            Error::InvalidTransformation(_) => "invalid_transformation",
+            Error::InvalidSignedUrl(_) => "invalid_signed_url",
+            Error::SignedUrlsDisabled => "signed_urls_disabled",
+            Error::InvalidAccessToken => "invalid_access_token",
+            Error::InvalidCountryCode(_) => "invalid_country_code",
+            Error::GeoRestricted => "geo_restricted",
+            Error::InvalidCacheControl => "invalid_cache_control",
+            Error::InvalidSaveDataQuality => "invalid_save_data_quality",
+            Error::InvalidFormatFallbacks(_) => "invalid_format_fallbacks",
+            Error::ApiKeyRestricted => "api_key_restricted",
+            Error::InvalidImgproxyUrl(_) => "invalid_imgproxy_url",
+            Error::CdnPurgeNotConfigured => "cdn_purge_not_configured",
+            Error::CdnPurge(_) => "cdn_purge",
+            Error::InvalidHostname => "invalid_hostname",
+            Error::DeliveryDomainTaken => "delivery_domain_taken",
+            Error::InvalidReferer(_) => "invalid_referer",
+            Error::HotlinkForbidden => "hotlink_forbidden",
+            Error::MissingOrganizationRole(_) => "missing_organization_role",
+            Error::TeamInOrganization => "team_in_organization",
+            Error::LastOrganizationAdmin => "last_organization_admin",
+            Error::InvalidStorageLocation(_) => "invalid_storage_location",
+            Error::InvalidCorsSettings(_) => "invalid_cors_settings",
+            Error::InvalidJson(_) => "invalid_json",
+            Error::UnknownFields(_) => "unknown_fields",
+            Error::InvalidLabel(_) => "invalid_label",
+            Error::InvalidLabelPolicy(_) => "invalid_label_policy",
+            Error::InvalidWebhook(_) => "invalid_webhook",
+            Error::InvalidWebhookTemplate(_) => "invalid_webhook_template",
+            Error::WebhookHostNotAllowed => "webhook_host_not_allowed",
+            Error::LabelPolicyViolation(_) => "label_policy_violation",
+            Error::PolicyDenied => "policy_denied",
+            Error::InvalidImageConstraints(_) => "invalid_image_constraints",
+            Error::ImageConstraintViolation(_) => "image_constraint_violation",
+            Error::InvalidOriginalRetention(_) => "invalid_original_retention",
+            Error::InvalidOutputMirrors(_) => "invalid_output_mirrors",
+            Error::OriginalUnavailable => "original_unavailable",
+            Error::InvalidBulkDeletion(_) => "invalid_bulk_deletion",
+            Error::InvalidConfirmationToken => "invalid_confirmation_token",
+            Error::ChecksumMismatch(_) => "checksum_mismatch",
+            Error::InvalidImageReferences(_) => "invalid_image_references",
+            Error::ImageAlreadyUploaded => "image_already_uploaded",
+            Error::DirectUploadUnsupported => "direct_upload_unsupported",
+            Error::InvalidExportColumn(_) => "invalid_export_column",
+            Error::InvalidUploadChunk(_) => "invalid_upload_chunk",
+            Error::MissingUploadChunks(_) => "missing_upload_chunks",
+            Error::InvalidPostProcessCallback(_) => "invalid_post_process_callback",
+            Error::InvalidRemoteUrl(_) => "invalid_remote_url",
+            Error::ForbiddenRemoteAddress => "forbidden_remote_address",
+            Error::RemoteFetchFailed(_) => "remote_fetch_failed",
+            Error::UnsupportedRemoteContentType(_) => "unsupported_remote_content_type",
         }
     }
 
@@ -107,6 +348,11 @@ impl Error {
         let status = match self {
             Error::NoUploadProfile => StatusCode::BAD_REQUEST,
             Error::MissingPermission(_) => StatusCode::FORBIDDEN,
+            Error::InstanceAdminRequired => StatusCode::FORBIDDEN,
+            Error::InvalidImpersonation => StatusCode::FORBIDDEN,
+            Error::TeamSuspended => StatusCode::FORBIDDEN,
+            Error::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            Error::TeamReadOnly => StatusCode::FORBIDDEN,
             Error::NotFound => StatusCode::NOT_FOUND,
             Error::Unauthenticated => StatusCode::FORBIDDEN,
             Error::AuthError(_) => StatusCode::UNAUTHORIZED,
@@ -114,17 +360,102 @@ impl Error {
             Error::InvalidSessionId => StatusCode::UNAUTHORIZED,
             Error::ObjectNotFound(_) => StatusCode::NOT_FOUND,
             Error::ContentLengthRequired => StatusCode::BAD_REQUEST,
-            Error::RequestTooLarge => StatusCode::BAD_REQUEST,
+            Error::RequestTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::InvalidKeyTemplate(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidAbuseReport(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidConversionProfile(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidTransformation(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidSignedUrl(_) => StatusCode::FORBIDDEN,
+            Error::SignedUrlsDisabled => StatusCode::BAD_REQUEST,
+            Error::InvalidAccessToken => StatusCode::FORBIDDEN,
+            Error::InvalidCountryCode(_) => StatusCode::BAD_REQUEST,
+            Error::GeoRestricted => StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+            Error::InvalidCacheControl => StatusCode::BAD_REQUEST,
+            Error::InvalidSaveDataQuality => StatusCode::BAD_REQUEST,
+            Error::InvalidFormatFallbacks(_) => StatusCode::BAD_REQUEST,
+            Error::ApiKeyRestricted => StatusCode::FORBIDDEN,
+            Error::InvalidImgproxyUrl(_) => StatusCode::BAD_REQUEST,
+            Error::CdnPurgeNotConfigured => StatusCode::BAD_REQUEST,
+            Error::CdnPurge(_) => StatusCode::BAD_GATEWAY,
+            Error::InvalidHostname => StatusCode::BAD_REQUEST,
+            Error::DeliveryDomainTaken => StatusCode::CONFLICT,
+            Error::InvalidReferer(_) => StatusCode::BAD_REQUEST,
+            Error::HotlinkForbidden => StatusCode::FORBIDDEN,
+            Error::MissingOrganizationRole(_) => StatusCode::FORBIDDEN,
+            Error::TeamInOrganization => StatusCode::CONFLICT,
+            Error::LastOrganizationAdmin => StatusCode::BAD_REQUEST,
+            Error::InvalidStorageLocation(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidCorsSettings(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidJson(_) => StatusCode::BAD_REQUEST,
+            Error::UnknownFields(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidLabel(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidLabelPolicy(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidWebhook(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidWebhookTemplate(_) => StatusCode::BAD_REQUEST,
+            Error::WebhookHostNotAllowed => StatusCode::FORBIDDEN,
+            Error::LabelPolicyViolation(_) => StatusCode::FORBIDDEN,
+            Error::PolicyDenied => StatusCode::FORBIDDEN,
+            Error::InvalidImageConstraints(_) => StatusCode::BAD_REQUEST,
+            Error::ImageConstraintViolation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::InvalidOriginalRetention(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidOutputMirrors(_) => StatusCode::BAD_REQUEST,
+            Error::OriginalUnavailable => StatusCode::GONE,
+            Error::InvalidBulkDeletion(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidConfirmationToken => StatusCode::FORBIDDEN,
+            Error::InvalidImageReferences(_) => StatusCode::BAD_REQUEST,
+            Error::ImageAlreadyUploaded => StatusCode::CONFLICT,
+            Error::DirectUploadUnsupported => StatusCode::BAD_REQUEST,
+            Error::InvalidExportColumn(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidUploadChunk(_) => StatusCode::BAD_REQUEST,
+            Error::MissingUploadChunks(_) => StatusCode::CONFLICT,
+            Error::InvalidPostProcessCallback(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidRemoteUrl(_) => StatusCode::BAD_REQUEST,
+            Error::ForbiddenRemoteAddress => StatusCode::BAD_REQUEST,
+            Error::RemoteFetchFailed(_) => StatusCode::BAD_GATEWAY,
+            Error::UnsupportedRemoteContentType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
             Error::ImageHeaderDecode(imageinfo::ImageInfoError::UnrecognizedFormat) => {
                 StatusCode::BAD_REQUEST
             }
+            Error::UnsupportedImageType(_) => StatusCode::BAD_REQUEST,
+            Error::ImageConversion(pic_store_convert::Error::Read { .. }) => {
+                StatusCode::BAD_REQUEST
+            }
+            Error::ImageConversion(pic_store_convert::Error::TooLarge(_)) => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
-        (
-            status,
-            pic_store_http_errors::ErrorResponseData::new(self.error_kind(), self.to_string()),
-        )
+        let mut data = pic_store_http_errors::ErrorResponseData::new(
+            self.error_kind(),
+            crate::redact::redact_secrets(&self.to_string()).into_owned(),
+        );
+
+        if let Error::UnknownFields(fields) = self {
+            data = data.with_fields(
+                fields
+                    .iter()
+                    .map(|field| FieldError {
+                        field: field.clone(),
+                        message: "unknown field".into(),
+                    })
+                    .collect(),
+            );
+        }
+
+        if let Error::ImageConstraintViolation(violations) = self {
+            data = data.with_fields(
+                violations
+                    .iter()
+                    .map(|violation| FieldError {
+                        field: violation.constraint.to_string(),
+                        message: violation.message.clone().into(),
+                    })
+                    .collect(),
+            );
+        }
+
+        (status, data)
     }
 }
 
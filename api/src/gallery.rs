@@ -0,0 +1,142 @@
+//! Gallery cookies, which give a browser access to every image in a project until the cookie
+//! expires. Private photo galleries can hand out one cookie instead of a signed URL for each
+//! image. The cookie is signed with the same key as signed URLs, and is only checked for images
+//! that need a signed URL or an access token.
+
+use axum::http::{header, HeaderMap, HeaderValue};
+use chrono::{DateTime, TimeZone, Utc};
+use pic_store_db::object_id::ProjectId;
+
+use crate::signed_url::UrlSigner;
+
+/// How long a gallery cookie lasts when the request doesn't say.
+pub const DEFAULT_TTL_SECONDS: i64 = 24 * 60 * 60;
+/// The longest that a gallery cookie can last.
+pub const MAX_TTL_SECONDS: i64 = 30 * 24 * 60 * 60;
+
+/// Each project gets its own cookie, so a browser can view several galleries at once.
+pub fn cookie_name(project_id: ProjectId) -> String {
+    format!("pic_gallery_{project_id}")
+}
+
+/// Build the `Set-Cookie` header for a gallery cookie. Galleries are usually on a different
+/// site than the images, so the cookie needs `SameSite=None`, which browsers only accept on
+/// secure cookies. The cookie is sent without those attributes when `secure` is false, for
+/// development over plain HTTP.
+pub fn set_cookie_header(
+    project_id: ProjectId,
+    expires: i64,
+    signature: &str,
+    now: DateTime<Utc>,
+    secure: bool,
+) -> Option<HeaderValue> {
+    let max_age = (expires - now.timestamp()).max(0);
+    let attributes = if secure {
+        "; Secure; SameSite=None"
+    } else {
+        "; SameSite=Lax"
+    };
+    HeaderValue::from_str(&format!(
+        "{}={expires}.{signature}; Max-Age={max_age}; Path=/; HttpOnly{attributes}",
+        cookie_name(project_id)
+    ))
+    .ok()
+}
+
+/// Check the request for a valid gallery cookie for the project, and return when it expires.
+pub fn verify_cookie(
+    headers: &HeaderMap,
+    signer: &UrlSigner,
+    project_id: ProjectId,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let name = cookie_name(project_id);
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .filter(|(key, _)| *key == name)
+        .find_map(|(_, value)| {
+            let (expires, signature) = value.split_once('.')?;
+            let expires = expires.parse::<i64>().ok()?;
+            signer
+                .verify_gallery(project_id, expires, signature, now)
+                .ok()?;
+            Utc.timestamp_opt(expires, 0).single()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn cookie_headers(cookie: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, HeaderValue::from_str(cookie).unwrap());
+        headers
+    }
+
+    #[test]
+    fn valid_cookie() {
+        let signer = UrlSigner::new("secret");
+        let project_id = ProjectId::new();
+        let now = Utc::now();
+        let expires = (now + Duration::hours(1)).timestamp();
+        let signature = signer.sign_gallery(project_id, expires);
+
+        let headers = cookie_headers(&format!(
+            "sid=abc; {}={expires}.{signature}",
+            cookie_name(project_id)
+        ));
+        assert_eq!(
+            verify_cookie(&headers, &signer, project_id, now).map(|e| e.timestamp()),
+            Some(expires)
+        );
+        assert_eq!(
+            verify_cookie(&headers, &signer, ProjectId::new(), now),
+            None
+        );
+    }
+
+    #[test]
+    fn invalid_cookie() {
+        let signer = UrlSigner::new("secret");
+        let project_id = ProjectId::new();
+        let now = Utc::now();
+        let expires = (now - Duration::seconds(1)).timestamp();
+        let signature = signer.sign_gallery(project_id, expires);
+        let name = cookie_name(project_id);
+
+        let expired = cookie_headers(&format!("{name}={expires}.{signature}"));
+        assert_eq!(verify_cookie(&expired, &signer, project_id, now), None);
+
+        let malformed = cookie_headers(&format!("{name}=abc"));
+        assert_eq!(verify_cookie(&malformed, &signer, project_id, now), None);
+    }
+
+    #[test]
+    fn cookie_attributes() {
+        let project_id = ProjectId::new();
+        let now = Utc::now();
+        let expires = (now + Duration::seconds(60)).timestamp();
+
+        let secure = set_cookie_header(project_id, expires, "abc", now, true).unwrap();
+        assert_eq!(
+            secure.to_str().unwrap(),
+            format!(
+                "{}={expires}.abc; Max-Age=60; Path=/; HttpOnly; Secure; SameSite=None",
+                cookie_name(project_id)
+            )
+        );
+
+        let insecure = set_cookie_header(project_id, expires, "abc", now, false).unwrap();
+        assert!(insecure
+            .to_str()
+            .unwrap()
+            .ends_with("HttpOnly; SameSite=Lax"));
+    }
+}
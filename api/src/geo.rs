@@ -0,0 +1,124 @@
+//! Country restrictions on serving images, for content that is only licensed in some regions.
+//! The viewer's country comes from a header set by the CDN in front of the server.
+
+use crate::Error;
+
+/// The countries that an upload profile's images may be served to.
+#[derive(Debug, Default)]
+pub struct GeoRestriction {
+    pub allowed_countries: Option<Vec<String>>,
+    pub blocked_countries: Option<Vec<String>>,
+}
+
+impl GeoRestriction {
+    pub fn is_restricted(&self) -> bool {
+        self.allowed_countries.is_some()
+            || self
+                .blocked_countries
+                .as_ref()
+                .map(|c| !c.is_empty())
+                .unwrap_or(false)
+    }
+
+    /// Check whether an image may be served to the given country. When the country is unknown,
+    /// only profiles without an allow list will serve the image.
+    pub fn allows(&self, country: Option<&str>) -> bool {
+        let country = country
+            .map(|c| c.trim())
+            .filter(|c| is_country_code(c))
+            .map(|c| c.to_ascii_uppercase());
+
+        if let Some(allowed) = &self.allowed_countries {
+            let Some(country) = country.as_deref() else {
+                return false;
+            };
+
+            if !allowed.iter().any(|c| c == country) {
+                return false;
+            }
+        }
+
+        match (&self.blocked_countries, country.as_deref()) {
+            (Some(blocked), Some(country)) => !blocked.iter().any(|c| c == country),
+            _ => true,
+        }
+    }
+}
+
+fn is_country_code(code: &str) -> bool {
+    code.len() == 2 && code.bytes().all(|b| b.is_ascii_alphabetic())
+}
+
+/// Check that a list contains only two-letter country codes, and convert them to uppercase.
+pub fn normalize_countries(countries: Option<Vec<String>>) -> Result<Option<Vec<String>>, Error> {
+    countries
+        .map(|countries| {
+            countries
+                .into_iter()
+                .map(|c| {
+                    let c = c.trim();
+                    if is_country_code(c) {
+                        Ok(c.to_ascii_uppercase())
+                    } else {
+                        Err(Error::InvalidCountryCode(c.to_string()))
+                    }
+                })
+                .collect()
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(codes: &[&str]) -> Option<Vec<String>> {
+        Some(codes.iter().map(|c| c.to_string()).collect())
+    }
+
+    #[test]
+    fn unrestricted() {
+        let geo = GeoRestriction::default();
+        assert!(!geo.is_restricted());
+        assert!(geo.allows(Some("US")));
+        assert!(geo.allows(None));
+    }
+
+    #[test]
+    fn allow_list() {
+        let geo = GeoRestriction {
+            allowed_countries: list(&["US", "CA"]),
+            blocked_countries: None,
+        };
+        assert!(geo.is_restricted());
+        assert!(geo.allows(Some("US")));
+        assert!(geo.allows(Some("ca")));
+        assert!(!geo.allows(Some("DE")));
+        assert!(!geo.allows(None));
+        // Cloudflare's code for unknown countries
+        assert!(!geo.allows(Some("XX")));
+    }
+
+    #[test]
+    fn block_list() {
+        let geo = GeoRestriction {
+            allowed_countries: None,
+            blocked_countries: list(&["DE"]),
+        };
+        assert!(geo.is_restricted());
+        assert!(geo.allows(Some("US")));
+        assert!(!geo.allows(Some("de")));
+        assert!(geo.allows(None));
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(
+            normalize_countries(list(&["us", " Ca "])).unwrap(),
+            list(&["US", "CA"])
+        );
+        assert_eq!(normalize_countries(None).unwrap(), None);
+        assert!(normalize_countries(list(&["USA"])).is_err());
+        assert!(normalize_countries(list(&["1A"])).is_err());
+    }
+}
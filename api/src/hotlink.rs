@@ -0,0 +1,174 @@
+//! Hotlink protection, so that other sites can't embed a project's images. The embedding site
+//! comes from the Referer header, or the Origin header when there is no Referer.
+//!
+//! Allowed sites are hostnames, and a leading `*.` allows every subdomain of a hostname too.
+//! Requests with neither header, such as direct visits and pages with a `no-referrer` policy,
+//! are allowed unless the project blocks them.
+
+use axum::http::{header, HeaderMap};
+use pic_store_db::delivery_domains::normalize_hostname;
+
+use crate::Error;
+
+/// The sites that may embed a project's images.
+#[derive(Debug, Default)]
+pub struct RefererRestriction {
+    pub allowed_referers: Option<Vec<String>>,
+    pub block_empty_referer: bool,
+}
+
+impl RefererRestriction {
+    pub fn is_restricted(&self) -> bool {
+        self.allowed_referers.is_some()
+    }
+
+    /// Check whether a request's Referer or Origin header is allowed to embed the image.
+    pub fn allows(&self, headers: &HeaderMap) -> bool {
+        let Some(allowed) = &self.allowed_referers else {
+            return true;
+        };
+
+        let referer = headers
+            .get(header::REFERER)
+            .or_else(|| headers.get(header::ORIGIN))
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            // Sandboxed and privacy-sensitive contexts send an Origin of "null".
+            .filter(|value| !value.is_empty() && *value != "null");
+
+        let Some(referer) = referer else {
+            return !self.block_empty_referer;
+        };
+
+        let Some(host) = referer_host(referer) else {
+            return false;
+        };
+
+        allowed
+            .iter()
+            .any(|pattern| match pattern.strip_prefix("*.") {
+                Some(domain) => {
+                    host == domain
+                        || host
+                            .strip_suffix(domain)
+                            .map(|sub| sub.ends_with('.'))
+                            .unwrap_or(false)
+                }
+                None => &host == pattern,
+            })
+    }
+}
+
+/// Get the hostname from a Referer or Origin header.
+fn referer_host(referer: &str) -> Option<String> {
+    let (scheme, rest) = referer.split_once("://")?;
+    if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+        return None;
+    }
+
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority
+        .rsplit_once('@')
+        .map(|(_, host)| host)
+        .unwrap_or(authority);
+    normalize_hostname(host)
+}
+
+/// Check that a list contains only hostnames or `*.` wildcards, and normalize them.
+pub fn normalize_referers(referers: Option<Vec<String>>) -> Result<Option<Vec<String>>, Error> {
+    referers
+        .map(|referers| {
+            referers
+                .into_iter()
+                .map(|r| {
+                    let trimmed = r.trim();
+                    let (wildcard, host) = match trimmed.strip_prefix("*.") {
+                        Some(host) => (true, host),
+                        None => (false, trimmed),
+                    };
+
+                    match normalize_hostname(host) {
+                        Some(host) if wildcard => Ok(format!("*.{host}")),
+                        Some(host) => Ok(host),
+                        None => Err(Error::InvalidReferer(trimmed.to_string())),
+                    }
+                })
+                .collect()
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn restriction(referers: &[&str], block_empty_referer: bool) -> RefererRestriction {
+        RefererRestriction {
+            allowed_referers: Some(referers.iter().map(|r| r.to_string()).collect()),
+            block_empty_referer,
+        }
+    }
+
+    fn headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn unrestricted() {
+        let r = RefererRestriction::default();
+        assert!(!r.is_restricted());
+        assert!(r.allows(&headers(header::REFERER, "https://other.com/")));
+        assert!(r.allows(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn exact_hosts() {
+        let r = restriction(&["example.com"], false);
+        assert!(r.allows(&headers(header::REFERER, "https://example.com/page?a=1")));
+        assert!(r.allows(&headers(header::REFERER, "http://Example.com:8080")));
+        assert!(r.allows(&headers(header::ORIGIN, "https://example.com")));
+        assert!(!r.allows(&headers(header::REFERER, "https://www.example.com/")));
+        assert!(!r.allows(&headers(header::REFERER, "https://example.com.evil.com/")));
+        assert!(!r.allows(&headers(header::REFERER, "https://evil.com/?example.com")));
+        assert!(!r.allows(&headers(header::REFERER, "https://example.com@evil.com/")));
+        assert!(!r.allows(&headers(header::REFERER, "not a url")));
+    }
+
+    #[test]
+    fn wildcard_hosts() {
+        let r = restriction(&["*.example.com"], false);
+        assert!(r.allows(&headers(header::REFERER, "https://example.com/")));
+        assert!(r.allows(&headers(header::REFERER, "https://a.b.example.com/")));
+        assert!(!r.allows(&headers(header::REFERER, "https://badexample.com/")));
+    }
+
+    #[test]
+    fn empty_referer() {
+        let r = restriction(&["example.com"], false);
+        assert!(r.allows(&HeaderMap::new()));
+        assert!(r.allows(&headers(header::ORIGIN, "null")));
+
+        let r = restriction(&["example.com"], true);
+        assert!(!r.allows(&HeaderMap::new()));
+        assert!(!r.allows(&headers(header::ORIGIN, "null")));
+    }
+
+    #[test]
+    fn normalize() {
+        let referers = vec!["Example.com".to_string(), " *.CDN.example.com ".to_string()];
+        assert_eq!(
+            normalize_referers(Some(referers)).unwrap(),
+            Some(vec![
+                "example.com".to_string(),
+                "*.cdn.example.com".to_string()
+            ])
+        );
+        assert!(normalize_referers(Some(vec!["https://example.com".to_string()])).is_err());
+        assert!(normalize_referers(Some(vec!["*".to_string()])).is_err());
+        assert_eq!(normalize_referers(None).unwrap(), None);
+    }
+}
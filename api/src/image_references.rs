@@ -0,0 +1,265 @@
+//! Tracking which site pages use each image, so that users can see what will break before they
+//! delete an image. References come from an uploaded list of page and image URLs, or from
+//! crawling the pages in a site's sitemap for `img` and `source` tags.
+//!
+//! Image URLs are matched to images by the image ID in a serve route URL, or otherwise by the
+//! longest stored location that the URL's path ends with.
+
+use std::collections::HashMap;
+
+use db::object_id::{BaseImageId, ProjectId};
+use diesel::prelude::*;
+use once_cell::sync::Lazy;
+use pic_store_db as db;
+use regex::Regex;
+use reqwest::Url;
+
+static IMAGE_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)<(?:img|source)\b[^>]*>").unwrap());
+
+static IMAGE_ATTRIBUTE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)\s(src|srcset|data-src|data-srcset)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap()
+});
+
+static SITEMAP_LOC: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<loc>\s*(.*?)\s*</loc>").unwrap());
+
+/// Decode the HTML entities that commonly appear in URLs.
+fn unescape(text: &str) -> String {
+    text.replace("&amp;", "&")
+        .replace("&#38;", "&")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+}
+
+/// Find the URLs of the images in an HTML page, resolved against the page's URL.
+pub fn extract_image_urls(html: &str, page_url: &Url) -> Vec<String> {
+    let mut urls = Vec::new();
+    for tag in IMAGE_TAG.find_iter(html) {
+        for attr in IMAGE_ATTRIBUTE.captures_iter(tag.as_str()) {
+            let name = attr[1].to_ascii_lowercase();
+            let Some(value) = attr.get(2).or_else(|| attr.get(3)) else {
+                continue;
+            };
+            let value = unescape(value.as_str());
+
+            let candidates = if name.ends_with("srcset") {
+                // Each entry is a URL followed by an optional width or density.
+                value
+                    .split(',')
+                    .filter_map(|entry| entry.split_whitespace().next())
+                    .map(|url| url.to_string())
+                    .collect::<Vec<_>>()
+            } else {
+                vec![value.trim().to_string()]
+            };
+
+            for candidate in candidates {
+                if candidate.is_empty() || candidate.starts_with("data:") {
+                    continue;
+                }
+
+                if let Ok(url) = page_url.join(&candidate) {
+                    let url = url.to_string();
+                    if !urls.contains(&url) {
+                        urls.push(url);
+                    }
+                }
+            }
+        }
+    }
+
+    urls
+}
+
+/// The kind of sitemap document.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Sitemap {
+    /// A list of pages.
+    Pages(Vec<String>),
+    /// A list of other sitemaps.
+    Index(Vec<String>),
+}
+
+/// Read the URLs from a sitemap or sitemap index.
+pub fn parse_sitemap(xml: &str) -> Sitemap {
+    let urls = SITEMAP_LOC
+        .captures_iter(xml)
+        .map(|loc| unescape(&loc[1]))
+        .collect::<Vec<_>>();
+
+    if xml.contains("<sitemapindex") {
+        Sitemap::Index(urls)
+    } else {
+        Sitemap::Pages(urls)
+    }
+}
+
+/// The path segments of an image URL. Query strings and fragments are ignored.
+fn path_segments(image_url: &str) -> Vec<String> {
+    let path = match Url::parse(image_url) {
+        Ok(url) => url.path().to_string(),
+        Err(_) => image_url
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+    };
+
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| segment.to_string())
+        .collect()
+}
+
+/// The image ID in a serve route URL, or any other URL that has an image ID as a path segment.
+fn image_id_in_path(segments: &[String]) -> Option<BaseImageId> {
+    segments.iter().find_map(|segment| {
+        let stem = segment.split('.').next().unwrap_or_default();
+        stem.parse::<BaseImageId>().ok()
+    })
+}
+
+/// Every trailing part of the path, longest first.
+fn path_suffixes(segments: &[String]) -> Vec<String> {
+    (0..segments.len())
+        .map(|start| segments[start..].join("/"))
+        .collect()
+}
+
+/// Find the images in a project that image URLs point to. URLs that don't match an image are
+/// left out of the result.
+pub fn resolve_image_urls(
+    conn: &mut PgConnection,
+    project_id: ProjectId,
+    image_urls: &[String],
+) -> QueryResult<HashMap<String, BaseImageId>> {
+    let mut by_id = Vec::new();
+    let mut by_location = Vec::new();
+    for url in image_urls {
+        let segments = path_segments(url);
+        match image_id_in_path(&segments) {
+            Some(id) => by_id.push((url, id)),
+            None => by_location.push((url, path_suffixes(&segments))),
+        }
+    }
+
+    let mut resolved = HashMap::new();
+
+    if !by_id.is_empty() {
+        let ids = by_id.iter().map(|(_, id)| *id).collect::<Vec<_>>();
+        let found = db::base_images::table
+            .filter(db::base_images::id.eq_any(ids))
+            .filter(db::base_images::project_id.eq(project_id))
+            .filter(db::base_images::deleted.is_null())
+            .select(db::base_images::id)
+            .load::<BaseImageId>(conn)?;
+
+        for (url, id) in by_id {
+            if found.contains(&id) {
+                resolved.insert(url.clone(), id);
+            }
+        }
+    }
+
+    if !by_location.is_empty() {
+        let suffixes = by_location
+            .iter()
+            .flat_map(|(_, suffixes)| suffixes.iter().cloned())
+            .collect::<Vec<_>>();
+
+        let mut locations = db::output_images::table
+            .inner_join(db::base_images::table)
+            .filter(db::base_images::project_id.eq(project_id))
+            .filter(db::base_images::deleted.is_null())
+            .filter(db::output_images::location.eq_any(suffixes.clone()))
+            .select((db::output_images::location, db::base_images::id))
+            .load::<(String, BaseImageId)>(conn)?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+
+        let originals = db::base_images::table
+            .filter(db::base_images::project_id.eq(project_id))
+            .filter(db::base_images::deleted.is_null())
+            .filter(db::base_images::location.eq_any(suffixes))
+            .select((db::base_images::location, db::base_images::id))
+            .load::<(String, BaseImageId)>(conn)?;
+        locations.extend(originals);
+
+        for (url, suffixes) in by_location {
+            // The suffixes are longest first, so the most specific location wins.
+            if let Some(id) = suffixes.iter().find_map(|suffix| locations.get(suffix)) {
+                resolved.insert(url.clone(), *id);
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_image_urls() {
+        let page = Url::parse("https://example.com/blog/post").unwrap();
+        let html = r#"
+            <img src="/images/a.webp" alt="A">
+            <picture>
+              <source srcset="https://cdn.example.com/b-400.avif 400w, https://cdn.example.com/b-800.avif 800w">
+              <IMG SRC='c.png?width=100&amp;format=webp'>
+            </picture>
+            <img data-src="/images/a.webp" src="data:image/gif;base64,R0lGOD">
+            <script src="/app.js"></script>
+        "#;
+
+        assert_eq!(
+            extract_image_urls(html, &page),
+            vec![
+                "https://example.com/images/a.webp",
+                "https://cdn.example.com/b-400.avif",
+                "https://cdn.example.com/b-800.avif",
+                "https://example.com/blog/c.png?width=100&format=webp",
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_sitemaps() {
+        let pages = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url><loc>https://example.com/</loc></url>
+              <url><loc>
+                https://example.com/a?x=1&amp;y=2
+              </loc></url>
+            </urlset>"#;
+        assert_eq!(
+            parse_sitemap(pages),
+            Sitemap::Pages(vec![
+                "https://example.com/".to_string(),
+                "https://example.com/a?x=1&y=2".to_string(),
+            ])
+        );
+
+        let index = r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <sitemap><loc>https://example.com/sitemap-1.xml</loc></sitemap>
+            </sitemapindex>"#;
+        assert_eq!(
+            parse_sitemap(index),
+            Sitemap::Index(vec!["https://example.com/sitemap-1.xml".to_string()])
+        );
+    }
+
+    #[test]
+    fn finds_image_ids() {
+        let id = BaseImageId::new();
+        let segments = path_segments(&format!("https://cdn.example.com/serve/{id}?width=400"));
+        assert_eq!(image_id_in_path(&segments), Some(id));
+
+        let segments = path_segments("https://cdn.example.com/photos/cat.webp");
+        assert_eq!(image_id_in_path(&segments), None);
+        assert_eq!(
+            path_suffixes(&segments),
+            vec!["photos/cat.webp".to_string(), "cat.webp".to_string()]
+        );
+    }
+}
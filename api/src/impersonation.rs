@@ -0,0 +1,129 @@
+//! Lets instance admins act as a member of another team, to debug problems without needing
+//! the team's credentials.
+//!
+//! An admin first creates an impersonation, which is time-limited, and then passes its ID in
+//! the `x-impersonate` header. Every request made this way is recorded.
+
+use axum::{
+    extract::State,
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use db::{
+    impersonations::{self, ImpersonationEvent},
+    object_id::{ImpersonationId, RoleId, TeamId},
+    PoolExt, TeamStatus,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use tracing::{event, Level};
+
+use crate::{auth::UserInfo, shared_state::AppState, Error};
+
+pub const IMPERSONATE_HEADER: &str = "x-impersonate";
+
+/// Replace the authenticated user with an impersonated one if the request asks for it and
+/// the user has an active impersonation.
+pub async fn impersonate<B>(
+    State(state): State<AppState>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, Error> {
+    let Some(header) = req.headers().get(IMPERSONATE_HEADER) else {
+        return Ok(next.run(req).await.into_response());
+    };
+
+    let impersonation_id = header
+        .to_str()
+        .ok()
+        .and_then(|h| h.parse::<ImpersonationId>().ok())
+        .ok_or(Error::InvalidImpersonation)?;
+
+    let user = req
+        .extensions()
+        .get::<UserInfo>()
+        .cloned()
+        .ok_or(Error::Unauthenticated)?;
+
+    let admin_user_id = user.user_id;
+    let method = req.method().to_string();
+    let path = req.uri().path().to_string();
+
+    let (team_id, team_status, roles) = state
+        .db
+        .transaction(move |conn| {
+            let (team_id, team_status) = impersonations::table
+                .inner_join(db::users::table)
+                .inner_join(db::teams::table)
+                .filter(impersonations::id.eq(impersonation_id))
+                .filter(impersonations::admin_user_id.eq(admin_user_id))
+                .filter(impersonations::ended.is_null())
+                .filter(impersonations::expires.gt(diesel::dsl::now))
+                .filter(db::users::instance_admin.eq(true))
+                .filter(db::users::deleted.is_null())
+                .select((impersonations::team_id, db::teams::status))
+                .first::<(TeamId, TeamStatus)>(conn)
+                .optional()?
+                .ok_or(Error::InvalidImpersonation)?;
+
+            let roles = db::roles::table
+                .filter(db::roles::team_id.eq(team_id))
+                .filter(db::roles::deleted.is_null())
+                .select(db::roles::id)
+                .load::<RoleId>(conn)?;
+
+            diesel::insert_into(db::impersonations::impersonation_events::table)
+                .values(ImpersonationEvent {
+                    id: db::new_uuid(),
+                    impersonation_id,
+                    method,
+                    path,
+                    created: Utc::now(),
+                })
+                .execute(conn)?;
+
+            Ok::<_, Error>((team_id, team_status, roles))
+        })
+        .await?;
+
+    event!(
+        Level::INFO,
+        %impersonation_id,
+        %admin_user_id,
+        %team_id,
+        "Impersonating team"
+    );
+
+    req.extensions_mut().insert(impersonated_user(
+        &user,
+        impersonation_id,
+        team_id,
+        team_status,
+        roles,
+    ));
+
+    Ok(next.run(req).await.into_response())
+}
+
+/// The user to act as while impersonating a team. The restrictions of the API key that made the
+/// request carry over, so that a bound key can't escape its binding by impersonating.
+pub(crate) fn impersonated_user(
+    user: &UserInfo,
+    impersonation_id: ImpersonationId,
+    team_id: TeamId,
+    team_status: TeamStatus,
+    roles: Vec<RoleId>,
+) -> UserInfo {
+    UserInfo {
+        user_id: user.user_id,
+        team_id,
+        roles,
+        default_upload_profile_id: None,
+        bound_upload_profile_id: user.bound_upload_profile_id,
+        team_status,
+        impersonation_id: Some(impersonation_id),
+        api_key_id: user.api_key_id,
+    }
+}
@@ -1,16 +1,78 @@
+pub mod bulk_delete_images;
+pub mod check_delivery_urls;
+pub mod crawl_image_references;
 pub mod create_output_images;
+pub mod delete_output_images;
+pub mod expire_chunked_uploads;
+pub mod original_retention;
+pub mod send_webhooks;
 
 use std::path::Path;
 
+pub use bulk_delete_images::*;
+pub use check_delivery_urls::*;
+pub use crawl_image_references::*;
 pub use create_output_images::*;
+pub use delete_output_images::*;
+pub use expire_chunked_uploads::*;
+pub use original_retention::*;
+pub use send_webhooks::*;
 
-use pic_store_db as db;
 use effectum::{JobRunner, Queue, Worker};
+use pic_store_convert::DecodeLimits;
+use pic_store_db as db;
+use pic_store_storage::ParallelGet;
 use tracing::{event, Level};
 
+use crate::{
+    captioning::Captioner, cdn_purge::CdnPurger, config::Config, queue_slo::LatencyRecorder,
+};
+
+/// The settings from the server configuration that jobs use.
+#[derive(Clone)]
+pub struct JobSettings {
+    pub decode_limits: DecodeLimits,
+    pub download: ParallelGet,
+    pub captioner: Option<Captioner>,
+    pub ocr_language: Option<String>,
+    pub detect_codes: bool,
+    pub allow_private_networks: bool,
+}
+
+impl JobSettings {
+    pub fn from_config(config: &Config, decode_limits: DecodeLimits) -> Self {
+        JobSettings {
+            decode_limits,
+            download: ParallelGet {
+                part_size: config.download_part_size,
+                concurrency: config.download_concurrency,
+            },
+            captioner: config
+                .captioning_url
+                .clone()
+                .map(|url| Captioner::new(url, config.captioning_api_key.clone())),
+            ocr_language: config.ocr_language.clone(),
+            detect_codes: config.detect_codes,
+            allow_private_networks: config.url_upload_allow_private_networks,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct JobContext {
     pub pool: db::Pool,
+    pub decode_limits: DecodeLimits,
+    pub download: ParallelGet,
+    pub cdn_purger: CdnPurger,
+    pub captioner: Option<Captioner>,
+    /// The tesseract language for extracting text, when OCR is enabled.
+    pub ocr_language: Option<String>,
+    /// Decode QR codes and barcodes in images.
+    pub detect_codes: bool,
+    /// Allow outgoing requests to URLs that users provide to reach private addresses.
+    pub allow_private_networks: bool,
+    /// How long conversions waited in the queue, for the queue objectives.
+    pub queue_latency: LatencyRecorder,
 }
 
 impl std::fmt::Debug for JobContext {
@@ -20,20 +82,61 @@ impl std::fmt::Debug for JobContext {
 }
 
 pub const CREATE_OUTPUT_IMAGES: &str = "create_output_images";
+pub const DELETE_OUTPUT_IMAGES: &str = "delete_output_images";
+pub const APPLY_ORIGINAL_RETENTION: &str = "apply_original_retention";
+pub const BULK_DELETE_IMAGES: &str = "bulk_delete_images";
+pub const CRAWL_IMAGE_REFERENCES: &str = "crawl_image_references";
+pub const CHECK_DELIVERY_URLS: &str = "check_delivery_urls";
+pub const EXPIRE_CHUNKED_UPLOADS: &str = "expire_chunked_uploads";
+pub const SEND_WEBHOOKS: &str = "send_webhooks";
 
 pub async fn create_job_queue(
     db_path: &Path,
     pool: db::Pool,
+    settings: JobSettings,
+    cdn_purger: CdnPurger,
+    queue_latency: LatencyRecorder,
 ) -> Result<(Queue, Worker), effectum::Error> {
     event!(Level::INFO, "Starting background worker task");
     let queue = Queue::new(db_path).await?;
-    let context = JobContext { pool };
+    let context = JobContext {
+        pool,
+        decode_limits: settings.decode_limits,
+        download: settings.download,
+        cdn_purger,
+        captioner: settings.captioner,
+        ocr_language: settings.ocr_language,
+        detect_codes: settings.detect_codes,
+        allow_private_networks: settings.allow_private_networks,
+        queue_latency,
+    };
 
     let create_output_images =
         JobRunner::builder(CREATE_OUTPUT_IMAGES, create_output_images_job).build();
+    let delete_output_images =
+        JobRunner::builder(DELETE_OUTPUT_IMAGES, delete_output_images_job).build();
+    let apply_original_retention =
+        JobRunner::builder(APPLY_ORIGINAL_RETENTION, apply_original_retention_job).build();
+    let bulk_delete_images = JobRunner::builder(BULK_DELETE_IMAGES, bulk_delete_images_job).build();
+    let crawl_image_references =
+        JobRunner::builder(CRAWL_IMAGE_REFERENCES, crawl_image_references_job).build();
+    let check_delivery_urls =
+        JobRunner::builder(CHECK_DELIVERY_URLS, check_delivery_urls_job).build();
+    let expire_chunked_uploads =
+        JobRunner::builder(EXPIRE_CHUNKED_UPLOADS, expire_chunked_uploads_job).build();
+    let send_webhooks = JobRunner::builder(SEND_WEBHOOKS, send_webhooks_job).build();
 
     let worker = Worker::builder(&queue, context)
-        .jobs([create_output_images])
+        .jobs([
+            create_output_images,
+            delete_output_images,
+            apply_original_retention,
+            bulk_delete_images,
+            crawl_image_references,
+            check_delivery_urls,
+            expire_chunked_uploads,
+            send_webhooks,
+        ])
         .max_concurrency(10)
         .build()
         .await?;
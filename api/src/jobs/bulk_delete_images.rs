@@ -0,0 +1,277 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use chrono::{DateTime, Utc};
+use db::{
+    bulk_deletions::{self, BulkDeletionFilter},
+    image_base_location,
+    object_id::{BaseImageId, BulkDeletionId, ProjectId, UploadProfileId},
+    storage_locations::Provider,
+    BaseImageStatus, BulkDeletionStatus, OutputImageStatus, PoolExt,
+};
+use diesel::prelude::*;
+use effectum::RunningJob;
+use pic_store_db as db;
+use pic_store_storage::{self as storage, Operator};
+use serde::{Deserialize, Serialize};
+use tracing::{event, instrument, Level};
+
+use super::{delete_output_images::delete_output_images, JobContext};
+
+/// The number of images to load from the database at a time.
+const BATCH_SIZE: i64 = 100;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BulkDeleteImagesJobPayload {
+    pub bulk_deletion: BulkDeletionId,
+}
+
+/// An image that the bulk deletion is removing.
+struct DeletingImage {
+    id: BaseImageId,
+    location: String,
+    upload_profile_id: UploadProfileId,
+    /// The image has an original in storage that needs to be deleted.
+    has_original: bool,
+}
+
+/// Delete every image that matches a confirmed bulk deletion, updating its progress as each
+/// image is removed.
+#[instrument(skip(job))]
+pub async fn bulk_delete_images_job(
+    job: RunningJob,
+    context: JobContext,
+) -> Result<(), eyre::Report> {
+    let payload = job.json_payload::<BulkDeleteImagesJobPayload>()?;
+    let bulk_deletion_id = payload.bulk_deletion;
+
+    let error = delete_matching_images(&context, bulk_deletion_id)
+        .await
+        .err()
+        .map(|e| {
+            event!(Level::ERROR, %bulk_deletion_id, error = ?e, "Bulk deletion failed");
+            e.to_string()
+        });
+
+    // A failed deletion is not retried. Previewing the same filter again picks up whatever is
+    // left.
+    context
+        .pool
+        .interact(move |conn| {
+            let deletion = bulk_deletions::table.filter(bulk_deletions::id.eq(bulk_deletion_id));
+            match error {
+                None => diesel::update(deletion)
+                    .set((
+                        bulk_deletions::status.eq(BulkDeletionStatus::Complete),
+                        bulk_deletions::finished.eq(diesel::dsl::now),
+                    ))
+                    .execute(conn)?,
+                Some(error) => diesel::update(deletion)
+                    .set((
+                        bulk_deletions::status.eq(BulkDeletionStatus::Failed),
+                        bulk_deletions::error.eq(error),
+                        bulk_deletions::finished.eq(diesel::dsl::now),
+                    ))
+                    .execute(conn)?,
+            };
+
+            Ok::<_, eyre::Report>(())
+        })
+        .await
+}
+
+async fn delete_matching_images(
+    context: &JobContext,
+    bulk_deletion_id: BulkDeletionId,
+) -> Result<(), eyre::Report> {
+    let (project_id, filter, as_of) = context
+        .pool
+        .interact(move |conn| {
+            bulk_deletions::table
+                .filter(bulk_deletions::id.eq(bulk_deletion_id))
+                .filter(bulk_deletions::status.eq(BulkDeletionStatus::Running))
+                .select((
+                    bulk_deletions::project_id,
+                    bulk_deletions::filter,
+                    bulk_deletions::created,
+                ))
+                .first::<(ProjectId, BulkDeletionFilter, DateTime<Utc>)>(conn)
+                .map_err(eyre::Report::new)
+        })
+        .await?;
+
+    let mut operators = HashMap::new();
+    loop {
+        let filter = filter.clone();
+        let images = context
+            .pool
+            .transaction(move |conn| {
+                let images = filter
+                    .matching_images(project_id, as_of)
+                    .select((
+                        db::base_images::id,
+                        db::base_images::location,
+                        db::base_images::upload_profile_id,
+                        db::base_images::status,
+                        db::base_images::original_removed.is_null(),
+                    ))
+                    .order(db::base_images::created.asc())
+                    .limit(BATCH_SIZE)
+                    .load::<(BaseImageId, String, UploadProfileId, BaseImageStatus, bool)>(conn)?
+                    .into_iter()
+                    .map(
+                        |(id, location, upload_profile_id, status, original_present)| {
+                            DeletingImage {
+                                id,
+                                location,
+                                upload_profile_id,
+                                has_original: original_present
+                                    && !matches!(status, BaseImageStatus::AwaitingUpload),
+                            }
+                        },
+                    )
+                    .collect::<Vec<_>>();
+
+                // Move the whole batch out of the filter's reach, so that an image that fails
+                // to delete isn't picked up again.
+                let ids = images.iter().map(|image| image.id).collect::<Vec<_>>();
+                diesel::update(db::base_images::table)
+                    .filter(db::base_images::id.eq_any(ids.clone()))
+                    .set((
+                        db::base_images::status.eq(BaseImageStatus::Deleting),
+                        db::base_images::updated.eq(diesel::dsl::now),
+                    ))
+                    .execute(conn)?;
+
+                diesel::update(db::output_images::table)
+                    .filter(db::output_images::base_image_id.eq_any(ids))
+                    .filter(db::output_images::status.ne(OutputImageStatus::Deleted))
+                    .set((
+                        db::output_images::status.eq(OutputImageStatus::QueuedForDelete),
+                        db::output_images::updated.eq(diesel::dsl::now),
+                    ))
+                    .execute(conn)?;
+
+                Ok::<_, eyre::Report>(images)
+            })
+            .await?;
+
+        let done = (images.len() as i64) < BATCH_SIZE;
+        for image in images {
+            let image_id = image.id;
+            let result = delete_image(context, &mut operators, project_id, image).await;
+            let error = result.err().map(|e| {
+                event!(Level::ERROR, %bulk_deletion_id, %image_id, error = ?e, "Failed to delete image");
+                format!("{image_id}: {e}")
+            });
+
+            context
+                .pool
+                .interact(move |conn| {
+                    let deletion =
+                        bulk_deletions::table.filter(bulk_deletions::id.eq(bulk_deletion_id));
+                    match error {
+                        None => diesel::update(deletion)
+                            .set(bulk_deletions::deleted.eq(bulk_deletions::deleted + 1))
+                            .execute(conn)?,
+                        Some(error) => diesel::update(deletion)
+                            .set((
+                                bulk_deletions::failed.eq(bulk_deletions::failed + 1),
+                                bulk_deletions::error.eq(error),
+                            ))
+                            .execute(conn)?,
+                    };
+
+                    Ok::<_, eyre::Report>(())
+                })
+                .await?;
+        }
+
+        if done {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Delete an image's output images and original, and then mark it deleted.
+async fn delete_image(
+    context: &JobContext,
+    operators: &mut HashMap<UploadProfileId, Operator>,
+    project_id: ProjectId,
+    image: DeletingImage,
+) -> Result<(), eyre::Report> {
+    delete_output_images(context, image.id).await?;
+
+    if image.has_original {
+        let operator = match operators.entry(image.upload_profile_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                entry.insert(base_operator(context, project_id, image.upload_profile_id).await?)
+            }
+        };
+
+        match operator.delete(&image.location).await {
+            Ok(()) => {}
+            Err(e) if e.is_not_found() => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let image_id = image.id;
+    context
+        .pool
+        .interact(move |conn| {
+            diesel::update(db::base_images::table)
+                .filter(db::base_images::id.eq(image_id))
+                .set((
+                    db::base_images::status.eq(BaseImageStatus::Deleted),
+                    db::base_images::updated.eq(diesel::dsl::now),
+                    db::base_images::deleted.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            Ok::<_, eyre::Report>(())
+        })
+        .await
+}
+
+/// Create an operator for the location of an upload profile's originals.
+async fn base_operator(
+    context: &JobContext,
+    project_id: ProjectId,
+    upload_profile_id: UploadProfileId,
+) -> Result<Operator, eyre::Report> {
+    let (project_base_location, profile_path, storage_base_location, provider) = context
+        .pool
+        .interact(move |conn| {
+            db::upload_profiles::table
+                .inner_join(db::storage_locations::table.on(
+                    db::storage_locations::id.eq(db::upload_profiles::base_storage_location_id),
+                ))
+                .inner_join(
+                    db::projects::table.on(db::projects::id.eq(db::upload_profiles::project_id)),
+                )
+                .filter(db::upload_profiles::id.eq(upload_profile_id))
+                .filter(db::projects::id.eq(project_id))
+                .select((
+                    db::projects::base_location,
+                    db::upload_profiles::base_storage_location_path,
+                    db::storage_locations::base_location,
+                    db::storage_locations::provider,
+                ))
+                .first::<(String, Option<String>, String, Provider)>(conn)
+                .map_err(eyre::Report::new)
+        })
+        .await?;
+
+    let base_location = image_base_location(
+        &storage_base_location,
+        &project_base_location,
+        &profile_path,
+    );
+    let operator = storage::Provider::from_db(provider)?
+        .create_operator(base_location.as_ref())
+        .await?;
+    Ok(operator)
+}
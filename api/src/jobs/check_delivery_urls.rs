@@ -0,0 +1,249 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use db::{
+    image_path,
+    link_checks::{self, LinkCheck},
+    object_id::{BaseImageId, ProjectId, TeamId},
+    BaseImageStatus, OutputImageStatus, PoolExt,
+};
+use diesel::prelude::*;
+use effectum::RunningJob;
+use futures::StreamExt;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use tracing::{event, instrument, Level};
+
+use super::JobContext;
+use crate::{remote_fetch, shared_state::AppState, Error};
+
+/// How long to wait for each URL.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The number of URLs to check at once.
+const CONCURRENCY: usize = 8;
+
+/// How long to keep the results of each check.
+const RETENTION_DAYS: i64 = 30;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CheckDeliveryUrlsJobPayload {
+    /// The number of images to check in each project.
+    pub sample_size: i64,
+}
+
+/// A delivery URL to check.
+struct DeliveryUrl {
+    team_id: TeamId,
+    project_id: ProjectId,
+    base_image_id: BaseImageId,
+    url: String,
+}
+
+/// Request a random sample of each project's delivery URLs through their public path, which
+/// includes any CDN in front of the storage, and record which ones fail.
+#[instrument(skip(job))]
+pub async fn check_delivery_urls_job(
+    job: RunningJob,
+    context: JobContext,
+) -> Result<(), eyre::Report> {
+    let payload = job.json_payload::<CheckDeliveryUrlsJobPayload>()?;
+    let sample_size = payload.sample_size;
+
+    let urls = context
+        .pool
+        .interact(move |conn| sample_urls(conn, sample_size))
+        .await?;
+
+    let allow_private_networks = context.allow_private_networks;
+    let results = futures::stream::iter(urls)
+        .map(|url| check_url(url, allow_private_networks))
+        .buffer_unordered(CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    let failed = results.iter().filter(|result| !result.ok).count();
+    event!(
+        Level::INFO,
+        checked = results.len(),
+        failed,
+        "Checked delivery URLs"
+    );
+
+    context
+        .pool
+        .interact(move |conn| {
+            if !results.is_empty() {
+                diesel::insert_into(link_checks::table)
+                    .values(&results)
+                    .execute(conn)?;
+            }
+            link_checks::prune(conn, Utc::now() - chrono::Duration::days(RETENTION_DAYS))?;
+            Ok::<_, eyre::Report>(())
+        })
+        .await
+}
+
+/// Choose random ready images in each project, and return the public URL of one output image
+/// of each. Projects with delivery domains also get the serve route URL on one of the domains.
+fn sample_urls(
+    conn: &mut PgConnection,
+    sample_size: i64,
+) -> Result<Vec<DeliveryUrl>, eyre::Report> {
+    let projects = db::projects::table
+        .filter(db::projects::deleted.is_null())
+        .select((db::projects::id, db::projects::team_id))
+        .load::<(ProjectId, TeamId)>(conn)?;
+
+    let mut urls = Vec::new();
+    for (project_id, team_id) in projects {
+        let image_ids = db::base_images::table
+            .filter(db::base_images::project_id.eq(project_id))
+            .filter(db::base_images::deleted.is_null())
+            .filter(db::base_images::status.eq(BaseImageStatus::Ready))
+            .select(db::base_images::id)
+            .order(db::random())
+            .limit(sample_size)
+            .load::<BaseImageId>(conn)?;
+
+        if image_ids.is_empty() {
+            continue;
+        }
+
+        let images = db::output_images::table
+            .inner_join(db::base_images::table)
+            .inner_join(
+                db::upload_profiles::table
+                    .on(db::upload_profiles::id.eq(db::base_images::upload_profile_id)),
+            )
+            .inner_join(
+                db::storage_locations::table
+                    .on(db::storage_locations::id
+                        .eq(db::upload_profiles::output_storage_location_id)),
+            )
+            .inner_join(db::projects::table.on(db::projects::id.eq(db::base_images::project_id)))
+            .filter(db::base_images::id.eq_any(&image_ids))
+            .filter(db::output_images::status.eq(OutputImageStatus::Ready))
+            .filter(db::output_images::archival.eq(false))
+            .distinct_on(db::base_images::id)
+            .order((db::base_images::id, db::random()))
+            .select((
+                db::base_images::id,
+                db::storage_locations::public_url_base,
+                db::projects::base_location,
+                db::upload_profiles::output_storage_location_path,
+                db::output_images::location,
+            ))
+            .load::<(BaseImageId, String, String, Option<String>, String)>(conn)?;
+
+        let domains = db::delivery_domains::table
+            .filter(db::delivery_domains::project_id.eq(project_id))
+            .select(db::delivery_domains::hostname)
+            .order(db::delivery_domains::hostname.asc())
+            .load::<String>(conn)?;
+
+        for (i, (base_image_id, public_url_base, project_base, profile_path, location)) in
+            images.into_iter().enumerate()
+        {
+            let url = image_path(&public_url_base, &project_base, &profile_path, &location);
+            // Storage without a public HTTP URL can't be checked from outside.
+            if url.starts_with("http://") || url.starts_with("https://") {
+                urls.push(DeliveryUrl {
+                    team_id,
+                    project_id,
+                    base_image_id,
+                    url,
+                });
+            }
+
+            if !domains.is_empty() {
+                let domain = &domains[i % domains.len()];
+                urls.push(DeliveryUrl {
+                    team_id,
+                    project_id,
+                    base_image_id,
+                    url: format!("https://{domain}/serve/{base_image_id}"),
+                });
+            }
+        }
+    }
+
+    Ok(urls)
+}
+
+/// Request the first byte of a URL, through the same address checks as uploads from URLs.
+/// Redirects aren't followed, since the server they lead to hasn't been checked, but a redirect
+/// still counts as a working URL.
+async fn check_url(url: DeliveryUrl, allow_private_networks: bool) -> LinkCheck {
+    let response = request_first_byte(&url.url, allow_private_networks).await;
+
+    let (ok, status_code, error) = match response {
+        Ok(response) => {
+            let status = response.status();
+            let ok = status.is_success() || status.is_redirection();
+            let error = (!ok).then(|| format!("HTTP {status}"));
+            (ok, Some(status.as_u16() as i32), error)
+        }
+        Err(e) => (false, None, Some(e.to_string())),
+    };
+
+    LinkCheck {
+        id: db::new_uuid(),
+        team_id: url.team_id,
+        project_id: url.project_id,
+        base_image_id: url.base_image_id,
+        url: url.url,
+        ok,
+        status_code,
+        error,
+        checked: Utc::now(),
+    }
+}
+
+async fn request_first_byte(
+    url: &str,
+    allow_private_networks: bool,
+) -> Result<reqwest::Response, Error> {
+    let url = remote_fetch::parse_url(url)?;
+    let client = remote_fetch::pinned_client(&url, allow_private_networks, REQUEST_TIMEOUT).await?;
+    client
+        .get(url)
+        .header(reqwest::header::RANGE, "bytes=0-0")
+        .send()
+        .await
+        .map_err(|e| {
+            // The client's errors can describe the server's network, so keep the message short.
+            let message = if e.is_timeout() {
+                "Timed out"
+            } else {
+                "The request failed"
+            };
+            Error::RemoteFetchFailed(message.to_string())
+        })
+}
+
+/// Queue a job to check delivery URLs periodically.
+pub fn start_delivery_url_check_task(
+    state: AppState,
+    interval: Duration,
+    sample_size: i64,
+) -> tokio::task::JoinHandle<()> {
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let result = async {
+                effectum::Job::builder(super::CHECK_DELIVERY_URLS)
+                    .json_payload(&CheckDeliveryUrlsJobPayload { sample_size })?
+                    .add_to(&state.queue)
+                    .await?;
+                Ok::<_, eyre::Report>(())
+            }
+            .await;
+            if let Err(e) = result {
+                event!(Level::ERROR, error = ?e, "Failed to queue delivery URL check job");
+            }
+        }
+    })
+}
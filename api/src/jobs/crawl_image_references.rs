@@ -0,0 +1,242 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use db::{
+    image_references::{self, NewImageReference},
+    object_id::{ProjectId, ReferenceCrawlId, TeamId},
+    reference_crawls, ImageReferenceSource, PoolExt, ReferenceCrawlStatus,
+};
+use diesel::prelude::*;
+use effectum::RunningJob;
+use pic_store_db as db;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tracing::{event, instrument, Level};
+
+use super::JobContext;
+use crate::{
+    image_references::{extract_image_urls, parse_sitemap, resolve_image_urls, Sitemap},
+    remote_fetch::{self, FetchPolicy},
+    Error,
+};
+
+/// How long to wait for each sitemap or page.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Pages and sitemaps larger than this are cut off.
+const MAX_DOCUMENT_BYTES: usize = 10 * 1024 * 1024;
+
+/// The most sitemaps to read from a sitemap index.
+const MAX_SITEMAPS: usize = 50;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CrawlImageReferencesJobPayload {
+    pub crawl: ReferenceCrawlId,
+}
+
+/// Scan the pages in a sitemap for the project's images and record the references that it
+/// finds.
+#[instrument(skip(job))]
+pub async fn crawl_image_references_job(
+    job: RunningJob,
+    context: JobContext,
+) -> Result<(), eyre::Report> {
+    let payload = job.json_payload::<CrawlImageReferencesJobPayload>()?;
+    let crawl_id = payload.crawl;
+
+    let error = crawl(&context, crawl_id).await.err().map(|e| {
+        event!(Level::ERROR, %crawl_id, error = ?e, "Reference crawl failed");
+        crawl_error_message(&e)
+    });
+
+    // A failed crawl is not retried, since it can be started again.
+    context
+        .pool
+        .interact(move |conn| {
+            let crawl = reference_crawls::table.filter(reference_crawls::id.eq(crawl_id));
+            match error {
+                None => diesel::update(crawl)
+                    .set((
+                        reference_crawls::status.eq(ReferenceCrawlStatus::Complete),
+                        reference_crawls::finished.eq(diesel::dsl::now),
+                    ))
+                    .execute(conn)?,
+                Some(error) => diesel::update(crawl)
+                    .set((
+                        reference_crawls::status.eq(ReferenceCrawlStatus::Failed),
+                        reference_crawls::error.eq(error),
+                        reference_crawls::finished.eq(diesel::dsl::now),
+                    ))
+                    .execute(conn)?,
+            };
+
+            Ok::<_, eyre::Report>(())
+        })
+        .await
+}
+
+async fn crawl(context: &JobContext, crawl_id: ReferenceCrawlId) -> Result<(), eyre::Report> {
+    let (team_id, project_id, sitemap_url, max_pages, started) = context
+        .pool
+        .interact(move |conn| {
+            reference_crawls::table
+                .filter(reference_crawls::id.eq(crawl_id))
+                .filter(reference_crawls::status.eq(ReferenceCrawlStatus::Running))
+                .select((
+                    reference_crawls::team_id,
+                    reference_crawls::project_id,
+                    reference_crawls::sitemap_url,
+                    reference_crawls::max_pages,
+                    reference_crawls::created,
+                ))
+                .first::<(TeamId, ProjectId, String, i32, DateTime<Utc>)>(conn)
+                .map_err(eyre::Report::new)
+        })
+        .await?;
+
+    let policy = FetchPolicy {
+        timeout: REQUEST_TIMEOUT,
+        max_size: MAX_DOCUMENT_BYTES,
+        allow_private_networks: context.allow_private_networks,
+    };
+    let sitemap_url = remote_fetch::parse_url(&sitemap_url)?;
+    let (pages, truncated) = sitemap_pages(&policy, &sitemap_url, max_pages as usize).await?;
+
+    let mut failed_pages = 0;
+    for page in pages {
+        let (html, page_base) = match fetch(&policy, &page).await {
+            Ok(result) => result,
+            Err(e) => {
+                event!(Level::WARN, %crawl_id, %page, error = ?e, "Failed to fetch page");
+                failed_pages += 1;
+                continue;
+            }
+        };
+
+        let image_urls = extract_image_urls(&html, &page_base);
+        let page_url = page.to_string();
+        context
+            .pool
+            .interact(move |conn| {
+                let mut ids = resolve_image_urls(conn, project_id, &image_urls)?
+                    .into_values()
+                    .collect::<Vec<_>>();
+                ids.sort();
+                ids.dedup();
+
+                let references = ids
+                    .into_iter()
+                    .map(|base_image_id| NewImageReference {
+                        base_image_id,
+                        team_id,
+                        project_id,
+                        page_url: page_url.clone(),
+                        source: ImageReferenceSource::Crawl,
+                    })
+                    .collect::<Vec<_>>();
+                image_references::record(conn, &references)?;
+
+                diesel::update(reference_crawls::table)
+                    .filter(reference_crawls::id.eq(crawl_id))
+                    .set((
+                        reference_crawls::pages_scanned.eq(reference_crawls::pages_scanned + 1),
+                        reference_crawls::references_found
+                            .eq(reference_crawls::references_found + references.len() as i32),
+                    ))
+                    .execute(conn)?;
+
+                Ok::<_, eyre::Report>(())
+            })
+            .await?;
+    }
+
+    // Crawled references that weren't seen again are gone from the site, but only when every
+    // page was scanned. Otherwise they may be on a page that was skipped.
+    if !truncated && failed_pages == 0 {
+        context
+            .pool
+            .interact(move |conn| {
+                image_references::remove_stale_crawled(conn, project_id, started)
+                    .map_err(eyre::Report::new)
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Read the pages from a sitemap, following a sitemap index one level down. Only pages on the
+/// sitemap's host are returned. Returns true if there were more than `max_pages` pages.
+async fn sitemap_pages(
+    policy: &FetchPolicy,
+    sitemap_url: &Url,
+    max_pages: usize,
+) -> Result<(Vec<Url>, bool), eyre::Report> {
+    let urls = match parse_sitemap(&fetch(policy, sitemap_url).await?.0) {
+        Sitemap::Pages(urls) => urls,
+        Sitemap::Index(sitemaps) => {
+            let mut urls = Vec::new();
+            for sitemap in sitemaps.iter().take(MAX_SITEMAPS) {
+                let Some(sitemap) = same_host(sitemap_url, sitemap) else {
+                    continue;
+                };
+                // Nested sitemap indexes aren't followed.
+                if let Sitemap::Pages(pages) = parse_sitemap(&fetch(policy, &sitemap).await?.0) {
+                    urls.extend(pages);
+                }
+            }
+            urls
+        }
+    };
+
+    let mut pages = urls
+        .iter()
+        .filter_map(|url| same_host(sitemap_url, url))
+        .collect::<Vec<_>>();
+    pages.dedup();
+    let truncated = pages.len() > max_pages;
+    pages.truncate(max_pages);
+    Ok((pages, truncated))
+}
+
+/// Parse a URL from a sitemap, if it is on the same host as the sitemap.
+fn same_host(sitemap_url: &Url, url: &str) -> Option<Url> {
+    let url = Url::parse(url).ok()?;
+    (url.scheme() == sitemap_url.scheme() && url.host() == sitemap_url.host()).then_some(url)
+}
+
+/// Download a page or sitemap as text. Each redirect is checked like the first request, so a
+/// public page can't redirect the crawler to a private address. Returns the text and the URL it
+/// came from after any redirects.
+async fn fetch(policy: &FetchPolicy, url: &Url) -> Result<(String, Url), eyre::Report> {
+    let (final_url, mut response) = remote_fetch::get(
+        url.clone(),
+        policy,
+        "text/html, application/xml;q=0.9, */*;q=0.8",
+    )
+    .await?;
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_DOCUMENT_BYTES {
+            body.truncate(MAX_DOCUMENT_BYTES);
+            break;
+        }
+    }
+
+    Ok((String::from_utf8_lossy(&body).into_owned(), final_url))
+}
+
+/// The error to record for a failed crawl. The underlying errors can describe the server's own
+/// network, so only the kind of failure is saved where the user can see it.
+fn crawl_error_message(e: &eyre::Report) -> String {
+    let message = match e.downcast_ref::<Error>() {
+        Some(Error::InvalidRemoteUrl(_)) => "The sitemap URL is not valid",
+        Some(Error::ForbiddenRemoteAddress) => {
+            "The sitemap URL leads to an address that can not be fetched"
+        }
+        _ => "Failed to read the sitemap",
+    };
+
+    message.to_string()
+}
@@ -1,30 +1,49 @@
 use std::sync::Arc;
 
+use base64::Engine;
 use bytes::Bytes;
 use db::{
     base_images,
-    conversion_profiles::{ConversionFormat, ConversionSize},
-    image_base_location,
-    object_id::{BaseImageId, OutputImageId},
-    storage_locations::Provider,
-    upload_profiles, BaseImageStatus, OutputImageStatus, PoolExt,
+    conversion_profiles::{
+        self, AnimationSettings, ConversionFormat, ConversionOutput, ConversionSize,
+    },
+    image_base_location, image_path,
+    object_id::{
+        BaseImageId, ConversionProfileId, OutputImageId, ProjectId, StorageLocationId, TeamId,
+    },
+    storage_locations::{CdnPurge, Provider},
+    tagging_rules::TaggingRule,
+    transformation_presets::{TransformationOperation, WatermarkPosition},
+    upload_profiles::{self, FormatFallbacks, PostProcessCallback},
+    BaseImageStatus, ImageFormat, OutputImageStatus, PoolExt,
 };
 use diesel::prelude::*;
 use effectum::RunningJob;
 use image::DynamicImage;
-use pic_store_convert as convert;
+use pic_store_convert::{
+    self as convert,
+    animation::{Frame, FrameSelection},
+    exif::ExifFields,
+};
 use pic_store_db as db;
 use pic_store_storage as storage;
 use serde::{Deserialize, Serialize};
 use tracing::{event, instrument, Level};
 
 use super::JobContext;
-use crate::Result;
+use crate::{
+    captioning::Captioner, cdn_purge::PurgeTarget, checksum, conversion_pause, mirrors,
+    post_process, tagging, webhooks, Result,
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CreateOutputImagesJobPayload {
     pub base_image: BaseImageId,
     pub conversions: Vec<OutputImageId>,
+    /// The conversion profile chooses its own sizes, so the output images need to be created
+    /// before the conversions can run.
+    #[serde(default)]
+    pub choose_breakpoints: bool,
 }
 
 #[instrument(skip(job))]
@@ -36,17 +55,54 @@ pub async fn create_output_images_job(
 
     event!(Level::INFO, ?payload);
 
+    // Jobs that were already queued when conversions were paused are held until they resume.
+    let (held, waiting_since) = {
+        let payload = payload.clone();
+        context
+            .pool
+            .interact(move |conn| {
+                let (team_id, status, updated) = db::base_images::table
+                    .filter(db::base_images::id.eq(payload.base_image))
+                    .select((
+                        db::base_images::team_id,
+                        db::base_images::status,
+                        db::base_images::updated,
+                    ))
+                    .first::<(TeamId, BaseImageStatus, chrono::DateTime<chrono::Utc>)>(conn)?;
+                let held = conversion_pause::hold_if_paused(conn, team_id, &payload)?;
+                let waiting_since =
+                    matches!(status, BaseImageStatus::Converting).then_some(updated);
+                Ok::<_, crate::Error>((held, waiting_since))
+            })
+            .await?
+    };
+
+    if held {
+        event!(Level::INFO, "Conversions are paused, holding job");
+        return Ok(());
+    }
+
+    if let Some(latency) =
+        waiting_since.and_then(|since| (chrono::Utc::now() - since).to_std().ok())
+    {
+        context.queue_latency.record(latency);
+    }
+
     let (bst, ost) = diesel::alias!(db::storage_locations as bst, db::storage_locations as ost);
 
     let (
         project_base_location,
         base_image_location,
+        base_image_hash,
         base_image_base_location,
         base_image_profile_base_path,
         base_image_storage_provider,
         output_image_base_location,
         output_image_profile_base_path,
         output_image_storage_provider,
+        output_public_url_base,
+        output_cdn_purge,
+        format_fallbacks,
     ) = context
         .pool
         .interact(move |conn| {
@@ -70,22 +126,30 @@ pub async fn create_output_images_job(
                 .select((
                     db::projects::base_location,
                     db::base_images::location,
+                    db::base_images::hash,
                     bst.field(db::storage_locations::base_location),
                     upload_profiles::base_storage_location_path,
                     bst.field(db::storage_locations::provider),
                     ost.field(db::storage_locations::base_location),
                     upload_profiles::output_storage_location_path,
                     ost.field(db::storage_locations::provider),
+                    ost.field(db::storage_locations::public_url_base),
+                    ost.field(db::storage_locations::cdn_purge),
+                    upload_profiles::format_fallbacks,
                 ))
                 .first::<(
                     String,
                     String,
+                    Option<String>,
                     String,
                     Option<String>,
                     Provider,
                     String,
                     Option<String>,
                     Provider,
+                    String,
+                    Option<CdnPurge>,
+                    Option<FormatFallbacks>,
                 )>(conn)
                 .map_err(eyre::Report::new)
         })
@@ -97,13 +161,57 @@ pub async fn create_output_images_job(
         &base_image_profile_base_path,
     );
 
+    let (operations, animation) = load_conversion_settings(&context, payload.base_image).await?;
+    let operations: Arc<[convert::Operation]> = operations.into();
+
     let base_image_storage = storage::Provider::from_db(base_image_storage_provider)?;
-    let base_image = read_image(
+    let (base_image, frames, exif) = match read_image(
         base_image_storage,
         base_image_base_location.as_ref(),
         base_image_location.as_str(),
+        base_image_hash.as_deref(),
+        animation,
+        &context,
     )
-    .await?;
+    .await
+    {
+        Ok(image) => image,
+        Err(e) => {
+            // Retrying won't help with an image that is too large, so mark it as rejected
+            // and finish the job.
+            if let Some(convert::Error::TooLarge(limit_error)) = e.downcast_ref::<convert::Error>()
+            {
+                event!(Level::WARN, %limit_error, "Rejecting image");
+                reject_base_image(&context, payload.base_image, limit_error.to_string()).await?;
+                return Ok(());
+            }
+
+            return Err(e);
+        }
+    };
+
+    apply_tagging_rules(&context, payload.base_image, exif.unwrap_or_default()).await?;
+
+    if let Some(captioner) = context.captioner.as_ref() {
+        generate_alt_text(&context, captioner, payload.base_image, &base_image).await?;
+    }
+
+    #[cfg(feature = "ocr")]
+    if let Some(language) = context.ocr_language.clone() {
+        extract_text(&context, payload.base_image, base_image.clone(), language).await?;
+    }
+
+    #[cfg(feature = "barcodes")]
+    if context.detect_codes {
+        detect_codes(&context, payload.base_image, base_image.clone()).await?;
+    }
+
+    if payload.choose_breakpoints {
+        payload.conversions =
+            create_breakpoint_output_images(&context, &payload, &base_image).await?;
+        payload.choose_breakpoints = false;
+        job.checkpoint_json(&payload).await?;
+    }
 
     let output_image_base_location = image_base_location(
         &output_image_base_location,
@@ -116,9 +224,34 @@ pub async fn create_output_images_job(
         .create_operator(output_image_base_location.as_ref())
         .await?;
 
+    let (team_id, mirror_locations) = context
+        .pool
+        .interact(move |conn| {
+            let (team_id, mirror_ids) = db::base_images::table
+                .inner_join(db::upload_profiles::table)
+                .filter(db::base_images::id.eq(payload.base_image))
+                .select((
+                    db::base_images::team_id,
+                    db::upload_profiles::output_mirror_location_ids,
+                ))
+                .first::<(TeamId, Vec<StorageLocationId>)>(conn)?;
+            let locations = mirrors::load_locations(conn, team_id, &mirror_ids)?;
+            Ok::<_, eyre::Report>((team_id, locations))
+        })
+        .await?;
+    let mirrors = mirrors::open(
+        mirror_locations,
+        &project_base_location,
+        &output_image_profile_base_path,
+    )
+    .await;
+
+    // Outputs which already existed with different contents, and so may be cached by the CDN.
+    let mut replaced_urls = Vec::new();
+
     while let Some(output_image_id) = payload.conversions.pop() {
         //  Get the next conversion profile from the list
-        let (output_location, conversion_format, conversion_size) = context
+        let (output_location, conversion_format, conversion_size, archival) = context
             .pool
             .interact(move |conn| {
                 diesel::update(db::output_images::table)
@@ -131,8 +264,9 @@ pub async fn create_output_images_job(
                         db::output_images::location,
                         db::output_images::format,
                         db::output_images::size,
+                        db::output_images::archival,
                     ))
-                    .get_result::<(String, ConversionFormat, ConversionSize)>(conn)
+                    .get_result::<(String, ConversionFormat, ConversionSize, bool)>(conn)
                     .map_err(eyre::Report::new)
             })
             .await?;
@@ -147,65 +281,750 @@ pub async fn create_output_images_job(
 
         let output_format = image::ImageFormat::from(&conversion_format);
         let quality = conversion_format.quality();
+        // Use the same effort as the serve route, so that both produce the same image.
+        let effort = format_fallbacks
+            .as_ref()
+            .and_then(|f| f.max_effort(conversion_format.as_db_image_format()));
         let b = base_image.clone();
+        let ops = operations.clone();
+        // Only WebP outputs can be animated. Other formats use the first frame.
+        let animation_frames = frames
+            .clone()
+            .filter(|_| !archival && output_format == image::ImageFormat::WebP);
 
-        event!(Level::INFO, image=%output_location, format=?output_format, quality=?quality, "Converting image");
+        event!(Level::INFO, image=%output_location, format=?output_format, quality=?quality, ?effort, archival, animated=animation_frames.is_some(), "Converting image");
         let convert_result = tokio::task::spawn_blocking(move || {
-            convert::convert(&b, output_format, quality, &size)
+            if archival {
+                convert::archival_copy(&b, output_format)
+            } else if let Some(frames) = animation_frames {
+                convert::animation::convert_animation(
+                    &frames,
+                    output_format,
+                    quality,
+                    effort,
+                    &size,
+                    &ops,
+                )
+            } else {
+                convert::convert_with_effort(&b, output_format, quality, effort, &size, &ops)
+            }
         })
         .await??;
 
         let size_bytes = convert_result.image.len() as i32;
-        output_operator
-            .put(output_location.as_str(), Bytes::from(convert_result.image))
-            .await?;
+        let etag = blake3::hash(&convert_result.image).to_hex().to_string();
+        let image = Bytes::from(convert_result.image);
+        let sha256 =
+            checksum::put_verified(&output_operator, output_location.as_str(), image.clone())
+                .await?;
+        mirrors::write(
+            &context.pool,
+            team_id,
+            output_image_id,
+            &mirrors,
+            output_location.as_str(),
+            image,
+        )
+        .await?;
 
-        context
+        let new_etag = etag.clone();
+        let old_etag = context
             .pool
             .interact(move |conn| {
+                let old_etag = db::output_images::table
+                    .filter(db::output_images::id.eq(output_image_id))
+                    .select(db::output_images::etag)
+                    .first::<Option<String>>(conn)?;
+
                 // Add the OutputImage entry
                 diesel::update(db::output_images::table)
                     .filter(db::output_images::id.eq(output_image_id))
                     .set((
                         db::output_images::status.eq(OutputImageStatus::Ready),
                         db::output_images::file_size.eq(size_bytes),
+                        db::output_images::etag.eq(etag),
+                        db::output_images::sha256.eq(sha256),
                         db::output_images::width.eq(convert_result.width as i32),
                         db::output_images::height.eq(convert_result.height as i32),
                         db::output_images::updated.eq(diesel::dsl::now),
                     ))
                     .execute(conn)?;
 
-                Ok::<_, eyre::Report>(())
+                Ok::<_, eyre::Report>(old_etag)
             })
             .await?;
 
+        if old_etag.map(|old| old != new_etag).unwrap_or(false) {
+            replaced_urls.push(image_path(
+                &output_public_url_base,
+                &project_base_location,
+                &output_image_profile_base_path,
+                &output_location,
+            ));
+        }
+
         job.checkpoint_json(&payload).await?;
     }
 
-    // Set the base image status to done.
+    // The profile's callback can add metadata to the image, or keep it from being published.
+    if let Some(reason) = run_post_process_callback(
+        &context,
+        payload.base_image,
+        &output_public_url_base,
+        &project_base_location,
+        &output_image_profile_base_path,
+    )
+    .await?
+    {
+        event!(Level::INFO, %reason, "Rejecting image");
+        reject_base_image(&context, payload.base_image, reason).await?;
+        return Ok(());
+    }
+
+    // Set the base image status to done, unless it was taken down while converting.
+    context
+        .pool
+        .transaction(move |conn| {
+            let converted = diesel::update(db::base_images::table)
+                .filter(db::base_images::id.eq(payload.base_image))
+                .filter(db::base_images::status.ne(BaseImageStatus::TakenDown))
+                .set((
+                    db::base_images::status.eq(BaseImageStatus::Ready),
+                    db::base_images::converted.eq(diesel::dsl::now),
+                ))
+                .returning((db::base_images::team_id, db::base_images::project_id))
+                .get_result::<(TeamId, ProjectId)>(conn)
+                .optional()?;
+
+            if let Some((team_id, project_id)) = converted {
+                webhooks::enqueue(
+                    conn,
+                    team_id,
+                    Some(project_id),
+                    webhooks::IMAGE_CONVERTED,
+                    serde_json::json!({ "image_id": payload.base_image }),
+                )?;
+            }
+
+            Ok::<_, crate::Error>(())
+        })
+        .await?;
+
+    context
+        .cdn_purger
+        .purge_best_effort(
+            &output_cdn_purge,
+            &PurgeTarget {
+                image_id: payload.base_image,
+                urls: &replaced_urls,
+            },
+        )
+        .await;
+
+    Ok(())
+}
+
+/// Send the image to its upload profile's post-processing callback, if the profile has one, and
+/// save the metadata from the response. Returns the reason when the callback rejects the image.
+async fn run_post_process_callback(
+    context: &JobContext,
+    base_image_id: BaseImageId,
+    public_url_base: &str,
+    project_base_location: &str,
+    profile_path: &Option<String>,
+) -> Result<Option<String>, eyre::Report> {
+    let loaded = context
+        .pool
+        .interact(move |conn| {
+            let (callback, image) = db::base_images::table
+                .inner_join(db::upload_profiles::table)
+                .filter(db::base_images::id.eq(base_image_id))
+                .select((
+                    db::upload_profiles::post_process_callback,
+                    (
+                        db::base_images::id,
+                        db::base_images::project_id,
+                        db::base_images::upload_profile_id,
+                        db::base_images::filename,
+                        db::base_images::width,
+                        db::base_images::height,
+                        db::base_images::format,
+                        db::base_images::tags,
+                        db::base_images::alt_text,
+                        db::base_images::collection,
+                    ),
+                ))
+                .first::<(Option<PostProcessCallback>, post_process::CallbackImage)>(conn)?;
+
+            let Some(callback) = callback else {
+                return Ok(None);
+            };
+
+            let outputs = db::output_images::table
+                .filter(db::output_images::base_image_id.eq(base_image_id))
+                .filter(db::output_images::status.eq(OutputImageStatus::Ready))
+                .filter(db::output_images::archival.eq(false))
+                .filter(db::output_images::deleted.is_null())
+                .select((
+                    db::output_images::id,
+                    db::output_images::format,
+                    db::output_images::width,
+                    db::output_images::height,
+                    db::output_images::location,
+                ))
+                .load::<(
+                    OutputImageId,
+                    ConversionFormat,
+                    Option<i32>,
+                    Option<i32>,
+                    String,
+                )>(conn)?;
+
+            Ok::<_, eyre::Report>(Some((callback, image, outputs)))
+        })
+        .await?;
+
+    let Some((callback, image, outputs)) = loaded else {
+        return Ok(None);
+    };
+
+    let outputs = outputs
+        .into_iter()
+        .map(
+            |(id, format, width, height, location)| post_process::CallbackOutput {
+                id,
+                format: format.as_db_image_format(),
+                width,
+                height,
+                url: image_path(
+                    public_url_base,
+                    project_base_location,
+                    profile_path,
+                    &location,
+                ),
+            },
+        )
+        .collect();
+    let request = post_process::CallbackRequest { image, outputs };
+
+    let response = match post_process::call(&callback, &request, context.allow_private_networks)
+        .await
+    {
+        Ok(response) => response,
+        Err(e) if callback.publish_on_failure => {
+            event!(Level::WARN, error = ?e, "Post-processing callback failed, publishing anyway");
+            return Ok(None);
+        }
+        Err(e) => return Err(e.wrap_err("Post-processing callback failed")),
+    };
+
+    if let Some(reason) = response.rejection() {
+        return Ok(Some(reason));
+    }
+
+    let mut tags = request.image.tags;
+    for tag in tagging::normalize_tags(response.tags) {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    let collection = response.collection.or(request.image.collection);
+    let alt_text = response.alt_text;
+
     context
         .pool
         .interact(move |conn| {
             diesel::update(db::base_images::table)
-                .filter(db::base_images::id.eq(payload.base_image))
-                .set(db::base_images::status.eq(BaseImageStatus::Ready))
+                .filter(db::base_images::id.eq(base_image_id))
+                .set((
+                    db::base_images::tags.eq(tags),
+                    db::base_images::collection.eq(collection),
+                    db::base_images::updated.eq(diesel::dsl::now),
+                ))
                 .execute(conn)?;
 
+            if let Some(alt_text) = alt_text {
+                diesel::update(db::base_images::table)
+                    .filter(db::base_images::id.eq(base_image_id))
+                    .set((
+                        db::base_images::alt_text.eq(alt_text),
+                        db::base_images::alt_text_machine_generated.eq(false),
+                    ))
+                    .execute(conn)?;
+            }
+
             Ok::<_, eyre::Report>(())
         })
         .await?;
 
-    Ok(())
+    Ok(None)
 }
 
+/// Choose the output widths for a conversion profile with automatic sizes, and create the
+/// output images for them.
+async fn create_breakpoint_output_images(
+    context: &JobContext,
+    payload: &CreateOutputImagesJobPayload,
+    base_image: &Arc<DynamicImage>,
+) -> Result<Vec<OutputImageId>, eyre::Report> {
+    let base_image_id = payload.base_image;
+    let (
+        team_id,
+        project_id,
+        base_image_location,
+        base_image_format,
+        output_key_prefix,
+        conversion_profile_id,
+        conversion_profile_version,
+        output,
+        key_template,
+    ) = context
+        .pool
+        .interact(move |conn| {
+            db::base_images::table
+                .inner_join(upload_profiles::table.inner_join(conversion_profiles::table))
+                .filter(db::base_images::id.eq(base_image_id))
+                .select((
+                    db::base_images::team_id,
+                    db::base_images::project_id,
+                    db::base_images::location,
+                    db::base_images::format,
+                    db::base_images::output_key_prefix,
+                    conversion_profiles::id,
+                    conversion_profiles::version,
+                    conversion_profiles::output,
+                    conversion_profiles::output_key_template,
+                ))
+                .first::<(
+                    TeamId,
+                    ProjectId,
+                    String,
+                    Option<ImageFormat>,
+                    Option<String>,
+                    ConversionProfileId,
+                    i32,
+                    ConversionOutput,
+                    Option<String>,
+                )>(conn)
+                .map_err(eyre::Report::new)
+        })
+        .await?;
+
+    let base_image_format =
+        base_image_format.ok_or_else(|| eyre::eyre!("Base image has no format"))?;
+
+    let ConversionOutput::Auto { formats, .. } = &output else {
+        return Ok(Vec::new());
+    };
+
+    let image = base_image.clone();
+    let auto_output = output.clone();
+    let sizes = tokio::task::spawn_blocking(move || {
+        choose_automatic_sizes(&image, base_image_format, &auto_output)
+    })
+    .await??;
+
+    let output_image_base = crate::routes::image::OutputImageBase {
+        team_id,
+        project_id,
+        id: base_image_id,
+        location: &base_image_location,
+        format: base_image_format,
+        key_prefix: output_key_prefix.as_deref(),
+    };
+    let mut output_images = crate::routes::image::build_output_images(
+        formats,
+        &sizes,
+        key_template.as_deref(),
+        &output_image_base,
+    )?;
+    // Keep the archival master that was created with the upload, since the outputs not in
+    // this list are deleted.
+    output_images.extend(crate::routes::image::archival_output_image(
+        &output,
+        &output_image_base,
+    ));
+
+    if output_images.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let output_image_ids = context
+        .pool
+        .transaction(move |conn| {
+            crate::routes::image::replace_output_images(
+                conn,
+                team_id,
+                base_image_id,
+                (conversion_profile_id, conversion_profile_version),
+                output_images,
+            )
+        })
+        .await?;
+
+    Ok(output_image_ids)
+}
+
+/// Choose the output sizes for a conversion profile with automatic sizes. This returns no
+/// sizes if the profile does not use automatic sizes, or none of its formats apply to the
+/// image. This does CPU-heavy work and should not be run directly in an async context.
+pub(crate) fn choose_automatic_sizes(
+    image: &DynamicImage,
+    image_format: ImageFormat,
+    output: &ConversionOutput,
+) -> Result<Vec<ConversionSize>, eyre::Report> {
+    let ConversionOutput::Auto {
+        formats,
+        min_width,
+        max_width,
+        byte_step,
+        max_sizes,
+        ..
+    } = output
+    else {
+        return Ok(Vec::new());
+    };
+
+    let Some(reference_format) = formats.iter().find(|f| f.matches_condition(image_format)) else {
+        return Ok(Vec::new());
+    };
+
+    let settings = convert::breakpoints::BreakpointSettings {
+        min_width: *min_width,
+        max_width: *max_width,
+        byte_step: *byte_step,
+        max_sizes: *max_sizes,
+    };
+
+    let widths = convert::breakpoints::choose_breakpoints(
+        image,
+        image::ImageFormat::from(reference_format),
+        reference_format.quality(),
+        &settings,
+    )?;
+
+    event!(Level::INFO, ?widths, "Chose image breakpoints");
+
+    let sizes = widths
+        .into_iter()
+        .map(|width| ConversionSize {
+            width: Some(width),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+
+    Ok(sizes)
+}
+
+/// Load the operations from the transformation preset referenced by the image's conversion
+/// profile, and the profile's settings for animated images. The preset is looked up when the
+/// job runs so that changes to it apply to every profile which uses it.
+async fn load_conversion_settings(
+    context: &JobContext,
+    base_image_id: BaseImageId,
+) -> Result<(Vec<convert::Operation>, Option<AnimationSettings>), eyre::Report> {
+    context
+        .pool
+        .interact(move |conn| {
+            let (team_id, project_id, output) = db::base_images::table
+                .inner_join(upload_profiles::table.inner_join(conversion_profiles::table))
+                .filter(db::base_images::id.eq(base_image_id))
+                .select((
+                    db::base_images::team_id,
+                    db::base_images::project_id,
+                    conversion_profiles::output,
+                ))
+                .first::<(TeamId, ProjectId, ConversionOutput)>(conn)?;
+
+            let operations = preset_operations(conn, team_id, Some(project_id), &output)?;
+            Ok((operations, output.animation()))
+        })
+        .await
+}
+
+/// Load the operations for the transformation preset used by a conversion profile's output.
+pub(crate) fn preset_operations(
+    conn: &mut PgConnection,
+    team_id: TeamId,
+    project_id: Option<ProjectId>,
+    output: &ConversionOutput,
+) -> Result<Vec<convert::Operation>, eyre::Report> {
+    let Some(name) = output.preset() else {
+        return Ok(Vec::new());
+    };
+
+    let preset = db::transformation_presets::find_by_name(conn, team_id, project_id, name)?
+        .ok_or_else(|| eyre::eyre!("Unknown transformation preset {name}"))?;
+
+    preset
+        .operations
+        .0
+        .into_iter()
+        .map(convert_operation)
+        .collect()
+}
+
+/// The width of a watermark as a fraction of the output's width, when the preset doesn't say.
+const DEFAULT_WATERMARK_SCALE: f32 = 0.2;
+
+fn convert_operation(op: TransformationOperation) -> Result<convert::Operation, eyre::Report> {
+    let op = match op {
+        TransformationOperation::Sharpen { sigma, threshold } => {
+            convert::Operation::Sharpen { sigma, threshold }
+        }
+        TransformationOperation::Blur { sigma } => convert::Operation::Blur { sigma },
+        TransformationOperation::Brighten { value } => convert::Operation::Brighten { value },
+        TransformationOperation::Contrast { value } => convert::Operation::Contrast { value },
+        TransformationOperation::Grayscale => convert::Operation::Grayscale,
+        TransformationOperation::Rotate90 => convert::Operation::Rotate90,
+        TransformationOperation::Rotate180 => convert::Operation::Rotate180,
+        TransformationOperation::Rotate270 => convert::Operation::Rotate270,
+        TransformationOperation::FlipHorizontal => convert::Operation::FlipHorizontal,
+        TransformationOperation::FlipVertical => convert::Operation::FlipVertical,
+        TransformationOperation::Watermark {
+            image,
+            position,
+            opacity,
+            scale,
+        } => convert::Operation::Watermark {
+            image: Arc::new(convert::WatermarkImage::new(
+                base64::engine::general_purpose::STANDARD.decode(image)?,
+            )),
+            position: match position {
+                WatermarkPosition::TopLeft => convert::WatermarkPosition::TopLeft,
+                WatermarkPosition::TopRight => convert::WatermarkPosition::TopRight,
+                WatermarkPosition::BottomLeft => convert::WatermarkPosition::BottomLeft,
+                WatermarkPosition::BottomRight => convert::WatermarkPosition::BottomRight,
+                WatermarkPosition::Center => convert::WatermarkPosition::Center,
+            },
+            opacity: opacity.unwrap_or(1.0),
+            scale: scale.unwrap_or(DEFAULT_WATERMARK_SCALE),
+        },
+    };
+
+    Ok(op)
+}
+
+/// Download and decode the base image. The download is checked against the hash recorded at
+/// upload time, since a large original is assembled from many range requests. When the profile
+/// has animation settings and the image is animated, its selected frames are decoded too.
 async fn read_image(
     storage_provider: pic_store_storage::Provider,
     base_location: &str,
     location: &str,
-) -> Result<Arc<DynamicImage>, eyre::Report> {
+    expected_hash: Option<&str>,
+    animation: Option<AnimationSettings>,
+    context: &JobContext,
+) -> Result<(Arc<DynamicImage>, Option<Arc<[Frame]>>, Option<ExifFields>), eyre::Report> {
     let op = storage_provider.create_operator(base_location).await?;
-    let base_image_data = op.get(location).await?;
-    let buffer = base_image_data.bytes().await?;
-    let base_image = Arc::new(convert::image_from_bytes(&buffer)?);
-    Ok(base_image)
+    let buffer = op.get_parallel(location, &context.download).await?;
+
+    if let Some(expected_hash) = expected_hash {
+        let hash = blake3::hash(&buffer);
+        if hash.to_hex().as_str() != expected_hash {
+            return Err(eyre::eyre!(
+                "Base image {location} has hash {hash}, expected {expected_hash}"
+            ));
+        }
+    }
+
+    let base_image = Arc::new(convert::image_from_bytes(&buffer, &context.decode_limits)?);
+    let frames = match animation {
+        Some(animation) => {
+            let selection = FrameSelection {
+                max_frames: animation.max_frames,
+                frame_step: animation.frame_step,
+            };
+            convert::animation::decode_frames(&buffer, &context.decode_limits, &selection)?
+                .map(Arc::from)
+        }
+        None => None,
+    };
+    let exif = convert::exif::read_exif(&buffer);
+    Ok((base_image, frames, exif))
+}
+
+/// Tag the image and set its collection using the project's tagging rules.
+async fn apply_tagging_rules(
+    context: &JobContext,
+    base_image_id: BaseImageId,
+    exif: ExifFields,
+) -> Result<(), eyre::Report> {
+    context
+        .pool
+        .interact(move |conn| {
+            let rules = db::tagging_rules::table
+                .inner_join(
+                    db::base_images::table
+                        .on(db::base_images::project_id.eq(db::tagging_rules::project_id)),
+                )
+                .filter(db::base_images::id.eq(base_image_id))
+                .filter(db::tagging_rules::deleted.is_null())
+                .order((
+                    db::tagging_rules::priority.asc(),
+                    db::tagging_rules::name.asc(),
+                ))
+                .select(TaggingRule::as_select())
+                .load::<TaggingRule>(conn)?;
+
+            if rules.is_empty() {
+                return Ok(());
+            }
+
+            let (tags, collection) = tagging::apply_rules(&rules, &exif);
+            event!(Level::DEBUG, ?tags, ?collection, "Applying tagging rules");
+
+            diesel::update(db::base_images::table)
+                .filter(db::base_images::id.eq(base_image_id))
+                .set((
+                    db::base_images::tags.eq(tags),
+                    db::base_images::collection.eq(collection),
+                    db::base_images::updated.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            Ok::<_, eyre::Report>(())
+        })
+        .await
+}
+
+/// Fill in missing alt text from the captioning service. Captioning failures are logged instead of
+/// failing the job, since the alt text can always be added by hand.
+async fn generate_alt_text(
+    context: &JobContext,
+    captioner: &Captioner,
+    base_image_id: BaseImageId,
+    image: &DynamicImage,
+) -> Result<(), eyre::Report> {
+    let alt_text = context
+        .pool
+        .interact(move |conn| {
+            db::base_images::table
+                .filter(db::base_images::id.eq(base_image_id))
+                .select(db::base_images::alt_text)
+                .first::<String>(conn)
+                .map_err(eyre::Report::new)
+        })
+        .await?;
+
+    if !alt_text.is_empty() {
+        return Ok(());
+    }
+
+    let caption = match captioner.caption(image).await {
+        Ok(caption) => caption,
+        Err(e) => {
+            event!(Level::WARN, error = ?e, "Failed to generate alt text");
+            return Ok(());
+        }
+    };
+
+    context
+        .pool
+        .interact(move |conn| {
+            // Don't overwrite alt text that was added while the caption was generated.
+            diesel::update(db::base_images::table)
+                .filter(db::base_images::id.eq(base_image_id))
+                .filter(db::base_images::alt_text.eq(""))
+                .set((
+                    db::base_images::alt_text.eq(caption),
+                    db::base_images::alt_text_machine_generated.eq(true),
+                    db::base_images::updated.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            Ok::<_, eyre::Report>(())
+        })
+        .await
+}
+
+/// Store the text in the image for searches. Like captioning, failures are logged and don't fail
+/// the job.
+#[cfg(feature = "ocr")]
+async fn extract_text(
+    context: &JobContext,
+    base_image_id: BaseImageId,
+    image: Arc<DynamicImage>,
+    language: String,
+) -> Result<(), eyre::Report> {
+    let text =
+        tokio::task::spawn_blocking(move || convert::ocr::extract_text(&image, &language)).await?;
+
+    let text = match text {
+        Ok(text) => text,
+        Err(e) => {
+            event!(Level::WARN, error = ?e, "Failed to extract text");
+            return Ok(());
+        }
+    };
+
+    context
+        .pool
+        .interact(move |conn| {
+            diesel::update(db::base_images::table)
+                .filter(db::base_images::id.eq(base_image_id))
+                .set((
+                    db::base_images::ocr_text.eq(text),
+                    db::base_images::updated.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            Ok::<_, eyre::Report>(())
+        })
+        .await
+}
+
+#[cfg(feature = "barcodes")]
+async fn detect_codes(
+    context: &JobContext,
+    base_image_id: BaseImageId,
+    image: Arc<DynamicImage>,
+) -> Result<(), eyre::Report> {
+    let codes = tokio::task::spawn_blocking(move || convert::codes::detect_codes(&image)).await?;
+    let codes = db::base_images::DetectedCodes(
+        codes
+            .into_iter()
+            .map(|code| db::base_images::DetectedCode {
+                format: code.format,
+                payload: code.payload,
+            })
+            .collect(),
+    );
+
+    context
+        .pool
+        .interact(move |conn| {
+            diesel::update(db::base_images::table)
+                .filter(db::base_images::id.eq(base_image_id))
+                .set((
+                    db::base_images::codes.eq(codes),
+                    db::base_images::updated.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            Ok::<_, eyre::Report>(())
+        })
+        .await
+}
+
+async fn reject_base_image(
+    context: &JobContext,
+    base_image_id: BaseImageId,
+    error: String,
+) -> Result<(), eyre::Report> {
+    context
+        .pool
+        .interact(move |conn| {
+            diesel::update(db::base_images::table)
+                .filter(db::base_images::id.eq(base_image_id))
+                .set((
+                    db::base_images::status.eq(BaseImageStatus::Rejected),
+                    db::base_images::error.eq(error),
+                    db::base_images::updated.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            Ok::<_, eyre::Report>(())
+        })
+        .await
 }
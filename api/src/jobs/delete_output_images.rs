@@ -0,0 +1,179 @@
+use db::{
+    image_base_location, image_path,
+    object_id::{BaseImageId, OutputImageId, StorageLocationId, TeamId},
+    storage_locations::{CdnPurge, Provider},
+    OutputImageStatus, PoolExt,
+};
+use diesel::prelude::*;
+use effectum::RunningJob;
+use pic_store_db as db;
+use pic_store_storage as storage;
+use serde::{Deserialize, Serialize};
+use tracing::{event, instrument, Level};
+
+use super::JobContext;
+use crate::{cdn_purge::PurgeTarget, mirrors};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeleteOutputImagesJobPayload {
+    pub base_image: BaseImageId,
+}
+
+/// Remove the files for all of a base image's output images that are queued for deletion.
+#[instrument(skip(job))]
+pub async fn delete_output_images_job(
+    job: RunningJob,
+    context: JobContext,
+) -> Result<(), eyre::Report> {
+    let payload = job.json_payload::<DeleteOutputImagesJobPayload>()?;
+
+    event!(Level::INFO, ?payload);
+
+    delete_output_images(&context, payload.base_image).await
+}
+
+/// Remove the files for the output images of `base_image` that are queued for deletion, and
+/// purge them from the CDN.
+pub(crate) async fn delete_output_images(
+    context: &JobContext,
+    base_image: BaseImageId,
+) -> Result<(), eyre::Report> {
+    let (
+        project_base_location,
+        output_base_location,
+        output_profile_base_path,
+        provider,
+        public_url_base,
+        cdn_purge,
+        outputs,
+        mirror_locations,
+    ) = context
+        .pool
+        .interact(move |conn| {
+            let (
+                project_base_location,
+                output_base_location,
+                output_profile_base_path,
+                provider,
+                public_url_base,
+                cdn_purge,
+            ) = db::base_images::table
+                .inner_join(
+                    db::upload_profiles::table.inner_join(
+                        db::storage_locations::table
+                            .on(db::upload_profiles::output_storage_location_id
+                                .eq(db::storage_locations::id)),
+                    ),
+                )
+                .inner_join(
+                    db::projects::table.on(db::projects::id.eq(db::base_images::project_id)),
+                )
+                .filter(db::base_images::id.eq(base_image))
+                .select((
+                    db::projects::base_location,
+                    db::storage_locations::base_location,
+                    db::upload_profiles::output_storage_location_path,
+                    db::storage_locations::provider,
+                    db::storage_locations::public_url_base,
+                    db::storage_locations::cdn_purge,
+                ))
+                .first::<(
+                    String,
+                    String,
+                    Option<String>,
+                    Provider,
+                    String,
+                    Option<CdnPurge>,
+                )>(conn)?;
+
+            let outputs = db::output_images::table
+                .filter(db::output_images::base_image_id.eq(base_image))
+                .filter(db::output_images::status.eq(OutputImageStatus::QueuedForDelete))
+                .select((db::output_images::id, db::output_images::location))
+                .load::<(OutputImageId, String)>(conn)?;
+
+            let (team_id, mirror_ids) = db::base_images::table
+                .inner_join(db::upload_profiles::table)
+                .filter(db::base_images::id.eq(base_image))
+                .select((
+                    db::base_images::team_id,
+                    db::upload_profiles::output_mirror_location_ids,
+                ))
+                .first::<(TeamId, Vec<StorageLocationId>)>(conn)?;
+            let mirror_locations = mirrors::load_locations(conn, team_id, &mirror_ids)?;
+
+            Ok::<_, eyre::Report>((
+                project_base_location,
+                output_base_location,
+                output_profile_base_path,
+                provider,
+                public_url_base,
+                cdn_purge,
+                outputs,
+                mirror_locations,
+            ))
+        })
+        .await?;
+
+    let base_location = image_base_location(
+        &output_base_location,
+        &project_base_location,
+        &output_profile_base_path,
+    );
+
+    let operator = storage::Provider::from_db(provider)?
+        .create_operator(base_location.as_ref())
+        .await?;
+
+    let mirrors = mirrors::open(
+        mirror_locations,
+        &project_base_location,
+        &output_profile_base_path,
+    )
+    .await;
+
+    let mut purge_urls = Vec::with_capacity(outputs.len());
+    for (output_image_id, location) in outputs {
+        operator.delete(&location).await?;
+        mirrors::delete(&mirrors, &location).await;
+        purge_urls.push(image_path(
+            &public_url_base,
+            &project_base_location,
+            &output_profile_base_path,
+            &location,
+        ));
+
+        context
+            .pool
+            .interact(move |conn| {
+                diesel::update(db::output_images::table)
+                    .filter(db::output_images::id.eq(output_image_id))
+                    .set((
+                        db::output_images::status.eq(OutputImageStatus::Deleted),
+                        db::output_images::updated.eq(diesel::dsl::now),
+                        db::output_images::deleted.eq(diesel::dsl::now),
+                    ))
+                    .execute(conn)?;
+
+                diesel::delete(db::output_image_mirrors::table)
+                    .filter(db::output_image_mirrors::output_image_id.eq(output_image_id))
+                    .execute(conn)?;
+
+                Ok::<_, eyre::Report>(())
+            })
+            .await?;
+    }
+
+    context
+        .cdn_purger
+        .purge_best_effort(
+            &cdn_purge,
+            &PurgeTarget {
+                image_id: base_image,
+                urls: &purge_urls,
+            },
+        )
+        .await;
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use db::{
+    image_base_location,
+    storage_locations::StorageLocation,
+    upload_sessions::{self, UploadSession},
+    PoolExt,
+};
+use diesel::prelude::*;
+use effectum::RunningJob;
+use pic_store_db as db;
+use pic_store_storage as storage;
+use tracing::{event, instrument, Level};
+
+use super::JobContext;
+use crate::shared_state::AppState;
+
+/// How often to look for abandoned chunked uploads.
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The most sessions to remove in one run. Anything left over is picked up by the next run.
+const BATCH_SIZE: i64 = 500;
+
+/// Remove the chunked uploads that expired without being completed, along with their stored
+/// chunks.
+#[instrument(skip(_job))]
+pub async fn expire_chunked_uploads_job(
+    _job: RunningJob,
+    context: JobContext,
+) -> Result<(), eyre::Report> {
+    let sessions = context
+        .pool
+        .interact(|conn| {
+            upload_sessions::table
+                .inner_join(db::base_images::table.inner_join(
+                    db::upload_profiles::table.inner_join(db::storage_locations::table.on(
+                        db::storage_locations::id.eq(db::upload_profiles::base_storage_location_id),
+                    )),
+                ))
+                .inner_join(
+                    db::projects::table.on(db::projects::id.eq(db::base_images::project_id)),
+                )
+                .filter(upload_sessions::expires.lt(Utc::now()))
+                .select((
+                    UploadSession::as_select(),
+                    db::base_images::location,
+                    db::projects::base_location,
+                    db::upload_profiles::base_storage_location_path,
+                    db::storage_locations::all_columns,
+                ))
+                .limit(BATCH_SIZE)
+                .load::<(
+                    UploadSession,
+                    String,
+                    String,
+                    Option<String>,
+                    StorageLocation,
+                )>(conn)
+                .map_err(eyre::Report::new)
+        })
+        .await?;
+
+    for (session, image_location, project_base_location, profile_path, base_storage) in sessions {
+        let session_id = session.id;
+        let result = async {
+            let provider = storage::Provider::from_db(base_storage.provider)?;
+            let base_location = image_base_location(
+                &base_storage.base_location,
+                &project_base_location,
+                &profile_path,
+            );
+            let operator = provider.create_operator(base_location.as_ref()).await?;
+            remove_chunked_upload(&context.pool, &operator, session, &image_location).await
+        }
+        .await;
+
+        if let Err(e) = result {
+            event!(Level::ERROR, %session_id, error = ?e, "Failed to remove expired chunked upload");
+        }
+    }
+
+    Ok(())
+}
+
+/// Delete a chunked upload's stored chunks and its records.
+pub async fn remove_chunked_upload(
+    pool: &db::Pool,
+    operator: &storage::Operator,
+    session: UploadSession,
+    image_location: &str,
+) -> Result<(), eyre::Report> {
+    let session_id = session.id;
+    let chunks = pool
+        .interact(move |conn| upload_sessions::chunks(conn, session_id).map_err(eyre::Report::new))
+        .await?;
+
+    for chunk in chunks {
+        let location = session.chunk_location(image_location, chunk.chunk_index);
+        match operator.delete(&location).await {
+            Ok(()) => {}
+            Err(e) if e.is_not_found() => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    pool.interact(move |conn| upload_sessions::remove(conn, session_id).map_err(eyre::Report::new))
+        .await
+}
+
+/// Queue a job to remove expired chunked uploads periodically.
+pub fn start_chunked_upload_expiry_task(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(EXPIRE_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let result = effectum::Job::builder(super::EXPIRE_CHUNKED_UPLOADS)
+                .add_to(&state.queue)
+                .await;
+            if let Err(e) = result {
+                event!(Level::ERROR, error = ?e, "Failed to queue chunked upload expiry job");
+            }
+        }
+    })
+}
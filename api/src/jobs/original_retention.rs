@@ -0,0 +1,226 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use db::{
+    image_base_location,
+    object_id::{BaseImageId, StorageLocationId, UploadProfileId},
+    storage_locations::{Provider, StorageLocation},
+    upload_profiles::OriginalRetention,
+    BaseImageStatus, PoolExt,
+};
+use diesel::prelude::*;
+use effectum::RunningJob;
+use pic_store_db as db;
+use pic_store_storage as storage;
+use tracing::{event, instrument, Level};
+
+use super::JobContext;
+use crate::{checksum, shared_state::AppState};
+
+/// How often to look for originals to remove.
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The most originals to remove from each upload profile in one run. Anything left over is
+/// picked up by the next run.
+const BATCH_SIZE: i64 = 500;
+
+/// An image whose original is due to be removed.
+struct ExpiredOriginal {
+    id: BaseImageId,
+    location: String,
+    project_base_location: String,
+    sha256: Option<String>,
+}
+
+/// Delete or archive the originals that are past their upload profile's retention period.
+#[instrument(skip(_job))]
+pub async fn apply_original_retention_job(
+    _job: RunningJob,
+    context: JobContext,
+) -> Result<(), eyre::Report> {
+    let profiles = context
+        .pool
+        .interact(|conn| {
+            db::upload_profiles::table
+                .inner_join(db::storage_locations::table.on(
+                    db::storage_locations::id.eq(db::upload_profiles::base_storage_location_id),
+                ))
+                .filter(db::upload_profiles::deleted.is_null())
+                .filter(db::upload_profiles::original_retention.is_not_null())
+                .select((
+                    db::upload_profiles::id,
+                    db::upload_profiles::original_retention.assume_not_null(),
+                    db::upload_profiles::base_storage_location_path,
+                    db::storage_locations::all_columns,
+                ))
+                .load::<(
+                    UploadProfileId,
+                    OriginalRetention,
+                    Option<String>,
+                    StorageLocation,
+                )>(conn)
+                .map_err(eyre::Report::new)
+        })
+        .await?;
+
+    for (upload_profile_id, retention, profile_path, base_storage) in profiles {
+        if let Err(e) = apply_retention(
+            &context,
+            upload_profile_id,
+            &retention,
+            &profile_path,
+            base_storage,
+        )
+        .await
+        {
+            event!(Level::ERROR, %upload_profile_id, error = ?e, "Failed to apply original retention");
+        }
+    }
+
+    Ok(())
+}
+
+async fn apply_retention(
+    context: &JobContext,
+    upload_profile_id: UploadProfileId,
+    retention: &OriginalRetention,
+    profile_path: &Option<String>,
+    base_storage: StorageLocation,
+) -> Result<(), eyre::Report> {
+    let cutoff = retention.cutoff(Utc::now());
+    let archive_location_id = retention.archive_location();
+    let team_id = base_storage.team_id;
+
+    let (images, archive) = context
+        .pool
+        .interact(move |conn| {
+            let images = db::base_images::table
+                .inner_join(
+                    db::projects::table.on(db::projects::id.eq(db::base_images::project_id)),
+                )
+                .filter(db::base_images::upload_profile_id.eq(upload_profile_id))
+                .filter(db::base_images::deleted.is_null())
+                .filter(db::base_images::status.eq(BaseImageStatus::Ready))
+                .filter(db::base_images::original_removed.is_null())
+                .filter(db::base_images::pinned.eq(false))
+                .filter(db::base_images::converted.lt(cutoff))
+                .select((
+                    db::base_images::id,
+                    db::base_images::location,
+                    db::projects::base_location,
+                    db::base_images::sha256,
+                ))
+                .limit(BATCH_SIZE)
+                .load::<(BaseImageId, String, String, Option<String>)>(conn)?
+                .into_iter()
+                .map(
+                    |(id, location, project_base_location, sha256)| ExpiredOriginal {
+                        id,
+                        location,
+                        project_base_location,
+                        sha256,
+                    },
+                )
+                .collect::<Vec<_>>();
+
+            let archive = archive_location_id
+                .map(|id| {
+                    db::storage_locations::table
+                        .filter(db::storage_locations::id.eq(id))
+                        .filter(db::storage_locations::team_id.eq(team_id))
+                        .filter(db::storage_locations::deleted.is_null())
+                        .select((
+                            db::storage_locations::provider,
+                            db::storage_locations::base_location,
+                        ))
+                        .first::<(Provider, String)>(conn)
+                        .optional()?
+                        .ok_or_else(|| eyre::eyre!("Archive storage location {id} not found"))
+                })
+                .transpose()?;
+
+            Ok::<_, eyre::Report>((images, archive))
+        })
+        .await?;
+
+    if images.is_empty() {
+        return Ok(());
+    }
+
+    event!(Level::INFO, %upload_profile_id, count = images.len(), archive = archive.is_some(), "Removing originals");
+
+    let base_provider = storage::Provider::from_db(base_storage.provider)?;
+    let archive_provider = archive
+        .as_ref()
+        .map(|(provider, _)| storage::Provider::from_db(provider.clone()))
+        .transpose()?;
+
+    for image in images {
+        let base_location = image_base_location(
+            &base_storage.base_location,
+            &image.project_base_location,
+            profile_path,
+        );
+        let base_operator = base_provider
+            .create_operator(base_location.as_ref())
+            .await?;
+
+        if let (Some(provider), Some((_, archive_base))) = (&archive_provider, &archive) {
+            let archive_location =
+                image_base_location(archive_base, &image.project_base_location, profile_path);
+            let archive_operator = provider.create_operator(archive_location.as_ref()).await?;
+            let contents = base_operator
+                .get_parallel(&image.location, &context.download)
+                .await?;
+            // Don't archive a corrupted original in place of the real one.
+            if let Some(sha256) = &image.sha256 {
+                checksum::check(&contents, sha256, &image.location)?;
+            }
+            checksum::put_verified(&archive_operator, &image.location, contents).await?;
+        }
+
+        base_operator.delete(&image.location).await?;
+        mark_original_removed(context, image.id, archive_location_id).await?;
+    }
+
+    Ok(())
+}
+
+async fn mark_original_removed(
+    context: &JobContext,
+    base_image_id: BaseImageId,
+    archive_location_id: Option<StorageLocationId>,
+) -> Result<(), eyre::Report> {
+    context
+        .pool
+        .interact(move |conn| {
+            diesel::update(db::base_images::table)
+                .filter(db::base_images::id.eq(base_image_id))
+                .set((
+                    db::base_images::original_removed.eq(diesel::dsl::now),
+                    db::base_images::original_archive_location_id.eq(archive_location_id),
+                    db::base_images::updated.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            Ok::<_, eyre::Report>(())
+        })
+        .await
+}
+
+/// Queue a job to apply the retention rules periodically.
+pub fn start_original_retention_task(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(RETENTION_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let result = effectum::Job::builder(super::APPLY_ORIGINAL_RETENTION)
+                .add_to(&state.queue)
+                .await;
+            if let Err(e) = result {
+                event!(Level::ERROR, error = ?e, "Failed to queue original retention job");
+            }
+        }
+    })
+}
@@ -0,0 +1,243 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    time::{Duration, SystemTime},
+};
+
+use chrono::Utc;
+use db::{
+    credentials,
+    webhook_allowlists::{self, WebhookAllowlist},
+    webhooks::{self, Webhook, WebhookDelivery},
+    PoolExt,
+};
+use effectum::RunningJob;
+use pic_store_client::webhook::{DELIVERY_HEADER, EVENT_HEADER, SIGNATURE_HEADER};
+use pic_store_db as db;
+use reqwest::header;
+use tracing::{event, instrument, Level};
+
+use super::JobContext;
+use crate::{remote_fetch, shared_state::AppState};
+
+/// How often to look for deliveries to send.
+const SEND_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The most deliveries to send in one run. Anything left over is picked up by the next run.
+const BATCH_SIZE: i64 = 100;
+
+/// How long a run has to send the deliveries that it claimed before another run may claim them.
+const LEASE: chrono::Duration = chrono::Duration::minutes(5);
+
+/// How long to wait for each request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Give up on a delivery after this many attempts.
+const MAX_ATTEMPTS: i32 = 8;
+
+/// How long to keep deliveries, so that their results can be looked up.
+const RETENTION: chrono::Duration = chrono::Duration::days(30);
+
+/// How long to wait before retrying after a failed attempt. This doubles with each attempt, from
+/// 30 seconds up to about an hour.
+fn retry_delay(attempts: i32) -> chrono::Duration {
+    chrono::Duration::seconds(30 << attempts.clamp(0, 7))
+}
+
+/// The result of an attempt to send a delivery.
+struct Attempt {
+    response_status: Option<i32>,
+    /// Why the attempt failed. This is kept generic since the team can see it.
+    error: Option<String>,
+    /// Whether a failure might succeed if tried again.
+    retry: bool,
+}
+
+impl Attempt {
+    /// A failure that will fail again the same way, such as a host that isn't allowed.
+    fn rejected(error: impl Into<String>) -> Self {
+        Attempt {
+            response_status: None,
+            error: Some(error.into()),
+            retry: false,
+        }
+    }
+
+    fn failed(response_status: Option<i32>, error: impl Into<String>) -> Self {
+        Attempt {
+            response_status,
+            error: Some(error.into()),
+            retry: true,
+        }
+    }
+}
+
+/// Send the webhook deliveries that are due.
+#[instrument(skip(_job))]
+pub async fn send_webhooks_job(_job: RunningJob, context: JobContext) -> Result<(), eyre::Report> {
+    let deliveries = context
+        .pool
+        .transaction(|conn| {
+            webhooks::prune(conn, Utc::now() - RETENTION)?;
+            webhooks::claim_due(conn, BATCH_SIZE, Utc::now() + LEASE).map_err(eyre::Report::new)
+        })
+        .await?;
+
+    // The allowlist for each team, since it may have changed since the webhook was saved.
+    let mut allowlists = HashMap::new();
+
+    for (delivery, webhook) in deliveries {
+        let delivery_id = delivery.id;
+        let attempts = delivery.attempts + 1;
+
+        let team_id = webhook.team_id;
+        if let Entry::Vacant(entry) = allowlists.entry(team_id) {
+            let allowlist = context
+                .pool
+                .interact(move |conn| {
+                    webhook_allowlists::for_team(conn, team_id).map_err(eyre::Report::new)
+                })
+                .await?;
+            entry.insert(allowlist);
+        }
+        let allowlist = allowlists.get(&team_id).and_then(|list| list.as_ref());
+
+        let attempt = if webhook.deleted.is_some() {
+            Attempt::rejected("The webhook was deleted")
+        } else {
+            send(&context, &delivery, &webhook, allowlist).await
+        };
+
+        let next_attempt = (attempt.error.is_some() && attempt.retry && attempts < MAX_ATTEMPTS)
+            .then(|| Utc::now() + retry_delay(attempts - 1));
+
+        if let Some(error) = &attempt.error {
+            event!(Level::INFO, %delivery_id, %error, attempts, "Webhook delivery failed");
+        }
+
+        context
+            .pool
+            .transaction(move |conn| {
+                webhooks::record_attempt(
+                    conn,
+                    delivery_id,
+                    attempt.response_status,
+                    attempt.error,
+                    next_attempt,
+                )
+                .map_err(eyre::Report::new)
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Send a delivery, if its URL is allowed by the team's allowlist.
+async fn send(
+    context: &JobContext,
+    delivery: &WebhookDelivery,
+    webhook: &Webhook,
+    allowlist: Option<&WebhookAllowlist>,
+) -> Attempt {
+    let url = match remote_fetch::parse_url(&webhook.url) {
+        Ok(url) => url,
+        Err(_) => return Attempt::rejected("Invalid URL"),
+    };
+
+    if let Some(allowlist) = allowlist {
+        if !url.host_str().is_some_and(|host| allowlist.allows(host)) {
+            return Attempt::rejected("The URL's host is not on the team's webhook allowlist");
+        }
+    }
+
+    let secret = match credentials::decrypt(&webhook.secret) {
+        Ok(secret) => secret,
+        Err(e) => {
+            event!(Level::ERROR, webhook_id = %webhook.id, error = ?e, "Failed to decrypt webhook secret");
+            return Attempt::failed(None, "The webhook's secret could not be read");
+        }
+    };
+
+    let body = match &webhook.payload_template {
+        Some(template) => match crate::webhooks::render_payload(template, &delivery.payload) {
+            Ok(body) => body,
+            Err(e) => return Attempt::rejected(format!("The payload template failed: {e}")),
+        },
+        None => delivery.payload.to_string(),
+    };
+    let signature = pic_store_client::webhook::sign(&secret, body.as_bytes(), SystemTime::now());
+
+    let result = async {
+        let client =
+            remote_fetch::pinned_client(&url, context.allow_private_networks, REQUEST_TIMEOUT)
+                .await?;
+        client
+            .post(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .header(EVENT_HEADER, &delivery.event_type)
+            .header(DELIVERY_HEADER, delivery.id.to_string())
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    crate::Error::RemoteFetchFailed("Timed out".to_string())
+                } else {
+                    crate::Error::RemoteFetchFailed("Could not connect".to_string())
+                }
+            })
+    }
+    .await;
+
+    match result {
+        Ok(response) => {
+            let status = response.status();
+            let response_status = Some(status.as_u16() as i32);
+            if status.is_success() {
+                Attempt {
+                    response_status,
+                    error: None,
+                    retry: false,
+                }
+            } else {
+                Attempt::failed(
+                    response_status,
+                    format!("The server responded with status {}", status.as_u16()),
+                )
+            }
+        }
+        Err(crate::Error::RemoteFetchFailed(message)) => Attempt::failed(None, message),
+        Err(_) => Attempt::failed(None, "The URL is not allowed"),
+    }
+}
+
+/// Queue a job to send webhook deliveries periodically.
+pub fn start_webhook_delivery_task(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(SEND_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let result = effectum::Job::builder(super::SEND_WEBHOOKS)
+                .add_to(&state.queue)
+                .await;
+            if let Err(e) = result {
+                event!(Level::ERROR, error = ?e, "Failed to queue webhook delivery job");
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_backoff() {
+        assert_eq!(retry_delay(0), chrono::Duration::seconds(30));
+        assert_eq!(retry_delay(1), chrono::Duration::seconds(60));
+        assert_eq!(retry_delay(7), chrono::Duration::seconds(3840));
+        assert_eq!(retry_delay(20), chrono::Duration::seconds(3840));
+    }
+}
@@ -0,0 +1,225 @@
+//! A JSON extractor for request bodies that can reject unknown fields, so that typos like
+//! `qualtiy` are reported instead of silently ignored.
+//!
+//! Strict mode is enabled for every request by the `strict_json` option. A request can also turn
+//! it on or off for itself with the `X-Strict-Json` header.
+//!
+//! Unknown fields inside internally tagged enums and flattened structs are not seen here, since
+//! serde buffers those values before deserializing them. Types like the conversion profile
+//! outputs and formats use `#[serde(deny_unknown_fields)]` instead, so they reject unknown fields
+//! in both modes.
+
+use async_trait::async_trait;
+use axum::{
+    body::{Bytes, HttpBody},
+    extract::FromRequest,
+    http::{header, HeaderMap, Request},
+    response::{IntoResponse, Response},
+    BoxError,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{shared_state::AppState, Error};
+
+pub const STRICT_JSON_HEADER: &str = "x-strict-json";
+
+/// Works like [axum::Json], and checks for unknown fields when strict mode is enabled. An empty
+/// body is read as `null`, so handlers with an optional body can use `Json<Option<T>>`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<T, B> FromRequest<AppState, B> for Json<T>
+where
+    T: DeserializeOwned,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: Request<B>, state: &AppState) -> Result<Self, Self::Rejection> {
+        let strict = strict_requested(req.headers()).unwrap_or(state.strict_json);
+        let is_json = is_json_content_type(req.headers());
+
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| Error::InvalidJson(e.body_text()))?;
+
+        if body.is_empty() {
+            return parse(b"null", strict).map(Json);
+        }
+
+        if !is_json {
+            return Err(Error::InvalidJson(
+                "Expected request with `Content-Type: application/json`".to_string(),
+            ));
+        }
+
+        parse(&body, strict).map(Json)
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+/// Read the request's strict mode header, if it has one.
+fn strict_requested(headers: &HeaderMap) -> Option<bool> {
+    let value = headers.get(STRICT_JSON_HEADER)?.to_str().ok()?.trim();
+    if value.eq_ignore_ascii_case("true") || value == "1" {
+        Some(true)
+    } else if value.eq_ignore_ascii_case("false") || value == "0" {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn is_json_content_type(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    mime == "application/json" || (mime.starts_with("application/") && mime.ends_with("+json"))
+}
+
+fn parse<T: DeserializeOwned>(body: &[u8], strict: bool) -> Result<T, Error> {
+    let mut unknown = Vec::new();
+    let mut deserializer = serde_json::Deserializer::from_slice(body);
+    let value =
+        serde_ignored::deserialize(&mut deserializer, |path| unknown.push(field_path(&path)))
+            .and_then(|value| deserializer.end().map(|_| value))
+            .map_err(|e| Error::InvalidJson(e.to_string()))?;
+
+    if strict && !unknown.is_empty() {
+        return Err(Error::UnknownFields(unknown));
+    }
+
+    Ok(value)
+}
+
+/// Format a path like `output.formats[0].quality`.
+fn field_path(path: &serde_ignored::Path) -> String {
+    use serde_ignored::Path;
+
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } => format!("{}[{index}]", field_path(parent)),
+        Path::Map { parent, key } => {
+            let parent = field_path(parent);
+            if parent.is_empty() {
+                key.clone()
+            } else {
+                format!("{parent}.{key}")
+            }
+        }
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => field_path(parent),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+    use crate::routes::conversion_profile::ConversionProfileInput;
+
+    const PROFILE: &[u8] = br#"{
+        "name": "a",
+        "output": {
+            "type": "cross",
+            "formats": [{"format": "avif", "quality": 50}],
+            "sizes": [{"width": 800}]
+        },
+        "extra": true
+    }"#;
+
+    const FORMAT_TYPO: &[u8] = br#"{
+        "name": "a",
+        "output": {
+            "type": "cross",
+            "formats": [{"format": "avif", "qualtiy": 50}],
+            "sizes": [{"width": 800}]
+        }
+    }"#;
+
+    #[test]
+    fn lenient() {
+        parse::<ConversionProfileInput>(PROFILE, false).unwrap();
+    }
+
+    #[test]
+    fn strict() {
+        let err = parse::<ConversionProfileInput>(PROFILE, true).unwrap_err();
+        let Error::UnknownFields(fields) = err else {
+            panic!("expected unknown fields error, got {err:?}");
+        };
+        assert_eq!(fields, vec!["extra"]);
+    }
+
+    #[test]
+    fn typo_in_tagged_enum() {
+        for strict in [false, true] {
+            let err = parse::<ConversionProfileInput>(FORMAT_TYPO, strict).unwrap_err();
+            let Error::InvalidJson(message) = err else {
+                panic!("expected invalid JSON error, got {err:?}");
+            };
+            assert!(message.contains("qualtiy"), "{message}");
+        }
+    }
+
+    #[test]
+    fn optional_body() {
+        assert!(parse::<Option<ConversionProfileInput>>(b"null", true)
+            .unwrap()
+            .is_none());
+        assert!(parse::<ConversionProfileInput>(b"null", true).is_err());
+    }
+
+    #[test]
+    fn strict_header() {
+        let headers = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(STRICT_JSON_HEADER, HeaderValue::from_static(value));
+            headers
+        };
+
+        assert_eq!(strict_requested(&headers("true")), Some(true));
+        assert_eq!(strict_requested(&headers("0")), Some(false));
+        assert_eq!(strict_requested(&headers("maybe")), None);
+        assert_eq!(strict_requested(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn content_types() {
+        let headers = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(value));
+            headers
+        };
+
+        assert!(is_json_content_type(&headers("application/json")));
+        assert!(is_json_content_type(&headers(
+            "application/json; charset=utf-8"
+        )));
+        assert!(is_json_content_type(&headers(
+            "application/merge-patch+json"
+        )));
+        assert!(!is_json_content_type(&headers("text/plain")));
+        assert!(!is_json_content_type(&HeaderMap::new()));
+    }
+}
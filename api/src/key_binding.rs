@@ -0,0 +1,145 @@
+//! API keys can be bound to an upload profile, so that a key embedded in a public website can
+//! only be used to upload images to one place. Bound keys may only create images, upload them,
+//! check on their progress, and ingest or validate uploads for an upload profile. The handlers
+//! of those routes make sure that the images and upload profiles match the bound upload profile.
+
+use axum::{
+    http::{Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{auth::UserInfo, Error};
+
+fn is_allowed(method: &Method, path: &str) -> bool {
+    let Some(rest) = path.strip_prefix("/api/") else {
+        return false;
+    };
+
+    let segments = rest
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+
+    matches!(
+        (method, segments.as_slice()),
+        (&Method::POST, ["images"])
+            | (&Method::GET, ["images", _])
+            | (&Method::POST, ["images", _, "upload"])
+            | (&Method::GET, ["image_by_hash", _])
+            | (
+                &Method::POST,
+                ["projects", _, "upload_profiles", _, "ingest" | "validate"]
+            )
+    )
+}
+
+fn check_binding(user: &UserInfo, method: &Method, path: &str) -> Result<(), Error> {
+    if user.bound_upload_profile_id.is_some() && !is_allowed(method, path) {
+        return Err(Error::ApiKeyRestricted);
+    }
+
+    Ok(())
+}
+
+/// Reject requests from bound API keys for anything other than uploading images.
+pub async fn enforce_key_binding<B>(req: Request<B>, next: Next<B>) -> Result<Response, Error> {
+    if let Some(user) = req.extensions().get::<UserInfo>() {
+        check_binding(user, req.method(), req.uri().path())?;
+    }
+
+    Ok(next.run(req).await.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use pic_store_db::{
+        object_id::{ImpersonationId, TeamId, UploadProfileId, UserId},
+        TeamStatus,
+    };
+
+    use super::*;
+    use crate::impersonation::impersonated_user;
+
+    fn bound_user() -> UserInfo {
+        UserInfo {
+            user_id: UserId::new(),
+            team_id: TeamId::new(),
+            roles: Vec::new(),
+            default_upload_profile_id: None,
+            bound_upload_profile_id: Some(UploadProfileId::new()),
+            team_status: TeamStatus::Active,
+            impersonation_id: None,
+            api_key_id: Some(uuid::Uuid::new_v4()),
+        }
+    }
+
+    #[test]
+    fn upload_routes_allowed() {
+        assert!(is_allowed(&Method::POST, "/api/images"));
+        assert!(is_allowed(&Method::POST, "/api/images/"));
+        assert!(is_allowed(&Method::POST, "/api/images/bimabc/upload"));
+        assert!(is_allowed(&Method::GET, "/api/images/bimabc"));
+        assert!(is_allowed(&Method::GET, "/api/image_by_hash/abc"));
+        assert!(is_allowed(
+            &Method::POST,
+            "/api/projects/prjabc/upload_profiles/uplabc/ingest"
+        ));
+        assert!(is_allowed(
+            &Method::POST,
+            "/api/projects/prjabc/upload_profiles/uplabc/validate"
+        ));
+    }
+
+    #[test]
+    fn other_routes_rejected() {
+        assert!(!is_allowed(&Method::GET, "/api/images"));
+        assert!(!is_allowed(&Method::DELETE, "/api/images/bimabc"));
+        assert!(!is_allowed(&Method::PUT, "/api/images/bimabc"));
+        assert!(!is_allowed(&Method::POST, "/api/images/bimabc/reconvert"));
+        assert!(!is_allowed(&Method::POST, "/api/images/bimabc/signed_url"));
+        assert!(!is_allowed(&Method::GET, "/api/images/bimabc/original"));
+        assert!(!is_allowed(&Method::GET, "/api/imagesx"));
+        assert!(!is_allowed(&Method::POST, "/api/images/from_url"));
+        assert!(!is_allowed(
+            &Method::GET,
+            "/api/projects/prjabc/upload_profiles"
+        ));
+        assert!(!is_allowed(
+            &Method::PUT,
+            "/api/projects/prjabc/upload_profiles/uplabc"
+        ));
+        assert!(!is_allowed(
+            &Method::POST,
+            "/api/projects/prjabc/images/bulk_delete/preview"
+        ));
+    }
+
+    #[test]
+    fn impersonation_keeps_binding() {
+        let user = bound_user();
+        assert!(
+            check_binding(&user, &Method::GET, "/api/projects/prjabc/upload_profiles").is_err()
+        );
+
+        let impersonated = impersonated_user(
+            &user,
+            ImpersonationId::new(),
+            TeamId::new(),
+            TeamStatus::Active,
+            Vec::new(),
+        );
+        assert_eq!(
+            impersonated.bound_upload_profile_id,
+            user.bound_upload_profile_id
+        );
+        assert_eq!(impersonated.api_key_id, user.api_key_id);
+        assert!(check_binding(
+            &impersonated,
+            &Method::GET,
+            "/api/projects/prjabc/upload_profiles"
+        )
+        .is_err());
+        assert!(check_binding(&impersonated, &Method::POST, "/api/images").is_ok());
+    }
+}
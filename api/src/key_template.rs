@@ -0,0 +1,365 @@
+//! Templates for the storage keys of output images, and for the key prefixes of storage
+//! locations.
+//!
+//! Output key templates are liquid templates, such as
+//! `{{ project }}/{{ image_id }}/{{ width }}w.{{ ext }}`, so filters such as `upcase` or
+//! `default` can be used to match an existing URL scheme. Key prefixes are simpler, with
+//! variables in single braces such as `{team}/{project}/{year}/{month}`.
+
+use chrono::{Datelike, NaiveDate};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum KeyTemplateError {
+    #[error("Unknown variable {0:?} in key template")]
+    UnknownVariable(String),
+    #[error("Unclosed '{{' in key template")]
+    Unclosed,
+    #[error("Unexpected '}}' in key template")]
+    UnexpectedClose,
+    #[error("Invalid key template: {0}")]
+    Template(String),
+    #[error("Key template must give each output a different key, using {0}")]
+    MissingVariable(&'static str),
+    #[error("Key prefix can not contain `..`")]
+    ParentDirectory,
+}
+
+/// The variables available to storage location key prefixes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PrefixVariable {
+    /// The ID of the team
+    Team,
+    /// The ID of the project
+    Project,
+    /// The upload date, as `YYYY-MM-DD`
+    Date,
+    Year,
+    /// The two-digit month
+    Month,
+    /// The two-digit day of the month
+    Day,
+}
+
+impl PrefixVariable {
+    fn parse(name: &str) -> Result<Self, KeyTemplateError> {
+        match name.trim() {
+            "team" => Ok(Self::Team),
+            "project" => Ok(Self::Project),
+            "date" => Ok(Self::Date),
+            "year" => Ok(Self::Year),
+            "month" => Ok(Self::Month),
+            "day" => Ok(Self::Day),
+            _ => Err(KeyTemplateError::UnknownVariable(name.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Segment<'a, V> {
+    Literal(&'a str),
+    Variable(V),
+}
+
+fn parse_segments<V>(
+    template: &str,
+    parse_variable: impl Fn(&str) -> Result<V, KeyTemplateError>,
+) -> Result<Vec<Segment<'_, V>>, KeyTemplateError> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+
+    while !rest.is_empty() {
+        let open = rest.find('{');
+        let close = rest.find('}');
+
+        match (open, close) {
+            (Some(open), Some(close)) if open < close => {
+                if open > 0 {
+                    segments.push(Segment::Literal(&rest[..open]));
+                }
+
+                let name = &rest[open + 1..close];
+                if name.contains('{') {
+                    return Err(KeyTemplateError::Unclosed);
+                }
+
+                segments.push(Segment::Variable(parse_variable(name)?));
+                rest = &rest[close + 1..];
+            }
+            (Some(_), None) => return Err(KeyTemplateError::Unclosed),
+            (_, Some(_)) => return Err(KeyTemplateError::UnexpectedClose),
+            (None, None) => {
+                segments.push(Segment::Literal(rest));
+                rest = "";
+            }
+        }
+    }
+
+    Ok(segments)
+}
+
+static TEMPLATE_PARSER: Lazy<liquid::Parser> = Lazy::new(|| {
+    liquid::ParserBuilder::with_stdlib()
+        .build()
+        .expect("building template parser")
+});
+
+/// The values available to a key template.
+#[derive(Debug, Serialize)]
+pub struct KeyTemplateValues<'a> {
+    /// The ID of the project
+    pub project: &'a str,
+    /// The ID of the base image
+    pub image_id: &'a str,
+    /// The base image location, without its extension
+    pub basename: &'a str,
+    /// Empty when the output has no set width.
+    pub width: Option<u32>,
+    /// Empty when the output has no set height.
+    pub height: Option<u32>,
+    /// A short description of the size, e.g. `w300` or `300x200`
+    pub size: &'a str,
+    /// The file extension of the output format
+    pub ext: &'a str,
+}
+
+/// The outputs that a template is checked against. Each needs its own key, so these differ in
+/// either width or format, like the variants that the serve route and automatic sizes create.
+const VALIDATION_OUTPUTS: [(u32, &str); 4] =
+    [(300, "webp"), (600, "webp"), (300, "avif"), (600, "avif")];
+
+pub struct KeyTemplate {
+    template: liquid::Template,
+}
+
+impl std::fmt::Debug for KeyTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyTemplate").finish_non_exhaustive()
+    }
+}
+
+impl KeyTemplate {
+    pub fn parse(template: &str) -> Result<Self, KeyTemplateError> {
+        let template = TEMPLATE_PARSER
+            .parse(template)
+            .map_err(|e| KeyTemplateError::Template(e.to_string()))?;
+        Ok(Self { template })
+    }
+
+    /// Parse a template and make sure that it will produce a different key for each output of
+    /// an image. Unknown variables are found here too, since they are only an error once the
+    /// template is rendered.
+    pub fn validate(template: &str) -> Result<Self, KeyTemplateError> {
+        let parsed = Self::parse(template)?;
+
+        let keys = VALIDATION_OUTPUTS
+            .iter()
+            .map(|(width, ext)| {
+                parsed.render(&KeyTemplateValues {
+                    project: "project",
+                    image_id: "image",
+                    basename: "image",
+                    width: Some(*width),
+                    height: None,
+                    size: &format!("w{width}"),
+                    ext,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let same_key = |a: usize, b: usize| keys[a] == keys[b];
+        if same_key(0, 2) || same_key(1, 3) {
+            return Err(KeyTemplateError::MissingVariable("{{ ext }}"));
+        }
+
+        if same_key(0, 1) || same_key(2, 3) {
+            return Err(KeyTemplateError::MissingVariable(
+                "a variable that differs for each size, such as {{ width }} or {{ size }}",
+            ));
+        }
+
+        Ok(parsed)
+    }
+
+    pub fn render(&self, values: &KeyTemplateValues) -> Result<String, KeyTemplateError> {
+        let globals =
+            liquid::to_object(values).map_err(|e| KeyTemplateError::Template(e.to_string()))?;
+        self.template
+            .render(&globals)
+            .map_err(|e| KeyTemplateError::Template(e.to_string()))
+    }
+}
+
+/// The values available to a key prefix template.
+pub struct KeyPrefixValues<'a> {
+    pub team: &'a str,
+    pub project: &'a str,
+    pub date: NaiveDate,
+}
+
+/// A prefix for every key written to a storage location, so that several teams or projects can
+/// share a bucket. The prefix is rendered when an image is created and saved as part of the
+/// locations of the image and its outputs.
+#[derive(Debug)]
+pub struct KeyPrefixTemplate<'a> {
+    segments: Vec<Segment<'a, PrefixVariable>>,
+}
+
+impl<'a> KeyPrefixTemplate<'a> {
+    pub fn parse(template: &'a str) -> Result<Self, KeyTemplateError> {
+        let segments = parse_segments(template, PrefixVariable::parse)?;
+        let escapes = segments.iter().any(|segment| {
+            matches!(segment, Segment::Literal(s) if s.split('/').any(|part| part == ".."))
+        });
+        if escapes {
+            return Err(KeyTemplateError::ParentDirectory);
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Render the prefix, without leading or trailing slashes. This returns `None` when the
+    /// prefix is empty.
+    pub fn render(&self, values: &KeyPrefixValues) -> Option<String> {
+        let mut output = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => output.push_str(s),
+                Segment::Variable(v) => match v {
+                    PrefixVariable::Team => output.push_str(values.team),
+                    PrefixVariable::Project => output.push_str(values.project),
+                    PrefixVariable::Date => {
+                        output.push_str(&values.date.format("%Y-%m-%d").to_string())
+                    }
+                    PrefixVariable::Year => output.push_str(&values.date.year().to_string()),
+                    PrefixVariable::Month => {
+                        output.push_str(&format!("{:02}", values.date.month()))
+                    }
+                    PrefixVariable::Day => output.push_str(&format!("{:02}", values.date.day())),
+                },
+            }
+        }
+
+        let output = output.trim_matches('/');
+        (!output.is_empty()).then(|| output.to_string())
+    }
+}
+
+/// Add a rendered key prefix to a location.
+pub fn with_key_prefix(prefix: Option<&str>, location: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("{prefix}/{location}"),
+        None => location.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> KeyTemplateValues<'static> {
+        KeyTemplateValues {
+            project: "proj",
+            image_id: "abc",
+            basename: "cat",
+            width: Some(300),
+            height: None,
+            size: "w300",
+            ext: "webp",
+        }
+    }
+
+    #[test]
+    fn render() {
+        let template =
+            KeyTemplate::validate("{{ project }}/{{image_id}}/{{ width }}w.{{ ext }}").unwrap();
+        assert_eq!(template.render(&values()).unwrap(), "proj/abc/300w.webp");
+    }
+
+    #[test]
+    fn render_with_filters_and_missing_height() {
+        let template =
+            KeyTemplate::validate("{{ basename | upcase }}-{{ height }}-{{ size }}.{{ ext }}")
+                .unwrap();
+        assert_eq!(template.render(&values()).unwrap(), "CAT--w300.webp");
+    }
+
+    #[test]
+    fn unknown_variable() {
+        assert!(matches!(
+            KeyTemplate::validate("{{ nope }}-{{ width }}.{{ ext }}").unwrap_err(),
+            KeyTemplateError::Template(_)
+        ));
+    }
+
+    #[test]
+    fn invalid_syntax() {
+        assert!(matches!(
+            KeyTemplate::parse("{{ size.{{ ext }}").unwrap_err(),
+            KeyTemplateError::Template(_)
+        ));
+        assert!(matches!(
+            KeyTemplate::parse("{% if width %}{{ width }}").unwrap_err(),
+            KeyTemplateError::Template(_)
+        ));
+    }
+
+    #[test]
+    fn render_prefix() {
+        let values = KeyPrefixValues {
+            team: "team1",
+            project: "proj",
+            date: NaiveDate::from_ymd_opt(2026, 3, 7).unwrap(),
+        };
+
+        let template = KeyPrefixTemplate::parse("/{team}/{project}/{year}/{month}/{day}/").unwrap();
+        assert_eq!(
+            template.render(&values).as_deref(),
+            Some("team1/proj/2026/03/07")
+        );
+
+        let template = KeyPrefixTemplate::parse("uploads-{date}").unwrap();
+        assert_eq!(
+            template.render(&values).as_deref(),
+            Some("uploads-2026-03-07")
+        );
+
+        assert_eq!(KeyPrefixTemplate::parse("/").unwrap().render(&values), None);
+    }
+
+    #[test]
+    fn invalid_prefix() {
+        assert_eq!(
+            KeyPrefixTemplate::parse("{team}/../{project}").unwrap_err(),
+            KeyTemplateError::ParentDirectory
+        );
+        assert_eq!(
+            KeyPrefixTemplate::parse("{team}/{ext}").unwrap_err(),
+            KeyTemplateError::UnknownVariable("ext".to_string())
+        );
+    }
+
+    #[test]
+    fn requires_distinguishing_variables() {
+        assert_eq!(
+            KeyTemplate::validate("{{ image_id }}/{{ width }}").unwrap_err(),
+            KeyTemplateError::MissingVariable("{{ ext }}")
+        );
+        assert!(matches!(
+            KeyTemplate::validate("{{ image_id }}.{{ ext }}").unwrap_err(),
+            KeyTemplateError::MissingVariable(_)
+        ));
+        // Only set for outputs with a height, so every width would get the same key.
+        assert!(matches!(
+            KeyTemplate::validate("{{ image_id }}-{{ height }}.{{ ext }}").unwrap_err(),
+            KeyTemplateError::MissingVariable(_)
+        ));
+        // Single braces are plain text in a liquid template.
+        assert!(matches!(
+            KeyTemplate::validate("{image_id}/{width}w.{ext}").unwrap_err(),
+            KeyTemplateError::MissingVariable(_)
+        ));
+    }
+}
@@ -0,0 +1,166 @@
+//! Labels on projects, upload profiles, and storage locations, and the team's policies for them.
+//! An image has the labels of its project and its upload profile. When it is uploaded, every
+//! policy for those labels must allow the storage locations that the profile uses, so that a
+//! team can make sure that images labeled `pii` only go to an approved bucket.
+
+use db::{
+    label_policies::{self, LabelPolicy},
+    object_id::{StorageLocationId, TeamId, UploadProfileId},
+    projects, storage_locations, upload_profiles,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+
+use crate::Error;
+
+const MAX_LABEL_LENGTH: usize = 64;
+const MAX_LABELS: usize = 32;
+
+/// Trim the labels and remove duplicates and empty labels.
+pub fn normalize_labels(labels: Vec<String>) -> Result<Vec<String>, Error> {
+    let mut output: Vec<String> = Vec::with_capacity(labels.len());
+    for label in labels {
+        let label = label.trim();
+        if label.is_empty() || output.iter().any(|l| l == label) {
+            continue;
+        }
+
+        if label.len() > MAX_LABEL_LENGTH {
+            return Err(Error::InvalidLabel("labels can be at most 64 characters"));
+        }
+
+        output.push(label.to_string());
+    }
+
+    if output.len() > MAX_LABELS {
+        return Err(Error::InvalidLabel("an object can have at most 32 labels"));
+    }
+
+    Ok(output)
+}
+
+/// A storage location that an upload will write to.
+#[derive(Debug, Queryable)]
+pub struct UploadLocation {
+    pub id: StorageLocationId,
+    pub labels: Vec<String>,
+}
+
+/// Find the first policy that doesn't allow one of the locations.
+pub fn find_violation<'a>(
+    policies: &'a [LabelPolicy],
+    locations: &[UploadLocation],
+) -> Option<&'a LabelPolicy> {
+    policies.iter().find(|policy| {
+        locations
+            .iter()
+            .any(|location| !policy.rule.allows_location(location.id, &location.labels))
+    })
+}
+
+/// Check the team's label policies for an upload with the profile.
+pub fn check_upload(
+    conn: &mut PgConnection,
+    team_id: TeamId,
+    upload_profile_id: UploadProfileId,
+) -> Result<(), Error> {
+    let (profile_labels, project_labels, base_location_id, output_location_id) =
+        upload_profiles::table
+            .inner_join(projects::table)
+            .filter(upload_profiles::id.eq(upload_profile_id))
+            .filter(upload_profiles::team_id.eq(team_id))
+            .select((
+                upload_profiles::labels,
+                projects::labels,
+                upload_profiles::base_storage_location_id,
+                upload_profiles::output_storage_location_id,
+            ))
+            .first::<(
+                Vec<String>,
+                Vec<String>,
+                StorageLocationId,
+                StorageLocationId,
+            )>(conn)?;
+
+    let mut labels = project_labels;
+    labels.extend(profile_labels);
+    let policies = label_policies::policies_for_labels(conn, team_id, &labels)?;
+    if policies.is_empty() {
+        return Ok(());
+    }
+
+    let locations = storage_locations::table
+        .filter(storage_locations::id.eq_any([base_location_id, output_location_id]))
+        .select((storage_locations::id, storage_locations::labels))
+        .load::<UploadLocation>(conn)?;
+
+    match find_violation(&policies, &locations) {
+        Some(policy) => Err(Error::LabelPolicyViolation(policy.label.clone())),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use db::{label_policies::LabelPolicyRule, object_id::LabelPolicyId};
+
+    use super::*;
+
+    fn policy(label: &str, rule: LabelPolicyRule) -> LabelPolicy {
+        LabelPolicy {
+            id: LabelPolicyId::new(),
+            team_id: TeamId::new(),
+            label: label.to_string(),
+            rule,
+            updated: Utc::now(),
+            deleted: None,
+        }
+    }
+
+    fn location(id: StorageLocationId, labels: &[&str]) -> UploadLocation {
+        UploadLocation {
+            id,
+            labels: labels.iter().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(
+            normalize_labels(vec![" pii ".into(), "".into(), "pii".into(), "eu".into()]).unwrap(),
+            vec!["pii".to_string(), "eu".to_string()]
+        );
+        assert!(normalize_labels(vec!["a".repeat(65)]).is_err());
+    }
+
+    #[test]
+    fn violations() {
+        let approved = StorageLocationId::new();
+        let other = StorageLocationId::new();
+        let policies = vec![policy(
+            "pii",
+            LabelPolicyRule::RequireStorageLocation {
+                storage_location_ids: vec![approved],
+            },
+        )];
+        assert!(find_violation(&policies, &[location(approved, &[])]).is_none());
+        assert_eq!(
+            find_violation(&policies, &[location(approved, &[]), location(other, &[])])
+                .map(|p| p.label.as_str()),
+            Some("pii")
+        );
+
+        let policies = vec![policy(
+            "eu",
+            LabelPolicyRule::RequireStorageLabel {
+                storage_label: "eu-region".to_string(),
+            },
+        )];
+        assert!(find_violation(&policies, &[location(other, &["eu-region"])]).is_none());
+        assert!(find_violation(&policies, &[location(other, &["us-region"])]).is_some());
+
+        let policies = vec![policy("frozen", LabelPolicyRule::DenyUpload)];
+        assert!(find_violation(&policies, &[location(approved, &[])]).is_some());
+    }
+}
@@ -1,16 +1,52 @@
+pub mod access_token;
 pub mod api_key;
+pub mod api_usage;
 pub mod auth;
+pub mod build_info;
+pub mod captioning;
+pub mod cdn_purge;
+pub mod checksum;
+pub mod client_hints;
 pub mod config;
+pub mod conversion_pause;
+pub mod cors;
 mod crud_helpers;
 pub mod error;
+pub mod gallery;
+pub mod geo;
+pub mod hotlink;
+pub mod image_references;
+pub mod impersonation;
 pub mod jobs;
+pub mod json;
+pub mod key_binding;
+pub mod key_template;
+pub mod labels;
+pub mod list_stream;
+pub mod metering;
+pub mod mirrors;
 pub mod obfuscate_errors;
 pub mod panic_handler;
+pub mod policy;
+pub mod post_process;
+pub mod profile_templates;
+pub mod queue_slo;
+pub mod range;
+pub mod rate_limit;
+pub mod recording;
+pub mod redact;
+pub mod remote_fetch;
 pub mod routes;
+pub mod share_link;
 pub mod shared_state;
+pub mod signed_url;
+pub mod tagging;
+pub mod team_status;
 pub mod tracing_config;
+pub mod webhooks;
+pub mod zip_stream;
 
-use axum::{routing::IntoMakeService, Extension, Router};
+use axum::{extract::connect_info::IntoMakeServiceWithConnectInfo, Extension, Router};
 use clap::Parser;
 use futures::Future;
 use hyper::server::conn::AddrIncoming;
@@ -26,7 +62,7 @@ use tower_cookies::CookieManagerLayer;
 use tower_http::{
     catch_panic::CatchPanicLayer,
     request_id::MakeRequestUuid,
-    trace::{DefaultMakeSpan, DefaultOnRequest, DefaultOnResponse, TraceLayer},
+    trace::{DefaultOnRequest, DefaultOnResponse, TraceLayer},
     ServiceBuilderExt,
 };
 use tracing::{event, Level};
@@ -42,7 +78,7 @@ use crate::{
 pub struct Server {
     pub host: String,
     pub port: u16,
-    pub server: axum::Server<AddrIncoming, IntoMakeService<Router>>,
+    pub server: axum::Server<AddrIncoming, IntoMakeServiceWithConnectInfo<Router, SocketAddr>>,
     pub state: Arc<InnerState>,
     pub worker: effectum::Worker,
 }
@@ -86,6 +122,14 @@ impl Server {
             .await
             .map_err(Error::ServerError)?;
 
+        if let Err(e) = self.state.api_usage.flush(&self.state.db).await {
+            event!(Level::ERROR, error = ?e, "Failed to save API usage");
+        }
+
+        if let Err(e) = self.state.metering.flush(&self.state.db).await {
+            event!(Level::ERROR, error = ?e, "Failed to save metered traffic");
+        }
+
         self.state.queue.close(Duration::from_secs(10)).await?;
         Ok(())
     }
@@ -96,14 +140,152 @@ pub async fn create_server(config: config::Config) -> Result<Server, eyre::Repor
 
     let production = config.env != "development" && !cfg!(debug_assertions);
 
-    let (queue, worker) = jobs::create_job_queue(&PathBuf::from(config.queue_db_path), db.clone())
-        .await
-        .map_err(|e| eyre::eyre!("Failed to create job queue: {}", e))?;
+    let decode_limits = pic_store_convert::DecodeLimits {
+        max_width: Some(config.max_image_width),
+        max_height: Some(config.max_image_height),
+        max_pixels: Some(config.max_image_pixels),
+        max_decoded_bytes: Some(config.max_decoded_image_bytes),
+    };
+    let job_settings = jobs::JobSettings::from_config(&config, decode_limits.clone());
+
+    let request_recorder = match config.record_requests_dir {
+        Some(_) if production => {
+            event!(
+                Level::WARN,
+                "Ignoring record_requests_dir, since request recording is disabled in production"
+            );
+            None
+        }
+        Some(dir) => {
+            std::fs::create_dir_all(&dir)?;
+            Some(recording::RequestRecorder {
+                dir,
+                sample_rate: config.record_requests_sample_rate,
+            })
+        }
+        None => None,
+    };
+
+    // Local storage locations are checked against this directory, so it needs to be absolute.
+    let local_storage_dir = match config.local_storage_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(&dir)?;
+            Some(dir.canonicalize()?)
+        }
+        None => None,
+    };
+
+    if let Some(key) = config.credentials_key.as_deref() {
+        let key = pic_store_db::credentials::MasterKey::from_base64(key)?;
+        pic_store_db::credentials::set_master_key(key);
+    }
+
+    if config.ocr_language.is_some() && !cfg!(feature = "ocr") {
+        return Err(eyre::eyre!(
+            "ocr_language is set, but the server was built without the ocr feature"
+        ));
+    }
+
+    if config.detect_codes && !cfg!(feature = "barcodes") {
+        return Err(eyre::eyre!(
+            "detect_codes is set, but the server was built without the barcodes feature"
+        ));
+    }
+
+    let policy_engine = config
+        .authz_policy_file
+        .as_deref()
+        .map(policy::PolicyEngine::from_file)
+        .transpose()?;
+
+    let cdn_purger = cdn_purge::CdnPurger::default();
+
+    let api_usage = api_usage::UsageRecorder::default();
+    api_usage.start_flush_task(db.clone());
+
+    let metering = metering::MeteringRecorder::default();
+    metering.start_tasks(db.clone());
+
+    let queue_latency = queue_slo::LatencyRecorder::default();
+
+    let (queue, worker) = jobs::create_job_queue(
+        &PathBuf::from(config.queue_db_path),
+        db.clone(),
+        job_settings,
+        cdn_purger.clone(),
+        queue_latency.clone(),
+    )
+    .await
+    .map_err(|e| eyre::eyre!("Failed to create job queue: {}", e))?;
 
     let state = Arc::new(InnerState {
         production,
         db: db.clone(),
         queue,
+        decode_limits,
+        url_signer: config
+            .url_signing_key
+            .as_deref()
+            .map(signed_url::UrlSigner::new),
+        imgproxy_key: config
+            .imgproxy_key
+            .as_deref()
+            .map(|key| {
+                routes::imgproxy::ImgproxyKey::new(
+                    key,
+                    config.imgproxy_salt.as_deref().unwrap_or_default(),
+                )
+            })
+            .transpose()?,
+        geo_country_header: http::HeaderName::try_from(config.geo_country_header.as_str())?,
+        allow_local_fs: config.allow_local_fs,
+        local_storage_dir,
+        policy_engine,
+        imgix_compat: config.imgix_compat,
+        early_hints: config.early_hints,
+        render_permits: tokio::sync::Semaphore::new(config.max_concurrent_renders.max(1)),
+        rate_limiter: rate_limit::RateLimiter::new(
+            rate_limit::RateLimits {
+                upload: config.rate_limit_uploads,
+                transform: config.rate_limit_transforms,
+                report: config.rate_limit_reports,
+                read: config.rate_limit_reads,
+                write: config.rate_limit_writes,
+            },
+            config
+                .client_ip_header
+                .as_deref()
+                .map(http::HeaderName::try_from)
+                .transpose()?,
+        ),
+        reference_crawler: config.reference_crawler,
+        strict_json: config.strict_json,
+        // The file size is stored as an i32.
+        max_upload_size: config.max_upload_size.min(i32::MAX as usize),
+        upload_part_size: config.upload_part_size,
+        url_fetch_policy: remote_fetch::FetchPolicy {
+            timeout: std::time::Duration::from_secs(config.url_upload_timeout_seconds),
+            max_size: config.max_upload_size.min(i32::MAX as usize),
+            allow_private_networks: config.url_upload_allow_private_networks,
+        },
+        request_recorder,
+        cdn_purger,
+        api_usage,
+        metering,
+        status_cache: Default::default(),
+        queue_slo: queue_slo::QueueSloMonitor::new(
+            queue_latency,
+            queue_slo::SloThresholds {
+                max_queue_depth: config.slo_max_queue_depth,
+                max_worker_lag: config
+                    .slo_max_worker_lag_seconds
+                    .map(std::time::Duration::from_secs),
+                max_p95_latency: config
+                    .slo_max_p95_latency_seconds
+                    .map(std::time::Duration::from_secs),
+            },
+            config.slo_alert_webhook_url,
+        ),
         // Temporary hardcoded values
         project_id: std::env::var("DEFAULT_PROJECT_ID")
             .expect("DEFAULT_PROJECT_ID")
@@ -119,6 +301,22 @@ pub async fn create_server(config: config::Config) -> Result<Server, eyre::Repor
             .unwrap(),
     });
 
+    jobs::start_original_retention_task(state.clone());
+    jobs::start_chunked_upload_expiry_task(state.clone());
+    jobs::start_webhook_delivery_task(state.clone());
+    if let Some(hours) = config.link_check_interval_hours {
+        jobs::start_delivery_url_check_task(
+            state.clone(),
+            std::time::Duration::from_secs(hours * 3600),
+            config.link_check_sample_size,
+        );
+    }
+    conversion_pause::start_release_task(state.clone());
+    queue_slo::start_monitor_task(
+        state.clone(),
+        std::time::Duration::from_secs(config.slo_check_interval_seconds.max(1)),
+    );
+
     let app: Router<AppState> = routes::configure_routes(Router::new()).layer(
         // Global middlewares
         ServiceBuilder::new()
@@ -127,21 +325,53 @@ pub async fn create_server(config: config::Config) -> Result<Server, eyre::Repor
             }))
             .layer(ObfuscateErrorLayer::new(production, false))
             .compression()
+            // Inside compression so that responses are recorded before they are compressed.
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                recording::record_exchange,
+            ))
             .decompression()
             .layer(CookieManagerLayer::new())
             .set_x_request_id(MakeRequestUuid)
             .propagate_x_request_id()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                cors::apply_cors,
+            ))
             .layer(auth_layer(
                 db.clone(),
                 config.session_cookie_name.clone(),
                 &config.cookie_key,
             ))
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                impersonation::impersonate,
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                api_usage::record_usage,
+            ))
+            .layer(axum::middleware::from_fn(team_status::enforce_team_status))
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                rate_limit::enforce_rate_limit,
+            ))
+            .layer(axum::middleware::from_fn(key_binding::enforce_key_binding))
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                policy::enforce_policy,
+            ))
             .layer(
                 TraceLayer::new_for_http()
-                    .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
+                    .make_span_with(redact::RedactedMakeSpan)
                     .on_response(DefaultOnResponse::new().level(Level::INFO))
                     .on_request(DefaultOnRequest::new().level(Level::INFO)),
             )
+            // Panics are also caught inside the trace layer, so that the panic and its error ID
+            // are recorded in the request's trace. The outer layer handles panics in middleware.
+            .layer(CatchPanicLayer::custom(move |err| {
+                panic_handler::handle_panic(production, err)
+            }))
             .into_inner(),
     );
 
@@ -149,9 +379,12 @@ pub async fn create_server(config: config::Config) -> Result<Server, eyre::Repor
 
     let bind_ip: IpAddr = config.host.parse()?;
     let bind_addr = SocketAddr::from((bind_ip, config.port));
-    let builder = axum::Server::bind(&bind_addr);
+    // Connections that start with the HTTP/2 preface are served as HTTP/2, so a CDN or proxy
+    // can multiplex image requests over a single cleartext connection.
+    let builder = axum::Server::bind(&bind_addr).http2_adaptive_window(true);
 
-    let server = builder.serve(app.into_make_service());
+    // The connection's address is used to rate limit unauthenticated requests.
+    let server = builder.serve(app.into_make_service_with_connect_info::<SocketAddr>());
     let actual_addr = server.local_addr();
     let port = actual_addr.port();
     event!(Level::INFO, "Listening on {}:{port}", config.host);
@@ -0,0 +1,284 @@
+//! Streamed responses for listings that are too large to build in memory, such as exporting
+//! every image in a project. Rows are read from the database in batches, in cursor order, and
+//! each batch is written out as it arrives.
+//!
+//! The `format` query parameter chooses the encoding. `json`, the default, is a single JSON
+//! array. `ndjson` is one JSON object per line, which clients can process as it arrives instead
+//! of parsing the whole response at once. Clients that send `Accept: application/x-ndjson` get
+//! NDJSON without the parameter.
+//!
+//! CSV exports use the same batching, with a header row naming the columns.
+//!
+//! The status code has already been sent when a later batch fails, so the body just ends early.
+//! Clients see this as an incomplete response.
+
+use axum::{
+    body::{Bytes, StreamBody},
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use diesel::PgConnection;
+use futures::{Stream, StreamExt, TryStreamExt};
+use pic_store_db::{self as db, PoolExt};
+use serde::{Deserialize, Serialize};
+use tracing::{event, Level};
+
+use crate::Error;
+
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListFormat {
+    #[default]
+    Json,
+    Ndjson,
+}
+
+impl ListFormat {
+    /// Use the format from the query, or from the Accept header when the query doesn't set one.
+    pub fn negotiate(query: Option<ListFormat>, headers: &HeaderMap) -> ListFormat {
+        if let Some(format) = query {
+            return format;
+        }
+
+        let accepts_ndjson = headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .map(|accept| {
+                accept.split(',').any(|part| {
+                    part.split(';')
+                        .next()
+                        .unwrap_or_default()
+                        .trim()
+                        .eq_ignore_ascii_case(NDJSON_CONTENT_TYPE)
+                })
+            })
+            .unwrap_or(false);
+
+        if accepts_ndjson {
+            ListFormat::Ndjson
+        } else {
+            ListFormat::Json
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            ListFormat::Json => "application/json",
+            ListFormat::Ndjson => NDJSON_CONTENT_TYPE,
+        }
+    }
+}
+
+/// Read rows in batches of `batch_size`. `load` gets the cursor of the last row in the previous
+/// batch, or `None` for the first batch, and returns the next rows after it in cursor order.
+pub fn batches<T, C, F>(
+    pool: db::Pool,
+    batch_size: i64,
+    cursor: fn(&T) -> C,
+    load: F,
+) -> impl Stream<Item = Result<T, Error>>
+where
+    T: Send + 'static,
+    C: Send + 'static,
+    F: Fn(&mut PgConnection, Option<C>, i64) -> Result<Vec<T>, Error> + Clone + Send + 'static,
+{
+    // The state is the cursor to read after, or None once the last batch has been read.
+    futures::stream::try_unfold(Some(None), move |after: Option<Option<C>>| {
+        let pool = pool.clone();
+        let load = load.clone();
+        async move {
+            let Some(after) = after else {
+                return Ok(None);
+            };
+
+            let rows = pool
+                .interact(move |conn| load(conn, after, batch_size))
+                .await?;
+            let next = if (rows.len() as i64) < batch_size {
+                None
+            } else {
+                rows.last().map(|row| Some(cursor(row)))
+            };
+
+            Ok::<_, Error>(Some((rows, next)))
+        }
+    })
+    .map_ok(|rows| futures::stream::iter(rows.into_iter().map(Ok)))
+    .try_flatten()
+}
+
+/// Encode a stream of rows as a response in the requested format.
+pub fn response<T, S>(format: ListFormat, rows: S) -> Response
+where
+    T: Serialize + Send + 'static,
+    S: Stream<Item = Result<T, Error>> + Send + 'static,
+{
+    let (open, close): (&'static [u8], &'static [u8]) = match format {
+        ListFormat::Json => (b"[", b"]"),
+        ListFormat::Ndjson => (b"", b""),
+    };
+
+    let rows = rows
+        .enumerate()
+        .map(move |(i, row)| encode_row(format, i, row));
+    let body = futures::stream::once(async move { Ok(Bytes::from_static(open)) })
+        .chain(rows)
+        .chain(futures::stream::once(async move {
+            Ok(Bytes::from_static(close))
+        }));
+
+    (
+        [(header::CONTENT_TYPE, format.content_type())],
+        StreamBody::new(body),
+    )
+        .into_response()
+}
+
+/// Encode a stream of CSV records as a downloadable file named `filename`.
+pub fn csv_response<S>(filename: &str, columns: Vec<&'static str>, rows: S) -> Response
+where
+    S: Stream<Item = Result<Vec<String>, Error>> + Send + 'static,
+{
+    let header_row = csv_record(columns.iter().copied());
+    let rows = rows.map(|row| {
+        row.map(|fields| Bytes::from(csv_record(fields.iter().map(String::as_str))))
+            .map_err(|e| {
+                event!(Level::ERROR, error = ?e, "Failed to read rows for a streamed response");
+                std::io::Error::other(e.to_string())
+            })
+    });
+    let body = futures::stream::once(async move { Ok(Bytes::from(header_row)) }).chain(rows);
+
+    let mut response = (
+        [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+        StreamBody::new(body),
+    )
+        .into_response();
+    let disposition = format!("attachment; filename=\"{filename}\"");
+    if let Ok(value) = HeaderValue::from_str(&disposition) {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_DISPOSITION, value);
+    }
+
+    response
+}
+
+/// Format a CSV record, ending with CRLF as in RFC 4180.
+fn csv_record<'a>(fields: impl Iterator<Item = &'a str>) -> String {
+    let mut line = String::new();
+    for (i, field) in fields.enumerate() {
+        if i > 0 {
+            line.push(',');
+        }
+
+        // Spreadsheets run cells that start with these characters as formulas, so quote them
+        // with a leading apostrophe.
+        let formula = field.starts_with(['=', '+', '-', '@']);
+        if formula || field.contains([',', '"', '\n', '\r']) {
+            line.push('"');
+            if formula {
+                line.push('\'');
+            }
+            line.push_str(&field.replace('"', "\"\""));
+            line.push('"');
+        } else {
+            line.push_str(field);
+        }
+    }
+
+    line.push_str("\r\n");
+    line
+}
+
+fn encode_row<T: Serialize>(
+    format: ListFormat,
+    index: usize,
+    row: Result<T, Error>,
+) -> Result<Bytes, std::io::Error> {
+    let row = row.map_err(|e| {
+        event!(Level::ERROR, error = ?e, "Failed to read rows for a streamed response");
+        std::io::Error::other(e.to_string())
+    })?;
+
+    let mut buf = Vec::new();
+    if format == ListFormat::Json && index > 0 {
+        buf.push(b',');
+    }
+    serde_json::to_writer(&mut buf, &row)?;
+    if format == ListFormat::Ndjson {
+        buf.push(b'\n');
+    }
+
+    Ok(Bytes::from(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body_text(format: ListFormat, rows: Vec<i32>) -> String {
+        let response = response(format, futures::stream::iter(rows.into_iter().map(Ok)));
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn json_array() {
+        assert_eq!(body_text(ListFormat::Json, vec![]).await, "[]");
+        assert_eq!(body_text(ListFormat::Json, vec![1, 2, 3]).await, "[1,2,3]");
+    }
+
+    #[tokio::test]
+    async fn ndjson_lines() {
+        assert_eq!(body_text(ListFormat::Ndjson, vec![]).await, "");
+        assert_eq!(body_text(ListFormat::Ndjson, vec![1, 2]).await, "1\n2\n");
+    }
+
+    #[test]
+    fn negotiate() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(ListFormat::negotiate(None, &headers), ListFormat::Json);
+
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/x-ndjson;q=1.0, application/json"),
+        );
+        assert_eq!(ListFormat::negotiate(None, &headers), ListFormat::Ndjson);
+        assert_eq!(
+            ListFormat::negotiate(Some(ListFormat::Json), &headers),
+            ListFormat::Json
+        );
+    }
+
+    #[test]
+    fn csv_escaping() {
+        assert_eq!(csv_record(["a", "b c", ""].into_iter()), "a,b c,\r\n");
+        assert_eq!(
+            csv_record(["a,b", "say \"hi\"", "x\ny"].into_iter()),
+            "\"a,b\",\"say \"\"hi\"\"\",\"x\ny\"\r\n"
+        );
+        assert_eq!(csv_record(["=1+1"].into_iter()), "\"'=1+1\"\r\n");
+    }
+
+    #[tokio::test]
+    async fn csv_body() {
+        let rows = futures::stream::iter(vec![Ok(vec!["1".to_string(), "a,b".to_string()])]);
+        let response = csv_response("export.csv", vec!["id", "tags"], rows);
+        assert_eq!(
+            response.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"export.csv\""
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"id,tags\r\n1,\"a,b\"\r\n");
+    }
+
+    #[tokio::test]
+    async fn error_ends_body() {
+        let rows = futures::stream::iter(vec![Ok(1), Err(Error::NotFound)]);
+        let body = response(ListFormat::Ndjson, rows).into_body();
+        assert!(hyper::body::to_bytes(body).await.is_err());
+    }
+}
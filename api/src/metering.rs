@@ -0,0 +1,157 @@
+//! Daily metering of the bytes that each project stores, uploads, and serves, so that operators
+//! can bill tenants or alert on heavy ones. Uploads and served images are counted in memory by
+//! the upload and serve routes and periodically added to the database, like [crate::api_usage].
+//! Storage is measured by a periodic snapshot of the stored images.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{NaiveDate, Utc};
+use db::{
+    metering::{self, MeteredTraffic},
+    object_id::{ProjectId, TeamId},
+    PoolExt,
+};
+use pic_store_db as db;
+use tracing::{event, Level};
+
+use crate::Error;
+
+/// How often the traffic counts are written to the database.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the stored bytes are measured.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct MeterKey {
+    team_id: TeamId,
+    project_id: ProjectId,
+    day: NaiveDate,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct TrafficCounts {
+    bytes_uploaded: i64,
+    bytes_served: i64,
+    images_served: i64,
+}
+
+/// Collects upload and serve traffic until it is flushed to the database.
+#[derive(Clone, Debug, Default)]
+pub struct MeteringRecorder {
+    pending: Arc<Mutex<HashMap<MeterKey, TrafficCounts>>>,
+}
+
+impl MeteringRecorder {
+    fn counts(&self, team_id: TeamId, project_id: ProjectId, f: impl FnOnce(&mut TrafficCounts)) {
+        let key = MeterKey {
+            team_id,
+            project_id,
+            day: Utc::now().date_naive(),
+        };
+        let mut pending = self.pending.lock().unwrap();
+        f(pending.entry(key).or_default());
+    }
+
+    /// Count an uploaded original.
+    pub fn record_upload(&self, team_id: TeamId, project_id: ProjectId, bytes: usize) {
+        self.counts(team_id, project_id, |counts| {
+            counts.bytes_uploaded += bytes as i64;
+        });
+    }
+
+    /// Count an image sent by a serve route.
+    pub fn record_served(&self, team_id: TeamId, project_id: ProjectId, bytes: usize) {
+        self.counts(team_id, project_id, |counts| {
+            counts.bytes_served += bytes as i64;
+            counts.images_served += 1;
+        });
+    }
+
+    /// Write the pending counts to the database.
+    pub async fn flush(&self, pool: &db::Pool) -> Result<(), Error> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let rows = pending
+            .into_iter()
+            .map(|(key, counts)| MeteredTraffic {
+                team_id: key.team_id,
+                project_id: key.project_id,
+                day: key.day,
+                bytes_uploaded: counts.bytes_uploaded,
+                bytes_served: counts.bytes_served,
+                images_served: counts.images_served,
+            })
+            .collect::<Vec<_>>();
+
+        pool.interact(move |conn| {
+            metering::add_traffic(conn, &rows)?;
+            Ok::<_, Error>(())
+        })
+        .await
+    }
+
+    /// Flush the traffic counts and take storage snapshots periodically.
+    pub fn start_tasks(&self, pool: db::Pool) -> tokio::task::JoinHandle<()> {
+        let recorder = self.clone();
+        tokio::task::spawn(async move {
+            let mut flush_interval = tokio::time::interval(FLUSH_INTERVAL);
+            flush_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            let mut snapshot_interval = tokio::time::interval(SNAPSHOT_INTERVAL);
+            snapshot_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = flush_interval.tick() => {
+                        if let Err(e) = recorder.flush(&pool).await {
+                            event!(Level::ERROR, error = ?e, "Failed to save metered traffic");
+                        }
+                    }
+                    _ = snapshot_interval.tick() => {
+                        if let Err(e) = snapshot_storage(&pool).await {
+                            event!(Level::ERROR, error = ?e, "Failed to measure stored bytes");
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Measure the bytes stored by every project and save them as today's storage.
+pub async fn snapshot_storage(pool: &db::Pool) -> Result<(), Error> {
+    let today = Utc::now().date_naive();
+    pool.interact(move |conn| {
+        metering::snapshot_storage(conn, today)?;
+        Ok::<_, Error>(())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_traffic() {
+        let recorder = MeteringRecorder::default();
+        let team_id = TeamId::new();
+        let project_id = ProjectId::new();
+        recorder.record_upload(team_id, project_id, 1000);
+        recorder.record_served(team_id, project_id, 200);
+        recorder.record_served(team_id, project_id, 300);
+
+        let pending = recorder.pending.lock().unwrap();
+        assert_eq!(pending.len(), 1);
+        let counts = pending.values().next().unwrap();
+        assert_eq!(counts.bytes_uploaded, 1000);
+        assert_eq!(counts.bytes_served, 500);
+        assert_eq!(counts.images_served, 2);
+    }
+}
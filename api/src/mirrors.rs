@@ -0,0 +1,133 @@
+//! Mirror storage locations for output images. An upload profile can list other storage
+//! locations to copy its output images to. The conversion worker writes each output image to
+//! every mirror and records which copies succeeded, and the serve route reads from a mirror when
+//! the output storage location fails.
+
+use bytes::Bytes;
+use chrono::Utc;
+use db::{
+    image_base_location,
+    object_id::{OutputImageId, StorageLocationId, TeamId},
+    output_image_mirrors::{self, OutputImageMirror},
+    storage_locations::{self, StorageLocation},
+    OutputMirrorStatus, PoolExt,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use pic_store_storage::{self as storage, Operator};
+use tracing::{event, Level};
+
+use crate::checksum;
+
+/// A mirror storage location, with the same path layout as the profile's output location.
+pub struct Mirror {
+    pub storage_location_id: StorageLocationId,
+    /// The error if the location's storage can't be used.
+    pub operator: Result<Operator, String>,
+}
+
+/// Load the storage locations of a profile's mirrors, in the profile's order. Locations that
+/// were deleted or belong to another team are left out.
+pub fn load_locations(
+    conn: &mut PgConnection,
+    team_id: TeamId,
+    ids: &[StorageLocationId],
+) -> QueryResult<Vec<StorageLocation>> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut locations = storage_locations::table
+        .filter(storage_locations::id.eq_any(ids))
+        .filter(storage_locations::team_id.eq(team_id))
+        .filter(storage_locations::deleted.is_null())
+        .load::<StorageLocation>(conn)?;
+    locations.sort_by_key(|location| ids.iter().position(|id| *id == location.id));
+    Ok(locations)
+}
+
+/// Set up the storage for each mirror location.
+pub async fn open(
+    locations: Vec<StorageLocation>,
+    project_base_location: &str,
+    profile_path: &Option<String>,
+) -> Vec<Mirror> {
+    let mut mirrors = Vec::with_capacity(locations.len());
+    for location in locations {
+        let base_location =
+            image_base_location(&location.base_location, project_base_location, profile_path);
+        let operator = match storage::Provider::from_db(location.provider) {
+            Ok(provider) => provider
+                .create_operator(base_location.as_ref())
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+
+        mirrors.push(Mirror {
+            storage_location_id: location.id,
+            operator,
+        });
+    }
+
+    mirrors
+}
+
+/// Copy an output image to each mirror and record the result. A failed copy doesn't fail the
+/// conversion, since the output storage location still has the image.
+pub async fn write(
+    pool: &db::Pool,
+    team_id: TeamId,
+    output_image_id: OutputImageId,
+    mirrors: &[Mirror],
+    location: &str,
+    contents: Bytes,
+) -> Result<(), eyre::Report> {
+    for mirror in mirrors {
+        let result = match &mirror.operator {
+            Ok(operator) => checksum::put_verified(operator, location, contents.clone())
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.clone()),
+        };
+
+        let (status, sha256, error) = match result {
+            Ok(sha256) => (OutputMirrorStatus::Ready, Some(sha256), None),
+            Err(error) => {
+                event!(Level::WARN, storage_location_id = %mirror.storage_location_id, %location, %error, "Failed to copy output image to mirror");
+                (OutputMirrorStatus::Failed, None, Some(error))
+            }
+        };
+
+        let record = OutputImageMirror {
+            output_image_id,
+            storage_location_id: mirror.storage_location_id,
+            team_id,
+            status,
+            sha256,
+            error,
+            updated: Utc::now(),
+        };
+        pool.interact(move |conn| {
+            output_image_mirrors::record(conn, &record).map_err(eyre::Report::new)
+        })
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Remove an output image from each mirror. Failures are only logged, since the output image
+/// is already gone from the output storage location.
+pub async fn delete(mirrors: &[Mirror], location: &str) {
+    for mirror in mirrors {
+        let result = match &mirror.operator {
+            Ok(operator) => operator.delete(location).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e.clone()),
+        };
+
+        if let Err(error) = result {
+            event!(Level::WARN, storage_location_id = %mirror.storage_location_id, %location, %error, "Failed to delete output image from mirror");
+        }
+    }
+}
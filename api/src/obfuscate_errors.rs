@@ -8,6 +8,8 @@ use futures::future::BoxFuture;
 use serde_json::json;
 use tower::{Layer, Service};
 
+use crate::panic_handler::ERROR_ID_HEADER;
+
 #[derive(Clone)]
 pub struct ObfuscateErrorLayer {
     enabled: bool,
@@ -89,13 +91,21 @@ where
                 return Ok(res);
             }
 
-            let new_response = json!({
+            let error_id = res.headers().get(ERROR_ID_HEADER).cloned();
+            let mut new_response = json!({
                 "error": {
                     "detail": message,
                 }
             });
+            // Keep the error ID so that the error can still be reported.
+            if let Some(id) = error_id.as_ref().and_then(|id| id.to_str().ok()) {
+                new_response["error"]["id"] = json!(id);
+            }
 
-            let new_res = (status, Json(new_response)).into_response();
+            let mut new_res = (status, Json(new_response)).into_response();
+            if let Some(id) = error_id {
+                new_res.headers_mut().insert(ERROR_ID_HEADER, id);
+            }
 
             Ok(new_res)
         })
@@ -113,6 +123,7 @@ mod test {
     use tower::ServiceExt;
 
     use super::ObfuscateErrorLayer;
+    use crate::panic_handler::ERROR_ID_HEADER;
 
     fn make_app(enabled: bool) -> Router {
         Router::new()
@@ -129,6 +140,16 @@ mod test {
                 "/403",
                 get(|| async { (StatusCode::FORBIDDEN, "error 403") }),
             )
+            .route(
+                "/500-id",
+                get(|| async {
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        [(ERROR_ID_HEADER, "abc")],
+                        "error 500",
+                    )
+                }),
+            )
             .layer(ObfuscateErrorLayer::new(enabled, true))
     }
 
@@ -199,5 +220,12 @@ mod test {
             body, r##"{"error":{"detail":"Internal error"}}"##,
             "/500 body should be obfuscated"
         );
+
+        let (code, body) = send_req(&app, "/500-id").await;
+        assert_eq!(code, 500, "/500-id status code");
+        assert_eq!(
+            body, r##"{"error":{"detail":"Internal error","id":"abc"}}"##,
+            "/500-id body should keep the error ID"
+        );
     }
 }
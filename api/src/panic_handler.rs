@@ -1,3 +1,7 @@
+//! Turn panics into error responses. Each panic gets an ID that is returned to the client and
+//! logged with the panic message, so that an error reported by a user can be found in the logs
+//! and traces.
+
 use std::any::Any;
 
 use axum::{
@@ -5,20 +9,35 @@ use axum::{
     http::{header, Response, StatusCode},
 };
 use pic_store_http_errors::ErrorResponseData;
+use tracing::{event, Level, Span};
+
+/// The response header that holds the ID of a panic.
+pub const ERROR_ID_HEADER: &str = "x-error-id";
+
+fn panic_message(err: &(dyn Any + Send + 'static)) -> String {
+    let message = if let Some(s) = err.downcast_ref::<String>() {
+        s.clone()
+    } else if let Some(s) = err.downcast_ref::<&str>() {
+        s.to_string()
+    } else {
+        "Unknown panic message".to_string()
+    };
+
+    crate::redact::redact_secrets(&message).into_owned()
+}
 
 pub fn handle_panic(production: bool, err: Box<dyn Any + Send + 'static>) -> Response<Body> {
+    let error_id = ulid::Ulid::new().to_string();
+    let details = panic_message(err.as_ref());
+
+    // When this runs inside the request span, this ties the ID to the request's trace.
+    Span::current().record("error_id", error_id.as_str());
+    event!(Level::ERROR, %error_id, panic = %details, "Request panicked");
+
     let body = if production {
-        ErrorResponseData::new("internal_server_error", "Server error")
+        ErrorResponseData::with_id("internal_server_error", "Server error", error_id.clone())
     } else {
-        let details = if let Some(s) = err.downcast_ref::<String>() {
-            s.clone()
-        } else if let Some(s) = err.downcast_ref::<&str>() {
-            s.to_string()
-        } else {
-            "Unknown panic message".to_string()
-        };
-
-        ErrorResponseData::new("panic", details)
+        ErrorResponseData::with_id("panic", details, error_id.clone())
     };
 
     let body = serde_json::to_string(&body).unwrap();
@@ -26,6 +45,54 @@ pub fn handle_panic(production: bool, err: Box<dyn Any + Send + 'static>) -> Res
     Response::builder()
         .status(StatusCode::INTERNAL_SERVER_ERROR)
         .header(header::CONTENT_TYPE, "application/json")
+        .header(ERROR_ID_HEADER, error_id)
         .body(Body::from(body))
         .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body_json(response: Response<Body>) -> serde_json::Value {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn production_hides_panic_message() {
+        let response = handle_panic(true, Box::new("secret details"));
+        let error_id = response.headers()[ERROR_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["id"], error_id.as_str());
+        assert_eq!(body["error"]["message"], "Server error");
+    }
+
+    #[tokio::test]
+    async fn development_shows_panic_message() {
+        let response = handle_panic(false, Box::new("something broke".to_string()));
+        let error_id = response.headers()[ERROR_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["id"], error_id.as_str());
+        assert_eq!(body["error"]["kind"], "panic");
+        assert_eq!(body["error"]["message"], "something broke");
+    }
+
+    #[test]
+    fn unique_ids() {
+        let first = handle_panic(true, Box::new("a"));
+        let second = handle_panic(true, Box::new("a"));
+        assert_ne!(
+            first.headers()[ERROR_ID_HEADER],
+            second.headers()[ERROR_ID_HEADER]
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! Cedar policies for authorization, for instances that need conditions that the roles and
+//! permissions can't express, such as only allowing changes during working hours or blocking
+//! access to projects with some label.
+//!
+//! When a policy file is configured, every authenticated request must be permitted by it, in
+//! addition to passing the normal permission checks. Cedar denies anything that no policy
+//! permits, so most policy files start with `permit(principal, action, resource);` and add
+//! `forbid` rules for the exceptions.
+//!
+//! Requests are evaluated with:
+//! * principal: `User::"<user id>"`
+//! * action: `Action::"<HTTP method>"`
+//! * resource: `Project::"<project id>"` for routes under a project, or `Team::"<team id>"`
+//! * context: `route` (such as `/api/projects/:project_id/labels`), `team`, `api_key`,
+//!   `impersonating`, `hour` and `weekday` (in UTC, with Monday as 1), and `project_labels`.
+
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use axum::{
+    extract::{MatchedPath, State},
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use cedar_policy::{
+    Authorizer, Context, Decision, Entities, EntityUid, PolicySet, Request as CedarRequest,
+};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use db::{object_id::ProjectId, projects, PoolExt};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde_json::json;
+use tracing::{event, Level};
+
+use crate::{auth::UserInfo, shared_state::AppState, Error};
+
+pub struct PolicyEngine {
+    policies: PolicySet,
+    authorizer: Authorizer,
+}
+
+impl std::fmt::Debug for PolicyEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PolicyEngine").finish_non_exhaustive()
+    }
+}
+
+/// The request to authorize.
+pub struct PolicyInput<'a> {
+    pub user: &'a UserInfo,
+    pub method: &'a str,
+    pub route: &'a str,
+    pub project_id: Option<ProjectId>,
+    pub project_labels: &'a [String],
+    pub now: DateTime<Utc>,
+}
+
+fn entity_uid(type_name: &str, id: impl Display) -> Result<EntityUid, eyre::Report> {
+    // JSON string quoting is also valid for the IDs that we use.
+    let id = serde_json::Value::String(id.to_string());
+    EntityUid::from_str(&format!("{type_name}::{id}"))
+        .map_err(|e| eyre::eyre!("Invalid entity {type_name}::{id}: {e}"))
+}
+
+impl PolicyEngine {
+    pub fn new(source: &str) -> Result<Self, eyre::Report> {
+        let policies =
+            PolicySet::from_str(source).map_err(|e| eyre::eyre!("Invalid policy: {e}"))?;
+        Ok(PolicyEngine {
+            policies,
+            authorizer: Authorizer::new(),
+        })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, eyre::Report> {
+        let source = std::fs::read_to_string(path)?;
+        Self::new(&source)
+    }
+
+    fn build_request(&self, input: &PolicyInput) -> Result<CedarRequest, eyre::Report> {
+        let principal = entity_uid("User", input.user.user_id)?;
+        let action = entity_uid("Action", input.method)?;
+        let resource = match input.project_id {
+            Some(project_id) => entity_uid("Project", project_id)?,
+            None => entity_uid("Team", input.user.team_id)?,
+        };
+
+        let context = Context::from_json_value(
+            json!({
+                "route": input.route,
+                "team": input.user.team_id.to_string(),
+                "api_key": input.user.api_key_id.is_some(),
+                "impersonating": input.user.impersonation_id.is_some(),
+                "hour": input.now.hour(),
+                "weekday": input.now.weekday().number_from_monday(),
+                "project_labels": input.project_labels,
+            }),
+            None,
+        )
+        .map_err(|e| eyre::eyre!("Invalid policy context: {e}"))?;
+
+        Ok(CedarRequest::new(
+            Some(principal),
+            Some(action),
+            Some(resource),
+            context,
+        ))
+    }
+
+    /// Whether the policies permit the request. Requests that can't be evaluated are denied.
+    pub fn is_allowed(&self, input: &PolicyInput) -> bool {
+        let request = match self.build_request(input) {
+            Ok(request) => request,
+            Err(e) => {
+                event!(Level::ERROR, error = ?e, "Failed to build policy request");
+                return false;
+            }
+        };
+
+        let response = self
+            .authorizer
+            .is_authorized(&request, &self.policies, &Entities::empty());
+        response.decision() == Decision::Allow
+    }
+}
+
+/// Find the project that a route is working with, from a path like `/api/projects/<id>/...`.
+fn project_id_from_path(path: &str) -> Option<ProjectId> {
+    let mut segments = path.split('/').filter(|s| !s.is_empty());
+    segments.find(|s| *s == "projects")?;
+    segments.next()?.parse().ok()
+}
+
+/// Reject authenticated requests that the configured policies don't permit.
+pub async fn enforce_policy<B>(
+    State(state): State<AppState>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, Error> {
+    let (Some(engine), Some(user)) = (
+        state.policy_engine.as_ref(),
+        req.extensions().get::<UserInfo>().cloned(),
+    ) else {
+        return Ok(next.run(req).await.into_response());
+    };
+
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| req.uri().path().to_string());
+    let project_id = project_id_from_path(req.uri().path());
+
+    let project_labels = match project_id {
+        Some(project_id) => {
+            let team_id = user.team_id;
+            state
+                .db
+                .interact(move |conn| {
+                    projects::table
+                        .filter(projects::id.eq(project_id))
+                        .filter(projects::team_id.eq(team_id))
+                        .select(projects::labels)
+                        .first::<Vec<String>>(conn)
+                        .optional()
+                        .map_err(Error::from)
+                })
+                .await?
+                .unwrap_or_default()
+        }
+        None => Vec::new(),
+    };
+
+    let allowed = engine.is_allowed(&PolicyInput {
+        user: &user,
+        method: req.method().as_str(),
+        route: &route,
+        project_id,
+        project_labels: &project_labels,
+        now: Utc::now(),
+    });
+
+    if !allowed {
+        return Err(Error::PolicyDenied);
+    }
+
+    Ok(next.run(req).await.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use db::{
+        object_id::{TeamId, UserId},
+        TeamStatus,
+    };
+
+    use super::*;
+
+    const POLICY: &str = r#"
+        permit(principal, action, resource);
+
+        forbid(principal, action == Action::"DELETE", resource)
+        when { context.hour < 9 || context.hour >= 17 };
+
+        forbid(principal, action, resource)
+        when { context.project_labels.contains("pii") && context.api_key };
+    "#;
+
+    fn user(api_key: bool) -> UserInfo {
+        UserInfo {
+            user_id: UserId::new(),
+            team_id: TeamId::new(),
+            roles: Vec::new(),
+            default_upload_profile_id: None,
+            bound_upload_profile_id: None,
+            team_status: TeamStatus::Active,
+            impersonation_id: None,
+            api_key_id: api_key.then(uuid::Uuid::new_v4),
+        }
+    }
+
+    #[test]
+    fn evaluate() {
+        let engine = PolicyEngine::new(POLICY).unwrap();
+        let user = user(false);
+        let input = |method, hour, labels: &'static [String]| PolicyInput {
+            user: &user,
+            method,
+            route: "/api/projects/:project_id",
+            project_id: Some(ProjectId::new()),
+            project_labels: labels,
+            now: Utc.with_ymd_and_hms(2026, 10, 15, hour, 0, 0).unwrap(),
+        };
+
+        assert!(engine.is_allowed(&input("GET", 20, &[])));
+        assert!(engine.is_allowed(&input("DELETE", 10, &[])));
+        assert!(!engine.is_allowed(&input("DELETE", 20, &[])));
+    }
+
+    #[test]
+    fn label_conditions() {
+        let engine = PolicyEngine::new(POLICY).unwrap();
+        let labels = vec!["pii".to_string()];
+        let input = |user| PolicyInput {
+            user,
+            method: "GET",
+            route: "/api/projects/:project_id",
+            project_id: Some(ProjectId::new()),
+            project_labels: &labels,
+            now: Utc.with_ymd_and_hms(2026, 10, 15, 12, 0, 0).unwrap(),
+        };
+
+        let allowed = user(false);
+        let denied = user(true);
+        assert!(engine.is_allowed(&input(&allowed)));
+        assert!(!engine.is_allowed(&input(&denied)));
+    }
+
+    #[test]
+    fn project_ids() {
+        let project_id = ProjectId::new();
+        assert_eq!(
+            project_id_from_path(&format!("/api/projects/{project_id}/labels")),
+            Some(project_id)
+        );
+        assert_eq!(project_id_from_path("/api/images"), None);
+        assert_eq!(project_id_from_path("/api/projects/not-an-id"), None);
+    }
+
+    #[test]
+    fn invalid_policy() {
+        assert!(PolicyEngine::new("permit(principal").is_err());
+    }
+}
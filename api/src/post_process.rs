@@ -0,0 +1,232 @@
+//! Call an upload profile's post-processing service after an image's outputs are generated.
+//!
+//! The service receives a `POST` with JSON describing the image and its outputs, and responds
+//! with JSON like `{ "approved": true, "tags": ["reviewed"], "alt_text": "A dog on a beach" }`.
+//! Every field of the response is optional. `tags` are added to the image's tags, and `alt_text`
+//! and `collection` replace the image's values when they are set. When `approved` is false the
+//! image is rejected, with `reason` as its error, and it is never published.
+//!
+//! The callback URL gets the same address checks as uploads from URLs, and redirects are not
+//! followed, so the callback can't be pointed at the server's own network.
+
+use std::time::Duration;
+
+use db::{
+    object_id::{BaseImageId, OutputImageId, ProjectId, UploadProfileId},
+    upload_profiles::PostProcessCallback,
+    ImageFormat,
+};
+use diesel::Queryable;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+
+use crate::remote_fetch;
+
+#[derive(Debug, Serialize, Queryable)]
+pub struct CallbackImage {
+    pub id: BaseImageId,
+    pub project_id: ProjectId,
+    pub upload_profile_id: UploadProfileId,
+    pub filename: String,
+    pub width: i32,
+    pub height: i32,
+    pub format: Option<ImageFormat>,
+    pub tags: Vec<String>,
+    pub alt_text: String,
+    pub collection: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CallbackOutput {
+    pub id: OutputImageId,
+    pub format: ImageFormat,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CallbackRequest {
+    pub image: CallbackImage,
+    pub outputs: Vec<CallbackOutput>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct CallbackResponse {
+    #[serde(default = "default_approved")]
+    pub approved: bool,
+    /// Why the image was rejected.
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub alt_text: Option<String>,
+    #[serde(default)]
+    pub collection: Option<String>,
+}
+
+fn default_approved() -> bool {
+    true
+}
+
+impl CallbackResponse {
+    /// The image's error message when the service rejects it.
+    pub fn rejection(&self) -> Option<String> {
+        if self.approved {
+            return None;
+        }
+
+        let reason = self
+            .reason
+            .as_deref()
+            .map(str::trim)
+            .filter(|reason| !reason.is_empty())
+            .unwrap_or("no reason given");
+        Some(format!(
+            "Rejected by the post-processing callback: {reason}"
+        ))
+    }
+}
+
+pub async fn call(
+    callback: &PostProcessCallback,
+    request: &CallbackRequest,
+    allow_private_networks: bool,
+) -> Result<CallbackResponse, eyre::Report> {
+    let url = remote_fetch::parse_url(&callback.url)?;
+    let client = remote_fetch::pinned_client(
+        &url,
+        allow_private_networks,
+        Duration::from_secs(u64::from(callback.timeout_seconds)),
+    )
+    .await?;
+
+    let response = client.post(url).json(request).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        eyre::bail!("The callback responded with status {status}");
+    }
+
+    let response = response.json::<CallbackResponse>().await?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{body_partial_json, method},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn request() -> CallbackRequest {
+        CallbackRequest {
+            image: CallbackImage {
+                id: BaseImageId::new(),
+                project_id: ProjectId::new(),
+                upload_profile_id: UploadProfileId::new(),
+                filename: "dog.jpg".to_string(),
+                width: 100,
+                height: 50,
+                format: Some(ImageFormat::Jpg),
+                tags: Vec::new(),
+                alt_text: String::new(),
+                collection: None,
+            },
+            outputs: Vec::new(),
+        }
+    }
+
+    fn callback(url: String) -> PostProcessCallback {
+        PostProcessCallback {
+            url,
+            timeout_seconds: 1,
+            publish_on_failure: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn approves_with_metadata() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(
+                serde_json::json!({ "image": { "filename": "dog.jpg" } }),
+            ))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "tags": ["reviewed"] })),
+            )
+            .mount(&server)
+            .await;
+
+        let response = call(&callback(server.uri()), &request(), true)
+            .await
+            .unwrap();
+        assert!(response.approved);
+        assert_eq!(response.tags, vec!["reviewed".to_string()]);
+        assert_eq!(response.rejection(), None);
+    }
+
+    #[tokio::test]
+    async fn rejects() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(
+                serde_json::json!({ "approved": false, "reason": "missing license" }),
+            ))
+            .mount(&server)
+            .await;
+
+        let response = call(&callback(server.uri()), &request(), true)
+            .await
+            .unwrap();
+        assert_eq!(
+            response.rejection().as_deref(),
+            Some("Rejected by the post-processing callback: missing license")
+        );
+    }
+
+    #[tokio::test]
+    async fn times_out() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(3)))
+            .mount(&server)
+            .await;
+
+        assert!(call(&callback(server.uri()), &request(), true)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_private_addresses() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        assert!(call(&callback(server.uri()), &request(), false)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn does_not_follow_redirects() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(307).insert_header("location", "http://169.254.169.254/"),
+            )
+            .mount(&server)
+            .await;
+
+        assert!(call(&callback(server.uri()), &request(), true)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,175 @@
+//! Built-in conversion profiles for common uses, so that new users don't have to design their
+//! output formats and sizes from scratch. Installing a template creates an ordinary conversion
+//! profile, which can then be edited like any other.
+
+use pic_store_db::{
+    conversion_profiles::{
+        ConversionFormat, ConversionOutput, ConversionSize, FormatConversionCondition,
+    },
+    ImageFormat,
+};
+
+#[derive(Debug)]
+pub struct ProfileTemplate {
+    pub name: &'static str,
+    pub description: &'static str,
+    build: fn() -> ConversionOutput,
+}
+
+impl ProfileTemplate {
+    pub fn output(&self) -> ConversionOutput {
+        (self.build)()
+    }
+}
+
+pub const TEMPLATES: &[ProfileTemplate] = &[
+    ProfileTemplate {
+        name: "blog",
+        description: "Responsive images for articles, with widths chosen to suit each image",
+        build: blog,
+    },
+    ProfileTemplate {
+        name: "ecommerce",
+        description: "Product photos from thumbnails to zoomable detail views",
+        build: ecommerce,
+    },
+    ProfileTemplate {
+        name: "avatars",
+        description: "Small profile pictures that fit in a square",
+        build: avatars,
+    },
+    ProfileTemplate {
+        name: "og-images",
+        description: "Social media previews at the 1200x630 size that most sites use",
+        build: og_images,
+    },
+];
+
+/// Look up a template by name.
+pub fn find(name: &str) -> Option<&'static ProfileTemplate> {
+    TEMPLATES.iter().find(|t| t.name == name)
+}
+
+fn width(width: u32) -> ConversionSize {
+    ConversionSize {
+        width: Some(width),
+        ..Default::default()
+    }
+}
+
+fn bounded(width: u32, height: u32) -> ConversionSize {
+    ConversionSize {
+        width: Some(width),
+        height: Some(height),
+        preserve_aspect_ratio: None,
+    }
+}
+
+fn modern_formats(quality: f32) -> Vec<ConversionFormat> {
+    vec![
+        ConversionFormat::Avif {
+            quality: Some(quality),
+            condition: None,
+        },
+        ConversionFormat::Webp {
+            quality: Some(quality),
+            condition: None,
+        },
+        ConversionFormat::Jpg {
+            quality: Some(quality),
+            condition: None,
+        },
+    ]
+}
+
+fn blog() -> ConversionOutput {
+    ConversionOutput::Auto {
+        formats: modern_formats(75.0),
+        min_width: 320,
+        max_width: 2048,
+        byte_step: 30_000,
+        max_sizes: Some(8),
+        preset: None,
+        archival: None,
+        animation: None,
+    }
+}
+
+fn ecommerce() -> ConversionOutput {
+    ConversionOutput::Cross {
+        formats: modern_formats(85.0),
+        sizes: [200, 400, 800, 1200, 2000].into_iter().map(width).collect(),
+        preset: None,
+        archival: None,
+        animation: None,
+    }
+}
+
+fn avatars() -> ConversionOutput {
+    ConversionOutput::Cross {
+        formats: vec![
+            ConversionFormat::Webp {
+                quality: Some(80.0),
+                condition: None,
+            },
+            ConversionFormat::Jpg {
+                quality: Some(80.0),
+                condition: None,
+            },
+        ],
+        sizes: [32, 64, 128, 256]
+            .into_iter()
+            .map(|size| bounded(size, size))
+            .collect(),
+        preset: None,
+        archival: None,
+        animation: None,
+    }
+}
+
+fn og_images() -> ConversionOutput {
+    // Social networks don't reliably support AVIF or WebP previews.
+    ConversionOutput::Cross {
+        formats: vec![
+            ConversionFormat::Jpg {
+                quality: Some(85.0),
+                condition: None,
+            },
+            ConversionFormat::Png {
+                // Keep transparency in PNG originals.
+                condition: Some(FormatConversionCondition::Must {
+                    formats: vec![ImageFormat::Png],
+                }),
+            },
+        ],
+        sizes: vec![bounded(1200, 630)],
+        preset: None,
+        archival: None,
+        animation: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_have_unique_names() {
+        for (i, template) in TEMPLATES.iter().enumerate() {
+            assert!(
+                TEMPLATES[i + 1..].iter().all(|t| t.name != template.name),
+                "duplicate template {}",
+                template.name
+            );
+        }
+    }
+
+    #[test]
+    fn find_template() {
+        assert!(matches!(
+            find("blog").map(|t| t.output()),
+            Some(ConversionOutput::Auto { .. })
+        ));
+        assert!(find("nonexistent").is_none());
+    }
+}
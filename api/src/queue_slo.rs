@@ -0,0 +1,396 @@
+//! Service level objectives for the conversion queue. The conversion job records how long each
+//! image waited in the queue, and a periodic task measures the queue depth and the worker lag,
+//! which is how long the oldest waiting image has been waiting. Each measurement is logged as an
+//! event, so it shows up with the other telemetry, and the latest one is available from the admin
+//! API.
+//!
+//! When a measurement crosses one of the configured thresholds, a notification is posted to the
+//! alert webhook, and another is posted when it recovers. Conversions held by a pause are not
+//! counted, since they are waiting on purpose.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use db::{conversion_pauses, PoolExt};
+use pic_store_db as db;
+use serde::Serialize;
+use tracing::{event, Level};
+
+use crate::{shared_state::AppState, Error};
+
+/// The latencies that the percentiles are computed from.
+const LATENCY_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// The most latency samples to keep, so that a burst of conversions can't use much memory.
+const MAX_LATENCY_SAMPLES: usize = 10_000;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Records how long each conversion waited in the queue before it started.
+#[derive(Clone, Debug, Default)]
+pub struct LatencyRecorder {
+    samples: Arc<Mutex<VecDeque<(Instant, Duration)>>>,
+}
+
+impl LatencyRecorder {
+    pub fn record(&self, latency: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() >= MAX_LATENCY_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back((Instant::now(), latency));
+    }
+
+    /// Compute the percentiles of the latencies recorded within the window.
+    fn percentiles(&self, window: Duration) -> Option<LatencyPercentiles> {
+        let mut samples = self.samples.lock().unwrap();
+        while samples
+            .front()
+            .map(|(recorded, _)| recorded.elapsed() > window)
+            .unwrap_or(false)
+        {
+            samples.pop_front();
+        }
+
+        let latencies = samples
+            .iter()
+            .map(|(_, latency)| *latency)
+            .collect::<Vec<_>>();
+        drop(samples);
+        LatencyPercentiles::from_latencies(latencies)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct LatencyPercentiles {
+    samples: usize,
+    p50_seconds: f64,
+    p95_seconds: f64,
+    p99_seconds: f64,
+}
+
+impl LatencyPercentiles {
+    fn from_latencies(mut latencies: Vec<Duration>) -> Option<LatencyPercentiles> {
+        if latencies.is_empty() {
+            return None;
+        }
+
+        latencies.sort();
+        // The nearest-rank percentile.
+        let percentile = |p: f64| {
+            let rank = ((p * latencies.len() as f64).ceil() as usize).max(1);
+            latencies[rank - 1].as_secs_f64()
+        };
+
+        Some(LatencyPercentiles {
+            samples: latencies.len(),
+            p50_seconds: percentile(0.5),
+            p95_seconds: percentile(0.95),
+            p99_seconds: percentile(0.99),
+        })
+    }
+}
+
+/// The thresholds for the queue. Objectives without a threshold are not checked.
+#[derive(Clone, Debug, Default)]
+pub struct SloThresholds {
+    pub max_queue_depth: Option<i64>,
+    pub max_worker_lag: Option<Duration>,
+    pub max_p95_latency: Option<Duration>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SloBreach {
+    objective: &'static str,
+    value: f64,
+    threshold: f64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct QueueMetrics {
+    measured: DateTime<Utc>,
+    queue_depth: i64,
+    worker_lag_seconds: f64,
+    /// Percentiles of the recent queue latencies, or `None` if nothing was converted recently.
+    latency: Option<LatencyPercentiles>,
+    /// The objectives that are currently breached.
+    breaches: Vec<SloBreach>,
+}
+
+impl SloThresholds {
+    fn breaches(
+        &self,
+        queue_depth: i64,
+        worker_lag: Duration,
+        latency: Option<&LatencyPercentiles>,
+    ) -> Vec<SloBreach> {
+        let mut breaches = Vec::new();
+        if let Some(max) = self.max_queue_depth {
+            if queue_depth > max {
+                breaches.push(SloBreach {
+                    objective: "queue_depth",
+                    value: queue_depth as f64,
+                    threshold: max as f64,
+                });
+            }
+        }
+
+        if let Some(max) = self.max_worker_lag {
+            if worker_lag > max {
+                breaches.push(SloBreach {
+                    objective: "worker_lag_seconds",
+                    value: worker_lag.as_secs_f64(),
+                    threshold: max.as_secs_f64(),
+                });
+            }
+        }
+
+        if let (Some(max), Some(latency)) = (self.max_p95_latency, latency) {
+            if latency.p95_seconds > max.as_secs_f64() {
+                breaches.push(SloBreach {
+                    objective: "p95_latency_seconds",
+                    value: latency.p95_seconds,
+                    threshold: max.as_secs_f64(),
+                });
+            }
+        }
+
+        breaches
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct SloNotification<'a> {
+    /// `breached` or `resolved`.
+    event: &'static str,
+    objective: &'static str,
+    value: f64,
+    threshold: f64,
+    measured: DateTime<Utc>,
+    metrics: &'a QueueMetrics,
+}
+
+/// Measures the queue against its objectives, and keeps the latest measurement.
+#[derive(Clone, Debug, Default)]
+pub struct QueueSloMonitor {
+    pub latency: LatencyRecorder,
+    thresholds: SloThresholds,
+    /// Notifications are posted here when an objective is breached or recovers.
+    webhook_url: Option<String>,
+    latest: Arc<Mutex<Option<QueueMetrics>>>,
+}
+
+impl QueueSloMonitor {
+    pub fn new(
+        latency: LatencyRecorder,
+        thresholds: SloThresholds,
+        webhook_url: Option<String>,
+    ) -> Self {
+        QueueSloMonitor {
+            latency,
+            thresholds,
+            webhook_url,
+            latest: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// The most recent measurement.
+    pub fn latest(&self) -> Option<QueueMetrics> {
+        self.latest.lock().unwrap().clone()
+    }
+
+    pub async fn measure(&self, pool: &db::Pool) -> Result<QueueMetrics, Error> {
+        let (queue_depth, oldest) = pool
+            .interact(|conn| conversion_pauses::waiting_conversions(conn).map_err(Error::from))
+            .await?;
+        let measured = Utc::now();
+        let worker_lag = oldest
+            .and_then(|oldest| (measured - oldest).to_std().ok())
+            .unwrap_or_default();
+        let latency = self.latency.percentiles(LATENCY_WINDOW);
+        let breaches = self
+            .thresholds
+            .breaches(queue_depth, worker_lag, latency.as_ref());
+
+        Ok(QueueMetrics {
+            measured,
+            queue_depth,
+            worker_lag_seconds: worker_lag.as_secs_f64(),
+            latency,
+            breaches,
+        })
+    }
+
+    /// Take a measurement, and send notifications for the objectives that were breached or
+    /// recovered since the last one.
+    async fn check(&self, state: &AppState, client: &reqwest::Client) -> Result<(), Error> {
+        let metrics = self.measure(&state.db).await?;
+        let latency = metrics.latency.unwrap_or(LatencyPercentiles {
+            samples: 0,
+            p50_seconds: 0.0,
+            p95_seconds: 0.0,
+            p99_seconds: 0.0,
+        });
+        event!(
+            Level::INFO,
+            queue_depth = metrics.queue_depth,
+            worker_lag_seconds = metrics.worker_lag_seconds,
+            latency_samples = latency.samples,
+            latency_p50_seconds = latency.p50_seconds,
+            latency_p95_seconds = latency.p95_seconds,
+            latency_p99_seconds = latency.p99_seconds,
+            breaches = metrics.breaches.len(),
+            "Conversion queue metrics"
+        );
+
+        let previous = self
+            .latest
+            .lock()
+            .unwrap()
+            .replace(metrics.clone())
+            .map(|previous| previous.breaches)
+            .unwrap_or_default();
+
+        for (event_name, changed) in breach_changes(&previous, &metrics.breaches) {
+            event!(
+                Level::WARN,
+                objective = changed.objective,
+                value = changed.value,
+                threshold = changed.threshold,
+                "Conversion queue objective {event_name}"
+            );
+            let Some(url) = self.webhook_url.as_deref() else {
+                continue;
+            };
+
+            let notification = SloNotification {
+                event: event_name,
+                objective: changed.objective,
+                value: changed.value,
+                threshold: changed.threshold,
+                measured: metrics.measured,
+                metrics: &metrics,
+            };
+            let result = client
+                .post(url)
+                .json(&notification)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = result {
+                event!(Level::ERROR, error = %e, objective = changed.objective, "Failed to send queue objective notification");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The objectives that were newly breached or resolved between two measurements. A breach that
+/// continues doesn't produce another notification.
+fn breach_changes<'a>(
+    previous: &'a [SloBreach],
+    current: &'a [SloBreach],
+) -> Vec<(&'static str, &'a SloBreach)> {
+    let breached = current
+        .iter()
+        .filter(|breach| !previous.iter().any(|p| p.objective == breach.objective))
+        .map(|breach| ("breached", breach));
+    let resolved = previous
+        .iter()
+        .filter(|breach| !current.iter().any(|c| c.objective == breach.objective))
+        .map(|breach| ("resolved", breach));
+    breached.chain(resolved).collect()
+}
+
+/// Check the queue objectives periodically.
+pub fn start_monitor_task(state: AppState, interval: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::task::spawn(async move {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = state.queue_slo.check(&state, &client).await {
+                event!(Level::ERROR, error = ?e, "Failed to check the conversion queue objectives");
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles() {
+        assert_eq!(LatencyPercentiles::from_latencies(Vec::new()), None);
+
+        let latencies = (1..=100).rev().map(Duration::from_secs).collect::<Vec<_>>();
+        let p = LatencyPercentiles::from_latencies(latencies).unwrap();
+        assert_eq!(p.samples, 100);
+        assert_eq!(p.p50_seconds, 50.0);
+        assert_eq!(p.p95_seconds, 95.0);
+        assert_eq!(p.p99_seconds, 99.0);
+
+        let p = LatencyPercentiles::from_latencies(vec![Duration::from_secs(3)]).unwrap();
+        assert_eq!(p.p50_seconds, 3.0);
+        assert_eq!(p.p99_seconds, 3.0);
+    }
+
+    #[test]
+    fn recorder_window() {
+        let recorder = LatencyRecorder::default();
+        recorder.record(Duration::from_secs(2));
+        assert_eq!(recorder.percentiles(LATENCY_WINDOW).unwrap().samples, 1);
+        assert_eq!(recorder.percentiles(Duration::ZERO), None);
+    }
+
+    #[test]
+    fn breaches() {
+        let thresholds = SloThresholds {
+            max_queue_depth: Some(100),
+            max_worker_lag: Some(Duration::from_secs(60)),
+            max_p95_latency: None,
+        };
+        assert!(thresholds
+            .breaches(100, Duration::from_secs(60), None)
+            .is_empty());
+
+        let breaches = thresholds.breaches(101, Duration::from_secs(120), None);
+        let objectives = breaches
+            .iter()
+            .map(|breach| breach.objective)
+            .collect::<Vec<_>>();
+        assert_eq!(objectives, vec!["queue_depth", "worker_lag_seconds"]);
+    }
+
+    #[test]
+    fn changes() {
+        let breach = |objective| SloBreach {
+            objective,
+            value: 2.0,
+            threshold: 1.0,
+        };
+        let previous = vec![breach("queue_depth"), breach("worker_lag_seconds")];
+        let current = vec![breach("worker_lag_seconds"), breach("p95_latency_seconds")];
+        let changes = breach_changes(&previous, &current)
+            .into_iter()
+            .map(|(event, breach)| (event, breach.objective))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                ("breached", "p95_latency_seconds"),
+                ("resolved", "queue_depth"),
+            ]
+        );
+    }
+}
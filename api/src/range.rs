@@ -0,0 +1,367 @@
+//! HTTP Range requests, so that clients can resume downloads of large files or fetch only part
+//! of them. Only a single range is supported. Requests for multiple ranges get the whole file,
+//! which the spec allows.
+
+use std::ops::Range;
+
+use axum::{
+    body::{Bytes, StreamBody},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use pic_store_storage as storage;
+
+/// The part of a file that a request asked for.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    Full,
+    Partial(Range<usize>),
+    Unsatisfiable,
+}
+
+impl RangeRequest {
+    /// Parse a `Range` header for a file of the given size.
+    pub fn parse(header: &str, size: usize) -> RangeRequest {
+        let Some(spec) = header.trim().strip_prefix("bytes=") else {
+            return RangeRequest::Full;
+        };
+
+        if spec.contains(',') {
+            return RangeRequest::Full;
+        }
+
+        let Some((start, end)) = spec.trim().split_once('-') else {
+            return RangeRequest::Full;
+        };
+
+        let start = start.trim();
+        let end = end.trim();
+        let range = if start.is_empty() {
+            // A suffix range, for the last N bytes.
+            match end.parse::<usize>() {
+                Ok(0) => return RangeRequest::Unsatisfiable,
+                Ok(len) => size.saturating_sub(len)..size,
+                Err(_) => return RangeRequest::Full,
+            }
+        } else {
+            let Ok(start) = start.parse::<usize>() else {
+                return RangeRequest::Full;
+            };
+
+            let end = if end.is_empty() {
+                size
+            } else {
+                match end.parse::<usize>() {
+                    Ok(end) if end >= start => end.saturating_add(1).min(size),
+                    _ => return RangeRequest::Full,
+                }
+            };
+
+            start..end
+        };
+
+        if range.start >= size {
+            RangeRequest::Unsatisfiable
+        } else {
+            RangeRequest::Partial(range)
+        }
+    }
+}
+
+/// Get the `Range` header from a request. The range is ignored if the request has an `If-Range`
+/// header that doesn't match the current version of the file.
+pub fn requested_range<'a>(
+    headers: &'a HeaderMap,
+    etag: Option<&str>,
+    last_modified: Option<DateTime<Utc>>,
+) -> Option<&'a str> {
+    let range = headers.get(header::RANGE)?.to_str().ok()?;
+
+    let Some(if_range) = headers.get(header::IF_RANGE) else {
+        return Some(range);
+    };
+    let if_range = if_range.to_str().ok()?.trim();
+
+    let matches = if if_range.starts_with('"') || if_range.starts_with("W/") {
+        // If-Range requires the strong comparison, so weak ETags never match.
+        etag.map(|etag| if_range.trim_matches('"') == etag && !if_range.starts_with("W/"))
+            .unwrap_or(false)
+    } else {
+        let date = DateTime::parse_from_rfc2822(if_range).ok();
+        match (date, last_modified) {
+            (Some(date), Some(last_modified)) => date.timestamp() == last_modified.timestamp(),
+            _ => false,
+        }
+    };
+
+    matches.then_some(range)
+}
+
+/// A response body that may be only part of a file.
+pub enum RangedBody {
+    Stream(storage::GetResult),
+    Bytes(Bytes),
+    Partial {
+        bytes: Bytes,
+        range: Range<usize>,
+        size: usize,
+    },
+    /// A range of a file in storage, streamed so that large ranges aren't read into memory.
+    PartialStream {
+        result: storage::GetResult,
+        range: Range<usize>,
+        size: usize,
+    },
+    Unsatisfiable {
+        size: usize,
+    },
+}
+
+impl RangedBody {
+    /// Read a file from storage, or the part of it in the requested range.
+    pub async fn from_storage(
+        operator: &storage::Operator,
+        location: &str,
+        range: Option<&str>,
+    ) -> Result<RangedBody, storage::Error> {
+        let Some(range) = range else {
+            return operator.get(location).await.map(RangedBody::Stream);
+        };
+
+        let size = operator.head(location).await?.size;
+        match RangeRequest::parse(range, size) {
+            RangeRequest::Full => operator.get(location).await.map(RangedBody::Stream),
+            RangeRequest::Partial(range) => {
+                let result = operator.get_range_stream(location, range.clone()).await?;
+                Ok(RangedBody::PartialStream {
+                    result,
+                    range,
+                    size,
+                })
+            }
+            RangeRequest::Unsatisfiable => Ok(RangedBody::Unsatisfiable { size }),
+        }
+    }
+
+    /// Return the part of an in-memory file in the requested range.
+    pub fn from_bytes(bytes: Bytes, range: Option<&str>) -> RangedBody {
+        let size = bytes.len();
+        let request = range
+            .map(|range| RangeRequest::parse(range, size))
+            .unwrap_or(RangeRequest::Full);
+        match request {
+            RangeRequest::Full => RangedBody::Bytes(bytes),
+            RangeRequest::Partial(range) => RangedBody::Partial {
+                bytes: bytes.slice(range.clone()),
+                range,
+                size,
+            },
+            RangeRequest::Unsatisfiable => RangedBody::Unsatisfiable { size },
+        }
+    }
+
+    /// The number of bytes in the body, if it is known before it is sent.
+    pub fn content_length(&self) -> Option<usize> {
+        match self {
+            RangedBody::Stream(_) => None,
+            RangedBody::Bytes(bytes) => Some(bytes.len()),
+            RangedBody::Partial { bytes, .. } => Some(bytes.len()),
+            RangedBody::PartialStream { range, .. } => Some(range.len()),
+            RangedBody::Unsatisfiable { .. } => Some(0),
+        }
+    }
+
+    pub fn into_response(self, content_type: &'static str) -> Response {
+        let accept_ranges = (header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        let content_type = (header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+        match self {
+            RangedBody::Stream(result) => (
+                StatusCode::OK,
+                [accept_ranges, content_type],
+                StreamBody::new(result.into_stream()),
+            )
+                .into_response(),
+            RangedBody::Bytes(bytes) => {
+                (StatusCode::OK, [accept_ranges, content_type], bytes).into_response()
+            }
+            RangedBody::Partial { bytes, range, size } => (
+                StatusCode::PARTIAL_CONTENT,
+                [accept_ranges, content_type, content_range(&range, size)],
+                bytes,
+            )
+                .into_response(),
+            RangedBody::PartialStream {
+                result,
+                range,
+                size,
+            } => (
+                StatusCode::PARTIAL_CONTENT,
+                [
+                    accept_ranges,
+                    content_type,
+                    content_range(&range, size),
+                    (header::CONTENT_LENGTH, HeaderValue::from(range.len())),
+                ],
+                StreamBody::new(result.into_stream()),
+            )
+                .into_response(),
+            RangedBody::Unsatisfiable { size } => (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [
+                    accept_ranges,
+                    (
+                        header::CONTENT_RANGE,
+                        HeaderValue::from_str(&format!("bytes */{size}")).unwrap(),
+                    ),
+                ],
+            )
+                .into_response(),
+        }
+    }
+}
+
+fn content_range(range: &Range<usize>, size: usize) -> (header::HeaderName, HeaderValue) {
+    let value = format!("bytes {}-{}/{size}", range.start, range.end - 1);
+    (
+        header::CONTENT_RANGE,
+        HeaderValue::from_str(&value).unwrap(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::HttpBody;
+
+    use super::*;
+
+    #[test]
+    fn parse_ranges() {
+        assert_eq!(
+            RangeRequest::parse("bytes=0-99", 1000),
+            RangeRequest::Partial(0..100)
+        );
+        assert_eq!(
+            RangeRequest::parse("bytes=900-", 1000),
+            RangeRequest::Partial(900..1000)
+        );
+        assert_eq!(
+            RangeRequest::parse("bytes=-100", 1000),
+            RangeRequest::Partial(900..1000)
+        );
+        assert_eq!(
+            RangeRequest::parse("bytes=900-2000", 1000),
+            RangeRequest::Partial(900..1000)
+        );
+        assert_eq!(
+            RangeRequest::parse("bytes=-2000", 1000),
+            RangeRequest::Partial(0..1000)
+        );
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(
+            RangeRequest::parse("bytes=1000-", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(
+            RangeRequest::parse("bytes=-0", 1000),
+            RangeRequest::Unsatisfiable
+        );
+    }
+
+    #[test]
+    fn ignored_ranges() {
+        assert_eq!(RangeRequest::parse("items=0-10", 1000), RangeRequest::Full);
+        assert_eq!(
+            RangeRequest::parse("bytes=0-10,20-30", 1000),
+            RangeRequest::Full
+        );
+        assert_eq!(RangeRequest::parse("bytes=50-10", 1000), RangeRequest::Full);
+        assert_eq!(RangeRequest::parse("bytes=a-b", 1000), RangeRequest::Full);
+    }
+
+    #[test]
+    fn if_range() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, HeaderValue::from_static("bytes=0-10"));
+        assert_eq!(requested_range(&headers, None, None), Some("bytes=0-10"));
+
+        let last_modified = DateTime::parse_from_rfc2822("Tue, 13 Oct 2026 08:00:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        headers.insert(header::IF_RANGE, HeaderValue::from_static("\"abc\""));
+        assert_eq!(
+            requested_range(&headers, Some("abc"), Some(last_modified)),
+            Some("bytes=0-10")
+        );
+        assert_eq!(
+            requested_range(&headers, Some("def"), Some(last_modified)),
+            None
+        );
+
+        headers.insert(header::IF_RANGE, HeaderValue::from_static("W/\"abc\""));
+        assert_eq!(requested_range(&headers, Some("abc"), None), None);
+
+        headers.insert(
+            header::IF_RANGE,
+            HeaderValue::from_static("Tue, 13 Oct 2026 08:00:00 GMT"),
+        );
+        assert_eq!(
+            requested_range(&headers, None, Some(last_modified)),
+            Some("bytes=0-10")
+        );
+        assert_eq!(requested_range(&headers, None, None), None);
+    }
+
+    #[test]
+    fn partial_bytes() {
+        let response = RangedBody::from_bytes(Bytes::from_static(b"0123456789"), Some("bytes=2-4"))
+            .into_response("image/png");
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers().get(header::CONTENT_RANGE).unwrap(),
+            "bytes 2-4/10"
+        );
+    }
+
+    #[tokio::test]
+    async fn open_ended_range_streams_from_storage() {
+        let dir = temp_dir::TempDir::new().unwrap();
+        let operator = storage::Provider::Local
+            .create_operator(dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+
+        let size = 32 * 1024 * 1024;
+        let data = (0..size).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        operator
+            .put("large.bin", Bytes::from(data.clone()))
+            .await
+            .unwrap();
+
+        let body = RangedBody::from_storage(&operator, "large.bin", Some("bytes=0-"))
+            .await
+            .unwrap();
+        assert!(matches!(body, RangedBody::PartialStream { .. }));
+        assert_eq!(body.content_length(), Some(size));
+
+        let response = body.into_response("application/octet-stream");
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers().get(header::CONTENT_RANGE).unwrap(),
+            &format!("bytes 0-{}/{size}", size - 1)
+        );
+
+        // The body arrives in chunks instead of as a single buffer of the whole file.
+        let mut body = response.into_body();
+        let first = body.data().await.unwrap().unwrap();
+        assert!(first.len() < size);
+
+        let rest = hyper::body::to_bytes(body).await.unwrap();
+        assert_eq!(first.len() + rest.len(), size);
+        assert_eq!(&first[..], &data[..first.len()]);
+        assert_eq!(&rest[..], &data[first.len()..]);
+    }
+}
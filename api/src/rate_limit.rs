@@ -0,0 +1,358 @@
+//! Rate limits for each class of route, so that heavy traffic of one kind can't use up the budget
+//! for another. A burst of image transforms shouldn't keep a team from managing its metadata.
+//!
+//! Each request is placed in a class based on its route, and each class has its own token bucket
+//! for every team. Unauthenticated requests, such as abuse reports and public serve requests, are
+//! limited per client address instead. A bucket holds up to `burst` requests and refills at the
+//! class's rate, so short bursts are allowed as long as the average rate stays under the limit.
+//! Classes without a limit are not limited.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, HeaderName, HeaderValue, Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use pic_store_db::object_id::TeamId;
+
+use crate::{auth::UserInfo, shared_state::AppState, Error};
+
+/// When there are this many buckets, the full ones are removed, since they behave the same as a
+/// new bucket.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RateClass {
+    /// Uploads of original images.
+    Upload,
+    /// Routes that render images, such as the serve routes and conversion previews.
+    Transform,
+    /// Abuse reports and share link passwords, which don't need authentication.
+    Report,
+    /// Other reads, such as listing images or reading settings.
+    Read,
+    /// Other changes.
+    Write,
+}
+
+impl RateClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RateClass::Upload => "upload",
+            RateClass::Transform => "transform",
+            RateClass::Report => "report",
+            RateClass::Read => "read",
+            RateClass::Write => "write",
+        }
+    }
+
+    /// Find the class of a request from its route.
+    pub fn classify(method: &Method, path: &str) -> RateClass {
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+        match (method, segments.as_slice()) {
+            (_, ["serve", ..] | ["imgproxy", ..]) => RateClass::Transform,
+            (&Method::POST, [.., "conversion_profiles", _, "preview" | "rerender"])
+            | (&Method::POST, ["api", "images", _, "reconvert"]) => RateClass::Transform,
+            (&Method::POST, ["api", "abuse_reports"] | ["share", _]) => RateClass::Report,
+            (&Method::POST, ["api", "images"] | ["api", "images", "from_url"])
+            | (&Method::POST | &Method::PUT, ["api", "images", _, "upload", ..])
+            | (&Method::POST, ["api", "projects", _, "upload_profiles", _, "ingest"]) => {
+                RateClass::Upload
+            }
+            (&Method::GET | &Method::HEAD | &Method::OPTIONS, _) => RateClass::Read,
+            _ => RateClass::Write,
+        }
+    }
+}
+
+/// A limit of `per_minute` requests on average, with bursts of up to `burst` requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    pub per_minute: u32,
+    pub burst: u32,
+}
+
+impl FromStr for RateLimit {
+    type Err = String;
+
+    /// Parse a limit like `600`, or `600:100` to set the burst size too. The burst size defaults
+    /// to the per-minute rate.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|value| *value > 0)
+                .ok_or_else(|| format!("`{value}` is not a positive number"))
+        };
+
+        let (per_minute, burst) = match s.split_once(':') {
+            Some((per_minute, burst)) => (parse(per_minute)?, parse(burst)?),
+            None => {
+                let per_minute = parse(s)?;
+                (per_minute, per_minute)
+            }
+        };
+
+        Ok(RateLimit { per_minute, burst })
+    }
+}
+
+/// The limit for each class. Classes set to `None` are not limited.
+#[derive(Clone, Debug, Default)]
+pub struct RateLimits {
+    pub upload: Option<RateLimit>,
+    pub transform: Option<RateLimit>,
+    pub report: Option<RateLimit>,
+    pub read: Option<RateLimit>,
+    pub write: Option<RateLimit>,
+}
+
+impl RateLimits {
+    fn get(&self, class: RateClass) -> Option<RateLimit> {
+        match class {
+            RateClass::Upload => self.upload,
+            RateClass::Transform => self.transform,
+            RateClass::Report => self.report,
+            RateClass::Read => self.read,
+            RateClass::Write => self.write,
+        }
+    }
+}
+
+/// Who a bucket belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum RateKey {
+    Team(TeamId),
+    Address(IpAddr),
+    /// Unauthenticated requests whose address isn't known share a bucket.
+    Unknown,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Add the tokens for the time since the bucket was last updated.
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * limit.per_minute as f64 / 60.0).min(limit.burst as f64);
+        self.updated = now;
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    /// A header set by a trusted proxy with the client's address, for unauthenticated requests.
+    client_ip_header: Option<HeaderName>,
+    buckets: Mutex<HashMap<(RateClass, RateKey), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits, client_ip_header: Option<HeaderName>) -> Self {
+        Self {
+            limits,
+            client_ip_header,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a token from the bucket. When the bucket is empty, this returns how long it will be
+    /// until the next token is added.
+    fn take(&self, class: RateClass, key: RateKey, now: Instant) -> Result<(), Duration> {
+        let Some(limit) = self.limits.get(class) else {
+            return Ok(());
+        };
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|(class, _), bucket| {
+                let Some(limit) = self.limits.get(*class) else {
+                    return false;
+                };
+                bucket.refill(limit, now);
+                bucket.tokens < limit.burst as f64
+            });
+        }
+
+        let bucket = buckets.entry((class, key)).or_insert(Bucket {
+            tokens: limit.burst as f64,
+            updated: now,
+        });
+        bucket.refill(limit, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) * 60.0 / limit.per_minute as f64;
+            Err(Duration::from_secs_f64(wait))
+        }
+    }
+
+    fn client_address<B>(&self, req: &Request<B>) -> Option<IpAddr> {
+        match &self.client_ip_header {
+            // Proxies append the address that they saw, so the last one is the one added by the
+            // trusted proxy.
+            Some(header) => req
+                .headers()
+                .get(header)?
+                .to_str()
+                .ok()?
+                .rsplit(',')
+                .next()?
+                .trim()
+                .parse()
+                .ok(),
+            None => req
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|info| info.0.ip()),
+        }
+    }
+
+    fn key<B>(&self, req: &Request<B>) -> RateKey {
+        if let Some(user) = req.extensions().get::<UserInfo>() {
+            return RateKey::Team(user.team_id);
+        }
+
+        self.client_address(req)
+            .map(RateKey::Address)
+            .unwrap_or(RateKey::Unknown)
+    }
+}
+
+/// Reject requests from teams or clients that are over the limit for the route's class.
+pub async fn enforce_rate_limit<B>(
+    State(state): State<AppState>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let class = RateClass::classify(req.method(), req.uri().path());
+    let key = state.rate_limiter.key(&req);
+
+    if let Err(wait) = state.rate_limiter.take(class, key, Instant::now()) {
+        let mut response = Error::RateLimited(class.as_str()).into_response();
+        let seconds = wait.as_secs_f64().ceil().max(1.0) as u64;
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        return response;
+    }
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes() {
+        let classify = |method: Method, path: &str| RateClass::classify(&method, path);
+
+        assert_eq!(classify(Method::GET, "/serve/bimabc"), RateClass::Transform);
+        assert_eq!(
+            classify(Method::GET, "/imgproxy/sig/rs:fit:100/plain/a"),
+            RateClass::Transform
+        );
+        assert_eq!(
+            classify(
+                Method::POST,
+                "/api/projects/prjabc/conversion_profiles/cprabc/preview"
+            ),
+            RateClass::Transform
+        );
+        assert_eq!(classify(Method::POST, "/api/images"), RateClass::Upload);
+        assert_eq!(
+            classify(Method::PUT, "/api/images/bimabc/upload/chunked/abc/1"),
+            RateClass::Upload
+        );
+        assert_eq!(
+            classify(Method::GET, "/api/images/bimabc/upload/chunked/abc"),
+            RateClass::Read
+        );
+        assert_eq!(
+            classify(Method::POST, "/api/abuse_reports"),
+            RateClass::Report
+        );
+        assert_eq!(classify(Method::GET, "/api/abuse_reports"), RateClass::Read);
+        assert_eq!(
+            classify(Method::POST, "/share/shlabc.def"),
+            RateClass::Report
+        );
+        assert_eq!(classify(Method::GET, "/share/shlabc.def"), RateClass::Read);
+        assert_eq!(classify(Method::GET, "/api/images/bimabc"), RateClass::Read);
+        assert_eq!(
+            classify(Method::PUT, "/api/images/bimabc"),
+            RateClass::Write
+        );
+    }
+
+    #[test]
+    fn parse_limits() {
+        assert_eq!(
+            "600".parse::<RateLimit>().unwrap(),
+            RateLimit {
+                per_minute: 600,
+                burst: 600
+            }
+        );
+        assert_eq!(
+            "60:10".parse::<RateLimit>().unwrap(),
+            RateLimit {
+                per_minute: 60,
+                burst: 10
+            }
+        );
+        assert!("0".parse::<RateLimit>().is_err());
+        assert!("60:".parse::<RateLimit>().is_err());
+        assert!("fast".parse::<RateLimit>().is_err());
+    }
+
+    #[test]
+    fn token_bucket() {
+        let limiter = RateLimiter::new(
+            RateLimits {
+                upload: Some(RateLimit {
+                    per_minute: 60,
+                    burst: 3,
+                }),
+                ..Default::default()
+            },
+            None,
+        );
+        let team = RateKey::Team(TeamId::new());
+        let now = Instant::now();
+
+        // The burst is allowed, and then requests have to wait for the bucket to refill.
+        for _ in 0..3 {
+            assert!(limiter.take(RateClass::Upload, team, now).is_ok());
+        }
+        let wait = limiter.take(RateClass::Upload, team, now).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(1));
+        assert!(limiter
+            .take(RateClass::Upload, team, now + Duration::from_secs(1))
+            .is_ok());
+
+        // Other classes and teams have their own budgets.
+        assert!(limiter.take(RateClass::Read, team, now).is_ok());
+        assert!(limiter
+            .take(RateClass::Upload, RateKey::Team(TeamId::new()), now)
+            .is_ok());
+    }
+}
@@ -0,0 +1,318 @@
+//! Save complete request and response pairs to files, to help debug client issues that are hard
+//! to reproduce. This is a development tool and is never enabled in production.
+//!
+//! A fraction of all requests can be recorded, and requests with the `X-Record-Request` header
+//! are always recorded. Each exchange is written as its own JSON file in the recording directory.
+//! Secret headers are replaced, and other secrets in the URL, headers, and bodies are redacted.
+//! JSON bodies are parsed so that credential fields are redacted wherever they appear.
+//! Bodies are only saved when they are text and reasonably small, so image uploads and
+//! downloads are recorded without their contents.
+
+use std::{path::PathBuf, time::Instant};
+
+use axum::{
+    body::{self, Body, Bytes, Full, HttpBody},
+    extract::State,
+    http::{header, HeaderMap, HeaderName, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use serde::Serialize;
+use tracing::{event, Level};
+use uuid::Uuid;
+
+use crate::{
+    access_token::ACCESS_TOKEN_HEADER,
+    redact::{redact_json, redact_secrets, REDACTED},
+    shared_state::AppState,
+};
+
+pub const RECORD_HEADER: &str = "x-record-request";
+
+/// Larger bodies are not recorded.
+const MAX_RECORDED_BODY: u64 = 64 * 1024;
+
+/// Headers whose values are always secret.
+const SECRET_HEADERS: &[HeaderName] = &[
+    header::AUTHORIZATION,
+    header::PROXY_AUTHORIZATION,
+    header::COOKIE,
+    header::SET_COOKIE,
+];
+
+#[derive(Clone, Debug)]
+pub struct RequestRecorder {
+    pub dir: PathBuf,
+    /// The fraction of requests to record, from 0.0 to 1.0.
+    pub sample_rate: f64,
+}
+
+impl RequestRecorder {
+    fn should_record(&self, headers: &HeaderMap) -> bool {
+        if headers.contains_key(RECORD_HEADER) {
+            return true;
+        }
+
+        if self.sample_rate <= 0.0 {
+            return false;
+        }
+
+        let (random, _) = Uuid::new_v4().as_u64_pair();
+        (random as f64 / u64::MAX as f64) < self.sample_rate
+    }
+}
+
+#[derive(Serialize)]
+struct RecordedRequest {
+    method: String,
+    uri: String,
+    headers: Vec<(String, String)>,
+    body: RecordedBody,
+}
+
+#[derive(Serialize)]
+struct RecordedResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: RecordedBody,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum RecordedBody {
+    Empty,
+    Text { text: String },
+    Omitted { content_length: Option<u64> },
+}
+
+#[derive(Serialize)]
+struct RecordedExchange {
+    id: Uuid,
+    recorded_at: chrono::DateTime<Utc>,
+    duration_ms: u128,
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+fn redact_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SECRET_HEADERS.contains(name) || name.as_str() == ACCESS_TOKEN_HEADER {
+                REDACTED.to_string()
+            } else {
+                redact_secrets(&String::from_utf8_lossy(value.as_bytes())).into_owned()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+/// Check if a body should be read into memory so that it can be recorded. Bodies without a
+/// known length are streamed, and are never recorded.
+fn recordable_body(headers: &HeaderMap, length: Option<u64>) -> bool {
+    let is_text = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            let mime = value.split(';').next().unwrap_or_default().trim();
+            mime.starts_with("text/")
+                || mime.ends_with("json")
+                || mime == "application/x-www-form-urlencoded"
+        })
+        .unwrap_or(false);
+
+    is_text && length.map(|len| len <= MAX_RECORDED_BODY).unwrap_or(false)
+}
+
+fn text_body(bytes: &Bytes) -> RecordedBody {
+    if bytes.is_empty() {
+        RecordedBody::Empty
+    } else if let Ok(mut value) = serde_json::from_slice::<serde_json::Value>(bytes) {
+        redact_json(&mut value);
+        RecordedBody::Text {
+            text: value.to_string(),
+        }
+    } else {
+        RecordedBody::Text {
+            text: redact_secrets(&String::from_utf8_lossy(bytes)).into_owned(),
+        }
+    }
+}
+
+/// Record the request and its response, if recording is enabled and the request is chosen.
+pub async fn record_exchange(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let Some(recorder) = state.request_recorder.as_ref() else {
+        return next.run(req).await;
+    };
+    if !recorder.should_record(req.headers()) {
+        return next.run(req).await;
+    }
+
+    let start = Instant::now();
+    let (parts, req_body) = req.into_parts();
+    let length = req_body.size_hint().exact();
+    let (req_body, recorded_req_body) = if recordable_body(&parts.headers, length) {
+        match hyper::body::to_bytes(req_body).await {
+            Ok(bytes) => (Body::from(bytes.clone()), text_body(&bytes)),
+            Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+        }
+    } else {
+        (
+            req_body,
+            RecordedBody::Omitted {
+                content_length: length,
+            },
+        )
+    };
+
+    let request = RecordedRequest {
+        method: parts.method.to_string(),
+        uri: redact_secrets(&parts.uri.to_string()).into_owned(),
+        headers: redact_headers(&parts.headers),
+        body: recorded_req_body,
+    };
+
+    let response = next.run(Request::from_parts(parts, req_body)).await;
+
+    let (parts, res_body) = response.into_parts();
+    let length = res_body.size_hint().exact();
+    let (res_body, recorded_res_body) = if recordable_body(&parts.headers, length) {
+        match hyper::body::to_bytes(res_body).await {
+            Ok(bytes) => (body::boxed(Full::from(bytes.clone())), text_body(&bytes)),
+            Err(e) => {
+                event!(Level::WARN, error = %e, "Failed to read response body for recording");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+    } else {
+        (
+            res_body,
+            RecordedBody::Omitted {
+                content_length: length,
+            },
+        )
+    };
+
+    let exchange = RecordedExchange {
+        id: Uuid::new_v4(),
+        recorded_at: Utc::now(),
+        duration_ms: start.elapsed().as_millis(),
+        request,
+        response: RecordedResponse {
+            status: parts.status.as_u16(),
+            headers: redact_headers(&parts.headers),
+            body: recorded_res_body,
+        },
+    };
+
+    let path = recorder.dir.join(format!(
+        "{}-{}.json",
+        exchange.recorded_at.format("%Y%m%dT%H%M%S%.3fZ"),
+        exchange.id
+    ));
+    tokio::task::spawn(async move {
+        let result = match serde_json::to_vec_pretty(&exchange) {
+            Ok(json) => tokio::fs::write(&path, json)
+                .await
+                .map_err(eyre::Report::new),
+            Err(e) => Err(eyre::Report::new(e)),
+        };
+        if let Err(e) = result {
+            event!(Level::ERROR, error = ?e, path = %path.display(), "Failed to save recorded request");
+        }
+    });
+
+    Response::from_parts(parts, res_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn headers(values: &[(HeaderName, &'static str)]) -> HeaderMap {
+        values
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn secret_headers_are_redacted() {
+        let redacted = redact_headers(&headers(&[
+            (header::AUTHORIZATION, "Bearer abc"),
+            (header::COOKIE, "sid=abc"),
+            (HeaderName::from_static(ACCESS_TOKEN_HEADER), "pat123"),
+            (header::CONTENT_TYPE, "application/json"),
+        ]));
+        assert_eq!(
+            redacted,
+            vec![
+                ("authorization".to_string(), REDACTED.to_string()),
+                ("cookie".to_string(), REDACTED.to_string()),
+                ("x-access-token".to_string(), REDACTED.to_string()),
+                ("content-type".to_string(), "application/json".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn recordable_bodies() {
+        let json = headers(&[(header::CONTENT_TYPE, "application/json; charset=utf-8")]);
+        assert!(recordable_body(&json, Some(100)));
+        // Too large
+        assert!(!recordable_body(&json, Some(1_000_000)));
+        // Streaming bodies have no length
+        assert!(!recordable_body(&json, None));
+        // Images are never recorded
+        assert!(!recordable_body(
+            &headers(&[(header::CONTENT_TYPE, "image/png")]),
+            Some(100)
+        ));
+    }
+
+    #[test]
+    fn json_bodies_are_redacted() {
+        let body = Bytes::from_static(
+            br#"{"name":"s3","provider":{"type":"s3","access_key_id":"AKIA1","secret_key":"abc"}}"#,
+        );
+        let RecordedBody::Text { text } = text_body(&body) else {
+            panic!("expected a text body");
+        };
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&text).unwrap(),
+            serde_json::json!({
+                "name": "s3",
+                "provider": { "type": "s3", "access_key_id": "AKIA1", "secret_key": REDACTED },
+            })
+        );
+
+        let RecordedBody::Text { text } = text_body(&Bytes::from_static(b"not json")) else {
+            panic!("expected a text body");
+        };
+        assert_eq!(text, "not json");
+    }
+
+    #[test]
+    fn sampling() {
+        let never = RequestRecorder {
+            dir: PathBuf::new(),
+            sample_rate: 0.0,
+        };
+        assert!(!never.should_record(&HeaderMap::new()));
+        assert!(never.should_record(&headers(&[(HeaderName::from_static(RECORD_HEADER), "1")])));
+
+        let always = RequestRecorder {
+            dir: PathBuf::new(),
+            sample_rate: 1.0,
+        };
+        assert!(always.should_record(&HeaderMap::new()));
+    }
+}
@@ -0,0 +1,232 @@
+//! Scrub secrets from text before it is logged, sent to the trace exporter, or returned in an
+//! error. This covers API keys, project access tokens, and URL signatures, whether they appear in
+//! a query string, an `Authorization` header value, or on their own in an error message. Storage
+//! and CDN credentials are redacted when they appear as JSON fields.
+
+use std::borrow::Cow;
+
+use axum::http::{Request, Uri};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tower_http::trace::MakeSpan;
+use tracing::Span;
+
+use crate::auth::API_KEY_PREFIX;
+
+pub const REDACTED: &str = "[redacted]";
+
+/// Query string parameters whose values are always secret.
+const SECRET_QUERY_PARAMS: &[&str] = &["api_key", "access_token", "signature"];
+
+/// JSON fields whose values are always secret, such as storage location and CDN credentials.
+pub const SECRET_JSON_FIELDS: &[&str] = &[
+    "secret_key",
+    "application_key",
+    "connection_string",
+    "password",
+    "private_key",
+    "service_account_key",
+    "api_token",
+];
+
+static SECRET_PATTERNS: Lazy<Vec<Regex>> = Lazy::new(|| {
+    vec![
+        // API keys
+        Regex::new(&format!(
+            r"{}\.[A-Za-z0-9_-]+\.[A-Za-z0-9_-]+",
+            regex::escape(API_KEY_PREFIX)
+        ))
+        .unwrap(),
+        // Project access tokens
+        Regex::new(r"pat[A-Za-z0-9_-]{22}\.[A-Za-z0-9_-]+").unwrap(),
+        // Bearer tokens of any kind
+        Regex::new(r"(?i)(bearer\s+)\S+").unwrap(),
+        // Credentials in JSON text
+        Regex::new(&format!(
+            r#"("(?:{})"\s*:\s*")(?:[^"\\]|\\.)*"#,
+            SECRET_JSON_FIELDS.join("|")
+        ))
+        .unwrap(),
+    ]
+});
+
+static SECRET_QUERY: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(&format!(
+        r"(?i)([?&](?:{})=)[^&#\s]*",
+        SECRET_QUERY_PARAMS.join("|")
+    ))
+    .unwrap()
+});
+
+/// Replace any secrets in the text.
+pub fn redact_secrets(text: &str) -> Cow<'_, str> {
+    let query_replacement = format!("${{1}}{REDACTED}");
+    let mut output = SECRET_QUERY.replace_all(text, query_replacement.as_str());
+
+    for pattern in SECRET_PATTERNS.iter() {
+        if pattern.is_match(&output) {
+            let replacement = if pattern.captures_len() > 1 {
+                format!("${{1}}{REDACTED}")
+            } else {
+                REDACTED.to_string()
+            };
+            output = Cow::Owned(
+                pattern
+                    .replace_all(&output, replacement.as_str())
+                    .into_owned(),
+            );
+        }
+    }
+
+    output
+}
+
+/// Replace secrets in a JSON value. Fields named in [SECRET_JSON_FIELDS] are replaced whatever
+/// their type, at any depth, and other strings are checked with [redact_secrets].
+pub fn redact_json(value: &mut serde_json::Value) {
+    use serde_json::Value;
+
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_JSON_FIELDS.contains(&key.as_str()) && !value.is_null() {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_json),
+        Value::String(text) => {
+            if let Cow::Owned(redacted) = redact_secrets(text) {
+                *text = redacted;
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
+
+/// Format a request URI with any secrets in the query string removed.
+pub fn redact_uri(uri: &Uri) -> String {
+    redact_secrets(&uri.to_string()).into_owned()
+}
+
+/// Creates the span for each request, like [tower_http::trace::DefaultMakeSpan] but without any
+/// secrets in the URI. The span also has a field for the ID of a panic in the request.
+#[derive(Clone, Debug, Default)]
+pub struct RedactedMakeSpan;
+
+impl<B> MakeSpan<B> for RedactedMakeSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        tracing::info_span!(
+            "request",
+            method = %request.method(),
+            uri = %redact_uri(request.uri()),
+            version = ?request.version(),
+            error_id = tracing::field::Empty,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pic_store_auth::api_key::ApiKeyData;
+    use pic_store_db::object_id::{BaseImageId, ProjectAccessTokenId};
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn api_key() -> String {
+        ApiKeyData::from_params(
+            API_KEY_PREFIX,
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            chrono::Utc::now(),
+        )
+        .key
+    }
+
+    #[test]
+    fn query_params() {
+        let key = api_key();
+        let (token, _) = crate::access_token::generate(ProjectAccessTokenId::new());
+        let image_id = BaseImageId::new();
+        let uri = format!(
+            "/serve/{image_id}?width=100&expires=1800000000&signature=abcdef0123&access_token={token}&API_KEY={key}"
+        )
+        .parse::<Uri>()
+        .unwrap();
+
+        let redacted = redact_uri(&uri);
+        assert!(!redacted.contains("abcdef0123"));
+        assert!(!redacted.contains(&token));
+        assert!(!redacted.contains(&key));
+        assert!(redacted.contains(&image_id.to_string()));
+        assert!(redacted.contains("width=100"));
+        assert!(redacted.contains("expires=1800000000"));
+        assert!(redacted.contains("signature=[redacted]"));
+    }
+
+    #[test]
+    fn bare_secrets() {
+        let key = api_key();
+        let (token, _) = crate::access_token::generate(ProjectAccessTokenId::new());
+
+        let text = format!("failed to authenticate with {key} or token {token}");
+        let redacted = redact_secrets(&text);
+        assert!(!redacted.contains(&key));
+        assert!(!redacted.contains(&token));
+        assert_eq!(
+            redacted,
+            "failed to authenticate with [redacted] or token [redacted]"
+        );
+
+        let redacted = redact_secrets("Authorization: Bearer some-session-token");
+        assert_eq!(redacted, "Authorization: Bearer [redacted]");
+    }
+
+    #[test]
+    fn json_credentials() {
+        let text = r#"invalid settings: {"type":"s3","access_key_id":"AKIA1","secret_key":"abc\"def","cdn_purge":{"type":"cloudflare","api_token": "tok"}}"#;
+        let redacted = redact_secrets(text);
+        assert_eq!(
+            redacted,
+            r#"invalid settings: {"type":"s3","access_key_id":"AKIA1","secret_key":"[redacted]","cdn_purge":{"type":"cloudflare","api_token": "[redacted]"}}"#
+        );
+    }
+
+    #[test]
+    fn json_values() {
+        let key = api_key();
+        let mut value = serde_json::json!({
+            "name": "cdn",
+            "provider": {
+                "type": "azure",
+                "connection_string": "AccountName=a;AccountKey=b",
+                "password": null,
+            },
+            "cdn_purge": { "type": "fastly", "api_token": "tok", "service_id": "svc" },
+            "notes": [format!("uses {key}")],
+        });
+        redact_json(&mut value);
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "name": "cdn",
+                "provider": {
+                    "type": "azure",
+                    "connection_string": REDACTED,
+                    "password": null,
+                },
+                "cdn_purge": { "type": "fastly", "api_token": REDACTED, "service_id": "svc" },
+                "notes": ["uses [redacted]"],
+            })
+        );
+    }
+
+    #[test]
+    fn leaves_other_text_alone() {
+        let text = "Image not found: /serve/bimabc?width=100";
+        assert!(matches!(redact_secrets(text), Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,515 @@
+//! Abuse reports for delivered images.
+//!
+//! Anyone can report a delivery URL. The report is matched to the image it came from, if
+//! possible, and added to a queue for instance admins to review. Taking down an image marks
+//! it as taken down, and deletes its output images so that the delivery URLs stop working.
+//! The original upload is kept so that the decision can be reviewed later. The owning team
+//! can see the reports that led to its images being taken down, and its webhooks receive an
+//! `image.taken_down` event.
+//!
+//! Since reports don't need authentication, they are limited per client address by the
+//! `report` rate limit class.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use db::{
+    abuse_reports::{self, AbuseReport, NewAbuseReport},
+    image_path,
+    object_id::{AbuseReportId, BaseImageId, ProjectId, TeamId},
+    AbuseReportStatus, BaseImageStatus, OutputImageStatus, PoolExt,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{event, Level};
+
+use crate::{
+    auth::{must_be_instance_admin, Authenticated},
+    json::Json,
+    shared_state::AppState,
+    webhooks, Error, Result,
+};
+
+const MAX_URL_LENGTH: usize = 2048;
+const MAX_REASON_LENGTH: usize = 5000;
+
+/// The most path segments to consider when matching a URL to an output image's location.
+const MAX_LOCATION_SEGMENTS: usize = 16;
+
+#[derive(Debug, Deserialize)]
+struct NewAbuseReportInput {
+    url: String,
+    reason: String,
+    reporter_email: Option<String>,
+}
+
+impl NewAbuseReportInput {
+    fn validate(&self) -> Result<(), Error> {
+        if self.url.is_empty() {
+            return Err(Error::InvalidAbuseReport("url is required"));
+        }
+
+        if self.url.len() > MAX_URL_LENGTH {
+            return Err(Error::InvalidAbuseReport("url is too long"));
+        }
+
+        if self.reason.trim().is_empty() {
+            return Err(Error::InvalidAbuseReport("reason is required"));
+        }
+
+        if self.reason.len() > MAX_REASON_LENGTH {
+            return Err(Error::InvalidAbuseReport("reason is too long"));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ListAbuseReportsQuery {
+    /// Only return reports with this status. Defaults to pending reports.
+    status: Option<AbuseReportStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResolveAbuseReportInput {
+    note: Option<String>,
+    /// The image to take down, for reports that could not be matched to an image
+    /// automatically.
+    base_image_id: Option<BaseImageId>,
+}
+
+#[derive(Debug, Serialize)]
+struct AbuseReportOutput {
+    id: AbuseReportId,
+    url: String,
+    reason: String,
+    reporter_email: Option<String>,
+    team_id: Option<TeamId>,
+    base_image_id: Option<BaseImageId>,
+    status: AbuseReportStatus,
+    created: DateTime<Utc>,
+    resolved: Option<DateTime<Utc>>,
+    resolution_note: Option<String>,
+}
+
+impl From<AbuseReport> for AbuseReportOutput {
+    fn from(value: AbuseReport) -> Self {
+        Self {
+            id: value.id,
+            url: value.url,
+            reason: value.reason,
+            reporter_email: value.reporter_email,
+            team_id: value.team_id,
+            base_image_id: value.base_image_id,
+            status: value.status,
+            created: value.created,
+            resolved: value.resolved,
+            resolution_note: value.resolution_note,
+        }
+    }
+}
+
+/// The view of a report given to the team that owns the image. This leaves out the
+/// reporter's contact information.
+#[derive(Debug, Serialize)]
+struct TeamAbuseReportOutput {
+    id: AbuseReportId,
+    url: String,
+    reason: String,
+    base_image_id: Option<BaseImageId>,
+    status: AbuseReportStatus,
+    created: DateTime<Utc>,
+    resolved: Option<DateTime<Utc>>,
+    resolution_note: Option<String>,
+}
+
+impl From<AbuseReport> for TeamAbuseReportOutput {
+    fn from(value: AbuseReport) -> Self {
+        Self {
+            id: value.id,
+            url: value.url,
+            reason: value.reason,
+            base_image_id: value.base_image_id,
+            status: value.status,
+            created: value.created,
+            resolved: value.resolved,
+            resolution_note: value.resolution_note,
+        }
+    }
+}
+
+/// The locations that an output image at this URL could have. An image's URL ends with its
+/// location, so these are the URL's path with leading segments removed.
+fn candidate_locations(url: &str) -> Vec<&str> {
+    let path = url
+        .split_once("://")
+        .map(|(_, rest)| {
+            rest.split_once('/')
+                .map(|(_, path)| path)
+                .unwrap_or_default()
+        })
+        .unwrap_or(url)
+        .trim_start_matches('/');
+
+    let mut locations = vec![path];
+    locations.extend(path.match_indices('/').map(|(i, _)| &path[i + 1..]));
+    locations.retain(|location| !location.is_empty());
+    locations.reverse();
+    locations.truncate(MAX_LOCATION_SEGMENTS);
+    locations
+}
+
+/// The image ID from a URL for the serve route, such as `/serve/bim...`.
+fn served_image_id(url: &str) -> Option<BaseImageId> {
+    let mut segments = url.split('/');
+    segments.find(|segment| *segment == "serve")?;
+    segments.next()?.parse().ok()
+}
+
+/// Find the image that a delivery URL points to.
+fn find_reported_image(
+    conn: &mut PgConnection,
+    url: &str,
+) -> Result<Option<(TeamId, BaseImageId)>, Error> {
+    let url = url.split(['?', '#']).next().unwrap_or_default();
+
+    if let Some(image_id) = served_image_id(url) {
+        let found = db::base_images::table
+            .filter(db::base_images::id.eq(image_id))
+            .filter(db::base_images::deleted.is_null())
+            .select((db::base_images::team_id, db::base_images::id))
+            .first::<(TeamId, BaseImageId)>(conn)
+            .optional()?;
+        return Ok(found);
+    }
+
+    let locations = candidate_locations(url);
+    if locations.is_empty() {
+        return Ok(None);
+    }
+
+    // Find the output images whose location matches the end of the URL exactly, and then check
+    // the full URL of each candidate.
+    let candidates = db::output_images::table
+        .inner_join(
+            db::base_images::table
+                .inner_join(db::projects::table)
+                .inner_join(
+                    db::upload_profiles::table.inner_join(
+                        db::storage_locations::table
+                            .on(db::upload_profiles::output_storage_location_id
+                                .eq(db::storage_locations::id)),
+                    ),
+                ),
+        )
+        .filter(db::output_images::location.eq_any(&locations))
+        .filter(db::output_images::deleted.is_null())
+        .select((
+            db::base_images::team_id,
+            db::base_images::id,
+            db::storage_locations::public_url_base,
+            db::projects::base_location,
+            db::upload_profiles::output_storage_location_path,
+            db::output_images::location,
+        ))
+        .limit(100)
+        .load::<(TeamId, BaseImageId, String, String, Option<String>, String)>(conn)?;
+
+    let found = candidates.into_iter().find_map(
+        |(team_id, base_image_id, url_base, project_path, profile_path, location)| {
+            let candidate_url = image_path(&url_base, &project_path, &profile_path, &location);
+            (candidate_url == url).then_some((team_id, base_image_id))
+        },
+    );
+
+    Ok(found)
+}
+
+/// Report a delivered image. This does not require authentication.
+async fn new_abuse_report(
+    State(state): State<AppState>,
+    Json(body): Json<NewAbuseReportInput>,
+) -> Result<impl IntoResponse> {
+    body.validate()?;
+
+    let report_id = state
+        .db
+        .interact(move |conn| {
+            let image = find_reported_image(conn, &body.url)?;
+
+            let value = NewAbuseReport {
+                id: AbuseReportId::new(),
+                url: body.url,
+                reason: body.reason,
+                reporter_email: body.reporter_email,
+                team_id: image.map(|(team_id, _)| team_id),
+                base_image_id: image.map(|(_, base_image_id)| base_image_id),
+            };
+
+            diesel::insert_into(abuse_reports::table)
+                .values(&value)
+                .execute(conn)?;
+
+            Ok::<_, Error>(value.id)
+        })
+        .await?;
+
+    event!(Level::INFO, %report_id, "Received abuse report");
+
+    Ok((StatusCode::OK, Json(json!({ "id": report_id }))))
+}
+
+/// List the reports against the user's team that led to an image being taken down.
+async fn list_team_abuse_reports(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+) -> Result<impl IntoResponse> {
+    let reports = state
+        .db
+        .interact(move |conn| {
+            abuse_reports::table
+                .filter(abuse_reports::team_id.eq(user.team_id))
+                .filter(abuse_reports::status.eq(AbuseReportStatus::TakenDown))
+                .select(AbuseReport::as_select())
+                .order(abuse_reports::created.desc())
+                .load(conn)
+                .map_err(Error::from)
+        })
+        .await?
+        .into_iter()
+        .map(TeamAbuseReportOutput::from)
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(reports)))
+}
+
+/// The admin review queue.
+async fn list_abuse_reports(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Query(query): Query<ListAbuseReportsQuery>,
+) -> Result<impl IntoResponse> {
+    let status = query.status.unwrap_or_default();
+    let reports = state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            abuse_reports::table
+                .filter(abuse_reports::status.eq(status))
+                .select(AbuseReport::as_select())
+                .order(abuse_reports::created.asc())
+                .limit(100)
+                .load(conn)
+                .map_err(Error::from)
+        })
+        .await?
+        .into_iter()
+        .map(AbuseReportOutput::from)
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(reports)))
+}
+
+async fn dismiss_abuse_report(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(report_id): Path<AbuseReportId>,
+    Json(body): Json<ResolveAbuseReportInput>,
+) -> Result<impl IntoResponse> {
+    let report = state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            diesel::update(abuse_reports::table)
+                .filter(abuse_reports::id.eq(report_id))
+                .filter(abuse_reports::status.eq(AbuseReportStatus::Pending))
+                .set((
+                    abuse_reports::status.eq(AbuseReportStatus::Dismissed),
+                    abuse_reports::resolved.eq(Utc::now()),
+                    abuse_reports::resolved_by.eq(user.user_id),
+                    abuse_reports::resolution_note.eq(body.note),
+                ))
+                .returning(AbuseReport::as_select())
+                .get_result(conn)
+                .optional()?
+                .ok_or(Error::NotFound)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(AbuseReportOutput::from(report))))
+}
+
+/// Take down the reported image. Other pending reports for the same image are resolved too.
+async fn take_down_abuse_report(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(report_id): Path<AbuseReportId>,
+    Json(body): Json<ResolveAbuseReportInput>,
+) -> Result<impl IntoResponse> {
+    let (report, team_id, base_image_id) = state
+        .db
+        .transaction(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            let report_image_id = abuse_reports::table
+                .filter(abuse_reports::id.eq(report_id))
+                .filter(abuse_reports::status.eq(AbuseReportStatus::Pending))
+                .select(abuse_reports::base_image_id)
+                .first::<Option<BaseImageId>>(conn)
+                .optional()?
+                .ok_or(Error::NotFound)?;
+
+            let base_image_id =
+                body.base_image_id
+                    .or(report_image_id)
+                    .ok_or(Error::InvalidAbuseReport(
+                        "report is not linked to an image",
+                    ))?;
+
+            let (team_id, project_id) = diesel::update(db::base_images::table)
+                .filter(db::base_images::id.eq(base_image_id))
+                .set((
+                    db::base_images::status.eq(BaseImageStatus::TakenDown),
+                    db::base_images::updated.eq(diesel::dsl::now),
+                ))
+                .returning((db::base_images::team_id, db::base_images::project_id))
+                .get_result::<(TeamId, ProjectId)>(conn)
+                .optional()?
+                .ok_or(Error::ObjectNotFound("base image"))?;
+
+            diesel::update(db::output_images::table)
+                .filter(db::output_images::base_image_id.eq(base_image_id))
+                .filter(db::output_images::status.ne(OutputImageStatus::Deleted))
+                .set((
+                    db::output_images::status.eq(OutputImageStatus::QueuedForDelete),
+                    db::output_images::updated.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            let now = Utc::now();
+            let report = diesel::update(abuse_reports::table)
+                .filter(abuse_reports::id.eq(report_id))
+                .set((
+                    abuse_reports::status.eq(AbuseReportStatus::TakenDown),
+                    abuse_reports::team_id.eq(team_id),
+                    abuse_reports::base_image_id.eq(base_image_id),
+                    abuse_reports::resolved.eq(now),
+                    abuse_reports::resolved_by.eq(user.user_id),
+                    abuse_reports::resolution_note.eq(&body.note),
+                ))
+                .returning(AbuseReport::as_select())
+                .get_result(conn)?;
+
+            diesel::update(abuse_reports::table)
+                .filter(abuse_reports::base_image_id.eq(base_image_id))
+                .filter(abuse_reports::status.eq(AbuseReportStatus::Pending))
+                .set((
+                    abuse_reports::status.eq(AbuseReportStatus::TakenDown),
+                    abuse_reports::team_id.eq(team_id),
+                    abuse_reports::resolved.eq(now),
+                    abuse_reports::resolved_by.eq(user.user_id),
+                    abuse_reports::resolution_note.eq(&body.note),
+                ))
+                .execute(conn)?;
+
+            webhooks::enqueue(
+                conn,
+                team_id,
+                Some(project_id),
+                webhooks::IMAGE_TAKEN_DOWN,
+                json!({
+                    "image_id": base_image_id,
+                    "report_id": report_id,
+                    "reason": report.reason,
+                }),
+            )?;
+
+            Ok::<_, Error>((report, team_id, base_image_id))
+        })
+        .await?;
+
+    let job_id = effectum::Job::builder(crate::jobs::DELETE_OUTPUT_IMAGES)
+        .json_payload(&crate::jobs::DeleteOutputImagesJobPayload {
+            base_image: base_image_id,
+        })?
+        .add_to(&state.queue)
+        .await?;
+
+    event!(
+        Level::WARN,
+        %report_id,
+        %team_id,
+        %base_image_id,
+        %job_id,
+        "Image taken down after abuse report"
+    );
+
+    Ok((StatusCode::OK, Json(AbuseReportOutput::from(report))))
+}
+
+pub fn configure() -> Router<AppState> {
+    let admin_routes = Router::new()
+        .route("/", get(list_abuse_reports))
+        .route("/:report_id/dismiss", post(dismiss_abuse_report))
+        .route("/:report_id/takedown", post(take_down_abuse_report));
+
+    let routes = Router::new()
+        .route("/", post(new_abuse_report))
+        .route("/", get(list_team_abuse_reports));
+
+    Router::new()
+        .nest("/abuse_reports", routes)
+        .nest("/admin/abuse_reports", admin_routes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations_from_url() {
+        assert_eq!(
+            candidate_locations("https://cdn.example.com/project/profile/bimabc/photo-400.webp"),
+            vec![
+                "photo-400.webp",
+                "bimabc/photo-400.webp",
+                "profile/bimabc/photo-400.webp",
+                "project/profile/bimabc/photo-400.webp",
+            ]
+        );
+        assert_eq!(
+            candidate_locations("https://cdn.example.com/"),
+            Vec::<&str>::new()
+        );
+        assert_eq!(
+            candidate_locations("https://cdn.example.com"),
+            Vec::<&str>::new()
+        );
+        // Wildcards are matched literally, since the locations are compared exactly.
+        assert_eq!(
+            candidate_locations("https://cdn.example.com/a/%_x"),
+            vec!["%_x", "a/%_x"]
+        );
+    }
+
+    #[test]
+    fn serve_urls() {
+        let id = BaseImageId::new();
+        assert_eq!(
+            served_image_id(&format!("https://api.example.com/serve/{id}")),
+            Some(id)
+        );
+        assert_eq!(served_image_id("https://api.example.com/serve/nope"), None);
+        assert_eq!(served_image_id("https://cdn.example.com/a/b.webp"), None);
+    }
+}
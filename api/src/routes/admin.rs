@@ -0,0 +1,674 @@
+//! Endpoints for instance admins, which work across all teams.
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post, put},
+    Router,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use db::{
+    conversion_pauses::{self, ConversionPause},
+    link_checks,
+    metering::{self, Metering},
+    object_id::{ProjectId, TeamId},
+    webhook_allowlists::{self, WebhookAllowlist},
+    OutputImageStatus, PoolExt, TeamStatus,
+};
+use diesel::{dsl::count_star, prelude::*};
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    auth::{must_be_instance_admin, Authenticated},
+    conversion_pause,
+    json::Json,
+    shared_state::AppState,
+    webhooks::normalize_allowed_hosts,
+    Error, Result,
+};
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = db::teams)]
+struct TeamOutput {
+    id: TeamId,
+    name: String,
+    status: TeamStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct TeamStatusInput {
+    status: TeamStatus,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Usage {
+    base_images: i64,
+    base_image_bytes: i64,
+    output_images: i64,
+    output_image_bytes: i64,
+}
+
+impl Usage {
+    fn add(&mut self, other: &Usage) {
+        self.base_images += other.base_images;
+        self.base_image_bytes += other.base_image_bytes;
+        self.output_images += other.output_images;
+        self.output_image_bytes += other.output_image_bytes;
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct TeamUsage {
+    team_id: TeamId,
+    name: String,
+    #[serde(flatten)]
+    usage: Usage,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct UsageOutput {
+    total: Usage,
+    teams: Vec<TeamUsage>,
+}
+
+async fn list_teams(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+) -> Result<impl IntoResponse> {
+    let teams = state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            db::teams::table
+                .filter(db::teams::deleted.is_null())
+                .select(TeamOutput::as_select())
+                .order(db::teams::name.asc())
+                .load(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(teams)))
+}
+
+/// Suspend a team, make it read-only, or make it active again.
+async fn set_team_status(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(team_id): Path<TeamId>,
+    Json(body): Json<TeamStatusInput>,
+) -> Result<impl IntoResponse> {
+    let team = state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            diesel::update(db::teams::table)
+                .filter(db::teams::id.eq(team_id))
+                .filter(db::teams::deleted.is_null())
+                .set(db::teams::status.eq(body.status))
+                .returning(TeamOutput::as_select())
+                .get_result(conn)
+                .optional()?
+                .ok_or(Error::NotFound)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(team)))
+}
+
+#[derive(Debug, Serialize)]
+struct ConversionPauseOutput {
+    /// The paused team, or null when conversions are paused for every team.
+    team_id: Option<TeamId>,
+    reason: Option<String>,
+    created: DateTime<Utc>,
+}
+
+impl From<ConversionPause> for ConversionPauseOutput {
+    fn from(pause: ConversionPause) -> Self {
+        ConversionPauseOutput {
+            team_id: (!pause.is_global()).then_some(pause.team_id),
+            reason: pause.reason,
+            created: pause.created,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct HeldConversions {
+    team_id: TeamId,
+    count: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct ConversionStatusOutput {
+    pauses: Vec<ConversionPauseOutput>,
+    /// The number of conversion jobs waiting for each paused team.
+    held: Vec<HeldConversions>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PauseConversionsInput {
+    /// The team to pause. Conversions for every team are paused when this is not set.
+    team_id: Option<TeamId>,
+    reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResumeConversionsInput {
+    team_id: Option<TeamId>,
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookAllowlistOutput {
+    /// The team that the list applies to, or `None` for the list that applies to every team.
+    team_id: Option<TeamId>,
+    hosts: Vec<String>,
+    updated: DateTime<Utc>,
+}
+
+impl From<WebhookAllowlist> for WebhookAllowlistOutput {
+    fn from(list: WebhookAllowlist) -> Self {
+        WebhookAllowlistOutput {
+            team_id: (!list.is_global()).then_some(list.team_id),
+            hosts: list.hosts,
+            updated: list.updated,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SetWebhookAllowlistInput {
+    /// The team to restrict. The list applies to every team without its own list when this is
+    /// not set.
+    team_id: Option<TeamId>,
+    hosts: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RemoveWebhookAllowlistInput {
+    team_id: Option<TeamId>,
+}
+
+/// List the conversion pauses and the jobs that they are holding.
+async fn get_conversion_status(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+) -> Result<impl IntoResponse> {
+    let status = state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            let pauses = conversion_pauses::table
+                .select(ConversionPause::as_select())
+                .order(conversion_pauses::created.asc())
+                .load(conn)?
+                .into_iter()
+                .map(ConversionPauseOutput::from)
+                .collect();
+            let held = conversion_pauses::held_counts(conn)?
+                .into_iter()
+                .map(|(team_id, count)| HeldConversions { team_id, count })
+                .collect();
+
+            Ok::<_, Error>(ConversionStatusOutput { pauses, held })
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(status)))
+}
+
+/// Pause conversions for one team or for every team. New conversion jobs are held until
+/// conversions are resumed.
+async fn pause_conversions(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Json(body): Json<PauseConversionsInput>,
+) -> Result<impl IntoResponse> {
+    let pause = state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            if let Some(team_id) = body.team_id {
+                let exists = diesel::select(diesel::dsl::exists(
+                    db::teams::table.filter(db::teams::id.eq(team_id)),
+                ))
+                .get_result::<bool>(conn)?;
+                if !exists {
+                    return Err(Error::ObjectNotFound("team"));
+                }
+            }
+
+            conversion_pauses::pause(conn, body.team_id, body.reason).map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(ConversionPauseOutput::from(pause))))
+}
+
+/// Lift a pause and queue the conversion jobs that it was holding.
+async fn resume_conversions(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Json(body): Json<ResumeConversionsInput>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            if !conversion_pauses::resume(conn, body.team_id)? {
+                return Err(Error::NotFound);
+            }
+
+            Ok(())
+        })
+        .await?;
+
+    let released = conversion_pause::release_held_conversions(&state).await?;
+
+    Ok((StatusCode::OK, Json(json!({ "released": released }))))
+}
+
+async fn list_webhook_allowlists(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+) -> Result<impl IntoResponse> {
+    let lists = state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            webhook_allowlists::table
+                .select(WebhookAllowlist::as_select())
+                .order(webhook_allowlists::team_id.asc())
+                .load(conn)
+                .map_err(Error::from)
+        })
+        .await?
+        .into_iter()
+        .map(WebhookAllowlistOutput::from)
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(lists)))
+}
+
+/// Restrict the hosts that a team's webhooks, or every team's webhooks, can send to. Webhooks
+/// that were already saved are checked again when each delivery is sent.
+async fn set_webhook_allowlist(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Json(body): Json<SetWebhookAllowlistInput>,
+) -> Result<impl IntoResponse> {
+    let hosts = normalize_allowed_hosts(body.hosts)?;
+
+    let list = state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            if let Some(team_id) = body.team_id {
+                let exists = diesel::select(diesel::dsl::exists(
+                    db::teams::table.filter(db::teams::id.eq(team_id)),
+                ))
+                .get_result::<bool>(conn)?;
+                if !exists {
+                    return Err(Error::ObjectNotFound("team"));
+                }
+            }
+
+            webhook_allowlists::set(conn, body.team_id, hosts).map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(WebhookAllowlistOutput::from(list))))
+}
+
+/// Remove an allowlist. A team whose own list is removed falls back to the global list.
+async fn remove_webhook_allowlist(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Json(body): Json<RemoveWebhookAllowlistInput>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            if !webhook_allowlists::remove(conn, body.team_id)? {
+                return Err(Error::NotFound);
+            }
+
+            Ok(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+/// Get the number and size of the images stored by each team.
+async fn get_usage(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+) -> Result<impl IntoResponse> {
+    let usage = state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            let teams = db::teams::table
+                .filter(db::teams::deleted.is_null())
+                .select((db::teams::id, db::teams::name))
+                .order(db::teams::name.asc())
+                .load::<(TeamId, String)>(conn)?;
+
+            storage_usage(conn, teams)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(usage)))
+}
+
+/// Count the images stored by each of the teams, and their size.
+pub(super) fn storage_usage(
+    conn: &mut PgConnection,
+    teams: Vec<(TeamId, String)>,
+) -> Result<UsageOutput> {
+    let team_ids = teams.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+
+    let base_images = db::base_images::table
+        .filter(db::base_images::deleted.is_null())
+        .filter(db::base_images::team_id.eq_any(&team_ids))
+        .group_by(db::base_images::team_id)
+        .select((
+            db::base_images::team_id,
+            count_star(),
+            diesel::dsl::sum(db::base_images::file_size),
+        ))
+        .load::<(TeamId, i64, Option<i64>)>(conn)?;
+
+    let output_images = db::output_images::table
+        .filter(db::output_images::status.eq(OutputImageStatus::Ready))
+        .filter(db::output_images::team_id.eq_any(&team_ids))
+        .group_by(db::output_images::team_id)
+        .select((
+            db::output_images::team_id,
+            count_star(),
+            diesel::dsl::sum(db::output_images::file_size),
+        ))
+        .load::<(TeamId, i64, Option<i64>)>(conn)?;
+
+    let mut usage: HashMap<TeamId, Usage> = HashMap::new();
+    for (team_id, count, bytes) in base_images {
+        let team_usage = usage.entry(team_id).or_default();
+        team_usage.base_images = count;
+        team_usage.base_image_bytes = bytes.unwrap_or(0);
+    }
+
+    for (team_id, count, bytes) in output_images {
+        let team_usage = usage.entry(team_id).or_default();
+        team_usage.output_images = count;
+        team_usage.output_image_bytes = bytes.unwrap_or(0);
+    }
+
+    let mut total = Usage::default();
+    let teams = teams
+        .into_iter()
+        .map(|(team_id, name)| {
+            let usage = usage.remove(&team_id).unwrap_or_default();
+            total.add(&usage);
+            TeamUsage {
+                team_id,
+                name,
+                usage,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Ok(UsageOutput { total, teams })
+}
+
+/// How far back the metering report goes when the request doesn't say.
+const DEFAULT_METERING_DAYS: i64 = 30;
+
+#[derive(Debug, Deserialize)]
+struct MeteringQuery {
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct MeteredUsage {
+    bytes_uploaded: i64,
+    bytes_served: i64,
+    images_served: i64,
+    /// The bytes stored at the latest snapshot in the period.
+    bytes_stored: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct ProjectMetering {
+    project_id: ProjectId,
+    #[serde(flatten)]
+    usage: MeteredUsage,
+}
+
+#[derive(Debug, Serialize)]
+struct TeamMetering {
+    team_id: TeamId,
+    name: String,
+    #[serde(flatten)]
+    usage: MeteredUsage,
+    projects: Vec<ProjectMetering>,
+}
+
+#[derive(Debug, Serialize)]
+struct MeteringOutput {
+    since: NaiveDate,
+    until: NaiveDate,
+    /// Sorted by the bytes served, highest first.
+    teams: Vec<TeamMetering>,
+}
+
+/// Get the bytes uploaded, served, and stored by each team and project between two dates,
+/// inclusive.
+async fn get_metering(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Query(query): Query<MeteringQuery>,
+) -> Result<impl IntoResponse> {
+    let until = query.until.unwrap_or_else(|| Utc::now().date_naive());
+    let since = query
+        .since
+        .unwrap_or_else(|| until - Duration::days(DEFAULT_METERING_DAYS - 1));
+
+    let (rows, team_names) = state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            let rows = metering::table
+                .filter(metering::day.ge(since))
+                .filter(metering::day.le(until))
+                .order(metering::day.asc())
+                .select(Metering::as_select())
+                .load(conn)?;
+
+            let team_names = db::teams::table
+                .filter(db::teams::id.eq_any(rows.iter().map(|row| row.team_id)))
+                .select((db::teams::id, db::teams::name))
+                .load::<(TeamId, String)>(conn)?
+                .into_iter()
+                .collect::<HashMap<_, _>>();
+
+            Ok::<_, Error>((rows, team_names))
+        })
+        .await?;
+
+    // The rows are in date order, so the last snapshot seen for a project is the latest one.
+    let mut projects: HashMap<(TeamId, ProjectId), MeteredUsage> = HashMap::new();
+    for row in rows {
+        let usage = projects.entry((row.team_id, row.project_id)).or_default();
+        usage.bytes_uploaded += row.bytes_uploaded;
+        usage.bytes_served += row.bytes_served;
+        usage.images_served += row.images_served;
+        if let Some(bytes_stored) = row.bytes_stored {
+            usage.bytes_stored = bytes_stored;
+        }
+    }
+
+    let mut teams: HashMap<TeamId, TeamMetering> = HashMap::new();
+    for ((team_id, project_id), usage) in projects {
+        let team = teams.entry(team_id).or_insert_with(|| TeamMetering {
+            team_id,
+            name: team_names.get(&team_id).cloned().unwrap_or_default(),
+            usage: MeteredUsage::default(),
+            projects: Vec::new(),
+        });
+        team.usage.bytes_uploaded += usage.bytes_uploaded;
+        team.usage.bytes_served += usage.bytes_served;
+        team.usage.images_served += usage.images_served;
+        team.usage.bytes_stored += usage.bytes_stored;
+        team.projects.push(ProjectMetering { project_id, usage });
+    }
+
+    let mut teams = teams.into_values().collect::<Vec<_>>();
+    for team in &mut teams {
+        team.projects
+            .sort_by_key(|p| std::cmp::Reverse(p.usage.bytes_served));
+    }
+    teams.sort_by_key(|t| std::cmp::Reverse(t.usage.bytes_served));
+
+    Ok((
+        StatusCode::OK,
+        Json(MeteringOutput {
+            since,
+            until,
+            teams,
+        }),
+    ))
+}
+
+const DEFAULT_LINK_CHECK_DAYS: i64 = 7;
+
+#[derive(Debug, Deserialize)]
+struct LinkCheckQuery {
+    since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProjectLinkChecks {
+    team_id: TeamId,
+    project_id: ProjectId,
+    checked: i64,
+    failed: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct LinkCheckSummary {
+    since: DateTime<Utc>,
+    /// Sorted by the number of failed checks, highest first.
+    projects: Vec<ProjectLinkChecks>,
+}
+
+/// Summarize the delivery URL checks for every project, to find misconfigured buckets or CDN
+/// rules.
+async fn get_link_checks(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Query(query): Query<LinkCheckQuery>,
+) -> Result<impl IntoResponse> {
+    let since = query
+        .since
+        .unwrap_or_else(|| Utc::now() - Duration::days(DEFAULT_LINK_CHECK_DAYS));
+
+    let rows = state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            link_checks::table
+                .filter(link_checks::checked.ge(since))
+                .group_by((
+                    link_checks::team_id,
+                    link_checks::project_id,
+                    link_checks::ok,
+                ))
+                .select((
+                    link_checks::team_id,
+                    link_checks::project_id,
+                    link_checks::ok,
+                    count_star(),
+                ))
+                .load::<(TeamId, ProjectId, bool, i64)>(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    let mut projects: HashMap<ProjectId, ProjectLinkChecks> = HashMap::new();
+    for (team_id, project_id, ok, count) in rows {
+        let project = projects
+            .entry(project_id)
+            .or_insert_with(|| ProjectLinkChecks {
+                team_id,
+                project_id,
+                checked: 0,
+                failed: 0,
+            });
+        project.checked += count;
+        if !ok {
+            project.failed += count;
+        }
+    }
+
+    let mut projects = projects.into_values().collect::<Vec<_>>();
+    projects.sort_by(|a, b| b.failed.cmp(&a.failed).then(b.checked.cmp(&a.checked)));
+
+    Ok((StatusCode::OK, Json(LinkCheckSummary { since, projects })))
+}
+
+/// The latest conversion queue metrics and the objectives that are breached. Before the first
+/// periodic check has run, the queue is measured for this request.
+async fn get_queue_metrics(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .interact(move |conn| must_be_instance_admin(conn, &user))
+        .await?;
+
+    let metrics = match state.queue_slo.latest() {
+        Some(metrics) => metrics,
+        None => state.queue_slo.measure(&state.db).await?,
+    };
+
+    Ok((StatusCode::OK, Json(metrics)))
+}
+
+pub fn configure() -> Router<AppState> {
+    let routes = Router::new()
+        .route("/teams", get(list_teams))
+        .route("/teams/:team_id/status", put(set_team_status))
+        .route("/usage", get(get_usage))
+        .route("/metering", get(get_metering))
+        .route("/conversions", get(get_conversion_status))
+        .route("/conversions/pause", post(pause_conversions))
+        .route("/conversions/resume", post(resume_conversions))
+        .route("/link_checks", get(get_link_checks))
+        .route("/queue", get(get_queue_metrics))
+        .route("/webhook_allowlists", get(list_webhook_allowlists))
+        .route("/webhook_allowlists", put(set_webhook_allowlist))
+        .route("/webhook_allowlists", delete(remove_webhook_allowlist));
+
+    Router::new().nest("/admin", routes)
+}
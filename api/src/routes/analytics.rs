@@ -0,0 +1,164 @@
+//! API usage for a project, so that integrators can monitor their own consumption.
+
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use db::{
+    api_keys,
+    api_usage::{self, ApiUsage, SESSION_KEY_ID},
+    object_id::ProjectId,
+    permissions::ProjectPermission,
+    PoolExt,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    auth::{must_own_project, Authenticated},
+    shared_state::AppState,
+    Error, Result,
+};
+
+/// How far back the analytics go when the request doesn't say.
+const DEFAULT_PERIOD_DAYS: i64 = 7;
+
+#[derive(Debug, Deserialize)]
+struct AnalyticsQuery {
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct UsageCounts {
+    requests: i64,
+    client_errors: i64,
+    server_errors: i64,
+    /// The fraction of requests that failed.
+    error_rate: f64,
+}
+
+impl UsageCounts {
+    fn add(&mut self, usage: &ApiUsage) {
+        self.requests += usage.requests;
+        self.client_errors += usage.client_errors;
+        self.server_errors += usage.server_errors;
+        if self.requests > 0 {
+            self.error_rate =
+                (self.client_errors + self.server_errors) as f64 / self.requests as f64;
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct KeyUsage {
+    /// The API key, or null for requests made with a login session.
+    api_key_id: Option<Uuid>,
+    name: Option<String>,
+    #[serde(flatten)]
+    usage: UsageCounts,
+}
+
+#[derive(Debug, Serialize)]
+struct HourlyUsage {
+    hour: DateTime<Utc>,
+    #[serde(flatten)]
+    usage: UsageCounts,
+}
+
+#[derive(Debug, Serialize)]
+struct AnalyticsOutput {
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    total: UsageCounts,
+    keys: Vec<KeyUsage>,
+    hourly: Vec<HourlyUsage>,
+}
+
+/// Get the number of API requests and errors for a project, in total, for each API key, and
+/// for each hour.
+async fn get_project_analytics(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<impl IntoResponse> {
+    let until = query.until.unwrap_or_else(Utc::now);
+    let since = query
+        .since
+        .unwrap_or_else(|| until - Duration::days(DEFAULT_PERIOD_DAYS));
+
+    let (usage, key_names) = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectRead)?;
+
+            let usage = api_usage::table
+                .filter(api_usage::project_id.eq(project_id))
+                .filter(api_usage::hour.ge(since))
+                .filter(api_usage::hour.lt(until))
+                .select(ApiUsage::as_select())
+                .load(conn)?;
+
+            let key_names = api_keys::table
+                .filter(api_keys::id.eq_any(usage.iter().map(|u| u.api_key_id)))
+                .filter(api_keys::team_id.eq(user.team_id))
+                .select((api_keys::id, api_keys::name))
+                .load::<(Uuid, String)>(conn)?
+                .into_iter()
+                .collect::<HashMap<_, _>>();
+
+            Ok::<_, Error>((usage, key_names))
+        })
+        .await?;
+
+    let mut total = UsageCounts::default();
+    let mut keys: HashMap<Uuid, UsageCounts> = HashMap::new();
+    let mut hourly: BTreeMap<DateTime<Utc>, UsageCounts> = BTreeMap::new();
+    for row in &usage {
+        total.add(row);
+        keys.entry(row.api_key_id).or_default().add(row);
+        hourly.entry(row.hour).or_default().add(row);
+    }
+
+    let mut keys = keys
+        .into_iter()
+        .map(|(api_key_id, usage)| KeyUsage {
+            name: key_names.get(&api_key_id).cloned(),
+            api_key_id: (api_key_id != SESSION_KEY_ID).then_some(api_key_id),
+            usage,
+        })
+        .collect::<Vec<_>>();
+    keys.sort_by_key(|k| std::cmp::Reverse(k.usage.requests));
+
+    let hourly = hourly
+        .into_iter()
+        .map(|(hour, usage)| HourlyUsage { hour, usage })
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(AnalyticsOutput {
+            since,
+            until,
+            total,
+            keys,
+            hourly,
+        }),
+    ))
+}
+
+pub fn configure() -> Router<AppState> {
+    Router::new().route(
+        "/projects/:project_id/analytics",
+        get(get_project_analytics),
+    )
+}
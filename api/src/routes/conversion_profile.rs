@@ -1,35 +1,265 @@
 use axum::{
-    extract::{Path, State},
+    extract::{DefaultBodyLimit, Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
     routing::{delete, get, post, put},
-    Json, Router,
+    Router,
 };
+use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use tracing::{event, Level};
 
 use db::{
     conversion_profiles,
-    conversion_profiles::{ConversionOutput, ConversionProfile, NewConversionProfile},
-    object_id::{ConversionProfileId, ProjectId},
+    conversion_profiles::{
+        ConversionOutput, ConversionProfile, ConversionProfileSettings, ConversionSize,
+        NewConversionProfile,
+    },
+    image_base_location,
+    object_id::{BaseImageId, ConversionProfileId, ProjectId, TeamId},
     permissions::ProjectPermission,
-    Permission,
+    BaseImageStatus, ImageFormat, Permission, PoolExt,
 };
+use pic_store_convert as convert;
 use pic_store_db as db;
+use pic_store_storage as storage;
 
 use crate::{
-    auth::{Authenticated, UserInfo},
-    create_object, disable_object, get_object, list_project_and_global_objects,
+    auth::{must_have_permission_on_project, Authenticated, UserInfo},
+    disable_object, get_object,
+    jobs::create_output_images::{choose_automatic_sizes, preset_operations},
+    json::Json,
+    key_template::KeyTemplate,
+    list_project_and_global_objects, profile_templates,
+    routes::image::{
+        build_output_images, db_image_format, generate_output_images, regenerate_output_images,
+        OutputImageBase,
+    },
     shared_state::AppState,
-    write_object, Error,
+    Error,
 };
 
+/// The longest chain of profiles that can extend each other.
+const MAX_EXTENDS_DEPTH: usize = 8;
+
+const DEFAULT_RERENDER_LIMIT: i64 = 100;
+const MAX_RERENDER_LIMIT: i64 = 1000;
+
 #[derive(Debug, Deserialize)]
 pub struct ConversionProfileInput {
     pub name: String,
-    pub output: ConversionOutput,
+    /// Required unless the profile extends another profile.
+    pub output: Option<ConversionOutput>,
+    pub output_key_template: Option<String>,
+    /// Inherit the settings of another profile.
+    pub extends: Option<ConversionProfileId>,
+    /// A JSON merge patch to apply to the settings of the profile in `extends`.
+    pub overrides: Option<serde_json::Value>,
+}
+
+/// Check a profile's settings before it is saved. Presets are looked up the same way as when
+/// images are converted, so a profile without a project can only use the team's global presets.
+fn validate_settings(
+    conn: &mut PgConnection,
+    team_id: TeamId,
+    project_id: Option<ProjectId>,
+    settings: &ConversionProfileSettings,
+) -> Result<(), Error> {
+    if let Some(name) = settings.output.preset() {
+        db::transformation_presets::find_by_name(conn, team_id, project_id, name)?.ok_or_else(
+            || Error::InvalidConversionProfile(format!("unknown transformation preset {name}")),
+        )?;
+    }
+
+    if let Some(template) = &settings.output_key_template {
+        KeyTemplate::validate(template)?;
+    }
+
+    if let Some(animation) = settings.output.animation() {
+        if animation.max_frames == Some(0) || animation.frame_step == Some(0) {
+            return Err(Error::InvalidConversionProfile(
+                "animation max_frames and frame_step must be at least 1".to_string(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Figure out the settings for a profile, applying its overrides to the settings of its
+/// parent if it has one.
+async fn resolve_settings(
+    state: &AppState,
+    team_id: TeamId,
+    project_id: Option<ProjectId>,
+    profile_id: ConversionProfileId,
+    body: &ConversionProfileInput,
+) -> Result<ConversionProfileSettings, Error> {
+    let Some(parent_id) = body.extends else {
+        if body.overrides.is_some() {
+            return Err(Error::InvalidConversionProfile(
+                "overrides can only be used with extends".to_string(),
+            ));
+        }
+
+        let output = body
+            .output
+            .clone()
+            .ok_or_else(|| Error::InvalidConversionProfile("output is required".to_string()))?;
+
+        return Ok(ConversionProfileSettings {
+            output,
+            output_key_template: body.output_key_template.clone(),
+        });
+    };
+
+    if body.output.is_some() || body.output_key_template.is_some() {
+        return Err(Error::InvalidConversionProfile(
+            "a profile that extends another must use overrides to change its settings".to_string(),
+        ));
+    }
+
+    let parent = state
+        .db
+        .interact(move |conn| load_parent(conn, team_id, project_id, profile_id, parent_id))
+        .await?;
+
+    let overrides = body.overrides.clone().unwrap_or_else(|| json!({}));
+    parent
+        .settings()
+        .with_overrides(&overrides)
+        .map_err(|e| Error::InvalidConversionProfile(e.to_string()))
+}
+
+/// Load the profile that a profile extends, making sure that it can be used by the profile
+/// and that the chain of profiles does not loop back on itself.
+fn load_parent(
+    conn: &mut PgConnection,
+    team_id: TeamId,
+    project_id: Option<ProjectId>,
+    profile_id: ConversionProfileId,
+    parent_id: ConversionProfileId,
+) -> Result<ConversionProfile, Error> {
+    let parent = conversion_profiles::table
+        .filter(conversion_profiles::id.eq(parent_id))
+        .filter(conversion_profiles::team_id.eq(team_id))
+        .filter(conversion_profiles::deleted.is_null())
+        .filter(
+            conversion_profiles::project_id
+                .is_null()
+                .or(conversion_profiles::project_id.is_not_distinct_from(project_id)),
+        )
+        .first::<ConversionProfile>(conn)
+        .optional()?
+        .ok_or(Error::ObjectNotFound("parent conversion profile"))?;
+
+    let mut ancestor = Some(parent.id);
+    for _ in 0..MAX_EXTENDS_DEPTH {
+        let Some(id) = ancestor else {
+            return Ok(parent);
+        };
+
+        if id == profile_id {
+            return Err(Error::InvalidConversionProfile(
+                "a profile can not extend itself".to_string(),
+            ));
+        }
+
+        ancestor = conversion_profiles::table
+            .filter(conversion_profiles::id.eq(id))
+            .select(conversion_profiles::extends)
+            .first::<Option<ConversionProfileId>>(conn)?;
+    }
+
+    Err(Error::InvalidConversionProfile(format!(
+        "profiles can only be nested {MAX_EXTENDS_DEPTH} levels deep"
+    )))
+}
+
+/// A profile that inherits from the written profile, but couldn't be updated to match it.
+#[derive(Debug, Serialize)]
+pub struct DescendantConflict {
+    id: ConversionProfileId,
+    /// Why the profile's overrides no longer apply.
+    error: String,
+}
+
+/// Recalculate the settings of every profile that inherits from this one. This fails if the user
+/// can't write one of the profiles. Profiles whose overrides no longer apply cleanly to the new
+/// settings are left unchanged, along with the profiles that inherit from them, and returned as
+/// conflicts.
+fn update_descendants(
+    conn: &mut PgConnection,
+    user: &UserInfo,
+    profile_id: ConversionProfileId,
+) -> Result<Vec<DescendantConflict>, Error> {
+    let mut conflicts = Vec::new();
+    let mut pending = vec![profile_id];
+    while let Some(parent_id) = pending.pop() {
+        let parent_settings = conversion_profiles::table
+            .filter(conversion_profiles::id.eq(parent_id))
+            .first::<ConversionProfile>(conn)?
+            .settings();
+
+        let children = conversion_profiles::table
+            .filter(conversion_profiles::extends.eq(parent_id))
+            .filter(conversion_profiles::deleted.is_null())
+            .select((
+                conversion_profiles::id,
+                conversion_profiles::project_id,
+                conversion_profiles::overrides,
+            ))
+            .load::<(
+                ConversionProfileId,
+                Option<ProjectId>,
+                Option<serde_json::Value>,
+            )>(conn)?;
+
+        for (child_id, child_project_id, overrides) in children {
+            must_have_permission_on_project(
+                conn,
+                user,
+                child_project_id.unwrap_or_else(ProjectId::nil),
+                ProjectPermission::ConversionProfileWrite,
+            )?;
+
+            let overrides = overrides.unwrap_or_else(|| json!({}));
+            let settings = match parent_settings.with_overrides(&overrides) {
+                Ok(settings) => settings,
+                Err(e) => {
+                    event!(
+                        Level::INFO,
+                        %child_id,
+                        %parent_id,
+                        error = %e,
+                        "Conversion profile overrides no longer apply to the parent profile"
+                    );
+                    conflicts.push(DescendantConflict {
+                        id: child_id,
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            diesel::update(conversion_profiles::table)
+                .filter(conversion_profiles::id.eq(child_id))
+                .set((
+                    conversion_profiles::output.eq(settings.output),
+                    conversion_profiles::output_key_template.eq(settings.output_key_template),
+                    conversion_profiles::updated.eq(Utc::now()),
+                ))
+                .execute(conn)?;
+            conversion_profiles::record_version(conn, child_id)?;
+
+            pending.push(child_id);
+        }
+    }
+
+    Ok(conflicts)
 }
 
 #[derive(Debug, Serialize, Queryable, Selectable)]
@@ -38,6 +268,10 @@ pub struct ConversionProfileOutput {
     id: ConversionProfileId,
     name: String,
     output: ConversionOutput,
+    output_key_template: Option<String>,
+    extends: Option<ConversionProfileId>,
+    overrides: Option<serde_json::Value>,
+    version: i32,
     updated: DateTime<Utc>,
 }
 
@@ -47,11 +281,36 @@ impl From<ConversionProfile> for ConversionProfileOutput {
             id: value.id,
             name: value.name,
             output: value.output,
+            output_key_template: value.output_key_template,
+            extends: value.extends,
+            overrides: value.overrides,
+            version: value.version,
             updated: value.updated,
         }
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct WriteConversionProfileOutput {
+    #[serde(flatten)]
+    profile: ConversionProfileOutput,
+    /// The profiles that inherit from this one but could not be updated, because their overrides
+    /// no longer apply. They keep their old settings until their overrides are fixed.
+    descendant_conflicts: Vec<DescendantConflict>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TemplateProfileInput {
+    /// The name of the new profile. Defaults to the template's name.
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ProjectTemplatePath {
+    project_id: ProjectId,
+    template: String,
+}
+
 #[derive(Deserialize)]
 pub struct ProjectConversionProfilePath {
     project_id: ProjectId,
@@ -124,23 +383,49 @@ async fn write_profile(
     profile_id: ConversionProfileId,
     body: ConversionProfileInput,
 ) -> Result<impl IntoResponse, Error> {
-    let result = write_object!(
-        conversion_profiles,
-        state,
-        user,
-        profile_id,
-        project_id.unwrap_or_else(ProjectId::nil),
-        ConversionProfileOutput,
-        ProjectPermission::ConversionProfileWrite,
-        (
-            dsl::name.eq(body.name),
-            dsl::output.eq(body.output),
-            dsl::updated.eq(Utc::now())
-        )
-    )
-    .await?;
+    let settings = resolve_settings(&state, user.team_id, project_id, profile_id, &body).await?;
+
+    // The profile and the profiles that inherit from it are written together, so that a failure
+    // partway through doesn't leave them out of sync.
+    let output = state
+        .db
+        .transaction(move |conn| {
+            validate_settings(conn, user.team_id, project_id, &settings)?;
+
+            let project_id = project_id.unwrap_or_else(ProjectId::nil);
+            must_have_permission_on_project(
+                conn,
+                &user,
+                project_id,
+                ProjectPermission::ConversionProfileWrite,
+            )?;
+
+            let result = diesel::update(conversion_profiles::table)
+                .filter(conversion_profiles::id.eq(profile_id))
+                .filter(conversion_profiles::project_id.is_not_distinct_from(project_id))
+                .filter(conversion_profiles::team_id.eq(user.team_id))
+                .set((
+                    conversion_profiles::name.eq(body.name),
+                    conversion_profiles::output.eq(settings.output),
+                    conversion_profiles::output_key_template.eq(settings.output_key_template),
+                    conversion_profiles::extends.eq(body.extends),
+                    conversion_profiles::overrides.eq(body.overrides),
+                    conversion_profiles::updated.eq(Utc::now()),
+                ))
+                .returning(ConversionProfileOutput::as_select())
+                .get_result::<ConversionProfileOutput>(conn)?;
 
-    Ok((StatusCode::OK, Json(result)))
+            let version = conversion_profiles::record_version(conn, profile_id)?;
+            let descendant_conflicts = update_descendants(conn, &user, profile_id)?;
+
+            Ok::<_, Error>(WriteConversionProfileOutput {
+                profile: ConversionProfileOutput { version, ..result },
+                descendant_conflicts,
+            })
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(output)))
 }
 
 async fn new_project_profile(
@@ -166,26 +451,106 @@ async fn new_profile(
     project_id: Option<ProjectId>,
     body: ConversionProfileInput,
 ) -> Result<impl IntoResponse, Error> {
+    let id = ConversionProfileId::new();
+    let settings = resolve_settings(&state, user.team_id, project_id, id, &body).await?;
+
     let value = NewConversionProfile {
-        id: ConversionProfileId::new(),
+        id,
         name: body.name,
         team_id: state.team_id,
         project_id,
-        output: body.output,
+        output: settings.output.clone(),
+        output_key_template: settings.output_key_template.clone(),
+        extends: body.extends,
+        overrides: body.overrides,
     };
 
-    let result = create_object!(
-        conversion_profiles,
+    // The first version is recorded in the same transaction so that a profile never exists
+    // without any history.
+    let result = state
+        .db
+        .transaction(move |conn| {
+            validate_settings(conn, user.team_id, project_id, &settings)?;
+            crate::auth::must_have_permission_on_project(
+                conn,
+                &user,
+                project_id.unwrap_or_else(ProjectId::nil),
+                ProjectPermission::ConversionProfileWrite,
+            )?;
+
+            let result = diesel::insert_into(conversion_profiles::table)
+                .values(&value)
+                .returning(ConversionProfileOutput::as_select())
+                .get_result::<ConversionProfileOutput>(conn)?;
+
+            let version = conversion_profiles::record_version(conn, id)?;
+            Ok::<_, Error>(ConversionProfileOutput { version, ..result })
+        })
+        .await?;
+
+    Ok((StatusCode::ACCEPTED, Json(result)))
+}
+
+/// List the built-in profile templates along with the settings that they would create.
+async fn list_templates(Authenticated(_user): Authenticated) -> impl IntoResponse {
+    let templates = profile_templates::TEMPLATES
+        .iter()
+        .map(|template| {
+            json!({
+                "name": template.name,
+                "description": template.description,
+                "output": template.output(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    (StatusCode::OK, Json(templates))
+}
+
+async fn new_project_profile_from_template(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<ProjectTemplatePath>,
+    Json(body): Json<Option<TemplateProfileInput>>,
+) -> Result<impl IntoResponse, Error> {
+    let body = body.unwrap_or_default();
+    new_profile_from_template(state, user, Some(path.project_id), &path.template, body).await
+}
+
+async fn new_global_profile_from_template(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(template): Path<String>,
+    Json(body): Json<Option<TemplateProfileInput>>,
+) -> Result<impl IntoResponse, Error> {
+    let body = body.unwrap_or_default();
+    new_profile_from_template(state, user, None, &template, body).await
+}
+
+/// Create a profile with the settings from one of the built-in templates.
+async fn new_profile_from_template(
+    state: AppState,
+    user: UserInfo,
+    project_id: Option<ProjectId>,
+    template: &str,
+    body: TemplateProfileInput,
+) -> Result<impl IntoResponse, Error> {
+    let template =
+        profile_templates::find(template).ok_or(Error::ObjectNotFound("profile template"))?;
+
+    new_profile(
         state,
         user,
-        project_id.unwrap_or_else(ProjectId::nil),
-        ConversionProfileOutput,
-        ProjectPermission::ConversionProfileWrite,
-        &value
+        project_id,
+        ConversionProfileInput {
+            name: body.name.unwrap_or_else(|| template.name.to_string()),
+            output: Some(template.output()),
+            output_key_template: None,
+            extends: None,
+            overrides: None,
+        },
     )
-    .await?;
-
-    Ok((StatusCode::ACCEPTED, Json(result)))
+    .await
 }
 
 async fn get_global_profile(
@@ -269,13 +634,498 @@ async fn disable_profile(
     Ok((StatusCode::OK, Json(json!({}))))
 }
 
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = db::base_images)]
+struct StaleImage {
+    id: BaseImageId,
+    project_id: ProjectId,
+    location: String,
+    format: Option<ImageFormat>,
+    conversion_profile_version: Option<i32>,
+    #[serde(skip)]
+    output_key_prefix: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RerenderInput {
+    /// The maximum number of images to re-render. Defaults to 100.
+    limit: Option<u32>,
+}
+
+/// Load a profile along with the images that were rendered with an older version of it, or
+/// with a different profile.
+fn load_stale_images(
+    conn: &mut PgConnection,
+    user: &UserInfo,
+    profile_id: ConversionProfileId,
+    limit: i64,
+) -> Result<(ConversionProfile, i64, Vec<StaleImage>), Error> {
+    let profile = conversion_profiles::table
+        .filter(conversion_profiles::id.eq(profile_id))
+        .filter(conversion_profiles::team_id.eq(user.team_id))
+        .filter(conversion_profiles::deleted.is_null())
+        .first::<ConversionProfile>(conn)
+        .optional()?
+        .ok_or(Error::NotFound)?;
+
+    let query = || {
+        db::base_images::table
+            .inner_join(db::upload_profiles::table)
+            .filter(db::upload_profiles::conversion_profile_id.eq(profile_id))
+            .filter(db::base_images::team_id.eq(user.team_id))
+            .filter(db::base_images::deleted.is_null())
+            .filter(db::base_images::status.eq(BaseImageStatus::Ready))
+            .filter(db::base_images::original_removed.is_null())
+            .filter(db::base_images::pinned.eq(false))
+            .filter(
+                db::base_images::conversion_profile_id
+                    .is_distinct_from(profile_id)
+                    .or(db::base_images::conversion_profile_version
+                        .assume_not_null()
+                        .lt(profile.version)),
+            )
+            .filter(db::obj_allowed!(
+                user.team_id,
+                &user.roles,
+                db::base_images::project_id,
+                Permission::ImageEdit
+            ))
+    };
+
+    let total = query().count().get_result::<i64>(conn)?;
+    let images = query()
+        .select(StaleImage::as_select())
+        .order(db::base_images::id.asc())
+        .limit(limit)
+        .load(conn)?;
+
+    Ok((profile, total, images))
+}
+
+async fn list_global_stale_images(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(profile_id): Path<ConversionProfileId>,
+) -> Result<impl IntoResponse, Error> {
+    list_stale_images(state, user, profile_id).await
+}
+
+async fn list_project_stale_images(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<ProjectConversionProfilePath>,
+) -> Result<impl IntoResponse, Error> {
+    list_stale_images(state, user, path.conversion_profile_id).await
+}
+
+/// List the images that have not been rendered with the latest version of the profile.
+async fn list_stale_images(
+    state: AppState,
+    user: UserInfo,
+    profile_id: ConversionProfileId,
+) -> Result<impl IntoResponse, Error> {
+    let (profile, total, images) = state
+        .db
+        .interact(move |conn| load_stale_images(conn, &user, profile_id, DEFAULT_RERENDER_LIMIT))
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "version": profile.version,
+            "total": total,
+            "images": images,
+        })),
+    ))
+}
+
+async fn rerender_global_stale_images(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(profile_id): Path<ConversionProfileId>,
+    Json(body): Json<Option<RerenderInput>>,
+) -> Result<impl IntoResponse, Error> {
+    let body = body.unwrap_or_default();
+    rerender_stale_images(state, user, profile_id, body).await
+}
+
+async fn rerender_project_stale_images(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<ProjectConversionProfilePath>,
+    Json(body): Json<Option<RerenderInput>>,
+) -> Result<impl IntoResponse, Error> {
+    let body = body.unwrap_or_default();
+    rerender_stale_images(state, user, path.conversion_profile_id, body).await
+}
+
+/// Regenerate the output images for images that were rendered with an older version of the
+/// profile. This handles a limited number of images per call, so it should be called again
+/// until no images remain.
+async fn rerender_stale_images(
+    state: AppState,
+    user: UserInfo,
+    profile_id: ConversionProfileId,
+    body: RerenderInput,
+) -> Result<impl IntoResponse, Error> {
+    let limit = body
+        .limit
+        .map(i64::from)
+        .unwrap_or(DEFAULT_RERENDER_LIMIT)
+        .min(MAX_RERENDER_LIMIT);
+
+    let team_id = user.team_id;
+    let (profile, total, images) = state
+        .db
+        .interact(move |conn| load_stale_images(conn, &user, profile_id, limit))
+        .await?;
+
+    let mut queued = Vec::with_capacity(images.len());
+    for image in images {
+        // Images which never finished uploading have nothing to render.
+        let Some(format) = image.format else {
+            continue;
+        };
+
+        regenerate_output_images(
+            &state,
+            &profile,
+            &OutputImageBase {
+                team_id,
+                project_id: image.project_id,
+                id: image.id,
+                location: &image.location,
+                format,
+                key_prefix: image.output_key_prefix.as_deref(),
+            },
+        )
+        .await?;
+
+        queued.push(image.id);
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "version": profile.version,
+            "queued": queued,
+            "remaining": total - queued.len() as i64,
+        })),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct PreviewQuery {
+    /// Preview an existing image instead of one uploaded in the request body.
+    image_id: Option<BaseImageId>,
+}
+
+#[derive(Debug, Serialize)]
+struct PreviewOutputImage {
+    location: String,
+    format: ImageFormat,
+    size_rule: ConversionSize,
+    width: u32,
+    height: u32,
+    file_size: usize,
+}
+
+/// The image to render a preview from.
+struct PreviewImage {
+    bytes: Bytes,
+    project_id: ProjectId,
+    id: BaseImageId,
+    location: String,
+    output_key_prefix: Option<String>,
+}
+
+async fn preview_global_profile(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(profile_id): Path<ConversionProfileId>,
+    Query(query): Query<PreviewQuery>,
+    body: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    preview_profile(state, user, None, profile_id, query, body).await
+}
+
+async fn preview_project_profile(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<ProjectConversionProfilePath>,
+    Query(query): Query<PreviewQuery>,
+    body: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    preview_profile(
+        state,
+        user,
+        Some(path.project_id),
+        path.conversion_profile_id,
+        query,
+        body,
+    )
+    .await
+}
+
+/// Run an image through a conversion profile without storing anything, so that changes to
+/// the profile can be checked before any real images use them. The image is either uploaded
+/// as the request body, or an existing image referenced by the `image_id` query parameter.
+async fn preview_profile(
+    state: AppState,
+    user: UserInfo,
+    project_id: Option<ProjectId>,
+    profile_id: ConversionProfileId,
+    query: PreviewQuery,
+    body: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let team_id = user.team_id;
+    let image_user = user.clone();
+    let (profile, operations) = state
+        .db
+        .interact(move |conn| {
+            let (profile, allowed) = conversion_profiles::table
+                .filter(conversion_profiles::id.eq(profile_id))
+                .filter(conversion_profiles::team_id.eq(user.team_id))
+                .filter(conversion_profiles::deleted.is_null())
+                .select((
+                    conversion_profiles::all_columns,
+                    db::obj_allowed!(
+                        user.team_id,
+                        &user.roles,
+                        conversion_profiles::project_id.assume_not_null(),
+                        Permission::ProjectRead
+                    ),
+                ))
+                .first::<(ConversionProfile, bool)>(conn)
+                .optional()?
+                .ok_or(Error::NotFound)?;
+
+            if !allowed {
+                return Err(Error::MissingPermission(Permission::ProjectRead));
+            }
+
+            let operations = preset_operations(
+                conn,
+                user.team_id,
+                project_id.or(profile.project_id),
+                &profile.output,
+            )?;
+
+            Ok((profile, operations))
+        })
+        .await?;
+
+    let image = match query.image_id {
+        Some(image_id) => load_preview_image(&state, image_user, image_id).await?,
+        None => PreviewImage {
+            bytes: body,
+            project_id: project_id
+                .or(profile.project_id)
+                .unwrap_or_else(ProjectId::nil),
+            id: BaseImageId::new(),
+            location: "preview".to_string(),
+            output_key_prefix: None,
+        },
+    };
+
+    let limits = state.decode_limits.clone();
+    let outputs = tokio::task::spawn_blocking(move || {
+        render_preview(&image, team_id, &limits, &profile, &operations)
+    })
+    .await
+    .map_err(eyre::Report::new)??;
+
+    Ok((StatusCode::OK, Json(json!({ "images": outputs }))))
+}
+
+/// Read an existing image from its storage location.
+async fn load_preview_image(
+    state: &AppState,
+    user: UserInfo,
+    image_id: BaseImageId,
+) -> Result<PreviewImage, Error> {
+    let (
+        project_id,
+        (location, output_key_prefix),
+        storage_location,
+        project_base_location,
+        profile_base_path,
+    ) = state
+        .db
+        .interact(move |conn| {
+            let (project_id, location, storage_location, project_base, profile_path, allowed) =
+                db::base_images::table
+                    .inner_join(
+                        db::upload_profiles::table.inner_join(
+                            db::storage_locations::table.on(db::storage_locations::id
+                                .eq(db::upload_profiles::base_storage_location_id)),
+                        ),
+                    )
+                    .inner_join(
+                        db::projects::table.on(db::projects::id.eq(db::base_images::project_id)),
+                    )
+                    .filter(db::base_images::id.eq(image_id))
+                    .filter(db::base_images::team_id.eq(user.team_id))
+                    .filter(db::base_images::deleted.is_null())
+                    .filter(db::base_images::status.ne(BaseImageStatus::TakenDown))
+                    .filter(db::base_images::original_removed.is_null())
+                    .select((
+                        db::base_images::project_id,
+                        (
+                            db::base_images::location,
+                            db::base_images::output_key_prefix,
+                        ),
+                        db::storage_locations::all_columns,
+                        db::projects::base_location,
+                        db::upload_profiles::base_storage_location_path,
+                        db::obj_allowed!(
+                            user.team_id,
+                            &user.roles,
+                            db::base_images::project_id,
+                            Permission::ProjectRead
+                        ),
+                    ))
+                    .first::<(
+                        ProjectId,
+                        (String, Option<String>),
+                        db::storage_locations::StorageLocation,
+                        String,
+                        Option<String>,
+                        bool,
+                    )>(conn)
+                    .optional()?
+                    .ok_or(Error::NotFound)?;
+
+            if !allowed {
+                return Err(Error::MissingPermission(Permission::ProjectRead));
+            }
+
+            Ok((
+                project_id,
+                location,
+                storage_location,
+                project_base,
+                profile_path,
+            ))
+        })
+        .await?;
+
+    let base_location = image_base_location(
+        &storage_location.base_location,
+        &project_base_location,
+        &profile_base_path,
+    );
+
+    let provider = storage::Provider::from_db(storage_location.provider)?;
+    let operator = provider.create_operator(base_location.as_ref()).await?;
+    let bytes = operator
+        .get(&location)
+        .await?
+        .bytes()
+        .await
+        .map_err(storage::Error::from)?;
+
+    Ok(PreviewImage {
+        bytes,
+        project_id,
+        id: image_id,
+        location,
+        output_key_prefix,
+    })
+}
+
+/// Convert the image to every output of the profile. This does CPU-heavy work and should not
+/// be run directly in an async context.
+fn render_preview(
+    source: &PreviewImage,
+    team_id: TeamId,
+    limits: &convert::DecodeLimits,
+    profile: &ConversionProfile,
+    operations: &[convert::Operation],
+) -> Result<Vec<PreviewOutputImage>, Error> {
+    let info = imageinfo::ImageInfo::from_raw_data(&source.bytes)?;
+    let format = db_image_format(info.format).ok_or(Error::ImageHeaderDecode(
+        imageinfo::ImageInfoError::UnrecognizedFormat,
+    ))?;
+    let decoded = convert::image_from_bytes(&source.bytes, limits)?;
+
+    let base = OutputImageBase {
+        team_id,
+        project_id: source.project_id,
+        id: source.id,
+        location: &source.location,
+        format,
+        key_prefix: source.output_key_prefix.as_deref(),
+    };
+
+    let output_images = match &profile.output {
+        ConversionOutput::Auto { formats, .. } => {
+            let sizes = choose_automatic_sizes(&decoded, format, &profile.output)?;
+            build_output_images(
+                formats,
+                &sizes,
+                profile.output_key_template.as_deref(),
+                &base,
+            )?
+        }
+        _ => generate_output_images(profile, &base)?,
+    };
+
+    // Archival masters aren't delivered, so they aren't part of the preview.
+    output_images
+        .into_iter()
+        .filter(|output_image| !output_image.archival)
+        .map(|output_image| {
+            let size = convert::ImageSizeTransform {
+                width: output_image.size.width,
+                height: output_image.size.height,
+                preserve_aspect_ratio: output_image.size.preserve_aspect_ratio.unwrap_or(true),
+            };
+
+            let result = convert::convert(
+                &decoded,
+                image::ImageFormat::from(&output_image.format),
+                output_image.format.quality(),
+                &size,
+                operations,
+            )
+            .map_err(convert::Error::from)?;
+
+            Ok(PreviewOutputImage {
+                location: output_image.location,
+                format: output_image.format.as_db_image_format(),
+                size_rule: output_image.size,
+                width: result.width,
+                height: result.height,
+                file_size: result.image.len(),
+            })
+        })
+        .collect()
+}
+
 pub fn configure() -> Router<AppState> {
     let project_routes = Router::new()
         .route("/", get(list_project_profiles))
         .route("/", post(new_project_profile))
+        .route(
+            "/from_template/:template",
+            post(new_project_profile_from_template),
+        )
         .route("/:conversion_profile_id", get(get_project_profile))
         .route("/:conversion_profile_id", put(write_project_profile))
-        .route("/:conversion_profile_id", delete(disable_project_profile));
+        .route("/:conversion_profile_id", delete(disable_project_profile))
+        .route(
+            "/:conversion_profile_id/stale_images",
+            get(list_project_stale_images),
+        )
+        .route(
+            "/:conversion_profile_id/rerender",
+            post(rerender_project_stale_images),
+        )
+        .route(
+            "/:conversion_profile_id/preview",
+            post(preview_project_profile).layer(DefaultBodyLimit::max(250 * 1048576)),
+        );
 
     let project_router =
         Router::new().nest("/projects/:project_id/conversion_profiles", project_routes);
@@ -283,11 +1133,29 @@ pub fn configure() -> Router<AppState> {
     let global_routes = Router::new()
         .route("/", get(list_global_profiles))
         .route("/", post(new_global_profile))
+        .route(
+            "/from_template/:template",
+            post(new_global_profile_from_template),
+        )
         .route("/:conversion_profile_id", get(get_global_profile))
         .route("/:conversion_profile_id", put(write_global_profile))
-        .route("/:conversion_profile_id", delete(disable_global_profile));
+        .route("/:conversion_profile_id", delete(disable_global_profile))
+        .route(
+            "/:conversion_profile_id/stale_images",
+            get(list_global_stale_images),
+        )
+        .route(
+            "/:conversion_profile_id/rerender",
+            post(rerender_global_stale_images),
+        )
+        .route(
+            "/:conversion_profile_id/preview",
+            post(preview_global_profile).layer(DefaultBodyLimit::max(250 * 1048576)),
+        );
 
     let global_router = Router::new().nest("/projects/global/conversion_profiles", global_routes);
 
-    global_router.merge(project_router)
+    global_router
+        .merge(project_router)
+        .route("/conversion_profile_templates", get(list_templates))
 }
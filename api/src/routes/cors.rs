@@ -0,0 +1,79 @@
+//! The CORS settings that are applied to a project's serve and upload routes.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, put},
+    Router,
+};
+use db::{
+    object_id::ProjectId, permissions::ProjectPermission, projects, projects::CorsSettings, PoolExt,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+
+use crate::{
+    auth::{must_own_project, Authenticated},
+    cors::normalize_settings,
+    json::Json,
+    shared_state::AppState,
+    Error, Result,
+};
+
+async fn get_cors_settings(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+) -> Result<impl IntoResponse> {
+    let settings = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            projects::table
+                .filter(projects::id.eq(project_id))
+                .select(projects::cors)
+                .first::<Option<CorsSettings>>(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(settings)))
+}
+
+/// Replace a project's CORS settings. Sending null removes them, so that no CORS headers are
+/// sent.
+async fn set_cors_settings(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Json(body): Json<Option<CorsSettings>>,
+) -> Result<impl IntoResponse> {
+    let settings = body.map(normalize_settings).transpose()?;
+
+    let settings = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            diesel::update(projects::table)
+                .filter(projects::id.eq(project_id))
+                .set((
+                    projects::cors.eq(&settings),
+                    projects::updated.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            Ok::<_, Error>(settings)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(settings)))
+}
+
+pub fn configure() -> Router<AppState> {
+    Router::new()
+        .route("/projects/:project_id/cors", get(get_cors_settings))
+        .route("/projects/:project_id/cors", put(set_cors_settings))
+}
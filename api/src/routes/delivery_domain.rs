@@ -0,0 +1,142 @@
+//! Delivery domains give a project its own hostname for the serve route. Requests that arrive on
+//! a delivery domain can only serve images from that project.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post},
+    Router,
+};
+use db::{
+    delivery_domains::{self, normalize_hostname, NewDeliveryDomain},
+    object_id::ProjectId,
+    permissions::ProjectPermission,
+    PoolExt,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    auth::{must_own_project, Authenticated},
+    json::Json,
+    shared_state::AppState,
+    Error, Result,
+};
+
+#[derive(Debug, Deserialize)]
+struct NewDeliveryDomainInput {
+    hostname: String,
+}
+
+#[derive(Deserialize)]
+struct DeliveryDomainPath {
+    project_id: ProjectId,
+    hostname: String,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = delivery_domains)]
+struct DeliveryDomainOutput {
+    hostname: String,
+    project_id: ProjectId,
+    created: chrono::DateTime<chrono::Utc>,
+}
+
+async fn list_delivery_domains(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+) -> Result<impl IntoResponse> {
+    let domains = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            delivery_domains::table
+                .filter(delivery_domains::project_id.eq(project_id))
+                .select(DeliveryDomainOutput::as_select())
+                .order(delivery_domains::hostname.asc())
+                .load(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(domains)))
+}
+
+/// Add a hostname to a project. Each hostname can only belong to one project.
+async fn add_delivery_domain(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Json(body): Json<NewDeliveryDomainInput>,
+) -> Result<impl IntoResponse> {
+    let hostname = normalize_hostname(&body.hostname).ok_or(Error::InvalidHostname)?;
+
+    let output = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            diesel::insert_into(delivery_domains::table)
+                .values(NewDeliveryDomain {
+                    hostname,
+                    team_id: user.team_id,
+                    project_id,
+                    created_by: user.user_id,
+                })
+                .on_conflict_do_nothing()
+                .returning(DeliveryDomainOutput::as_select())
+                .get_result(conn)
+                .optional()?
+                .ok_or(Error::DeliveryDomainTaken)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(output)))
+}
+
+async fn remove_delivery_domain(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<DeliveryDomainPath>,
+) -> Result<impl IntoResponse> {
+    let hostname = normalize_hostname(&path.hostname).ok_or(Error::InvalidHostname)?;
+
+    state
+        .db
+        .interact(move |conn| {
+            must_own_project(
+                conn,
+                &user,
+                path.project_id,
+                ProjectPermission::ProjectWrite,
+            )?;
+
+            let deleted = diesel::delete(delivery_domains::table)
+                .filter(delivery_domains::hostname.eq(hostname))
+                .filter(delivery_domains::project_id.eq(path.project_id))
+                .execute(conn)?;
+
+            if deleted == 0 {
+                return Err(Error::NotFound);
+            }
+
+            Ok(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+pub fn configure() -> Router<AppState> {
+    let domain_routes = Router::new()
+        .route("/", get(list_delivery_domains))
+        .route("/", post(add_delivery_domain))
+        .route("/:hostname", delete(remove_delivery_domain));
+
+    Router::new().nest("/projects/:project_id/delivery_domains", domain_routes)
+}
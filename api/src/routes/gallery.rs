@@ -0,0 +1,129 @@
+//! Issue gallery cookies, which give a browser access to every image in a private project.
+//!
+//! The API call sets the cookie on the caller and also returns a URL that sets it. Galleries
+//! usually make the API call from their own server, and send the viewer's browser to the URL so
+//! that the cookie is set for the image server's domain.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use chrono::{Duration, Utc};
+use db::{object_id::ProjectId, permissions::ProjectPermission, PoolExt};
+use pic_store_db as db;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    auth::{must_own_project, Authenticated},
+    gallery::{self, DEFAULT_TTL_SECONDS, MAX_TTL_SECONDS},
+    json::Json,
+    shared_state::AppState,
+    Error, Result,
+};
+
+#[derive(Debug, Default, Deserialize)]
+struct GalleryAccessInput {
+    /// How long the cookie lasts. Defaults to one day, and can't be more than 30 days.
+    ttl_seconds: Option<i64>,
+}
+
+async fn create_gallery_access(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Json(body): Json<Option<GalleryAccessInput>>,
+) -> Result<impl IntoResponse> {
+    let body = body.unwrap_or_default();
+    let signer = state.url_signer.as_ref().ok_or(Error::SignedUrlsDisabled)?;
+
+    state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectRead)
+        })
+        .await?;
+
+    let now = Utc::now();
+    let ttl = body
+        .ttl_seconds
+        .unwrap_or(DEFAULT_TTL_SECONDS)
+        .clamp(1, MAX_TTL_SECONDS);
+    let expires = now + Duration::seconds(ttl);
+    let signature = signer.sign_gallery(project_id, expires.timestamp());
+    let cookie = gallery::set_cookie_header(
+        project_id,
+        expires.timestamp(),
+        &signature,
+        now,
+        state.production,
+    )
+    .ok_or(Error::InvalidSignedUrl("invalid cookie"))?;
+
+    let url = format!(
+        "/gallery_access?project_id={project_id}&expires={}&signature={signature}",
+        expires.timestamp()
+    );
+
+    Ok((
+        StatusCode::OK,
+        [(header::SET_COOKIE, cookie)],
+        Json(json!({
+            "url": url,
+            "expires": expires,
+        })),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct RedeemQuery {
+    project_id: ProjectId,
+    expires: i64,
+    signature: String,
+}
+
+/// Set the gallery cookie from a signed URL.
+async fn redeem_gallery_access(
+    State(state): State<AppState>,
+    Query(query): Query<RedeemQuery>,
+) -> Result<impl IntoResponse> {
+    let signer = state.url_signer.as_ref().ok_or(Error::SignedUrlsDisabled)?;
+    let now = Utc::now();
+    signer.verify_gallery(query.project_id, query.expires, &query.signature, now)?;
+
+    let cookie = gallery::set_cookie_header(
+        query.project_id,
+        query.expires,
+        &query.signature,
+        now,
+        state.production,
+    )
+    .ok_or(Error::InvalidSignedUrl("invalid cookie"))?;
+
+    Ok((
+        StatusCode::NO_CONTENT,
+        [
+            (header::SET_COOKIE, cookie),
+            (
+                header::CACHE_CONTROL,
+                header::HeaderValue::from_static("no-store"),
+            ),
+        ],
+    ))
+}
+
+/// Routes under `/api`.
+pub fn configure() -> Router<AppState> {
+    Router::new().route(
+        "/projects/:project_id/gallery_access",
+        post(create_gallery_access),
+    )
+}
+
+/// The route that browsers visit to get the cookie, which doesn't need authentication.
+pub fn configure_redeem() -> Router<AppState> {
+    Router::new().route("/gallery_access", get(redeem_gallery_access))
+}
@@ -1,8 +1,16 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, TimeZone, Utc};
 use diesel::{sql_query, RunQueryDsl};
 use serde::Serialize;
+use std::collections::BTreeMap;
 
-use crate::{shared_state::AppState, Error};
+use crate::{build_info, shared_state::AppState, Error};
 
 #[derive(Serialize)]
 struct HealthResponse {
@@ -32,6 +40,68 @@ async fn health(State(state): State<AppState>) -> impl IntoResponse {
     )
 }
 
+#[derive(Serialize)]
+struct VersionResponse {
+    version: &'static str,
+    git_sha: &'static str,
+    /// When this binary was built, or `SOURCE_DATE_EPOCH` if it was set during the build.
+    build_time: Option<DateTime<Utc>>,
+    /// When the commit in `git_sha` was made.
+    commit_time: Option<DateTime<Utc>>,
+    /// Enabled cargo features. Features from the conversion crate are prefixed with `convert/`.
+    features: Vec<String>,
+    /// Versions of the image encoding and decoding libraries.
+    libraries: BTreeMap<&'static str, &'static str>,
+}
+
+async fn version() -> impl IntoResponse {
+    let features = build_info::FEATURES
+        .iter()
+        .map(|f| f.to_string())
+        .chain(
+            pic_store_convert::ENABLED_FEATURES
+                .iter()
+                .map(|f| format!("convert/{f}")),
+        )
+        .collect();
+
+    (
+        StatusCode::OK,
+        Json(VersionResponse {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: build_info::GIT_SHA,
+            build_time: Utc.timestamp_opt(build_info::BUILD_TIMESTAMP, 0).single(),
+            commit_time: build_info::COMMIT_TIMESTAMP
+                .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single()),
+            features,
+            libraries: build_info::LIBRARY_VERSIONS.iter().copied().collect(),
+        }),
+    )
+}
+
+async fn openapi_document() -> impl IntoResponse {
+    Json(pic_store_client::api::openapi_document())
+}
+
+/// A Postman collection of the API, with its base URL set to the host that requested it.
+async fn postman_collection(headers: HeaderMap) -> impl IntoResponse {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or("localhost");
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|proto| proto.to_str().ok())
+        .unwrap_or("http");
+    Json(pic_store_client::api::postman_collection(&format!(
+        "{scheme}://{host}"
+    )))
+}
+
 pub fn configure() -> Router<AppState> {
-    Router::new().route("/health", get(health))
+    Router::new()
+        .route("/health", get(health))
+        .route("/version", get(version))
+        .route("/openapi.json", get(openapi_document))
+        .route("/postman_collection.json", get(postman_collection))
 }
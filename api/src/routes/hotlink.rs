@@ -0,0 +1,90 @@
+//! The sites that may embed a project's images from the serve route.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, put},
+    Router,
+};
+use db::{object_id::ProjectId, permissions::ProjectPermission, projects, PoolExt};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::{must_own_project, Authenticated},
+    hotlink::normalize_referers,
+    json::Json,
+    shared_state::AppState,
+    Error, Result,
+};
+
+#[derive(Debug, Deserialize, Serialize, Queryable)]
+struct RefererSettings {
+    /// The hostnames that may embed the project's images. A leading `*.` allows subdomains
+    /// too. Any site may embed the images when this is null.
+    allowed_referers: Option<Vec<String>>,
+    /// Refuse requests that don't say which site they came from.
+    #[serde(default)]
+    block_empty_referer: bool,
+}
+
+async fn get_referer_settings(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+) -> Result<impl IntoResponse> {
+    let settings = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            projects::table
+                .filter(projects::id.eq(project_id))
+                .select((projects::allowed_referers, projects::block_empty_referer))
+                .first::<RefererSettings>(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(settings)))
+}
+
+async fn set_referer_settings(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Json(body): Json<RefererSettings>,
+) -> Result<impl IntoResponse> {
+    let settings = RefererSettings {
+        allowed_referers: normalize_referers(body.allowed_referers)?,
+        block_empty_referer: body.block_empty_referer,
+    };
+
+    let settings = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            diesel::update(projects::table)
+                .filter(projects::id.eq(project_id))
+                .set((
+                    projects::allowed_referers.eq(&settings.allowed_referers),
+                    projects::block_empty_referer.eq(settings.block_empty_referer),
+                    projects::updated.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            Ok::<_, Error>(settings)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(settings)))
+}
+
+pub fn configure() -> Router<AppState> {
+    Router::new()
+        .route("/projects/:project_id/referers", get(get_referer_settings))
+        .route("/projects/:project_id/referers", put(set_referer_settings))
+}
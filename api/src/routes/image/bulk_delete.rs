@@ -0,0 +1,271 @@
+//! Deleting every image in a project that matches a filter. A deletion is previewed first, which
+//! reports how many images match and returns a token. The deletion only starts once it is
+//! confirmed with that token, and then runs in the background.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use db::{
+    base_images,
+    bulk_deletions::{self, BulkDeletion, BulkDeletionFilter, NewBulkDeletion},
+    object_id::{BaseImageId, BulkDeletionId, ProjectId},
+    permissions::ProjectPermission,
+    BulkDeletionStatus, PoolExt,
+};
+use diesel::{dsl::count_star, prelude::*};
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use tracing::{event, Level};
+use uuid::Uuid;
+
+use crate::{
+    auth::{must_own_project, Authenticated},
+    jobs::{BulkDeleteImagesJobPayload, BULK_DELETE_IMAGES},
+    json::Json,
+    shared_state::AppState,
+    Error, Result,
+};
+
+/// How long the confirmation token from a preview can be used.
+const CONFIRMATION_TTL_MINUTES: i64 = 15;
+
+/// The number of matching images to include in a preview.
+const PREVIEW_SAMPLE_SIZE: i64 = 10;
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = base_images)]
+struct PreviewImage {
+    id: BaseImageId,
+    filename: String,
+    location: String,
+    created: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+struct PreviewOutput {
+    id: BulkDeletionId,
+    /// The number of images that will be deleted.
+    matched: i64,
+    /// The total size of the originals that will be deleted.
+    total_size: i64,
+    /// The number of pinned images that match but will not be deleted.
+    pinned: i64,
+    /// A few of the matching images.
+    sample: Vec<PreviewImage>,
+    /// Send this to the confirm endpoint to start the deletion.
+    confirmation_token: String,
+    expires: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmInput {
+    confirmation_token: String,
+}
+
+#[derive(Debug, Serialize)]
+struct BulkDeletionOutput {
+    id: BulkDeletionId,
+    project_id: ProjectId,
+    filter: BulkDeletionFilter,
+    status: BulkDeletionStatus,
+    matched: i64,
+    deleted: i64,
+    failed: i64,
+    error: Option<String>,
+    created: DateTime<Utc>,
+    started: Option<DateTime<Utc>>,
+    finished: Option<DateTime<Utc>>,
+}
+
+impl From<BulkDeletion> for BulkDeletionOutput {
+    fn from(deletion: BulkDeletion) -> Self {
+        BulkDeletionOutput {
+            id: deletion.id,
+            project_id: deletion.project_id,
+            filter: deletion.filter,
+            status: deletion.status,
+            matched: deletion.matched,
+            deleted: deletion.deleted,
+            failed: deletion.failed,
+            error: deletion.error,
+            created: deletion.created,
+            started: deletion.started,
+            finished: deletion.finished,
+        }
+    }
+}
+
+/// Count the images that match a filter, and return a token that confirms their deletion.
+pub async fn preview_bulk_delete(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Json(filter): Json<BulkDeletionFilter>,
+) -> Result<impl IntoResponse> {
+    if filter.is_empty() {
+        return Err(Error::InvalidBulkDeletion(
+            "the filter must have at least one condition",
+        ));
+    }
+
+    let id = BulkDeletionId::new();
+    let random = Uuid::new_v4();
+    let confirmation_token =
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(random.as_bytes());
+    let confirmation_hash = blake3::hash(confirmation_token.as_bytes());
+    let now = Utc::now();
+    let expires = now + Duration::minutes(CONFIRMATION_TTL_MINUTES);
+
+    let (matched, total_size, pinned, sample) = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            let (matched, total_size) = filter
+                .matching_images(project_id, now)
+                .select((count_star(), diesel::dsl::sum(base_images::file_size)))
+                .get_result::<(i64, Option<i64>)>(conn)?;
+
+            let pinned = filter
+                .pinned_images(project_id, now)
+                .count()
+                .get_result::<i64>(conn)?;
+
+            let sample = filter
+                .matching_images(project_id, now)
+                .select(PreviewImage::as_select())
+                .order(base_images::created.asc())
+                .limit(PREVIEW_SAMPLE_SIZE)
+                .load(conn)?;
+
+            diesel::insert_into(bulk_deletions::table)
+                .values(NewBulkDeletion {
+                    id,
+                    team_id: user.team_id,
+                    project_id,
+                    user_id: user.user_id,
+                    filter,
+                    confirmation_hash: confirmation_hash.as_bytes().to_vec(),
+                    expires,
+                    matched,
+                    created: now,
+                })
+                .execute(conn)?;
+
+            Ok::<_, Error>((matched, total_size.unwrap_or(0), pinned, sample))
+        })
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(PreviewOutput {
+            id,
+            matched,
+            total_size,
+            pinned,
+            sample,
+            confirmation_token,
+            expires,
+        }),
+    ))
+}
+
+/// Start a previewed deletion. Only images that matched when the deletion was previewed are
+/// deleted, even if more images match by now.
+pub async fn confirm_bulk_delete(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path((project_id, bulk_deletion_id)): Path<(ProjectId, BulkDeletionId)>,
+    Json(body): Json<ConfirmInput>,
+) -> Result<impl IntoResponse> {
+    let hash = blake3::hash(body.confirmation_token.as_bytes());
+
+    let deletion = state
+        .db
+        .transaction(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            let deletion = bulk_deletions::table
+                .filter(bulk_deletions::id.eq(bulk_deletion_id))
+                .filter(bulk_deletions::project_id.eq(project_id))
+                .select(BulkDeletion::as_select())
+                .for_update()
+                .first(conn)
+                .optional()?
+                .ok_or(Error::NotFound)?;
+
+            if deletion.status != BulkDeletionStatus::Previewed {
+                return Err(Error::InvalidBulkDeletion(
+                    "the deletion was already started",
+                ));
+            }
+
+            // blake3::Hash compares in constant time.
+            let token_matches = <[u8; 32]>::try_from(deletion.confirmation_hash.as_slice())
+                .map(|stored| blake3::Hash::from(stored) == hash)
+                .unwrap_or(false);
+            if !token_matches || deletion.expires < Utc::now() {
+                return Err(Error::InvalidConfirmationToken);
+            }
+
+            diesel::update(bulk_deletions::table)
+                .filter(bulk_deletions::id.eq(bulk_deletion_id))
+                .set((
+                    bulk_deletions::status.eq(BulkDeletionStatus::Running),
+                    bulk_deletions::started.eq(diesel::dsl::now),
+                ))
+                .returning(BulkDeletion::as_select())
+                .get_result(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    let job_id = effectum::Job::builder(BULK_DELETE_IMAGES)
+        .json_payload(&BulkDeleteImagesJobPayload {
+            bulk_deletion: bulk_deletion_id,
+        })?
+        .add_to(&state.queue)
+        .await?;
+
+    event!(
+        Level::INFO,
+        %project_id,
+        %bulk_deletion_id,
+        matched = deletion.matched,
+        %job_id,
+        "Started bulk deletion"
+    );
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(BulkDeletionOutput::from(deletion)),
+    ))
+}
+
+/// Get the progress of a bulk deletion.
+pub async fn get_bulk_delete(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path((project_id, bulk_deletion_id)): Path<(ProjectId, BulkDeletionId)>,
+) -> Result<impl IntoResponse> {
+    let deletion = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectRead)?;
+
+            bulk_deletions::table
+                .filter(bulk_deletions::id.eq(bulk_deletion_id))
+                .filter(bulk_deletions::project_id.eq(project_id))
+                .select(BulkDeletion::as_select())
+                .first(conn)
+                .optional()?
+                .ok_or(Error::NotFound)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(BulkDeletionOutput::from(deletion))))
+}
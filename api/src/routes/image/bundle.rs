@@ -0,0 +1,195 @@
+use std::str::FromStr;
+
+use axum::{
+    body::{Bytes, StreamBody},
+    extract::{Path, Query, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use db::{
+    base_images, image_base_location,
+    object_id::{BaseImageId, OutputImageId},
+    output_images, projects, storage_locations, upload_profiles, BaseImageStatus,
+    OutputImageStatus, Permission, PoolExt,
+};
+use diesel::prelude::*;
+use futures::{channel::mpsc, SinkExt, StreamExt};
+use pic_store_db as db;
+use pic_store_storage as storage;
+use serde::Deserialize;
+use tracing::{event, Level};
+
+use crate::{auth::Authenticated, shared_state::AppState, zip_stream::ZipWriter, Error, Result};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BundleQuery {
+    /// A comma-separated list of output image IDs to include. All of the image's outputs are
+    /// included if this is not set.
+    outputs: Option<String>,
+}
+
+fn parse_output_ids(outputs: &str) -> Result<Vec<OutputImageId>> {
+    outputs
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| OutputImageId::from_str(id).map_err(|_| Error::ObjectNotFound("output image")))
+        .collect()
+}
+
+/// Download a zip file with an image's output images. The zip file is streamed as the images are
+/// read from storage.
+pub async fn download_bundle(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(image_id): Path<BaseImageId>,
+    Query(query): Query<BundleQuery>,
+) -> Result<Response> {
+    let selected = query.outputs.as_deref().map(parse_output_ids).transpose()?;
+
+    let (storage_location, project_base_location, profile_path, outputs) = state
+        .db
+        .interact(move |conn| {
+            let (storage_location, project_base_location, profile_path, allowed) =
+                base_images::table
+                    .inner_join(upload_profiles::table.inner_join(
+                        storage_locations::table.on(
+                            storage_locations::id.eq(upload_profiles::output_storage_location_id),
+                        ),
+                    ))
+                    .inner_join(projects::table.on(projects::id.eq(base_images::project_id)))
+                    .filter(base_images::id.eq(image_id))
+                    .filter(base_images::team_id.eq(user.team_id))
+                    .filter(base_images::deleted.is_null())
+                    .filter(base_images::status.ne(BaseImageStatus::TakenDown))
+                    .select((
+                        storage_locations::all_columns,
+                        projects::base_location,
+                        upload_profiles::output_storage_location_path,
+                        db::obj_allowed!(
+                            user.team_id,
+                            &user.roles,
+                            base_images::project_id.assume_not_null(),
+                            Permission::ProjectRead
+                        ),
+                    ))
+                    .first::<(
+                        storage_locations::StorageLocation,
+                        String,
+                        Option<String>,
+                        bool,
+                    )>(conn)
+                    .optional()?
+                    .ok_or(Error::NotFound)?;
+
+            if !allowed {
+                return Err(Error::MissingPermission(Permission::ProjectRead));
+            }
+
+            let mut outputs_query = output_images::table
+                .filter(output_images::base_image_id.eq(image_id))
+                .filter(output_images::status.eq(OutputImageStatus::Ready))
+                .select(output_images::location)
+                .order(output_images::location.asc())
+                .into_boxed();
+            if let Some(selected) = selected {
+                outputs_query = outputs_query.filter(output_images::id.eq_any(selected));
+            }
+            let outputs = outputs_query.load::<String>(conn)?;
+
+            Ok((
+                storage_location,
+                project_base_location,
+                profile_path,
+                outputs,
+            ))
+        })
+        .await?;
+
+    if outputs.is_empty() {
+        return Err(Error::NotFound);
+    }
+
+    let provider = storage::Provider::from_db(storage_location.provider)?;
+    let operator = provider
+        .create_operator(&image_base_location(
+            &storage_location.base_location,
+            &project_base_location,
+            &profile_path,
+        ))
+        .await?;
+
+    let (tx, rx) = mpsc::channel::<Result<Bytes>>(4);
+    tokio::spawn(async move {
+        let mut error_tx = tx.clone();
+        if let Err(e) = write_bundle(operator, outputs, tx).await {
+            event!(Level::ERROR, error=?e, %image_id, "Failed to write image bundle");
+            // Fail the response body so that the client doesn't get a truncated zip file.
+            error_tx.send(Err(e)).await.ok();
+        }
+    });
+
+    let disposition = format!("attachment; filename=\"{image_id}.zip\"");
+    let mut response = (
+        StatusCode::OK,
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/zip"),
+        )],
+        StreamBody::new(rx),
+    )
+        .into_response();
+    if let Ok(value) = HeaderValue::from_str(&disposition) {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_DISPOSITION, value);
+    }
+
+    Ok(response)
+}
+
+async fn write_bundle(
+    operator: storage::Operator,
+    outputs: Vec<String>,
+    mut tx: mpsc::Sender<Result<Bytes>>,
+) -> Result<()> {
+    let mut zip = ZipWriter::new(Utc::now());
+    let zip_error = |e| Error::Generic(eyre::Report::new(e));
+
+    for location in outputs {
+        let mut stream = operator.get(&location).await?.into_stream();
+
+        send(&mut tx, zip.start_file(&location).map_err(zip_error)?).await?;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(storage::Error::from)?;
+            zip.write(&chunk).map_err(zip_error)?;
+            send(&mut tx, chunk).await?;
+        }
+        send(&mut tx, zip.finish_file().map_err(zip_error)?).await?;
+    }
+
+    send(&mut tx, zip.finish().map_err(zip_error)?).await
+}
+
+/// Send data to the response body. This fails if the client has gone away.
+async fn send(tx: &mut mpsc::Sender<Result<Bytes>>, data: Bytes) -> Result<()> {
+    tx.send(Ok(data))
+        .await
+        .map_err(|_| Error::Generic(eyre::eyre!("Client disconnected")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_ids() {
+        let a = OutputImageId::new();
+        let b = OutputImageId::new();
+        let ids = parse_output_ids(&format!("{a}, {b},")).unwrap();
+        assert_eq!(ids, vec![a, b]);
+
+        assert!(parse_output_ids("not-an-id").is_err());
+    }
+}
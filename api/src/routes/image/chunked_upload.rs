@@ -0,0 +1,406 @@
+//! Uploads of an original in separate chunks, for clients that can't stream a large file in one
+//! request and can't use presigned uploads. The client starts a session with the file's size,
+//! PUTs each chunk by its index in any order, and then completes the session. Chunks can be
+//! uploaded again if a request fails, and the session lists the chunks that were received so
+//! that an interrupted upload can be resumed.
+//!
+//! Each chunk is stored next to the original until the upload is completed, when they are joined
+//! into the original and checked the same way as any other upload. Sessions that aren't completed
+//! before they expire are removed by the `expire_chunked_uploads` job.
+
+use axum::{
+    extract::{BodyStream, Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Utc};
+use db::{
+    object_id::BaseImageId,
+    upload_sessions::{self, UploadChunk, UploadSession},
+    BaseImageStatus, PoolExt,
+};
+use diesel::prelude::*;
+use futures::TryStreamExt;
+use pic_store_db as db;
+use pic_store_storage as storage;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tracing::{event, Level};
+use uuid::Uuid;
+
+use super::upload::{finish_upload, load_upload_target, UploadInspector, UploadTarget};
+use crate::{
+    auth::{Authenticated, UserInfo},
+    checksum,
+    jobs::remove_chunked_upload,
+    json::Json,
+    shared_state::AppState,
+    Error,
+};
+
+/// How long a session can take to complete.
+const SESSION_TTL_HOURS: i64 = 24;
+
+const MIN_CHUNK_SIZE: i32 = 64 * 1024;
+const MAX_CHUNK_SIZE: i32 = 64 * 1024 * 1024;
+
+/// The most chunks in one upload.
+const MAX_CHUNKS: i64 = 10_000;
+
+#[derive(Debug, Deserialize)]
+pub struct StartChunkedUploadInput {
+    /// The size of the whole original.
+    size: i64,
+    /// The size of each chunk except the last. Defaults to the server's upload part size.
+    chunk_size: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChunkedUploadOutput {
+    id: Uuid,
+    size: i64,
+    chunk_size: i32,
+    chunk_count: i32,
+    expires: DateTime<Utc>,
+    /// The chunks that have been uploaded so far.
+    received: Vec<UploadChunk>,
+}
+
+impl ChunkedUploadOutput {
+    fn new(session: UploadSession, received: Vec<UploadChunk>) -> Self {
+        ChunkedUploadOutput {
+            id: session.id,
+            size: session.total_size,
+            chunk_size: session.chunk_size,
+            chunk_count: session.chunk_count,
+            expires: session.expires,
+            received,
+        }
+    }
+}
+
+/// Decide how to split a file of `size` bytes, returning the chunk size and chunk count.
+fn plan_chunks(size: i64, chunk_size: i32, max_upload_size: usize) -> Result<(i32, i32), Error> {
+    if size <= 0 {
+        return Err(Error::InvalidUploadChunk(
+            "the size must be greater than zero".to_string(),
+        ));
+    }
+
+    if size as u64 > max_upload_size as u64 {
+        return Err(Error::RequestTooLarge);
+    }
+
+    if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+        return Err(Error::InvalidUploadChunk(format!(
+            "the chunk size must be between {MIN_CHUNK_SIZE} and {MAX_CHUNK_SIZE} bytes"
+        )));
+    }
+
+    let chunk_count = (size + chunk_size as i64 - 1) / chunk_size as i64;
+    if chunk_count > MAX_CHUNKS {
+        return Err(Error::InvalidUploadChunk(format!(
+            "an upload can have at most {MAX_CHUNKS} chunks"
+        )));
+    }
+
+    Ok((chunk_size, chunk_count as i32))
+}
+
+/// Load the upload target for an image that is still waiting for its original.
+async fn load_awaiting_target(
+    state: &AppState,
+    user: &UserInfo,
+    image_id: BaseImageId,
+) -> Result<UploadTarget, Error> {
+    let target = load_upload_target(state, user, image_id).await?;
+    if !matches!(target.base_image.status, BaseImageStatus::AwaitingUpload) {
+        return Err(Error::ImageAlreadyUploaded);
+    }
+
+    Ok(target)
+}
+
+async fn load_session(
+    state: &AppState,
+    user: &UserInfo,
+    image_id: BaseImageId,
+    upload_id: Uuid,
+) -> Result<UploadSession, Error> {
+    let team_id = user.team_id;
+    state
+        .db
+        .interact(move |conn| {
+            upload_sessions::table
+                .filter(upload_sessions::id.eq(upload_id))
+                .filter(upload_sessions::base_image_id.eq(image_id))
+                .filter(upload_sessions::team_id.eq(team_id))
+                .filter(upload_sessions::expires.gt(Utc::now()))
+                .select(UploadSession::as_select())
+                .first(conn)
+                .optional()?
+                .ok_or(Error::ObjectNotFound("upload"))
+        })
+        .await
+}
+
+/// Start a chunked upload of an image's original.
+pub async fn start_chunked_upload(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(image_id): Path<BaseImageId>,
+    Json(body): Json<StartChunkedUploadInput>,
+) -> Result<impl IntoResponse, Error> {
+    let target = load_awaiting_target(&state, &user, image_id).await?;
+    let chunk_size = body
+        .chunk_size
+        .unwrap_or_else(|| state.upload_part_size.min(MAX_CHUNK_SIZE as usize) as i32);
+    let (chunk_size, chunk_count) = plan_chunks(body.size, chunk_size, state.max_upload_size)?;
+
+    let now = Utc::now();
+    let session = UploadSession {
+        id: Uuid::new_v4(),
+        team_id: user.team_id,
+        base_image_id: target.base_image.id,
+        total_size: body.size,
+        chunk_size,
+        chunk_count,
+        created: now,
+        expires: now + chrono::Duration::hours(SESSION_TTL_HOURS),
+    };
+
+    let session = state
+        .db
+        .interact(move |conn| {
+            diesel::insert_into(upload_sessions::table)
+                .values(&session)
+                .execute(conn)?;
+            Ok::<_, Error>(session)
+        })
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ChunkedUploadOutput::new(session, Vec::new())),
+    ))
+}
+
+/// Get a chunked upload and the chunks it has received.
+pub async fn get_chunked_upload(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path((image_id, upload_id)): Path<(BaseImageId, Uuid)>,
+) -> Result<impl IntoResponse, Error> {
+    load_upload_target(&state, &user, image_id).await?;
+    let session = load_session(&state, &user, image_id, upload_id).await?;
+    let received = state
+        .db
+        .interact(move |conn| upload_sessions::chunks(conn, upload_id).map_err(Error::from))
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(ChunkedUploadOutput::new(session, received)),
+    ))
+}
+
+/// Read a chunk's body, which must be exactly `expected` bytes.
+async fn read_chunk(mut stream: BodyStream, index: i32, expected: i64) -> Result<Bytes, Error> {
+    let wrong_size =
+        || Error::InvalidUploadChunk(format!("chunk {index} must be {expected} bytes"));
+
+    let mut buf = BytesMut::with_capacity(expected as usize);
+    while let Some(data) = stream.try_next().await? {
+        if (buf.len() + data.len()) as i64 > expected {
+            return Err(wrong_size());
+        }
+        buf.extend_from_slice(&data);
+    }
+
+    if buf.len() as i64 != expected {
+        return Err(wrong_size());
+    }
+
+    Ok(buf.freeze())
+}
+
+/// Upload one chunk, replacing it if it was already uploaded.
+pub async fn put_chunk(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path((image_id, upload_id, index)): Path<(BaseImageId, Uuid, i32)>,
+    stream: BodyStream,
+) -> Result<impl IntoResponse, Error> {
+    let target = load_awaiting_target(&state, &user, image_id).await?;
+    let session = load_session(&state, &user, image_id, upload_id).await?;
+    let expected = session
+        .expected_chunk_size(index)
+        .ok_or_else(|| Error::InvalidUploadChunk(format!("there is no chunk {index}")))?;
+
+    let data = read_chunk(stream, index, expected).await?;
+    let location = session.chunk_location(&target.base_image.location, index);
+    let sha256 = checksum::put_verified(&target.operator, &location, data).await?;
+
+    let chunk = UploadChunk {
+        upload_session_id: session.id,
+        chunk_index: index,
+        size: expected as i32,
+        sha256,
+        uploaded: Utc::now(),
+    };
+    let chunk = state
+        .db
+        .interact(move |conn| {
+            upload_sessions::record_chunk(conn, &chunk)?;
+            Ok::<_, Error>(chunk)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(chunk)))
+}
+
+/// Join the chunks into the original, checking each one against the checksum it had when it was
+/// uploaded.
+async fn assemble(
+    state: &AppState,
+    target: &UploadTarget,
+    session: &UploadSession,
+    chunks: &[UploadChunk],
+    upload: &mut storage::Upload,
+) -> Result<super::upload::UploadedImage, Error> {
+    let mut inspector = UploadInspector::new(state.max_upload_size, target.constraints.as_ref());
+    for chunk in chunks {
+        let location = session.chunk_location(&target.base_image.location, chunk.chunk_index);
+        let mut hasher = Sha256::new();
+        let mut stream = target.operator.get(&location).await?.into_stream();
+        while let Some(data) = stream.try_next().await.map_err(storage::Error::from)? {
+            hasher.update(&data);
+            inspector.add_chunk(&data)?;
+            upload.write(&data).await?;
+        }
+
+        if checksum::to_hex(&hasher.finalize()) != chunk.sha256 {
+            return Err(Error::ChecksumMismatch(location));
+        }
+    }
+
+    inspector.finish()
+}
+
+/// Join the uploaded chunks into the original and queue the conversions.
+pub async fn complete_chunked_upload(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path((image_id, upload_id)): Path<(BaseImageId, Uuid)>,
+) -> Result<impl IntoResponse, Error> {
+    let target = load_awaiting_target(&state, &user, image_id).await?;
+    let session = load_session(&state, &user, image_id, upload_id).await?;
+    let chunks = state
+        .db
+        .interact(move |conn| upload_sessions::chunks(conn, upload_id).map_err(Error::from))
+        .await?;
+
+    let missing = (session.chunk_count as usize).saturating_sub(chunks.len());
+    if missing > 0 {
+        return Err(Error::MissingUploadChunks(missing));
+    }
+
+    let location = target.base_image.location.clone();
+    let mut upload = target
+        .operator
+        .start_upload(&location, state.upload_part_size)
+        .await?;
+    let uploaded = match assemble(&state, &target, &session, &chunks, &mut upload).await {
+        Ok(uploaded) => {
+            upload.finish().await?;
+            uploaded
+        }
+        Err(e) => {
+            upload.abort().await.ok();
+            return Err(e);
+        }
+    };
+
+    // The session is kept if this fails, so the client can complete it again.
+    checksum::verify_stored(&target.operator, &location, &uploaded.sha256).await?;
+
+    if let Err(e) = remove_chunked_upload(&state.db, &target.operator, session, &location).await {
+        // The expiry job will try again later.
+        event!(Level::WARN, error = ?e, %upload_id, "Failed to remove completed chunked upload");
+    }
+
+    finish_upload(&state, user.team_id, target, uploaded).await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+/// Cancel a chunked upload and delete its chunks.
+pub async fn abort_chunked_upload(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path((image_id, upload_id)): Path<(BaseImageId, Uuid)>,
+) -> Result<impl IntoResponse, Error> {
+    let target = load_upload_target(&state, &user, image_id).await?;
+    let session = load_session(&state, &user, image_id, upload_id).await?;
+    remove_chunked_upload(
+        &state.db,
+        &target.operator,
+        session,
+        &target.base_image.location,
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+#[cfg(test)]
+mod tests {
+    use db::object_id::TeamId;
+
+    use super::*;
+
+    #[test]
+    fn chunk_plan() {
+        const MIB: i32 = 1024 * 1024;
+        assert_eq!(
+            plan_chunks(10 * MIB as i64, 4 * MIB, usize::MAX).unwrap(),
+            (4 * MIB, 3)
+        );
+        assert_eq!(
+            plan_chunks(8 * MIB as i64, 4 * MIB, usize::MAX).unwrap(),
+            (4 * MIB, 2)
+        );
+        assert!(matches!(
+            plan_chunks(0, 4 * MIB, usize::MAX),
+            Err(Error::InvalidUploadChunk(_))
+        ));
+        assert!(matches!(
+            plan_chunks(10 * MIB as i64, 4 * MIB, MIB as usize),
+            Err(Error::RequestTooLarge)
+        ));
+        assert!(matches!(
+            plan_chunks(10 * MIB as i64, 1024, usize::MAX),
+            Err(Error::InvalidUploadChunk(_))
+        ));
+    }
+
+    #[test]
+    fn chunk_sizes() {
+        let session = UploadSession {
+            id: Uuid::new_v4(),
+            team_id: TeamId::new(),
+            base_image_id: BaseImageId::new(),
+            total_size: 250,
+            chunk_size: 100,
+            chunk_count: 3,
+            created: Utc::now(),
+            expires: Utc::now(),
+        };
+        assert_eq!(session.expected_chunk_size(0), Some(100));
+        assert_eq!(session.expected_chunk_size(2), Some(50));
+        assert_eq!(session.expected_chunk_size(3), None);
+        assert_eq!(session.expected_chunk_size(-1), None);
+    }
+}
@@ -0,0 +1,377 @@
+//! Export the records of every image in a project, streamed so that large projects don't have to
+//! fit in memory. The CSV export has a chosen subset of columns, for use in spreadsheets.
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use db::{
+    base_images,
+    conversion_profiles::ConversionFormat,
+    image_path,
+    object_id::{BaseImageId, ProjectId, UploadProfileId},
+    output_images,
+    permissions::ProjectPermission,
+    projects, storage_locations, upload_profiles, BaseImageStatus, ImageFormat, OutputImageStatus,
+    PoolExt,
+};
+use diesel::prelude::*;
+use futures::TryStreamExt;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::{must_own_project, Authenticated},
+    list_stream::{self, ListFormat},
+    shared_state::AppState,
+    Error, Result,
+};
+
+/// The number of images to read from the database at once.
+const BATCH_SIZE: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    format: Option<ListFormat>,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = base_images)]
+struct ExportedImage {
+    id: BaseImageId,
+    filename: String,
+    location: String,
+    hash: Option<String>,
+    sha256: Option<String>,
+    file_size: i32,
+    width: i32,
+    height: i32,
+    format: Option<ImageFormat>,
+    upload_profile_id: UploadProfileId,
+    status: BaseImageStatus,
+    alt_text: String,
+    tags: Vec<String>,
+    collection: Option<String>,
+    pinned: bool,
+    created: DateTime<Utc>,
+    updated: DateTime<Utc>,
+}
+
+pub async fn export_images(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Query(query): Query<ExportQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectRead)
+        })
+        .await?;
+
+    let format = ListFormat::negotiate(query.format, &headers);
+    let images = list_stream::batches(
+        state.db.clone(),
+        BATCH_SIZE,
+        |image: &ExportedImage| image.id,
+        move |conn, after: Option<BaseImageId>, limit| {
+            let mut query = base_images::table
+                .filter(base_images::project_id.eq(project_id))
+                .filter(base_images::deleted.is_null())
+                .select(ExportedImage::as_select())
+                .order(base_images::id.asc())
+                .limit(limit)
+                .into_boxed();
+
+            if let Some(after) = after {
+                query = query.filter(base_images::id.gt(after));
+            }
+
+            query.load(conn).map_err(Error::from)
+        },
+    );
+
+    Ok(list_stream::response(format, images))
+}
+
+/// A column group in the CSV export. Each image is one row.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum CsvColumn {
+    Path,
+    Dimensions,
+    Formats,
+    Bytes,
+    Urls,
+    Tags,
+}
+
+const ALL_CSV_COLUMNS: [CsvColumn; 6] = [
+    CsvColumn::Path,
+    CsvColumn::Dimensions,
+    CsvColumn::Formats,
+    CsvColumn::Bytes,
+    CsvColumn::Urls,
+    CsvColumn::Tags,
+];
+
+impl CsvColumn {
+    fn parse(name: &str) -> Option<CsvColumn> {
+        let column = match name {
+            "path" => CsvColumn::Path,
+            "dimensions" => CsvColumn::Dimensions,
+            "formats" => CsvColumn::Formats,
+            "bytes" => CsvColumn::Bytes,
+            "urls" => CsvColumn::Urls,
+            "tags" => CsvColumn::Tags,
+            _ => return None,
+        };
+
+        Some(column)
+    }
+
+    fn headers(self) -> &'static [&'static str] {
+        match self {
+            CsvColumn::Path => &["path"],
+            CsvColumn::Dimensions => &["width", "height"],
+            CsvColumn::Formats => &["formats"],
+            CsvColumn::Bytes => &["bytes"],
+            CsvColumn::Urls => &["urls"],
+            CsvColumn::Tags => &["tags"],
+        }
+    }
+
+    fn needs_outputs(self) -> bool {
+        matches!(self, CsvColumn::Formats | CsvColumn::Urls)
+    }
+}
+
+/// Parse a comma-separated list of columns. All columns are exported when none are given.
+fn parse_csv_columns(columns: Option<&str>) -> Result<Vec<CsvColumn>> {
+    let mut parsed = Vec::new();
+    for name in columns.unwrap_or_default().split(',') {
+        let name = name.trim();
+        if name.is_empty() {
+            continue;
+        }
+
+        let column =
+            CsvColumn::parse(name).ok_or_else(|| Error::InvalidExportColumn(name.to_string()))?;
+        if !parsed.contains(&column) {
+            parsed.push(column);
+        }
+    }
+
+    if parsed.is_empty() {
+        parsed = ALL_CSV_COLUMNS.to_vec();
+    }
+
+    Ok(parsed)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CsvExportQuery {
+    /// The columns to include, such as `path,dimensions,tags`.
+    columns: Option<String>,
+}
+
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = base_images)]
+struct InventoryImage {
+    id: BaseImageId,
+    location: String,
+    width: i32,
+    height: i32,
+    file_size: i32,
+    tags: Vec<String>,
+}
+
+/// An output image's format and public URL.
+struct InventoryOutput {
+    format: &'static str,
+    url: String,
+}
+
+struct InventoryRow {
+    image: InventoryImage,
+    outputs: Vec<InventoryOutput>,
+}
+
+impl InventoryRow {
+    fn fields(&self, columns: &[CsvColumn]) -> Vec<String> {
+        let mut fields = vec![self.image.id.to_string()];
+        for column in columns {
+            match column {
+                CsvColumn::Path => fields.push(self.image.location.clone()),
+                CsvColumn::Dimensions => {
+                    fields.push(self.image.width.to_string());
+                    fields.push(self.image.height.to_string());
+                }
+                CsvColumn::Formats => {
+                    let mut formats = self
+                        .outputs
+                        .iter()
+                        .map(|output| output.format)
+                        .collect::<Vec<_>>();
+                    formats.dedup();
+                    fields.push(formats.join(" "));
+                }
+                CsvColumn::Bytes => fields.push(self.image.file_size.to_string()),
+                CsvColumn::Urls => fields.push(
+                    self.outputs
+                        .iter()
+                        .map(|output| output.url.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+                CsvColumn::Tags => fields.push(self.image.tags.join(", ")),
+            }
+        }
+
+        fields
+    }
+}
+
+pub async fn export_images_csv(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Query(query): Query<CsvExportQuery>,
+) -> Result<Response> {
+    let columns = parse_csv_columns(query.columns.as_deref())?;
+    let project_base = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectRead)?;
+            projects::table
+                .find(project_id)
+                .select(projects::base_location)
+                .first::<String>(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    let needs_outputs = columns.iter().any(|column| column.needs_outputs());
+    let rows = list_stream::batches(
+        state.db.clone(),
+        BATCH_SIZE,
+        |row: &InventoryRow| row.image.id,
+        move |conn, after: Option<BaseImageId>, limit| {
+            let mut query = base_images::table
+                .filter(base_images::project_id.eq(project_id))
+                .filter(base_images::deleted.is_null())
+                .select(InventoryImage::as_select())
+                .order(base_images::id.asc())
+                .limit(limit)
+                .into_boxed();
+
+            if let Some(after) = after {
+                query = query.filter(base_images::id.gt(after));
+            }
+
+            let images = query.load::<InventoryImage>(conn)?;
+            let mut outputs = if needs_outputs {
+                load_outputs(conn, &images, &project_base)?
+            } else {
+                HashMap::new()
+            };
+
+            let rows = images
+                .into_iter()
+                .map(|image| InventoryRow {
+                    outputs: outputs.remove(&image.id).unwrap_or_default(),
+                    image,
+                })
+                .collect();
+            Ok(rows)
+        },
+    );
+
+    let mut headers = vec!["id"];
+    for column in &columns {
+        headers.extend_from_slice(column.headers());
+    }
+
+    let rows = rows.map_ok(move |row| row.fields(&columns));
+    Ok(list_stream::csv_response(
+        &format!("{project_id}.csv"),
+        headers,
+        rows,
+    ))
+}
+
+/// Load the ready output images of each image, ordered by format.
+fn load_outputs(
+    conn: &mut PgConnection,
+    images: &[InventoryImage],
+    project_base: &str,
+) -> Result<HashMap<BaseImageId, Vec<InventoryOutput>>> {
+    let ids = images.iter().map(|image| image.id).collect::<Vec<_>>();
+    let outputs = output_images::table
+        .inner_join(base_images::table)
+        .inner_join(
+            upload_profiles::table.on(upload_profiles::id.eq(base_images::upload_profile_id)),
+        )
+        .inner_join(
+            storage_locations::table
+                .on(storage_locations::id.eq(upload_profiles::output_storage_location_id)),
+        )
+        .filter(output_images::base_image_id.eq_any(&ids))
+        .filter(output_images::status.eq(OutputImageStatus::Ready))
+        .filter(output_images::archival.eq(false))
+        .filter(output_images::deleted.is_null())
+        .select((
+            output_images::base_image_id,
+            output_images::format,
+            storage_locations::public_url_base,
+            upload_profiles::output_storage_location_path,
+            output_images::location,
+        ))
+        .load::<(
+            BaseImageId,
+            ConversionFormat,
+            String,
+            Option<String>,
+            String,
+        )>(conn)?;
+
+    let mut by_image: HashMap<BaseImageId, Vec<InventoryOutput>> = HashMap::new();
+    for (base_image_id, format, public_url_base, profile_path, location) in outputs {
+        by_image
+            .entry(base_image_id)
+            .or_default()
+            .push(InventoryOutput {
+                format: format.extension(),
+                url: image_path(&public_url_base, project_base, &profile_path, &location),
+            });
+    }
+
+    for outputs in by_image.values_mut() {
+        outputs.sort_by(|a, b| a.format.cmp(b.format).then_with(|| a.url.cmp(&b.url)));
+    }
+
+    Ok(by_image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_columns() {
+        assert_eq!(parse_csv_columns(None).unwrap(), ALL_CSV_COLUMNS.to_vec());
+        assert_eq!(
+            parse_csv_columns(Some("tags, path,tags")).unwrap(),
+            vec![CsvColumn::Tags, CsvColumn::Path]
+        );
+        assert!(matches!(
+            parse_csv_columns(Some("path,size")),
+            Err(Error::InvalidExportColumn(name)) if name == "size"
+        ));
+    }
+}
@@ -0,0 +1,130 @@
+//! Upload an original by having the server fetch it from a URL. The fetch goes through
+//! [crate::remote_fetch], which keeps it away from private addresses and enforces the size and
+//! time limits. The body is sniffed to find its format, the same way as any other upload, so
+//! the `Content-Type` that the remote server declares is never trusted.
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
+use db::{object_id::BaseImageId, PoolExt};
+use pic_store_db as db;
+use pic_store_storage as storage;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{event, Level};
+
+use super::{
+    ingest::reject_image,
+    insert_base_image,
+    upload::{finish_upload, load_upload_target, UploadInspector, UploadedImage},
+    NewBaseImageInput, UploadProfileOrShortId,
+};
+use crate::{
+    api_usage::UsageProject,
+    auth::{Authenticated, UserInfo},
+    checksum,
+    json::Json,
+    remote_fetch::{self, RemoteImage},
+    shared_state::AppState,
+    Result,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct UploadFromUrlInput {
+    url: String,
+    /// Defaults to the last part of the URL's path.
+    filename: Option<String>,
+    location: Option<String>,
+    alt_text: Option<String>,
+    upload_profile_id: Option<UploadProfileOrShortId>,
+}
+
+pub async fn upload_from_url(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Json(payload): Json<UploadFromUrlInput>,
+) -> Result<impl IntoResponse> {
+    let url = remote_fetch::parse_url(&payload.url)?;
+    let filename = payload
+        .filename
+        .or_else(|| remote_fetch::filename_from_url(&url))
+        .unwrap_or_else(|| "image".to_string());
+
+    let query_user = user.clone();
+    let input = NewBaseImageInput {
+        filename,
+        location: payload.location,
+        alt_text: payload.alt_text,
+        upload_profile_id: payload.upload_profile_id,
+    };
+    // Create the image first, so that nothing is fetched for a user who can't upload.
+    let (image_id, project_id) = state
+        .db
+        .interact(move |conn| insert_base_image(conn, &query_user, input))
+        .await?;
+
+    if let Err(e) = fetch_original(&state, &user, image_id, url).await {
+        event!(Level::WARN, error = %e, %image_id, "Failed to upload an image from a URL");
+        reject_image(&state, image_id, e.to_string()).await?;
+        return Err(e);
+    }
+
+    Ok((
+        StatusCode::OK,
+        Extension(UsageProject(project_id)),
+        Json(json!({
+            "id": image_id,
+        })),
+    ))
+}
+
+/// Fetch the original into the image's storage location, and queue the conversions.
+async fn fetch_original(
+    state: &AppState,
+    user: &UserInfo,
+    image_id: BaseImageId,
+    url: reqwest::Url,
+) -> Result<()> {
+    let target = load_upload_target(state, user, image_id).await?;
+    let mut remote = remote_fetch::open(url, &state.url_fetch_policy).await?;
+
+    let location = target.base_image.location.clone();
+    let mut upload = target
+        .operator
+        .start_upload(&location, state.upload_part_size)
+        .await?;
+    let uploaded = match copy_remote(
+        &mut upload,
+        &mut remote,
+        state.url_fetch_policy.max_size,
+        target.constraints.as_ref(),
+    )
+    .await
+    {
+        Ok(result) => {
+            upload.finish().await?;
+            result
+        }
+        Err(e) => {
+            upload.abort().await.ok();
+            return Err(e);
+        }
+    };
+
+    checksum::verify_stored(&target.operator, &location, &uploaded.sha256).await?;
+
+    finish_upload(state, user.team_id, target, uploaded).await
+}
+
+async fn copy_remote(
+    upload: &mut storage::Upload,
+    remote: &mut RemoteImage,
+    max_size: usize,
+    constraints: Option<&db::upload_profiles::ImageConstraints>,
+) -> Result<UploadedImage> {
+    let mut inspector = UploadInspector::new(max_size, constraints);
+    while let Some(chunk) = remote.chunk().await? {
+        inspector.add_chunk(&chunk)?;
+        upload.write(&chunk).await?;
+    }
+
+    inspector.finish()
+}
@@ -0,0 +1,452 @@
+//! Register files that other systems put directly into a bucket. S3 sends an event for each new
+//! object to a relay, such as an EventBridge API destination or a Lambda function, which posts
+//! it here. Each new object under the upload profile's base location becomes an image, and is
+//! checked and converted the same way as an upload through the API.
+//!
+//! Both the S3 event notification format, with a `Records` array, and the EventBridge
+//! `Object Created` format are accepted.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use db::{
+    base_images, image_base_location,
+    object_id::{BaseImageId, ProjectId, UploadProfileId},
+    output_images,
+    permissions::ProjectPermission,
+    projects, storage_locations, upload_profiles, BaseImageStatus, PoolExt,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use tracing::{event, Level};
+
+use super::{
+    render_key_prefixes,
+    upload::{finish_upload, inspect_stored, load_upload_target},
+};
+use crate::{
+    auth::{must_have_permission_on_project, Authenticated, UserInfo},
+    labels,
+    shared_state::AppState,
+    Error, Result,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum BucketNotification {
+    S3 {
+        #[serde(rename = "Records")]
+        records: Vec<S3Record>,
+    },
+    EventBridge(EventBridgeEvent),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct S3Record {
+    #[serde(rename = "eventName")]
+    event_name: String,
+    s3: S3Entity,
+}
+
+#[derive(Debug, Deserialize)]
+struct S3Entity {
+    bucket: BucketInfo,
+    object: ObjectInfo,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventBridgeEvent {
+    #[serde(rename = "detail-type")]
+    detail_type: String,
+    detail: S3Entity,
+}
+
+#[derive(Debug, Deserialize)]
+struct BucketInfo {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectInfo {
+    key: String,
+}
+
+impl BucketNotification {
+    /// The bucket and decoded key of each created object.
+    fn created_objects(self) -> Vec<(String, String)> {
+        let entities = match self {
+            BucketNotification::S3 { records } => records
+                .into_iter()
+                .filter(|record| record.event_name.starts_with("ObjectCreated:"))
+                .map(|record| record.s3)
+                .collect(),
+            BucketNotification::EventBridge(event) if event.detail_type == "Object Created" => {
+                vec![event.detail]
+            }
+            BucketNotification::EventBridge(_) => Vec::new(),
+        };
+
+        entities
+            .into_iter()
+            .map(|entity| (entity.bucket.name, decode_key(&entity.object.key)))
+            .collect()
+    }
+}
+
+/// Decode an object key from an S3 event, which is URL-encoded with `+` for spaces.
+fn decode_key(key: &str) -> String {
+    let bytes = key.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum IngestStatus {
+    /// The object became a new image, and its conversions were queued.
+    Registered,
+    /// The object is already an image or an output image.
+    Skipped,
+    /// The object is outside the upload profile's base location.
+    Ignored,
+    /// The object became an image, but could not be read as one. The image is marked as
+    /// rejected.
+    Rejected,
+}
+
+#[derive(Debug, Serialize)]
+struct IngestResult {
+    bucket: String,
+    key: String,
+    status: IngestStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_id: Option<BaseImageId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Return the location of an object relative to the profile's base location, or `None` if it
+/// isn't inside the base location.
+fn relative_location(base_location: &str, bucket: &str, key: &str) -> Option<String> {
+    let full = format!("{bucket}/{key}");
+    let prefix = format!("{}/", base_location.trim_end_matches('/'));
+    full.strip_prefix(&prefix)
+        // Keys ending in a slash are folder markers, not files.
+        .filter(|location| !location.is_empty() && !location.ends_with('/'))
+        .map(|location| location.to_string())
+}
+
+pub async fn ingest_bucket_notification(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path((project_id, upload_profile_id)): Path<(ProjectId, UploadProfileId)>,
+    // AWS events have many fields that aren't used here, so this doesn't use the strict
+    // extractor.
+    Json(notification): Json<BucketNotification>,
+) -> Result<impl IntoResponse> {
+    if user
+        .bound_upload_profile_id
+        .map(|bound| bound != upload_profile_id)
+        .unwrap_or(false)
+    {
+        return Err(Error::ApiKeyRestricted);
+    }
+
+    let query_user = user.clone();
+    let base_location = state
+        .db
+        .interact(move |conn| {
+            let user = query_user;
+            must_have_permission_on_project(
+                conn,
+                &user,
+                project_id,
+                ProjectPermission::ImageCreate,
+            )?;
+
+            let (storage_base, project_base, profile_path) = upload_profiles::table
+                .inner_join(
+                    storage_locations::table
+                        .on(storage_locations::id.eq(upload_profiles::base_storage_location_id)),
+                )
+                .inner_join(projects::table.on(projects::id.eq(upload_profiles::project_id)))
+                .filter(upload_profiles::id.eq(upload_profile_id))
+                .filter(upload_profiles::project_id.eq(project_id))
+                .filter(upload_profiles::team_id.eq(user.team_id))
+                .filter(upload_profiles::deleted.is_null())
+                .select((
+                    storage_locations::base_location,
+                    projects::base_location,
+                    upload_profiles::base_storage_location_path,
+                ))
+                .first::<(String, String, Option<String>)>(conn)
+                .optional()?
+                .ok_or(Error::ObjectNotFound("upload profile"))?;
+
+            labels::check_upload(conn, user.team_id, upload_profile_id)?;
+
+            Ok::<_, Error>(
+                image_base_location(&storage_base, &project_base, &profile_path).into_owned(),
+            )
+        })
+        .await?;
+
+    let mut results = Vec::new();
+    for (bucket, key) in notification.created_objects() {
+        let Some(location) = relative_location(&base_location, &bucket, &key) else {
+            results.push(IngestResult {
+                bucket,
+                key,
+                status: IngestStatus::Ignored,
+                image_id: None,
+                error: None,
+            });
+            continue;
+        };
+
+        let image_id = register_image(
+            &state,
+            &user,
+            project_id,
+            upload_profile_id,
+            location.clone(),
+        )
+        .await?;
+        let Some(image_id) = image_id else {
+            results.push(IngestResult {
+                bucket,
+                key,
+                status: IngestStatus::Skipped,
+                image_id: None,
+                error: None,
+            });
+            continue;
+        };
+
+        let (status, error) = match convert_image(&state, &user, image_id).await {
+            Ok(()) => (IngestStatus::Registered, None),
+            Err(e) => {
+                event!(Level::WARN, error = %e, %image_id, %location, "Rejected an image from a bucket notification");
+                let error = e.to_string();
+                reject_image(&state, image_id, error.clone()).await?;
+                (IngestStatus::Rejected, Some(error))
+            }
+        };
+
+        results.push(IngestResult {
+            bucket,
+            key,
+            status,
+            image_id: Some(image_id),
+            error,
+        });
+    }
+
+    Ok((StatusCode::OK, Json(results)))
+}
+
+/// Add an image for an object in the bucket, unless it's already an image or the output of one.
+async fn register_image(
+    state: &AppState,
+    user: &UserInfo,
+    project_id: ProjectId,
+    upload_profile_id: UploadProfileId,
+    location: String,
+) -> Result<Option<BaseImageId>> {
+    let team_id = user.team_id;
+    let user_id = user.user_id;
+    state
+        .db
+        .interact(move |conn| {
+            let existing_image = base_images::table
+                .filter(base_images::upload_profile_id.eq(upload_profile_id))
+                .filter(base_images::location.eq(&location))
+                .filter(base_images::deleted.is_null())
+                .select(base_images::id)
+                .first::<BaseImageId>(conn)
+                .optional()?;
+            // Output images written to the same bucket send notifications too.
+            let existing_output = output_images::table
+                .filter(output_images::team_id.eq(team_id))
+                .filter(output_images::location.eq(&location))
+                .select(output_images::id)
+                .first::<db::object_id::OutputImageId>(conn)
+                .optional()?;
+            if existing_image.is_some() || existing_output.is_some() {
+                return Ok(None);
+            }
+
+            // The object's key already includes any prefix, so only the output prefix is used.
+            let (_, output_key_prefix) =
+                render_key_prefixes(conn, upload_profile_id, team_id, project_id)?;
+            let filename = location.rsplit('/').next().unwrap_or(&location).to_string();
+
+            let new_image = base_images::NewBaseImage {
+                id: BaseImageId::new(),
+                user_id,
+                team_id,
+                project_id,
+                upload_profile_id,
+                filename,
+                location,
+                format: None,
+                hash: String::new(),
+                width: 0,
+                height: 0,
+                status: BaseImageStatus::AwaitingUpload,
+                alt_text: String::new(),
+                placeholder: String::new(),
+                output_key_prefix,
+            };
+
+            diesel::insert_into(base_images::table)
+                .values(&new_image)
+                .execute(conn)?;
+
+            Ok::<_, Error>(Some(new_image.id))
+        })
+        .await
+}
+
+/// Read the object back to check it, then queue the conversions.
+async fn convert_image(state: &AppState, user: &UserInfo, image_id: BaseImageId) -> Result<()> {
+    let target = load_upload_target(state, user, image_id).await?;
+    let uploaded = inspect_stored(
+        &target.operator,
+        &target.base_image.location,
+        state.max_upload_size,
+        target.constraints.as_ref(),
+    )
+    .await?;
+
+    finish_upload(state, user.team_id, target, uploaded).await
+}
+
+pub(super) async fn reject_image(
+    state: &AppState,
+    image_id: BaseImageId,
+    error: String,
+) -> Result<()> {
+    state
+        .db
+        .interact(move |conn| {
+            diesel::update(base_images::table)
+                .filter(base_images::id.eq(image_id))
+                .set((
+                    base_images::status.eq(BaseImageStatus::Rejected),
+                    base_images::error.eq(error),
+                    base_images::updated.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+            Ok::<_, Error>(())
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            decode_key("photos/my+cat%281%29.jpg"),
+            "photos/my cat(1).jpg"
+        );
+        assert_eq!(decode_key("caf%C3%A9.png"), "café.png");
+        assert_eq!(decode_key("100%"), "100%");
+        assert_eq!(decode_key("a%zz"), "a%zz");
+    }
+
+    #[test]
+    fn locations() {
+        assert_eq!(
+            relative_location(
+                "bucket/project/profile",
+                "bucket",
+                "project/profile/a/b.jpg"
+            ),
+            Some("a/b.jpg".to_string())
+        );
+        assert_eq!(
+            relative_location("bucket", "bucket", "b.jpg"),
+            Some("b.jpg".to_string())
+        );
+        assert_eq!(
+            relative_location("bucket/project", "other", "project/b.jpg"),
+            None
+        );
+        assert_eq!(
+            relative_location("bucket/project", "bucket", "projects/b.jpg"),
+            None
+        );
+        assert_eq!(
+            relative_location("bucket/project", "bucket", "project/dir/"),
+            None
+        );
+    }
+
+    #[test]
+    fn notifications() {
+        let s3 = serde_json::json!({
+            "Records": [
+                {
+                    "eventVersion": "2.1",
+                    "eventName": "ObjectCreated:Put",
+                    "s3": {
+                        "bucket": { "name": "bucket", "arn": "arn:aws:s3:::bucket" },
+                        "object": { "key": "a+b.jpg", "size": 1024 }
+                    }
+                },
+                {
+                    "eventName": "ObjectRemoved:Delete",
+                    "s3": { "bucket": { "name": "bucket" }, "object": { "key": "c.jpg" } }
+                }
+            ]
+        });
+        let notification: BucketNotification = serde_json::from_value(s3).unwrap();
+        assert_eq!(
+            notification.created_objects(),
+            vec![("bucket".to_string(), "a b.jpg".to_string())]
+        );
+
+        let eventbridge = serde_json::json!({
+            "source": "aws.s3",
+            "detail-type": "Object Created",
+            "detail": {
+                "bucket": { "name": "bucket" },
+                "object": { "key": "dir/c.png", "size": 10 }
+            }
+        });
+        let notification: BucketNotification = serde_json::from_value(eventbridge).unwrap();
+        assert_eq!(
+            notification.created_objects(),
+            vec![("bucket".to_string(), "dir/c.png".to_string())]
+        );
+    }
+}
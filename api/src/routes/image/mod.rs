@@ -1,10 +1,25 @@
+mod bulk_delete;
+mod bundle;
+mod chunked_upload;
+mod export;
+mod from_url;
+mod ingest;
+mod original;
+mod purge;
+mod references;
+mod search;
+mod signed_url;
 mod upload;
 
+pub(crate) use upload::db_image_format;
+
+use std::collections::HashMap;
+
 use axum::{
     extract::{DefaultBodyLimit, Path, State},
     response::IntoResponse,
     routing::{delete, get, post, put},
-    Json, Router,
+    Extension, Router,
 };
 use db::{
     base_images,
@@ -12,7 +27,10 @@ use db::{
         self, ConversionFormat, ConversionOutput, ConversionProfile, ConversionSize,
     },
     image_path,
-    object_id::{BaseImageId, OutputImageId, ProjectId, TeamId, UploadProfileId},
+    object_id::{
+        BaseImageId, ConversionProfileId, OutputImageId, ProjectId, StorageLocationId, TeamId,
+        UploadProfileId,
+    },
     output_images::{self, NewOutputImage},
     projects, storage_locations, upload_profiles, BaseImageStatus, ImageFormat, OutputImageStatus,
     Permission, PoolExt,
@@ -25,8 +43,15 @@ use serde_json::json;
 use tracing::{event, Level};
 
 use crate::{
+    api_usage::UsageProject,
     auth::{Authenticated, UserInfo},
-    get_object_by_field_query, get_object_query,
+    conversion_pause, get_object_by_field_query, get_object_query,
+    json::Json,
+    key_template::{
+        with_key_prefix, KeyPrefixTemplate, KeyPrefixValues, KeyTemplate, KeyTemplateError,
+        KeyTemplateValues,
+    },
+    labels,
     shared_state::AppState,
     Error, Result,
 };
@@ -52,6 +77,27 @@ async fn new_base_image(
     Json(payload): Json<NewBaseImageInput>,
 ) -> Result<impl IntoResponse, Error> {
     event!(Level::INFO, ?user);
+    let conn = state.db.get().await?;
+    let (image_id, project_id) = conn
+        .interact(move |conn| insert_base_image(conn, &user, payload))
+        .await??;
+
+    Ok((
+        StatusCode::OK,
+        Extension(UsageProject(project_id)),
+        Json(json!({
+            "id": image_id,
+        })),
+    ))
+}
+
+/// Add an image that is waiting for its original to be uploaded, after checking that the user
+/// can upload through the upload profile.
+fn insert_base_image(
+    conn: &mut PgConnection,
+    user: &UserInfo,
+    payload: NewBaseImageInput,
+) -> Result<(BaseImageId, ProjectId)> {
     let upload_profile = payload
         .upload_profile_id
         .or_else(|| {
@@ -60,85 +106,130 @@ async fn new_base_image(
         })
         .ok_or(Error::NoUploadProfile)?;
 
-    let conn = state.db.get().await?;
+    #[derive(Debug, Queryable, Selectable)]
+    #[diesel(table_name = upload_profiles)]
+    struct UploadProfileInfo {
+        id: UploadProfileId,
+        project_id: ProjectId,
+    }
 
-    let image_id = conn
-        .interact(move |conn| {
-            #[derive(Debug, Queryable, Selectable)]
-            #[diesel(table_name = upload_profiles)]
-            struct UploadProfileInfo {
-                id: UploadProfileId,
-                project_id: ProjectId,
-            }
+    let (profile, allowed) = match upload_profile {
+        UploadProfileOrShortId::Id(id) => get_object_query!(
+            upload_profiles,
+            conn,
+            user,
+            UploadProfileInfo,
+            id,
+            Permission::ImageCreate
+        ),
+        UploadProfileOrShortId::ShortId(short_id) => get_object_by_field_query!(
+            upload_profiles,
+            conn,
+            user,
+            UploadProfileInfo,
+            short_id,
+            short_id,
+            Permission::ImageCreate
+        ),
+    }?;
+
+    if !allowed {
+        return Err(Error::MissingPermission(Permission::ImageCreate));
+    }
 
-            let (profile, allowed) = match upload_profile {
-                UploadProfileOrShortId::Id(id) => get_object_query!(
-                    upload_profiles,
-                    conn,
-                    user,
-                    UploadProfileInfo,
-                    id,
-                    Permission::ImageCreate
-                ),
-                UploadProfileOrShortId::ShortId(short_id) => get_object_by_field_query!(
-                    upload_profiles,
-                    conn,
-                    user,
-                    UploadProfileInfo,
-                    short_id,
-                    short_id,
-                    Permission::ImageCreate
-                ),
-            }?;
+    if user
+        .bound_upload_profile_id
+        .map(|bound| bound != profile.id)
+        .unwrap_or(false)
+    {
+        return Err(Error::ApiKeyRestricted);
+    }
 
-            if !allowed {
-                return Err(Error::MissingPermission(Permission::ImageCreate));
-            }
+    labels::check_upload(conn, user.team_id, profile.id)?;
+
+    let new_image_id = BaseImageId::new();
+
+    // TODO sanitize file path for standard path exploits
+    // URL encoding is inconsistent between providers, so just replace any url-encoded
+    // characters with a dash.
+    static URLENCODED: once_cell::sync::OnceCell<regex::Regex> = once_cell::sync::OnceCell::new();
+    let url_encoded =
+        URLENCODED.get_or_init(|| regex::Regex::new(r##"[^a-zA-Z0-9-_.~]+"##).unwrap());
+    let location = url_encoded
+        .replace_all(payload.location.as_ref().unwrap_or(&payload.filename), "-")
+        .to_string();
+
+    let (base_prefix, output_key_prefix) =
+        render_key_prefixes(conn, profile.id, user.team_id, profile.project_id)?;
+    let location = with_key_prefix(base_prefix.as_deref(), &location);
+
+    let new_image = db::base_images::NewBaseImage {
+        id: new_image_id,
+        user_id: user.user_id,
+        team_id: user.team_id,
+        project_id: profile.project_id,
+        upload_profile_id: profile.id,
+        filename: payload.filename,
+        location,
+        format: None,
+        hash: String::new(),
+        width: 0,
+        height: 0,
+        status: db::BaseImageStatus::AwaitingUpload,
+        alt_text: payload.alt_text.unwrap_or_default(),
+        placeholder: String::new(),
+        output_key_prefix,
+    };
 
-            let new_image_id = BaseImageId::new();
-
-            // TODO sanitize file path for standard path exploits
-            // URL encoding is inconsistent between providers, so just replace any url-encoded
-            // characters with a dash.
-            static URLENCODED: once_cell::sync::OnceCell<regex::Regex> =
-                once_cell::sync::OnceCell::new();
-            let url_encoded =
-                URLENCODED.get_or_init(|| regex::Regex::new(r##"[^a-zA-Z0-9-_.~]+"##).unwrap());
-            let location = url_encoded
-                .replace_all(payload.location.as_ref().unwrap_or(&payload.filename), "-")
-                .to_string();
-
-            let new_image = db::base_images::NewBaseImage {
-                id: new_image_id,
-                user_id: user.user_id,
-                team_id: user.team_id,
-                project_id: profile.project_id,
-                upload_profile_id: profile.id,
-                filename: payload.filename,
-                location,
-                format: None,
-                hash: String::new(),
-                width: 0,
-                height: 0,
-                status: db::BaseImageStatus::AwaitingUpload,
-                alt_text: payload.alt_text.unwrap_or_default(),
-                placeholder: String::new(),
-            };
+    diesel::insert_into(db::base_images::table)
+        .values(&new_image)
+        .execute(conn)?;
 
-            diesel::insert_into(db::base_images::table)
-                .values(&new_image)
-                .execute(conn)?;
+    Ok((new_image_id, profile.project_id))
+}
 
-            Ok(new_image_id)
-        })
-        .await??;
+/// Render the key prefixes of the upload profile's base and output storage locations for a
+/// new image.
+fn render_key_prefixes(
+    conn: &mut PgConnection,
+    upload_profile_id: UploadProfileId,
+    team_id: TeamId,
+    project_id: ProjectId,
+) -> Result<(Option<String>, Option<String>)> {
+    let (bst, ost) = diesel::alias!(storage_locations as bst, storage_locations as ost);
+    let (base_template, output_template) =
+        upload_profiles::table
+            .inner_join(
+                bst.on(
+                    upload_profiles::base_storage_location_id.eq(bst.field(storage_locations::id))
+                ),
+            )
+            .inner_join(ost.on(
+                upload_profiles::output_storage_location_id.eq(ost.field(storage_locations::id)),
+            ))
+            .filter(upload_profiles::id.eq(upload_profile_id))
+            .select((
+                bst.field(storage_locations::key_prefix_template),
+                ost.field(storage_locations::key_prefix_template),
+            ))
+            .first::<(Option<String>, Option<String>)>(conn)?;
+
+    let team = team_id.display_without_prefix().to_string();
+    let project = project_id.display_without_prefix().to_string();
+    let values = KeyPrefixValues {
+        team: &team,
+        project: &project,
+        date: chrono::Utc::now().date_naive(),
+    };
+    let render = |template: Option<String>| {
+        template
+            .as_deref()
+            .map(KeyPrefixTemplate::parse)
+            .transpose()
+            .map(|template| template.and_then(|t| t.render(&values)))
+    };
 
-    Ok((
-        StatusCode::OK,
-        Json(json!({
-            "id": image_id,
-        })),
-    ))
+    Ok((render(base_template)?, render(output_template)?))
 }
 
 enum BaseImageFetchType {
@@ -180,6 +271,8 @@ async fn get_base_image(
 
         pub status: OutputImageStatus,
         pub updated: chrono::DateTime<chrono::Utc>,
+        pub archival: bool,
+        pub sha256: Option<String>,
     }
 
     #[derive(Debug, Queryable, Selectable)]
@@ -196,8 +289,16 @@ async fn get_base_image(
         pub format: Option<ImageFormat>,
         pub upload_profile_id: UploadProfileId,
         pub status: BaseImageStatus,
+        pub error: Option<String>,
         pub alt_text: String,
         pub placeholder: Option<String>,
+        pub conversion_profile_version: Option<i32>,
+        pub tags: Vec<String>,
+        pub collection: Option<String>,
+        pub alt_text_machine_generated: bool,
+        pub original_removed: Option<chrono::DateTime<chrono::Utc>>,
+        pub pinned: bool,
+        pub sha256: Option<String>,
 
         pub updated: chrono::DateTime<chrono::Utc>,
     }
@@ -219,12 +320,18 @@ async fn get_base_image(
         profile_base_path,
         profile_output_path,
         output_images,
+        references,
+        mirror_copies,
     ) = state
         .db
         .interact(move |conn| {
             let mut query = base_images::table
                 .filter(base_images::deleted.is_null())
-                .filter(base_images::team_id.eq(user.team_id))
+                .filter(base_images::team_id.eq(user.team_id).or(db::obj_granted!(
+                    user.team_id,
+                    base_images::project_id,
+                    base_images::collection
+                )))
                 .inner_join(
                     db::upload_profiles::table
                         .on(base_images::upload_profile_id.eq(upload_profiles::id))
@@ -253,6 +360,7 @@ async fn get_base_image(
                     projects::base_location,
                     upload_profiles::base_storage_location_path,
                     upload_profiles::output_storage_location_path,
+                    base_images::team_id,
                     db::obj_allowed!(
                         user.team_id,
                         &user.roles,
@@ -264,7 +372,14 @@ async fn get_base_image(
 
             query = match lookup {
                 BaseImageFetchType::ById(id) => query.filter(base_images::id.eq(id)),
-                BaseImageFetchType::ByHash(hash) => query.filter(base_images::hash.eq(hash)),
+                // The same file can be in this team's projects and in projects that other teams
+                // have shared with it, so prefer the team's own copy.
+                BaseImageFetchType::ByHash(hash) => {
+                    query.filter(base_images::hash.eq(hash)).order((
+                        base_images::team_id.ne(user.team_id),
+                        base_images::created.desc(),
+                    ))
+                }
             };
 
             let (
@@ -274,6 +389,7 @@ async fn get_base_image(
                 project_base_path,
                 profile_base_location,
                 profile_output_location,
+                owner_team_id,
                 allowed,
             ) = query
                 .first::<(
@@ -283,14 +399,46 @@ async fn get_base_image(
                     String,
                     Option<String>,
                     Option<String>,
+                    TeamId,
                     bool,
                 )>(conn)
                 .optional()
                 .map_err(Error::from)?
                 .ok_or(Error::NotFound)?;
 
-            if !allowed {
-                return Err(Error::NotFound);
+            if owner_team_id == user.team_id {
+                if !allowed {
+                    return Err(Error::NotFound);
+                }
+
+                // Keys bound to an upload profile can only see the images they can upload to.
+                if user
+                    .bound_upload_profile_id
+                    .map(|bound| bound != info.upload_profile_id)
+                    .unwrap_or(false)
+                {
+                    return Err(Error::NotFound);
+                }
+            } else {
+                if user.bound_upload_profile_id.is_some() {
+                    return Err(Error::NotFound);
+                }
+
+                // The image belongs to another team which has shared the project or collection
+                // with this team, so log the access for the owning team to see.
+                let grant = db::project_grants::find_covering(
+                    conn,
+                    info.project_id,
+                    user.team_id,
+                    info.collection.as_deref(),
+                )?
+                .ok_or(Error::NotFound)?;
+                db::project_grants::record_access(
+                    conn,
+                    grant.id,
+                    (user.team_id, user.user_id),
+                    info.id,
+                )?;
             }
 
             let oi = output_images::table
@@ -299,6 +447,10 @@ async fn get_base_image(
                 .load::<OutputImageQueryResult>(conn)
                 .map_err(Error::from)?;
 
+            let references = db::image_references::for_image(conn, info.id)?;
+            let output_ids = oi.iter().map(|o| o.id).collect::<Vec<_>>();
+            let mirror_copies = db::output_image_mirrors::for_outputs(conn, &output_ids)?;
+
             Ok((
                 info,
                 base_storage,
@@ -307,10 +459,21 @@ async fn get_base_image(
                 profile_base_location,
                 profile_output_location,
                 oi,
+                references,
+                mirror_copies,
             ))
         })
         .await?;
 
+    #[derive(Debug, Serialize)]
+    struct MirrorResult {
+        pub storage_location_id: StorageLocationId,
+        pub status: db::OutputMirrorStatus,
+        pub sha256: Option<String>,
+        pub error: Option<String>,
+        pub updated: chrono::DateTime<chrono::Utc>,
+    }
+
     #[derive(Debug, Serialize)]
     struct OutputImageResult {
         pub id: OutputImageId,
@@ -324,6 +487,12 @@ async fn get_base_image(
         pub format: ImageFormat,
 
         pub status: OutputImageStatus,
+        /// An archival master, which is kept for preservation and not served.
+        pub archival: bool,
+        /// The SHA-256 checksum of the stored file, in hex.
+        pub sha256: Option<String>,
+        /// The copies in the upload profile's mirror storage locations.
+        pub mirrors: Vec<MirrorResult>,
 
         pub updated: chrono::DateTime<chrono::Utc>,
     }
@@ -333,6 +502,8 @@ async fn get_base_image(
         pub id: BaseImageId,
         pub project_id: ProjectId,
         pub hash: Option<String>,
+        /// The SHA-256 checksum of the original, in hex.
+        pub sha256: Option<String>,
         pub filename: String,
         pub location: String,
         pub url: String,
@@ -342,12 +513,27 @@ async fn get_base_image(
         pub format: Option<ImageFormat>,
         pub upload_profile_id: UploadProfileId,
         pub status: BaseImageStatus,
+        pub error: Option<String>,
         pub alt_text: String,
+        /// The alt text came from the captioning service and hasn't been reviewed yet.
+        pub alt_text_machine_generated: bool,
         pub placeholder: Option<String>,
+        /// The version of the conversion profile that the output images were generated with.
+        pub conversion_profile_version: Option<i32>,
+        /// Tags applied by the project's tagging rules.
+        pub tags: Vec<String>,
+        pub collection: Option<String>,
+        /// When the upload profile's retention rules removed the original, which can no longer
+        /// be downloaded or converted again.
+        pub original_removed: Option<chrono::DateTime<chrono::Utc>>,
+        /// Pinned images are skipped by bulk operations.
+        pub pinned: bool,
 
         pub updated: chrono::DateTime<chrono::Utc>,
 
         pub output: Vec<OutputImageResult>,
+        /// The site pages that use the image, from sitemap crawls and imported lists.
+        pub referenced_by: Vec<db::image_references::ImageReference>,
     }
 
     let base_image_path = image_path(
@@ -363,6 +549,20 @@ async fn get_base_image(
         &info.location,
     );
 
+    let mut mirrors_by_output: HashMap<OutputImageId, Vec<MirrorResult>> = HashMap::new();
+    for copy in mirror_copies {
+        mirrors_by_output
+            .entry(copy.output_image_id)
+            .or_default()
+            .push(MirrorResult {
+                storage_location_id: copy.storage_location_id,
+                status: copy.status,
+                sha256: copy.sha256,
+                error: copy.error,
+                updated: copy.updated,
+            });
+    }
+
     let output_images = output_images
         .into_iter()
         .map(|o| {
@@ -389,6 +589,9 @@ async fn get_base_image(
                 size_rule: o.size,
                 format: o.format.as_db_image_format(),
                 status: o.status,
+                archival: o.archival,
+                sha256: o.sha256,
+                mirrors: mirrors_by_output.remove(&o.id).unwrap_or_default(),
                 updated: o.updated,
             }
         })
@@ -398,6 +601,7 @@ async fn get_base_image(
         id: info.id,
         project_id: info.project_id,
         hash: info.hash,
+        sha256: info.sha256,
         filename: info.filename,
         location: base_image_path,
         url: base_image_url,
@@ -407,10 +611,18 @@ async fn get_base_image(
         format: info.format,
         upload_profile_id: info.upload_profile_id,
         status: info.status,
+        error: info.error,
         alt_text: info.alt_text,
+        alt_text_machine_generated: info.alt_text_machine_generated,
         placeholder: info.placeholder,
+        conversion_profile_version: info.conversion_profile_version,
+        tags: info.tags,
+        collection: info.collection,
+        original_removed: info.original_removed,
+        pinned: info.pinned,
         updated: info.updated,
         output: output_images,
+        referenced_by: references,
     };
 
     Ok((StatusCode::OK, Json(result)))
@@ -421,24 +633,38 @@ async fn reconvert_base_image(
     Authenticated(user): Authenticated,
     Path(image_id): Path<BaseImageId>,
 ) -> impl IntoResponse {
-    let (base_image_id, base_image_location, base_image_format, conversion_profile) = state
+    let (
+        base_image_id,
+        project_id,
+        base_image_location,
+        base_image_format,
+        output_key_prefix,
+        conversion_profile,
+    ) = state
         .db
         .interact(move |conn| {
             let (
                 base_image_id,
+                project_id,
                 base_image_location,
                 base_image_format,
+                output_key_prefix,
+                original_removed,
                 conversion_profile,
                 allowed,
             ) = base_images::table
                 .filter(base_images::id.eq(image_id))
                 .filter(base_images::deleted.is_null())
+                .filter(base_images::status.ne(BaseImageStatus::TakenDown))
                 .filter(base_images::team_id.eq(user.team_id))
                 .inner_join(upload_profiles::table.inner_join(conversion_profiles::table))
                 .select((
                     base_images::id,
+                    base_images::project_id,
                     base_images::location,
                     base_images::format,
+                    base_images::output_key_prefix,
+                    base_images::original_removed,
                     conversion_profiles::all_columns,
                     db::obj_allowed!(
                         user.team_id,
@@ -449,8 +675,11 @@ async fn reconvert_base_image(
                 ))
                 .first::<(
                     BaseImageId,
+                    ProjectId,
                     String,
                     Option<ImageFormat>,
+                    Option<String>,
+                    Option<chrono::DateTime<chrono::Utc>>,
                     ConversionProfile,
                     bool,
                 )>(conn)
@@ -461,10 +690,16 @@ async fn reconvert_base_image(
                 return Err(Error::MissingPermission(Permission::ImageEdit));
             }
 
+            if original_removed.is_some() {
+                return Err(Error::OriginalUnavailable);
+            }
+
             Ok((
                 base_image_id,
+                project_id,
                 base_image_location,
                 base_image_format,
+                output_key_prefix,
                 conversion_profile,
             ))
         })
@@ -477,34 +712,66 @@ async fn reconvert_base_image(
         ));
     };
 
-    let output_images = generate_output_images(
-        user.team_id,
+    let output_image_ids = regenerate_output_images(
+        &state,
         &conversion_profile,
-        base_image_id,
-        &base_image_location,
-        base_image_format,
-    );
+        &OutputImageBase {
+            team_id: user.team_id,
+            project_id,
+            id: base_image_id,
+            location: &base_image_location,
+            format: base_image_format,
+            key_prefix: output_key_prefix.as_deref(),
+        },
+    )
+    .await?;
 
-    if output_images.is_empty() {
-        return Ok((StatusCode::OK, Json(json!({ "images": [] }))));
-    }
+    Ok::<_, Error>((StatusCode::OK, Json(json!({ "images": output_image_ids }))))
+}
 
+/// Replace the output images of an existing base image with the ones from the conversion
+/// profile, and queue a job to create them.
+pub(crate) async fn regenerate_output_images(
+    state: &AppState,
+    conversion_profile: &ConversionProfile,
+    base_image: &OutputImageBase<'_>,
+) -> Result<Vec<OutputImageId>, Error> {
+    let output_images = generate_output_images(conversion_profile, base_image)?;
+
+    let choose_breakpoints = conversion_profile.output.has_automatic_sizes();
+    let team_id = base_image.team_id;
+    let base_image_id = base_image.id;
+    let conversion_profile_id = conversion_profile.id;
+    let conversion_profile_version = conversion_profile.version;
     let output_image_ids = state
         .db
-        .transaction(move |conn| replace_output_images(conn, user.team_id, image_id, output_images))
+        .transaction(move |conn| {
+            replace_output_images(
+                conn,
+                team_id,
+                base_image_id,
+                (conversion_profile_id, conversion_profile_version),
+                output_images,
+            )
+        })
         .await?;
 
-    let job_id = effectum::Job::builder(crate::jobs::CREATE_OUTPUT_IMAGES)
-        .json_payload(&crate::jobs::CreateOutputImagesJobPayload {
-            base_image: image_id,
-            conversions: output_image_ids.clone(),
-        })?
-        .add_to(&state.queue)
-        .await?;
+    if output_image_ids.is_empty() && !choose_breakpoints {
+        return Ok(output_image_ids);
+    }
 
-    event!(Level::INFO, %job_id, "enqueued image conversion job");
+    conversion_pause::queue_conversion(
+        state,
+        team_id,
+        crate::jobs::CreateOutputImagesJobPayload {
+            base_image: base_image_id,
+            conversions: output_image_ids.clone(),
+            choose_breakpoints,
+        },
+    )
+    .await?;
 
-    Ok::<_, Error>((StatusCode::OK, Json(json!({ "images": output_image_ids }))))
+    Ok(output_image_ids)
 }
 
 async fn remove_base_image() -> impl IntoResponse {
@@ -513,67 +780,277 @@ async fn remove_base_image() -> impl IntoResponse {
     todo!();
 }
 
-async fn update_base_image_info() -> impl IntoResponse {
-    todo!();
+#[derive(Debug, Deserialize)]
+struct UpdateBaseImageInput {
+    alt_text: String,
 }
 
-fn generate_output_images(
-    team_id: TeamId,
+/// Update an image's alt text. This also marks generated alt text as reviewed, so sending the
+/// existing alt text back confirms it.
+async fn update_base_image_info(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(image_id): Path<BaseImageId>,
+    Json(body): Json<UpdateBaseImageInput>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .interact(move |conn| {
+            let allowed = base_images::table
+                .filter(base_images::id.eq(image_id))
+                .filter(base_images::team_id.eq(user.team_id))
+                .filter(base_images::deleted.is_null())
+                .select(db::obj_allowed!(
+                    user.team_id,
+                    &user.roles,
+                    base_images::project_id.assume_not_null(),
+                    Permission::ImageEdit
+                ))
+                .first::<bool>(conn)
+                .optional()?
+                .ok_or(Error::NotFound)?;
+
+            if !allowed {
+                return Err(Error::MissingPermission(Permission::ImageEdit));
+            }
+
+            diesel::update(base_images::table)
+                .filter(base_images::id.eq(image_id))
+                .set((
+                    base_images::alt_text.eq(body.alt_text.trim()),
+                    base_images::alt_text_machine_generated.eq(false),
+                    base_images::updated.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            Ok(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+/// Pin an image, so that bulk deletions, bulk re-renders, and retention rules leave it alone.
+async fn pin_base_image(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(image_id): Path<BaseImageId>,
+) -> Result<impl IntoResponse> {
+    set_pinned(state, user, image_id, true).await
+}
+
+/// Unpin an image, which lets bulk operations affect it again.
+async fn unpin_base_image(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(image_id): Path<BaseImageId>,
+) -> Result<impl IntoResponse> {
+    set_pinned(state, user, image_id, false).await
+}
+
+async fn set_pinned(
+    state: AppState,
+    user: UserInfo,
+    image_id: BaseImageId,
+    pinned: bool,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .interact(move |conn| {
+            let allowed = base_images::table
+                .filter(base_images::id.eq(image_id))
+                .filter(base_images::team_id.eq(user.team_id))
+                .filter(base_images::deleted.is_null())
+                .select(db::obj_allowed!(
+                    user.team_id,
+                    &user.roles,
+                    base_images::project_id.assume_not_null(),
+                    Permission::ImageEdit
+                ))
+                .first::<bool>(conn)
+                .optional()?
+                .ok_or(Error::NotFound)?;
+
+            if !allowed {
+                return Err(Error::MissingPermission(Permission::ImageEdit));
+            }
+
+            diesel::update(base_images::table)
+                .filter(base_images::id.eq(image_id))
+                .set((
+                    base_images::pinned.eq(pinned),
+                    base_images::updated.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            Ok(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({ "pinned": pinned }))))
+}
+
+/// Information about a base image needed to generate its output images.
+pub(crate) struct OutputImageBase<'a> {
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub id: BaseImageId,
+    pub location: &'a str,
+    pub format: ImageFormat,
+    /// The rendered key prefix of the output storage location.
+    pub key_prefix: Option<&'a str>,
+}
+
+impl<'a> OutputImageBase<'a> {
+    /// The name of the base image file, without its key prefix or extension.
+    fn basename(&self) -> &'a str {
+        let filename = match self.location.rsplit_once('/') {
+            Some((_prefix, filename)) => filename,
+            None => self.location,
+        };
+
+        match filename.rsplit_once('.') {
+            Some((base, _ext)) => base,
+            None => filename,
+        }
+    }
+}
+
+pub(crate) fn generate_output_images(
     conversion_profile: &ConversionProfile,
-    base_image_id: BaseImageId,
-    base_image_location: &str,
-    base_image_format: ImageFormat,
-) -> Vec<NewOutputImage> {
-    let basename = match base_image_location.rsplit_once('.') {
-        Some((base, _ext)) => base,
-        None => base_image_location,
+    base_image: &OutputImageBase,
+) -> Result<Vec<NewOutputImage>, KeyTemplateError> {
+    let mut images = match &conversion_profile.output {
+        ConversionOutput::Cross { formats, sizes, .. } => build_output_images(
+            formats,
+            sizes,
+            conversion_profile.output_key_template.as_deref(),
+            base_image,
+        )?,
+        ConversionOutput::PerFormat { outputs, .. } => {
+            let mut images = Vec::new();
+            for output in outputs {
+                images.extend(build_output_images(
+                    std::slice::from_ref(&output.format),
+                    &output.sizes,
+                    conversion_profile.output_key_template.as_deref(),
+                    base_image,
+                )?);
+            }
+            images
+        }
+        // The sizes are chosen by the conversion worker once it has looked at the image.
+        ConversionOutput::Auto { .. } => Vec::new(),
     };
 
-    let output_images = match &conversion_profile.output {
-        ConversionOutput::Cross { formats, sizes, .. } => formats
-            .iter()
-            .filter(|format| format.matches_condition(base_image_format))
-            .flat_map(|format| {
-                sizes.iter().map(|size| {
-                    let size_str = match (size.width, size.height) {
-                        (Some(w), Some(h)) => format!("{w}x{h}"),
-                        (Some(w), None) => format!("w{w}"),
-                        (None, Some(h)) => format!("h{h}"),
-                        (None, None) => "szun".to_string(),
-                    };
-
-                    let output_image_id = OutputImageId::new();
-                    let location = format!(
-                        "{basename}-{size_str}-{}.{}",
-                        base_image_id.display_without_prefix(),
-                        format.extension()
-                    );
-
-                    NewOutputImage {
-                        id: output_image_id,
-                        base_image_id,
-                        width: None,
-                        height: None,
-                        size: size.clone(),
-                        format: format.clone(),
-                        team_id,
-                        status: db::OutputImageStatus::Queued,
-                        location,
-                    }
+    images.extend(archival_output_image(
+        &conversion_profile.output,
+        base_image,
+    ));
+    Ok(images)
+}
+
+/// Build the archival master for the image, if the conversion profile asks for one. Archival
+/// masters always use the default layout, since key templates are designed around the
+/// delivery outputs.
+pub(crate) fn archival_output_image(
+    output: &ConversionOutput,
+    base_image: &OutputImageBase,
+) -> Option<NewOutputImage> {
+    let format = output.archival()?.conversion_format();
+    let basename = base_image.basename();
+    let image_id = base_image.id.display_without_prefix();
+    let location = format!("{basename}-archival-{image_id}.{}", format.extension());
+
+    Some(NewOutputImage {
+        id: OutputImageId::new(),
+        base_image_id: base_image.id,
+        width: None,
+        height: None,
+        size: ConversionSize::default(),
+        location: with_key_prefix(base_image.key_prefix, &location),
+        format,
+        team_id: base_image.team_id,
+        status: db::OutputImageStatus::Queued,
+        archival: true,
+    })
+}
+
+pub(crate) fn build_output_images(
+    formats: &[ConversionFormat],
+    sizes: &[ConversionSize],
+    key_template: Option<&str>,
+    base_image: &OutputImageBase,
+) -> Result<Vec<NewOutputImage>, KeyTemplateError> {
+    let basename = base_image.basename();
+
+    let key_template = key_template.map(KeyTemplate::parse).transpose()?;
+    let project_id = base_image.project_id.display_without_prefix().to_string();
+    let image_id = base_image.id.display_without_prefix().to_string();
+
+    let output_images = formats
+        .iter()
+        .filter(|format| format.matches_condition(base_image.format))
+        .flat_map(|format| {
+            sizes.iter().map(|size| {
+                let size_str = match (size.width, size.height) {
+                    (Some(w), Some(h)) => format!("{w}x{h}"),
+                    (Some(w), None) => format!("w{w}"),
+                    (None, Some(h)) => format!("h{h}"),
+                    (None, None) => "szun".to_string(),
+                };
+
+                let output_image_id = OutputImageId::new();
+                let location = match &key_template {
+                    Some(template) => template.render(&KeyTemplateValues {
+                        project: &project_id,
+                        image_id: &image_id,
+                        basename,
+                        width: size.width,
+                        height: size.height,
+                        size: &size_str,
+                        ext: format.extension(),
+                    })?,
+                    None => format!("{basename}-{size_str}-{image_id}.{}", format.extension()),
+                };
+                let location = with_key_prefix(base_image.key_prefix, &location);
+
+                Ok(NewOutputImage {
+                    id: output_image_id,
+                    base_image_id: base_image.id,
+                    width: None,
+                    height: None,
+                    size: size.clone(),
+                    format: format.clone(),
+                    team_id: base_image.team_id,
+                    status: db::OutputImageStatus::Queued,
+                    location,
+                    archival: false,
                 })
             })
-            .collect::<Vec<_>>(),
-    };
+        })
+        .collect::<Result<Vec<_>, KeyTemplateError>>()?;
 
-    output_images
+    Ok(output_images)
 }
 
-fn replace_output_images(
+/// Replace the output images for a base image, and record the conversion profile version that
+/// they came from.
+pub(crate) fn replace_output_images(
     conn: &mut PgConnection,
     team_id: TeamId,
     base_image_id: BaseImageId,
+    (conversion_profile_id, conversion_profile_version): (ConversionProfileId, i32),
     output_images: Vec<NewOutputImage>,
 ) -> Result<Vec<OutputImageId>, eyre::Report> {
+    diesel::update(base_images::table)
+        .filter(base_images::id.eq(base_image_id))
+        .set((
+            base_images::conversion_profile_id.eq(conversion_profile_id),
+            base_images::conversion_profile_version.eq(conversion_profile_version),
+        ))
+        .execute(conn)?;
+
     let output_image_locations = output_images
         .iter()
         .map(|oi| &oi.location)
@@ -590,6 +1067,10 @@ fn replace_output_images(
         .set((output_images::status.eq(db::OutputImageStatus::QueuedForDelete),))
         .execute(conn)?;
 
+    if output_images.is_empty() {
+        return Ok(Vec::new());
+    }
+
     let output_image_ids = diesel::insert_into(db::output_images::table)
         .values(&output_images)
         .on_conflict((output_images::base_image_id, output_images::location))
@@ -609,18 +1090,84 @@ fn replace_output_images(
 pub fn configure() -> Router<AppState> {
     let routes = Router::new()
         .route("/", post(new_base_image))
+        .route("/from_url", post(from_url::upload_from_url))
         .route("/:image_id", get(get_base_image_by_id))
         .route("/:image_id", put(update_base_image_info))
         .route("/:image_id", delete(remove_base_image))
-        .route("/:image_id/reconvert", post(reconvert_base_image));
+        .route("/:image_id/bundle", get(bundle::download_bundle))
+        .route("/:image_id/original", get(original::download_original))
+        .route("/:image_id/pin", post(pin_base_image))
+        .route("/:image_id/pin", delete(unpin_base_image))
+        .route("/:image_id/purge", post(purge::purge_image))
+        .route("/:image_id/reconvert", post(reconvert_base_image))
+        .route("/:image_id/signed_url", post(signed_url::create_signed_url))
+        .route("/:image_id/upload/presign", post(upload::presign_upload))
+        .route("/:image_id/upload/complete", post(upload::complete_upload))
+        .route(
+            "/:image_id/upload/chunked",
+            post(chunked_upload::start_chunked_upload),
+        )
+        .route(
+            "/:image_id/upload/chunked/:upload_id",
+            get(chunked_upload::get_chunked_upload).delete(chunked_upload::abort_chunked_upload),
+        )
+        .route(
+            "/:image_id/upload/chunked/:upload_id/complete",
+            post(chunked_upload::complete_chunked_upload),
+        );
 
     let upload_route = Router::new()
         .route("/:image_id/upload", post(upload::upload_image))
-        .layer(DefaultBodyLimit::max(250 * 1048576));
+        .route(
+            "/:image_id/upload/chunked/:upload_id/:index",
+            put(chunked_upload::put_chunk),
+        )
+        // The upload route enforces the configured maximum size itself.
+        .layer(DefaultBodyLimit::disable());
 
     let image_id_routes = Router::new().nest("/images", routes.merge(upload_route));
 
     Router::new()
         .route("/image_by_hash/:hash", get(get_base_image_by_hash))
+        .route(
+            "/projects/:project_id/images/export",
+            get(export::export_images),
+        )
+        .route(
+            "/projects/:project_id/images/export.csv",
+            get(export::export_images_csv),
+        )
+        .route(
+            "/projects/:project_id/upload_profiles/:upload_profile_id/ingest",
+            post(ingest::ingest_bucket_notification),
+        )
+        .route(
+            "/projects/:project_id/images/search",
+            get(search::search_images),
+        )
+        .route(
+            "/projects/:project_id/images/bulk_delete/preview",
+            post(bulk_delete::preview_bulk_delete),
+        )
+        .route(
+            "/projects/:project_id/images/bulk_delete/:bulk_deletion_id",
+            get(bulk_delete::get_bulk_delete),
+        )
+        .route(
+            "/projects/:project_id/images/bulk_delete/:bulk_deletion_id/confirm",
+            post(bulk_delete::confirm_bulk_delete),
+        )
+        .route(
+            "/projects/:project_id/image_references",
+            post(references::import_references),
+        )
+        .route(
+            "/projects/:project_id/image_references/crawl",
+            post(references::start_crawl),
+        )
+        .route(
+            "/projects/:project_id/image_references/crawl/:crawl_id",
+            get(references::get_crawl),
+        )
         .merge(image_id_routes)
 }
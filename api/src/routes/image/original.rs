@@ -0,0 +1,127 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue},
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use db::{
+    base_images, image_base_location,
+    object_id::{BaseImageId, ProjectId},
+    projects, storage_locations, upload_profiles, BaseImageStatus, ImageFormat, Permission,
+    PoolExt,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use pic_store_storage as storage;
+
+use crate::{
+    auth::Authenticated,
+    range::{self, RangedBody},
+    routes::serve::content_type,
+    shared_state::AppState,
+    Error, Result,
+};
+
+/// Download the originally uploaded image. This supports Range requests so that large
+/// downloads can be resumed.
+pub async fn download_original(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(image_id): Path<BaseImageId>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let (
+        location,
+        format,
+        hash,
+        original_removed,
+        storage_location,
+        project_base_location,
+        profile_path,
+        project_id,
+        file_size,
+        allowed,
+    ) = state
+        .db
+        .interact(move |conn| {
+            base_images::table
+                .inner_join(upload_profiles::table.inner_join(
+                    storage_locations::table.on(
+                        storage_locations::id.eq(upload_profiles::base_storage_location_id),
+                    ),
+                ))
+                .inner_join(projects::table.on(projects::id.eq(base_images::project_id)))
+                .filter(base_images::id.eq(image_id))
+                .filter(base_images::team_id.eq(user.team_id))
+                .filter(base_images::deleted.is_null())
+                .filter(base_images::status.ne(BaseImageStatus::TakenDown))
+                .select((
+                    base_images::location,
+                    base_images::format,
+                    base_images::hash,
+                    base_images::original_removed,
+                    storage_locations::all_columns,
+                    projects::base_location,
+                    upload_profiles::base_storage_location_path,
+                    base_images::project_id,
+                    base_images::file_size,
+                    db::obj_allowed!(
+                        user.team_id,
+                        &user.roles,
+                        base_images::project_id.assume_not_null(),
+                        Permission::ProjectRead
+                    ),
+                ))
+                .first::<(
+                    String,
+                    Option<ImageFormat>,
+                    Option<String>,
+                    Option<DateTime<Utc>>,
+                    storage_locations::StorageLocation,
+                    String,
+                    Option<String>,
+                    ProjectId,
+                    i32,
+                    bool,
+                )>(conn)
+                .optional()?
+                .ok_or(Error::NotFound)
+        })
+        .await?;
+
+    if !allowed {
+        return Err(Error::MissingPermission(Permission::ProjectRead));
+    }
+
+    // The image has not been uploaded yet.
+    let format = format.ok_or(Error::NotFound)?;
+    if original_removed.is_some() {
+        return Err(Error::OriginalUnavailable);
+    }
+
+    let provider = storage::Provider::from_db(storage_location.provider)?;
+    let operator = provider
+        .create_operator(&image_base_location(
+            &storage_location.base_location,
+            &project_base_location,
+            &profile_path,
+        ))
+        .await?;
+
+    let range = range::requested_range(&headers, hash.as_deref(), None);
+    let body = RangedBody::from_storage(&operator, &location, range).await?;
+    state.metering.record_served(
+        user.team_id,
+        project_id,
+        body.content_length().unwrap_or(file_size as usize),
+    );
+    let mut response = body.into_response(content_type(format));
+
+    if let Some(hash) = hash {
+        if let Ok(value) = HeaderValue::from_str(&format!("\"{hash}\"")) {
+            response.headers_mut().insert(header::ETAG, value);
+        }
+    }
+
+    Ok(response)
+}
@@ -0,0 +1,95 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use db::{
+    base_images, image_path,
+    object_id::BaseImageId,
+    output_images, projects,
+    storage_locations::{self, CdnPurge},
+    upload_profiles, OutputImageStatus, Permission, PoolExt,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde_json::json;
+
+use crate::{auth::Authenticated, cdn_purge::PurgeTarget, shared_state::AppState, Error, Result};
+
+/// Purge an image's output images from the CDN configured on its output storage location.
+pub async fn purge_image(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(image_id): Path<BaseImageId>,
+) -> Result<impl IntoResponse> {
+    let (cdn_purge, urls) = state
+        .db
+        .interact(move |conn| {
+            let (public_url_base, cdn_purge, project_base_location, profile_path, allowed) =
+                base_images::table
+                    .inner_join(upload_profiles::table.inner_join(
+                        storage_locations::table.on(
+                            storage_locations::id.eq(upload_profiles::output_storage_location_id),
+                        ),
+                    ))
+                    .inner_join(projects::table.on(projects::id.eq(base_images::project_id)))
+                    .filter(base_images::id.eq(image_id))
+                    .filter(base_images::team_id.eq(user.team_id))
+                    .filter(base_images::deleted.is_null())
+                    .select((
+                        storage_locations::public_url_base,
+                        storage_locations::cdn_purge,
+                        projects::base_location,
+                        upload_profiles::output_storage_location_path,
+                        db::obj_allowed!(
+                            user.team_id,
+                            &user.roles,
+                            base_images::project_id.assume_not_null(),
+                            Permission::ImageEdit
+                        ),
+                    ))
+                    .first::<(String, Option<CdnPurge>, String, Option<String>, bool)>(conn)
+                    .optional()?
+                    .ok_or(Error::NotFound)?;
+
+            if !allowed {
+                return Err(Error::MissingPermission(Permission::ImageEdit));
+            }
+
+            let cdn_purge = cdn_purge.ok_or(Error::CdnPurgeNotConfigured)?;
+
+            let urls = output_images::table
+                .filter(output_images::base_image_id.eq(image_id))
+                .filter(output_images::status.eq(OutputImageStatus::Ready))
+                .select(output_images::location)
+                .load::<String>(conn)?
+                .into_iter()
+                .map(|location| {
+                    image_path(
+                        &public_url_base,
+                        &project_base_location,
+                        &profile_path,
+                        &location,
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            Ok((cdn_purge, urls))
+        })
+        .await?;
+
+    state
+        .cdn_purger
+        .purge(
+            &cdn_purge,
+            &PurgeTarget {
+                image_id,
+                urls: &urls,
+            },
+        )
+        .await
+        .map_err(Error::CdnPurge)?;
+
+    Ok((StatusCode::OK, Json(json!({ "urls": urls }))))
+}
@@ -0,0 +1,250 @@
+//! Recording the site pages that use a project's images, from an uploaded list or a sitemap
+//! crawl. The references for an image are returned with the image's details.
+
+use std::collections::BTreeSet;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use db::{
+    image_references::{self, NewImageReference},
+    object_id::{ProjectId, ReferenceCrawlId},
+    permissions::ProjectPermission,
+    reference_crawls::{self, NewReferenceCrawl, ReferenceCrawl},
+    ImageReferenceSource, PoolExt, ReferenceCrawlStatus,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use tracing::{event, Level};
+
+use crate::{
+    auth::{must_own_project, Authenticated},
+    image_references::resolve_image_urls,
+    jobs::{CrawlImageReferencesJobPayload, CRAWL_IMAGE_REFERENCES},
+    json::Json,
+    remote_fetch,
+    shared_state::AppState,
+    Error, Result,
+};
+
+/// The most references that can be imported in one request.
+const MAX_IMPORT: usize = 10_000;
+
+const DEFAULT_CRAWL_PAGES: i32 = 1_000;
+const MAX_CRAWL_PAGES: i32 = 10_000;
+
+#[derive(Debug, Deserialize)]
+pub struct ReferenceInput {
+    page_url: String,
+    image_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportInput {
+    references: Vec<ReferenceInput>,
+}
+
+#[derive(Debug, Serialize)]
+struct ImportOutput {
+    /// The number of references to the project's images that were saved.
+    recorded: usize,
+    /// The image URLs that didn't match any image in the project.
+    unmatched: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CrawlInput {
+    sitemap_url: String,
+    max_pages: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReferenceCrawlOutput {
+    id: ReferenceCrawlId,
+    project_id: ProjectId,
+    sitemap_url: String,
+    max_pages: i32,
+    status: ReferenceCrawlStatus,
+    pages_scanned: i32,
+    references_found: i32,
+    error: Option<String>,
+    created: DateTime<Utc>,
+    finished: Option<DateTime<Utc>>,
+}
+
+impl From<ReferenceCrawl> for ReferenceCrawlOutput {
+    fn from(crawl: ReferenceCrawl) -> Self {
+        ReferenceCrawlOutput {
+            id: crawl.id,
+            project_id: crawl.project_id,
+            sitemap_url: crawl.sitemap_url,
+            max_pages: crawl.max_pages,
+            status: crawl.status,
+            pages_scanned: crawl.pages_scanned,
+            references_found: crawl.references_found,
+            error: crawl.error,
+            created: crawl.created,
+            finished: crawl.finished,
+        }
+    }
+}
+
+/// Record a list of the pages that use the project's images.
+pub async fn import_references(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Json(body): Json<ImportInput>,
+) -> Result<impl IntoResponse> {
+    if body.references.len() > MAX_IMPORT {
+        return Err(Error::InvalidImageReferences(
+            "at most 10000 references can be imported at once",
+        ));
+    }
+
+    if body
+        .references
+        .iter()
+        .any(|r| r.page_url.trim().is_empty() || r.image_url.trim().is_empty())
+    {
+        return Err(Error::InvalidImageReferences(
+            "every reference needs a page_url and an image_url",
+        ));
+    }
+
+    let output = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            let image_urls = body
+                .references
+                .iter()
+                .map(|r| r.image_url.clone())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>();
+            let resolved = resolve_image_urls(conn, project_id, &image_urls)?;
+
+            // The same page can list an image under several URLs.
+            let pairs = body
+                .references
+                .iter()
+                .filter_map(|r| {
+                    resolved
+                        .get(&r.image_url)
+                        .map(|id| (*id, r.page_url.trim().to_string()))
+                })
+                .collect::<BTreeSet<_>>();
+
+            let references = pairs
+                .into_iter()
+                .map(|(base_image_id, page_url)| NewImageReference {
+                    base_image_id,
+                    team_id: user.team_id,
+                    project_id,
+                    page_url,
+                    source: ImageReferenceSource::Import,
+                })
+                .collect::<Vec<_>>();
+            image_references::record(conn, &references)?;
+
+            let unmatched = image_urls
+                .into_iter()
+                .filter(|url| !resolved.contains_key(url))
+                .collect();
+
+            Ok::<_, Error>(ImportOutput {
+                recorded: references.len(),
+                unmatched,
+            })
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(output)))
+}
+
+/// Start crawling a sitemap for the pages that use the project's images.
+pub async fn start_crawl(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Json(body): Json<CrawlInput>,
+) -> Result<impl IntoResponse> {
+    if !state.reference_crawler {
+        return Err(Error::InvalidImageReferences(
+            "crawling is not enabled on this server",
+        ));
+    }
+
+    let sitemap_url = remote_fetch::parse_url(&body.sitemap_url)
+        .map_err(|_| Error::InvalidImageReferences("sitemap_url must be an http or https URL"))?;
+
+    let max_pages = body.max_pages.unwrap_or(DEFAULT_CRAWL_PAGES);
+    if !(1..=MAX_CRAWL_PAGES).contains(&max_pages) {
+        return Err(Error::InvalidImageReferences(
+            "max_pages must be between 1 and 10000",
+        ));
+    }
+
+    let crawl_id = ReferenceCrawlId::new();
+    let crawl = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            diesel::insert_into(reference_crawls::table)
+                .values(NewReferenceCrawl {
+                    id: crawl_id,
+                    team_id: user.team_id,
+                    project_id,
+                    user_id: user.user_id,
+                    sitemap_url: sitemap_url.to_string(),
+                    max_pages,
+                })
+                .returning(ReferenceCrawl::as_select())
+                .get_result(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    let job_id = effectum::Job::builder(CRAWL_IMAGE_REFERENCES)
+        .json_payload(&CrawlImageReferencesJobPayload { crawl: crawl_id })?
+        .add_to(&state.queue)
+        .await?;
+
+    event!(Level::INFO, %project_id, %crawl_id, %job_id, "Started reference crawl");
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(ReferenceCrawlOutput::from(crawl)),
+    ))
+}
+
+/// Get the progress of a crawl.
+pub async fn get_crawl(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path((project_id, crawl_id)): Path<(ProjectId, ReferenceCrawlId)>,
+) -> Result<impl IntoResponse> {
+    let crawl = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectRead)?;
+
+            reference_crawls::table
+                .filter(reference_crawls::id.eq(crawl_id))
+                .filter(reference_crawls::project_id.eq(project_id))
+                .select(ReferenceCrawl::as_select())
+                .first(conn)
+                .optional()?
+                .ok_or(Error::NotFound)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(ReferenceCrawlOutput::from(crawl))))
+}
@@ -0,0 +1,114 @@
+//! Full-text search over the filename, alt text, and OCR text of a project's images, and lookups
+//! by the payload of a QR code or barcode in the image.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use db::{
+    base_images::{self, DetectedCodes},
+    object_id::{BaseImageId, ProjectId},
+    permissions::ProjectPermission,
+    BaseImageStatus, PoolExt,
+};
+use diesel::{
+    dsl::sql,
+    prelude::*,
+    sql_types::{Bool, Text},
+};
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::{must_own_project, Authenticated},
+    json::Json,
+    shared_state::AppState,
+    Error, Result,
+};
+
+/// The searched text. This must match the expression in the `base_images_search` index.
+const SEARCH_DOCUMENT: &str =
+    "to_tsvector('simple', filename || ' ' || alt_text || ' ' || coalesce(ocr_text, ''))";
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    /// The search, in the web search syntax, such as `"error message" -warning`.
+    q: Option<String>,
+    /// Only return images with a code that has exactly this payload.
+    code: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = base_images)]
+struct SearchResult {
+    id: BaseImageId,
+    filename: String,
+    alt_text: String,
+    ocr_text: Option<String>,
+    codes: DetectedCodes,
+    tags: Vec<String>,
+    collection: Option<String>,
+    status: BaseImageStatus,
+    updated: chrono::DateTime<chrono::Utc>,
+}
+
+pub async fn search_images(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Query(query): Query<SearchQuery>,
+) -> Result<impl IntoResponse> {
+    let search = query
+        .q
+        .map(|q| q.trim().to_string())
+        .filter(|q| !q.is_empty());
+    let code = query.code.filter(|c| !c.is_empty());
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let results = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectRead)?;
+
+            if search.is_none() && code.is_none() {
+                return Ok(Vec::new());
+            }
+
+            let mut q = base_images::table
+                .filter(base_images::project_id.eq(project_id))
+                .filter(base_images::deleted.is_null())
+                .into_boxed();
+
+            if let Some(search) = search {
+                q = q.filter(
+                    sql::<Bool>(&format!(
+                        "{SEARCH_DOCUMENT} @@ websearch_to_tsquery('simple', "
+                    ))
+                    .bind::<Text, _>(search)
+                    .sql(")"),
+                );
+            }
+
+            if let Some(code) = code {
+                q = q.filter(
+                    sql::<Bool>("codes @> jsonb_build_array(jsonb_build_object('payload', ")
+                        .bind::<Text, _>(code)
+                        .sql("::text))"),
+                );
+            }
+
+            q.order(base_images::updated.desc())
+                .limit(limit)
+                .select(SearchResult::as_select())
+                .load(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(results)))
+}
@@ -0,0 +1,92 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use chrono::{Duration, Utc};
+use db::{base_images, object_id::BaseImageId, upload_profiles, ImageFormat, Permission, PoolExt};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    auth::Authenticated,
+    json::Json,
+    shared_state::AppState,
+    signed_url::{SignedParams, DEFAULT_TTL_SECONDS},
+    Error, Result,
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SignedUrlInput {
+    /// How long the URL should last. This is capped at the upload profile's TTL.
+    ttl_seconds: Option<i64>,
+    width: Option<u32>,
+    format: Option<ImageFormat>,
+    quality: Option<u8>,
+}
+
+/// Create a URL that can be used to fetch an image from the serve route until it expires.
+pub async fn create_signed_url(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(image_id): Path<BaseImageId>,
+    Json(body): Json<Option<SignedUrlInput>>,
+) -> Result<impl IntoResponse> {
+    let body = body.unwrap_or_default();
+    let signer = state.url_signer.as_ref().ok_or(Error::SignedUrlsDisabled)?;
+
+    let (profile_ttl, allowed) = state
+        .db
+        .interact(move |conn| {
+            base_images::table
+                .inner_join(upload_profiles::table)
+                .filter(base_images::id.eq(image_id))
+                .filter(base_images::team_id.eq(user.team_id))
+                .filter(base_images::deleted.is_null())
+                .select((
+                    upload_profiles::signed_url_ttl_seconds,
+                    db::obj_allowed!(
+                        user.team_id,
+                        &user.roles,
+                        base_images::project_id.assume_not_null(),
+                        Permission::ProjectRead
+                    ),
+                ))
+                .first::<(Option<i32>, bool)>(conn)
+                .optional()?
+                .ok_or(Error::NotFound)
+        })
+        .await?;
+
+    if !allowed {
+        return Err(Error::MissingPermission(Permission::ProjectRead));
+    }
+
+    let max_ttl = profile_ttl.map(i64::from).unwrap_or(DEFAULT_TTL_SECONDS);
+    let ttl = body.ttl_seconds.unwrap_or(max_ttl).clamp(1, max_ttl.max(1));
+    let expires = Utc::now() + Duration::seconds(ttl);
+    let params = SignedParams {
+        width: body.width,
+        height: None,
+        format: body.format,
+        quality: body.quality,
+    };
+    let signature = signer.sign(image_id, expires.timestamp(), &params);
+
+    let url = format!(
+        "/serve/{image_id}?expires={}&signature={signature}{}",
+        expires.timestamp(),
+        params.query_string()
+    );
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "url": url,
+            "expires": expires,
+        })),
+    ))
+}
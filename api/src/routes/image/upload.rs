@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use axum::{
     extract::{BodyStream, Path, State},
     http::StatusCode,
@@ -5,26 +7,36 @@ use axum::{
     Json,
 };
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use db::{
-    base_images::BaseImage, conversion_profiles, image_base_location, object_id::BaseImageId,
-    projects, Permission, PoolExt,
+    base_images::BaseImage,
+    conversion_profiles, image_base_location,
+    object_id::{BaseImageId, TeamId},
+    projects,
+    upload_profiles::ImageConstraints,
+    BaseImageStatus, Permission, PoolExt,
 };
 use diesel::prelude::*;
 use futures::TryStreamExt;
 use imageinfo::{ImageFormat, ImageInfo, ImageInfoError};
 use pic_store_db as db;
 use pic_store_storage as storage;
+use serde::Serialize;
 use serde_json::json;
-use tokio::io::{AsyncWrite, AsyncWriteExt};
+use sha2::{Digest, Sha256};
 use tracing::{event, Level};
 
 use crate::{
-    auth::Authenticated,
-    routes::image::{generate_output_images, replace_output_images},
+    auth::{Authenticated, UserInfo},
+    checksum, conversion_pause, labels,
+    routes::image::{generate_output_images, replace_output_images, OutputImageBase},
     shared_state::AppState,
     Error,
 };
 
+/// How long a presigned upload URL lasts.
+const PRESIGNED_UPLOAD_TTL: Duration = Duration::from_secs(15 * 60);
+
 struct Header {
     buf: HeaderBuf,
 }
@@ -98,58 +110,157 @@ impl Header {
     }
 }
 
-async fn handle_upload(
-    upload: &mut Box<dyn AsyncWrite + Unpin + Send>,
-    mut stream: BodyStream,
-) -> Result<(String, usize, ImageInfo), Error> {
-    let mut hasher = blake3::Hasher::new();
+/// The results of streaming an upload to storage.
+pub(super) struct UploadedImage {
+    hash: String,
+    pub(super) sha256: String,
+    size: usize,
+    info: ImageInfo,
+}
 
-    let mut header = Header::new();
-    let mut total_size = 0;
-    let mut info: Option<ImageInfo> = None;
+/// Hashes an original as it streams by, and reads its format and dimensions from the header.
+pub(super) struct UploadInspector<'a> {
+    hasher: blake3::Hasher,
+    sha256: Sha256,
+    header: Header,
+    total_size: usize,
+    info: Option<ImageInfo>,
+    max_size: usize,
+    constraints: Option<&'a ImageConstraints>,
+}
 
-    while let Some(chunk) = stream.try_next().await? {
-        hasher.update(&chunk);
-        total_size += chunk.len();
-
-        if info.is_none() {
-            header.add_chunk(&chunk);
-            if header.ready() {
-                let i = header.parse()?;
-                info = Some(i);
+impl<'a> UploadInspector<'a> {
+    pub(super) fn new(max_size: usize, constraints: Option<&'a ImageConstraints>) -> Self {
+        UploadInspector {
+            hasher: blake3::Hasher::new(),
+            sha256: Sha256::new(),
+            header: Header::new(),
+            total_size: 0,
+            info: None,
+            max_size,
+            constraints,
+        }
+    }
+
+    pub(super) fn add_chunk(&mut self, chunk: &Bytes) -> Result<(), Error> {
+        self.hasher.update(chunk);
+        self.sha256.update(chunk);
+        self.total_size += chunk.len();
+        if self.total_size > self.max_size {
+            return Err(Error::RequestTooLarge);
+        }
+
+        if self.info.is_none() {
+            self.header.add_chunk(chunk);
+            if self.header.ready() {
+                let i = self.header.parse()?;
+                // Check the dimensions as soon as we know them, so that a rejected image
+                // doesn't have to finish uploading.
+                if let Some(constraints) = self.constraints {
+                    let violations = constraints.check(i.size.width as u32, i.size.height as u32);
+                    if !violations.is_empty() {
+                        return Err(Error::ImageConstraintViolation(violations));
+                    }
+                }
+                self.info = Some(i);
             }
         }
 
-        upload.write_all(&chunk).await?;
+        Ok(())
     }
 
-    let info = info.ok_or(Error::ImageHeaderDecode(ImageInfoError::UnrecognizedFormat))?;
+    pub(super) fn finish(self) -> Result<UploadedImage, Error> {
+        let info = self
+            .info
+            .ok_or(Error::ImageHeaderDecode(ImageInfoError::UnrecognizedFormat))?;
+
+        Ok(UploadedImage {
+            hash: self.hasher.finalize().to_string(),
+            sha256: checksum::to_hex(&self.sha256.finalize()),
+            size: self.total_size,
+            info,
+        })
+    }
+}
 
-    let hash = hasher.finalize();
-    let hash_hex = hash.to_string();
+async fn handle_upload(
+    upload: &mut storage::Upload,
+    mut stream: BodyStream,
+    max_size: usize,
+    constraints: Option<&ImageConstraints>,
+) -> Result<UploadedImage, Error> {
+    let mut inspector = UploadInspector::new(max_size, constraints);
+    while let Some(chunk) = stream.try_next().await? {
+        inspector.add_chunk(&chunk)?;
+        upload.write(&chunk).await?;
+    }
 
-    Ok((hash_hex, total_size, info))
+    inspector.finish()
 }
 
-pub async fn upload_image(
-    State(state): State<AppState>,
-    Authenticated(user): Authenticated,
-    Path(image_id): Path<BaseImageId>,
-    stream: BodyStream,
-) -> Result<impl IntoResponse, Error> {
+/// Read back an original that the client uploaded straight to storage.
+pub(super) async fn inspect_stored(
+    operator: &storage::Operator,
+    location: &str,
+    max_size: usize,
+    constraints: Option<&ImageConstraints>,
+) -> Result<UploadedImage, Error> {
+    // Check the size first, so that a large file isn't downloaded just to be rejected.
+    let meta = operator.head(location).await?;
+    if meta.size > max_size {
+        return Err(Error::RequestTooLarge);
+    }
+
+    let mut inspector = UploadInspector::new(max_size, constraints);
+    let mut stream = operator.get(location).await?.into_stream();
+    while let Some(chunk) = stream.try_next().await.map_err(storage::Error::from)? {
+        inspector.add_chunk(&chunk)?;
+    }
+
+    inspector.finish()
+}
+
+/// Convert a detected image format to one of the formats that we can store.
+pub(crate) fn db_image_format(format: ImageFormat) -> Option<db::ImageFormat> {
+    match format {
+        ImageFormat::PNG => Some(db::ImageFormat::Png),
+        ImageFormat::AVIF => Some(db::ImageFormat::Avif),
+        ImageFormat::JPEG => Some(db::ImageFormat::Jpg),
+        ImageFormat::WEBP => Some(db::ImageFormat::Webp),
+        _ => None,
+    }
+}
+
+/// An image whose original is being uploaded, and the storage that the original goes to.
+pub(super) struct UploadTarget {
+    pub(super) base_image: BaseImage,
+    conversion_profile: conversion_profiles::ConversionProfile,
+    pub(super) constraints: Option<ImageConstraints>,
+    pub(super) operator: storage::Operator,
+}
+
+/// Load an image for uploading its original, and check that the user can upload it.
+pub(super) async fn load_upload_target(
+    state: &AppState,
+    user: &UserInfo,
+    image_id: BaseImageId,
+) -> Result<UploadTarget, Error> {
     use db::{base_images, storage_locations, upload_profiles};
 
     let conn = state.db.get().await?;
 
+    let query_user = user.clone();
     let (
         base_image,
         output_path,
         conversion_profile,
         project_base_path,
         base_image_profile_location,
+        constraints,
         allowed,
     ) = conn
         .interact(move |conn| {
+            let user = query_user;
             base_images::table
                 .inner_join(
                     upload_profiles::table
@@ -167,6 +278,7 @@ pub async fn upload_image(
                     conversion_profiles::all_columns,
                     projects::base_location,
                     upload_profiles::base_storage_location_path,
+                    upload_profiles::constraints,
                     db::obj_allowed!(
                         user.team_id,
                         &user.roles,
@@ -180,6 +292,7 @@ pub async fn upload_image(
                     conversion_profiles::ConversionProfile,
                     String,
                     Option<String>,
+                    Option<ImageConstraints>,
                     bool,
                 )>(conn)
         })
@@ -189,6 +302,22 @@ pub async fn upload_image(
         return Err(Error::MissingPermission(Permission::ImageCreate));
     }
 
+    if user
+        .bound_upload_profile_id
+        .map(|bound| bound != base_image.upload_profile_id)
+        .unwrap_or(false)
+    {
+        return Err(Error::ApiKeyRestricted);
+    }
+
+    // Check the policies again, since they may have changed since the image was created.
+    let team_id = user.team_id;
+    let upload_profile_id = base_image.upload_profile_id;
+    state
+        .db
+        .interact(move |conn| labels::check_upload(conn, team_id, upload_profile_id))
+        .await?;
+
     let provider = storage::Provider::from_db(output_path.provider)?;
 
     let output_base_location = image_base_location(
@@ -201,45 +330,61 @@ pub async fn upload_image(
         .create_operator(output_base_location.as_ref())
         .await?;
 
-    let (upload_id, mut writer) = operator.put_multipart(&base_image.location).await?;
-    let (hash_hex, total_size, info) = match handle_upload(&mut writer, stream).await {
-        Ok(result) => {
-            writer.shutdown().await?;
-            result
-        }
-        Err(e) => {
-            operator
-                .abort_multipart(&base_image.location, &upload_id)
-                .await
-                .ok();
-            return Err(e);
-        }
-    };
+    Ok(UploadTarget {
+        base_image,
+        conversion_profile,
+        constraints,
+        operator,
+    })
+}
 
-    let upload_format = match info.format {
-        ImageFormat::PNG => db::ImageFormat::Png,
-        ImageFormat::AVIF => db::ImageFormat::Avif,
-        ImageFormat::JPEG => db::ImageFormat::Jpg,
-        ImageFormat::WEBP => db::ImageFormat::Webp,
-        _ => return Err(Error::ImageHeaderDecode(ImageInfoError::UnrecognizedFormat)),
-    };
+/// Save the details of an uploaded original and queue the conversions.
+pub(super) async fn finish_upload(
+    state: &AppState,
+    team_id: TeamId,
+    target: UploadTarget,
+    uploaded: UploadedImage,
+) -> Result<(), Error> {
+    use db::base_images;
 
+    let UploadTarget {
+        base_image,
+        conversion_profile,
+        ..
+    } = target;
+    let image_id = base_image.id;
+    let UploadedImage {
+        hash: hash_hex,
+        sha256,
+        size: total_size,
+        info,
+    } = uploaded;
+
+    let upload_format = db_image_format(info.format)
+        .ok_or(Error::ImageHeaderDecode(ImageInfoError::UnrecognizedFormat))?;
+
+    let choose_breakpoints = conversion_profile.output.has_automatic_sizes();
     let output_images = generate_output_images(
-        user.team_id,
         &conversion_profile,
-        base_image.id,
-        &base_image.location,
-        upload_format,
-    );
+        &OutputImageBase {
+            team_id,
+            project_id: base_image.project_id,
+            id: base_image.id,
+            location: &base_image.location,
+            format: upload_format,
+            key_prefix: base_image.output_key_prefix.as_deref(),
+        },
+    )?;
 
     let output_image_ids = state
         .db
         .transaction(move |conn| {
             diesel::update(base_images::table)
                 .filter(base_images::id.eq(image_id))
-                .filter(base_images::team_id.eq(user.team_id))
+                .filter(base_images::team_id.eq(team_id))
                 .set((
                     base_images::hash.eq(hash_hex),
+                    base_images::sha256.eq(sha256),
                     base_images::file_size.eq(total_size as i32),
                     base_images::format.eq(Some(upload_format)),
                     base_images::width.eq(info.size.width as i32),
@@ -247,18 +392,147 @@ pub async fn upload_image(
                     base_images::status.eq(db::BaseImageStatus::Converting),
                 ))
                 .execute(conn)?;
-            replace_output_images(conn, user.team_id, image_id, output_images)
+            replace_output_images(
+                conn,
+                team_id,
+                image_id,
+                (conversion_profile.id, conversion_profile.version),
+                output_images,
+            )
         })
         .await?;
 
-    let job_id = effectum::Job::builder(crate::jobs::CREATE_OUTPUT_IMAGES)
-        .json_payload(&crate::jobs::CreateOutputImagesJobPayload {
+    state
+        .metering
+        .record_upload(team_id, base_image.project_id, total_size);
+
+    conversion_pause::queue_conversion(
+        state,
+        team_id,
+        crate::jobs::CreateOutputImagesJobPayload {
             base_image: image_id,
             conversions: output_image_ids,
-        })?
-        .add_to(&state.queue)
+            choose_breakpoints,
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn upload_image(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(image_id): Path<BaseImageId>,
+    stream: BodyStream,
+) -> Result<impl IntoResponse, Error> {
+    let target = load_upload_target(&state, &user, image_id).await?;
+    let location = target.base_image.location.clone();
+
+    let mut upload = target
+        .operator
+        .start_upload(&location, state.upload_part_size)
         .await?;
-    event!(Level::INFO, %job_id, "enqueued image conversion job");
+    let uploaded = match handle_upload(
+        &mut upload,
+        stream,
+        state.max_upload_size,
+        target.constraints.as_ref(),
+    )
+    .await
+    {
+        Ok(result) => {
+            upload.finish().await?;
+            result
+        }
+        Err(e) => {
+            upload.abort().await.ok();
+            return Err(e);
+        }
+    };
+
+    // The image stays awaiting upload if this fails, so the client can upload it again.
+    checksum::verify_stored(&target.operator, &location, &uploaded.sha256).await?;
+
+    finish_upload(&state, user.team_id, target, uploaded).await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+#[derive(Debug, Serialize)]
+struct PresignedUploadOutput {
+    /// The URL to PUT the original to.
+    url: String,
+    method: &'static str,
+    expires: DateTime<Utc>,
+}
+
+/// Create a URL that the client can upload the original to directly, so that large files go
+/// straight to storage instead of through the server. Once the upload is done, the client calls
+/// the `complete` endpoint to start the conversions.
+pub async fn presign_upload(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(image_id): Path<BaseImageId>,
+) -> Result<impl IntoResponse, Error> {
+    let target = load_upload_target(&state, &user, image_id).await?;
+    if !matches!(target.base_image.status, BaseImageStatus::AwaitingUpload) {
+        return Err(Error::ImageAlreadyUploaded);
+    }
+
+    let url = target
+        .operator
+        .presigned_put(&target.base_image.location, PRESIGNED_UPLOAD_TTL)
+        .await?
+        .ok_or(Error::DirectUploadUnsupported)?;
+    let expires = Utc::now() + chrono::Duration::from_std(PRESIGNED_UPLOAD_TTL).unwrap();
+
+    Ok((
+        StatusCode::OK,
+        Json(PresignedUploadOutput {
+            url,
+            method: "PUT",
+            expires,
+        }),
+    ))
+}
+
+/// Finish an upload that went straight to storage. The original is read back to check it, the
+/// same way as an upload through the server, and then the conversions are queued.
+pub async fn complete_upload(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(image_id): Path<BaseImageId>,
+) -> Result<impl IntoResponse, Error> {
+    let target = load_upload_target(&state, &user, image_id).await?;
+    if !matches!(target.base_image.status, BaseImageStatus::AwaitingUpload) {
+        return Err(Error::ImageAlreadyUploaded);
+    }
+
+    let location = &target.base_image.location;
+    let uploaded = match inspect_stored(
+        &target.operator,
+        location,
+        state.max_upload_size,
+        target.constraints.as_ref(),
+    )
+    .await
+    {
+        Ok(uploaded) => uploaded,
+        Err(Error::StorageError(e)) if e.is_not_found() => {
+            return Err(Error::ObjectNotFound("upload"));
+        }
+        Err(e) => {
+            // Remove the rejected file, so that it isn't left in storage. The image stays
+            // awaiting upload, so the client can try again.
+            if let Err(delete_error) = target.operator.delete(location).await {
+                event!(Level::WARN, error = %delete_error, %location, "Failed to delete rejected upload");
+            }
+            return Err(e);
+        }
+    };
+
+    finish_upload(&state, user.team_id, target, uploaded).await?;
 
     Ok((StatusCode::OK, Json(json!({}))))
 }
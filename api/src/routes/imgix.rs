@@ -0,0 +1,144 @@
+//! Map imgix query parameters onto the serve route, to ease migrating sites that build imgix URLs.
+//!
+//! Supported parameters are `w`, `h`, `q`, `fm`, `fit`, and `auto=format`. Images always keep
+//! their aspect ratio and are never enlarged, so every `fit` mode behaves like `max`. The other
+//! `auto` options are accepted and ignored.
+
+use db::ImageFormat;
+use pic_store_db as db;
+use serde::Deserialize;
+
+use super::serve::ServeQuery;
+use crate::Error;
+
+const FIT_MODES: &[&str] = &[
+    "clamp", "clip", "crop", "facearea", "fill", "fillmax", "max", "min", "scale",
+];
+
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct ImgixQuery {
+    w: Option<u32>,
+    h: Option<u32>,
+    q: Option<u8>,
+    fm: Option<String>,
+    fit: Option<String>,
+    auto: Option<String>,
+}
+
+fn parse_format(format: &str) -> Result<ImageFormat, Error> {
+    match format {
+        "jpg" | "pjpg" => Ok(ImageFormat::Jpg),
+        "png" | "png8" | "png32" => Ok(ImageFormat::Png),
+        "webp" => Ok(ImageFormat::Webp),
+        "avif" => Ok(ImageFormat::Avif),
+        _ => Err(Error::InvalidTransformation("unsupported format")),
+    }
+}
+
+impl ImgixQuery {
+    /// Apply the imgix parameters to a serve query. Parameters that are present take precedence
+    /// over the serve route's own parameters.
+    pub fn apply(self, query: &mut ServeQuery) -> Result<(), Error> {
+        if let Some(fit) = self.fit.as_deref() {
+            if !FIT_MODES.contains(&fit) {
+                return Err(Error::InvalidTransformation("unsupported fit mode"));
+            }
+        }
+
+        if self.w.is_some() {
+            query.width = self.w;
+        }
+        if self.h.is_some() {
+            query.height = self.h;
+        }
+        if self.q.is_some() {
+            query.quality = self.q;
+        }
+        if let Some(format) = self.fm.as_deref() {
+            query.format = Some(parse_format(format)?);
+        }
+
+        // Like imgix, `auto=format` picks the best format for the browser even when `fm` is set.
+        let auto_format = self
+            .auto
+            .as_deref()
+            .map(|auto| auto.split(',').any(|option| option.trim() == "format"))
+            .unwrap_or(false);
+        if auto_format {
+            query.format = None;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_params() {
+        let mut query = ServeQuery::default();
+        ImgixQuery {
+            w: Some(300),
+            h: Some(200),
+            q: Some(60),
+            fm: Some("webp".to_string()),
+            fit: Some("crop".to_string()),
+            ..Default::default()
+        }
+        .apply(&mut query)
+        .unwrap();
+        assert_eq!(query.width, Some(300));
+        assert_eq!(query.height, Some(200));
+        assert_eq!(query.quality, Some(60));
+        assert_eq!(query.format, Some(ImageFormat::Webp));
+    }
+
+    #[test]
+    fn auto_format() {
+        let mut query = ServeQuery::default();
+        ImgixQuery {
+            fm: Some("png".to_string()),
+            auto: Some("compress,format".to_string()),
+            ..Default::default()
+        }
+        .apply(&mut query)
+        .unwrap();
+        assert_eq!(query.format, None);
+    }
+
+    #[test]
+    fn keeps_serve_params() {
+        let mut query = ServeQuery {
+            width: Some(500),
+            format: Some(ImageFormat::Jpg),
+            ..Default::default()
+        };
+        ImgixQuery {
+            h: Some(100),
+            ..Default::default()
+        }
+        .apply(&mut query)
+        .unwrap();
+        assert_eq!(query.width, Some(500));
+        assert_eq!(query.height, Some(100));
+        assert_eq!(query.format, Some(ImageFormat::Jpg));
+    }
+
+    #[test]
+    fn rejects_unsupported_values() {
+        let mut query = ServeQuery::default();
+        let fit = ImgixQuery {
+            fit: Some("stretch".to_string()),
+            ..Default::default()
+        };
+        assert!(fit.apply(&mut query).is_err());
+
+        let format = ImgixQuery {
+            fm: Some("gif".to_string()),
+            ..Default::default()
+        };
+        assert!(format.apply(&mut query).is_err());
+    }
+}
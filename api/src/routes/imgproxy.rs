@@ -0,0 +1,342 @@
+//! Serve images using imgproxy's URL syntax, so that frontends built against imgproxy can switch
+//! to pic-store without changing how they build URLs.
+//!
+//! URLs look like `/imgproxy/{signature}/{options}/plain/{source}@{extension}` or
+//! `/imgproxy/{signature}/{options}/{base64 source}.{extension}`. The last path segment of the
+//! source URL must be the ID of a base image, and the request is served the same way as the
+//! serve route.
+//!
+//! Only the options that map onto the serve route are supported: sizes, quality, and format.
+//! Images always keep their aspect ratio and are never enlarged, so every resizing type behaves
+//! like `fit`.
+//!
+//! Signatures use imgproxy's scheme, an HMAC-SHA256 of the salt and path. They are checked when
+//! an imgproxy key is configured, and ignored otherwise. A valid signature does not count as a
+//! signed URL for upload profiles that require them, since imgproxy signatures never expire.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, Uri},
+    response::Response,
+    routing::get,
+    Router,
+};
+use base64::Engine;
+use db::{object_id::BaseImageId, ImageFormat};
+use hmac::{Hmac, Mac};
+use pic_store_db as db;
+use sha2::Sha256;
+
+use super::serve::{serve_image, ServeQuery};
+use crate::{access_token::AccessToken, shared_state::AppState, signed_url::SignedUrl, Error};
+
+const ROUTE_PREFIX: &str = "/imgproxy/";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The key and salt used to sign imgproxy URLs.
+pub struct ImgproxyKey {
+    key: Vec<u8>,
+    salt: Vec<u8>,
+}
+
+impl ImgproxyKey {
+    /// Create the key from hex-encoded values, the same format that imgproxy uses.
+    pub fn new(key: &str, salt: &str) -> Result<Self, eyre::Report> {
+        Ok(Self {
+            key: decode_hex(key).map_err(|e| eyre::eyre!("imgproxy key: {e}"))?,
+            salt: decode_hex(salt).map_err(|e| eyre::eyre!("imgproxy salt: {e}"))?,
+        })
+    }
+
+    fn mac(&self, path: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any size");
+        mac.update(&self.salt);
+        mac.update(path.as_bytes());
+        mac
+    }
+
+    /// Sign the part of a URL after the signature, including its leading slash.
+    pub fn sign(&self, path: &str) -> String {
+        let signature = self.mac(path).finalize().into_bytes();
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(signature)
+    }
+
+    pub fn verify(&self, signature: &str, path: &str) -> Result<(), Error> {
+        let signature = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(signature.trim_end_matches('='))
+            .map_err(|_| Error::InvalidSignedUrl("malformed signature"))?;
+        self.mac(path)
+            .verify_slice(&signature)
+            .map_err(|_| Error::InvalidSignedUrl("bad signature"))
+    }
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, &'static str> {
+    if !value.is_ascii() || !value.len().is_multiple_of(2) {
+        return Err("must be a hex string");
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).map_err(|_| "must be a hex string"))
+        .collect()
+}
+
+/// An imgproxy URL, translated into a request for the serve route.
+#[derive(Debug)]
+struct ImgproxyRequest {
+    image_id: BaseImageId,
+    query: ServeQuery,
+}
+
+fn parse_format(extension: &str) -> Result<ImageFormat, Error> {
+    match extension.to_ascii_lowercase().as_str() {
+        "jpg" | "jpeg" => Ok(ImageFormat::Jpg),
+        "png" => Ok(ImageFormat::Png),
+        "webp" => Ok(ImageFormat::Webp),
+        "avif" => Ok(ImageFormat::Avif),
+        _ => Err(Error::InvalidImgproxyUrl("unsupported format")),
+    }
+}
+
+/// Parse a width or height. imgproxy uses 0 to mean that the dimension is not constrained.
+fn parse_dimension(value: Option<&&str>) -> Result<Option<u32>, Error> {
+    match value.copied() {
+        None | Some("") => Ok(None),
+        Some(value) => {
+            let value = value
+                .parse::<u32>()
+                .map_err(|_| Error::InvalidImgproxyUrl("invalid size"))?;
+            Ok((value > 0).then_some(value))
+        }
+    }
+}
+
+fn apply_option(query: &mut ServeQuery, option: &str) -> Result<(), Error> {
+    let mut args = option.split(':');
+    let name = args.next().unwrap_or_default();
+    let args = args.collect::<Vec<_>>();
+
+    match name {
+        "resize" | "rs" => {
+            // The resizing type in the first argument is ignored, since everything works like `fit`.
+            query.width = parse_dimension(args.get(1))?;
+            query.height = parse_dimension(args.get(2))?;
+        }
+        "size" | "s" => {
+            query.width = parse_dimension(args.first())?;
+            query.height = parse_dimension(args.get(1))?;
+        }
+        "width" | "w" => query.width = parse_dimension(args.first())?,
+        "height" | "h" => query.height = parse_dimension(args.first())?,
+        "quality" | "q" => {
+            let quality = args
+                .first()
+                .and_then(|q| q.parse::<u8>().ok())
+                .ok_or(Error::InvalidImgproxyUrl("invalid quality"))?;
+            // 0 means the default quality.
+            query.quality = (quality > 0).then_some(quality);
+        }
+        "format" | "f" | "ext" => {
+            let format = args
+                .first()
+                .ok_or(Error::InvalidImgproxyUrl("missing format"))?;
+            query.format = Some(parse_format(format)?);
+        }
+        // Images are never enlarged, and everything is resized like `fit`.
+        "resizing_type" | "rt" | "enlarge" | "el" => {}
+        _ => return Err(Error::InvalidImgproxyUrl("unsupported processing option")),
+    }
+
+    Ok(())
+}
+
+/// Find the image ID at the end of a source URL.
+fn source_image_id(source: &str) -> Result<BaseImageId, Error> {
+    // Plain source URLs may be percent-encoded.
+    let source = source.replace("%2F", "/").replace("%2f", "/");
+    let source = source.split(['?', '#']).next().unwrap_or_default();
+    let last = source.rsplit('/').next().unwrap_or_default();
+    let id = last.split('.').next().unwrap_or_default();
+    id.parse::<BaseImageId>()
+        .map_err(|_| Error::InvalidImgproxyUrl("source must end with an image ID"))
+}
+
+impl ImgproxyRequest {
+    /// Parse the part of an imgproxy URL after the signature.
+    fn parse(path: &str) -> Result<ImgproxyRequest, Error> {
+        let mut query = ServeQuery::default();
+        let mut segments = path.split('/').filter(|s| !s.is_empty());
+
+        let (source, extension) = loop {
+            let segment = segments
+                .next()
+                .ok_or(Error::InvalidImgproxyUrl("missing source URL"))?;
+
+            if segment == "plain" {
+                let source = segments.collect::<Vec<_>>().join("/");
+                break match source.rsplit_once('@') {
+                    Some((source, extension)) => (source.to_string(), Some(extension.to_string())),
+                    None => (source, None),
+                };
+            }
+
+            if !segment.contains(':') {
+                // A base64-encoded source URL, which may be split across several segments.
+                let encoded = std::iter::once(segment).chain(segments).collect::<String>();
+                let (encoded, extension) = match encoded.rsplit_once('.') {
+                    Some((encoded, extension)) => {
+                        (encoded.to_string(), Some(extension.to_string()))
+                    }
+                    None => (encoded, None),
+                };
+                let source = base64::engine::general_purpose::URL_SAFE_NO_PAD
+                    .decode(encoded.trim_end_matches('='))
+                    .ok()
+                    .and_then(|source| String::from_utf8(source).ok())
+                    .ok_or(Error::InvalidImgproxyUrl("invalid encoded source URL"))?;
+                break (source, extension);
+            }
+
+            apply_option(&mut query, segment)?;
+        };
+
+        if let Some(extension) = extension.filter(|e| !e.is_empty()) {
+            query.format = Some(parse_format(&extension)?);
+        }
+
+        Ok(ImgproxyRequest {
+            image_id: source_image_id(&source)?,
+            query,
+        })
+    }
+}
+
+async fn serve_imgproxy(
+    State(state): State<AppState>,
+    uri: Uri,
+    access_token: AccessToken,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let path = uri
+        .path()
+        .strip_prefix(ROUTE_PREFIX)
+        .ok_or(Error::NotFound)?;
+    let (signature, _) = path
+        .split_once('/')
+        .ok_or(Error::InvalidImgproxyUrl("missing source URL"))?;
+    // The signed path starts at the slash after the signature.
+    let signed_path = &path[signature.len()..];
+
+    // Without a key any signature is accepted, so the URL only gets the variants that an
+    // unsigned serve URL would.
+    let verified = match state.imgproxy_key.as_ref() {
+        Some(key) => {
+            key.verify(signature, signed_path)?;
+            true
+        }
+        None => false,
+    };
+
+    let request = ImgproxyRequest::parse(signed_path)?;
+    serve_image(
+        State(state),
+        Path(request.image_id),
+        Query(request.query),
+        SignedUrl::default(),
+        verified,
+        access_token,
+        headers,
+    )
+    .await
+}
+
+pub fn configure() -> Router<AppState> {
+    Router::new().route("/imgproxy/*path", get(serve_imgproxy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_source() {
+        let id = BaseImageId::new();
+        let request = ImgproxyRequest::parse(&format!(
+            "/rs:fill:300:400/q:80/plain/s3://bucket/{id}@webp"
+        ))
+        .unwrap();
+        assert_eq!(request.image_id, id);
+        assert_eq!(request.query.width, Some(300));
+        assert_eq!(request.query.height, Some(400));
+        assert_eq!(request.query.quality, Some(80));
+        assert_eq!(request.query.format, Some(ImageFormat::Webp));
+    }
+
+    #[test]
+    fn percent_encoded_source() {
+        let id = BaseImageId::new();
+        let request =
+            ImgproxyRequest::parse(&format!("/w:100/plain/https%3A%2F%2Fexample.com%2F{id}"))
+                .unwrap();
+        assert_eq!(request.image_id, id);
+    }
+
+    #[test]
+    fn encoded_source() {
+        let id = BaseImageId::new();
+        let encoded = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(format!("https://images.example.com/{id}.jpg"));
+        let (first, second) = encoded.split_at(10);
+        let request =
+            ImgproxyRequest::parse(&format!("/w:200/rt:fit/{first}/{second}.png")).unwrap();
+        assert_eq!(request.image_id, id);
+        assert_eq!(request.query.width, Some(200));
+        assert_eq!(request.query.height, None);
+        assert_eq!(request.query.format, Some(ImageFormat::Png));
+    }
+
+    #[test]
+    fn zero_means_unset() {
+        let id = BaseImageId::new();
+        let request = ImgproxyRequest::parse(&format!("/rs:fit:0:300/q:0/plain/{id}")).unwrap();
+        assert_eq!(request.query.width, None);
+        assert_eq!(request.query.height, Some(300));
+        assert_eq!(request.query.quality, None);
+        assert_eq!(request.query.format, None);
+    }
+
+    #[test]
+    fn invalid_urls() {
+        let id = BaseImageId::new();
+        assert!(ImgproxyRequest::parse("/rs:fit:300:300").is_err());
+        assert!(ImgproxyRequest::parse("/rs:fit:300:300/plain/not-an-id").is_err());
+        assert!(ImgproxyRequest::parse(&format!("/blur:5/plain/{id}")).is_err());
+        assert!(ImgproxyRequest::parse(&format!("/plain/{id}@gif")).is_err());
+        assert!(ImgproxyRequest::parse(&format!("/w:abc/plain/{id}")).is_err());
+    }
+
+    #[test]
+    fn signatures() {
+        let key = ImgproxyKey::new("736563726574", "68656c6c6f").unwrap();
+        let path = "/rs:fit:300:300/plain/bimabc";
+        let signature = key.sign(path);
+        assert!(key.verify(&signature, path).is_ok());
+        assert!(key
+            .verify(&signature, "/rs:fit:600:600/plain/bimabc")
+            .is_err());
+        assert!(key.verify("not a signature", path).is_err());
+
+        let other = ImgproxyKey::new("736563726574", "").unwrap();
+        assert!(other.verify(&signature, path).is_err());
+    }
+
+    #[test]
+    fn hex_keys() {
+        assert_eq!(decode_hex("00ff10").unwrap(), vec![0, 255, 16]);
+        assert!(decode_hex("abc").is_err());
+        assert!(decode_hex("zz").is_err());
+        assert!(ImgproxyKey::new("not hex", "").is_err());
+    }
+}
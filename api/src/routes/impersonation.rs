@@ -0,0 +1,192 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post},
+    Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use db::{
+    impersonations::{self, impersonation_events, NewImpersonation},
+    object_id::{ImpersonationId, TeamId},
+    PoolExt,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    auth::{must_be_instance_admin, Authenticated},
+    json::Json,
+    shared_state::AppState,
+    Error, Result,
+};
+
+const DEFAULT_DURATION_MINUTES: u32 = 60;
+const MAX_DURATION_MINUTES: u32 = 8 * 60;
+
+#[derive(Debug, Deserialize)]
+struct NewImpersonationInput {
+    team_id: TeamId,
+    reason: String,
+    /// How long the impersonation should last. Defaults to one hour, and may be at most
+    /// eight hours.
+    duration_minutes: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = impersonations)]
+struct ImpersonationOutput {
+    id: ImpersonationId,
+    admin_user_id: db::object_id::UserId,
+    team_id: TeamId,
+    reason: String,
+    created: DateTime<Utc>,
+    expires: DateTime<Utc>,
+    ended: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = impersonation_events)]
+struct ImpersonationEventOutput {
+    method: String,
+    path: String,
+    created: DateTime<Utc>,
+}
+
+async fn new_impersonation(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Json(body): Json<NewImpersonationInput>,
+) -> Result<impl IntoResponse> {
+    let duration = body
+        .duration_minutes
+        .unwrap_or(DEFAULT_DURATION_MINUTES)
+        .min(MAX_DURATION_MINUTES);
+
+    let result = state
+        .db
+        .transaction(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            let team_exists = diesel::select(diesel::dsl::exists(
+                db::teams::table
+                    .filter(db::teams::id.eq(body.team_id))
+                    .filter(db::teams::deleted.is_null()),
+            ))
+            .get_result::<bool>(conn)?;
+            if !team_exists {
+                return Err(Error::ObjectNotFound("team"));
+            }
+
+            let value = NewImpersonation {
+                id: ImpersonationId::new(),
+                admin_user_id: user.user_id,
+                team_id: body.team_id,
+                reason: body.reason,
+                expires: Utc::now() + Duration::minutes(duration as i64),
+            };
+
+            diesel::insert_into(impersonations::table)
+                .values(&value)
+                .returning(ImpersonationOutput::as_select())
+                .get_result(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(result)))
+}
+
+async fn list_impersonations(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+) -> Result<impl IntoResponse> {
+    let objects = state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            impersonations::table
+                .select(ImpersonationOutput::as_select())
+                .order(impersonations::created.desc())
+                .limit(100)
+                .load(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(objects)))
+}
+
+/// Get an impersonation and the requests that were made with it.
+async fn get_impersonation(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(impersonation_id): Path<ImpersonationId>,
+) -> Result<impl IntoResponse> {
+    let (impersonation, events) = state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            let impersonation = impersonations::table
+                .filter(impersonations::id.eq(impersonation_id))
+                .select(ImpersonationOutput::as_select())
+                .first(conn)
+                .optional()?
+                .ok_or(Error::NotFound)?;
+
+            let events = impersonation_events::table
+                .filter(impersonation_events::impersonation_id.eq(impersonation_id))
+                .select(ImpersonationEventOutput::as_select())
+                .order(impersonation_events::created.asc())
+                .load(conn)?;
+
+            Ok::<_, Error>((impersonation, events))
+        })
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "impersonation": impersonation,
+            "events": events,
+        })),
+    ))
+}
+
+/// End an impersonation before it expires.
+async fn end_impersonation(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(impersonation_id): Path<ImpersonationId>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .interact(move |conn| {
+            must_be_instance_admin(conn, &user)?;
+
+            diesel::update(impersonations::table)
+                .filter(impersonations::id.eq(impersonation_id))
+                .filter(impersonations::ended.is_null())
+                .set(impersonations::ended.eq(Utc::now()))
+                .execute(conn)?;
+
+            Ok::<_, Error>(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+pub fn configure() -> Router<AppState> {
+    let routes = Router::new()
+        .route("/", get(list_impersonations))
+        .route("/", post(new_impersonation))
+        .route("/:impersonation_id", get(get_impersonation))
+        .route("/:impersonation_id", delete(end_impersonation));
+
+    Router::new().nest("/impersonations", routes)
+}
@@ -0,0 +1,254 @@
+//! Project labels and the team's label policies. Upload profiles and storage locations set their
+//! labels along with the rest of their settings.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post, put},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use db::{
+    label_policies::{self, LabelPolicyRule, NewLabelPolicy},
+    object_id::{LabelPolicyId, ProjectId, TeamId},
+    permissions::{GlobalPermission, ProjectPermission},
+    projects, storage_locations, PoolExt,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    auth::{must_have_global_permission, must_own_project, Authenticated},
+    json::Json,
+    labels::normalize_labels,
+    shared_state::AppState,
+    Error, Result,
+};
+
+#[derive(Debug, Deserialize)]
+struct LabelPolicyInput {
+    label: String,
+    rule: LabelPolicyRule,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = label_policies)]
+struct LabelPolicyOutput {
+    id: LabelPolicyId,
+    label: String,
+    rule: LabelPolicyRule,
+    updated: DateTime<Utc>,
+}
+
+fn validate_label(label: String) -> Result<String> {
+    normalize_labels(vec![label])?
+        .pop()
+        .ok_or(Error::InvalidLabel("labels can not be empty"))
+}
+
+/// Check that a policy's label and rule make sense for the team.
+fn validate_policy(
+    conn: &mut PgConnection,
+    team_id: TeamId,
+    input: LabelPolicyInput,
+) -> Result<(String, LabelPolicyRule)> {
+    let label = validate_label(input.label)?;
+    let rule = match input.rule {
+        LabelPolicyRule::RequireStorageLocation {
+            storage_location_ids,
+        } => {
+            if storage_location_ids.is_empty() {
+                return Err(Error::InvalidLabelPolicy(
+                    "require_storage_location needs at least one storage location",
+                ));
+            }
+
+            let found = storage_locations::table
+                .filter(storage_locations::id.eq_any(&storage_location_ids))
+                .filter(storage_locations::team_id.eq(team_id))
+                .filter(storage_locations::deleted.is_null())
+                .count()
+                .get_result::<i64>(conn)?;
+            if found as usize != storage_location_ids.len() {
+                return Err(Error::ObjectNotFound("storage location"));
+            }
+
+            LabelPolicyRule::RequireStorageLocation {
+                storage_location_ids,
+            }
+        }
+        LabelPolicyRule::RequireStorageLabel { storage_label } => {
+            LabelPolicyRule::RequireStorageLabel {
+                storage_label: validate_label(storage_label)?,
+            }
+        }
+        LabelPolicyRule::DenyUpload => LabelPolicyRule::DenyUpload,
+    };
+
+    Ok((label, rule))
+}
+
+async fn get_project_labels(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+) -> Result<impl IntoResponse> {
+    let labels = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectRead)?;
+
+            projects::table
+                .filter(projects::id.eq(project_id))
+                .select(projects::labels)
+                .first::<Vec<String>>(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(labels)))
+}
+
+/// Replace a project's labels.
+async fn set_project_labels(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Json(body): Json<Vec<String>>,
+) -> Result<impl IntoResponse> {
+    let labels = normalize_labels(body)?;
+
+    let labels = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            diesel::update(projects::table)
+                .filter(projects::id.eq(project_id))
+                .set((
+                    projects::labels.eq(&labels),
+                    projects::updated.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            Ok::<_, Error>(labels)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(labels)))
+}
+
+async fn list_label_policies(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+) -> Result<impl IntoResponse> {
+    let objects = state
+        .db
+        .interact(move |conn| {
+            label_policies::table
+                .filter(label_policies::team_id.eq(user.team_id))
+                .filter(label_policies::deleted.is_null())
+                .select(LabelPolicyOutput::as_select())
+                .order(label_policies::label.asc())
+                .load(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(objects)))
+}
+
+async fn new_label_policy(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Json(body): Json<LabelPolicyInput>,
+) -> Result<impl IntoResponse> {
+    let policy = state
+        .db
+        .interact(move |conn| {
+            must_have_global_permission(conn, &user, GlobalPermission::TeamAdmin)?;
+            let (label, rule) = validate_policy(conn, user.team_id, body)?;
+
+            diesel::insert_into(label_policies::table)
+                .values(NewLabelPolicy {
+                    id: LabelPolicyId::new(),
+                    team_id: user.team_id,
+                    label,
+                    rule,
+                })
+                .returning(LabelPolicyOutput::as_select())
+                .get_result(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(policy)))
+}
+
+async fn write_label_policy(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(policy_id): Path<LabelPolicyId>,
+    Json(body): Json<LabelPolicyInput>,
+) -> Result<impl IntoResponse> {
+    let policy = state
+        .db
+        .interact(move |conn| {
+            must_have_global_permission(conn, &user, GlobalPermission::TeamAdmin)?;
+            let (label, rule) = validate_policy(conn, user.team_id, body)?;
+
+            diesel::update(label_policies::table)
+                .filter(label_policies::id.eq(policy_id))
+                .filter(label_policies::team_id.eq(user.team_id))
+                .filter(label_policies::deleted.is_null())
+                .set((
+                    label_policies::label.eq(label),
+                    label_policies::rule.eq(rule),
+                    label_policies::updated.eq(Utc::now()),
+                ))
+                .returning(LabelPolicyOutput::as_select())
+                .get_result(conn)
+                .optional()?
+                .ok_or(Error::ObjectNotFound("label policy"))
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(policy)))
+}
+
+async fn delete_label_policy(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(policy_id): Path<LabelPolicyId>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .interact(move |conn| {
+            must_have_global_permission(conn, &user, GlobalPermission::TeamAdmin)?;
+
+            diesel::update(label_policies::table)
+                .filter(label_policies::id.eq(policy_id))
+                .filter(label_policies::team_id.eq(user.team_id))
+                .filter(label_policies::deleted.is_null())
+                .set(label_policies::deleted.eq(Some(Utc::now())))
+                .execute(conn)?;
+
+            Ok::<_, Error>(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+pub fn configure() -> Router<AppState> {
+    Router::new()
+        .route("/projects/:project_id/labels", get(get_project_labels))
+        .route("/projects/:project_id/labels", put(set_project_labels))
+        .route("/label_policies", get(list_label_policies))
+        .route("/label_policies", post(new_label_policy))
+        .route("/label_policies/:policy_id", put(write_label_policy))
+        .route("/label_policies/:policy_id", delete(delete_label_policy))
+}
@@ -0,0 +1,101 @@
+//! Reports from the scheduled checks of each project's delivery URLs.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use db::{
+    link_checks::{self, LinkCheck},
+    object_id::ProjectId,
+    permissions::ProjectPermission,
+    PoolExt,
+};
+use diesel::{dsl::count_star, prelude::*};
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::{must_own_project, Authenticated},
+    json::Json,
+    shared_state::AppState,
+    Error, Result,
+};
+
+const DEFAULT_REPORT_DAYS: i64 = 7;
+
+/// The most failures to return in a report.
+const MAX_FAILURES: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+struct ReportQuery {
+    since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+struct LinkCheckReport {
+    since: DateTime<Utc>,
+    checked: i64,
+    failed: i64,
+    /// The most recent failures, newest first.
+    failures: Vec<LinkCheck>,
+}
+
+async fn get_link_check_report(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Query(query): Query<ReportQuery>,
+) -> Result<impl IntoResponse> {
+    let since = query
+        .since
+        .unwrap_or_else(|| Utc::now() - Duration::days(DEFAULT_REPORT_DAYS));
+
+    let report = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectRead)?;
+
+            let checks = link_checks::table
+                .filter(link_checks::project_id.eq(project_id))
+                .filter(link_checks::checked.ge(since));
+
+            let counts = checks
+                .group_by(link_checks::ok)
+                .select((link_checks::ok, count_star()))
+                .load::<(bool, i64)>(conn)?;
+            let failed = counts
+                .iter()
+                .filter(|(ok, _)| !ok)
+                .map(|(_, count)| count)
+                .sum::<i64>();
+            let checked = counts.iter().map(|(_, count)| count).sum::<i64>();
+
+            let failures = checks
+                .filter(link_checks::ok.eq(false))
+                .order(link_checks::checked.desc())
+                .limit(MAX_FAILURES)
+                .select(LinkCheck::as_select())
+                .load(conn)?;
+
+            Ok::<_, Error>(LinkCheckReport {
+                since,
+                checked,
+                failed,
+                failures,
+            })
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(report)))
+}
+
+pub fn configure() -> Router<AppState> {
+    Router::new().route(
+        "/projects/:project_id/link_checks",
+        get(get_link_check_report),
+    )
+}
@@ -0,0 +1,114 @@
+//! Serve files from local filesystem storage locations, so that a server without S3 can still
+//! give its images public URLs. To use this, set the storage location's `public_url_base` to
+//! `https://<server>/local_storage/<storage location id>`.
+//!
+//! Only locations in redirect mode are served, since their files are meant to be public. Files in
+//! proxied locations are only available through the serve route, which checks the access
+//! restrictions for each image.
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue},
+    response::Response,
+    routing::get,
+    Router,
+};
+use db::{
+    object_id::StorageLocationId,
+    storage_locations::{self, Provider, StorageLocation},
+    PoolExt, StorageServeMode,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use pic_store_storage as storage;
+
+use crate::{
+    range::{self, RangedBody},
+    shared_state::AppState,
+    Error, Result,
+};
+
+fn content_type(path: &str) -> &'static str {
+    let extension = path
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "avif" => "image/avif",
+        "webp" => "image/webp",
+        "heic" | "heif" => "image/heic",
+        _ => "application/octet-stream",
+    }
+}
+
+async fn serve_local_file(
+    State(state): State<AppState>,
+    Path((location_id, path)): Path<(StorageLocationId, String)>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let path = path.trim_start_matches('/').to_string();
+    if path.is_empty() || path.split('/').any(|part| part == "." || part == "..") {
+        return Err(Error::NotFound);
+    }
+
+    let location = state
+        .db
+        .interact(move |conn| {
+            storage_locations::table
+                .filter(storage_locations::id.eq(location_id))
+                .filter(storage_locations::deleted.is_null())
+                .first::<StorageLocation>(conn)
+                .optional()
+                .map_err(Error::from)
+        })
+        .await?
+        .filter(|location| {
+            matches!(location.provider, Provider::Local)
+                && location.serve_mode == StorageServeMode::Redirect
+                && !location.base_location.is_empty()
+        })
+        .ok_or(Error::NotFound)?;
+
+    let provider = storage::Provider::from_db(location.provider)?;
+    let operator = provider.create_operator(&location.base_location).await?;
+
+    let range = range::requested_range(&headers, None, None);
+    let body = RangedBody::from_storage(&operator, &path, range)
+        .await
+        .map_err(|e| {
+            if e.is_not_found() {
+                Error::NotFound
+            } else {
+                Error::from(e)
+            }
+        })?;
+
+    let mut response = body.into_response(content_type(&path));
+    response.headers_mut().insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    Ok(response)
+}
+
+pub fn configure() -> Router<AppState> {
+    Router::new().route(
+        "/local_storage/:storage_location_id/*path",
+        get(serve_local_file),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_types() {
+        assert_eq!(content_type("a/b/image.JPG"), "image/jpeg");
+        assert_eq!(content_type("image.avif"), "image/avif");
+        assert_eq!(content_type("notes.html"), "application/octet-stream");
+        assert_eq!(content_type("no_extension"), "application/octet-stream");
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use db::{
+    object_id::{ProjectId, TeamId, UploadProfileId},
+    Permission, PoolExt, TeamStatus,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{auth::Authenticated, json::Json, shared_state::AppState, Result};
+
+#[derive(Debug, Deserialize)]
+struct PermissionsQuery {
+    /// Only return the permissions on this project.
+    project_id: Option<ProjectId>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct ProjectPermissions {
+    project_id: ProjectId,
+    permissions: Vec<Permission>,
+}
+
+#[derive(Debug, Serialize)]
+struct PermissionsOutput {
+    team_id: TeamId,
+    /// A team that is not active can not make changes, whatever its permissions say.
+    team_status: TeamStatus,
+    /// Permissions that apply to the whole team.
+    permissions: Vec<Permission>,
+    projects: Vec<ProjectPermissions>,
+    /// Set when using an API key that may only upload through this profile.
+    bound_upload_profile_id: Option<UploadProfileId>,
+}
+
+/// Group the user's granted permissions into team and per-project lists. A team admin has every
+/// permission on every one of `team_projects`.
+fn summarize(
+    granted: &[(Uuid, Permission)],
+    team_projects: &[ProjectId],
+) -> (Vec<Permission>, Vec<ProjectPermissions>) {
+    let is_admin = granted
+        .iter()
+        .any(|(_, permission)| *permission == Permission::TeamAdmin);
+
+    if is_admin {
+        let global = Permission::ALL
+            .into_iter()
+            .filter(|p| !p.requires_project())
+            .collect::<Vec<_>>();
+        let project_permissions = Permission::ALL
+            .into_iter()
+            .filter(|p| p.requires_project())
+            .collect::<Vec<_>>();
+        let projects = team_projects
+            .iter()
+            .map(|project_id| ProjectPermissions {
+                project_id: *project_id,
+                permissions: project_permissions.clone(),
+            })
+            .collect();
+        return (global, projects);
+    }
+
+    let mut global = BTreeSet::new();
+    let mut by_project = BTreeMap::<Uuid, BTreeSet<Permission>>::new();
+    for (project_id, permission) in granted {
+        if !permission.requires_project() {
+            global.insert(*permission);
+        } else if team_projects.iter().any(|p| **p == *project_id) {
+            by_project
+                .entry(*project_id)
+                .or_default()
+                .insert(*permission);
+        }
+    }
+
+    let projects = by_project
+        .into_iter()
+        .map(|(project_id, permissions)| ProjectPermissions {
+            project_id: ProjectId::from_uuid(project_id),
+            permissions: permissions.into_iter().collect(),
+        })
+        .collect();
+
+    (global.into_iter().collect(), projects)
+}
+
+/// List what the current user is allowed to do, so that clients can hide actions that would be
+/// refused.
+async fn get_permissions(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Query(query): Query<PermissionsQuery>,
+) -> Result<impl IntoResponse> {
+    let team_id = user.team_id;
+    let roles = user.roles.clone();
+    let (granted, team_projects) = state
+        .db
+        .interact(move |conn| {
+            let granted = db::role_permissions::table
+                .filter(db::role_permissions::team_id.eq(team_id))
+                .filter(db::role_permissions::role_id.eq_any(&roles))
+                .select((
+                    db::role_permissions::project_id,
+                    db::role_permissions::permission,
+                ))
+                .load::<(Uuid, Permission)>(conn)?;
+
+            let mut projects = db::projects::table
+                .filter(db::projects::team_id.eq(team_id))
+                .filter(db::projects::deleted.is_null())
+                .select(db::projects::id)
+                .order(db::projects::id.asc())
+                .into_boxed();
+            if let Some(project_id) = query.project_id {
+                projects = projects.filter(db::projects::id.eq(project_id));
+            }
+            let projects = projects.load::<ProjectId>(conn)?;
+
+            Ok::<_, crate::Error>((granted, projects))
+        })
+        .await?;
+
+    let (permissions, projects) = summarize(&granted, &team_projects);
+
+    Ok((
+        StatusCode::OK,
+        Json(PermissionsOutput {
+            team_id: user.team_id,
+            team_status: user.team_status,
+            permissions,
+            projects,
+            bound_upload_profile_id: user.bound_upload_profile_id,
+        }),
+    ))
+}
+
+pub fn configure() -> Router<AppState> {
+    let routes = Router::new().route("/permissions", get(get_permissions));
+
+    Router::new().nest("/me", routes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_permissions_by_project() {
+        let project = ProjectId::new();
+        let other_team_project = Uuid::new_v4();
+        let granted = vec![
+            (Uuid::nil(), Permission::ProjectCreate),
+            (*project, Permission::ImageCreate),
+            (*project, Permission::ProjectRead),
+            (*project, Permission::ImageCreate),
+            (other_team_project, Permission::UploadProfileWrite),
+        ];
+
+        let (global, projects) = summarize(&granted, &[project]);
+        assert_eq!(global, vec![Permission::ProjectCreate]);
+        assert_eq!(
+            projects,
+            vec![ProjectPermissions {
+                project_id: project,
+                permissions: vec![Permission::ProjectRead, Permission::ImageCreate],
+            }]
+        );
+    }
+
+    #[test]
+    fn admin_has_every_permission() {
+        let project = ProjectId::new();
+        let granted = vec![(Uuid::nil(), Permission::TeamAdmin)];
+
+        let (global, projects) = summarize(&granted, &[project]);
+        assert!(global.contains(&Permission::TeamAdmin));
+        assert_eq!(projects.len(), 1);
+        assert!(projects[0]
+            .permissions
+            .contains(&Permission::UploadProfileWrite));
+        assert!(projects[0].permissions.contains(&Permission::ImageCreate));
+    }
+}
@@ -2,19 +2,125 @@ use axum::Router;
 
 use crate::shared_state::AppState;
 
-mod conversion_profile;
+mod abuse_report;
+mod admin;
+mod analytics;
+pub(crate) mod conversion_profile;
+mod cors;
+mod delivery_domain;
+mod gallery;
 mod health;
-mod image;
+mod hotlink;
+pub(crate) mod image;
+mod imgix;
+pub mod imgproxy;
+mod impersonation;
+mod label_policy;
+mod link_check;
+mod local_storage;
+mod me;
+mod organization;
+mod project_access_token;
+mod project_grant;
+mod serve;
+mod share_link;
+pub mod status;
 pub mod storage_location;
+mod tagging_rule;
+mod transformation_preset;
 mod upload_profile;
+mod webhook;
 
 pub fn configure_routes(router: Router<AppState>) -> Router<AppState> {
     let api_routes = router
         .merge(health::configure())
+        .merge(admin::configure())
+        .merge(analytics::configure())
+        .merge(abuse_report::configure())
+        .merge(hotlink::configure())
         .merge(image::configure())
+        .merge(impersonation::configure())
+        .merge(label_policy::configure())
+        .merge(link_check::configure())
+        .merge(me::configure())
+        .merge(status::configure())
+        .merge(project_access_token::configure())
+        .merge(project_grant::configure())
         .merge(upload_profile::configure())
         .merge(conversion_profile::configure())
-        .merge(storage_location::configure());
+        .merge(cors::configure())
+        .merge(delivery_domain::configure())
+        .merge(organization::configure())
+        .merge(gallery::configure())
+        .merge(share_link::configure())
+        .merge(storage_location::configure())
+        .merge(tagging_rule::configure())
+        .merge(transformation_preset::configure())
+        .merge(webhook::configure());
 
-    Router::new().nest("/api", api_routes)
+    Router::new()
+        .nest("/api", api_routes)
+        .merge(serve::configure())
+        .merge(gallery::configure_redeem())
+        .merge(share_link::configure_pages())
+        .merge(imgproxy::configure())
+        .merge(local_storage::configure())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use pic_store_client::api::ENDPOINTS;
+    use regex::Regex;
+
+    /// Routes served outside of `/api`, which aren't part of the API description.
+    const NON_API_ROUTES: &[&str] = &[
+        "/serve/:image_id",
+        "/gallery_access",
+        "/share/:token",
+        "/imgproxy/*path",
+        "/local_storage/:storage_location_id/*path",
+    ];
+
+    fn route_paths(dir: &Path, pattern: &Regex, paths: &mut Vec<String>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                route_paths(&path, pattern, paths);
+            } else if path.extension().is_some_and(|ext| ext == "rs") {
+                let source = std::fs::read_to_string(&path).unwrap();
+                paths.extend(pattern.captures_iter(&source).map(|c| c[1].to_string()));
+            }
+        }
+    }
+
+    /// Each route must be in the API description that the OpenAPI document and the generated
+    /// clients come from. Routes are often nested under a prefix, so this only checks that some
+    /// endpoint ends with each route's path.
+    #[test]
+    fn routes_are_described() {
+        let pattern = Regex::new(r#"\.route\(\s*"([^"]+)""#).unwrap();
+        let mut paths = Vec::new();
+        route_paths(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("src/routes"),
+            &pattern,
+            &mut paths,
+        );
+        assert!(!paths.is_empty());
+
+        for path in paths {
+            if NON_API_ROUTES.contains(&path.as_str()) {
+                continue;
+            }
+
+            let suffix = path.trim_end_matches('/');
+            assert!(
+                ENDPOINTS
+                    .iter()
+                    .any(|endpoint| endpoint.path.ends_with(suffix)),
+                "route {path} is missing from pic_store_client::api::ENDPOINTS"
+            );
+        }
+    }
 }
@@ -0,0 +1,638 @@
+//! Organizations group several teams, for customers that split their work by brand or department.
+//! The teams in an organization share its billing details, and can share storage locations with
+//! each other.
+//!
+//! Organization roles are separate from team roles. Admins manage the organization and its
+//! teams, the billing role can see the usage of every team, and members can see the
+//! organization. Managing the projects inside each team still uses the team's own roles.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post, put},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use db::{
+    object_id::{OrganizationId, StorageLocationId, TeamId, UserId},
+    organizations::{self, organization_members, NewOrganization},
+    permissions::{self, GlobalPermission, ProjectPermission},
+    storage_locations, OrganizationRole, PoolExt,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    auth::{
+        must_be_instance_admin, must_have_global_permission, must_have_organization_role,
+        Authenticated, UserInfo,
+    },
+    json::Json,
+    shared_state::AppState,
+    Error, Result,
+};
+
+use super::admin::storage_usage;
+
+#[derive(Debug, Deserialize)]
+struct OrganizationInput {
+    name: String,
+    billing_email: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BillingInput {
+    billing_email: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MemberInput {
+    role: OrganizationRole,
+}
+
+#[derive(Debug, Deserialize)]
+struct TeamInput {
+    team_id: TeamId,
+}
+
+#[derive(Deserialize)]
+struct MemberPath {
+    organization_id: OrganizationId,
+    user_id: UserId,
+}
+
+#[derive(Deserialize)]
+struct TeamPath {
+    organization_id: OrganizationId,
+    team_id: TeamId,
+}
+
+#[derive(Deserialize)]
+struct StorageLocationPath {
+    organization_id: OrganizationId,
+    storage_location_id: StorageLocationId,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = organizations)]
+struct OrganizationOutput {
+    id: OrganizationId,
+    name: String,
+    billing_email: Option<String>,
+    created: DateTime<Utc>,
+    updated: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = organization_members)]
+struct MemberOutput {
+    user_id: UserId,
+    role: OrganizationRole,
+    added: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = db::teams)]
+struct TeamOutput {
+    id: TeamId,
+    name: String,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = storage_locations)]
+struct SharedStorageLocationOutput {
+    id: StorageLocationId,
+    team_id: TeamId,
+    name: String,
+    public_url_base: String,
+}
+
+fn admin_count(conn: &mut PgConnection, organization_id: OrganizationId) -> QueryResult<i64> {
+    organization_members::table
+        .filter(organization_members::organization_id.eq(organization_id))
+        .filter(organization_members::role.eq(OrganizationRole::Admin))
+        .count()
+        .get_result(conn)
+}
+
+/// Make sure that the organization keeps an admin when the user stops being one.
+fn must_keep_admin(
+    conn: &mut PgConnection,
+    organization_id: OrganizationId,
+    user_id: UserId,
+) -> Result<()> {
+    let role = organizations::member_role(conn, organization_id, user_id)?;
+    if role == Some(OrganizationRole::Admin) && admin_count(conn, organization_id)? <= 1 {
+        return Err(Error::LastOrganizationAdmin);
+    }
+
+    Ok(())
+}
+
+/// List the organizations that the user belongs to.
+async fn list_organizations(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+) -> Result<impl IntoResponse> {
+    let objects = state
+        .db
+        .interact(move |conn| {
+            organizations::table
+                .inner_join(organization_members::table)
+                .filter(organization_members::user_id.eq(user.user_id))
+                .filter(organizations::deleted.is_null())
+                .select((OrganizationOutput::as_select(), organization_members::role))
+                .order(organizations::name.asc())
+                .load::<(OrganizationOutput, OrganizationRole)>(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    let objects = objects
+        .into_iter()
+        .map(|(organization, role)| json!({ "organization": organization, "role": role }))
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(objects)))
+}
+
+/// Create an organization containing the user's team. The user becomes its admin.
+async fn new_organization(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Json(body): Json<OrganizationInput>,
+) -> Result<impl IntoResponse> {
+    let organization = state
+        .db
+        .transaction(move |conn| {
+            must_have_global_permission(conn, &user, GlobalPermission::TeamAdmin)?;
+
+            let organization = diesel::insert_into(organizations::table)
+                .values(NewOrganization {
+                    id: OrganizationId::new(),
+                    name: body.name,
+                    billing_email: body.billing_email,
+                })
+                .returning(OrganizationOutput::as_select())
+                .get_result(conn)?;
+
+            let updated = diesel::update(db::teams::table)
+                .filter(db::teams::id.eq(user.team_id))
+                .filter(db::teams::organization_id.is_null())
+                .set(db::teams::organization_id.eq(organization.id))
+                .execute(conn)?;
+            if updated == 0 {
+                return Err(Error::TeamInOrganization);
+            }
+
+            diesel::insert_into(organization_members::table)
+                .values((
+                    organization_members::organization_id.eq(organization.id),
+                    organization_members::user_id.eq(user.user_id),
+                    organization_members::role.eq(OrganizationRole::Admin),
+                ))
+                .execute(conn)?;
+
+            Ok::<_, Error>(organization)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(organization)))
+}
+
+/// Get an organization with its teams and members.
+async fn get_organization(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(organization_id): Path<OrganizationId>,
+) -> Result<impl IntoResponse> {
+    let (organization, role, teams, members) = state
+        .db
+        .interact(move |conn| {
+            let role = must_have_organization_role(
+                conn,
+                &user,
+                organization_id,
+                OrganizationRole::Member,
+            )?;
+
+            let organization = organizations::table
+                .filter(organizations::id.eq(organization_id))
+                .select(OrganizationOutput::as_select())
+                .first(conn)?;
+
+            let teams = db::teams::table
+                .filter(db::teams::organization_id.eq(organization_id))
+                .filter(db::teams::deleted.is_null())
+                .select(TeamOutput::as_select())
+                .order(db::teams::name.asc())
+                .load(conn)?;
+
+            let members = organization_members::table
+                .filter(organization_members::organization_id.eq(organization_id))
+                .select(MemberOutput::as_select())
+                .order(organization_members::added.asc())
+                .load(conn)?;
+
+            Ok::<_, Error>((organization, role, teams, members))
+        })
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "organization": organization,
+            "role": role,
+            "teams": teams,
+            "members": members,
+        })),
+    ))
+}
+
+async fn write_organization(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(organization_id): Path<OrganizationId>,
+    Json(body): Json<OrganizationInput>,
+) -> Result<impl IntoResponse> {
+    let organization = state
+        .db
+        .interact(move |conn| {
+            must_have_organization_role(conn, &user, organization_id, OrganizationRole::Admin)?;
+
+            diesel::update(organizations::table)
+                .filter(organizations::id.eq(organization_id))
+                .set((
+                    organizations::name.eq(body.name),
+                    organizations::billing_email.eq(body.billing_email),
+                    organizations::updated.eq(Utc::now()),
+                ))
+                .returning(OrganizationOutput::as_select())
+                .get_result(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(organization)))
+}
+
+/// Change where the organization's invoices go. This only needs the billing role.
+async fn write_billing(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(organization_id): Path<OrganizationId>,
+    Json(body): Json<BillingInput>,
+) -> Result<impl IntoResponse> {
+    let organization = state
+        .db
+        .interact(move |conn| {
+            must_have_organization_role(conn, &user, organization_id, OrganizationRole::Billing)?;
+
+            diesel::update(organizations::table)
+                .filter(organizations::id.eq(organization_id))
+                .set((
+                    organizations::billing_email.eq(body.billing_email),
+                    organizations::updated.eq(Utc::now()),
+                ))
+                .returning(OrganizationOutput::as_select())
+                .get_result(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(organization)))
+}
+
+/// Add a user to the organization, or change their role.
+async fn write_member(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<MemberPath>,
+    Json(body): Json<MemberInput>,
+) -> Result<impl IntoResponse> {
+    let member = state
+        .db
+        .transaction(move |conn| {
+            must_have_organization_role(
+                conn,
+                &user,
+                path.organization_id,
+                OrganizationRole::Admin,
+            )?;
+
+            let user_exists = diesel::select(diesel::dsl::exists(
+                db::users::table
+                    .filter(db::users::id.eq(path.user_id))
+                    .filter(db::users::deleted.is_null()),
+            ))
+            .get_result::<bool>(conn)?;
+            if !user_exists {
+                return Err(Error::ObjectNotFound("user"));
+            }
+
+            if body.role != OrganizationRole::Admin {
+                must_keep_admin(conn, path.organization_id, path.user_id)?;
+            }
+
+            diesel::insert_into(organization_members::table)
+                .values((
+                    organization_members::organization_id.eq(path.organization_id),
+                    organization_members::user_id.eq(path.user_id),
+                    organization_members::role.eq(body.role),
+                ))
+                .on_conflict((
+                    organization_members::organization_id,
+                    organization_members::user_id,
+                ))
+                .do_update()
+                .set(organization_members::role.eq(body.role))
+                .returning(MemberOutput::as_select())
+                .get_result(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(member)))
+}
+
+async fn remove_member(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<MemberPath>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .transaction(move |conn| {
+            must_have_organization_role(
+                conn,
+                &user,
+                path.organization_id,
+                OrganizationRole::Admin,
+            )?;
+            must_keep_admin(conn, path.organization_id, path.user_id)?;
+
+            diesel::delete(organization_members::table)
+                .filter(organization_members::organization_id.eq(path.organization_id))
+                .filter(organization_members::user_id.eq(path.user_id))
+                .execute(conn)?;
+
+            Ok::<_, Error>(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+/// Add a team to the organization. Organization admins can add their own team if they are also
+/// an admin of it. Instance admins can add any team.
+async fn add_team(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(organization_id): Path<OrganizationId>,
+    Json(body): Json<TeamInput>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .transaction(move |conn| {
+            must_have_organization_role(conn, &user, organization_id, OrganizationRole::Admin)?;
+            if body.team_id != user.team_id {
+                must_be_instance_admin(conn, &user)?;
+            } else {
+                must_have_global_permission(conn, &user, GlobalPermission::TeamAdmin)?;
+            }
+
+            let team_org = db::teams::table
+                .filter(db::teams::id.eq(body.team_id))
+                .filter(db::teams::deleted.is_null())
+                .select(db::teams::organization_id)
+                .first::<Option<OrganizationId>>(conn)
+                .optional()?
+                .ok_or(Error::ObjectNotFound("team"))?;
+
+            match team_org {
+                Some(id) if id == organization_id => {}
+                Some(_) => return Err(Error::TeamInOrganization),
+                None => {
+                    diesel::update(db::teams::table)
+                        .filter(db::teams::id.eq(body.team_id))
+                        .set(db::teams::organization_id.eq(organization_id))
+                        .execute(conn)?;
+                }
+            }
+
+            Ok::<_, Error>(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+/// Remove a team from the organization. Its storage locations stop being shared with the other
+/// teams.
+async fn remove_team(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<TeamPath>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .transaction(move |conn| {
+            must_have_organization_role(
+                conn,
+                &user,
+                path.organization_id,
+                OrganizationRole::Admin,
+            )?;
+
+            let removed = diesel::update(db::teams::table)
+                .filter(db::teams::id.eq(path.team_id))
+                .filter(db::teams::organization_id.eq(path.organization_id))
+                .set(db::teams::organization_id.eq(None::<OrganizationId>))
+                .execute(conn)?;
+            if removed == 0 {
+                return Err(Error::ObjectNotFound("team"));
+            }
+
+            diesel::update(storage_locations::table)
+                .filter(storage_locations::team_id.eq(path.team_id))
+                .filter(storage_locations::organization_id.eq(path.organization_id))
+                .set(storage_locations::organization_id.eq(None::<OrganizationId>))
+                .execute(conn)?;
+
+            Ok::<_, Error>(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+/// Get the storage used by each team in the organization, for billing.
+async fn get_usage(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(organization_id): Path<OrganizationId>,
+) -> Result<impl IntoResponse> {
+    let usage = state
+        .db
+        .interact(move |conn| {
+            must_have_organization_role(conn, &user, organization_id, OrganizationRole::Billing)?;
+
+            let teams = db::teams::table
+                .filter(db::teams::organization_id.eq(organization_id))
+                .filter(db::teams::deleted.is_null())
+                .select((db::teams::id, db::teams::name))
+                .order(db::teams::name.asc())
+                .load::<(TeamId, String)>(conn)?;
+
+            storage_usage(conn, teams)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(usage)))
+}
+
+async fn list_shared_storage_locations(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(organization_id): Path<OrganizationId>,
+) -> Result<impl IntoResponse> {
+    let objects = state
+        .db
+        .interact(move |conn| {
+            must_have_organization_role(conn, &user, organization_id, OrganizationRole::Member)?;
+
+            storage_locations::table
+                .filter(storage_locations::organization_id.eq(organization_id))
+                .filter(storage_locations::deleted.is_null())
+                .select(SharedStorageLocationOutput::as_select())
+                .order(storage_locations::name.asc())
+                .load(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(objects)))
+}
+
+/// Check that an organization admin can share or unshare a storage location. The location must
+/// be one of the team's global locations, and the user needs permission to change it.
+fn must_manage_shared_location(
+    conn: &mut PgConnection,
+    user: &UserInfo,
+    path: &StorageLocationPath,
+) -> Result<()> {
+    must_have_organization_role(conn, user, path.organization_id, OrganizationRole::Admin)?;
+
+    let in_organization = diesel::select(diesel::dsl::exists(
+        db::teams::table
+            .filter(db::teams::id.eq(user.team_id))
+            .filter(db::teams::organization_id.eq(path.organization_id)),
+    ))
+    .get_result::<bool>(conn)?;
+    if !in_organization {
+        return Err(Error::ObjectNotFound("team"));
+    }
+
+    let location_exists = diesel::select(diesel::dsl::exists(
+        storage_locations::table
+            .filter(storage_locations::id.eq(path.storage_location_id))
+            .filter(storage_locations::team_id.eq(user.team_id))
+            .filter(storage_locations::project_id.is_null())
+            .filter(storage_locations::deleted.is_null()),
+    ))
+    .get_result::<bool>(conn)?;
+    if !location_exists {
+        return Err(Error::ObjectNotFound("storage location"));
+    }
+
+    if !permissions::has_permission_on_project(
+        conn,
+        user.team_id,
+        &user.roles,
+        None,
+        ProjectPermission::StorageLocationWrite,
+    )? {
+        return Err(Error::MissingPermission(
+            ProjectPermission::StorageLocationWrite.into(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Share one of the team's storage locations with the other teams in the organization.
+async fn share_storage_location(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<StorageLocationPath>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .interact(move |conn| {
+            must_manage_shared_location(conn, &user, &path)?;
+
+            diesel::update(storage_locations::table)
+                .filter(storage_locations::id.eq(path.storage_location_id))
+                .set(storage_locations::organization_id.eq(path.organization_id))
+                .execute(conn)?;
+
+            Ok::<_, Error>(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+async fn unshare_storage_location(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<StorageLocationPath>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .interact(move |conn| {
+            must_manage_shared_location(conn, &user, &path)?;
+
+            diesel::update(storage_locations::table)
+                .filter(storage_locations::id.eq(path.storage_location_id))
+                .filter(storage_locations::organization_id.eq(path.organization_id))
+                .set(storage_locations::organization_id.eq(None::<OrganizationId>))
+                .execute(conn)?;
+
+            Ok::<_, Error>(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+pub fn configure() -> Router<AppState> {
+    let routes = Router::new()
+        .route("/", get(list_organizations))
+        .route("/", post(new_organization))
+        .route("/:organization_id", get(get_organization))
+        .route("/:organization_id", put(write_organization))
+        .route("/:organization_id/billing", put(write_billing))
+        .route("/:organization_id/usage", get(get_usage))
+        .route("/:organization_id/members/:user_id", put(write_member))
+        .route("/:organization_id/members/:user_id", delete(remove_member))
+        .route("/:organization_id/teams", post(add_team))
+        .route("/:organization_id/teams/:team_id", delete(remove_team))
+        .route(
+            "/:organization_id/storage_locations",
+            get(list_shared_storage_locations),
+        )
+        .route(
+            "/:organization_id/storage_locations/:storage_location_id",
+            put(share_storage_location),
+        )
+        .route(
+            "/:organization_id/storage_locations/:storage_location_id",
+            delete(unshare_storage_location),
+        );
+
+    Router::new().nest("/organizations", routes)
+}
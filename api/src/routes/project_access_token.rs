@@ -0,0 +1,189 @@
+//! Private projects and the access tokens used to read their images from the serve route.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post, put},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use db::{
+    object_id::{ProjectAccessTokenId, ProjectId, TeamId, UserId},
+    permissions::ProjectPermission,
+    project_access_tokens::{self, NewProjectAccessToken},
+    PoolExt,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    access_token,
+    auth::{must_own_project, Authenticated},
+    json::Json,
+    shared_state::AppState,
+    Error, Result,
+};
+
+#[derive(Debug, Deserialize)]
+struct ProjectPrivacyInput {
+    private: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct NewAccessTokenInput {
+    name: String,
+    expires: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct AccessTokenPath {
+    project_id: ProjectId,
+    token_id: ProjectAccessTokenId,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = project_access_tokens)]
+struct AccessTokenOutput {
+    id: ProjectAccessTokenId,
+    team_id: TeamId,
+    project_id: ProjectId,
+    name: String,
+    created_by: UserId,
+    created: DateTime<Utc>,
+    expires: Option<DateTime<Utc>>,
+    revoked: Option<DateTime<Utc>>,
+}
+
+/// Mark a project as private or public. Images in private projects can only be served with an
+/// access token or a signed URL.
+async fn set_project_private(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Json(body): Json<ProjectPrivacyInput>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            diesel::update(db::projects::table)
+                .filter(db::projects::id.eq(project_id))
+                .set((
+                    db::projects::private.eq(body.private),
+                    db::projects::updated.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            Ok::<_, Error>(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({ "private": body.private }))))
+}
+
+async fn list_access_tokens(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+) -> Result<impl IntoResponse> {
+    let tokens = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            project_access_tokens::table
+                .filter(project_access_tokens::project_id.eq(project_id))
+                .select(AccessTokenOutput::as_select())
+                .order(project_access_tokens::created.desc())
+                .load(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(tokens)))
+}
+
+/// Create an access token for a project. The token itself is only returned here, and can not be
+/// retrieved later.
+async fn new_access_token(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Json(body): Json<NewAccessTokenInput>,
+) -> Result<impl IntoResponse> {
+    let id = ProjectAccessTokenId::new();
+    let (token, hash) = access_token::generate(id);
+
+    let output = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            diesel::insert_into(project_access_tokens::table)
+                .values(NewProjectAccessToken {
+                    id,
+                    team_id: user.team_id,
+                    project_id,
+                    name: body.name,
+                    hash: hash.as_bytes().to_vec(),
+                    created_by: user.user_id,
+                    expires: body.expires,
+                })
+                .returning(AccessTokenOutput::as_select())
+                .get_result(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "token": token,
+            "info": output,
+        })),
+    ))
+}
+
+async fn revoke_access_token(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<AccessTokenPath>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .interact(move |conn| {
+            must_own_project(
+                conn,
+                &user,
+                path.project_id,
+                ProjectPermission::ProjectWrite,
+            )?;
+
+            diesel::update(project_access_tokens::table)
+                .filter(project_access_tokens::id.eq(path.token_id))
+                .filter(project_access_tokens::project_id.eq(path.project_id))
+                .filter(project_access_tokens::revoked.is_null())
+                .set(project_access_tokens::revoked.eq(Utc::now()))
+                .execute(conn)?;
+
+            Ok::<_, Error>(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+pub fn configure() -> Router<AppState> {
+    let token_routes = Router::new()
+        .route("/", get(list_access_tokens))
+        .route("/", post(new_access_token))
+        .route("/:token_id", delete(revoke_access_token));
+
+    Router::new()
+        .route("/projects/:project_id/private", put(set_project_private))
+        .nest("/projects/:project_id/access_tokens", token_routes)
+}
@@ -0,0 +1,274 @@
+//! Grants that give another team read access to the images in a project, or in one collection
+//! of a project, for sharing work between teams. Every grant change is recorded, along with the
+//! images read through a grant, so that the owning team can see how its project has been used.
+//! Repeated reads of an image by the same user are recorded at most once a day.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use db::{
+    object_id::{BaseImageId, ProjectGrantId, ProjectId, TeamId, UserId},
+    permissions::ProjectPermission,
+    project_grants::{self, project_grant_events, NewProjectGrant},
+    PoolExt, ProjectGrantEventType,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    auth::{must_own_project, Authenticated},
+    json::Json,
+    shared_state::AppState,
+    Error, Result,
+};
+
+#[derive(Debug, Deserialize)]
+struct NewProjectGrantInput {
+    /// The team to give access to.
+    team_id: TeamId,
+    /// Only give access to the images in this collection, instead of the whole project.
+    collection: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProjectGrantPath {
+    project_id: ProjectId,
+    grant_id: ProjectGrantId,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = project_grants)]
+struct ProjectGrantOutput {
+    id: ProjectGrantId,
+    team_id: TeamId,
+    project_id: ProjectId,
+    grantee_team_id: TeamId,
+    created_by: UserId,
+    created: DateTime<Utc>,
+    revoked: Option<DateTime<Utc>>,
+    revoked_by: Option<UserId>,
+    collection: Option<String>,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = project_grant_events)]
+struct ProjectGrantEventOutput {
+    event_type: ProjectGrantEventType,
+    team_id: TeamId,
+    user_id: UserId,
+    base_image_id: Option<BaseImageId>,
+    created: DateTime<Utc>,
+}
+
+async fn list_project_grants(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+) -> Result<impl IntoResponse> {
+    let grants = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectRead)?;
+
+            project_grants::table
+                .filter(project_grants::project_id.eq(project_id))
+                .select(ProjectGrantOutput::as_select())
+                .order(project_grants::created.desc())
+                .load(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(grants)))
+}
+
+/// Give another team read access to a project or one of its collections. If the team already has
+/// the same access, the existing grant is returned.
+async fn new_project_grant(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Json(body): Json<NewProjectGrantInput>,
+) -> Result<impl IntoResponse> {
+    let grant = state
+        .db
+        .transaction(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            if body.team_id == user.team_id {
+                return Err(Error::ObjectNotFound("team"));
+            }
+
+            let team_exists = diesel::select(diesel::dsl::exists(
+                db::teams::table
+                    .filter(db::teams::id.eq(body.team_id))
+                    .filter(db::teams::deleted.is_null()),
+            ))
+            .get_result::<bool>(conn)?;
+            if !team_exists {
+                return Err(Error::ObjectNotFound("team"));
+            }
+
+            let grant_collection = body.collection.filter(|c| !c.is_empty());
+            if let Some(existing) = project_grants::find_active(
+                conn,
+                project_id,
+                body.team_id,
+                grant_collection.as_deref(),
+            )? {
+                return project_grants::table
+                    .filter(project_grants::id.eq(existing.id))
+                    .select(ProjectGrantOutput::as_select())
+                    .first(conn)
+                    .map_err(Error::from);
+            }
+
+            let value = NewProjectGrant {
+                id: ProjectGrantId::new(),
+                team_id: user.team_id,
+                project_id,
+                grantee_team_id: body.team_id,
+                created_by: user.user_id,
+                collection: grant_collection,
+            };
+
+            let grant = diesel::insert_into(project_grants::table)
+                .values(&value)
+                .returning(ProjectGrantOutput::as_select())
+                .get_result(conn)?;
+
+            project_grants::record_event(
+                conn,
+                value.id,
+                ProjectGrantEventType::Created,
+                (user.team_id, user.user_id),
+                None,
+            )?;
+
+            Ok(grant)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(grant)))
+}
+
+/// Revoke a grant's access to a project or collection.
+async fn revoke_project_grant(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<ProjectGrantPath>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .transaction(move |conn| {
+            must_own_project(
+                conn,
+                &user,
+                path.project_id,
+                ProjectPermission::ProjectWrite,
+            )?;
+
+            let revoked = diesel::update(project_grants::table)
+                .filter(project_grants::id.eq(path.grant_id))
+                .filter(project_grants::project_id.eq(path.project_id))
+                .filter(project_grants::revoked.is_null())
+                .set((
+                    project_grants::revoked.eq(Utc::now()),
+                    project_grants::revoked_by.eq(user.user_id),
+                ))
+                .execute(conn)?;
+
+            if revoked > 0 {
+                project_grants::record_event(
+                    conn,
+                    path.grant_id,
+                    ProjectGrantEventType::Revoked,
+                    (user.team_id, user.user_id),
+                    None,
+                )?;
+            }
+
+            Ok::<_, Error>(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+/// Get the audit log for a grant.
+async fn list_project_grant_events(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<ProjectGrantPath>,
+) -> Result<impl IntoResponse> {
+    let (grant, events) = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, path.project_id, ProjectPermission::ProjectRead)?;
+
+            let grant = project_grants::table
+                .filter(project_grants::id.eq(path.grant_id))
+                .filter(project_grants::project_id.eq(path.project_id))
+                .select(ProjectGrantOutput::as_select())
+                .first(conn)
+                .optional()?
+                .ok_or(Error::NotFound)?;
+
+            let events = project_grant_events::table
+                .filter(project_grant_events::grant_id.eq(path.grant_id))
+                .select(ProjectGrantEventOutput::as_select())
+                .order(project_grant_events::created.asc())
+                .load(conn)?;
+
+            Ok::<_, Error>((grant, events))
+        })
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "grant": grant,
+            "events": events,
+        })),
+    ))
+}
+
+/// List the projects that other teams have shared with the user's team.
+async fn list_received_grants(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+) -> Result<impl IntoResponse> {
+    let grants = state
+        .db
+        .interact(move |conn| {
+            project_grants::table
+                .filter(project_grants::grantee_team_id.eq(user.team_id))
+                .filter(project_grants::revoked.is_null())
+                .select(ProjectGrantOutput::as_select())
+                .order(project_grants::created.desc())
+                .load(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(grants)))
+}
+
+pub fn configure() -> Router<AppState> {
+    let project_routes = Router::new()
+        .route("/", get(list_project_grants))
+        .route("/", post(new_project_grant))
+        .route("/:grant_id", delete(revoke_project_grant))
+        .route("/:grant_id/events", get(list_project_grant_events));
+
+    Router::new()
+        .nest("/projects/:project_id/grants", project_routes)
+        .route("/grants", get(list_received_grants))
+}
@@ -0,0 +1,1892 @@
+//! Serve image variants that are generated on demand.
+//!
+//! This lets a CDN sit directly in front of the server. The first request for a given
+//! width, format and quality converts the image and saves the result as an output image, and
+//! later requests read the saved image from storage. Profile outputs that match the request
+//! are reused.
+
+use axum::{
+    body::Bytes,
+    extract::{rejection::QueryRejection, Path, Query, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use db::{
+    conversion_profiles::{self, ConversionFormat, ConversionOutput, ConversionSize},
+    delivery_domains, image_base_location,
+    object_id::{
+        BaseImageId, ConversionProfileId, OutputImageId, ProjectId, StorageLocationId, TeamId,
+    },
+    output_image_mirrors,
+    output_images::{self, NewOutputImage},
+    project_access_tokens,
+    storage_locations::{self, StorageLocation},
+    upload_profiles::FormatFallbacks,
+    BaseImageStatus, ImageFormat, OutputImageStatus, PoolExt, StorageServeMode, TeamStatus,
+};
+use diesel::{prelude::*, upsert::excluded};
+use pic_store_convert as convert;
+use pic_store_db as db;
+use pic_store_storage as storage;
+use serde::Deserialize;
+use tracing::{event, Level};
+
+use crate::{
+    access_token::AccessToken,
+    cdn_purge::SURROGATE_KEY_HEADER,
+    checksum,
+    client_hints::{self, ClientHints},
+    conversion_pause, gallery,
+    geo::GeoRestriction,
+    hotlink::RefererRestriction,
+    jobs::{create_output_images::preset_operations, CreateOutputImagesJobPayload},
+    mirrors,
+    range::{self, RangedBody},
+    routes::{
+        image::{build_output_images, OutputImageBase},
+        imgix::ImgixQuery,
+    },
+    shared_state::AppState,
+    signed_url::{SignedParams, SignedUrl},
+    Error, Result,
+};
+
+/// How long a CDN or browser may cache a served image, in seconds.
+const CACHE_MAX_AGE: i64 = 86400;
+
+/// The quality used for clients that want to save data, when the upload profile doesn't set one.
+const SAVE_DATA_QUALITY: u8 = 50;
+
+/// How long a stand-in for a missing output may be cached, in seconds.
+const STAND_IN_MAX_AGE: i64 = 60;
+
+/// The most stored outputs to try reading when looking for a stand-in.
+const MAX_STAND_IN_READS: usize = 3;
+
+/// An output that has been waiting for conversion for longer than this is assumed to be stuck,
+/// and is converted by the serve route instead of waiting for the job.
+const REGENERATION_TIMEOUT_MINUTES: i64 = 10;
+
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct ServeQuery {
+    /// The width of the image. Defaults to the width of the original image.
+    pub width: Option<u32>,
+    /// The maximum height of the image. The image keeps its aspect ratio.
+    pub height: Option<u32>,
+    /// The format of the image. If omitted, the format is chosen from the `Accept` header.
+    pub format: Option<ImageFormat>,
+    /// Encoder quality from 1 to 100. Uses the encoder's default if omitted.
+    pub quality: Option<u8>,
+}
+
+impl ServeQuery {
+    /// The parameters that a signed URL covers.
+    fn signed_params(&self) -> SignedParams {
+        SignedParams {
+            width: self.width,
+            height: self.height,
+            format: self.format,
+            quality: self.quality,
+        }
+    }
+}
+
+/// Everything needed to find or create a variant of an image.
+struct ServeSource {
+    team_id: TeamId,
+    project_id: ProjectId,
+    location: String,
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    key_template: Option<String>,
+    output_key_prefix: Option<String>,
+    /// False when the retention rules have removed the original, so new variants can't be made.
+    original_available: bool,
+    /// The formats produced by the conversion profile.
+    profile_formats: Vec<ImageFormat>,
+    /// The widths that the conversion profile lists. Profiles that choose their sizes
+    /// automatically don't list any.
+    profile_widths: Vec<u32>,
+    /// The qualities that the conversion profile uses.
+    profile_qualities: Vec<u8>,
+    /// The conversion profile and its current version.
+    profile_version: (ConversionProfileId, i32),
+    /// The profile version that the base image's profile outputs were rendered with.
+    base_profile_version: Option<(ConversionProfileId, i32)>,
+    operations: Vec<convert::Operation>,
+    base_storage: StorageLocation,
+    base_storage_path: String,
+    output_storage: StorageLocation,
+    output_storage_path: String,
+    /// The profile's mirror storage locations, which are read when the output storage location
+    /// fails.
+    output_mirrors: Vec<StorageLocation>,
+    project_base_location: String,
+    /// The profile's path within the output storage location.
+    output_profile_path: Option<String>,
+    /// The public URL of the output images, when the output storage location redirects instead
+    /// of proxying.
+    output_redirect_base: Option<String>,
+    require_signed_urls: bool,
+    private: bool,
+    geo: GeoRestriction,
+    referers: RefererRestriction,
+    /// The upload profile's Cache-Control setting.
+    cache_control: Option<String>,
+    /// The quality for clients that want to save data, or `None` if the profile turns it off.
+    save_data_quality: Option<u8>,
+    /// The upload profile's preferred formats, which replace the usual negotiation.
+    format_fallbacks: Option<FormatFallbacks>,
+}
+
+/// Return true if a variant that was rendered with the given profile version should be rendered
+/// again. Variants that didn't record a version were rendered along with the base image's
+/// outputs.
+fn variant_is_stale(
+    current: (ConversionProfileId, i32),
+    base_image: Option<(ConversionProfileId, i32)>,
+    rendered_with: Option<(ConversionProfileId, i32)>,
+) -> bool {
+    rendered_with
+        .or(base_image)
+        .map(|rendered_with| rendered_with != current)
+        .unwrap_or(false)
+}
+
+fn conversion_format(format: ImageFormat, quality: Option<f32>) -> Result<ConversionFormat> {
+    let format = match format {
+        ImageFormat::Png => ConversionFormat::Png { condition: None },
+        ImageFormat::Jpg => ConversionFormat::Jpg {
+            quality,
+            condition: None,
+        },
+        ImageFormat::Avif => ConversionFormat::Avif {
+            quality,
+            condition: None,
+        },
+        ImageFormat::Webp => ConversionFormat::Webp {
+            quality,
+            condition: None,
+        },
+        ImageFormat::Heic => {
+            return Err(Error::InvalidTransformation("heic output is not supported"))
+        }
+    };
+
+    Ok(format)
+}
+
+pub(crate) fn content_type(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png => "image/png",
+        ImageFormat::Jpg => "image/jpeg",
+        ImageFormat::Avif => "image/avif",
+        ImageFormat::Webp => "image/webp",
+        ImageFormat::Heic => "image/heic",
+    }
+}
+
+/// The formats that the client accepts, in the order that we would prefer to send them.
+fn accepted_formats(accept: &str, source_format: ImageFormat) -> Vec<ImageFormat> {
+    let accept = if accept.trim().is_empty() {
+        "*/*"
+    } else {
+        accept
+    };
+
+    let accepted = accept
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let media_type = parts.next()?.trim().to_ascii_lowercase();
+            let rejected = parts.any(|param| {
+                param
+                    .trim()
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .map(|q| q <= 0.0)
+                    .unwrap_or(false)
+            });
+
+            (!rejected).then_some(media_type)
+        })
+        .collect::<Vec<_>>();
+
+    // Stick with PNG for PNG images when possible, since JPEG would lose any transparency.
+    let order = if source_format == ImageFormat::Png {
+        [
+            ImageFormat::Avif,
+            ImageFormat::Webp,
+            ImageFormat::Png,
+            ImageFormat::Jpg,
+        ]
+    } else {
+        [
+            ImageFormat::Avif,
+            ImageFormat::Webp,
+            ImageFormat::Jpg,
+            ImageFormat::Png,
+        ]
+    };
+
+    order
+        .into_iter()
+        .filter(|&format| {
+            accepted.iter().any(|media_type| {
+                media_type == content_type(format)
+                    // Wildcards only count for the formats that every client can display.
+                    || (matches!(format, ImageFormat::Jpg | ImageFormat::Png)
+                        && (media_type == "*/*" || media_type == "image/*"))
+            })
+        })
+        .collect()
+}
+
+/// Choose the best format for the client, preferring the formats that the conversion profile
+/// already produces.
+fn negotiate_format(
+    accept: &str,
+    source_format: ImageFormat,
+    profile_formats: &[ImageFormat],
+) -> Option<ImageFormat> {
+    let accepted = accepted_formats(accept, source_format);
+    accepted
+        .iter()
+        .find(|format| profile_formats.contains(format))
+        .or_else(|| accepted.first())
+        .copied()
+}
+
+/// Choose the first format in the fallback chain that the client accepts. Clients that accept
+/// none of them get the last one, which is usually a format that everything can display.
+fn negotiate_fallback(
+    accept: &str,
+    source_format: ImageFormat,
+    fallbacks: &FormatFallbacks,
+) -> Option<ImageFormat> {
+    let accepted = accepted_formats(accept, source_format);
+    fallbacks
+        .formats()
+        .find(|format| accepted.contains(format))
+        .or_else(|| fallbacks.formats().last())
+}
+
+fn profile_formats(output: &ConversionOutput) -> Vec<ImageFormat> {
+    match output {
+        ConversionOutput::Cross { formats, .. } | ConversionOutput::Auto { formats, .. } => {
+            formats.iter().map(|f| f.as_db_image_format()).collect()
+        }
+        ConversionOutput::PerFormat { outputs, .. } => outputs
+            .iter()
+            .map(|o| o.format.as_db_image_format())
+            .collect(),
+    }
+}
+
+fn profile_widths(output: &ConversionOutput) -> Vec<u32> {
+    let sizes = match output {
+        ConversionOutput::Cross { sizes, .. } => sizes.iter().collect::<Vec<_>>(),
+        ConversionOutput::PerFormat { outputs, .. } => {
+            outputs.iter().flat_map(|o| o.sizes.iter()).collect()
+        }
+        ConversionOutput::Auto { .. } => Vec::new(),
+    };
+
+    sizes.into_iter().filter_map(|size| size.width).collect()
+}
+
+fn profile_qualities(output: &ConversionOutput) -> Vec<u8> {
+    let formats = match output {
+        ConversionOutput::Cross { formats, .. } | ConversionOutput::Auto { formats, .. } => {
+            formats.iter().collect::<Vec<_>>()
+        }
+        ConversionOutput::PerFormat { outputs, .. } => outputs.iter().map(|o| &o.format).collect(),
+    };
+
+    formats
+        .into_iter()
+        .filter_map(|format| format.quality())
+        .map(|quality| quality.round().clamp(1.0, 100.0) as u8)
+        .collect()
+}
+
+fn load_source(conn: &mut PgConnection, image_id: BaseImageId) -> Result<ServeSource> {
+    let (
+        (team_id, project_id, location, format, width, height, output_key_prefix, original_removed),
+        (base_profile_id, base_profile_version),
+        (
+            base_storage_id,
+            base_storage_path,
+            output_storage_id,
+            output_storage_path,
+            require_signed_urls,
+            output_mirror_ids,
+        ),
+        (
+            allowed_countries,
+            blocked_countries,
+            cache_control,
+            save_data_enabled,
+            save_data_quality,
+            format_fallbacks,
+        ),
+        (profile_id, profile_version, output, key_template),
+        (project_base_location, private, allowed_referers, block_empty_referer),
+        team_status,
+    ) = db::base_images::table
+        .inner_join(db::upload_profiles::table.inner_join(conversion_profiles::table))
+        .inner_join(db::projects::table.on(db::projects::id.eq(db::base_images::project_id)))
+        .inner_join(db::teams::table.on(db::teams::id.eq(db::base_images::team_id)))
+        .filter(db::base_images::id.eq(image_id))
+        .filter(db::base_images::deleted.is_null())
+        .filter(db::base_images::status.eq(BaseImageStatus::Ready))
+        .select((
+            (
+                db::base_images::team_id,
+                db::base_images::project_id,
+                db::base_images::location,
+                db::base_images::format,
+                db::base_images::width,
+                db::base_images::height,
+                db::base_images::output_key_prefix,
+                db::base_images::original_removed,
+            ),
+            (
+                db::base_images::conversion_profile_id,
+                db::base_images::conversion_profile_version,
+            ),
+            (
+                db::upload_profiles::base_storage_location_id,
+                db::upload_profiles::base_storage_location_path,
+                db::upload_profiles::output_storage_location_id,
+                db::upload_profiles::output_storage_location_path,
+                db::upload_profiles::require_signed_urls,
+                db::upload_profiles::output_mirror_location_ids,
+            ),
+            (
+                db::upload_profiles::allowed_countries,
+                db::upload_profiles::blocked_countries,
+                db::upload_profiles::cache_control,
+                db::upload_profiles::save_data_enabled,
+                db::upload_profiles::save_data_quality,
+                db::upload_profiles::format_fallbacks,
+            ),
+            (
+                conversion_profiles::id,
+                conversion_profiles::version,
+                conversion_profiles::output,
+                conversion_profiles::output_key_template,
+            ),
+            (
+                db::projects::base_location,
+                db::projects::private,
+                db::projects::allowed_referers,
+                db::projects::block_empty_referer,
+            ),
+            db::teams::status,
+        ))
+        .first::<(
+            (
+                TeamId,
+                ProjectId,
+                String,
+                Option<ImageFormat>,
+                i32,
+                i32,
+                Option<String>,
+                Option<DateTime<Utc>>,
+            ),
+            (Option<ConversionProfileId>, Option<i32>),
+            (
+                StorageLocationId,
+                Option<String>,
+                StorageLocationId,
+                Option<String>,
+                bool,
+                Vec<StorageLocationId>,
+            ),
+            (
+                Option<Vec<String>>,
+                Option<Vec<String>>,
+                Option<String>,
+                bool,
+                Option<i32>,
+                Option<FormatFallbacks>,
+            ),
+            (ConversionProfileId, i32, ConversionOutput, Option<String>),
+            (String, bool, Option<Vec<String>>, bool),
+            TeamStatus,
+        )>(conn)
+        .optional()?
+        .ok_or(Error::NotFound)?;
+
+    if team_status == TeamStatus::Suspended {
+        return Err(Error::TeamSuspended);
+    }
+
+    let format = format.ok_or(Error::NotFound)?;
+    let profile_formats = profile_formats(&output);
+    let profile_widths = profile_widths(&output);
+    let profile_qualities = profile_qualities(&output);
+    let operations = preset_operations(conn, team_id, Some(project_id), &output)?;
+
+    let base_storage = storage_locations::table
+        .filter(storage_locations::id.eq(base_storage_id))
+        .first::<StorageLocation>(conn)?;
+    let output_storage = storage_locations::table
+        .filter(storage_locations::id.eq(output_storage_id))
+        .first::<StorageLocation>(conn)?;
+    let output_mirrors = mirrors::load_locations(conn, team_id, &output_mirror_ids)?;
+    let output_profile_path = output_storage_path.clone();
+
+    let base_storage_path = image_base_location(
+        &base_storage.base_location,
+        &project_base_location,
+        &base_storage_path,
+    )
+    .into_owned();
+    let output_redirect_base =
+        (output_storage.serve_mode == StorageServeMode::Redirect).then(|| {
+            image_base_location(
+                &output_storage.public_url_base,
+                &project_base_location,
+                &output_storage_path,
+            )
+            .into_owned()
+        });
+    let output_storage_path = image_base_location(
+        &output_storage.base_location,
+        &project_base_location,
+        &output_storage_path,
+    )
+    .into_owned();
+
+    Ok(ServeSource {
+        team_id,
+        project_id,
+        location,
+        format,
+        width: width as u32,
+        height: height as u32,
+        key_template,
+        output_key_prefix,
+        original_available: original_removed.is_none(),
+        profile_formats,
+        profile_widths,
+        profile_qualities,
+        profile_version: (profile_id, profile_version),
+        base_profile_version: base_profile_id.zip(base_profile_version),
+        operations,
+        base_storage,
+        base_storage_path,
+        output_storage,
+        output_storage_path,
+        output_mirrors,
+        project_base_location,
+        output_profile_path,
+        output_redirect_base,
+        require_signed_urls,
+        private,
+        geo: GeoRestriction {
+            allowed_countries,
+            blocked_countries,
+        },
+        referers: RefererRestriction {
+            allowed_referers,
+            block_empty_referer,
+        },
+        cache_control,
+        save_data_quality: save_data_enabled.then(|| {
+            save_data_quality
+                .map(|q| q.clamp(1, 100) as u8)
+                .unwrap_or(SAVE_DATA_QUALITY)
+        }),
+        format_fallbacks,
+    })
+}
+
+/// The caching headers sent with an image.
+struct CacheHeaders {
+    cache_control: String,
+    vary: Vec<HeaderName>,
+    /// A hash of the image, without the quotes.
+    etag: Option<String>,
+    last_modified: Option<DateTime<Utc>>,
+    /// Tags the response so that a CDN can purge every variant of the image at once.
+    surrogate_key: Option<String>,
+    /// A `Link` header that preloads the chosen variant.
+    preload: Option<String>,
+}
+
+impl CacheHeaders {
+    fn apply(&self, headers: &mut HeaderMap) {
+        if let Ok(value) = HeaderValue::from_str(&self.cache_control) {
+            headers.insert(header::CACHE_CONTROL, value);
+        }
+
+        if let Some(key) = self.surrogate_key.as_deref() {
+            if let Ok(value) = HeaderValue::from_str(key) {
+                headers.insert(SURROGATE_KEY_HEADER, value);
+            }
+        }
+
+        if !self.vary.is_empty() {
+            let vary = self
+                .vary
+                .iter()
+                .map(|name| name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            if let Ok(value) = HeaderValue::from_str(&vary) {
+                headers.insert(header::VARY, value);
+            }
+        }
+
+        if let Some(etag) = self.etag.as_deref() {
+            if let Ok(value) = HeaderValue::from_str(&format!("\"{etag}\"")) {
+                headers.insert(header::ETAG, value);
+            }
+        }
+
+        if let Some(last_modified) = self.last_modified {
+            let value = last_modified.format(HTTP_DATE_FORMAT).to_string();
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(header::LAST_MODIFIED, value);
+            }
+        }
+
+        if let Some(preload) = self.preload.as_deref() {
+            if let Ok(value) = HeaderValue::from_str(preload) {
+                headers.insert(header::LINK, value);
+            }
+        }
+    }
+
+    /// Check the request's conditional headers to see if the client already has this image.
+    fn not_modified(&self, request: &HeaderMap) -> bool {
+        // If-Modified-Since is ignored when If-None-Match is present.
+        if let Some(if_none_match) = request.get(header::IF_NONE_MATCH) {
+            return match (self.etag.as_deref(), if_none_match.to_str()) {
+                (Some(etag), Ok(if_none_match)) => etag_matches(if_none_match, etag),
+                _ => false,
+            };
+        }
+
+        let if_modified_since = request
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok());
+        match (self.last_modified, if_modified_since) {
+            // HTTP dates only have one second of precision.
+            (Some(last_modified), Some(since)) => last_modified.timestamp() <= since.timestamp(),
+            _ => false,
+        }
+    }
+}
+
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Check an `If-None-Match` header against an ETag. This uses the weak comparison, as the
+/// spec requires for `If-None-Match`.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').any(|tag| {
+        let tag = tag.trim();
+        tag == "*" || tag.trim_start_matches("W/").trim_matches('"') == etag
+    })
+}
+
+fn image_response(format: ImageFormat, cache: &CacheHeaders, body: RangedBody) -> Response {
+    let mut response = body.into_response(content_type(format));
+    cache.apply(response.headers_mut());
+    response
+}
+
+/// Redirect to the image's public URL. The ETag and Last-Modified date describe the image, so
+/// only the caching headers for the redirect itself are sent.
+fn redirect_response(location: HeaderValue, cache: &CacheHeaders) -> Response {
+    let mut response = (StatusCode::FOUND, [(header::LOCATION, location)]).into_response();
+    let redirect_cache = CacheHeaders {
+        cache_control: cache.cache_control.clone(),
+        vary: cache.vary.clone(),
+        etag: None,
+        last_modified: None,
+        surrogate_key: cache.surrogate_key.clone(),
+        preload: cache.preload.clone(),
+    };
+    redirect_cache.apply(response.headers_mut());
+    response
+}
+
+fn not_modified_response(cache: &CacheHeaders) -> Response {
+    let mut response = StatusCode::NOT_MODIFIED.into_response();
+    cache.apply(response.headers_mut());
+    response
+}
+
+/// Build the output image for a variant, without saving it.
+fn variant_output_image(
+    source: &ServeSource,
+    image_id: BaseImageId,
+    format: ImageFormat,
+    width: u32,
+    quality: Option<u8>,
+) -> Result<NewOutputImage> {
+    let conversion_format = conversion_format(format, quality.map(f32::from))?;
+    let size = ConversionSize {
+        width: Some(width),
+        ..Default::default()
+    };
+
+    let mut output_image = build_output_images(
+        std::slice::from_ref(&conversion_format),
+        std::slice::from_ref(&size),
+        source.key_template.as_deref(),
+        &OutputImageBase {
+            team_id: source.team_id,
+            project_id: source.project_id,
+            id: image_id,
+            location: &source.location,
+            format: source.format,
+            key_prefix: source.output_key_prefix.as_deref(),
+        },
+    )?
+    .pop()
+    .ok_or(Error::NotFound)?;
+
+    // Profile outputs don't include the quality in their location, so keep variants with an
+    // explicit quality separate from them.
+    if let Some(quality) = quality {
+        output_image.location = match output_image.location.rsplit_once('.') {
+            Some((base, ext)) => format!("{base}-q{quality}.{ext}"),
+            None => format!("{}-q{quality}", output_image.location),
+        };
+    }
+
+    Ok(output_image)
+}
+
+/// The URL of a variant, with everything that the request left to the server filled in.
+fn variant_url(image_id: BaseImageId, width: u32, format: &ConversionFormat) -> String {
+    let mut url = format!(
+        "/serve/{image_id}?width={width}&format={}",
+        format.extension()
+    );
+    if let Some(quality) = format.quality() {
+        url.push_str(&format!("&quality={quality}"));
+    }
+    url
+}
+
+/// Build a `Link` header value that preloads an image.
+fn preload_link(url: &str, format: ImageFormat) -> String {
+    format!(
+        "<{url}>; rel=preload; as=image; type=\"{}\"",
+        content_type(format)
+    )
+}
+
+/// The width to convert the image to, which is never larger than the original image. A maximum
+/// height is converted to a width using the original image's aspect ratio.
+fn variant_width(query: &ServeQuery, source_width: u32, source_height: u32) -> u32 {
+    let width = query.width.unwrap_or(source_width).min(source_width);
+    match query.height {
+        Some(height) if source_height > 0 => {
+            let height_width = (height as u64 * source_width as u64 / source_height as u64).max(1);
+            width.min(height_width as u32)
+        }
+        _ => width,
+    }
+}
+
+/// Round a width for a request without a signature up to the closest width that the conversion
+/// profile lists or that is already stored, so that unsigned URLs can't create a variant for every
+/// possible width.
+async fn unsigned_variant_width(
+    state: &AppState,
+    source: &ServeSource,
+    image_id: BaseImageId,
+    width: u32,
+) -> Result<u32> {
+    if width == source.width || source.profile_widths.contains(&width) {
+        return Ok(width);
+    }
+
+    let stored = state
+        .db
+        .interact(move |conn| {
+            output_images::table
+                .filter(output_images::base_image_id.eq(image_id))
+                .filter(output_images::status.eq(OutputImageStatus::Ready))
+                .filter(output_images::width.is_not_null())
+                .select(output_images::width.assume_not_null())
+                .load::<i32>(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    let allowed = source
+        .profile_widths
+        .iter()
+        .copied()
+        .chain(stored.into_iter().map(|w| w as u32))
+        .filter(|w| *w <= source.width)
+        .chain([source.width])
+        .collect::<Vec<_>>();
+    Ok(client_hints::closest_stored_width(width, &allowed).unwrap_or(source.width))
+}
+
+/// Whether a valid signature covers the width that will be rendered. The signature is checked
+/// against the query, so a width from a client hint that differs from the signed width isn't
+/// covered.
+fn signature_covers_width(
+    verified: bool,
+    signed_width: Option<u32>,
+    hinted_width: Option<u32>,
+) -> bool {
+    verified
+        && hinted_width
+            .map(|width| Some(width) == signed_width)
+            .unwrap_or(true)
+}
+
+/// Serve a variant of an image, creating it first if it does not exist yet. `verified` is true
+/// when a valid signature covers the requested variant, which allows it to create any variant.
+pub(super) async fn serve_image(
+    State(state): State<AppState>,
+    Path(image_id): Path<BaseImageId>,
+    Query(mut query): Query<ServeQuery>,
+    signed: SignedUrl,
+    verified: bool,
+    AccessToken(access_token): AccessToken,
+    headers: HeaderMap,
+) -> Result<Response> {
+    if query.width == Some(0) {
+        return Err(Error::InvalidTransformation("width must be greater than 0"));
+    }
+    if query.height == Some(0) {
+        return Err(Error::InvalidTransformation(
+            "height must be greater than 0",
+        ));
+    }
+
+    let quality = match query.quality {
+        Some(q) if !(1..=100).contains(&q) => {
+            return Err(Error::InvalidTransformation(
+                "quality must be between 1 and 100",
+            ))
+        }
+        q => q,
+    };
+
+    let host = headers
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .and_then(delivery_domains::normalize_hostname);
+
+    let source = state
+        .db
+        .interact(move |conn| {
+            let source = load_source(conn, image_id)?;
+
+            // A project's delivery domains only serve its own images, and respond as if images
+            // from other projects don't exist.
+
+            if let Some(host) = host {
+                let domain_project = delivery_domains::project_for_host(conn, &host)?;
+                if domain_project
+                    .map(|p| p != source.project_id)
+                    .unwrap_or(false)
+                {
+                    return Err(Error::NotFound);
+                }
+            }
+
+            Ok(source)
+        })
+        .await?;
+
+    // The country comes from a header set by the CDN. Responses vary on it so that the CDN
+    // doesn't serve a cached image to a blocked country.
+    let mut vary = Vec::new();
+    if source.geo.is_restricted() {
+        let country = headers
+            .get(&state.geo_country_header)
+            .and_then(|value| value.to_str().ok());
+        if !source.geo.allows(country) {
+            return Err(Error::GeoRestricted);
+        }
+
+        vary.push(state.geo_country_header.clone());
+    }
+
+    // Other sites get a 403, and responses vary on the headers for the same reason.
+    if source.referers.is_restricted() {
+        if !source.referers.allows(&headers) {
+            return Err(Error::HotlinkForbidden);
+        }
+
+        vary.push(header::REFERER);
+        vary.push(header::ORIGIN);
+    }
+
+    // A gallery cookie for the image's project can stand in for a signed URL or an access token.
+    let gallery_expires =
+        if signed.expires.is_none() && (source.private || source.require_signed_urls) {
+            vary.push(header::COOKIE);
+            state.url_signer.as_ref().and_then(|signer| {
+                gallery::verify_cookie(&headers, signer, source.project_id, Utc::now())
+            })
+        } else {
+            None
+        };
+
+    if source.require_signed_urls && signed.expires.is_none() && gallery_expires.is_none() {
+        return Err(Error::InvalidSignedUrl("signature required"));
+    }
+
+    // Responses for signed URLs aren't cached past the URL's expiration. Responses that depend on
+    // a cookie or access token are private so that shared caches don't store them.
+    let cache_control = match (signed.expires, gallery_expires) {
+        (Some(expires), _) => {
+            let max_age = (expires - Utc::now()).num_seconds().clamp(0, CACHE_MAX_AGE);
+            format!("public, max-age={max_age}")
+        }
+        (None, Some(expires)) => {
+            let max_age = (expires - Utc::now()).num_seconds().clamp(0, CACHE_MAX_AGE);
+            format!("private, max-age={max_age}")
+        }
+        (None, None) if source.private => {
+            let (token_id, hash) = access_token.ok_or(Error::InvalidAccessToken)?;
+            let project_id = source.project_id;
+            let valid = state
+                .db
+                .interact(move |conn| {
+                    project_access_tokens::is_valid(conn, token_id, hash.as_bytes(), project_id)
+                        .map_err(Error::from)
+                })
+                .await?;
+            if !valid {
+                return Err(Error::InvalidAccessToken);
+            }
+
+            "private, no-store".to_string()
+        }
+        (None, None) => source
+            .cache_control
+            .clone()
+            .unwrap_or_else(|| format!("public, max-age={CACHE_MAX_AGE}")),
+    };
+
+    // Without a format in the query, the upload profile's fallback chain or the conversion
+    // profile's formats decide which of the formats in the `Accept` header is used.
+    if query.format.is_none() {
+        vary.push(header::ACCEPT);
+    }
+    let output_format = query
+        .format
+        .or_else(|| {
+            let accept = headers
+                .get(header::ACCEPT)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            match &source.format_fallbacks {
+                Some(fallbacks) => negotiate_fallback(accept, source.format, fallbacks),
+                None => negotiate_format(accept, source.format, &source.profile_formats),
+            }
+        })
+        .unwrap_or(match source.format {
+            ImageFormat::Heic => ImageFormat::Jpg,
+            format => format,
+        });
+
+    // Clients that send `Save-Data: on` or an `ECT` of 3G or slower get a lower quality when the
+    // query has none, unless the upload profile turns this off.
+    let hints = ClientHints::from_headers(&headers);
+    let quality = match (quality, source.save_data_quality) {
+        (Some(q), _) => Some(q),
+        (None, Some(save_data_quality)) => {
+            vary.push(client_hints::SAVE_DATA);
+            vary.push(client_hints::ECT);
+            hints.wants_less_data().then_some(save_data_quality)
+        }
+        (None, None) => None,
+    };
+
+    // The `Sec-CH-Width` or `Width` hint is used when the query has no width, and a width in the
+    // query is scaled by `Sec-CH-DPR`. Hinted widths are rounded to a stored output when possible,
+    // so that hints don't create a variant for every possible size.
+    vary.extend(ClientHints::width_vary(query.width));
+    let hinted_width = hints.requested_width(query.width);
+    let width_verified = signature_covers_width(verified, query.width, hinted_width);
+    if hinted_width.is_some() {
+        query.width = hinted_width;
+    }
+    let mut width = variant_width(&query, source.width, source.height);
+
+    if hinted_width.is_some() {
+        // Stored outputs are only reused when the request has no quality.
+        let stored = if quality.is_some() {
+            Vec::new()
+        } else {
+            state
+                .db
+                .interact(move |conn| {
+                    output_images::table
+                        .filter(output_images::base_image_id.eq(image_id))
+                        .filter(output_images::status.eq(OutputImageStatus::Ready))
+                        .filter(output_images::width.is_not_null())
+                        .select((
+                            output_images::width.assume_not_null(),
+                            output_images::location,
+                        ))
+                        .load::<(i32, String)>(conn)
+                        .map_err(Error::from)
+                })
+                .await?
+        };
+
+        // Only consider outputs that this route would read for the same format and width, and
+        // that fit within the requested height.
+        let max_width = variant_width(
+            &ServeQuery {
+                height: query.height,
+                ..Default::default()
+            },
+            source.width,
+            source.height,
+        );
+        let stored_widths = stored
+            .into_iter()
+            .filter(|(stored_width, location)| {
+                variant_output_image(&source, image_id, output_format, *stored_width as u32, None)
+                    .map(|output| output.location == *location)
+                    .unwrap_or(false)
+            })
+            .map(|(stored_width, _)| stored_width as u32)
+            .filter(|stored_width| *stored_width <= max_width)
+            .collect::<Vec<_>>();
+        width = client_hints::closest_stored_width(width, &stored_widths).unwrap_or_else(|| {
+            let rounded = ServeQuery {
+                width: Some(client_hints::round_width(width)),
+                height: query.height,
+                ..Default::default()
+            };
+            variant_width(&rounded, source.width, source.height)
+        });
+    }
+
+    // Unsigned requests can only create the variants that the conversion profile would.
+    if !verified {
+        let format_allowed = query
+            .format
+            .map(|format| {
+                format == source.format
+                    || source.profile_formats.contains(&format)
+                    || source
+                        .format_fallbacks
+                        .as_ref()
+                        .map(|fallbacks| fallbacks.formats().any(|f| f == format))
+                        .unwrap_or(false)
+            })
+            .unwrap_or(true);
+        if !format_allowed {
+            return Err(Error::InvalidSignedUrl(
+                "a signature is required for formats that the profile doesn't produce",
+            ));
+        }
+
+        if let Some(q) = query.quality {
+            if !source.profile_qualities.contains(&q) && source.save_data_quality != Some(q) {
+                return Err(Error::InvalidSignedUrl(
+                    "a signature is required for qualities that the profile doesn't use",
+                ));
+            }
+        }
+    }
+
+    // A hinted width that the signature doesn't cover gets the same limits as an unsigned one.
+    if !width_verified {
+        width = unsigned_variant_width(&state, &source, image_id, width).await?;
+    }
+
+    let output_image = variant_output_image(&source, image_id, output_format, width, quality)?;
+
+    let output_provider = storage::Provider::from_db(source.output_storage.provider.clone())?;
+    let output_operator = output_provider
+        .create_operator(&source.output_storage_path)
+        .await?;
+
+    // Output storage in redirect mode sends the client to the image's public URL, unless the image
+    // has access restrictions that the public URL would bypass.
+    let restricted = signed.expires.is_some()
+        || gallery_expires.is_some()
+        || source.private
+        || source.require_signed_urls
+        || source.geo.is_restricted()
+        || source.referers.is_restricted();
+    let redirect_url = source
+        .output_redirect_base
+        .as_deref()
+        .filter(|_| !restricted)
+        .and_then(|base| HeaderValue::from_str(&format!("{base}/{}", output_image.location)).ok());
+
+    // CDNs that support Early Hints send this header as a 103 response on later requests.
+    // Restricted images don't get it, since the preload URL wouldn't carry the signature or token.
+    let preload = if state.early_hints && !restricted {
+        let url = match redirect_url.as_ref().and_then(|url| url.to_str().ok()) {
+            Some(url) => url.to_string(),
+            None => variant_url(image_id, width, &output_image.format),
+        };
+        Some(preload_link(&url, output_format))
+    } else {
+        None
+    };
+
+    let location = output_image.location.clone();
+    let existing = state
+        .db
+        .interact(move |conn| {
+            output_images::table
+                .filter(output_images::base_image_id.eq(image_id))
+                .filter(output_images::location.eq(location))
+                .filter(output_images::status.eq_any([
+                    OutputImageStatus::Ready,
+                    OutputImageStatus::Queued,
+                    OutputImageStatus::Converting,
+                ]))
+                .select((
+                    output_images::id,
+                    output_images::status,
+                    output_images::etag,
+                    output_images::updated,
+                    output_images::file_size,
+                    output_images::conversion_profile_id,
+                    output_images::conversion_profile_version,
+                ))
+                .first::<(
+                    OutputImageId,
+                    OutputImageStatus,
+                    Option<String>,
+                    DateTime<Utc>,
+                    i32,
+                    Option<ConversionProfileId>,
+                    Option<i32>,
+                )>(conn)
+                .optional()
+                .map_err(Error::from)
+        })
+        .await?;
+
+    let mut cache = CacheHeaders {
+        cache_control,
+        vary,
+        etag: None,
+        last_modified: None,
+        surrogate_key: Some(image_id.to_string()),
+        preload,
+    };
+
+    // Serve the closest stored output instead, if there is one.
+    let mut use_stand_in = false;
+    match existing {
+        Some((
+            output_image_id,
+            OutputImageStatus::Ready,
+            etag,
+            updated,
+            file_size,
+            profile_id,
+            profile_version,
+        )) => {
+            let response = if let Some(url) = redirect_url.clone() {
+                Some(redirect_response(url, &cache))
+            } else {
+                cache.etag = etag;
+                cache.last_modified = Some(updated);
+                if cache.not_modified(&headers) {
+                    Some(not_modified_response(&cache))
+                } else {
+                    let range = range::requested_range(
+                        &headers,
+                        cache.etag.as_deref(),
+                        cache.last_modified,
+                    );
+                    let mut missing = false;
+                    let body = match RangedBody::from_storage(
+                        &output_operator,
+                        &output_image.location,
+                        range,
+                    )
+                    .await
+                    {
+                        Ok(body) => Some(body),
+                        Err(e) => {
+                            missing = e.is_not_found();
+                            event!(Level::WARN, error = %e, location = %output_image.location, "Failed to read output image");
+                            read_from_mirror(
+                                &state,
+                                &source,
+                                output_image_id,
+                                &output_image.location,
+                                range,
+                            )
+                            .await?
+                        }
+                    };
+
+                    match body {
+                        Some(body) => {
+                            let served = body.content_length().unwrap_or(file_size as usize);
+                            state
+                                .metering
+                                .record_served(source.team_id, source.project_id, served);
+                            Some(image_response(output_format, &cache, body))
+                        }
+                        None if missing && source.original_available => {
+                            event!(Level::WARN, location = %output_image.location, "Output image is missing from storage, queueing regeneration");
+                            queue_regeneration(
+                                &state,
+                                &source,
+                                image_id,
+                                output_image_id,
+                                &output_image,
+                                true,
+                            )
+                            .await?;
+                            use_stand_in = true;
+                            None
+                        }
+                        None if missing => {
+                            use_stand_in = true;
+                            None
+                        }
+                        None => {
+                            event!(Level::WARN, location = %output_image.location, "No copy of the output image could be read, recreating it");
+                            None
+                        }
+                    }
+                }
+            };
+
+            if let Some(response) = response {
+                // Without the original, the stale variant is the best that can be served.
+                if source.original_available
+                    && variant_is_stale(
+                        source.profile_version,
+                        source.base_profile_version,
+                        profile_id.zip(profile_version),
+                    )
+                {
+                    let queued = queue_regeneration(
+                        &state,
+                        &source,
+                        image_id,
+                        output_image_id,
+                        &output_image,
+                        false,
+                    )
+                    .await;
+                    if let Err(e) = queued {
+                        event!(Level::ERROR, error = %e, location = %output_image.location, "Failed to queue regeneration of stale output image");
+                    }
+                }
+                return Ok(response);
+            }
+        }
+        Some((_, _, _, updated, ..)) => {
+            // A conversion job is already making this output.
+            use_stand_in =
+                Utc::now() - updated < chrono::Duration::minutes(REGENERATION_TIMEOUT_MINUTES);
+        }
+        None => {}
+    }
+
+    if use_stand_in {
+        let stand_in = serve_stand_in(
+            &state,
+            &source,
+            image_id,
+            &output_image,
+            &output_operator,
+            &cache,
+            &headers,
+        )
+        .await?;
+        if let Some(response) = stand_in {
+            return Ok(response);
+        }
+    }
+
+    if !source.original_available {
+        return Err(Error::OriginalUnavailable);
+    }
+
+    let (image, etag, updated) =
+        render_variant(&state, &source, &output_operator, output_image, width).await?;
+    cache.etag = Some(etag);
+    cache.last_modified = Some(updated);
+
+    if let Some(url) = redirect_url {
+        return Ok(redirect_response(url, &cache));
+    }
+
+    let range = range::requested_range(&headers, cache.etag.as_deref(), cache.last_modified);
+    let body = RangedBody::from_bytes(image, range);
+    state.metering.record_served(
+        source.team_id,
+        source.project_id,
+        body.content_length().unwrap_or_default(),
+    );
+    Ok(image_response(output_format, &cache, body))
+}
+
+/// Render a variant from the original and save it, returning the image along with its ETag and
+/// update time.
+async fn render_variant(
+    state: &AppState,
+    source: &ServeSource,
+    output_operator: &storage::Operator,
+    mut output_image: NewOutputImage,
+    width: u32,
+) -> Result<(Bytes, String, DateTime<Utc>)> {
+    // Held until the conversion is done, since decoding and encoding are the expensive parts.
+    let permit = state
+        .render_permits
+        .acquire()
+        .await
+        .map_err(eyre::Report::new)?;
+    let base_provider = storage::Provider::from_db(source.base_storage.provider.clone())?;
+    let base_operator = base_provider
+        .create_operator(&source.base_storage_path)
+        .await?;
+    let base_bytes = base_operator
+        .get(&source.location)
+        .await?
+        .bytes()
+        .await
+        .map_err(storage::Error::from)?;
+
+    let limits = state.decode_limits.clone();
+    let operations = source.operations.clone();
+    let transform = convert::ImageSizeTransform {
+        width: Some(width),
+        height: None,
+        preserve_aspect_ratio: true,
+    };
+    let encode_format = image::ImageFormat::from(&output_image.format);
+    let encode_quality = output_image.format.quality();
+    let encode_effort = source
+        .format_fallbacks
+        .as_ref()
+        .and_then(|fallbacks| fallbacks.max_effort(output_image.format.as_db_image_format()));
+    let result = tokio::task::spawn_blocking(move || {
+        let image = convert::image_from_bytes(&base_bytes, &limits)?;
+        convert::convert_with_effort(
+            &image,
+            encode_format,
+            encode_quality,
+            encode_effort,
+            &transform,
+            &operations,
+        )
+        .map_err(convert::Error::from)
+    })
+    .await
+    .map_err(eyre::Report::new)??;
+    drop(permit);
+
+    let image = Bytes::from(result.image);
+    let sha256 =
+        checksum::put_verified(output_operator, &output_image.location, image.clone()).await?;
+
+    output_image.status = OutputImageStatus::Ready;
+    output_image.width = Some(result.width as i32);
+    output_image.height = Some(result.height as i32);
+    let file_size = image.len() as i32;
+    let etag = blake3::hash(&image).to_hex().to_string();
+    let (profile_id, profile_version) = source.profile_version;
+    let saved_etag = etag.clone();
+    let updated = state
+        .db
+        .interact(move |conn| {
+            diesel::insert_into(output_images::table)
+                .values((
+                    &output_image,
+                    output_images::file_size.eq(file_size),
+                    output_images::etag.eq(saved_etag),
+                    output_images::sha256.eq(sha256),
+                    output_images::conversion_profile_id.eq(profile_id),
+                    output_images::conversion_profile_version.eq(profile_version),
+                ))
+                .on_conflict((output_images::base_image_id, output_images::location))
+                .do_update()
+                .set((
+                    output_images::status.eq(OutputImageStatus::Ready),
+                    output_images::width.eq(excluded(output_images::width)),
+                    output_images::height.eq(excluded(output_images::height)),
+                    output_images::file_size.eq(excluded(output_images::file_size)),
+                    output_images::size.eq(excluded(output_images::size)),
+                    output_images::format.eq(excluded(output_images::format)),
+                    output_images::etag.eq(excluded(output_images::etag)),
+                    output_images::sha256.eq(excluded(output_images::sha256)),
+                    output_images::conversion_profile_id
+                        .eq(excluded(output_images::conversion_profile_id)),
+                    output_images::conversion_profile_version
+                        .eq(excluded(output_images::conversion_profile_version)),
+                    output_images::updated.eq(diesel::dsl::now),
+                ))
+                .returning(output_images::updated)
+                .get_result::<DateTime<Utc>>(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((image, etag, updated))
+}
+
+/// Queue a conversion job to render an output image again, unless another request already did.
+/// The output image is updated to the current conversion profile first, since the job renders it
+/// with the format and size that it has.
+///
+/// A missing output is marked as queued, so that requests serve a stand-in until the job
+/// finishes. A stale output stays ready and is served as it is until the job replaces it, and
+/// the request that moves it to the current profile version is the one that queues the job.
+async fn queue_regeneration(
+    state: &AppState,
+    source: &ServeSource,
+    image_id: BaseImageId,
+    output_image_id: OutputImageId,
+    output_image: &NewOutputImage,
+    missing: bool,
+) -> Result<()> {
+    let (profile_id, profile_version) = source.profile_version;
+    let format = output_image.format.clone();
+    let size = output_image.size.clone();
+    let claimed = state
+        .db
+        .interact(move |conn| {
+            let query = diesel::update(output_images::table)
+                .filter(output_images::id.eq(output_image_id))
+                .filter(output_images::status.eq(OutputImageStatus::Ready));
+            let values = (
+                output_images::format.eq(format),
+                output_images::size.eq(size),
+                output_images::conversion_profile_id.eq(profile_id),
+                output_images::conversion_profile_version.eq(profile_version),
+            );
+
+            if missing {
+                query
+                    .set((
+                        values,
+                        output_images::status.eq(OutputImageStatus::Queued),
+                        output_images::updated.eq(diesel::dsl::now),
+                    ))
+                    .execute(conn)
+                    .map_err(Error::from)
+            } else {
+                query
+                    .filter(
+                        output_images::conversion_profile_id
+                            .is_distinct_from(profile_id)
+                            .or(output_images::conversion_profile_version
+                                .is_distinct_from(profile_version)),
+                    )
+                    .set(values)
+                    .execute(conn)
+                    .map_err(Error::from)
+            }
+        })
+        .await?;
+
+    if claimed > 0 {
+        conversion_pause::queue_conversion(
+            state,
+            source.team_id,
+            CreateOutputImagesJobPayload {
+                base_image: image_id,
+                conversions: vec![output_image_id],
+                choose_breakpoints: false,
+            },
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Order stand-ins for a requested width. Outputs at least as wide come first, since they can be
+/// scaled down without looking worse, and then the closest widths.
+fn stand_in_rank(requested_width: u32, width: u32) -> (bool, u32) {
+    (width < requested_width, width.abs_diff(requested_width))
+}
+
+/// Serve the stored output of the same format with the closest width in place of a missing one.
+/// The response can only be cached briefly, so that the regenerated output replaces it soon.
+async fn serve_stand_in(
+    state: &AppState,
+    source: &ServeSource,
+    image_id: BaseImageId,
+    output_image: &NewOutputImage,
+    operator: &storage::Operator,
+    cache: &CacheHeaders,
+    headers: &HeaderMap,
+) -> Result<Option<Response>> {
+    let missing_location = output_image.location.clone();
+    let stored = state
+        .db
+        .interact(move |conn| {
+            output_images::table
+                .filter(output_images::base_image_id.eq(image_id))
+                .filter(output_images::status.eq(OutputImageStatus::Ready))
+                .filter(output_images::archival.eq(false))
+                .filter(output_images::location.ne(missing_location))
+                .filter(output_images::width.is_not_null())
+                .select((
+                    output_images::location,
+                    output_images::format,
+                    output_images::width.assume_not_null(),
+                    output_images::etag,
+                    output_images::updated,
+                ))
+                .load::<(String, ConversionFormat, i32, Option<String>, DateTime<Utc>)>(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    let format = output_image.format.as_db_image_format();
+    let requested_width = output_image.size.width.unwrap_or(source.width);
+    let mut candidates = stored
+        .into_iter()
+        .filter(|(_, stored_format, ..)| stored_format.as_db_image_format() == format)
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|(_, _, width, ..)| stand_in_rank(requested_width, *width as u32));
+
+    // Stand-ins are private if the image is, and otherwise shared caches can keep them briefly.
+    let cache_control = if cache.cache_control.starts_with("public") {
+        format!("public, max-age={STAND_IN_MAX_AGE}")
+    } else {
+        cache.cache_control.clone()
+    };
+
+    for (location, _, _, etag, updated) in candidates.into_iter().take(MAX_STAND_IN_READS) {
+        let stand_in_cache = CacheHeaders {
+            cache_control: cache_control.clone(),
+            vary: cache.vary.clone(),
+            etag,
+            last_modified: Some(updated),
+            surrogate_key: cache.surrogate_key.clone(),
+            preload: None,
+        };
+        if stand_in_cache.not_modified(headers) {
+            return Ok(Some(not_modified_response(&stand_in_cache)));
+        }
+
+        match RangedBody::from_storage(operator, &location, None).await {
+            Ok(body) => {
+                event!(Level::INFO, %location, "Serving a stand-in for a missing output image");
+                state.metering.record_served(
+                    source.team_id,
+                    source.project_id,
+                    body.content_length().unwrap_or_default(),
+                );
+                return Ok(Some(image_response(format, &stand_in_cache, body)));
+            }
+            Err(e) => {
+                event!(Level::WARN, error = %e, %location, "Failed to read stand-in output image");
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Read an output image from the first of the profile's mirrors that has a good copy of it.
+async fn read_from_mirror(
+    state: &AppState,
+    source: &ServeSource,
+    output_image_id: OutputImageId,
+    location: &str,
+    range: Option<&str>,
+) -> Result<Option<RangedBody>> {
+    if source.output_mirrors.is_empty() {
+        return Ok(None);
+    }
+
+    let ready = state
+        .db
+        .interact(move |conn| {
+            output_image_mirrors::ready_locations(conn, output_image_id).map_err(Error::from)
+        })
+        .await?;
+    let locations = source
+        .output_mirrors
+        .iter()
+        .filter(|mirror| ready.contains(&mirror.id))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mirrors = mirrors::open(
+        locations,
+        &source.project_base_location,
+        &source.output_profile_path,
+    )
+    .await;
+    for mirror in mirrors {
+        let Ok(operator) = &mirror.operator else {
+            continue;
+        };
+
+        match RangedBody::from_storage(operator, location, range).await {
+            Ok(body) => {
+                event!(Level::INFO, storage_location_id = %mirror.storage_location_id, %location, "Serving output image from mirror");
+                return Ok(Some(body));
+            }
+            Err(e) => {
+                event!(Level::WARN, error = %e, storage_location_id = %mirror.storage_location_id, %location, "Failed to read output image from mirror");
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// The handler for the serve route, which also accepts imgix parameters when they are enabled.
+async fn serve_route(
+    State(state): State<AppState>,
+    Path(image_id): Path<BaseImageId>,
+    Query(mut query): Query<ServeQuery>,
+    imgix: std::result::Result<Query<ImgixQuery>, QueryRejection>,
+    signed: SignedUrl,
+    access_token: AccessToken,
+    headers: HeaderMap,
+) -> Result<Response> {
+    // The imgix parameters are only checked when they are enabled.
+    if state.imgix_compat {
+        let Query(imgix) =
+            imgix.map_err(|_| Error::InvalidTransformation("invalid imgix parameters"))?;
+        imgix.apply(&mut query)?;
+    }
+
+    // The signature only covers the serve parameters, so imgix parameters that change them get
+    // the same limits as an unsigned URL.
+    let verified = signed.expires.is_some() && signed.params == query.signed_params();
+    serve_image(
+        State(state),
+        Path(image_id),
+        Query(query),
+        signed,
+        verified,
+        access_token,
+        headers,
+    )
+    .await
+}
+
+pub fn configure() -> Router<AppState> {
+    Router::new().route("/serve/:image_id", get(serve_route))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BROWSER_ACCEPT: &str = "image/avif,image/webp,image/apng,image/svg+xml,image/*,*/*;q=0.8";
+
+    #[test]
+    fn prefers_modern_formats() {
+        assert_eq!(
+            negotiate_format(BROWSER_ACCEPT, ImageFormat::Jpg, &[]),
+            Some(ImageFormat::Avif)
+        );
+        assert_eq!(
+            negotiate_format("image/webp,*/*", ImageFormat::Jpg, &[]),
+            Some(ImageFormat::Webp)
+        );
+    }
+
+    #[test]
+    fn wildcard_only_allows_common_formats() {
+        assert_eq!(
+            negotiate_format("*/*", ImageFormat::Jpg, &[]),
+            Some(ImageFormat::Jpg)
+        );
+        assert_eq!(
+            negotiate_format("", ImageFormat::Jpg, &[]),
+            Some(ImageFormat::Jpg)
+        );
+        assert_eq!(
+            negotiate_format("image/*", ImageFormat::Png, &[]),
+            Some(ImageFormat::Png)
+        );
+    }
+
+    #[test]
+    fn rejected_formats() {
+        assert_eq!(
+            negotiate_format("image/avif;q=0,image/webp", ImageFormat::Jpg, &[]),
+            Some(ImageFormat::Webp)
+        );
+        assert_eq!(negotiate_format("text/html", ImageFormat::Jpg, &[]), None);
+    }
+
+    #[test]
+    fn prefers_profile_formats() {
+        assert_eq!(
+            negotiate_format(
+                BROWSER_ACCEPT,
+                ImageFormat::Jpg,
+                &[ImageFormat::Webp, ImageFormat::Jpg]
+            ),
+            Some(ImageFormat::Webp)
+        );
+        assert_eq!(
+            negotiate_format("image/webp,*/*", ImageFormat::Jpg, &[ImageFormat::Jpg]),
+            Some(ImageFormat::Jpg)
+        );
+        // Falls back to the client's preference if the profile has nothing it accepts.
+        assert_eq!(
+            negotiate_format("image/webp", ImageFormat::Jpg, &[ImageFormat::Jpg]),
+            Some(ImageFormat::Webp)
+        );
+    }
+
+    #[test]
+    fn stale_variants() {
+        let profile = ConversionProfileId::new();
+        let other_profile = ConversionProfileId::new();
+        let current = (profile, 3);
+
+        assert!(!variant_is_stale(
+            current,
+            Some((profile, 1)),
+            Some((profile, 3))
+        ));
+        assert!(variant_is_stale(
+            current,
+            Some((profile, 3)),
+            Some((profile, 2))
+        ));
+        assert!(variant_is_stale(current, None, Some((other_profile, 3))));
+        // Variants without a version fall back to the base image's version.
+        assert!(variant_is_stale(current, Some((profile, 2)), None));
+        assert!(!variant_is_stale(current, Some((profile, 3)), None));
+        assert!(!variant_is_stale(current, None, None));
+    }
+
+    #[test]
+    fn fallback_chain() {
+        let chain = FormatFallbacks(
+            [ImageFormat::Avif, ImageFormat::Webp, ImageFormat::Jpg]
+                .into_iter()
+                .map(|format| db::upload_profiles::FormatFallback {
+                    format,
+                    max_effort: None,
+                })
+                .collect(),
+        );
+
+        assert_eq!(
+            negotiate_fallback(BROWSER_ACCEPT, ImageFormat::Jpg, &chain),
+            Some(ImageFormat::Avif)
+        );
+        assert_eq!(
+            negotiate_fallback("image/webp,*/*", ImageFormat::Jpg, &chain),
+            Some(ImageFormat::Webp)
+        );
+        // Clients that accept nothing in the chain get the last format.
+        assert_eq!(
+            negotiate_fallback("image/heic", ImageFormat::Jpg, &chain),
+            Some(ImageFormat::Jpg)
+        );
+    }
+
+    #[test]
+    fn profile_variants() {
+        let output: ConversionOutput = serde_json::from_value(serde_json::json!({
+            "type": "per_format",
+            "outputs": [
+                {
+                    "format": "avif",
+                    "quality": 49.6,
+                    "sizes": [{ "width": 800 }, { "height": 300 }]
+                },
+                { "format": "png", "sizes": [{ "width": 400 }] }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(profile_widths(&output), vec![800, 400]);
+        assert_eq!(profile_qualities(&output), vec![50]);
+
+        let auto: ConversionOutput = serde_json::from_value(serde_json::json!({
+            "type": "auto",
+            "formats": [{ "format": "webp", "quality": 80.0 }],
+            "min_width": 200,
+            "max_width": 2000,
+            "byte_step": 20000
+        }))
+        .unwrap();
+        assert!(profile_widths(&auto).is_empty());
+        assert_eq!(profile_qualities(&auto), vec![80]);
+    }
+
+    fn cache_headers() -> CacheHeaders {
+        CacheHeaders {
+            cache_control: String::new(),
+            vary: Vec::new(),
+            etag: Some("abc".to_string()),
+            last_modified: Some(
+                DateTime::parse_from_rfc2822("Tue, 13 Oct 2026 08:00:00 GMT")
+                    .unwrap()
+                    .with_timezone(&Utc),
+            ),
+            surrogate_key: None,
+            preload: None,
+        }
+    }
+
+    fn request(headers: &[(HeaderName, &str)]) -> HeaderMap {
+        headers
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn if_none_match() {
+        let cache = cache_headers();
+        assert!(cache.not_modified(&request(&[(header::IF_NONE_MATCH, "\"abc\"")])));
+        assert!(cache.not_modified(&request(&[(header::IF_NONE_MATCH, "\"x\", W/\"abc\"")])));
+        assert!(cache.not_modified(&request(&[(header::IF_NONE_MATCH, "*")])));
+        assert!(!cache.not_modified(&request(&[(header::IF_NONE_MATCH, "\"def\"")])));
+        // If-None-Match takes precedence over If-Modified-Since.
+        assert!(!cache.not_modified(&request(&[
+            (header::IF_NONE_MATCH, "\"def\""),
+            (header::IF_MODIFIED_SINCE, "Wed, 14 Oct 2026 08:00:00 GMT"),
+        ])));
+    }
+
+    #[test]
+    fn if_modified_since() {
+        let cache = cache_headers();
+        assert!(cache.not_modified(&request(&[(
+            header::IF_MODIFIED_SINCE,
+            "Tue, 13 Oct 2026 08:00:00 GMT"
+        )])));
+        assert!(!cache.not_modified(&request(&[(
+            header::IF_MODIFIED_SINCE,
+            "Mon, 12 Oct 2026 08:00:00 GMT"
+        )])));
+        assert!(!cache.not_modified(&request(&[(header::IF_MODIFIED_SINCE, "yesterday")])));
+        assert!(!cache.not_modified(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn http_date_roundtrip() {
+        let cache = cache_headers();
+        let mut headers = HeaderMap::new();
+        cache.apply(&mut headers);
+        assert_eq!(
+            headers.get(header::LAST_MODIFIED).unwrap(),
+            "Tue, 13 Oct 2026 08:00:00 GMT"
+        );
+        assert_eq!(headers.get(header::ETAG).unwrap(), "\"abc\"");
+    }
+
+    #[test]
+    fn redirect_omits_image_headers() {
+        let mut cache = cache_headers();
+        cache.surrogate_key = Some("bimabc".to_string());
+        let response = redirect_response(
+            HeaderValue::from_static("https://cdn.example.com/image.webp"),
+            &cache,
+        );
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            response.headers().get(header::LOCATION).unwrap(),
+            "https://cdn.example.com/image.webp"
+        );
+        assert!(response.headers().get(header::CACHE_CONTROL).is_some());
+        assert!(response.headers().get(header::ETAG).is_none());
+        assert!(response.headers().get(header::LAST_MODIFIED).is_none());
+        assert_eq!(
+            response.headers().get(SURROGATE_KEY_HEADER).unwrap(),
+            "bimabc"
+        );
+    }
+
+    #[test]
+    fn preload_header() {
+        let image_id = BaseImageId::new();
+        let format = ConversionFormat::Webp {
+            quality: Some(80.0),
+            condition: None,
+        };
+        let url = variant_url(image_id, 400, &format);
+        assert_eq!(
+            url,
+            format!("/serve/{image_id}?width=400&format=webp&quality=80")
+        );
+
+        let mut cache = cache_headers();
+        cache.preload = Some(preload_link(&url, ImageFormat::Webp));
+        let mut headers = HeaderMap::new();
+        cache.apply(&mut headers);
+        assert_eq!(
+            headers.get(header::LINK).unwrap().to_str().unwrap(),
+            format!("<{url}>; rel=preload; as=image; type=\"image/webp\"")
+        );
+    }
+
+    #[test]
+    fn stand_in_order() {
+        let mut widths = vec![200, 1600, 640, 800, 1200];
+        widths.sort_by_key(|width| stand_in_rank(700, *width));
+        assert_eq!(widths, vec![800, 1200, 1600, 640, 200]);
+    }
+
+    #[test]
+    fn variant_widths() {
+        let query = |width, height| ServeQuery {
+            width,
+            height,
+            ..Default::default()
+        };
+        assert_eq!(variant_width(&query(None, None), 1000, 500), 1000);
+        assert_eq!(variant_width(&query(Some(2000), None), 1000, 500), 1000);
+        assert_eq!(variant_width(&query(Some(300), None), 1000, 500), 300);
+        assert_eq!(variant_width(&query(None, Some(100)), 1000, 500), 200);
+        assert_eq!(variant_width(&query(Some(300), Some(100)), 1000, 500), 200);
+        assert_eq!(variant_width(&query(Some(100), Some(400)), 1000, 500), 100);
+    }
+
+    #[test]
+    fn hinted_widths_escape_the_signature() {
+        // A signed URL for a 300 pixel wide image, requested with a DPR of 2.
+        let hints = ClientHints::from_headers(&request(&[(client_hints::SEC_CH_DPR, "2")]));
+        let hinted = hints.requested_width(Some(300));
+        assert_eq!(hinted, Some(600));
+        assert!(!signature_covers_width(true, Some(300), hinted));
+
+        let hints = ClientHints::from_headers(&request(&[(client_hints::SEC_CH_DPR, "1")]));
+        assert!(signature_covers_width(
+            true,
+            Some(300),
+            hints.requested_width(Some(300))
+        ));
+
+        // A Width hint on a signed URL without a width.
+        let hints = ClientHints::from_headers(&request(&[(client_hints::WIDTH, "640")]));
+        assert!(!signature_covers_width(
+            true,
+            None,
+            hints.requested_width(None)
+        ));
+
+        assert!(!signature_covers_width(false, Some(300), None));
+    }
+}
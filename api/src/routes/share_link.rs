@@ -0,0 +1,317 @@
+//! Share links for collections, and the gallery pages that they open.
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::{delete, get},
+    Form, Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use db::{
+    object_id::{BaseImageId, ProjectId, ShareLinkId, TeamId, UserId},
+    permissions::ProjectPermission,
+    share_links::{self, NewShareLink, ShareLink},
+    BaseImageStatus, PoolExt,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    access_token,
+    auth::{must_own_project, Authenticated},
+    json::Json,
+    share_link::{self, GalleryImage, DEFAULT_TTL_SECONDS, MAX_IMAGES, MAX_TTL_SECONDS},
+    shared_state::AppState,
+    Error, Result,
+};
+
+#[derive(Debug, Deserialize)]
+struct NewShareLinkInput {
+    collection: String,
+    /// The title of the gallery page. Defaults to the collection's name.
+    title: Option<String>,
+    /// When set, viewers must enter this password to see the gallery.
+    password: Option<String>,
+    /// How long the link lasts. Defaults to seven days, and can't be more than 90 days.
+    ttl_seconds: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct ShareLinkPath {
+    project_id: ProjectId,
+    share_link_id: ShareLinkId,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = share_links)]
+struct ShareLinkOutput {
+    id: ShareLinkId,
+    team_id: TeamId,
+    project_id: ProjectId,
+    collection: String,
+    title: Option<String>,
+    #[diesel(select_expression = share_links::password_hash.is_not_null())]
+    #[diesel(select_expression_type = diesel::dsl::IsNotNull<share_links::password_hash>)]
+    has_password: bool,
+    created_by: UserId,
+    created: DateTime<Utc>,
+    expires: DateTime<Utc>,
+    revoked: Option<DateTime<Utc>>,
+}
+
+async fn list_share_links(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+) -> Result<impl IntoResponse> {
+    let links = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectRead)?;
+
+            share_links::table
+                .filter(share_links::project_id.eq(project_id))
+                .select(ShareLinkOutput::as_select())
+                .order(share_links::created.desc())
+                .load(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(links)))
+}
+
+/// Create a share link for a collection. The link's URL contains its token, so it is only
+/// returned here and can not be retrieved later.
+async fn new_share_link(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Json(body): Json<NewShareLinkInput>,
+) -> Result<impl IntoResponse> {
+    // The gallery page uses signed URLs for its images.
+    if state.url_signer.is_none() {
+        return Err(Error::SignedUrlsDisabled);
+    }
+
+    let id = ShareLinkId::new();
+    let (token, hash) = access_token::generate(id);
+    let password_hash = body
+        .password
+        .filter(|password| !password.is_empty())
+        .map(|password| pic_store_auth::password::new_hash(&password))
+        .transpose()?;
+    let ttl = body
+        .ttl_seconds
+        .unwrap_or(DEFAULT_TTL_SECONDS)
+        .clamp(1, MAX_TTL_SECONDS);
+    let expires = Utc::now() + Duration::seconds(ttl);
+
+    let output = state
+        .db
+        .interact(move |conn| {
+            must_own_project(conn, &user, project_id, ProjectPermission::ProjectWrite)?;
+
+            diesel::insert_into(share_links::table)
+                .values(NewShareLink {
+                    id,
+                    team_id: user.team_id,
+                    project_id,
+                    collection: body.collection,
+                    title: body.title,
+                    hash: hash.as_bytes().to_vec(),
+                    password_hash,
+                    created_by: user.user_id,
+                    expires,
+                })
+                .returning(ShareLinkOutput::as_select())
+                .get_result(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "url": format!("/share/{token}"),
+            "info": output,
+        })),
+    ))
+}
+
+async fn revoke_share_link(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<ShareLinkPath>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .interact(move |conn| {
+            must_own_project(
+                conn,
+                &user,
+                path.project_id,
+                ProjectPermission::ProjectWrite,
+            )?;
+
+            diesel::update(share_links::table)
+                .filter(share_links::id.eq(path.share_link_id))
+                .filter(share_links::project_id.eq(path.project_id))
+                .filter(share_links::revoked.is_null())
+                .set(share_links::revoked.eq(Utc::now()))
+                .execute(conn)?;
+
+            Ok::<_, Error>(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+async fn load_share_link(state: &AppState, token: &str) -> Result<ShareLink> {
+    let (id, hash) = access_token::split::<ShareLinkId>(token).ok_or(Error::NotFound)?;
+    state
+        .db
+        .interact(move |conn| {
+            share_links::find_active(conn, id, hash.as_bytes()).map_err(Error::from)
+        })
+        .await?
+        .ok_or(Error::NotFound)
+}
+
+/// The page holds the link's token in its URL, so it isn't cached or sent on as a referrer.
+fn page_response(status: StatusCode, html: String, cookie: Option<HeaderValue>) -> Response {
+    let mut response = (status, Html(html)).into_response();
+    let headers = response.headers_mut();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    headers.insert(
+        header::REFERRER_POLICY,
+        HeaderValue::from_static("no-referrer"),
+    );
+    if let Some(cookie) = cookie {
+        headers.insert(header::SET_COOKIE, cookie);
+    }
+    response
+}
+
+async fn gallery_response(
+    state: &AppState,
+    link: ShareLink,
+    cookie: Option<HeaderValue>,
+) -> Result<Response> {
+    let signer = state.url_signer.as_ref().ok_or(Error::SignedUrlsDisabled)?;
+
+    let project_id = link.project_id;
+    let collection = link.collection.clone();
+    let images = state
+        .db
+        .interact(move |conn| {
+            db::base_images::table
+                .filter(db::base_images::project_id.eq(project_id))
+                .filter(db::base_images::collection.eq(collection))
+                .filter(db::base_images::status.eq(BaseImageStatus::Ready))
+                .filter(db::base_images::deleted.is_null())
+                .order(db::base_images::created.desc())
+                .limit(MAX_IMAGES)
+                .select((db::base_images::id, db::base_images::alt_text))
+                .load::<(BaseImageId, String)>(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    let expires = share_link::image_urls_expire(link.expires, Utc::now());
+    let images = images
+        .into_iter()
+        .map(|(id, alt_text)| GalleryImage::new(signer, id, alt_text, expires))
+        .collect::<Vec<_>>();
+
+    let title = share_link::page_title(link.title.as_deref(), &link.collection);
+    Ok(page_response(
+        StatusCode::OK,
+        share_link::render_gallery(title, &images),
+        cookie,
+    ))
+}
+
+/// Show the gallery for a share link, or ask for its password.
+async fn view_share_link(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let signer = state.url_signer.as_ref().ok_or(Error::SignedUrlsDisabled)?;
+    let link = load_share_link(&state, &token).await?;
+
+    let unlocked = link.password_hash.is_none()
+        || share_link::verify_cookie(&headers, signer, link.id, Utc::now());
+    if !unlocked {
+        let title = share_link::page_title(link.title.as_deref(), &link.collection);
+        return Ok(page_response(
+            StatusCode::UNAUTHORIZED,
+            share_link::render_password_form(title, false),
+            None,
+        ));
+    }
+
+    gallery_response(&state, link, None).await
+}
+
+#[derive(Deserialize)]
+struct PasswordForm {
+    password: String,
+}
+
+/// Check the password from the form, and show the gallery with a cookie that keeps it unlocked.
+async fn unlock_share_link(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    Form(form): Form<PasswordForm>,
+) -> Result<Response> {
+    let signer = state.url_signer.as_ref().ok_or(Error::SignedUrlsDisabled)?;
+    let link = load_share_link(&state, &token).await?;
+
+    let Some(password_hash) = link.password_hash.clone() else {
+        return gallery_response(&state, link, None).await;
+    };
+
+    let correct = tokio::task::spawn_blocking(move || {
+        pic_store_auth::password::verify_password(&form.password, &password_hash).is_ok()
+    })
+    .await
+    .map_err(eyre::Report::new)?;
+
+    if !correct {
+        let title = share_link::page_title(link.title.as_deref(), &link.collection);
+        return Ok(page_response(
+            StatusCode::UNAUTHORIZED,
+            share_link::render_password_form(title, true),
+            None,
+        ));
+    }
+
+    let now = Utc::now();
+    let expires = link.expires.timestamp();
+    let signature = signer.sign_share(link.id, expires);
+    let cookie = share_link::set_cookie_header(link.id, expires, &signature, now, state.production);
+    gallery_response(&state, link, cookie).await
+}
+
+/// Routes under `/api`.
+pub fn configure() -> Router<AppState> {
+    let link_routes = Router::new()
+        .route("/", get(list_share_links).post(new_share_link))
+        .route("/:share_link_id", delete(revoke_share_link));
+
+    Router::new().nest("/projects/:project_id/share_links", link_routes)
+}
+
+/// The gallery pages, which don't need authentication.
+pub fn configure_pages() -> Router<AppState> {
+    Router::new().route(
+        "/share/:token",
+        get(view_share_link).post(unlock_share_link),
+    )
+}
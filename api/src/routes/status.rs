@@ -0,0 +1,413 @@
+//! A public summary of the service's health, for embedding in a status page. It reports on the
+//! API, the database, the conversion queue and workers, and delivery from storage, as JSON or as
+//! a small HTML page.
+//!
+//! The report is computed at most once per [CACHE_DURATION], no matter how many requests come
+//! in, and responses can be cached for the same time by proxies and browsers. This keeps the
+//! endpoint cheap enough to leave open to the public.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use db::{conversion_pauses, link_checks, PoolExt};
+use diesel::{dsl::count_star, prelude::*};
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{shared_state::AppState, Error};
+
+/// How long to reuse a status report.
+pub const CACHE_DURATION: Duration = Duration::from_secs(30);
+
+/// Conversions waiting longer than this make the workers degraded, or down at twice the time.
+const WORKER_LAG_DEGRADED_MINUTES: i64 = 15;
+
+/// The window of link checks that the storage status is based on.
+const LINK_CHECK_WINDOW_HOURS: i64 = 24;
+
+/// The fraction of failed link checks at which storage is degraded, or down.
+const LINK_CHECK_DEGRADED: f64 = 0.05;
+const LINK_CHECK_DOWN: f64 = 0.5;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentStatus {
+    Operational,
+    /// There isn't enough information to tell.
+    Unknown,
+    Degraded,
+    Down,
+}
+
+impl ComponentStatus {
+    fn label(self) -> &'static str {
+        match self {
+            ComponentStatus::Operational => "Operational",
+            ComponentStatus::Unknown => "Unknown",
+            ComponentStatus::Degraded => "Degraded",
+            ComponentStatus::Down => "Down",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Component {
+    name: &'static str,
+    status: ComponentStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusReport {
+    /// The worst status of any component. Components with an unknown status are left out.
+    status: ComponentStatus,
+    updated: DateTime<Utc>,
+    components: Vec<Component>,
+}
+
+/// The most recent status report, shared by all requests.
+#[derive(Default)]
+pub struct StatusCache {
+    report: Mutex<Option<(Instant, Arc<StatusReport>)>>,
+}
+
+impl StatusCache {
+    /// Return the cached report, or build a new one if it's too old. Requests that arrive while a
+    /// report is being built wait for it instead of building their own.
+    async fn get(&self, state: &AppState) -> Arc<StatusReport> {
+        let mut cached = self.report.lock().await;
+        if let Some((built, report)) = cached.as_ref() {
+            if built.elapsed() < CACHE_DURATION {
+                return report.clone();
+            }
+        }
+
+        let report = Arc::new(build_report(state).await);
+        *cached = Some((Instant::now(), report.clone()));
+        report
+    }
+}
+
+/// Counts of the conversions that are waiting to run.
+struct QueueStats {
+    depth: i64,
+    oldest: Option<DateTime<Utc>>,
+}
+
+async fn build_report(state: &AppState) -> StatusReport {
+    let now = Utc::now();
+    let started = Instant::now();
+    let database = state
+        .db
+        .interact(|conn| {
+            diesel::sql_query("SELECT 1").execute(conn)?;
+            Ok::<_, Error>(())
+        })
+        .await;
+    let database_latency = started.elapsed();
+
+    let (queue, storage) = if database.is_ok() {
+        let queue = state
+            .db
+            .interact(|conn| {
+                let (depth, oldest) = conversion_pauses::waiting_conversions(conn)?;
+                Ok::<_, Error>(QueueStats { depth, oldest })
+            })
+            .await;
+        let since = now - chrono::Duration::hours(LINK_CHECK_WINDOW_HOURS);
+        let storage = state
+            .db
+            .interact(move |conn| load_link_check_counts(conn, since))
+            .await;
+        (queue.ok(), storage.ok())
+    } else {
+        (None, None)
+    };
+
+    let mut components = vec![
+        Component {
+            name: "api",
+            status: ComponentStatus::Operational,
+            detail: None,
+        },
+        match database {
+            Ok(()) => Component {
+                name: "database",
+                status: ComponentStatus::Operational,
+                detail: Some(format!("{} ms", database_latency.as_millis())),
+            },
+            Err(_) => Component {
+                name: "database",
+                status: ComponentStatus::Down,
+                detail: None,
+            },
+        },
+    ];
+
+    match queue {
+        Some(queue) => {
+            components.push(Component {
+                name: "queue",
+                status: ComponentStatus::Operational,
+                detail: Some(format!("{} images waiting for conversion", queue.depth)),
+            });
+
+            let lag = queue
+                .oldest
+                .map(|oldest| now - oldest)
+                .unwrap_or_else(chrono::Duration::zero);
+            components.push(Component {
+                name: "workers",
+                status: worker_status(lag),
+                detail: Some(format!("{} seconds behind", lag.num_seconds().max(0))),
+            });
+        }
+        None => {
+            for name in ["queue", "workers"] {
+                components.push(Component {
+                    name,
+                    status: ComponentStatus::Unknown,
+                    detail: None,
+                });
+            }
+        }
+    }
+
+    let (storage_status, storage_detail) = match storage {
+        Some((checked, failed)) if checked > 0 => (
+            storage_status(checked, failed),
+            Some(format!(
+                "{failed} of {checked} delivery checks failed in the last \
+                 {LINK_CHECK_WINDOW_HOURS} hours"
+            )),
+        ),
+        _ => (ComponentStatus::Unknown, None),
+    };
+    components.push(Component {
+        name: "storage",
+        status: storage_status,
+        detail: storage_detail,
+    });
+
+    StatusReport {
+        status: overall_status(&components),
+        updated: now,
+        components,
+    }
+}
+
+/// Return the number of delivery URL checks since a time, and how many of them failed.
+fn load_link_check_counts(
+    conn: &mut PgConnection,
+    since: DateTime<Utc>,
+) -> Result<(i64, i64), Error> {
+    let counts = link_checks::table
+        .filter(link_checks::checked.ge(since))
+        .group_by(link_checks::ok)
+        .select((link_checks::ok, count_star()))
+        .load::<(bool, i64)>(conn)?;
+
+    let checked = counts.iter().map(|(_, count)| count).sum::<i64>();
+    let failed = counts
+        .iter()
+        .filter(|(ok, _)| !ok)
+        .map(|(_, count)| count)
+        .sum::<i64>();
+    Ok((checked, failed))
+}
+
+fn worker_status(lag: chrono::Duration) -> ComponentStatus {
+    let degraded = chrono::Duration::minutes(WORKER_LAG_DEGRADED_MINUTES);
+    if lag >= degraded * 2 {
+        ComponentStatus::Down
+    } else if lag >= degraded {
+        ComponentStatus::Degraded
+    } else {
+        ComponentStatus::Operational
+    }
+}
+
+fn storage_status(checked: i64, failed: i64) -> ComponentStatus {
+    let failure_rate = failed as f64 / checked as f64;
+    if failure_rate >= LINK_CHECK_DOWN {
+        ComponentStatus::Down
+    } else if failure_rate >= LINK_CHECK_DEGRADED {
+        ComponentStatus::Degraded
+    } else {
+        ComponentStatus::Operational
+    }
+}
+
+fn overall_status(components: &[Component]) -> ComponentStatus {
+    components
+        .iter()
+        .map(|component| component.status)
+        .filter(|status| *status != ComponentStatus::Unknown)
+        .max()
+        .unwrap_or(ComponentStatus::Unknown)
+}
+
+fn render_html(report: &StatusReport) -> String {
+    // Every value in the report is generated here, so nothing needs escaping.
+    let rows = report
+        .components
+        .iter()
+        .map(|component| {
+            format!(
+                "<tr class=\"{status:?}\"><td>{name}</td><td>{label}</td><td>{detail}</td></tr>",
+                status = component.status,
+                name = component.name,
+                label = component.status.label(),
+                detail = component.detail.as_deref().unwrap_or_default(),
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Status: {overall}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+td {{ padding: 0.25em 1em 0.25em 0; }}
+.Operational td:nth-child(2) {{ color: #15803d; }}
+.Degraded td:nth-child(2) {{ color: #b45309; }}
+.Down td:nth-child(2) {{ color: #b91c1c; }}
+.Unknown td:nth-child(2) {{ color: #6b7280; }}
+</style>
+</head>
+<body>
+<h1>{overall}</h1>
+<table>{rows}</table>
+<p>Updated {updated}</p>
+</body>
+</html>
+"#,
+        overall = report.status.label(),
+        updated = report.updated.to_rfc3339(),
+    )
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum StatusFormat {
+    Json,
+    Html,
+}
+
+#[derive(Debug, Deserialize)]
+struct StatusQuery {
+    format: Option<StatusFormat>,
+}
+
+async fn get_status(
+    State(state): State<AppState>,
+    Query(query): Query<StatusQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let report = state.status_cache.get(&state).await;
+
+    // Browsers get the HTML page when the query doesn't choose a format.
+    let format = query.format.unwrap_or_else(|| {
+        let wants_html = headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .map(|accept| accept.contains("text/html"))
+            .unwrap_or(false);
+        if wants_html {
+            StatusFormat::Html
+        } else {
+            StatusFormat::Json
+        }
+    });
+
+    let cache_control = [
+        (
+            header::CACHE_CONTROL,
+            format!("public, max-age={}", CACHE_DURATION.as_secs()),
+        ),
+        (header::VARY, "accept".to_string()),
+    ];
+    match format {
+        StatusFormat::Json => {
+            (StatusCode::OK, cache_control, Json(report.as_ref())).into_response()
+        }
+        StatusFormat::Html => {
+            (StatusCode::OK, cache_control, Html(render_html(&report))).into_response()
+        }
+    }
+}
+
+pub fn configure() -> Router<AppState> {
+    Router::new().route("/status", get(get_status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component(status: ComponentStatus) -> Component {
+        Component {
+            name: "test",
+            status,
+            detail: None,
+        }
+    }
+
+    #[test]
+    fn overall() {
+        assert_eq!(
+            overall_status(&[
+                component(ComponentStatus::Operational),
+                component(ComponentStatus::Unknown)
+            ]),
+            ComponentStatus::Operational
+        );
+        assert_eq!(
+            overall_status(&[
+                component(ComponentStatus::Degraded),
+                component(ComponentStatus::Operational),
+                component(ComponentStatus::Down),
+            ]),
+            ComponentStatus::Down
+        );
+        assert_eq!(
+            overall_status(&[component(ComponentStatus::Unknown)]),
+            ComponentStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn thresholds() {
+        assert_eq!(
+            worker_status(chrono::Duration::minutes(1)),
+            ComponentStatus::Operational
+        );
+        assert_eq!(
+            worker_status(chrono::Duration::minutes(20)),
+            ComponentStatus::Degraded
+        );
+        assert_eq!(
+            worker_status(chrono::Duration::hours(2)),
+            ComponentStatus::Down
+        );
+
+        assert_eq!(storage_status(100, 0), ComponentStatus::Operational);
+        assert_eq!(storage_status(100, 10), ComponentStatus::Degraded);
+        assert_eq!(storage_status(10, 6), ComponentStatus::Down);
+    }
+}
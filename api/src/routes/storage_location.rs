@@ -1,30 +1,43 @@
+use std::{
+    convert::Infallible,
+    path::{Component, Path as FsPath},
+};
+
 use axum::{
     extract::{Path, State},
     http::StatusCode,
     response::IntoResponse,
     routing::{delete, get, post, put},
-    Json, Router,
+    Router,
 };
 use chrono::{DateTime, Utc};
 use diesel::prelude::*;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 
 use db::{
-    object_id::{ProjectId, StorageLocationId},
+    object_id::{OrganizationId, ProjectId, StorageLocationId},
     permissions::ProjectPermission,
-    storage_locations::{self, NewStorageLocation, Provider},
-    Permission,
+    storage_locations::{self, CdnPurge, NewStorageLocation, Provider},
+    Permission, PoolExt, StorageServeMode,
 };
 use pic_store_db as db;
+use pic_store_storage as storage;
 use serde_json::json;
 
 use crate::{
-    auth::{Authenticated, UserInfo},
-    create_object, disable_object, get_object, list_project_and_global_objects,
+    auth::{must_have_permission_on_project, Authenticated, UserInfo},
+    create_object, disable_object, get_object,
+    json::Json,
+    key_template::KeyPrefixTemplate,
+    labels, list_project_and_global_objects,
+    redact::REDACTED,
     shared_state::AppState,
     write_object, Error,
 };
 
+/// The file written by the storage location test.
+const HEALTH_CHECK_KEY: &str = ".pic-store-health-check";
+
 #[derive(Deserialize)]
 pub struct ProjectStorageLocationPath {
     project_id: ProjectId,
@@ -36,20 +49,157 @@ pub struct StorageLocationInput {
     pub name: String,
     pub provider: Provider,
     pub base_location: String,
+    /// Defaults to the provider's standard public URL for the location, if it has one.
+    #[serde(default)]
     pub public_url_base: String,
+    #[serde(default)]
+    pub serve_mode: StorageServeMode,
+    #[serde(default)]
+    pub cdn_purge: Option<CdnPurge>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// A template for a prefix added to the keys of every image written to the location.
+    #[serde(default)]
+    pub key_prefix_template: Option<String>,
 }
 
+/// A storage location as returned from the API. Credentials are never returned, even when
+/// they're encrypted, and their values are replaced with a placeholder.
 #[derive(Debug, Serialize, Queryable, Selectable)]
 #[diesel(table_name = db::storage_locations)]
 pub struct StorageLocationOutput {
     pub id: StorageLocationId,
     pub name: String,
+    #[serde(serialize_with = "serialize_provider")]
     pub provider: Provider,
     pub base_location: String,
     pub public_url_base: String,
+    pub serve_mode: StorageServeMode,
+    #[serde(serialize_with = "serialize_cdn_purge")]
+    pub cdn_purge: Option<CdnPurge>,
+    /// The organization that this location is shared with, if any.
+    pub organization_id: Option<OrganizationId>,
+    pub labels: Vec<String>,
+    pub key_prefix_template: Option<String>,
     pub updated: DateTime<Utc>,
 }
 
+fn serialize_provider<S: Serializer>(
+    provider: &Provider,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let redacted = provider
+        .clone()
+        .map_credentials(|_| Ok::<_, Infallible>(REDACTED.to_string()))
+        .unwrap_or_else(|e| match e {});
+    redacted.serialize(serializer)
+}
+
+fn serialize_cdn_purge<S: Serializer>(
+    cdn_purge: &Option<CdnPurge>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let redacted = cdn_purge.clone().map(|cdn| {
+        cdn.map_credentials(|_| Ok::<_, Infallible>(REDACTED.to_string()))
+            .unwrap_or_else(|e| match e {})
+    });
+    redacted.serialize(serializer)
+}
+
+/// Make sure that a credential isn't the placeholder from [StorageLocationOutput], which would
+/// happen if a client sent back a location that it had read. The credentials have to be sent
+/// again in full when a location is updated.
+fn check_credential(value: String) -> Result<String, Error> {
+    if value == REDACTED {
+        Err(Error::InvalidStorageLocation(
+            "credentials must be sent in full, not as the redacted placeholder",
+        ))
+    } else {
+        Ok(value)
+    }
+}
+
+/// Check and encrypt the credentials in a location's provider and CDN settings.
+fn encrypt_credentials(
+    provider: Provider,
+    cdn_purge: Option<CdnPurge>,
+) -> Result<(Provider, Option<CdnPurge>), Error> {
+    let provider = provider
+        .map_credentials(check_credential)?
+        .encrypt_credentials()?;
+    let cdn_purge = cdn_purge
+        .map(|cdn| {
+            cdn.map_credentials(check_credential)?
+                .encrypt_credentials()
+                .map_err(Error::from)
+        })
+        .transpose()?;
+    Ok((provider, cdn_purge))
+}
+
+/// Check the base location of a local filesystem storage location. Local locations must be
+/// allowed by the server, and must be inside `local_storage_dir` when it is set. Relative paths
+/// are placed inside that directory. Other providers are returned unchanged.
+fn local_base_location(
+    allow_local_fs: bool,
+    local_storage_dir: Option<&FsPath>,
+    provider: &Provider,
+    base_location: String,
+) -> Result<String, Error> {
+    if !matches!(provider, Provider::Local) {
+        return Ok(base_location);
+    }
+
+    if !allow_local_fs {
+        return Err(Error::InvalidStorageLocation(
+            "local storage is not enabled on this server",
+        ));
+    }
+
+    let path = FsPath::new(&base_location);
+    if base_location.is_empty() || path.components().any(|c| c == Component::ParentDir) {
+        return Err(Error::InvalidStorageLocation(
+            "base_location must be a directory, and can not contain `..`",
+        ));
+    }
+
+    let Some(root) = local_storage_dir else {
+        return Ok(base_location);
+    };
+
+    let path = root.join(path);
+    if !path.starts_with(root) {
+        return Err(Error::InvalidStorageLocation(
+            "base_location must be inside the local storage directory",
+        ));
+    }
+
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Check that a key prefix template can be rendered. Empty templates are treated as unset.
+fn validate_key_prefix_template(template: Option<String>) -> Result<Option<String>, Error> {
+    let Some(template) = template
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+    else {
+        return Ok(None);
+    };
+
+    KeyPrefixTemplate::parse(&template)?;
+    Ok(Some(template))
+}
+
+fn public_url_base(provider: &Provider, base_location: &str, public_url_base: String) -> String {
+    if public_url_base.is_empty() {
+        provider
+            .default_public_url_base(base_location)
+            .unwrap_or(public_url_base)
+    } else {
+        public_url_base
+    }
+}
+
 async fn list_global_locations(
     State(state): State<AppState>,
     Authenticated(user): Authenticated,
@@ -115,6 +265,17 @@ async fn write_location(
     location_id: StorageLocationId,
     body: StorageLocationInput,
 ) -> Result<impl IntoResponse, Error> {
+    let base_location = local_base_location(
+        state.allow_local_fs,
+        state.local_storage_dir.as_deref(),
+        &body.provider,
+        body.base_location,
+    )?;
+    let public_url_base = public_url_base(&body.provider, &base_location, body.public_url_base);
+    let labels = labels::normalize_labels(body.labels)?;
+    let key_prefix_template = validate_key_prefix_template(body.key_prefix_template)?;
+    let (provider, cdn_purge) = encrypt_credentials(body.provider, body.cdn_purge)?;
+
     let result = write_object!(
         storage_locations,
         state,
@@ -125,9 +286,13 @@ async fn write_location(
         ProjectPermission::StorageLocationWrite,
         (
             dsl::name.eq(body.name),
-            dsl::provider.eq(body.provider),
-            dsl::base_location.eq(body.base_location),
-            dsl::public_url_base.eq(body.public_url_base),
+            dsl::provider.eq(provider),
+            dsl::base_location.eq(base_location),
+            dsl::public_url_base.eq(public_url_base),
+            dsl::serve_mode.eq(body.serve_mode),
+            dsl::cdn_purge.eq(cdn_purge),
+            dsl::labels.eq(labels),
+            dsl::key_prefix_template.eq(key_prefix_template),
             dsl::updated.eq(Utc::now()),
         )
     )
@@ -159,12 +324,25 @@ async fn new_location(
     project_id: Option<ProjectId>,
     body: StorageLocationInput,
 ) -> Result<impl IntoResponse, Error> {
+    let base_location = local_base_location(
+        state.allow_local_fs,
+        state.local_storage_dir.as_deref(),
+        &body.provider,
+        body.base_location,
+    )?;
+    let public_url_base = public_url_base(&body.provider, &base_location, body.public_url_base);
+    let (provider, cdn_purge) = encrypt_credentials(body.provider, body.cdn_purge)?;
+
     let value = NewStorageLocation {
         id: StorageLocationId::new(),
         name: body.name,
-        provider: body.provider,
-        base_location: body.base_location,
-        public_url_base: body.public_url_base,
+        provider,
+        base_location,
+        public_url_base,
+        serve_mode: body.serve_mode,
+        cdn_purge,
+        labels: labels::normalize_labels(body.labels)?,
+        key_prefix_template: validate_key_prefix_template(body.key_prefix_template)?,
         team_id: state.team_id,
         project_id,
     };
@@ -258,13 +436,73 @@ async fn disable_location(
     Ok((StatusCode::OK, Json(json!({}))))
 }
 
+async fn test_project_location(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<ProjectStorageLocationPath>,
+) -> Result<impl IntoResponse, crate::Error> {
+    test_location(state, user, Some(path.project_id), path.storage_location_id).await
+}
+
+async fn test_global_location(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(location_id): Path<StorageLocationId>,
+) -> Result<impl IntoResponse, crate::Error> {
+    test_location(state, user, None, location_id).await
+}
+
+/// Write, read, and delete a small file in the storage location, so that its settings can be
+/// checked before uploads are sent to it. Failures are reported in the response body rather
+/// than as an error status.
+async fn test_location(
+    state: AppState,
+    user: UserInfo,
+    project_id: Option<ProjectId>,
+    location_id: StorageLocationId,
+) -> Result<impl IntoResponse, crate::Error> {
+    let project_id = project_id.unwrap_or_else(ProjectId::nil);
+    let (provider, base_location) = state
+        .db
+        .interact(move |conn| {
+            must_have_permission_on_project(
+                conn,
+                &user,
+                project_id,
+                ProjectPermission::StorageLocationWrite,
+            )?;
+
+            storage_locations::table
+                .filter(storage_locations::id.eq(location_id))
+                .filter(storage_locations::project_id.is_not_distinct_from(project_id))
+                .filter(storage_locations::team_id.eq(user.team_id))
+                .filter(storage_locations::deleted.is_null())
+                .select((
+                    storage_locations::provider,
+                    storage_locations::base_location,
+                ))
+                .first::<(Provider, String)>(conn)
+                .optional()?
+                .ok_or(Error::ObjectNotFound("storage location"))
+        })
+        .await?;
+
+    let operator = storage::Provider::from_db(provider)?
+        .create_operator(&base_location)
+        .await?;
+    let result = operator.health_check(HEALTH_CHECK_KEY).await;
+
+    Ok((StatusCode::OK, Json(result)))
+}
+
 pub fn configure() -> Router<AppState> {
     let project_routes = Router::new()
         .route("/", get(list_project_locations))
         .route("/", post(new_project_location))
         .route("/:storage_location_id", get(get_project_location))
         .route("/:storage_location_id", put(write_project_location))
-        .route("/:storage_location_id", delete(disable_project_location));
+        .route("/:storage_location_id", delete(disable_project_location))
+        .route("/:storage_location_id/test", post(test_project_location));
 
     let project_router =
         Router::new().nest("/projects/:project_id/storage_locations", project_routes);
@@ -274,9 +512,131 @@ pub fn configure() -> Router<AppState> {
         .route("/", post(new_global_location))
         .route("/:storage_location_id", get(get_global_location))
         .route("/:storage_location_id", put(write_global_location))
-        .route("/:storage_location_id", delete(disable_global_location));
+        .route("/:storage_location_id", delete(disable_global_location))
+        .route("/:storage_location_id/test", post(test_global_location));
 
     let global_router = Router::new().nest("/projects/global/storage_locations", global_routes);
 
     global_router.merge(project_router)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const S3: Provider = Provider::S3 {
+        endpoint: None,
+        region: None,
+        access_key_id: None,
+        secret_key: None,
+        virtual_host_style: None,
+        skip_tls_verify: None,
+    };
+
+    #[test]
+    fn local_base_locations() {
+        let root = FsPath::new("/srv/images");
+        let check = |allow, root, provider: &Provider, location: &str| {
+            local_base_location(allow, root, provider, location.to_string())
+        };
+
+        assert_eq!(
+            check(true, Some(root), &Provider::Local, "originals").unwrap(),
+            "/srv/images/originals"
+        );
+        assert_eq!(
+            check(true, Some(root), &Provider::Local, "/srv/images/outputs").unwrap(),
+            "/srv/images/outputs"
+        );
+        assert_eq!(
+            check(true, None, &Provider::Local, "/data/images").unwrap(),
+            "/data/images"
+        );
+
+        assert!(check(false, None, &Provider::Local, "/data/images").is_err());
+        assert!(check(true, Some(root), &Provider::Local, "/etc").is_err());
+        assert!(check(true, Some(root), &Provider::Local, "a/../../etc").is_err());
+        assert!(check(true, None, &Provider::Local, "").is_err());
+
+        // Other providers are not checked.
+        assert_eq!(
+            check(false, Some(root), &S3, "bucket/path").unwrap(),
+            "bucket/path"
+        );
+    }
+
+    #[test]
+    fn default_public_urls() {
+        let gcs = Provider::Gcs {
+            service_account_key: None,
+        };
+        assert_eq!(
+            public_url_base(&gcs, "bucket/images/", String::new()),
+            "https://storage.googleapis.com/bucket/images"
+        );
+        assert_eq!(
+            public_url_base(&gcs, "bucket", "https://cdn.example.com".to_string()),
+            "https://cdn.example.com"
+        );
+        assert_eq!(public_url_base(&S3, "bucket", String::new()), "");
+
+        let azure = Provider::Azure {
+            account: None,
+            connection_string: Some(
+                "DefaultEndpointsProtocol=https;AccountName=pics;AccountKey=a2V5==".to_string(),
+            ),
+            client_id: None,
+        };
+        assert_eq!(
+            public_url_base(&azure, "images", String::new()),
+            "https://pics.blob.core.windows.net/images"
+        );
+
+        let b2 = Provider::B2 {
+            region: "us-west-004".to_string(),
+            key_id: "key".to_string(),
+            application_key: "secret".to_string(),
+        };
+        assert_eq!(
+            public_url_base(&b2, "archive/images", String::new()),
+            "https://s3.us-west-004.backblazeb2.com/archive/images"
+        );
+    }
+
+    #[test]
+    fn credentials_are_redacted() {
+        let output = StorageLocationOutput {
+            id: StorageLocationId::new(),
+            name: "images".to_string(),
+            provider: Provider::S3 {
+                endpoint: None,
+                region: None,
+                access_key_id: Some("AKIA1".to_string()),
+                secret_key: Some("s3-secret".to_string()),
+                virtual_host_style: None,
+                skip_tls_verify: None,
+            },
+            base_location: "bucket".to_string(),
+            public_url_base: String::new(),
+            serve_mode: StorageServeMode::default(),
+            cdn_purge: Some(CdnPurge::Cloudflare {
+                zone_id: "zone".to_string(),
+                api_token: "cf-token".to_string(),
+            }),
+            organization_id: None,
+            labels: Vec::new(),
+            key_prefix_template: None,
+            updated: Utc::now(),
+        };
+
+        let value = serde_json::to_value(&output).unwrap();
+        assert_eq!(value["provider"]["access_key_id"], "AKIA1");
+        assert_eq!(value["provider"]["secret_key"], REDACTED);
+        assert_eq!(value["cdn_purge"]["zone_id"], "zone");
+        assert_eq!(value["cdn_purge"]["api_token"], REDACTED);
+
+        // Sending the placeholder back is rejected instead of saving it as the credential.
+        let cdn_purge = serde_json::from_value::<CdnPurge>(value["cdn_purge"].clone()).unwrap();
+        assert!(encrypt_credentials(S3, Some(cdn_purge)).is_err());
+    }
+}
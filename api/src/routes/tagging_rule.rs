@@ -0,0 +1,193 @@
+//! Manage a project's tagging rules, which tag uploaded images based on their EXIF data.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post, put},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use db::{
+    object_id::{ProjectId, TaggingRuleId},
+    permissions::ProjectPermission,
+    tagging_rules::{self, ExifConditions, NewTaggingRule},
+    Permission,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    auth::Authenticated, create_object, disable_object, get_object, json::Json,
+    list_project_objects, shared_state::AppState, tagging::normalize_tags, write_object, Error,
+    Result,
+};
+
+#[derive(Debug, Deserialize)]
+struct TaggingRuleInput {
+    name: String,
+    #[serde(default)]
+    priority: i32,
+    #[serde(default)]
+    conditions: ExifConditions,
+    #[serde(default)]
+    tags: Vec<String>,
+    collection: Option<String>,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = tagging_rules)]
+struct TaggingRuleOutput {
+    id: TaggingRuleId,
+    project_id: ProjectId,
+    name: String,
+    priority: i32,
+    conditions: ExifConditions,
+    tags: Vec<String>,
+    collection: Option<String>,
+    updated: DateTime<Utc>,
+}
+
+fn normalize_collection(collection: Option<String>) -> Option<String> {
+    collection
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+}
+
+async fn list_tagging_rules(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+) -> Result<impl IntoResponse> {
+    let mut objects = list_project_objects!(
+        tagging_rules,
+        state,
+        user,
+        TaggingRuleOutput,
+        project_id,
+        ProjectPermission::ProjectRead
+    )
+    .await?;
+    objects.sort_by(|a, b| {
+        a.priority
+            .cmp(&b.priority)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    Ok((StatusCode::OK, Json(objects)))
+}
+
+async fn get_tagging_rule(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path((_project_id, rule_id)): Path<(ProjectId, TaggingRuleId)>,
+) -> Result<impl IntoResponse> {
+    let (object, allowed) = get_object!(
+        tagging_rules,
+        state,
+        user,
+        TaggingRuleOutput,
+        rule_id,
+        Permission::ProjectRead
+    )
+    .await?;
+
+    if !allowed {
+        return Err(Error::MissingPermission(Permission::ProjectRead));
+    }
+
+    Ok((StatusCode::OK, Json(object)))
+}
+
+async fn new_tagging_rule(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Json(body): Json<TaggingRuleInput>,
+) -> Result<impl IntoResponse> {
+    let value = NewTaggingRule {
+        id: TaggingRuleId::new(),
+        team_id: user.team_id,
+        project_id,
+        name: body.name,
+        priority: body.priority,
+        conditions: body.conditions,
+        tags: normalize_tags(body.tags),
+        collection: normalize_collection(body.collection),
+    };
+
+    let result = create_object!(
+        tagging_rules,
+        state,
+        user,
+        project_id,
+        TaggingRuleOutput,
+        ProjectPermission::ProjectWrite,
+        &value
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(result)))
+}
+
+async fn write_tagging_rule(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path((project_id, rule_id)): Path<(ProjectId, TaggingRuleId)>,
+    Json(body): Json<TaggingRuleInput>,
+) -> Result<impl IntoResponse> {
+    let tags = normalize_tags(body.tags);
+    let collection = normalize_collection(body.collection);
+
+    let result = write_object!(
+        tagging_rules,
+        state,
+        user,
+        rule_id,
+        project_id,
+        TaggingRuleOutput,
+        ProjectPermission::ProjectWrite,
+        (
+            dsl::name.eq(body.name),
+            dsl::priority.eq(body.priority),
+            dsl::conditions.eq(body.conditions),
+            dsl::tags.eq(tags),
+            dsl::collection.eq(collection),
+            dsl::updated.eq(Utc::now()),
+        )
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(result)))
+}
+
+async fn disable_tagging_rule(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path((project_id, rule_id)): Path<(ProjectId, TaggingRuleId)>,
+) -> Result<impl IntoResponse> {
+    disable_object!(
+        tagging_rules,
+        state,
+        user,
+        rule_id,
+        project_id,
+        ProjectPermission::ProjectWrite
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+pub fn configure() -> Router<AppState> {
+    let project_routes = Router::new()
+        .route("/", get(list_tagging_rules))
+        .route("/", post(new_tagging_rule))
+        .route("/:tagging_rule_id", get(get_tagging_rule))
+        .route("/:tagging_rule_id", put(write_tagging_rule))
+        .route("/:tagging_rule_id", delete(disable_tagging_rule));
+
+    Router::new().nest("/projects/:project_id/tagging_rules", project_routes)
+}
@@ -0,0 +1,360 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post, put},
+    Router,
+};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use image::GenericImageView;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use db::{
+    object_id::{ProjectId, TransformationPresetId},
+    permissions::ProjectPermission,
+    transformation_presets,
+    transformation_presets::{
+        NewTransformationPreset, TransformationOperation, TransformationOperations,
+        TransformationPreset,
+    },
+    Permission,
+};
+use pic_store_db as db;
+
+use crate::{
+    auth::{Authenticated, UserInfo},
+    create_object, disable_object, get_object,
+    json::Json,
+    list_project_and_global_objects,
+    shared_state::AppState,
+    write_object, Error,
+};
+
+/// The largest watermark image that a preset can hold, since presets are loaded for every
+/// image that they apply to.
+const MAX_WATERMARK_BYTES: usize = 256 * 1024;
+/// The largest width or height of a watermark image.
+const MAX_WATERMARK_DIMENSION: u32 = 2048;
+
+#[derive(Debug, Deserialize)]
+pub struct TransformationPresetInput {
+    pub name: String,
+    pub operations: TransformationOperations,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = transformation_presets)]
+pub struct TransformationPresetOutput {
+    id: TransformationPresetId,
+    name: String,
+    operations: TransformationOperations,
+    updated: DateTime<Utc>,
+}
+
+impl From<TransformationPreset> for TransformationPresetOutput {
+    fn from(value: TransformationPreset) -> Self {
+        TransformationPresetOutput {
+            id: value.id,
+            name: value.name,
+            operations: value.operations,
+            updated: value.updated,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ProjectTransformationPresetPath {
+    project_id: ProjectId,
+    transformation_preset_id: TransformationPresetId,
+}
+
+/// List transformation presets for the project and also the global presets.
+async fn list_project_presets(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+) -> Result<impl IntoResponse, crate::Error> {
+    list_presets(state, user, Some(project_id)).await
+}
+
+async fn list_global_presets(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+) -> Result<impl IntoResponse, Error> {
+    list_presets(state, user, None).await
+}
+
+async fn list_presets(
+    state: AppState,
+    user: UserInfo,
+    project_id: Option<ProjectId>,
+) -> Result<impl IntoResponse, Error> {
+    let objects = list_project_and_global_objects!(
+        transformation_presets,
+        state,
+        user,
+        TransformationPresetOutput,
+        project_id,
+        Permission::ProjectRead
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(objects)))
+}
+
+async fn write_project_preset(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<ProjectTransformationPresetPath>,
+    Json(body): Json<TransformationPresetInput>,
+) -> Result<impl IntoResponse, Error> {
+    write_preset(
+        state,
+        user,
+        Some(path.project_id),
+        path.transformation_preset_id,
+        body,
+    )
+    .await
+}
+
+async fn write_global_preset(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(preset_id): Path<TransformationPresetId>,
+    Json(body): Json<TransformationPresetInput>,
+) -> Result<impl IntoResponse, Error> {
+    write_preset(state, user, None, preset_id, body).await
+}
+
+/// Check the settings of the operations, and that watermark images can be read.
+fn validate_operations(operations: &TransformationOperations) -> Result<(), Error> {
+    for op in &operations.0 {
+        let TransformationOperation::Watermark {
+            image,
+            opacity,
+            scale,
+            ..
+        } = op
+        else {
+            continue;
+        };
+
+        if opacity.is_some_and(|opacity| !(0.0..=1.0).contains(&opacity)) {
+            return Err(Error::InvalidTransformation(
+                "watermark opacity must be between 0 and 1",
+            ));
+        }
+
+        if scale.is_some_and(|scale| !(scale > 0.0 && scale <= 1.0)) {
+            return Err(Error::InvalidTransformation(
+                "watermark scale must be more than 0 and at most 1",
+            ));
+        }
+
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(image)
+            .map_err(|_| Error::InvalidTransformation("watermark image must be base64 encoded"))?;
+        if bytes.len() > MAX_WATERMARK_BYTES {
+            return Err(Error::InvalidTransformation(
+                "watermark image can be at most 256 KiB",
+            ));
+        }
+
+        let (width, height) = image::load_from_memory(&bytes)
+            .map_err(|_| Error::InvalidTransformation("watermark image could not be read"))?
+            .dimensions();
+        if width > MAX_WATERMARK_DIMENSION || height > MAX_WATERMARK_DIMENSION {
+            return Err(Error::InvalidTransformation(
+                "watermark image can be at most 2048 pixels wide and high",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+async fn write_preset(
+    state: AppState,
+    user: UserInfo,
+    project_id: Option<ProjectId>,
+    preset_id: TransformationPresetId,
+    body: TransformationPresetInput,
+) -> Result<impl IntoResponse, Error> {
+    validate_operations(&body.operations)?;
+
+    let result = write_object!(
+        transformation_presets,
+        state,
+        user,
+        preset_id,
+        project_id.unwrap_or_else(ProjectId::nil),
+        TransformationPresetOutput,
+        ProjectPermission::ConversionProfileWrite,
+        (
+            dsl::name.eq(body.name),
+            dsl::operations.eq(body.operations),
+            dsl::updated.eq(Utc::now())
+        )
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(result)))
+}
+
+async fn new_project_preset(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(project_id): Path<ProjectId>,
+    Json(body): Json<TransformationPresetInput>,
+) -> Result<impl IntoResponse, crate::Error> {
+    new_preset(state, user, Some(project_id), body).await
+}
+
+async fn new_global_preset(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Json(body): Json<TransformationPresetInput>,
+) -> Result<impl IntoResponse, crate::Error> {
+    new_preset(state, user, None, body).await
+}
+
+async fn new_preset(
+    state: AppState,
+    user: UserInfo,
+    project_id: Option<ProjectId>,
+    body: TransformationPresetInput,
+) -> Result<impl IntoResponse, Error> {
+    validate_operations(&body.operations)?;
+
+    let value = NewTransformationPreset {
+        id: TransformationPresetId::new(),
+        name: body.name,
+        team_id: state.team_id,
+        project_id,
+        operations: body.operations,
+    };
+
+    let result = create_object!(
+        transformation_presets,
+        state,
+        user,
+        project_id.unwrap_or_else(ProjectId::nil),
+        TransformationPresetOutput,
+        ProjectPermission::ConversionProfileWrite,
+        &value
+    )
+    .await?;
+
+    Ok((StatusCode::ACCEPTED, Json(result)))
+}
+
+async fn get_global_preset(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(preset_id): Path<TransformationPresetId>,
+) -> Result<impl IntoResponse, crate::Error> {
+    get_preset(state, user, preset_id).await
+}
+
+async fn get_project_preset(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<ProjectTransformationPresetPath>,
+) -> Result<impl IntoResponse, crate::Error> {
+    get_preset(state, user, path.transformation_preset_id).await
+}
+
+async fn get_preset(
+    state: AppState,
+    user: UserInfo,
+    preset_id: TransformationPresetId,
+) -> Result<impl IntoResponse, crate::Error> {
+    let (preset, allowed) = get_object!(
+        transformation_presets,
+        state,
+        user,
+        TransformationPresetOutput,
+        preset_id,
+        db::role_permissions::Permission::ProjectRead
+    )
+    .await?;
+
+    if !allowed {
+        return Err(Error::MissingPermission(
+            db::role_permissions::Permission::ProjectRead,
+        ));
+    }
+
+    Ok((StatusCode::OK, Json(preset)))
+}
+
+async fn disable_project_preset(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(path): Path<ProjectTransformationPresetPath>,
+) -> Result<impl IntoResponse, crate::Error> {
+    disable_preset(
+        state,
+        user,
+        Some(path.project_id),
+        path.transformation_preset_id,
+    )
+    .await
+}
+
+async fn disable_global_preset(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(preset_id): Path<TransformationPresetId>,
+) -> Result<impl IntoResponse, crate::Error> {
+    disable_preset(state, user, None, preset_id).await
+}
+
+async fn disable_preset(
+    state: AppState,
+    user: UserInfo,
+    project_id: Option<ProjectId>,
+    preset_id: TransformationPresetId,
+) -> Result<impl IntoResponse, crate::Error> {
+    disable_object!(
+        transformation_presets,
+        state,
+        user,
+        preset_id,
+        project_id.unwrap_or_else(ProjectId::nil),
+        ProjectPermission::ConversionProfileWrite
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+pub fn configure() -> Router<AppState> {
+    let project_routes = Router::new()
+        .route("/", get(list_project_presets))
+        .route("/", post(new_project_preset))
+        .route("/:transformation_preset_id", get(get_project_preset))
+        .route("/:transformation_preset_id", put(write_project_preset))
+        .route("/:transformation_preset_id", delete(disable_project_preset));
+
+    let project_router = Router::new().nest(
+        "/projects/:project_id/transformation_presets",
+        project_routes,
+    );
+
+    let global_routes = Router::new()
+        .route("/", get(list_global_presets))
+        .route("/", post(new_global_preset))
+        .route("/:transformation_preset_id", get(get_global_preset))
+        .route("/:transformation_preset_id", put(write_global_preset))
+        .route("/:transformation_preset_id", delete(disable_global_preset));
+
+    let global_router =
+        Router::new().nest("/projects/global/transformation_presets", global_routes);
+
+    global_router.merge(project_router)
+}
@@ -1,16 +1,19 @@
 use axum::{
     extract::{Path, State},
-    http::StatusCode,
+    http::{HeaderValue, StatusCode},
     response::IntoResponse,
     routing::{delete, get, post, put},
-    Extension, Json, Router,
+    Extension, Router,
 };
 use chrono::Utc;
 use db::{
     object_id::{ConversionProfileId, ProjectId, StorageLocationId, UploadProfileId},
     permissions::ProjectPermission,
-    upload_profiles::{self, NewUploadProfile},
-    Permission, PoolExt,
+    upload_profiles::{
+        self, ConstraintViolation, FormatFallbacks, ImageConstraints, NewUploadProfile,
+        OriginalRetention, PostProcessCallback,
+    },
+    ImageFormat, Permission, PoolExt,
 };
 use diesel::prelude::*;
 use pic_store_db as db;
@@ -19,7 +22,10 @@ use serde_json::json;
 
 use crate::{
     auth::{must_have_permission_on_project, Authenticated, UserInfo},
-    create_object, disable_object, get_object, list_project_objects,
+    create_object, disable_object, geo, get_object,
+    json::Json,
+    labels, list_project_objects, remote_fetch,
+    routes::serve::content_type,
     shared_state::AppState,
     write_object, Error, Result,
 };
@@ -33,6 +39,27 @@ struct UploadProfileInput {
     pub output_storage_location_id: StorageLocationId,
     pub output_storage_location_path: Option<String>,
     pub conversion_profile_id: ConversionProfileId,
+    #[serde(default)]
+    pub require_signed_urls: bool,
+    pub signed_url_ttl_seconds: Option<i32>,
+    pub allowed_countries: Option<Vec<String>>,
+    pub blocked_countries: Option<Vec<String>>,
+    pub cache_control: Option<String>,
+    #[serde(default = "default_save_data_enabled")]
+    pub save_data_enabled: bool,
+    pub save_data_quality: Option<i32>,
+    pub format_fallbacks: Option<FormatFallbacks>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    pub constraints: Option<ImageConstraints>,
+    pub original_retention: Option<OriginalRetention>,
+    #[serde(default)]
+    pub output_mirror_location_ids: Vec<StorageLocationId>,
+    pub post_process_callback: Option<PostProcessCallback>,
+}
+
+fn default_save_data_enabled() -> bool {
+    true
 }
 
 #[derive(Debug, Serialize, Queryable, Selectable)]
@@ -46,6 +73,206 @@ struct UploadProfileOutput {
     pub output_storage_location_id: StorageLocationId,
     pub output_storage_location_path: Option<String>,
     pub conversion_profile_id: ConversionProfileId,
+    pub require_signed_urls: bool,
+    pub signed_url_ttl_seconds: Option<i32>,
+    pub allowed_countries: Option<Vec<String>>,
+    pub blocked_countries: Option<Vec<String>>,
+    pub cache_control: Option<String>,
+    pub save_data_enabled: bool,
+    pub save_data_quality: Option<i32>,
+    pub format_fallbacks: Option<FormatFallbacks>,
+    pub labels: Vec<String>,
+    pub constraints: Option<ImageConstraints>,
+    pub original_retention: Option<OriginalRetention>,
+    pub output_mirror_location_ids: Vec<StorageLocationId>,
+    pub post_process_callback: Option<PostProcessCallback>,
+}
+
+/// Make sure that a Cache-Control value can be sent as a header. Empty values are treated as
+/// unset.
+fn validate_cache_control(value: Option<String>) -> Result<Option<String>> {
+    let Some(value) = value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+    else {
+        return Ok(None);
+    };
+
+    HeaderValue::from_str(&value).map_err(|_| Error::InvalidCacheControl)?;
+    Ok(Some(value))
+}
+
+fn validate_save_data_quality(value: Option<i32>) -> Result<Option<i32>> {
+    match value {
+        Some(q) if !(1..=100).contains(&q) => Err(Error::InvalidSaveDataQuality),
+        q => Ok(q),
+    }
+}
+
+/// Check that a fallback chain can be encoded. An empty chain is treated as unset.
+fn validate_format_fallbacks(value: Option<FormatFallbacks>) -> Result<Option<FormatFallbacks>> {
+    let Some(value) = value.filter(|v| !v.0.is_empty()) else {
+        return Ok(None);
+    };
+
+    for (i, fallback) in value.0.iter().enumerate() {
+        if fallback.format == ImageFormat::Heic {
+            return Err(Error::InvalidFormatFallbacks(
+                "HEIC output is not supported",
+            ));
+        }
+
+        if value.0[..i].iter().any(|f| f.format == fallback.format) {
+            return Err(Error::InvalidFormatFallbacks(
+                "formats can only appear once",
+            ));
+        }
+
+        if fallback.max_effort.unwrap_or(0) > pic_store_convert::write_format::MAX_EFFORT {
+            return Err(Error::InvalidFormatFallbacks(
+                "max_effort must be between 0 and 10",
+            ));
+        }
+    }
+
+    Ok(Some(value))
+}
+
+/// Check that the constraints can be met by some image. Empty constraints are treated as unset.
+fn validate_constraints(value: Option<ImageConstraints>) -> Result<Option<ImageConstraints>> {
+    let Some(value) = value.filter(|v| !v.is_empty()) else {
+        return Ok(None);
+    };
+
+    let out_of_order = |min: Option<u32>, max: Option<u32>| {
+        min.zip(max).map(|(min, max)| min > max).unwrap_or(false)
+    };
+    if out_of_order(value.min_width, value.max_width)
+        || out_of_order(value.min_height, value.max_height)
+    {
+        return Err(Error::InvalidImageConstraints(
+            "minimum dimensions can not be larger than the maximum",
+        ));
+    }
+
+    if value
+        .aspect_ratios
+        .iter()
+        .any(|ratio| ratio.width == 0 || ratio.height == 0)
+    {
+        return Err(Error::InvalidImageConstraints(
+            "aspect ratios must have a non-zero width and height",
+        ));
+    }
+
+    if value
+        .aspect_ratio_tolerance
+        .map(|t| !(0.0..1.0).contains(&t))
+        .unwrap_or(false)
+    {
+        return Err(Error::InvalidImageConstraints(
+            "aspect_ratio_tolerance must be at least 0 and less than 1",
+        ));
+    }
+
+    Ok(Some(value))
+}
+
+fn validate_original_retention(
+    value: Option<OriginalRetention>,
+    base_storage_location_id: StorageLocationId,
+) -> Result<Option<OriginalRetention>> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+
+    if value.after_days() == 0 {
+        return Err(Error::InvalidOriginalRetention(
+            "after_days must be at least 1",
+        ));
+    }
+
+    if value.archive_location() == Some(base_storage_location_id) {
+        return Err(Error::InvalidOriginalRetention(
+            "originals can not be archived to the storage location they are already in",
+        ));
+    }
+
+    Ok(Some(value))
+}
+
+/// The most mirrors that a profile can copy its output images to.
+const MAX_OUTPUT_MIRRORS: usize = 4;
+
+fn validate_output_mirrors(
+    value: Vec<StorageLocationId>,
+    output_storage_location_id: StorageLocationId,
+) -> Result<Vec<StorageLocationId>> {
+    if value.len() > MAX_OUTPUT_MIRRORS {
+        return Err(Error::InvalidOutputMirrors(
+            "a profile can have at most 4 mirrors",
+        ));
+    }
+
+    if value.contains(&output_storage_location_id) {
+        return Err(Error::InvalidOutputMirrors(
+            "the output storage location can not also be a mirror",
+        ));
+    }
+
+    if value
+        .iter()
+        .enumerate()
+        .any(|(i, id)| value[..i].contains(id))
+    {
+        return Err(Error::InvalidOutputMirrors("mirrors can only appear once"));
+    }
+
+    Ok(value)
+}
+
+/// The longest that a post-processing callback can take, since the conversion worker waits for
+/// it.
+const MAX_CALLBACK_TIMEOUT_SECONDS: u32 = 60;
+
+async fn validate_post_process_callback(
+    state: &AppState,
+    value: Option<PostProcessCallback>,
+) -> Result<Option<PostProcessCallback>> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+
+    let url = reqwest::Url::parse(&value.url)
+        .map_err(|_| Error::InvalidPostProcessCallback("the URL is not valid"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(Error::InvalidPostProcessCallback(
+            "the URL must use http or https",
+        ));
+    }
+
+    if !url.username().is_empty() || url.password().is_some() {
+        return Err(Error::InvalidPostProcessCallback(
+            "the URL can not contain credentials",
+        ));
+    }
+
+    remote_fetch::check_address(&url, state.url_fetch_policy.allow_private_networks)
+        .await
+        .map_err(|e| match e {
+            Error::ForbiddenRemoteAddress => Error::InvalidPostProcessCallback(
+                "the URL leads to an address that can not be called",
+            ),
+            _ => Error::InvalidPostProcessCallback("the URL's host could not be resolved"),
+        })?;
+
+    if !(1..=MAX_CALLBACK_TIMEOUT_SECONDS).contains(&value.timeout_seconds) {
+        return Err(Error::InvalidPostProcessCallback(
+            "timeout_seconds must be between 1 and 60",
+        ));
+    }
+
+    Ok(Some(value))
 }
 
 async fn list_project_upload_profiles(
@@ -77,13 +304,13 @@ async fn get_project_upload_profile(
         user,
         UploadProfileOutput,
         profile_id,
-        db::role_permissions::Permission::ProjectRead
+        db::role_permissions::Permission::UploadProfileRead
     )
     .await?;
 
     if !allowed {
         return Err(Error::MissingPermission(
-            db::role_permissions::Permission::ProjectRead,
+            db::role_permissions::Permission::UploadProfileRead,
         ));
     }
 
@@ -96,6 +323,22 @@ async fn write_project_upload_profile(
     Path((project_id, profile_id)): Path<(ProjectId, UploadProfileId)>,
     Json(body): Json<UploadProfileInput>,
 ) -> Result<impl IntoResponse> {
+    let allowed_countries = geo::normalize_countries(body.allowed_countries)?;
+    let blocked_countries = geo::normalize_countries(body.blocked_countries)?;
+    let cache_control = validate_cache_control(body.cache_control)?;
+    let save_data_quality = validate_save_data_quality(body.save_data_quality)?;
+    let format_fallbacks = validate_format_fallbacks(body.format_fallbacks)?;
+    let labels = labels::normalize_labels(body.labels)?;
+    let constraints = validate_constraints(body.constraints)?;
+    let original_retention =
+        validate_original_retention(body.original_retention, body.base_storage_location_id)?;
+    let output_mirror_location_ids = validate_output_mirrors(
+        body.output_mirror_location_ids,
+        body.output_storage_location_id,
+    )?;
+    let post_process_callback =
+        validate_post_process_callback(&state, body.post_process_callback).await?;
+
     let result = write_object!(
         upload_profiles,
         state,
@@ -103,7 +346,7 @@ async fn write_project_upload_profile(
         profile_id,
         project_id,
         UploadProfileOutput,
-        ProjectPermission::ProjectWrite,
+        ProjectPermission::UploadProfileWrite,
         (
             dsl::name.eq(body.name),
             dsl::updated.eq(Utc::now()),
@@ -112,6 +355,19 @@ async fn write_project_upload_profile(
             dsl::base_storage_location_path.eq(body.base_storage_location_path),
             dsl::output_storage_location_id.eq(body.output_storage_location_id),
             dsl::output_storage_location_path.eq(body.output_storage_location_path),
+            dsl::require_signed_urls.eq(body.require_signed_urls),
+            dsl::signed_url_ttl_seconds.eq(body.signed_url_ttl_seconds),
+            dsl::allowed_countries.eq(allowed_countries),
+            dsl::blocked_countries.eq(blocked_countries),
+            dsl::cache_control.eq(cache_control),
+            dsl::save_data_enabled.eq(body.save_data_enabled),
+            dsl::save_data_quality.eq(save_data_quality),
+            dsl::format_fallbacks.eq(format_fallbacks),
+            dsl::labels.eq(labels),
+            dsl::constraints.eq(constraints),
+            dsl::original_retention.eq(original_retention),
+            dsl::output_mirror_location_ids.eq(output_mirror_location_ids),
+            dsl::post_process_callback.eq(post_process_callback),
         )
     )
     .await?;
@@ -136,6 +392,29 @@ async fn new_project_upload_profile(
         output_storage_location_id: payload.output_storage_location_id,
         output_storage_location_path: payload.output_storage_location_path,
         conversion_profile_id: payload.conversion_profile_id,
+        require_signed_urls: payload.require_signed_urls,
+        signed_url_ttl_seconds: payload.signed_url_ttl_seconds,
+        allowed_countries: geo::normalize_countries(payload.allowed_countries)?,
+        blocked_countries: geo::normalize_countries(payload.blocked_countries)?,
+        cache_control: validate_cache_control(payload.cache_control)?,
+        save_data_enabled: payload.save_data_enabled,
+        save_data_quality: validate_save_data_quality(payload.save_data_quality)?,
+        format_fallbacks: validate_format_fallbacks(payload.format_fallbacks)?,
+        labels: labels::normalize_labels(payload.labels)?,
+        constraints: validate_constraints(payload.constraints)?,
+        original_retention: validate_original_retention(
+            payload.original_retention,
+            payload.base_storage_location_id,
+        )?,
+        output_mirror_location_ids: validate_output_mirrors(
+            payload.output_mirror_location_ids,
+            payload.output_storage_location_id,
+        )?,
+        post_process_callback: validate_post_process_callback(
+            &state,
+            payload.post_process_callback,
+        )
+        .await?,
         project_id,
         team_id: user.team_id,
     };
@@ -146,7 +425,7 @@ async fn new_project_upload_profile(
         user,
         project_id,
         UploadProfileOutput,
-        ProjectPermission::ProjectWrite,
+        ProjectPermission::UploadProfileWrite,
         &value
     )
     .await?;
@@ -154,6 +433,129 @@ async fn new_project_upload_profile(
     Ok((StatusCode::OK, Json(result)))
 }
 
+/// The metadata of a file that a client is about to upload. Anything that isn't known yet can be
+/// left out, and is not checked.
+#[derive(Debug, Deserialize)]
+struct ValidateUploadInput {
+    size: Option<usize>,
+    content_type: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct ValidateUploadOutput {
+    accepted: bool,
+    violations: Vec<ConstraintViolation>,
+}
+
+/// The formats that can be uploaded.
+const UPLOAD_FORMATS: [ImageFormat; 4] = [
+    ImageFormat::Png,
+    ImageFormat::Jpg,
+    ImageFormat::Webp,
+    ImageFormat::Avif,
+];
+
+/// Check whether an upload with the given metadata would be accepted, so that clients can reject
+/// files before sending them.
+async fn validate_project_upload(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path((project_id, profile_id)): Path<(ProjectId, UploadProfileId)>,
+    Json(body): Json<ValidateUploadInput>,
+) -> Result<impl IntoResponse> {
+    if user
+        .bound_upload_profile_id
+        .map(|bound| bound != profile_id)
+        .unwrap_or(false)
+    {
+        return Err(Error::ApiKeyRestricted);
+    }
+
+    let (constraints, policy_violation) = state
+        .db
+        .interact(move |conn| {
+            must_have_permission_on_project(
+                conn,
+                &user,
+                project_id,
+                ProjectPermission::ImageCreate,
+            )?;
+
+            let constraints = upload_profiles::table
+                .filter(upload_profiles::id.eq(profile_id))
+                .filter(upload_profiles::project_id.eq(project_id))
+                .filter(upload_profiles::team_id.eq(user.team_id))
+                .filter(upload_profiles::deleted.is_null())
+                .select(upload_profiles::constraints)
+                .first::<Option<ImageConstraints>>(conn)
+                .optional()?
+                .ok_or(Error::NotFound)?;
+
+            let policy_violation = match labels::check_upload(conn, user.team_id, profile_id) {
+                Ok(()) => None,
+                Err(Error::LabelPolicyViolation(label)) => Some(label),
+                Err(e) => return Err(e),
+            };
+
+            Ok((constraints, policy_violation))
+        })
+        .await?;
+
+    let mut violations = Vec::new();
+    if let Some(label) = policy_violation {
+        violations.push(ConstraintViolation {
+            constraint: "label_policy",
+            message: format!("uploads are not allowed by the policy for the label {label}"),
+        });
+    }
+
+    if let Some(size) = body.size.filter(|size| *size > state.max_upload_size) {
+        violations.push(ConstraintViolation {
+            constraint: "max_size",
+            message: format!(
+                "size {size} is larger than the maximum of {}",
+                state.max_upload_size
+            ),
+        });
+    }
+
+    if let Some(value) = body.content_type.as_deref() {
+        let mime = value.split(';').next().unwrap_or_default().trim();
+        let supported = UPLOAD_FORMATS
+            .iter()
+            .any(|format| content_type(*format).eq_ignore_ascii_case(mime));
+        if !supported {
+            violations.push(ConstraintViolation {
+                constraint: "content_type",
+                message: format!("{mime} is not a supported image type"),
+            });
+        }
+    }
+
+    if let (Some(width), Some(height)) = (body.width, body.height) {
+        if let Err(e) = state.decode_limits.check(width, height) {
+            violations.push(ConstraintViolation {
+                constraint: "image_limits",
+                message: e.to_string(),
+            });
+        }
+
+        if let Some(constraints) = constraints.as_ref() {
+            violations.extend(constraints.check(width, height));
+        }
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(ValidateUploadOutput {
+            accepted: violations.is_empty(),
+            violations,
+        }),
+    ))
+}
+
 async fn disable_project_upload_profile(
     State(state): State<AppState>,
     Authenticated(user): Authenticated,
@@ -165,7 +567,7 @@ async fn disable_project_upload_profile(
         user,
         profile_id,
         project_id,
-        ProjectPermission::ProjectWrite
+        ProjectPermission::UploadProfileWrite
     )
     .await?;
 
@@ -181,6 +583,10 @@ pub fn configure() -> Router<AppState> {
         .route(
             "/:upload_profile_id",
             delete(disable_project_upload_profile),
+        )
+        .route(
+            "/:upload_profile_id/validate",
+            post(validate_project_upload),
         );
 
     Router::new().nest("/projects/:project_id/upload_profiles", project_routes)
@@ -0,0 +1,498 @@
+//! The team's webhooks. The signing secret is only returned when a webhook is created.
+//!
+//! A webhook receives every event unless it lists the `event_types` or `project_ids` that it
+//! wants. See [crate::webhooks] for the payload templates.
+//!
+//! Instance admins can limit the hosts that a team's webhooks send to. The URL is checked against
+//! the allowlist when the webhook is saved, and again before each delivery.
+//!
+//! Deliveries are kept for 30 days, along with the response to each attempt to send them, so that
+//! a receiver's failures can be debugged and the events it missed sent again.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post, put},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use db::{
+    credentials,
+    object_id::{ProjectId, TeamId, WebhookDeliveryId, WebhookId},
+    permissions::GlobalPermission,
+    projects,
+    webhooks::{
+        self, webhook_deliveries, webhook_delivery_attempts, NewWebhook, WebhookDelivery,
+        WebhookDeliveryAttempt,
+    },
+    PoolExt, WebhookDeliveryStatus,
+};
+use diesel::prelude::*;
+use pic_store_db as db;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    auth::{must_have_global_permission, Authenticated},
+    json::Json,
+    remote_fetch,
+    shared_state::AppState,
+    webhooks::{check_allowlist, generate_secret, validate_template, EVENT_TYPES},
+    Error, Result,
+};
+
+#[derive(Debug, Deserialize)]
+struct WebhookInput {
+    url: String,
+    description: Option<String>,
+    #[serde(default)]
+    event_types: Vec<String>,
+    #[serde(default)]
+    project_ids: Vec<ProjectId>,
+    payload_template: Option<String>,
+}
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = webhooks)]
+struct WebhookOutput {
+    id: WebhookId,
+    url: String,
+    description: Option<String>,
+    event_types: Vec<String>,
+    project_ids: Vec<ProjectId>,
+    payload_template: Option<String>,
+    updated: DateTime<Utc>,
+}
+
+/// The most deliveries to return in a list.
+const MAX_DELIVERIES: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+struct ListDeliveriesQuery {
+    status: Option<WebhookDeliveryStatus>,
+    /// Only return deliveries created before this time, for paging through older deliveries.
+    before: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+struct DeliveryOutput {
+    id: WebhookDeliveryId,
+    event_type: String,
+    project_id: Option<ProjectId>,
+    status: WebhookDeliveryStatus,
+    attempts: i32,
+    /// When the next attempt will be made, for pending deliveries.
+    next_attempt: Option<DateTime<Utc>>,
+    /// The status code from the most recent attempt.
+    response_status: Option<i32>,
+    error: Option<String>,
+    created: DateTime<Utc>,
+    delivered: Option<DateTime<Utc>>,
+}
+
+impl From<WebhookDelivery> for DeliveryOutput {
+    fn from(value: WebhookDelivery) -> Self {
+        DeliveryOutput {
+            id: value.id,
+            event_type: value.event_type,
+            project_id: value.project_id,
+            status: value.status,
+            attempts: value.attempts,
+            next_attempt: (value.status == WebhookDeliveryStatus::Pending)
+                .then_some(value.next_attempt),
+            response_status: value.response_status,
+            error: value.error,
+            created: value.created,
+            delivered: value.delivered,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AttemptOutput {
+    attempt: i32,
+    response_status: Option<i32>,
+    error: Option<String>,
+    created: DateTime<Utc>,
+}
+
+impl From<WebhookDeliveryAttempt> for AttemptOutput {
+    fn from(value: WebhookDeliveryAttempt) -> Self {
+        AttemptOutput {
+            attempt: value.attempt,
+            response_status: value.response_status,
+            error: value.error,
+            created: value.created,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DeliveryDetailOutput {
+    #[serde(flatten)]
+    delivery: DeliveryOutput,
+    /// The standard event. Webhooks with a payload template receive the rendered template.
+    payload: serde_json::Value,
+    /// Each attempt to send the delivery, oldest first.
+    attempt_history: Vec<AttemptOutput>,
+}
+
+/// Check that the webhook belongs to the team and hasn't been deleted.
+fn must_own_webhook(conn: &mut PgConnection, team_id: TeamId, webhook_id: WebhookId) -> Result<()> {
+    let found = diesel::select(diesel::dsl::exists(
+        webhooks::table
+            .filter(webhooks::id.eq(webhook_id))
+            .filter(webhooks::team_id.eq(team_id))
+            .filter(webhooks::deleted.is_null()),
+    ))
+    .get_result::<bool>(conn)?;
+
+    if found {
+        Ok(())
+    } else {
+        Err(Error::ObjectNotFound("webhook"))
+    }
+}
+
+/// Check the event types and template. The projects are checked by [check_projects] since that
+/// needs the database.
+fn validate_filters(input: &mut WebhookInput) -> Result<()> {
+    input.event_types.sort();
+    input.event_types.dedup();
+    if input
+        .event_types
+        .iter()
+        .any(|t| !EVENT_TYPES.contains(&t.as_str()))
+    {
+        return Err(Error::InvalidWebhook(
+            "event_types has an unknown event type",
+        ));
+    }
+
+    if input
+        .payload_template
+        .as_deref()
+        .is_some_and(|t| t.trim().is_empty())
+    {
+        input.payload_template = None;
+    }
+
+    if let Some(template) = &input.payload_template {
+        validate_template(template, &input.event_types)?;
+    }
+
+    input.project_ids.sort();
+    input.project_ids.dedup();
+    Ok(())
+}
+
+/// Check that the projects belong to the team.
+fn check_projects(
+    conn: &mut PgConnection,
+    team_id: TeamId,
+    project_ids: &[ProjectId],
+) -> Result<()> {
+    if project_ids.is_empty() {
+        return Ok(());
+    }
+
+    let found = projects::table
+        .filter(projects::id.eq_any(project_ids))
+        .filter(projects::team_id.eq(team_id))
+        .filter(projects::deleted.is_null())
+        .count()
+        .get_result::<i64>(conn)?;
+    if found as usize != project_ids.len() {
+        return Err(Error::ObjectNotFound("project"));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct NewWebhookOutput {
+    #[serde(flatten)]
+    webhook: WebhookOutput,
+    /// The secret for verifying the signatures. This is only returned once.
+    secret: String,
+}
+
+/// Check that the URL can be called. The team's allowlist is checked separately by
+/// [check_allowlist], since that needs the database.
+async fn validate_url(state: &AppState, url: &str) -> Result<reqwest::Url> {
+    let url = remote_fetch::parse_url(url)
+        .map_err(|_| Error::InvalidWebhook("the URL must be a valid http or https URL"))?;
+
+    remote_fetch::check_address(&url, state.url_fetch_policy.allow_private_networks)
+        .await
+        .map_err(|e| match e {
+            Error::ForbiddenRemoteAddress => {
+                Error::InvalidWebhook("the URL leads to an address that can not be called")
+            }
+            _ => Error::InvalidWebhook("the URL's host could not be resolved"),
+        })?;
+
+    Ok(url)
+}
+
+async fn list_webhooks(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+) -> Result<impl IntoResponse> {
+    let objects = state
+        .db
+        .interact(move |conn| {
+            must_have_global_permission(conn, &user, GlobalPermission::TeamAdmin)?;
+
+            webhooks::table
+                .filter(webhooks::team_id.eq(user.team_id))
+                .filter(webhooks::deleted.is_null())
+                .select(WebhookOutput::as_select())
+                .order(webhooks::id.asc())
+                .load(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(objects)))
+}
+
+async fn get_webhook(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(webhook_id): Path<WebhookId>,
+) -> Result<impl IntoResponse> {
+    let object = state
+        .db
+        .interact(move |conn| {
+            must_have_global_permission(conn, &user, GlobalPermission::TeamAdmin)?;
+
+            webhooks::table
+                .filter(webhooks::id.eq(webhook_id))
+                .filter(webhooks::team_id.eq(user.team_id))
+                .filter(webhooks::deleted.is_null())
+                .select(WebhookOutput::as_select())
+                .first(conn)
+                .optional()?
+                .ok_or(Error::ObjectNotFound("webhook"))
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(object)))
+}
+
+async fn new_webhook(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Json(mut body): Json<WebhookInput>,
+) -> Result<impl IntoResponse> {
+    validate_filters(&mut body)?;
+    let url = validate_url(&state, &body.url).await?;
+    let secret = generate_secret();
+    let encrypted_secret = credentials::encrypt(&secret)?;
+
+    let webhook = state
+        .db
+        .interact(move |conn| {
+            must_have_global_permission(conn, &user, GlobalPermission::TeamAdmin)?;
+            check_projects(conn, user.team_id, &body.project_ids)?;
+            check_allowlist(conn, user.team_id, &url)?;
+
+            diesel::insert_into(webhooks::table)
+                .values(NewWebhook {
+                    id: WebhookId::new(),
+                    team_id: user.team_id,
+                    url: url.to_string(),
+                    secret: encrypted_secret,
+                    description: body.description,
+                    event_types: body.event_types,
+                    project_ids: body.project_ids,
+                    payload_template: body.payload_template,
+                })
+                .returning(WebhookOutput::as_select())
+                .get_result(conn)
+                .map_err(Error::from)
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(NewWebhookOutput { webhook, secret })))
+}
+
+async fn write_webhook(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(webhook_id): Path<WebhookId>,
+    Json(mut body): Json<WebhookInput>,
+) -> Result<impl IntoResponse> {
+    validate_filters(&mut body)?;
+    let url = validate_url(&state, &body.url).await?;
+
+    let webhook = state
+        .db
+        .interact(move |conn| {
+            must_have_global_permission(conn, &user, GlobalPermission::TeamAdmin)?;
+            check_projects(conn, user.team_id, &body.project_ids)?;
+            check_allowlist(conn, user.team_id, &url)?;
+
+            diesel::update(webhooks::table)
+                .filter(webhooks::id.eq(webhook_id))
+                .filter(webhooks::team_id.eq(user.team_id))
+                .filter(webhooks::deleted.is_null())
+                .set((
+                    webhooks::url.eq(url.to_string()),
+                    webhooks::description.eq(body.description),
+                    webhooks::event_types.eq(body.event_types),
+                    webhooks::project_ids.eq(body.project_ids),
+                    webhooks::payload_template.eq(body.payload_template),
+                    webhooks::updated.eq(Utc::now()),
+                ))
+                .returning(WebhookOutput::as_select())
+                .get_result(conn)
+                .optional()?
+                .ok_or(Error::ObjectNotFound("webhook"))
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(webhook)))
+}
+
+async fn delete_webhook(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(webhook_id): Path<WebhookId>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .interact(move |conn| {
+            must_have_global_permission(conn, &user, GlobalPermission::TeamAdmin)?;
+
+            diesel::update(webhooks::table)
+                .filter(webhooks::id.eq(webhook_id))
+                .filter(webhooks::team_id.eq(user.team_id))
+                .filter(webhooks::deleted.is_null())
+                .set(webhooks::deleted.eq(Some(Utc::now())))
+                .execute(conn)?;
+
+            Ok::<_, Error>(())
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(json!({}))))
+}
+
+/// The webhook's deliveries, newest first.
+async fn list_deliveries(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path(webhook_id): Path<WebhookId>,
+    Query(query): Query<ListDeliveriesQuery>,
+) -> Result<impl IntoResponse> {
+    let deliveries = state
+        .db
+        .interact(move |conn| {
+            must_have_global_permission(conn, &user, GlobalPermission::TeamAdmin)?;
+            must_own_webhook(conn, user.team_id, webhook_id)?;
+
+            let mut q = webhook_deliveries::table
+                .filter(webhook_deliveries::webhook_id.eq(webhook_id))
+                .select(WebhookDelivery::as_select())
+                .order(webhook_deliveries::created.desc())
+                .limit(MAX_DELIVERIES)
+                .into_boxed();
+            if let Some(status) = query.status {
+                q = q.filter(webhook_deliveries::status.eq(status));
+            }
+            if let Some(before) = query.before {
+                q = q.filter(webhook_deliveries::created.lt(before));
+            }
+
+            q.load(conn).map_err(Error::from)
+        })
+        .await?
+        .into_iter()
+        .map(DeliveryOutput::from)
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(deliveries)))
+}
+
+/// A delivery with its payload and the result of each attempt.
+async fn get_delivery(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path((webhook_id, delivery_id)): Path<(WebhookId, WebhookDeliveryId)>,
+) -> Result<impl IntoResponse> {
+    let (delivery, attempts) = state
+        .db
+        .interact(move |conn| {
+            must_have_global_permission(conn, &user, GlobalPermission::TeamAdmin)?;
+            must_own_webhook(conn, user.team_id, webhook_id)?;
+
+            let delivery = webhook_deliveries::table
+                .filter(webhook_deliveries::id.eq(delivery_id))
+                .filter(webhook_deliveries::webhook_id.eq(webhook_id))
+                .select(WebhookDelivery::as_select())
+                .first(conn)
+                .optional()?
+                .ok_or(Error::ObjectNotFound("webhook delivery"))?;
+
+            let attempts = webhook_delivery_attempts::table
+                .filter(webhook_delivery_attempts::delivery_id.eq(delivery_id))
+                .select(WebhookDeliveryAttempt::as_select())
+                .order(webhook_delivery_attempts::attempt.asc())
+                .load(conn)?;
+
+            Ok::<_, Error>((delivery, attempts))
+        })
+        .await?;
+
+    let payload = delivery.payload.clone();
+    Ok((
+        StatusCode::OK,
+        Json(DeliveryDetailOutput {
+            delivery: DeliveryOutput::from(delivery),
+            payload,
+            attempt_history: attempts.into_iter().map(AttemptOutput::from).collect(),
+        }),
+    ))
+}
+
+/// Send a delivery again right away, whether it was delivered or failed. It keeps its ID, so
+/// receivers can tell that it is the same event. If the attempt fails, it is retried only if the
+/// delivery hasn't used up its retries.
+async fn redeliver(
+    State(state): State<AppState>,
+    Authenticated(user): Authenticated,
+    Path((webhook_id, delivery_id)): Path<(WebhookId, WebhookDeliveryId)>,
+) -> Result<impl IntoResponse> {
+    let delivery = state
+        .db
+        .interact(move |conn| {
+            must_have_global_permission(conn, &user, GlobalPermission::TeamAdmin)?;
+
+            webhooks::redeliver(conn, user.team_id, webhook_id, delivery_id)?
+                .ok_or(Error::ObjectNotFound("webhook delivery"))
+        })
+        .await?;
+
+    Ok((StatusCode::OK, Json(DeliveryOutput::from(delivery))))
+}
+
+pub fn configure() -> Router<AppState> {
+    Router::new()
+        .route("/webhooks", get(list_webhooks))
+        .route("/webhooks", post(new_webhook))
+        .route("/webhooks/:webhook_id", get(get_webhook))
+        .route("/webhooks/:webhook_id", put(write_webhook))
+        .route("/webhooks/:webhook_id", delete(delete_webhook))
+        .route("/webhooks/:webhook_id/deliveries", get(list_deliveries))
+        .route(
+            "/webhooks/:webhook_id/deliveries/:delivery_id",
+            get(get_delivery),
+        )
+        .route(
+            "/webhooks/:webhook_id/deliveries/:delivery_id/redeliver",
+            post(redeliver),
+        )
+}
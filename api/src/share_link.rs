@@ -0,0 +1,297 @@
+//! Share links, which show the images in a collection on a gallery page to anyone holding the
+//! link. The page is rendered here, and its images use signed serve URLs so that they can be
+//! viewed even in a private project.
+//!
+//! A link can have a password. Viewers enter it on a form, and then get a cookie signed with the
+//! signed URL key so that they don't have to enter it again until the link expires.
+
+use axum::http::{header, HeaderMap, HeaderValue};
+use chrono::{DateTime, Utc};
+use pic_store_db::object_id::{BaseImageId, ShareLinkId};
+
+use crate::signed_url::{SignedParams, UrlSigner};
+
+/// How long a share link lasts when the request doesn't say.
+pub const DEFAULT_TTL_SECONDS: i64 = 7 * 24 * 60 * 60;
+/// The longest that a share link can last.
+pub const MAX_TTL_SECONDS: i64 = 90 * 24 * 60 * 60;
+/// The most images shown on a gallery page.
+pub const MAX_IMAGES: i64 = 500;
+/// The width of the images in the grid. Each one links to the full image.
+const THUMBNAIL_WIDTH: u32 = 480;
+
+/// Each link gets its own cookie, so a browser can have several links unlocked at once.
+pub fn cookie_name(share_link_id: ShareLinkId) -> String {
+    format!("pic_share_{share_link_id}")
+}
+
+/// Build the `Set-Cookie` header that unlocks a password-protected link. The cookie is only
+/// sent to the share pages.
+pub fn set_cookie_header(
+    share_link_id: ShareLinkId,
+    expires: i64,
+    signature: &str,
+    now: DateTime<Utc>,
+    secure: bool,
+) -> Option<HeaderValue> {
+    let max_age = (expires - now.timestamp()).max(0);
+    let secure = if secure { "; Secure" } else { "" };
+    HeaderValue::from_str(&format!(
+        "{}={expires}.{signature}; Max-Age={max_age}; Path=/share; HttpOnly; SameSite=Lax{secure}",
+        cookie_name(share_link_id)
+    ))
+    .ok()
+}
+
+/// Check whether the request has a valid cookie for the share link.
+pub fn verify_cookie(
+    headers: &HeaderMap,
+    signer: &UrlSigner,
+    share_link_id: ShareLinkId,
+    now: DateTime<Utc>,
+) -> bool {
+    let name = cookie_name(share_link_id);
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .filter(|(key, _)| *key == name)
+        .any(|(_, value)| {
+            let Some((expires, signature)) = value.split_once('.') else {
+                return false;
+            };
+            let Ok(expires) = expires.parse::<i64>() else {
+                return false;
+            };
+            signer
+                .verify_share(share_link_id, expires, signature, now)
+                .is_ok()
+        })
+}
+
+/// When the image URLs on a gallery page expire. The URLs stay the same for at least an hour so
+/// that browsers and caches can reuse them, and stop working within two hours of the link being
+/// revoked.
+pub fn image_urls_expire(link_expires: DateTime<Utc>, now: DateTime<Utc>) -> i64 {
+    let hour = 60 * 60;
+    let next_hour = (now.timestamp() / hour + 2) * hour;
+    next_hour.min(link_expires.timestamp())
+}
+
+/// An image shown on the gallery page.
+#[derive(Debug)]
+pub struct GalleryImage {
+    pub thumbnail_url: String,
+    pub url: String,
+    pub alt_text: String,
+}
+
+impl GalleryImage {
+    pub fn new(signer: &UrlSigner, id: BaseImageId, alt_text: String, expires: i64) -> Self {
+        let thumbnail = SignedParams {
+            width: Some(THUMBNAIL_WIDTH),
+            ..Default::default()
+        };
+        let thumbnail_signature = signer.sign(id, expires, &thumbnail);
+        let signature = signer.sign(id, expires, &SignedParams::default());
+        GalleryImage {
+            thumbnail_url: format!(
+                "/serve/{id}?expires={expires}&signature={thumbnail_signature}{}",
+                thumbnail.query_string()
+            ),
+            url: format!("/serve/{id}?expires={expires}&signature={signature}"),
+            alt_text,
+        }
+    }
+}
+
+/// Escape text for use in HTML content or a quoted attribute.
+pub fn escape_html(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="robots" content="noindex">
+<title>{title}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; margin: 2rem; }}
+.grid {{ display: grid; grid-template-columns: repeat(auto-fill, minmax(240px, 1fr)); gap: 1rem; }}
+.grid img {{ width: 100%; height: auto; display: block; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+{body}
+</body>
+</html>
+"#,
+        title = escape_html(title),
+    )
+}
+
+/// Render the gallery page for a share link.
+pub fn render_gallery(title: &str, images: &[GalleryImage]) -> String {
+    if images.is_empty() {
+        return page(title, "<p>There are no images here yet.</p>");
+    }
+
+    let items = images
+        .iter()
+        .map(|image| {
+            format!(
+                r#"<a href="{url}"><img src="{thumbnail_url}" alt="{alt}" loading="lazy"></a>"#,
+                url = escape_html(&image.url),
+                thumbnail_url = escape_html(&image.thumbnail_url),
+                alt = escape_html(&image.alt_text),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    page(title, &format!("<div class=\"grid\">\n{items}\n</div>"))
+}
+
+/// Render the form that asks for a share link's password. The form posts back to the same URL.
+pub fn render_password_form(title: &str, incorrect: bool) -> String {
+    let error = if incorrect {
+        "<p>The password is incorrect.</p>\n"
+    } else {
+        ""
+    };
+    page(
+        title,
+        &format!(
+            r#"{error}<form method="post">
+<label>Password <input type="password" name="password" autofocus required></label>
+<button type="submit">View</button>
+</form>"#
+        ),
+    )
+}
+
+/// The title shown on the page, which defaults to the collection's name.
+pub fn page_title<'a>(title: Option<&'a str>, collection: &'a str) -> &'a str {
+    title
+        .filter(|title| !title.is_empty())
+        .unwrap_or(collection)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    fn cookie_headers(cookie: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, HeaderValue::from_str(cookie).unwrap());
+        headers
+    }
+
+    #[test]
+    fn cookie() {
+        let signer = UrlSigner::new("secret");
+        let id = ShareLinkId::new();
+        let now = Utc::now();
+        let expires = (now + Duration::hours(1)).timestamp();
+        let signature = signer.sign_share(id, expires);
+
+        let headers = cookie_headers(&format!("{}={expires}.{signature}", cookie_name(id)));
+        assert!(verify_cookie(&headers, &signer, id, now));
+        assert!(!verify_cookie(&headers, &signer, ShareLinkId::new(), now));
+        assert!(!verify_cookie(
+            &headers,
+            &signer,
+            id,
+            now + Duration::hours(2)
+        ));
+
+        let malformed = cookie_headers(&format!("{}=abc", cookie_name(id)));
+        assert!(!verify_cookie(&malformed, &signer, id, now));
+
+        let header = set_cookie_header(id, expires, &signature, now, true).unwrap();
+        assert_eq!(
+            header.to_str().unwrap(),
+            format!(
+                "{}={expires}.{signature}; Max-Age=3600; Path=/share; HttpOnly; SameSite=Lax; Secure",
+                cookie_name(id)
+            )
+        );
+    }
+
+    #[test]
+    fn image_url_expiration() {
+        let now = Utc.timestamp_opt(10 * 3600 + 120, 0).unwrap();
+        let later = now + Duration::days(1);
+        assert_eq!(image_urls_expire(later, now), 12 * 3600);
+
+        let soon = now + Duration::minutes(5);
+        assert_eq!(image_urls_expire(soon, now), soon.timestamp());
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn gallery_page() {
+        let signer = UrlSigner::new("secret");
+        let id = BaseImageId::new();
+        let image = GalleryImage::new(&signer, id, "A \"quoted\" <b>cat</b>".to_string(), 100);
+        let html = render_gallery("Trip <2026>", &[image]);
+
+        assert!(html.contains("<title>Trip &lt;2026&gt;</title>"));
+        assert!(html.contains(r#"alt="A &quot;quoted&quot; &lt;b&gt;cat&lt;/b&gt;""#));
+        assert!(html.contains(&format!(
+            "/serve/{id}?expires=100&amp;signature={}&amp;width=480",
+            signer.sign(
+                id,
+                100,
+                &SignedParams {
+                    width: Some(480),
+                    ..Default::default()
+                }
+            )
+        )));
+
+        assert!(render_gallery("Empty", &[]).contains("no images"));
+    }
+
+    #[test]
+    fn password_page() {
+        let html = render_password_form("Trip", false);
+        assert!(html.contains(r#"name="password""#));
+        assert!(!html.contains("incorrect"));
+        assert!(render_password_form("Trip", true).contains("incorrect"));
+    }
+
+    #[test]
+    fn titles() {
+        assert_eq!(page_title(Some("Trip"), "trip-2026"), "Trip");
+        assert_eq!(page_title(Some(""), "trip-2026"), "trip-2026");
+        assert_eq!(page_title(None, "trip-2026"), "trip-2026");
+    }
+}
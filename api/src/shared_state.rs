@@ -10,6 +10,49 @@ pub struct InnerState {
     pub production: bool,
     pub db: db::Pool,
     pub queue: effectum::Queue,
+    pub decode_limits: pic_store_convert::DecodeLimits,
+    /// Signs URLs for the serve route. Signed URLs are disabled if this is not set.
+    pub url_signer: Option<crate::signed_url::UrlSigner>,
+    /// Checks the signatures on imgproxy-style URLs. Any signature is accepted if this is not set.
+    pub imgproxy_key: Option<crate::routes::imgproxy::ImgproxyKey>,
+    /// The header that contains the viewer's country, for geo-restricted upload profiles.
+    pub geo_country_header: http::HeaderName,
+    /// Allow storage locations on the local filesystem.
+    pub allow_local_fs: bool,
+    /// The directory that local storage locations must be inside. Any directory is allowed if
+    /// this is not set.
+    pub local_storage_dir: Option<std::path::PathBuf>,
+    /// Cedar policies that every authenticated request must pass.
+    pub policy_engine: Option<crate::policy::PolicyEngine>,
+    /// Accept imgix query parameters on the serve route.
+    pub imgix_compat: bool,
+    /// Add Link preload headers for the selected variant to served images.
+    pub early_hints: bool,
+    /// Limits how many variants the serve route renders at once.
+    pub render_permits: tokio::sync::Semaphore,
+    /// The rate limits for each class of route.
+    pub rate_limiter: crate::rate_limit::RateLimiter,
+    /// Allow crawling sitemaps for the pages that use each image.
+    pub reference_crawler: bool,
+    /// Reject unknown fields in JSON request bodies, unless the request opts out.
+    pub strict_json: bool,
+    /// The largest original that can be uploaded.
+    pub max_upload_size: usize,
+    /// The part size for multipart uploads of originals.
+    pub upload_part_size: usize,
+    /// Limits on fetching originals for uploads from a URL.
+    pub url_fetch_policy: crate::remote_fetch::FetchPolicy,
+    /// Saves requests and responses for debugging. Never set in production.
+    pub request_recorder: Option<crate::recording::RequestRecorder>,
+    pub cdn_purger: crate::cdn_purge::CdnPurger,
+    /// Request counts for the API usage analytics.
+    pub api_usage: crate::api_usage::UsageRecorder,
+    /// Upload and serve traffic for the metering tables.
+    pub metering: crate::metering::MeteringRecorder,
+    /// The most recent report for the public status endpoint.
+    pub status_cache: crate::routes::status::StatusCache,
+    /// Conversion queue metrics and alerts.
+    pub queue_slo: crate::queue_slo::QueueSloMonitor,
 
     // Hardcoded values until we have real user auth and such.
     pub user_id: UserId,
@@ -0,0 +1,404 @@
+//! Signed URLs, which let anyone holding the URL fetch an image from the serve route until the
+//! URL expires. Upload profiles can require them, so that their images can only be fetched
+//! with a URL handed out through the API.
+//!
+//! The same key signs gallery cookies, which cover every image in a project, and the cookies that
+//! unlock password-protected share links. See [crate::gallery] and [crate::share_link].
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequestParts, Path, Query},
+    http::request::Parts,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use pic_store_db::{
+    object_id::{BaseImageId, ProjectId, ShareLinkId},
+    ImageFormat,
+};
+use serde::Deserialize;
+
+use crate::{shared_state::AppState, Error};
+
+/// How long a signed URL lasts when its upload profile doesn't say.
+pub const DEFAULT_TTL_SECONDS: i64 = 60 * 60;
+
+const KEY_CONTEXT: &str = "pic-store 2026-10-15 signed image URLs";
+
+pub struct UrlSigner {
+    key: [u8; 32],
+}
+
+impl UrlSigner {
+    pub fn new(secret: &str) -> Self {
+        UrlSigner {
+            key: blake3::derive_key(KEY_CONTEXT, secret.as_bytes()),
+        }
+    }
+
+    fn hash(&self, image_id: BaseImageId, expires: i64, params: &SignedParams) -> blake3::Hash {
+        blake3::keyed_hash(
+            &self.key,
+            format!("{image_id}:{expires}:{}", params.message()).as_bytes(),
+        )
+    }
+
+    /// Gallery signatures cover a whole project. Project and image IDs have different prefixes,
+    /// but the message is marked anyway so that the two kinds of signature can never match.
+    fn gallery_hash(&self, project_id: ProjectId, expires: i64) -> blake3::Hash {
+        blake3::keyed_hash(
+            &self.key,
+            format!("gallery:{project_id}:{expires}").as_bytes(),
+        )
+    }
+
+    /// Share link signatures show that the viewer entered the link's password.
+    fn share_hash(&self, share_link_id: ShareLinkId, expires: i64) -> blake3::Hash {
+        blake3::keyed_hash(
+            &self.key,
+            format!("share:{share_link_id}:{expires}").as_bytes(),
+        )
+    }
+
+    /// Create the signature for a URL to a variant of an image that expires at the given Unix
+    /// timestamp.
+    pub fn sign(&self, image_id: BaseImageId, expires: i64, params: &SignedParams) -> String {
+        self.hash(image_id, expires, params).to_hex().to_string()
+    }
+
+    /// Check that the signature is valid for the image and variant and has not expired.
+    pub fn verify(
+        &self,
+        image_id: BaseImageId,
+        expires: i64,
+        params: &SignedParams,
+        signature: &str,
+        now: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        check_signature(
+            self.hash(image_id, expires, params),
+            expires,
+            signature,
+            now,
+        )
+    }
+
+    /// Create the signature for access to every image in a project.
+    pub fn sign_gallery(&self, project_id: ProjectId, expires: i64) -> String {
+        self.gallery_hash(project_id, expires).to_hex().to_string()
+    }
+
+    /// Check that a gallery signature is valid for the project and has not expired.
+    pub fn verify_gallery(
+        &self,
+        project_id: ProjectId,
+        expires: i64,
+        signature: &str,
+        now: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        check_signature(
+            self.gallery_hash(project_id, expires),
+            expires,
+            signature,
+            now,
+        )
+    }
+
+    /// Create the signature for the cookie that unlocks a password-protected share link.
+    pub fn sign_share(&self, share_link_id: ShareLinkId, expires: i64) -> String {
+        self.share_hash(share_link_id, expires).to_hex().to_string()
+    }
+
+    /// Check that a share link cookie signature is valid and has not expired.
+    pub fn verify_share(
+        &self,
+        share_link_id: ShareLinkId,
+        expires: i64,
+        signature: &str,
+        now: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        check_signature(
+            self.share_hash(share_link_id, expires),
+            expires,
+            signature,
+            now,
+        )
+    }
+}
+
+fn check_signature(
+    expected: blake3::Hash,
+    expires: i64,
+    signature: &str,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    let signature = blake3::Hash::from_hex(signature)
+        .map_err(|_| Error::InvalidSignedUrl("malformed signature"))?;
+
+    // blake3::Hash compares in constant time.
+    if signature != expected {
+        return Err(Error::InvalidSignedUrl("bad signature"));
+    }
+
+    if expires <= now.timestamp() {
+        return Err(Error::InvalidSignedUrl("expired"));
+    }
+
+    Ok(())
+}
+
+impl std::fmt::Debug for UrlSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UrlSigner").finish_non_exhaustive()
+    }
+}
+
+/// The serve route parameters that a signature covers, so that the holder of a signed URL can't
+/// change them to create other variants of the image.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SignedParams {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub format: Option<ImageFormat>,
+    pub quality: Option<u8>,
+}
+
+impl SignedParams {
+    fn message(&self) -> String {
+        fn field<T: ToString>(value: Option<T>) -> String {
+            value.map(|v| v.to_string()).unwrap_or_default()
+        }
+
+        format!(
+            "{}:{}:{}:{}",
+            field(self.width),
+            field(self.height),
+            field(self.format.map(format_name)),
+            field(self.quality)
+        )
+    }
+
+    /// The parameters as query string arguments, each starting with `&`.
+    pub fn query_string(&self) -> String {
+        let mut query = String::new();
+        if let Some(width) = self.width {
+            query.push_str(&format!("&width={width}"));
+        }
+        if let Some(height) = self.height {
+            query.push_str(&format!("&height={height}"));
+        }
+        if let Some(format) = self.format {
+            query.push_str(&format!("&format={}", format_name(format)));
+        }
+        if let Some(quality) = self.quality {
+            query.push_str(&format!("&quality={quality}"));
+        }
+        query
+    }
+}
+
+fn format_name(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png => "png",
+        ImageFormat::Jpg => "jpg",
+        ImageFormat::Avif => "avif",
+        ImageFormat::Webp => "webp",
+        ImageFormat::Heic => "heic",
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SignatureQuery {
+    expires: Option<i64>,
+    signature: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    format: Option<ImageFormat>,
+    quality: Option<u8>,
+}
+
+/// Verifies the signature on a request to the serve route. Requests without a signature are
+/// allowed through, and [SignedUrl::expires] is `None` for them, since only some images
+/// require signed URLs. A request with an invalid or expired signature, or whose parameters
+/// differ from the signed ones, is rejected.
+#[derive(Debug, Default)]
+pub struct SignedUrl {
+    pub expires: Option<DateTime<Utc>>,
+    /// The parameters that the signature covers.
+    pub params: SignedParams,
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for SignedUrl {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Error> {
+        let Query(query) = Query::<SignatureQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| Error::InvalidSignedUrl("malformed query string"))?;
+
+        let (expires, signature) = match (query.expires, query.signature) {
+            (None, None) => return Ok(SignedUrl::default()),
+            (Some(expires), Some(signature)) => (expires, signature),
+            _ => {
+                return Err(Error::InvalidSignedUrl(
+                    "expires and signature are both required",
+                ))
+            }
+        };
+
+        let signer = state.url_signer.as_ref().ok_or(Error::SignedUrlsDisabled)?;
+        let Path(image_id) = Path::<BaseImageId>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| Error::NotFound)?;
+
+        let params = SignedParams {
+            width: query.width,
+            height: query.height,
+            format: query.format,
+            quality: query.quality,
+        };
+        signer.verify(image_id, expires, &params, &signature, Utc::now())?;
+
+        let expires = Utc
+            .timestamp_opt(expires, 0)
+            .single()
+            .ok_or(Error::InvalidSignedUrl("invalid expiration time"))?;
+        Ok(SignedUrl {
+            expires: Some(expires),
+            params,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn valid_signature() {
+        let signer = UrlSigner::new("secret");
+        let image_id = BaseImageId::new();
+        let params = SignedParams::default();
+        let now = Utc::now();
+        let expires = (now + Duration::minutes(5)).timestamp();
+
+        let signature = signer.sign(image_id, expires, &params);
+        signer
+            .verify(image_id, expires, &params, &signature, now)
+            .unwrap();
+    }
+
+    #[test]
+    fn expired_signature() {
+        let signer = UrlSigner::new("secret");
+        let image_id = BaseImageId::new();
+        let params = SignedParams::default();
+        let now = Utc::now();
+        let expires = (now - Duration::seconds(1)).timestamp();
+
+        let signature = signer.sign(image_id, expires, &params);
+        assert!(matches!(
+            signer.verify(image_id, expires, &params, &signature, now),
+            Err(Error::InvalidSignedUrl("expired"))
+        ));
+    }
+
+    #[test]
+    fn wrong_image_or_expiry() {
+        let signer = UrlSigner::new("secret");
+        let image_id = BaseImageId::new();
+        let params = SignedParams::default();
+        let now = Utc::now();
+        let expires = (now + Duration::minutes(5)).timestamp();
+        let signature = signer.sign(image_id, expires, &params);
+
+        assert!(signer
+            .verify(BaseImageId::new(), expires, &params, &signature, now)
+            .is_err());
+        assert!(signer
+            .verify(image_id, expires + 60, &params, &signature, now)
+            .is_err());
+        assert!(signer
+            .verify(image_id, expires, &params, "abc", now)
+            .is_err());
+    }
+
+    #[test]
+    fn wrong_key() {
+        let image_id = BaseImageId::new();
+        let params = SignedParams::default();
+        let now = Utc::now();
+        let expires = (now + Duration::minutes(5)).timestamp();
+        let signature = UrlSigner::new("secret").sign(image_id, expires, &params);
+
+        assert!(UrlSigner::new("other secret")
+            .verify(image_id, expires, &params, &signature, now)
+            .is_err());
+    }
+
+    #[test]
+    fn gallery_signature() {
+        let signer = UrlSigner::new("secret");
+        let project_id = ProjectId::new();
+        let now = Utc::now();
+        let expires = (now + Duration::minutes(5)).timestamp();
+        let signature = signer.sign_gallery(project_id, expires);
+
+        signer
+            .verify_gallery(project_id, expires, &signature, now)
+            .unwrap();
+        assert!(signer
+            .verify_gallery(ProjectId::new(), expires, &signature, now)
+            .is_err());
+        assert!(signer
+            .verify_gallery(project_id, expires, &signature, now + Duration::minutes(10))
+            .is_err());
+    }
+
+    #[test]
+    fn changed_params() {
+        let signer = UrlSigner::new("secret");
+        let image_id = BaseImageId::new();
+        let now = Utc::now();
+        let expires = (now + Duration::minutes(5)).timestamp();
+        let params = SignedParams {
+            width: Some(480),
+            format: Some(ImageFormat::Webp),
+            ..Default::default()
+        };
+        let signature = signer.sign(image_id, expires, &params);
+
+        signer
+            .verify(image_id, expires, &params, &signature, now)
+            .unwrap();
+        for changed in [
+            SignedParams {
+                width: Some(4800),
+                ..params
+            },
+            SignedParams {
+                width: None,
+                ..params
+            },
+            SignedParams {
+                height: Some(100),
+                ..params
+            },
+            SignedParams {
+                format: Some(ImageFormat::Png),
+                ..params
+            },
+            SignedParams {
+                quality: Some(100),
+                ..params
+            },
+        ] {
+            assert!(matches!(
+                signer.verify(image_id, expires, &changed, &signature, now),
+                Err(Error::InvalidSignedUrl("bad signature"))
+            ));
+        }
+    }
+}
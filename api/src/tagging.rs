@@ -0,0 +1,199 @@
+//! Tagging rules, which tag images and place them in collections based on their EXIF data.
+//! The rules are applied by the conversion job, since it has the whole original image.
+
+use chrono::NaiveDate;
+use pic_store_convert::exif::ExifFields;
+use pic_store_db::tagging_rules::{ExifConditions, TaggingRule};
+
+fn contains_ignore_case(value: Option<&str>, pattern: Option<&str>) -> bool {
+    match (value, pattern) {
+        (_, None) => true,
+        (None, Some(_)) => false,
+        (Some(value), Some(pattern)) => value.to_lowercase().contains(&pattern.to_lowercase()),
+    }
+}
+
+/// Trim the tags and remove duplicates and empty tags.
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut output: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim();
+        if !tag.is_empty() && !output.iter().any(|t| t == tag) {
+            output.push(tag.to_string());
+        }
+    }
+
+    output
+}
+
+/// Parse the date from an EXIF timestamp like `2023:06:01 14:22:10`.
+fn captured_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.get(..10)?, "%Y:%m:%d").ok()
+}
+
+pub fn matches(conditions: &ExifConditions, fields: &ExifFields) -> bool {
+    if !contains_ignore_case(
+        fields.camera_make.as_deref(),
+        conditions.camera_make.as_deref(),
+    ) || !contains_ignore_case(
+        fields.camera_model.as_deref(),
+        conditions.camera_model.as_deref(),
+    ) || !contains_ignore_case(
+        fields.lens_model.as_deref(),
+        conditions.lens_model.as_deref(),
+    ) {
+        return false;
+    }
+
+    if conditions.captured_after.is_none() && conditions.captured_before.is_none() {
+        return true;
+    }
+
+    let Some(captured) = fields.captured.as_deref().and_then(captured_date) else {
+        return false;
+    };
+
+    conditions
+        .captured_after
+        .map(|after| captured >= after)
+        .unwrap_or(true)
+        && conditions
+            .captured_before
+            .map(|before| captured < before)
+            .unwrap_or(true)
+}
+
+/// Apply the rules, which should already be sorted by priority, and return the tags and
+/// collection for the image.
+pub fn apply_rules(rules: &[TaggingRule], fields: &ExifFields) -> (Vec<String>, Option<String>) {
+    let mut tags: Vec<String> = Vec::new();
+    let mut collection = None;
+
+    for rule in rules
+        .iter()
+        .filter(|rule| matches(&rule.conditions, fields))
+    {
+        for tag in &rule.tags {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+
+        if collection.is_none() {
+            collection = rule.collection.clone();
+        }
+    }
+
+    (tags, collection)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use pic_store_db::object_id::{ProjectId, TaggingRuleId, TeamId};
+
+    use super::*;
+
+    fn fields() -> ExifFields {
+        ExifFields {
+            camera_make: Some("FUJIFILM".to_string()),
+            camera_model: Some("X-T4".to_string()),
+            lens_model: Some("XF23mmF1.4 R".to_string()),
+            captured: Some("2023:06:01 14:22:10".to_string()),
+        }
+    }
+
+    fn rule(
+        priority: i32,
+        conditions: ExifConditions,
+        tags: &[&str],
+        collection: Option<&str>,
+    ) -> TaggingRule {
+        TaggingRule {
+            id: TaggingRuleId::new(),
+            team_id: TeamId::new(),
+            project_id: ProjectId::new(),
+            name: "rule".to_string(),
+            priority,
+            conditions,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            collection: collection.map(|c| c.to_string()),
+            updated: Utc::now(),
+            deleted: None,
+        }
+    }
+
+    #[test]
+    fn match_conditions() {
+        let fields = fields();
+        assert!(matches(&ExifConditions::default(), &fields));
+        assert!(matches(
+            &ExifConditions {
+                camera_make: Some("fujifilm".to_string()),
+                lens_model: Some("xf23".to_string()),
+                ..Default::default()
+            },
+            &fields
+        ));
+        assert!(!matches(
+            &ExifConditions {
+                camera_model: Some("X100".to_string()),
+                ..Default::default()
+            },
+            &fields
+        ));
+
+        let june = ExifConditions {
+            captured_after: NaiveDate::from_ymd_opt(2023, 6, 1),
+            captured_before: NaiveDate::from_ymd_opt(2023, 7, 1),
+            ..Default::default()
+        };
+        assert!(matches(&june, &fields));
+        assert!(!matches(&june, &ExifFields::default()));
+        assert!(!matches(
+            &ExifConditions {
+                captured_before: NaiveDate::from_ymd_opt(2023, 6, 1),
+                ..Default::default()
+            },
+            &fields
+        ));
+    }
+
+    #[test]
+    fn apply() {
+        let rules = vec![
+            rule(
+                0,
+                ExifConditions {
+                    camera_make: Some("fujifilm".to_string()),
+                    ..Default::default()
+                },
+                &["fuji"],
+                Some("Fujifilm"),
+            ),
+            rule(
+                1,
+                ExifConditions::default(),
+                &["fuji", "photo"],
+                Some("Everything"),
+            ),
+            rule(
+                2,
+                ExifConditions {
+                    camera_make: Some("canon".to_string()),
+                    ..Default::default()
+                },
+                &["canon"],
+                None,
+            ),
+        ];
+
+        assert_eq!(
+            apply_rules(&rules, &fields()),
+            (
+                vec!["fuji".to_string(), "photo".to_string()],
+                Some("Fujifilm".to_string())
+            )
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Restrict what a team can do based on its status, so that billing or abuse problems can be
+//! handled without deleting any data.
+//!
+//! * Active teams have no restrictions.
+//! * Read-only teams can view their data but can not make any changes.
+//! * Suspended teams can not use the API at all. Instance admins impersonating the team may
+//!   still view its data.
+
+use axum::{
+    http::{Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use pic_store_db::TeamStatus;
+
+use crate::{auth::UserInfo, Error};
+
+/// Routes which check for instance admin permissions on their own, and so are usable no
+/// matter what the status of the admin's team is.
+const EXEMPT_PREFIXES: &[&str] = &["/api/admin", "/api/impersonations"];
+
+fn is_read_method(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+fn is_exempt(path: &str) -> bool {
+    EXEMPT_PREFIXES.iter().any(|prefix| {
+        path.strip_prefix(prefix)
+            .map(|rest| rest.is_empty() || rest.starts_with('/'))
+            .unwrap_or(false)
+    })
+}
+
+fn check_status(user: &UserInfo, method: &Method, path: &str) -> Result<(), Error> {
+    if is_exempt(path) {
+        return Ok(());
+    }
+
+    match user.team_status {
+        TeamStatus::Active => Ok(()),
+        TeamStatus::ReadOnly if is_read_method(method) => Ok(()),
+        TeamStatus::ReadOnly => Err(Error::TeamReadOnly),
+        TeamStatus::Suspended if user.impersonation_id.is_some() && is_read_method(method) => {
+            Ok(())
+        }
+        TeamStatus::Suspended => Err(Error::TeamSuspended),
+    }
+}
+
+/// Reject requests that the authenticated user's team is not allowed to make.
+/// Unauthenticated requests are passed through, and left to the routes to handle.
+pub async fn enforce_team_status<B>(req: Request<B>, next: Next<B>) -> Result<Response, Error> {
+    if let Some(user) = req.extensions().get::<UserInfo>() {
+        check_status(user, req.method(), req.uri().path())?;
+    }
+
+    Ok(next.run(req).await.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use pic_store_db::object_id::{ImpersonationId, TeamId, UserId};
+
+    use super::*;
+
+    fn user(team_status: TeamStatus, impersonating: bool) -> UserInfo {
+        UserInfo {
+            user_id: UserId::new(),
+            team_id: TeamId::new(),
+            roles: Vec::new(),
+            default_upload_profile_id: None,
+            bound_upload_profile_id: None,
+            team_status,
+            impersonation_id: impersonating.then(ImpersonationId::new),
+            api_key_id: None,
+        }
+    }
+
+    #[test]
+    fn active() {
+        let user = user(TeamStatus::Active, false);
+        assert!(check_status(&user, &Method::GET, "/api/images").is_ok());
+        assert!(check_status(&user, &Method::POST, "/api/images").is_ok());
+    }
+
+    #[test]
+    fn read_only() {
+        let user = user(TeamStatus::ReadOnly, false);
+        assert!(check_status(&user, &Method::GET, "/api/images").is_ok());
+        assert!(matches!(
+            check_status(&user, &Method::POST, "/api/images"),
+            Err(Error::TeamReadOnly)
+        ));
+        assert!(matches!(
+            check_status(&user, &Method::DELETE, "/api/images/abc"),
+            Err(Error::TeamReadOnly)
+        ));
+    }
+
+    #[test]
+    fn suspended() {
+        let user = user(TeamStatus::Suspended, false);
+        assert!(matches!(
+            check_status(&user, &Method::GET, "/api/images"),
+            Err(Error::TeamSuspended)
+        ));
+        assert!(matches!(
+            check_status(&user, &Method::POST, "/api/images"),
+            Err(Error::TeamSuspended)
+        ));
+    }
+
+    #[test]
+    fn suspended_impersonation_can_read() {
+        let user = user(TeamStatus::Suspended, true);
+        assert!(check_status(&user, &Method::GET, "/api/images").is_ok());
+        assert!(matches!(
+            check_status(&user, &Method::PUT, "/api/images/abc"),
+            Err(Error::TeamSuspended)
+        ));
+    }
+
+    #[test]
+    fn admin_routes_exempt() {
+        let user = user(TeamStatus::Suspended, false);
+        assert!(check_status(&user, &Method::PUT, "/api/admin/teams/abc/status").is_ok());
+        assert!(check_status(&user, &Method::POST, "/api/impersonations").is_ok());
+        assert!(matches!(
+            check_status(&user, &Method::GET, "/api/administrators"),
+            Err(Error::TeamSuspended)
+        ));
+    }
+}
@@ -1,3 +1,19 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::Duration,
+};
+
+use opentelemetry::{
+    sdk::{
+        export::trace::{SpanData, SpanExporter},
+        trace::{BatchSpanProcessor, Sampler, Span, SpanProcessor, TracerProvider},
+    },
+    trace::{
+        Span as _, SpanId, StatusCode, TraceContextExt, TraceId, TraceResult, TracerProvider as _,
+    },
+    Context,
+};
 use opentelemetry_otlp::WithExportConfig;
 use tracing::subscriber::set_global_default;
 use tracing_error::ErrorLayer;
@@ -16,7 +32,205 @@ pub enum TracingExportConfig {
     Jaeger(String),
 }
 
-pub fn configure(export_config: TracingExportConfig) -> Result<(), eyre::Report> {
+/// Controls which traces are exported, so that busy servers don't have to send every request.
+#[derive(Debug, Clone)]
+pub struct SamplingConfig {
+    /// The fraction of traces to export, from 0.0 to 1.0.
+    pub sample_rate: f64,
+    /// Export traces that contain an error, even when they were not sampled.
+    pub always_sample_errors: bool,
+    /// Export traces whose root span took at least this long, even when they were not sampled.
+    pub slow_threshold: Option<Duration>,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 1.0,
+            always_sample_errors: true,
+            slow_threshold: None,
+        }
+    }
+}
+
+impl SamplingConfig {
+    /// Whether traces have to be kept until they finish, to see if they had errors or were slow.
+    fn needs_tail_sampling(&self) -> bool {
+        self.sample_rate < 1.0 && (self.always_sample_errors || self.slow_threshold.is_some())
+    }
+
+    fn sampler(&self) -> Sampler {
+        if self.sample_rate >= 1.0 || self.needs_tail_sampling() {
+            // The tail sampler makes the decision once the whole trace has been recorded.
+            Sampler::AlwaysOn
+        } else {
+            Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                self.sample_rate.max(0.0),
+            )))
+        }
+    }
+}
+
+/// Stop buffering new traces when this many are in progress, so that memory use stays bounded.
+const MAX_PENDING_TRACES: usize = 10_000;
+/// The most spans that will be buffered for a single trace.
+const MAX_SPANS_PER_TRACE: usize = 1_000;
+
+#[derive(Debug, Default)]
+struct PendingTrace {
+    spans: Vec<SpanData>,
+    has_error: bool,
+}
+
+/// A span processor that holds on to the spans of each trace until its local root span ends,
+/// and then decides whether to pass the trace on to the exporter. This lets errors and slow
+/// requests always be exported while the rest are sampled.
+#[derive(Debug)]
+struct TailSampler<P: SpanProcessor> {
+    inner: P,
+    config: SamplingConfig,
+    roots: Mutex<HashSet<SpanId>>,
+    traces: Mutex<HashMap<TraceId, PendingTrace>>,
+}
+
+impl<P: SpanProcessor> TailSampler<P> {
+    fn new(inner: P, config: SamplingConfig) -> Self {
+        Self {
+            inner,
+            config,
+            roots: Mutex::new(HashSet::new()),
+            traces: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// Check if a span failed or logged an error.
+fn is_error(span: &SpanData) -> bool {
+    span.status_code == StatusCode::Error
+        || span.events.iter().any(|event| {
+            event
+                .attributes
+                .iter()
+                .any(|kv| kv.key.as_str() == "level" && kv.value.as_str() == "ERROR")
+        })
+}
+
+/// Decide whether a trace is in the sample, using the trace ID so that the decision is the
+/// same for every span in the trace.
+fn sampled_by_rate(trace_id: TraceId, rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+
+    let value = (u128::from_be_bytes(trace_id.to_bytes()) as u64) >> 1;
+    let bound = (rate.max(0.0) * (1u64 << 63) as f64) as u64;
+    value < bound
+}
+
+impl<P: SpanProcessor> SpanProcessor for TailSampler<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        let parent = cx.span();
+        let parent = parent.span_context();
+        if !parent.is_valid() || parent.is_remote() {
+            self.roots
+                .lock()
+                .unwrap()
+                .insert(span.span_context().span_id());
+        }
+
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        let trace_id = span.span_context.trace_id();
+        let is_root = self
+            .roots
+            .lock()
+            .unwrap()
+            .remove(&span.span_context.span_id());
+        let error = self.config.always_sample_errors && is_error(&span);
+
+        let pending = {
+            let mut traces = self.traces.lock().unwrap();
+            if !is_root {
+                let can_buffer =
+                    traces.len() < MAX_PENDING_TRACES || traces.contains_key(&trace_id);
+                if can_buffer {
+                    let pending = traces.entry(trace_id).or_default();
+                    pending.has_error |= error;
+                    if pending.spans.len() < MAX_SPANS_PER_TRACE {
+                        pending.spans.push(span);
+                    }
+                }
+                return;
+            }
+
+            traces.remove(&trace_id).unwrap_or_default()
+        };
+
+        let slow = self
+            .config
+            .slow_threshold
+            .map(|threshold| {
+                span.end_time
+                    .duration_since(span.start_time)
+                    .map(|duration| duration >= threshold)
+                    .unwrap_or(false)
+            })
+            .unwrap_or(false);
+
+        let keep = pending.has_error
+            || error
+            || slow
+            || sampled_by_rate(trace_id, self.config.sample_rate);
+        if keep {
+            for span in pending.spans.into_iter().chain(std::iter::once(span)) {
+                self.inner.on_end(span);
+            }
+        }
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        self.inner.force_flush()
+    }
+
+    fn shutdown(&mut self) -> TraceResult<()> {
+        self.roots.lock().unwrap().clear();
+        self.traces.lock().unwrap().clear();
+        self.inner.shutdown()
+    }
+}
+
+/// Set up the global tracer provider to send spans to an exporter, with the configured sampling.
+fn install_tracer<E: SpanExporter + 'static>(
+    exporter: E,
+    resource: Option<opentelemetry::sdk::Resource>,
+    sampling: &SamplingConfig,
+) -> opentelemetry::sdk::trace::Tracer {
+    let mut trace_config = opentelemetry::sdk::trace::config().with_sampler(sampling.sampler());
+    if let Some(resource) = resource {
+        trace_config = trace_config.with_resource(resource);
+    }
+
+    let batch =
+        BatchSpanProcessor::builder(exporter, opentelemetry::runtime::TokioCurrentThread).build();
+    let provider = TracerProvider::builder().with_config(trace_config);
+    let provider = if sampling.needs_tail_sampling() {
+        provider.with_span_processor(TailSampler::new(batch, sampling.clone()))
+    } else {
+        provider.with_span_processor(batch)
+    }
+    .build();
+
+    let tracer = provider.tracer("pic-store-api");
+    opentelemetry::global::set_tracer_provider(provider);
+    tracer
+}
+
+pub fn configure(
+    export_config: TracingExportConfig,
+    sampling: SamplingConfig,
+) -> Result<(), eyre::Report> {
     LogTracer::builder()
         .ignore_crate("rustls")
         .with_max_level(log::LevelFilter::Debug)
@@ -42,28 +256,25 @@ pub fn configure(export_config: TracingExportConfig) -> Result<(), eyre::Report>
                 .tonic()
                 .with_endpoint("api.honeycomb.io:443")
                 .with_metadata(oltp_meta);
+            let exporter =
+                opentelemetry_otlp::SpanExporterBuilder::from(exporter).build_span_exporter()?;
 
-            let oltp = opentelemetry_otlp::new_pipeline()
-                .tracing()
-                .with_trace_config(opentelemetry::sdk::trace::config().with_resource(
-                    opentelemetry::sdk::Resource::new(vec![opentelemetry::KeyValue::new(
-                        "service.name",
-                        honeycomb_config.dataset,
-                    )]),
-                ))
-                .with_exporter(exporter)
-                .install_batch(opentelemetry::runtime::TokioCurrentThread)?;
-            let telemetry = tracing_opentelemetry::layer().with_tracer(oltp);
+            let resource = opentelemetry::sdk::Resource::new(vec![opentelemetry::KeyValue::new(
+                "service.name",
+                honeycomb_config.dataset,
+            )]);
+            let tracer = install_tracer(exporter, Some(resource), &sampling);
+            let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
 
             let subscriber = subscriber.with(telemetry);
             set_global_default(subscriber).expect("Setting subscriber");
         }
         TracingExportConfig::Jaeger(endpoint) => {
-            let tracer = opentelemetry_jaeger::new_pipeline()
+            let exporter = opentelemetry_jaeger::new_pipeline()
                 .with_service_name("pic-store-api")
                 .with_agent_endpoint(endpoint.as_str())
-                .install_batch(opentelemetry::runtime::TokioCurrentThread)
-                .unwrap();
+                .init_async_exporter(opentelemetry::runtime::TokioCurrentThread)?;
+            let tracer = install_tracer(exporter, None, &sampling);
             let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
 
             let subscriber = subscriber.with(telemetry);
@@ -80,3 +291,39 @@ pub fn configure(export_config: TracingExportConfig) -> Result<(), eyre::Report>
 pub fn teardown() {
     opentelemetry::global::shutdown_tracer_provider();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_sampling() {
+        let low = TraceId::from_bytes([0; 16]);
+        let high = TraceId::from_bytes([0xff; 16]);
+        assert!(sampled_by_rate(low, 1.0));
+        assert!(sampled_by_rate(high, 1.0));
+        assert!(!sampled_by_rate(low, 0.0));
+        assert!(!sampled_by_rate(high, 0.0));
+        assert!(sampled_by_rate(low, 0.5));
+        assert!(!sampled_by_rate(high, 0.5));
+    }
+
+    #[test]
+    fn tail_sampling_only_when_needed() {
+        let all = SamplingConfig::default();
+        assert!(!all.needs_tail_sampling());
+
+        let head_only = SamplingConfig {
+            sample_rate: 0.1,
+            always_sample_errors: false,
+            slow_threshold: None,
+        };
+        assert!(!head_only.needs_tail_sampling());
+
+        let errors = SamplingConfig {
+            sample_rate: 0.1,
+            ..Default::default()
+        };
+        assert!(errors.needs_tail_sampling());
+    }
+}
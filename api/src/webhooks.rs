@@ -0,0 +1,301 @@
+//! Webhooks that notify a team's own services of events, such as an image finishing conversion.
+//!
+//! Events are added to the `webhook_deliveries` table in the same transaction as the change that
+//! caused them, so an event is never sent for a change that was rolled back, and is never lost
+//! when the change commits. The [send_webhooks_job](crate::jobs::send_webhooks_job) sends them
+//! afterward and retries the ones that fail.
+//!
+//! Each request is signed with the webhook's secret. The `pic-store-client` crate has a helper to
+//! verify the signature.
+//!
+//! A webhook can limit the event types and projects that it receives, and can set a liquid
+//! template to change the body's shape. The template sees the standard event as its variables,
+//! and has a `json` filter to write values as JSON, as in `{"image": {{ data.image_id | json }}}`.
+//! The template must render valid JSON.
+
+use base64::Engine;
+use chrono::Utc;
+use db::{
+    object_id::{AbuseReportId, BaseImageId, ProjectId, TeamId, WebhookDeliveryId},
+    webhook_allowlists,
+    webhooks::{self, NewWebhookDelivery},
+};
+use diesel::prelude::*;
+use liquid_core::{
+    Display_filter, Filter, FilterReflection, ParseFilter, Runtime, Value, ValueView,
+};
+use once_cell::sync::Lazy;
+use pic_store_db as db;
+use serde::Serialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::Error;
+
+/// An image and all of its output images were converted.
+pub const IMAGE_CONVERTED: &str = "image.converted";
+
+/// An instance admin took down an image after an abuse report.
+pub const IMAGE_TAKEN_DOWN: &str = "image.taken_down";
+
+/// The event types that webhooks can receive.
+pub const EVENT_TYPES: &[&str] = &[IMAGE_CONVERTED, IMAGE_TAKEN_DOWN];
+
+/// Example data for each event type, for checking templates when they are saved.
+fn sample_data(event_type: &str) -> serde_json::Value {
+    match event_type {
+        IMAGE_CONVERTED => json!({ "image_id": BaseImageId::nil() }),
+        IMAGE_TAKEN_DOWN => json!({
+            "image_id": BaseImageId::nil(),
+            "report_id": AbuseReportId::nil(),
+            "reason": "",
+        }),
+        _ => json!({}),
+    }
+}
+
+/// The body sent to the webhook.
+#[derive(Debug, Serialize)]
+pub struct WebhookEvent<'a, T: Serialize> {
+    /// The event's ID, which is the same for every webhook that receives it.
+    pub id: Uuid,
+    #[serde(rename = "type")]
+    pub event_type: &'a str,
+    pub created: chrono::DateTime<Utc>,
+    pub team_id: TeamId,
+    pub project_id: Option<ProjectId>,
+    pub data: T,
+}
+
+/// Create a new signing secret.
+pub fn generate_secret() -> String {
+    let mut random = [0u8; 32];
+    random[..16].copy_from_slice(Uuid::new_v4().as_bytes());
+    random[16..].copy_from_slice(Uuid::new_v4().as_bytes());
+    let random = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(random);
+    format!("whsec_{random}")
+}
+
+/// Normalize the host patterns for an allowlist. Each one must be a host name, or `*.` followed
+/// by a domain to allow any of its subdomains.
+pub fn normalize_allowed_hosts(hosts: Vec<String>) -> Result<Vec<String>, Error> {
+    let mut hosts = hosts
+        .into_iter()
+        .map(|host| {
+            let host = host.trim().trim_end_matches('.').to_ascii_lowercase();
+            let domain = host.strip_prefix("*.").unwrap_or(&host);
+            let valid = !domain.is_empty()
+                && domain.split('.').all(|label| {
+                    !label.is_empty()
+                        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                });
+            if valid {
+                Ok(host)
+            } else {
+                Err(Error::InvalidWebhook(
+                    "allowed hosts must be host names, optionally starting with `*.`",
+                ))
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    hosts.sort();
+    hosts.dedup();
+    Ok(hosts)
+}
+
+/// Check that the URL's host is allowed by the team's webhook allowlist.
+pub fn check_allowlist(
+    conn: &mut PgConnection,
+    team_id: TeamId,
+    url: &reqwest::Url,
+) -> Result<(), Error> {
+    let Some(allowlist) = webhook_allowlists::for_team(conn, team_id)? else {
+        return Ok(());
+    };
+
+    if url.host_str().is_some_and(|host| allowlist.allows(host)) {
+        Ok(())
+    } else {
+        Err(Error::WebhookHostNotAllowed)
+    }
+}
+
+/// Add a delivery of the event for each of the team's webhooks. This should run in the same
+/// transaction as the change that caused the event.
+pub fn enqueue(
+    conn: &mut PgConnection,
+    team_id: TeamId,
+    project_id: Option<ProjectId>,
+    event_type: &str,
+    data: impl Serialize,
+) -> Result<(), Error> {
+    let hooks = webhooks::active_for_team(conn, team_id)?
+        .into_iter()
+        .filter(|hook| hook.wants(event_type, project_id))
+        .collect::<Vec<_>>();
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    let payload = serde_json::to_value(WebhookEvent {
+        id: Uuid::new_v4(),
+        event_type,
+        created: Utc::now(),
+        team_id,
+        project_id,
+        data,
+    })
+    .map_err(eyre::Report::new)?;
+
+    let deliveries = hooks
+        .into_iter()
+        .map(|hook| NewWebhookDelivery {
+            id: WebhookDeliveryId::new(),
+            team_id,
+            webhook_id: hook.id,
+            project_id,
+            event_type: event_type.to_string(),
+            payload: payload.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    webhooks::add_deliveries(conn, &deliveries)?;
+    Ok(())
+}
+
+#[derive(Clone, ParseFilter, FilterReflection)]
+#[filter(
+    name = "json",
+    description = "Write the value as JSON.",
+    parsed(JsonFilter)
+)]
+struct Json;
+
+#[derive(Debug, Default, Display_filter)]
+#[name = "json"]
+struct JsonFilter;
+
+impl Filter for JsonFilter {
+    fn evaluate(
+        &self,
+        input: &dyn ValueView,
+        _runtime: &dyn Runtime,
+    ) -> liquid_core::Result<Value> {
+        let json = serde_json::to_string(&input.to_value())
+            .map_err(|e| liquid_core::Error::with_msg(e.to_string()))?;
+        Ok(Value::scalar(json))
+    }
+}
+
+static TEMPLATE_PARSER: Lazy<liquid::Parser> = Lazy::new(|| {
+    liquid::ParserBuilder::with_stdlib()
+        .filter(Json)
+        .build()
+        .expect("building template parser")
+});
+
+/// Render a webhook's payload template with the event as its variables. The result must be
+/// valid JSON.
+pub fn render_payload(template: &str, event: &serde_json::Value) -> Result<String, String> {
+    let template = TEMPLATE_PARSER.parse(template).map_err(|e| e.to_string())?;
+    let globals = liquid::to_object(event).map_err(|e| e.to_string())?;
+    let body = template.render(&globals).map_err(|e| e.to_string())?;
+    serde_json::from_str::<serde_json::Value>(&body)
+        .map_err(|e| format!("The template did not render valid JSON: {e}"))?;
+    Ok(body)
+}
+
+/// Check that a payload template renders valid JSON for each of the event types.
+pub fn validate_template(template: &str, event_types: &[String]) -> Result<(), Error> {
+    let event_types = if event_types.is_empty() {
+        EVENT_TYPES.iter().map(|t| t.to_string()).collect()
+    } else {
+        event_types.to_vec()
+    };
+
+    for event_type in event_types {
+        let event = serde_json::to_value(WebhookEvent {
+            id: Uuid::nil(),
+            event_type: &event_type,
+            created: Utc::now(),
+            team_id: TeamId::nil(),
+            project_id: Some(ProjectId::nil()),
+            data: sample_data(&event_type),
+        })
+        .map_err(eyre::Report::new)?;
+
+        render_payload(template, &event)
+            .map_err(|e| Error::InvalidWebhookTemplate(format!("For {event_type} events: {e}")))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event() -> serde_json::Value {
+        json!({
+            "id": Uuid::nil(),
+            "type": IMAGE_CONVERTED,
+            "team_id": TeamId::nil(),
+            "project_id": null,
+            "data": { "image_id": "bim\"quoted" },
+        })
+    }
+
+    #[test]
+    fn renders_json() {
+        let body = render_payload(
+            r#"{"kind": {{ type | json }}, "image": {{ data.image_id | json }}}"#,
+            &event(),
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            json!({ "kind": "image.converted", "image": "bim\"quoted" })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        assert!(render_payload(r#"{"image": {{ data.image_id }}}"#, &event()).is_err());
+        assert!(render_payload("{{ data.missing }}", &event()).is_err());
+    }
+
+    #[test]
+    fn allowed_host_patterns() {
+        assert_eq!(
+            normalize_allowed_hosts(vec![
+                " Hooks.Example.com. ".to_string(),
+                "*.corp.test".to_string(),
+                "hooks.example.com".to_string(),
+            ])
+            .unwrap(),
+            vec!["*.corp.test".to_string(), "hooks.example.com".to_string()]
+        );
+
+        for host in [
+            "",
+            "*",
+            "*.",
+            "a.*.test",
+            "https://example.com",
+            "a..test",
+            "a b",
+        ] {
+            assert!(
+                normalize_allowed_hosts(vec![host.to_string()]).is_err(),
+                "{host}"
+            );
+        }
+    }
+
+    #[test]
+    fn validates_against_samples() {
+        assert!(validate_template(r#"{"image": {{ data.image_id | json }}}"#, &[]).is_ok());
+        assert!(validate_template(r#"{"reason": {{ data.reason | json }}}"#, &[]).is_err());
+    }
+}
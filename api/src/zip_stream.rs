@@ -0,0 +1,264 @@
+//! Write a zip archive incrementally, so that it can be streamed to the client while the files
+//! are still being read from storage. Files are stored without compression, since images are
+//! already compressed. Each file's CRC and size go in a data descriptor after its contents, so
+//! nothing has to be buffered.
+//!
+//! Zip64 is not supported, so the archive must be smaller than 4GiB.
+
+use bytes::{BufMut, Bytes, BytesMut};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+
+/// Zip 2.0, the minimum for directories and data descriptors.
+const VERSION: u16 = 20;
+/// The CRC and sizes follow the data, and file names are UTF-8.
+const FLAGS: u16 = (1 << 3) | (1 << 11);
+const METHOD_STORED: u16 = 0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ZipError {
+    #[error("The zip file is larger than 4GiB")]
+    TooLarge,
+    #[error("The zip file has too many entries")]
+    TooManyEntries,
+    #[error("A zip entry is already in progress")]
+    EntryInProgress,
+}
+
+struct Entry {
+    name: String,
+    offset: u32,
+    crc: u32,
+    size: u32,
+}
+
+struct CurrentEntry {
+    entry: Entry,
+    hasher: crc32fast::Hasher,
+    size: u64,
+}
+
+pub struct ZipWriter {
+    entries: Vec<Entry>,
+    current: Option<CurrentEntry>,
+    /// The number of bytes written so far.
+    offset: u64,
+    time: u16,
+    date: u16,
+}
+
+impl ZipWriter {
+    /// Create a writer whose entries all have the given modification time.
+    pub fn new(modified: DateTime<Utc>) -> Self {
+        let (time, date) = dos_date_time(modified);
+        Self {
+            entries: Vec::new(),
+            current: None,
+            offset: 0,
+            time,
+            date,
+        }
+    }
+
+    fn offset_u32(&self) -> Result<u32, ZipError> {
+        u32::try_from(self.offset).map_err(|_| ZipError::TooLarge)
+    }
+
+    /// Start a new file, returning the bytes for its header.
+    pub fn start_file(&mut self, name: &str) -> Result<Bytes, ZipError> {
+        if self.current.is_some() {
+            return Err(ZipError::EntryInProgress);
+        }
+        if self.entries.len() >= u16::MAX as usize {
+            return Err(ZipError::TooManyEntries);
+        }
+
+        let offset = self.offset_u32()?;
+        let name = name.trim_start_matches('/');
+
+        let mut header = BytesMut::with_capacity(30 + name.len());
+        header.put_u32_le(LOCAL_HEADER_SIGNATURE);
+        header.put_u16_le(VERSION);
+        header.put_u16_le(FLAGS);
+        header.put_u16_le(METHOD_STORED);
+        header.put_u16_le(self.time);
+        header.put_u16_le(self.date);
+        // The CRC and sizes are in the data descriptor instead.
+        header.put_u32_le(0);
+        header.put_u32_le(0);
+        header.put_u32_le(0);
+        header.put_u16_le(name.len() as u16);
+        header.put_u16_le(0);
+        header.put_slice(name.as_bytes());
+
+        self.offset += header.len() as u64;
+        self.current = Some(CurrentEntry {
+            entry: Entry {
+                name: name.to_string(),
+                offset,
+                crc: 0,
+                size: 0,
+            },
+            hasher: crc32fast::Hasher::new(),
+            size: 0,
+        });
+
+        Ok(header.freeze())
+    }
+
+    /// Record part of the current file's contents. The caller sends the data itself.
+    pub fn write(&mut self, data: &[u8]) -> Result<(), ZipError> {
+        if let Some(current) = self.current.as_mut() {
+            current.hasher.update(data);
+            current.size += data.len() as u64;
+        }
+
+        self.offset += data.len() as u64;
+        self.offset_u32()?;
+        Ok(())
+    }
+
+    /// Finish the current file, returning the bytes for its data descriptor.
+    pub fn finish_file(&mut self) -> Result<Bytes, ZipError> {
+        let Some(current) = self.current.take() else {
+            return Ok(Bytes::new());
+        };
+
+        let mut entry = current.entry;
+        entry.crc = current.hasher.finalize();
+        entry.size = u32::try_from(current.size).map_err(|_| ZipError::TooLarge)?;
+
+        let mut descriptor = BytesMut::with_capacity(16);
+        descriptor.put_u32_le(DATA_DESCRIPTOR_SIGNATURE);
+        descriptor.put_u32_le(entry.crc);
+        descriptor.put_u32_le(entry.size);
+        descriptor.put_u32_le(entry.size);
+
+        self.offset += descriptor.len() as u64;
+        self.entries.push(entry);
+        Ok(descriptor.freeze())
+    }
+
+    /// Finish the archive, returning the bytes for the central directory.
+    pub fn finish(mut self) -> Result<Bytes, ZipError> {
+        let mut output = BytesMut::from(&self.finish_file()?[..]);
+        let directory_start = output.len();
+        let directory_offset = self.offset_u32()?;
+
+        for entry in &self.entries {
+            output.put_u32_le(CENTRAL_HEADER_SIGNATURE);
+            output.put_u16_le(VERSION);
+            output.put_u16_le(VERSION);
+            output.put_u16_le(FLAGS);
+            output.put_u16_le(METHOD_STORED);
+            output.put_u16_le(self.time);
+            output.put_u16_le(self.date);
+            output.put_u32_le(entry.crc);
+            output.put_u32_le(entry.size);
+            output.put_u32_le(entry.size);
+            output.put_u16_le(entry.name.len() as u16);
+            // Extra field, comment, disk number, and attributes
+            output.put_u16_le(0);
+            output.put_u16_le(0);
+            output.put_u16_le(0);
+            output.put_u16_le(0);
+            output.put_u32_le(0);
+            output.put_u32_le(entry.offset);
+            output.put_slice(entry.name.as_bytes());
+        }
+
+        let directory_size = output.len() - directory_start;
+        if self.offset + directory_size as u64 > u32::MAX as u64 {
+            return Err(ZipError::TooLarge);
+        }
+
+        output.put_u32_le(END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+        output.put_u16_le(0);
+        output.put_u16_le(0);
+        output.put_u16_le(self.entries.len() as u16);
+        output.put_u16_le(self.entries.len() as u16);
+        output.put_u32_le(directory_size as u32);
+        output.put_u32_le(directory_offset);
+        output.put_u16_le(0);
+
+        Ok(output.freeze())
+    }
+}
+
+/// Convert a date to the MS-DOS format used by zip files. Dates before 1980 can't be
+/// represented, so they are clamped.
+fn dos_date_time(date: DateTime<Utc>) -> (u16, u16) {
+    if date.year() < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+
+    let time = (date.hour() << 11) | (date.minute() << 5) | (date.second() / 2);
+    let date = (((date.year() - 1980) as u32) << 9) | (date.month() << 5) | date.day();
+    (time as u16, date as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(data: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn writes_archive() {
+        let mut writer = ZipWriter::new(Utc::now());
+        let mut output = BytesMut::new();
+
+        for (name, contents) in [("a.txt", "hello"), ("dir/b.txt", "world!")] {
+            output.extend_from_slice(&writer.start_file(name).unwrap());
+            writer.write(contents.as_bytes()).unwrap();
+            output.extend_from_slice(contents.as_bytes());
+            output.extend_from_slice(&writer.finish_file().unwrap());
+        }
+        output.extend_from_slice(&writer.finish().unwrap());
+
+        assert_eq!(u32_at(&output, 0), LOCAL_HEADER_SIGNATURE);
+        assert_eq!(&output[30..35], b"a.txt");
+        assert_eq!(&output[35..40], b"hello");
+        assert_eq!(u32_at(&output, 40), DATA_DESCRIPTOR_SIGNATURE);
+        assert_eq!(u32_at(&output, 44), 0x3610a686, "crc of hello");
+        assert_eq!(u32_at(&output, 48), 5);
+
+        let end = output.len() - 22;
+        assert_eq!(u32_at(&output, end), END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+        assert_eq!(u16_at(&output, end + 10), 2);
+        let directory_size = u32_at(&output, end + 12) as usize;
+        let directory_offset = u32_at(&output, end + 16) as usize;
+        assert_eq!(directory_offset + directory_size, end);
+        assert_eq!(u32_at(&output, directory_offset), CENTRAL_HEADER_SIGNATURE);
+
+        // The second central directory entry points at the second local header.
+        let second = directory_offset + 46 + "a.txt".len();
+        assert_eq!(u32_at(&output, second), CENTRAL_HEADER_SIGNATURE);
+        let second_offset = u32_at(&output, second + 42) as usize;
+        assert_eq!(u32_at(&output, second_offset), LOCAL_HEADER_SIGNATURE);
+        assert_eq!(
+            &output[second_offset + 30..second_offset + 39],
+            b"dir/b.txt"
+        );
+    }
+
+    #[test]
+    fn dos_dates() {
+        let date = DateTime::parse_from_rfc3339("2026-10-15T13:45:30Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let (time, date) = dos_date_time(date);
+        assert_eq!(time, (13 << 11) | (45 << 5) | 15);
+        assert_eq!(date, (46 << 9) | (10 << 5) | 15);
+    }
+}
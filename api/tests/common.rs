@@ -6,8 +6,8 @@ use once_cell::sync::Lazy;
 pub use crate::client::*;
 
 use pic_store_api::Server;
-use pic_store_db::object_id::{TeamId, UserId};
-use pic_store_db::test::{create_database, DatabaseUser, TestDatabase};
+use pic_store_db::object_id::{ProjectId, TeamId, UploadProfileId, UserId};
+use pic_store_db::test::{create_database, DatabaseInfo, TestDatabase};
 use pic_store_db::users::NewUser;
 use pic_store_db::PoolExt;
 // use proc_macro::TokenStream;
@@ -36,6 +36,10 @@ pub struct TestApp {
     pub database: TestDatabase,
     /// The ID of the precreated organization.
     pub team_id: TeamId,
+    /// The ID of the precreated project.
+    pub project_id: ProjectId,
+    /// The ID of the precreated upload profile in the project.
+    pub upload_profile_id: UploadProfileId,
     pub admin_user: TestUser,
     /// A client set to the base url of the server.
     pub client: TestClient,
@@ -43,11 +47,9 @@ pub struct TestApp {
     pub base_url: String,
 }
 
-async fn start_app(
-    database: TestDatabase,
-    team_id: TeamId,
-    admin_user: DatabaseUser,
-) -> Result<TestApp> {
+async fn start_app(database: TestDatabase, db_info: DatabaseInfo) -> Result<TestApp> {
+    let admin_user = db_info.admin_user;
+
     let queue_dir = temp_dir::TempDir::new().expect("Creating queue temp dir");
     let queue_path = queue_dir.path().join("queue.db");
 
@@ -60,9 +62,53 @@ async fn start_app(
         honeycomb_dataset: String::new(),
         env: "test".to_string(),
         jaeger_endpoint: None,
+        trace_sample_rate: 1.0,
+        trace_sample_errors: true,
+        trace_slow_threshold_ms: None,
         allow_local_fs: true,
+        local_storage_dir: None,
+        authz_policy_file: None,
+        credentials_key: None,
+        captioning_url: None,
+        captioning_api_key: None,
+        ocr_language: None,
+        detect_codes: false,
+        imgix_compat: true,
+        early_hints: false,
+        max_concurrent_renders: 4,
+        rate_limit_uploads: None,
+        rate_limit_transforms: None,
+        rate_limit_reports: None,
+        rate_limit_reads: None,
+        rate_limit_writes: None,
+        client_ip_header: None,
+        reference_crawler: false,
+        link_check_interval_hours: None,
+        link_check_sample_size: 20,
+        slo_check_interval_seconds: 60,
+        slo_max_queue_depth: None,
+        slo_max_worker_lag_seconds: None,
+        slo_max_p95_latency_seconds: None,
+        slo_alert_webhook_url: None,
+        record_requests_dir: None,
+        record_requests_sample_rate: 0.0,
+        strict_json: false,
         cookie_key: "QjX+c1Nggom7lrxVTJFxMI7iQ0BRVr1oR9N64orRgdW3pp/SV+lE/1FOwo12UZj9QoBUUuv2rvcO0x+Omq+25Q==".to_string(),
         session_cookie_name: "sid".to_string(),
+        max_image_width: 16384,
+        max_image_height: 16384,
+        max_image_pixels: 100_000_000,
+        max_decoded_image_bytes: 512 * 1024 * 1024,
+        download_part_size: 8 * 1024 * 1024,
+        download_concurrency: 8,
+        max_upload_size: 1024 * 1024 * 1024,
+        upload_part_size: 16 * 1024 * 1024,
+        url_upload_timeout_seconds: 30,
+        url_upload_allow_private_networks: false,
+        url_signing_key: Some("test signing key".to_string()),
+        imgproxy_key: None,
+        imgproxy_salt: None,
+        geo_country_header: "cf-ipcountry".to_string(),
     };
     Lazy::force(&pic_store_test::TRACING);
     let server = pic_store_api::create_server(config).await?;
@@ -88,7 +134,7 @@ async fn start_app(
 
     let api_key = conn
         .interact(move |conn| {
-            pic_store_api::api_key::make_key(conn, admin_user.user_id, false, None, None)
+            pic_store_api::api_key::make_key(conn, admin_user.user_id, false, None, None, None)
         })
         .await
         .unwrap()?
@@ -96,7 +142,9 @@ async fn start_app(
 
     Ok(TestApp {
         database,
-        team_id,
+        team_id: db_info.team_id,
+        project_id: db_info.project_id,
+        upload_profile_id: db_info.upload_profile_id,
         admin_user: TestUser {
             team_id: admin_user.team_id,
             user_id: admin_user.user_id,
@@ -116,7 +164,7 @@ where
     R: Future<Output = Result<(), eyre::Report>>,
 {
     let (database, db_info) = create_database().await.expect("Creating database");
-    let app = start_app(database.clone(), db_info.team_id, db_info.admin_user)
+    let app = start_app(database.clone(), db_info)
         .await
         .expect("Starting app");
     f(app).await.unwrap();
@@ -168,6 +216,7 @@ impl TestApp {
             team_id,
             password_hash: hash,
             default_upload_profile_id: None,
+            instance_admin: false,
         };
 
         let key = self
@@ -178,7 +227,7 @@ impl TestApp {
                     .values(&user)
                     .execute(conn)?;
 
-                let key = pic_store_api::api_key::make_key(conn, user_id, false, None, None)?;
+                let key = pic_store_api::api_key::make_key(conn, user_id, false, None, None, None)?;
 
                 Ok::<_, eyre::Report>(key)
             })
@@ -0,0 +1,92 @@
+use pic_store_db::PoolExt;
+use serde_json::json;
+
+use crate::common::run_app_test;
+
+#[tokio::test]
+async fn bound_key_uploads_only_to_its_profile() {
+    run_app_test(|app| async move {
+        let profiles_url = format!("projects/{}/upload_profiles", app.project_id);
+
+        // Create a second upload profile with the same storage as the precreated one.
+        let bound_profile: serde_json::Value = app
+            .admin_user
+            .client
+            .get(format!("{profiles_url}/{}", app.upload_profile_id))
+            .send()
+            .await?
+            .json()
+            .await?;
+        let response = app
+            .admin_user
+            .client
+            .post(&profiles_url)
+            .json(&json!({
+                "name": "Other profile",
+                "short_id": "other",
+                "base_storage_location_id": bound_profile["base_storage_location_id"],
+                "output_storage_location_id": bound_profile["output_storage_location_id"],
+                "conversion_profile_id": bound_profile["conversion_profile_id"],
+            }))
+            .send()
+            .await?;
+        assert_eq!(response.status().as_u16(), 200, "creating upload profile");
+        let other_profile: serde_json::Value = response.json().await?;
+        let other_profile_id = other_profile["id"].as_str().unwrap().to_string();
+
+        let user_id = app.admin_user.user_id;
+        let bound_profile_id = app.upload_profile_id;
+        let key = app
+            .database
+            .pool
+            .interact(move |conn| {
+                pic_store_api::api_key::make_key(
+                    conn,
+                    user_id,
+                    false,
+                    None,
+                    None,
+                    Some(bound_profile_id),
+                )
+            })
+            .await
+            .unwrap()?
+            .key;
+        let client = app.client.clone_with_api_key(key);
+
+        for (profile_id, expected) in [(bound_profile_id.to_string(), 200), (other_profile_id, 403)]
+        {
+            let response = client
+                .post("images")
+                .json(&json!({ "filename": "test.png", "upload_profile_id": profile_id }))
+                .send()
+                .await?;
+            assert_eq!(response.status().as_u16(), expected, "creating an image");
+
+            let response = client
+                .post(format!("{profiles_url}/{profile_id}/validate"))
+                .json(&json!({ "size": 1000 }))
+                .send()
+                .await?;
+            assert_eq!(response.status().as_u16(), expected, "validating an upload");
+
+            let response = client
+                .post(format!("{profiles_url}/{profile_id}/ingest"))
+                .json(&json!({ "Records": [] }))
+                .send()
+                .await?;
+            assert_eq!(
+                response.status().as_u16(),
+                expected,
+                "ingesting a bucket notification"
+            );
+        }
+
+        // Routes that don't upload images are still refused.
+        let response = client.get(&profiles_url).send().await?;
+        assert_eq!(response.status().as_u16(), 403);
+
+        Ok(())
+    })
+    .await
+}
@@ -1,3 +1,4 @@
 mod client;
 mod common;
+mod key_binding;
 mod smoke_test;
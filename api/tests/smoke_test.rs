@@ -27,3 +27,40 @@ async fn smoke_test() {
     })
     .await
 }
+
+#[tokio::test]
+async fn version() {
+    run_app_test(|app| async move {
+        let response = app.client.get("version").send().await?;
+        assert_eq!(response.status().as_u16(), 200);
+
+        let body: serde_json::Value = response.json().await?;
+        assert!(body["git_sha"].is_string(), "git_sha should be present");
+        assert!(
+            body["libraries"]["image"].is_string(),
+            "image library version should be present"
+        );
+        Ok(())
+    })
+    .await
+}
+
+#[tokio::test]
+async fn status() {
+    run_app_test(|app| async move {
+        let response = app.client.get("status").send().await?;
+        assert_eq!(response.status().as_u16(), 200);
+
+        let body: serde_json::Value = response.json().await?;
+        assert!(body["status"].is_string(), "status should be present");
+        let database = body["components"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|component| component["name"] == "database")
+            .expect("database component should be present");
+        assert_eq!(database["status"], "operational");
+        Ok(())
+    })
+    .await
+}
@@ -29,7 +29,7 @@ pub struct ApiKey {
 
 pub type Hash = blake3::Hash;
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct ApiKeyData {
     pub id: Uuid,
     pub key: String,
@@ -38,6 +38,16 @@ pub struct ApiKeyData {
     pub expires: DateTime<Utc>,
 }
 
+impl std::fmt::Debug for ApiKeyData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiKeyData")
+            .field("id", &self.id)
+            .field("prefix", &self.prefix)
+            .field("expires", &self.expires)
+            .finish_non_exhaustive()
+    }
+}
+
 impl ApiKeyData {
     pub fn new<STORE: ApiKeyStore>(store: &STORE, expires: DateTime<Utc>) -> ApiKeyData {
         let id = Uuid::new_v4();
@@ -151,7 +161,7 @@ impl<Store: ApiKeyStore> ApiKeyManager<Store> {
         self.store.lookup_api_key(api_key_id, hash).await
     }
 
-    #[instrument(level = "DEBUG")]
+    #[instrument(level = "DEBUG", skip_all)]
     pub async fn get_api_key(
         &self,
         req: &Request<Body>,
@@ -275,6 +285,15 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn debug_hides_key() {
+        let test_store = TestKeyStore::default();
+        let data = ApiKeyData::new(&test_store, Utc.ymd(3000, 1, 1).and_hms(0, 0, 0));
+        let debug = format!("{data:?}");
+        assert!(!debug.contains(&data.key));
+        assert!(debug.contains(&data.id.to_string()));
+    }
+
     #[test]
     fn bad_prefix() {
         let test_store = TestKeyStore::default();
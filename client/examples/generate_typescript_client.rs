@@ -0,0 +1,27 @@
+//! Write the OpenAPI document and the TypeScript client generated from it.
+//!
+//! Usage: `cargo run -p pic-store-client --example generate_typescript_client [OUTPUT_DIR]`,
+//! where the output directory defaults to `client/typescript`.
+
+use std::path::PathBuf;
+
+use pic_store_client::api;
+
+fn main() -> std::io::Result<()> {
+    let output_dir = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("typescript"));
+
+    let document = api::openapi_document();
+    let openapi = serde_json::to_string_pretty(&document).expect("serializing OpenAPI document");
+    std::fs::create_dir_all(output_dir.join("src"))?;
+    std::fs::write(output_dir.join("openapi.json"), format!("{openapi}\n"))?;
+    std::fs::write(
+        output_dir.join("src/index.ts"),
+        api::typescript_client(&document),
+    )?;
+
+    println!("Wrote the TypeScript client to {}", output_dir.display());
+    Ok(())
+}
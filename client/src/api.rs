@@ -0,0 +1,1600 @@
+//! A description of the server's HTTP API, and the documents generated from it.
+//!
+//! [ENDPOINTS] lists every route under `/api`. The server serves the [openapi_document] built
+//! from it at `/api/openapi.json`, and [typescript_client] turns that document into the
+//! TypeScript client in `client/typescript`. Run `just generate-typescript-client` after changing
+//! the routes to regenerate it.
+//!
+//! The server also serves a [postman_collection] at `/api/postman_collection.json`, which Postman
+//! and Bruno can both import.
+
+use std::fmt::Write as _;
+
+use serde_json::{json, Map, Value};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Post,
+    Put,
+    Delete,
+}
+
+impl Method {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+        }
+    }
+}
+
+/// The kind of body that a request sends or a response returns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Content {
+    None,
+    Json,
+    /// Raw bytes, such as an image file.
+    Binary,
+}
+
+const JSON_TYPE: &str = "application/json";
+const BINARY_TYPE: &str = "application/octet-stream";
+
+impl Content {
+    fn media_type(self) -> Option<&'static str> {
+        match self {
+            Content::None => None,
+            Content::Json => Some(JSON_TYPE),
+            Content::Binary => Some(BINARY_TYPE),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Endpoint {
+    pub method: Method,
+    /// The path under `/api`, with path parameters written as `:name`.
+    pub path: &'static str,
+    /// A unique name for the endpoint, which becomes the method name in generated clients.
+    pub operation: &'static str,
+    /// The group that the endpoint is listed under.
+    pub tag: &'static str,
+    pub summary: &'static str,
+    pub request: Content,
+    pub response: Content,
+}
+
+impl Endpoint {
+    const fn new(
+        method: Method,
+        tag: &'static str,
+        path: &'static str,
+        operation: &'static str,
+        summary: &'static str,
+    ) -> Self {
+        Endpoint {
+            method,
+            path,
+            operation,
+            tag,
+            summary,
+            request: Content::None,
+            response: Content::Json,
+        }
+    }
+
+    const fn json_body(mut self) -> Self {
+        self.request = Content::Json;
+        self
+    }
+
+    const fn binary_body(mut self) -> Self {
+        self.request = Content::Binary;
+        self
+    }
+
+    const fn binary_response(mut self) -> Self {
+        self.response = Content::Binary;
+        self
+    }
+
+    /// The names of the path parameters, in order.
+    pub fn path_params(&self) -> impl Iterator<Item = &'static str> {
+        self.path
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix(':'))
+    }
+}
+
+const fn get(
+    tag: &'static str,
+    path: &'static str,
+    op: &'static str,
+    summary: &'static str,
+) -> Endpoint {
+    Endpoint::new(Method::Get, tag, path, op, summary)
+}
+
+const fn post(
+    tag: &'static str,
+    path: &'static str,
+    op: &'static str,
+    summary: &'static str,
+) -> Endpoint {
+    Endpoint::new(Method::Post, tag, path, op, summary)
+}
+
+const fn put(
+    tag: &'static str,
+    path: &'static str,
+    op: &'static str,
+    summary: &'static str,
+) -> Endpoint {
+    Endpoint::new(Method::Put, tag, path, op, summary)
+}
+
+const fn delete(
+    tag: &'static str,
+    path: &'static str,
+    op: &'static str,
+    summary: &'static str,
+) -> Endpoint {
+    Endpoint::new(Method::Delete, tag, path, op, summary)
+}
+
+/// Every endpoint under `/api`.
+pub const ENDPOINTS: &[Endpoint] = &[
+    // abuse reports
+    post(
+        "abuse_reports",
+        "/abuse_reports",
+        "new_abuse_report",
+        "Report an image for abuse",
+    )
+    .json_body(),
+    get(
+        "abuse_reports",
+        "/abuse_reports",
+        "list_team_abuse_reports",
+        "List the reports against the team's images",
+    ),
+    get(
+        "abuse_reports",
+        "/admin/abuse_reports",
+        "list_abuse_reports",
+        "List abuse reports from every team",
+    ),
+    post(
+        "abuse_reports",
+        "/admin/abuse_reports/:report_id/dismiss",
+        "dismiss_abuse_report",
+        "Dismiss an abuse report",
+    )
+    .json_body(),
+    post(
+        "abuse_reports",
+        "/admin/abuse_reports/:report_id/takedown",
+        "take_down_abuse_report",
+        "Take down the image from an abuse report",
+    )
+    .json_body(),
+    // admin
+    get("admin", "/admin/teams", "list_teams", "List teams"),
+    put(
+        "admin",
+        "/admin/teams/:team_id/status",
+        "set_team_status",
+        "Suspend or restore a team",
+    )
+    .json_body(),
+    get(
+        "admin",
+        "/admin/usage",
+        "get_instance_usage",
+        "Get usage for every team",
+    ),
+    get(
+        "admin",
+        "/admin/metering",
+        "get_metering",
+        "Get metered usage",
+    ),
+    get(
+        "admin",
+        "/admin/conversions",
+        "get_conversion_status",
+        "Get whether conversions are paused",
+    ),
+    post(
+        "admin",
+        "/admin/conversions/pause",
+        "pause_conversions",
+        "Pause conversions",
+    )
+    .json_body(),
+    post(
+        "admin",
+        "/admin/conversions/resume",
+        "resume_conversions",
+        "Resume conversions",
+    )
+    .json_body(),
+    get(
+        "admin",
+        "/admin/link_checks",
+        "get_link_checks",
+        "List broken links found by link checks",
+    ),
+    get(
+        "admin",
+        "/admin/queue",
+        "get_queue_metrics",
+        "Get job queue metrics",
+    ),
+    get(
+        "admin",
+        "/admin/webhook_allowlists",
+        "list_webhook_allowlists",
+        "List webhook host allowlists",
+    ),
+    put(
+        "admin",
+        "/admin/webhook_allowlists",
+        "set_webhook_allowlist",
+        "Set a webhook host allowlist",
+    )
+    .json_body(),
+    delete(
+        "admin",
+        "/admin/webhook_allowlists",
+        "remove_webhook_allowlist",
+        "Remove a webhook host allowlist",
+    )
+    .json_body(),
+    // analytics
+    get(
+        "analytics",
+        "/projects/:project_id/analytics",
+        "get_project_analytics",
+        "Get a project's request analytics",
+    ),
+    // conversion profiles
+    get(
+        "conversion_profiles",
+        "/conversion_profile_templates",
+        "list_conversion_profile_templates",
+        "List the built-in conversion profile templates",
+    ),
+    get(
+        "conversion_profiles",
+        "/projects/:project_id/conversion_profiles",
+        "list_project_profiles",
+        "List a project's conversion profiles",
+    ),
+    post(
+        "conversion_profiles",
+        "/projects/:project_id/conversion_profiles",
+        "new_project_profile",
+        "Create a conversion profile",
+    )
+    .json_body(),
+    post(
+        "conversion_profiles",
+        "/projects/:project_id/conversion_profiles/from_template/:template",
+        "new_project_profile_from_template",
+        "Create a conversion profile from a template",
+    )
+    .json_body(),
+    get(
+        "conversion_profiles",
+        "/projects/:project_id/conversion_profiles/:conversion_profile_id",
+        "get_project_profile",
+        "Get a conversion profile",
+    ),
+    put(
+        "conversion_profiles",
+        "/projects/:project_id/conversion_profiles/:conversion_profile_id",
+        "write_project_profile",
+        "Update a conversion profile",
+    )
+    .json_body(),
+    delete(
+        "conversion_profiles",
+        "/projects/:project_id/conversion_profiles/:conversion_profile_id",
+        "disable_project_profile",
+        "Disable a conversion profile",
+    ),
+    get(
+        "conversion_profiles",
+        "/projects/:project_id/conversion_profiles/:conversion_profile_id/stale_images",
+        "list_project_stale_images",
+        "List images converted with an older version of a profile",
+    ),
+    post(
+        "conversion_profiles",
+        "/projects/:project_id/conversion_profiles/:conversion_profile_id/rerender",
+        "rerender_project_stale_images",
+        "Convert stale images again",
+    )
+    .json_body(),
+    post(
+        "conversion_profiles",
+        "/projects/:project_id/conversion_profiles/:conversion_profile_id/preview",
+        "preview_project_profile",
+        "Preview a conversion profile on an image",
+    )
+    .binary_body()
+    .binary_response(),
+    get(
+        "conversion_profiles",
+        "/projects/global/conversion_profiles",
+        "list_global_profiles",
+        "List global conversion profiles",
+    ),
+    post(
+        "conversion_profiles",
+        "/projects/global/conversion_profiles",
+        "new_global_profile",
+        "Create a global conversion profile",
+    )
+    .json_body(),
+    post(
+        "conversion_profiles",
+        "/projects/global/conversion_profiles/from_template/:template",
+        "new_global_profile_from_template",
+        "Create a global conversion profile from a template",
+    )
+    .json_body(),
+    get(
+        "conversion_profiles",
+        "/projects/global/conversion_profiles/:conversion_profile_id",
+        "get_global_profile",
+        "Get a global conversion profile",
+    ),
+    put(
+        "conversion_profiles",
+        "/projects/global/conversion_profiles/:conversion_profile_id",
+        "write_global_profile",
+        "Update a global conversion profile",
+    )
+    .json_body(),
+    delete(
+        "conversion_profiles",
+        "/projects/global/conversion_profiles/:conversion_profile_id",
+        "disable_global_profile",
+        "Disable a global conversion profile",
+    ),
+    get(
+        "conversion_profiles",
+        "/projects/global/conversion_profiles/:conversion_profile_id/stale_images",
+        "list_global_stale_images",
+        "List images converted with an older version of a global profile",
+    ),
+    post(
+        "conversion_profiles",
+        "/projects/global/conversion_profiles/:conversion_profile_id/rerender",
+        "rerender_global_stale_images",
+        "Convert stale images again",
+    )
+    .json_body(),
+    post(
+        "conversion_profiles",
+        "/projects/global/conversion_profiles/:conversion_profile_id/preview",
+        "preview_global_profile",
+        "Preview a global conversion profile on an image",
+    )
+    .binary_body()
+    .binary_response(),
+    // cors
+    get(
+        "cors",
+        "/projects/:project_id/cors",
+        "get_cors_settings",
+        "Get a project's CORS settings",
+    ),
+    put(
+        "cors",
+        "/projects/:project_id/cors",
+        "set_cors_settings",
+        "Set a project's CORS settings",
+    )
+    .json_body(),
+    // delivery domains
+    get(
+        "delivery_domains",
+        "/projects/:project_id/delivery_domains",
+        "list_delivery_domains",
+        "List a project's delivery domains",
+    ),
+    post(
+        "delivery_domains",
+        "/projects/:project_id/delivery_domains",
+        "add_delivery_domain",
+        "Add a delivery domain",
+    )
+    .json_body(),
+    delete(
+        "delivery_domains",
+        "/projects/:project_id/delivery_domains/:hostname",
+        "remove_delivery_domain",
+        "Remove a delivery domain",
+    ),
+    // gallery
+    post(
+        "gallery",
+        "/projects/:project_id/gallery_access",
+        "create_gallery_access",
+        "Create a gallery access link",
+    )
+    .json_body(),
+    get(
+        "gallery",
+        "/projects/:project_id/share_links",
+        "list_share_links",
+        "List a project's share links",
+    ),
+    post(
+        "gallery",
+        "/projects/:project_id/share_links",
+        "new_share_link",
+        "Create a share link for a collection",
+    )
+    .json_body(),
+    delete(
+        "gallery",
+        "/projects/:project_id/share_links/:share_link_id",
+        "revoke_share_link",
+        "Revoke a share link",
+    ),
+    // health
+    get("health", "/health", "health", "Check the server's health"),
+    get("health", "/version", "version", "Get the server's version"),
+    get(
+        "health",
+        "/status",
+        "get_status",
+        "Get the status of the server's dependencies",
+    ),
+    get(
+        "health",
+        "/openapi.json",
+        "get_openapi_document",
+        "Get the OpenAPI description of the API",
+    ),
+    get(
+        "health",
+        "/postman_collection.json",
+        "get_postman_collection",
+        "Get a Postman collection of the API",
+    ),
+    // hotlink protection
+    get(
+        "hotlink",
+        "/projects/:project_id/referers",
+        "get_referer_settings",
+        "Get a project's allowed referers",
+    ),
+    put(
+        "hotlink",
+        "/projects/:project_id/referers",
+        "set_referer_settings",
+        "Set a project's allowed referers",
+    )
+    .json_body(),
+    // images
+    post("images", "/images", "new_base_image", "Create an image").json_body(),
+    post(
+        "images",
+        "/images/from_url",
+        "upload_from_url",
+        "Create an image from a URL",
+    )
+    .json_body(),
+    get(
+        "images",
+        "/images/:image_id",
+        "get_base_image_by_id",
+        "Get an image",
+    ),
+    put(
+        "images",
+        "/images/:image_id",
+        "update_base_image_info",
+        "Update an image",
+    )
+    .json_body(),
+    delete(
+        "images",
+        "/images/:image_id",
+        "remove_base_image",
+        "Delete an image",
+    ),
+    get(
+        "images",
+        "/images/:image_id/bundle",
+        "download_bundle",
+        "Download an image and its outputs as a zip file",
+    )
+    .binary_response(),
+    get(
+        "images",
+        "/images/:image_id/original",
+        "download_original",
+        "Download an image's original file",
+    )
+    .binary_response(),
+    post(
+        "images",
+        "/images/:image_id/pin",
+        "pin_base_image",
+        "Pin an image",
+    ),
+    delete(
+        "images",
+        "/images/:image_id/pin",
+        "unpin_base_image",
+        "Unpin an image",
+    ),
+    post(
+        "images",
+        "/images/:image_id/purge",
+        "purge_image",
+        "Purge an image from the CDN",
+    ),
+    post(
+        "images",
+        "/images/:image_id/reconvert",
+        "reconvert_base_image",
+        "Convert an image again",
+    ),
+    post(
+        "images",
+        "/images/:image_id/signed_url",
+        "create_signed_url",
+        "Create a signed URL for an image",
+    )
+    .json_body(),
+    post(
+        "images",
+        "/images/:image_id/upload",
+        "upload_image",
+        "Upload an image's file",
+    )
+    .binary_body(),
+    post(
+        "images",
+        "/images/:image_id/upload/presign",
+        "presign_upload",
+        "Get a URL to upload an image's file directly to storage",
+    ),
+    post(
+        "images",
+        "/images/:image_id/upload/complete",
+        "complete_upload",
+        "Finish a direct upload",
+    ),
+    post(
+        "images",
+        "/images/:image_id/upload/chunked",
+        "start_chunked_upload",
+        "Start a chunked upload",
+    )
+    .json_body(),
+    get(
+        "images",
+        "/images/:image_id/upload/chunked/:upload_id",
+        "get_chunked_upload",
+        "Get a chunked upload's progress",
+    ),
+    delete(
+        "images",
+        "/images/:image_id/upload/chunked/:upload_id",
+        "abort_chunked_upload",
+        "Abort a chunked upload",
+    ),
+    put(
+        "images",
+        "/images/:image_id/upload/chunked/:upload_id/:index",
+        "put_chunk",
+        "Upload one chunk",
+    )
+    .binary_body(),
+    post(
+        "images",
+        "/images/:image_id/upload/chunked/:upload_id/complete",
+        "complete_chunked_upload",
+        "Finish a chunked upload",
+    ),
+    get(
+        "images",
+        "/image_by_hash/:hash",
+        "get_base_image_by_hash",
+        "Find an image by the hash of its file",
+    ),
+    get(
+        "images",
+        "/projects/:project_id/images/export",
+        "export_images",
+        "Export a project's images",
+    ),
+    get(
+        "images",
+        "/projects/:project_id/images/export.csv",
+        "export_images_csv",
+        "Export a project's images as CSV",
+    )
+    .binary_response(),
+    get(
+        "images",
+        "/projects/:project_id/images/search",
+        "search_images",
+        "Search a project's images",
+    ),
+    post(
+        "images",
+        "/projects/:project_id/images/bulk_delete/preview",
+        "preview_bulk_delete",
+        "Preview the images that a bulk delete would remove",
+    )
+    .json_body(),
+    get(
+        "images",
+        "/projects/:project_id/images/bulk_delete/:bulk_deletion_id",
+        "get_bulk_delete",
+        "Get a bulk delete",
+    ),
+    post(
+        "images",
+        "/projects/:project_id/images/bulk_delete/:bulk_deletion_id/confirm",
+        "confirm_bulk_delete",
+        "Run a previewed bulk delete",
+    )
+    .json_body(),
+    post(
+        "images",
+        "/projects/:project_id/image_references",
+        "import_references",
+        "Import references to a project's images",
+    )
+    .json_body(),
+    post(
+        "images",
+        "/projects/:project_id/image_references/crawl",
+        "start_crawl",
+        "Crawl a site for references to a project's images",
+    )
+    .json_body(),
+    get(
+        "images",
+        "/projects/:project_id/image_references/crawl/:crawl_id",
+        "get_crawl",
+        "Get a crawl's progress",
+    ),
+    post(
+        "images",
+        "/projects/:project_id/upload_profiles/:upload_profile_id/ingest",
+        "ingest_bucket_notification",
+        "Ingest images from a storage bucket notification",
+    )
+    .json_body(),
+    // impersonations
+    get(
+        "impersonations",
+        "/impersonations",
+        "list_impersonations",
+        "List impersonations",
+    ),
+    post(
+        "impersonations",
+        "/impersonations",
+        "new_impersonation",
+        "Start impersonating a user",
+    )
+    .json_body(),
+    get(
+        "impersonations",
+        "/impersonations/:impersonation_id",
+        "get_impersonation",
+        "Get an impersonation",
+    ),
+    delete(
+        "impersonations",
+        "/impersonations/:impersonation_id",
+        "end_impersonation",
+        "End an impersonation",
+    ),
+    // labels
+    get(
+        "labels",
+        "/projects/:project_id/labels",
+        "get_project_labels",
+        "Get a project's labels",
+    ),
+    put(
+        "labels",
+        "/projects/:project_id/labels",
+        "set_project_labels",
+        "Set a project's labels",
+    )
+    .json_body(),
+    get(
+        "labels",
+        "/label_policies",
+        "list_label_policies",
+        "List label policies",
+    ),
+    post(
+        "labels",
+        "/label_policies",
+        "new_label_policy",
+        "Create a label policy",
+    )
+    .json_body(),
+    put(
+        "labels",
+        "/label_policies/:policy_id",
+        "write_label_policy",
+        "Update a label policy",
+    )
+    .json_body(),
+    delete(
+        "labels",
+        "/label_policies/:policy_id",
+        "delete_label_policy",
+        "Delete a label policy",
+    ),
+    // link checks
+    get(
+        "link_checks",
+        "/projects/:project_id/link_checks",
+        "get_link_check_report",
+        "Get a project's broken link report",
+    ),
+    // me
+    get(
+        "me",
+        "/me/permissions",
+        "get_permissions",
+        "Get the current user's permissions",
+    ),
+    // organizations
+    get(
+        "organizations",
+        "/organizations",
+        "list_organizations",
+        "List organizations",
+    ),
+    post(
+        "organizations",
+        "/organizations",
+        "new_organization",
+        "Create an organization",
+    )
+    .json_body(),
+    get(
+        "organizations",
+        "/organizations/:organization_id",
+        "get_organization",
+        "Get an organization",
+    ),
+    put(
+        "organizations",
+        "/organizations/:organization_id",
+        "write_organization",
+        "Update an organization",
+    )
+    .json_body(),
+    put(
+        "organizations",
+        "/organizations/:organization_id/billing",
+        "write_billing",
+        "Update an organization's billing details",
+    )
+    .json_body(),
+    get(
+        "organizations",
+        "/organizations/:organization_id/usage",
+        "get_organization_usage",
+        "Get an organization's usage",
+    ),
+    put(
+        "organizations",
+        "/organizations/:organization_id/members/:user_id",
+        "write_member",
+        "Add or update an organization member",
+    )
+    .json_body(),
+    delete(
+        "organizations",
+        "/organizations/:organization_id/members/:user_id",
+        "remove_member",
+        "Remove an organization member",
+    ),
+    post(
+        "organizations",
+        "/organizations/:organization_id/teams",
+        "add_team",
+        "Add a team to an organization",
+    )
+    .json_body(),
+    delete(
+        "organizations",
+        "/organizations/:organization_id/teams/:team_id",
+        "remove_team",
+        "Remove a team from an organization",
+    ),
+    get(
+        "organizations",
+        "/organizations/:organization_id/storage_locations",
+        "list_shared_storage_locations",
+        "List storage locations shared with an organization",
+    ),
+    put(
+        "organizations",
+        "/organizations/:organization_id/storage_locations/:storage_location_id",
+        "share_storage_location",
+        "Share a storage location with an organization",
+    ),
+    delete(
+        "organizations",
+        "/organizations/:organization_id/storage_locations/:storage_location_id",
+        "unshare_storage_location",
+        "Stop sharing a storage location with an organization",
+    ),
+    // project access
+    put(
+        "project_access",
+        "/projects/:project_id/private",
+        "set_project_private",
+        "Make a project private or public",
+    )
+    .json_body(),
+    get(
+        "project_access",
+        "/projects/:project_id/access_tokens",
+        "list_access_tokens",
+        "List a project's access tokens",
+    ),
+    post(
+        "project_access",
+        "/projects/:project_id/access_tokens",
+        "new_access_token",
+        "Create an access token",
+    )
+    .json_body(),
+    delete(
+        "project_access",
+        "/projects/:project_id/access_tokens/:token_id",
+        "revoke_access_token",
+        "Revoke an access token",
+    ),
+    get(
+        "project_access",
+        "/projects/:project_id/grants",
+        "list_project_grants",
+        "List a project's grants to other teams",
+    ),
+    post(
+        "project_access",
+        "/projects/:project_id/grants",
+        "new_project_grant",
+        "Grant another team access to a project or collection",
+    )
+    .json_body(),
+    delete(
+        "project_access",
+        "/projects/:project_id/grants/:grant_id",
+        "revoke_project_grant",
+        "Revoke a grant",
+    ),
+    get(
+        "project_access",
+        "/projects/:project_id/grants/:grant_id/events",
+        "list_project_grant_events",
+        "List a grant's events",
+    ),
+    get(
+        "project_access",
+        "/grants",
+        "list_received_grants",
+        "List the grants that other teams have given the team",
+    ),
+    // storage locations
+    get(
+        "storage_locations",
+        "/projects/:project_id/storage_locations",
+        "list_project_locations",
+        "List a project's storage locations",
+    ),
+    post(
+        "storage_locations",
+        "/projects/:project_id/storage_locations",
+        "new_project_location",
+        "Create a storage location",
+    )
+    .json_body(),
+    get(
+        "storage_locations",
+        "/projects/:project_id/storage_locations/:storage_location_id",
+        "get_project_location",
+        "Get a storage location",
+    ),
+    put(
+        "storage_locations",
+        "/projects/:project_id/storage_locations/:storage_location_id",
+        "write_project_location",
+        "Update a storage location",
+    )
+    .json_body(),
+    delete(
+        "storage_locations",
+        "/projects/:project_id/storage_locations/:storage_location_id",
+        "disable_project_location",
+        "Disable a storage location",
+    ),
+    post(
+        "storage_locations",
+        "/projects/:project_id/storage_locations/:storage_location_id/test",
+        "test_project_location",
+        "Test a storage location's connection",
+    ),
+    get(
+        "storage_locations",
+        "/projects/global/storage_locations",
+        "list_global_locations",
+        "List global storage locations",
+    ),
+    post(
+        "storage_locations",
+        "/projects/global/storage_locations",
+        "new_global_location",
+        "Create a global storage location",
+    )
+    .json_body(),
+    get(
+        "storage_locations",
+        "/projects/global/storage_locations/:storage_location_id",
+        "get_global_location",
+        "Get a global storage location",
+    ),
+    put(
+        "storage_locations",
+        "/projects/global/storage_locations/:storage_location_id",
+        "write_global_location",
+        "Update a global storage location",
+    )
+    .json_body(),
+    delete(
+        "storage_locations",
+        "/projects/global/storage_locations/:storage_location_id",
+        "disable_global_location",
+        "Disable a global storage location",
+    ),
+    post(
+        "storage_locations",
+        "/projects/global/storage_locations/:storage_location_id/test",
+        "test_global_location",
+        "Test a global storage location's connection",
+    ),
+    // tagging rules
+    get(
+        "tagging_rules",
+        "/projects/:project_id/tagging_rules",
+        "list_tagging_rules",
+        "List a project's tagging rules",
+    ),
+    post(
+        "tagging_rules",
+        "/projects/:project_id/tagging_rules",
+        "new_tagging_rule",
+        "Create a tagging rule",
+    )
+    .json_body(),
+    get(
+        "tagging_rules",
+        "/projects/:project_id/tagging_rules/:tagging_rule_id",
+        "get_tagging_rule",
+        "Get a tagging rule",
+    ),
+    put(
+        "tagging_rules",
+        "/projects/:project_id/tagging_rules/:tagging_rule_id",
+        "write_tagging_rule",
+        "Update a tagging rule",
+    )
+    .json_body(),
+    delete(
+        "tagging_rules",
+        "/projects/:project_id/tagging_rules/:tagging_rule_id",
+        "disable_tagging_rule",
+        "Disable a tagging rule",
+    ),
+    // transformation presets
+    get(
+        "transformation_presets",
+        "/projects/:project_id/transformation_presets",
+        "list_project_presets",
+        "List a project's transformation presets",
+    ),
+    post(
+        "transformation_presets",
+        "/projects/:project_id/transformation_presets",
+        "new_project_preset",
+        "Create a transformation preset",
+    )
+    .json_body(),
+    get(
+        "transformation_presets",
+        "/projects/:project_id/transformation_presets/:transformation_preset_id",
+        "get_project_preset",
+        "Get a transformation preset",
+    ),
+    put(
+        "transformation_presets",
+        "/projects/:project_id/transformation_presets/:transformation_preset_id",
+        "write_project_preset",
+        "Update a transformation preset",
+    )
+    .json_body(),
+    delete(
+        "transformation_presets",
+        "/projects/:project_id/transformation_presets/:transformation_preset_id",
+        "disable_project_preset",
+        "Disable a transformation preset",
+    ),
+    get(
+        "transformation_presets",
+        "/projects/global/transformation_presets",
+        "list_global_presets",
+        "List global transformation presets",
+    ),
+    post(
+        "transformation_presets",
+        "/projects/global/transformation_presets",
+        "new_global_preset",
+        "Create a global transformation preset",
+    )
+    .json_body(),
+    get(
+        "transformation_presets",
+        "/projects/global/transformation_presets/:transformation_preset_id",
+        "get_global_preset",
+        "Get a global transformation preset",
+    ),
+    put(
+        "transformation_presets",
+        "/projects/global/transformation_presets/:transformation_preset_id",
+        "write_global_preset",
+        "Update a global transformation preset",
+    )
+    .json_body(),
+    delete(
+        "transformation_presets",
+        "/projects/global/transformation_presets/:transformation_preset_id",
+        "disable_global_preset",
+        "Disable a global transformation preset",
+    ),
+    // upload profiles
+    get(
+        "upload_profiles",
+        "/projects/:project_id/upload_profiles",
+        "list_project_upload_profiles",
+        "List a project's upload profiles",
+    ),
+    post(
+        "upload_profiles",
+        "/projects/:project_id/upload_profiles",
+        "new_project_upload_profile",
+        "Create an upload profile",
+    )
+    .json_body(),
+    get(
+        "upload_profiles",
+        "/projects/:project_id/upload_profiles/:upload_profile_id",
+        "get_project_upload_profile",
+        "Get an upload profile",
+    ),
+    put(
+        "upload_profiles",
+        "/projects/:project_id/upload_profiles/:upload_profile_id",
+        "write_project_upload_profile",
+        "Update an upload profile",
+    )
+    .json_body(),
+    delete(
+        "upload_profiles",
+        "/projects/:project_id/upload_profiles/:upload_profile_id",
+        "disable_project_upload_profile",
+        "Disable an upload profile",
+    ),
+    post(
+        "upload_profiles",
+        "/projects/:project_id/upload_profiles/:upload_profile_id/validate",
+        "validate_project_upload",
+        "Check whether an upload would be accepted",
+    )
+    .json_body(),
+    // webhooks
+    get("webhooks", "/webhooks", "list_webhooks", "List webhooks"),
+    post("webhooks", "/webhooks", "new_webhook", "Create a webhook").json_body(),
+    get(
+        "webhooks",
+        "/webhooks/:webhook_id",
+        "get_webhook",
+        "Get a webhook",
+    ),
+    put(
+        "webhooks",
+        "/webhooks/:webhook_id",
+        "write_webhook",
+        "Update a webhook",
+    )
+    .json_body(),
+    delete(
+        "webhooks",
+        "/webhooks/:webhook_id",
+        "delete_webhook",
+        "Delete a webhook",
+    ),
+    get(
+        "webhooks",
+        "/webhooks/:webhook_id/deliveries",
+        "list_deliveries",
+        "List a webhook's deliveries",
+    ),
+    get(
+        "webhooks",
+        "/webhooks/:webhook_id/deliveries/:delivery_id",
+        "get_delivery",
+        "Get a delivery and its attempts",
+    ),
+    post(
+        "webhooks",
+        "/webhooks/:webhook_id/deliveries/:delivery_id/redeliver",
+        "redeliver",
+        "Send a delivery again",
+    ),
+];
+
+/// Convert a `snake_case` name to `camelCase`.
+fn camel_case(name: &str) -> String {
+    let mut output = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            output.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+/// The path with `{name}` parameters, as OpenAPI writes them.
+fn openapi_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(name) => format!("{{{name}}}"),
+            None => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn content_object(content: Content) -> Option<Value> {
+    let media_type = content.media_type()?;
+    let schema = match content {
+        Content::Binary => json!({ "type": "string", "format": "binary" }),
+        _ => json!({}),
+    };
+    Some(json!({ media_type: { "schema": schema } }))
+}
+
+/// An OpenAPI 3 document describing [ENDPOINTS].
+pub fn openapi_document() -> Value {
+    let mut paths = Map::new();
+    for endpoint in ENDPOINTS {
+        let parameters = endpoint
+            .path_params()
+            .map(|name| {
+                json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "string" },
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut response = json!({ "description": "Success" });
+        if let Some(content) = content_object(endpoint.response) {
+            response["content"] = content;
+        }
+
+        let mut operation = json!({
+            "operationId": endpoint.operation,
+            "summary": endpoint.summary,
+            "tags": [endpoint.tag],
+            "parameters": parameters,
+            "responses": { "200": response },
+        });
+        if let Some(content) = content_object(endpoint.request) {
+            operation["requestBody"] = json!({ "required": true, "content": content });
+        }
+
+        let path = paths
+            .entry(openapi_path(endpoint.path))
+            .or_insert_with(|| json!({}));
+        path[endpoint.method.as_str().to_ascii_lowercase()] = operation;
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "pic-store",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [{ "url": "/api" }],
+        "components": {
+            "securitySchemes": {
+                "apiKey": { "type": "http", "scheme": "bearer" },
+            },
+        },
+        "security": [{ "apiKey": [] }],
+        "paths": paths,
+    })
+}
+
+/// A Postman collection, in the v2.1 format, with a request for each of [ENDPOINTS] grouped into
+/// a folder for each tag. Requests use the `baseUrl` and `apiKey` collection variables, and
+/// `baseUrl` starts out as `base_url`.
+pub fn postman_collection(base_url: &str) -> Value {
+    let mut folders = Vec::<(&str, Vec<Value>)>::new();
+    for endpoint in ENDPOINTS {
+        let segments = std::iter::once("api")
+            .chain(
+                endpoint
+                    .path
+                    .split('/')
+                    .filter(|segment| !segment.is_empty()),
+            )
+            .collect::<Vec<_>>();
+        let variables = endpoint
+            .path_params()
+            .map(|name| json!({ "key": name, "value": "" }))
+            .collect::<Vec<_>>();
+
+        let mut request = json!({
+            "method": endpoint.method.as_str(),
+            "url": {
+                "raw": format!("{{{{baseUrl}}}}/{}", segments.join("/")),
+                "host": ["{{baseUrl}}"],
+                "path": segments,
+                "variable": variables,
+            },
+        });
+        match endpoint.request {
+            Content::None => {}
+            Content::Json => {
+                request["header"] = json!([{ "key": "Content-Type", "value": JSON_TYPE }]);
+                request["body"] = json!({
+                    "mode": "raw",
+                    "raw": "{}",
+                    "options": { "raw": { "language": "json" } },
+                });
+            }
+            Content::Binary => {
+                request["header"] = json!([{ "key": "Content-Type", "value": BINARY_TYPE }]);
+                request["body"] = json!({ "mode": "file", "file": {} });
+            }
+        }
+
+        let item = json!({ "name": endpoint.summary, "request": request });
+        match folders.iter_mut().find(|(tag, _)| *tag == endpoint.tag) {
+            Some((_, items)) => items.push(item),
+            None => folders.push((endpoint.tag, vec![item])),
+        }
+    }
+
+    let folders = folders
+        .into_iter()
+        .map(|(tag, items)| json!({ "name": tag, "item": items }))
+        .collect::<Vec<_>>();
+
+    json!({
+        "info": {
+            "name": "pic-store",
+            "schema": "https://schema.getpostman.com/json/collection/v2.1.0/collection.json",
+        },
+        "auth": {
+            "type": "bearer",
+            "bearer": [{ "key": "token", "value": "{{apiKey}}", "type": "string" }],
+        },
+        "variable": [
+            { "key": "baseUrl", "value": base_url },
+            { "key": "apiKey", "value": "" },
+        ],
+        "item": folders,
+    })
+}
+
+const TYPESCRIPT_PRELUDE: &str = r#"// Generated from openapi.json by `just generate-typescript-client`. Do not edit.
+
+export interface ClientOptions {
+  /** The server's URL, such as `https://images.example.com`. */
+  baseUrl: string;
+  /** An API key, sent as a bearer token. */
+  apiKey?: string;
+  /** The fetch implementation to use. Defaults to the global `fetch`. */
+  fetch?: typeof fetch;
+}
+
+export type Query = Record<string, string | number | boolean | null | undefined>;
+
+export interface RequestOptions {
+  query?: Query;
+  headers?: Record<string, string>;
+  signal?: AbortSignal;
+}
+
+/** An error response from the server. */
+export class ApiError extends Error {
+  constructor(
+    public status: number,
+    public body: unknown,
+  ) {
+    super(`Request failed with status ${status}`);
+  }
+}
+
+type RequestBody = { json: unknown } | { binary: BodyInit } | undefined;
+
+export class PicStoreClient {
+  constructor(private options: ClientOptions) {}
+
+  private async request(
+    method: string,
+    path: string,
+    body: RequestBody,
+    binaryResponse: boolean,
+    options: RequestOptions = {},
+  ): Promise<unknown> {
+    const url = new URL(`${this.options.baseUrl.replace(/\/$/, '')}/api${path}`);
+    for (const [key, value] of Object.entries(options.query ?? {})) {
+      if (value !== undefined && value !== null) {
+        url.searchParams.set(key, String(value));
+      }
+    }
+
+    const headers: Record<string, string> = { ...options.headers };
+    if (this.options.apiKey) {
+      headers['Authorization'] = `Bearer ${this.options.apiKey}`;
+    }
+
+    let requestBody: BodyInit | undefined;
+    if (body && 'json' in body) {
+      headers['Content-Type'] = 'application/json';
+      requestBody = JSON.stringify(body.json);
+    } else if (body) {
+      headers['Content-Type'] = 'application/octet-stream';
+      requestBody = body.binary;
+    }
+
+    const response = await (this.options.fetch ?? fetch)(url, {
+      method,
+      headers,
+      body: requestBody,
+      signal: options.signal,
+    });
+
+    if (!response.ok) {
+      const text = await response.text();
+      let errorBody: unknown = text;
+      try {
+        errorBody = JSON.parse(text);
+      } catch {
+        // Keep the body as text.
+      }
+      throw new ApiError(response.status, errorBody);
+    }
+
+    if (binaryResponse) {
+      return response.blob();
+    }
+
+    const text = await response.text();
+    return text ? JSON.parse(text) : undefined;
+  }
+"#;
+
+/// Write a TypeScript client with a method for each operation in an OpenAPI document from
+/// [openapi_document].
+pub fn typescript_client(document: &Value) -> String {
+    let mut output = TYPESCRIPT_PRELUDE.to_string();
+
+    let paths = document["paths"].as_object().cloned().unwrap_or_default();
+    for (path, item) in &paths {
+        for method in ["get", "post", "put", "delete"] {
+            let Some(operation) = item.get(method) else {
+                continue;
+            };
+
+            let name = camel_case(operation["operationId"].as_str().unwrap_or_default());
+            let summary = operation["summary"].as_str().unwrap_or_default();
+            let request_type = operation["requestBody"]["content"]
+                .as_object()
+                .and_then(|content| content.keys().next().cloned());
+            let binary_response = operation["responses"]["200"]["content"]
+                .get(BINARY_TYPE)
+                .is_some();
+
+            let params = operation["parameters"]
+                .as_array()
+                .map(|params| {
+                    params
+                        .iter()
+                        .filter_map(|param| param["name"].as_str())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+
+            let mut args = params
+                .iter()
+                .map(|param| format!("{}: string", camel_case(param)))
+                .collect::<Vec<_>>();
+            let body = match request_type.as_deref() {
+                Some(JSON_TYPE) => {
+                    args.push("body: unknown".to_string());
+                    "{ json: body }"
+                }
+                Some(_) => {
+                    args.push("body: BodyInit".to_string());
+                    "{ binary: body }"
+                }
+                None => "undefined",
+            };
+            args.push("options?: RequestOptions".to_string());
+
+            let url = params.iter().fold(path.clone(), |url, param| {
+                url.replace(
+                    &format!("{{{param}}}"),
+                    &format!("${{encodeURIComponent({})}}", camel_case(param)),
+                )
+            });
+
+            let (generic, return_type) = if binary_response {
+                ("", "Blob")
+            } else {
+                ("<T = unknown>", "T")
+            };
+
+            writeln!(output).unwrap();
+            writeln!(output, "  /** {summary} */").unwrap();
+            writeln!(
+                output,
+                "  {name}{generic}({}): Promise<{return_type}> {{",
+                args.join(", ")
+            )
+            .unwrap();
+            writeln!(
+                output,
+                "    return this.request('{}', `{url}`, {body}, {binary_response}, options) as Promise<{return_type}>;",
+                method.to_ascii_uppercase()
+            )
+            .unwrap();
+            writeln!(output, "  }}").unwrap();
+        }
+    }
+
+    output.push_str("}\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn unique_endpoints() {
+        let mut operations = HashSet::new();
+        let mut routes = HashSet::new();
+        for endpoint in ENDPOINTS {
+            assert!(
+                operations.insert(endpoint.operation),
+                "duplicate operation {}",
+                endpoint.operation
+            );
+            assert!(
+                routes.insert((endpoint.method, endpoint.path)),
+                "duplicate route {} {}",
+                endpoint.method.as_str(),
+                endpoint.path
+            );
+        }
+    }
+
+    #[test]
+    fn openapi_paths() {
+        let document = openapi_document();
+        let operation =
+            &document["paths"]["/images/{image_id}/upload/chunked/{upload_id}/{index}"]["put"];
+        assert_eq!(operation["operationId"], "put_chunk");
+        assert_eq!(operation["parameters"].as_array().unwrap().len(), 3);
+        assert!(operation["requestBody"]["content"][BINARY_TYPE].is_object());
+    }
+
+    #[test]
+    fn typescript_methods() {
+        let client = typescript_client(&openapi_document());
+        assert!(client.contains(
+            "  getBaseImageById<T = unknown>(imageId: string, options?: RequestOptions): Promise<T> {\n    return this.request('GET', `/images/${encodeURIComponent(imageId)}`, undefined, false, options) as Promise<T>;"
+        ));
+        assert!(client.contains(
+            "  downloadOriginal(imageId: string, options?: RequestOptions): Promise<Blob> {"
+        ));
+        assert!(client.contains(
+            "  newWebhook<T = unknown>(body: unknown, options?: RequestOptions): Promise<T> {"
+        ));
+    }
+
+    #[test]
+    fn postman_requests() {
+        let collection = postman_collection("https://images.example.com");
+        assert_eq!(
+            collection["variable"][0]["value"],
+            "https://images.example.com"
+        );
+
+        let images = collection["item"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|folder| folder["name"] == "images")
+            .unwrap();
+        let upload = images["item"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|item| item["name"] == "Upload an image's file")
+            .unwrap();
+        assert_eq!(upload["request"]["method"], "POST");
+        assert_eq!(
+            upload["request"]["url"]["raw"],
+            "{{baseUrl}}/api/images/:image_id/upload"
+        );
+        assert_eq!(upload["request"]["url"]["variable"][0]["key"], "image_id");
+        assert_eq!(upload["request"]["body"]["mode"], "file");
+
+        let count = collection["item"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|folder| folder["item"].as_array().unwrap().len())
+            .sum::<usize>();
+        assert_eq!(count, ENDPOINTS.len());
+    }
+
+    /// The generated client in the repository must match the routes.
+    #[test]
+    fn generated_client_is_current() {
+        let document = openapi_document();
+        assert_eq!(
+            include_str!("../typescript/openapi.json"),
+            format!("{}\n", serde_json::to_string_pretty(&document).unwrap()),
+            "run `just generate-typescript-client`"
+        );
+        assert_eq!(
+            include_str!("../typescript/src/index.ts"),
+            typescript_client(&document),
+            "run `just generate-typescript-client`"
+        );
+    }
+}
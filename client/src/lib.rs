@@ -0,0 +1,4 @@
+//! Helpers for applications that use pic-store.
+
+pub mod api;
+pub mod webhook;
@@ -0,0 +1,179 @@
+//! Verify the signatures on webhook requests.
+//!
+//! Each request has a `pic-store-signature` header like `t=1700000000,v1=5257a869...`, where `t`
+//! is when the request was signed, as a Unix timestamp, and `v1` is the hex-encoded
+//! HMAC-SHA256 of `{t}.{body}` using the webhook's secret. The header may have more than one
+//! `v1` entry, and the request is valid if any of them match.
+//!
+//! ```
+//! use pic_store_client::webhook;
+//!
+//! let secret = "whsec_abc";
+//! let body = br#"{"type":"image.converted"}"#;
+//! # let header = webhook::sign(secret, body, std::time::SystemTime::now());
+//!
+//! // `header` is the value of the `pic-store-signature` header.
+//! webhook::verify(secret, &header, body).expect("valid signature");
+//! ```
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The header that holds the signature.
+pub const SIGNATURE_HEADER: &str = "pic-store-signature";
+/// The header with the event type, such as `image.converted`.
+pub const EVENT_HEADER: &str = "pic-store-event";
+/// The header with the delivery's ID. Redeliveries of an event have the same ID.
+pub const DELIVERY_HEADER: &str = "pic-store-delivery";
+
+/// How far the signature's timestamp may be from the current time, to limit replays of old
+/// requests.
+pub const DEFAULT_TOLERANCE: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum VerifyError {
+    #[error("The signature header is malformed")]
+    Malformed,
+    #[error("The signature's timestamp is outside the tolerance")]
+    Expired,
+    #[error("The signature does not match")]
+    Mismatch,
+}
+
+fn mac(secret: &str, timestamp: u64, body: &[u8]) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Create the signature header for a body, signed at `time`.
+pub fn sign(secret: &str, body: &[u8], time: SystemTime) -> String {
+    let timestamp = unix_seconds(time);
+    let signature = mac(secret, timestamp, body).finalize().into_bytes();
+    format!("t={timestamp},v1={}", hex::encode(signature))
+}
+
+/// Verify a request's signature header against its body, with the default tolerance.
+pub fn verify(secret: &str, header: &str, body: &[u8]) -> Result<(), VerifyError> {
+    verify_at(secret, header, body, SystemTime::now(), DEFAULT_TOLERANCE)
+}
+
+/// Verify a request's signature header against its body, as if the current time were `now`.
+pub fn verify_at(
+    secret: &str,
+    header: &str,
+    body: &[u8],
+    now: SystemTime,
+    tolerance: Duration,
+) -> Result<(), VerifyError> {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => {
+                timestamp = Some(value.parse::<u64>().map_err(|_| VerifyError::Malformed)?)
+            }
+            Some(("v1", value)) => signatures.push(value),
+            // Unknown schemes are skipped so that new ones can be added later.
+            Some(_) => {}
+            None => return Err(VerifyError::Malformed),
+        }
+    }
+
+    let timestamp = timestamp.ok_or(VerifyError::Malformed)?;
+    if signatures.is_empty() {
+        return Err(VerifyError::Malformed);
+    }
+
+    if unix_seconds(now).abs_diff(timestamp) > tolerance.as_secs() {
+        return Err(VerifyError::Expired);
+    }
+
+    let expected = mac(secret, timestamp, body);
+    let matches = signatures.into_iter().any(|signature| {
+        hex::decode(signature)
+            .map(|signature| expected.clone().verify_slice(&signature).is_ok())
+            .unwrap_or(false)
+    });
+
+    if matches {
+        Ok(())
+    } else {
+        Err(VerifyError::Mismatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "whsec_test";
+    const BODY: &[u8] = br#"{"type":"image.converted"}"#;
+
+    #[test]
+    fn round_trip() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let header = sign(SECRET, BODY, now);
+        assert!(header.starts_with("t=1700000000,v1="));
+        assert_eq!(
+            verify_at(SECRET, &header, BODY, now, DEFAULT_TOLERANCE),
+            Ok(())
+        );
+        assert_eq!(
+            verify_at("whsec_other", &header, BODY, now, DEFAULT_TOLERANCE),
+            Err(VerifyError::Mismatch)
+        );
+        assert_eq!(
+            verify_at(SECRET, &header, b"{}", now, DEFAULT_TOLERANCE),
+            Err(VerifyError::Mismatch)
+        );
+    }
+
+    #[test]
+    fn any_signature_may_match() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let header = sign(SECRET, BODY, now);
+        let signature = header.split_once(",v1=").unwrap().1;
+        let header = format!("t=1700000000,v1=00ff,v1={signature},v2=abc");
+        assert_eq!(
+            verify_at(SECRET, &header, BODY, now, DEFAULT_TOLERANCE),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn old_signatures_expire() {
+        let signed = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let header = sign(SECRET, BODY, signed);
+        let later = signed + DEFAULT_TOLERANCE + Duration::from_secs(1);
+        assert_eq!(
+            verify_at(SECRET, &header, BODY, later, DEFAULT_TOLERANCE),
+            Err(VerifyError::Expired)
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let now = SystemTime::now();
+        for header in ["", "v1=abcd", "t=abc,v1=abcd", "t=1700000000", "garbage"] {
+            assert_eq!(
+                verify_at(SECRET, header, BODY, now, DEFAULT_TOLERANCE),
+                Err(VerifyError::Malformed),
+                "{header}"
+            );
+        }
+    }
+}
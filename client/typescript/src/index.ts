@@ -0,0 +1,883 @@
+// Generated from openapi.json by `just generate-typescript-client`. Do not edit.
+
+export interface ClientOptions {
+  /** The server's URL, such as `https://images.example.com`. */
+  baseUrl: string;
+  /** An API key, sent as a bearer token. */
+  apiKey?: string;
+  /** The fetch implementation to use. Defaults to the global `fetch`. */
+  fetch?: typeof fetch;
+}
+
+export type Query = Record<string, string | number | boolean | null | undefined>;
+
+export interface RequestOptions {
+  query?: Query;
+  headers?: Record<string, string>;
+  signal?: AbortSignal;
+}
+
+/** An error response from the server. */
+export class ApiError extends Error {
+  constructor(
+    public status: number,
+    public body: unknown,
+  ) {
+    super(`Request failed with status ${status}`);
+  }
+}
+
+type RequestBody = { json: unknown } | { binary: BodyInit } | undefined;
+
+export class PicStoreClient {
+  constructor(private options: ClientOptions) {}
+
+  private async request(
+    method: string,
+    path: string,
+    body: RequestBody,
+    binaryResponse: boolean,
+    options: RequestOptions = {},
+  ): Promise<unknown> {
+    const url = new URL(`${this.options.baseUrl.replace(/\/$/, '')}/api${path}`);
+    for (const [key, value] of Object.entries(options.query ?? {})) {
+      if (value !== undefined && value !== null) {
+        url.searchParams.set(key, String(value));
+      }
+    }
+
+    const headers: Record<string, string> = { ...options.headers };
+    if (this.options.apiKey) {
+      headers['Authorization'] = `Bearer ${this.options.apiKey}`;
+    }
+
+    let requestBody: BodyInit | undefined;
+    if (body && 'json' in body) {
+      headers['Content-Type'] = 'application/json';
+      requestBody = JSON.stringify(body.json);
+    } else if (body) {
+      headers['Content-Type'] = 'application/octet-stream';
+      requestBody = body.binary;
+    }
+
+    const response = await (this.options.fetch ?? fetch)(url, {
+      method,
+      headers,
+      body: requestBody,
+      signal: options.signal,
+    });
+
+    if (!response.ok) {
+      const text = await response.text();
+      let errorBody: unknown = text;
+      try {
+        errorBody = JSON.parse(text);
+      } catch {
+        // Keep the body as text.
+      }
+      throw new ApiError(response.status, errorBody);
+    }
+
+    if (binaryResponse) {
+      return response.blob();
+    }
+
+    const text = await response.text();
+    return text ? JSON.parse(text) : undefined;
+  }
+
+  /** List the reports against the team's images */
+  listTeamAbuseReports<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/abuse_reports`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Report an image for abuse */
+  newAbuseReport<T = unknown>(body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/abuse_reports`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** List abuse reports from every team */
+  listAbuseReports<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/admin/abuse_reports`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Dismiss an abuse report */
+  dismissAbuseReport<T = unknown>(reportId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/admin/abuse_reports/${encodeURIComponent(reportId)}/dismiss`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Take down the image from an abuse report */
+  takeDownAbuseReport<T = unknown>(reportId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/admin/abuse_reports/${encodeURIComponent(reportId)}/takedown`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get whether conversions are paused */
+  getConversionStatus<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/admin/conversions`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Pause conversions */
+  pauseConversions<T = unknown>(body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/admin/conversions/pause`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Resume conversions */
+  resumeConversions<T = unknown>(body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/admin/conversions/resume`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** List broken links found by link checks */
+  getLinkChecks<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/admin/link_checks`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Get metered usage */
+  getMetering<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/admin/metering`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Get job queue metrics */
+  getQueueMetrics<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/admin/queue`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List teams */
+  listTeams<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/admin/teams`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Suspend or restore a team */
+  setTeamStatus<T = unknown>(teamId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/admin/teams/${encodeURIComponent(teamId)}/status`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get usage for every team */
+  getInstanceUsage<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/admin/usage`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List webhook host allowlists */
+  listWebhookAllowlists<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/admin/webhook_allowlists`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Set a webhook host allowlist */
+  setWebhookAllowlist<T = unknown>(body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/admin/webhook_allowlists`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Remove a webhook host allowlist */
+  removeWebhookAllowlist<T = unknown>(body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/admin/webhook_allowlists`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** List the built-in conversion profile templates */
+  listConversionProfileTemplates<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/conversion_profile_templates`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List the grants that other teams have given the team */
+  listReceivedGrants<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/grants`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Check the server's health */
+  health<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/health`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Find an image by the hash of its file */
+  getBaseImageByHash<T = unknown>(hash: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/image_by_hash/${encodeURIComponent(hash)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create an image */
+  newBaseImage<T = unknown>(body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/images`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Create an image from a URL */
+  uploadFromUrl<T = unknown>(body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/images/from_url`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get an image */
+  getBaseImageById<T = unknown>(imageId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/images/${encodeURIComponent(imageId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Update an image */
+  updateBaseImageInfo<T = unknown>(imageId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/images/${encodeURIComponent(imageId)}`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Delete an image */
+  removeBaseImage<T = unknown>(imageId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/images/${encodeURIComponent(imageId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Download an image and its outputs as a zip file */
+  downloadBundle(imageId: string, options?: RequestOptions): Promise<Blob> {
+    return this.request('GET', `/images/${encodeURIComponent(imageId)}/bundle`, undefined, true, options) as Promise<Blob>;
+  }
+
+  /** Download an image's original file */
+  downloadOriginal(imageId: string, options?: RequestOptions): Promise<Blob> {
+    return this.request('GET', `/images/${encodeURIComponent(imageId)}/original`, undefined, true, options) as Promise<Blob>;
+  }
+
+  /** Pin an image */
+  pinBaseImage<T = unknown>(imageId: string, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/images/${encodeURIComponent(imageId)}/pin`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Unpin an image */
+  unpinBaseImage<T = unknown>(imageId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/images/${encodeURIComponent(imageId)}/pin`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Purge an image from the CDN */
+  purgeImage<T = unknown>(imageId: string, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/images/${encodeURIComponent(imageId)}/purge`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Convert an image again */
+  reconvertBaseImage<T = unknown>(imageId: string, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/images/${encodeURIComponent(imageId)}/reconvert`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create a signed URL for an image */
+  createSignedUrl<T = unknown>(imageId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/images/${encodeURIComponent(imageId)}/signed_url`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Upload an image's file */
+  uploadImage<T = unknown>(imageId: string, body: BodyInit, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/images/${encodeURIComponent(imageId)}/upload`, { binary: body }, false, options) as Promise<T>;
+  }
+
+  /** Start a chunked upload */
+  startChunkedUpload<T = unknown>(imageId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/images/${encodeURIComponent(imageId)}/upload/chunked`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get a chunked upload's progress */
+  getChunkedUpload<T = unknown>(imageId: string, uploadId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/images/${encodeURIComponent(imageId)}/upload/chunked/${encodeURIComponent(uploadId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Abort a chunked upload */
+  abortChunkedUpload<T = unknown>(imageId: string, uploadId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/images/${encodeURIComponent(imageId)}/upload/chunked/${encodeURIComponent(uploadId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Finish a chunked upload */
+  completeChunkedUpload<T = unknown>(imageId: string, uploadId: string, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/images/${encodeURIComponent(imageId)}/upload/chunked/${encodeURIComponent(uploadId)}/complete`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Upload one chunk */
+  putChunk<T = unknown>(imageId: string, uploadId: string, index: string, body: BodyInit, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/images/${encodeURIComponent(imageId)}/upload/chunked/${encodeURIComponent(uploadId)}/${encodeURIComponent(index)}`, { binary: body }, false, options) as Promise<T>;
+  }
+
+  /** Finish a direct upload */
+  completeUpload<T = unknown>(imageId: string, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/images/${encodeURIComponent(imageId)}/upload/complete`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Get a URL to upload an image's file directly to storage */
+  presignUpload<T = unknown>(imageId: string, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/images/${encodeURIComponent(imageId)}/upload/presign`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List impersonations */
+  listImpersonations<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/impersonations`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Start impersonating a user */
+  newImpersonation<T = unknown>(body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/impersonations`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get an impersonation */
+  getImpersonation<T = unknown>(impersonationId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/impersonations/${encodeURIComponent(impersonationId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** End an impersonation */
+  endImpersonation<T = unknown>(impersonationId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/impersonations/${encodeURIComponent(impersonationId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List label policies */
+  listLabelPolicies<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/label_policies`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create a label policy */
+  newLabelPolicy<T = unknown>(body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/label_policies`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Update a label policy */
+  writeLabelPolicy<T = unknown>(policyId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/label_policies/${encodeURIComponent(policyId)}`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Delete a label policy */
+  deleteLabelPolicy<T = unknown>(policyId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/label_policies/${encodeURIComponent(policyId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Get the current user's permissions */
+  getPermissions<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/me/permissions`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Get the OpenAPI description of the API */
+  getOpenapiDocument<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/openapi.json`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List organizations */
+  listOrganizations<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/organizations`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create an organization */
+  newOrganization<T = unknown>(body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/organizations`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get an organization */
+  getOrganization<T = unknown>(organizationId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/organizations/${encodeURIComponent(organizationId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Update an organization */
+  writeOrganization<T = unknown>(organizationId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/organizations/${encodeURIComponent(organizationId)}`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Update an organization's billing details */
+  writeBilling<T = unknown>(organizationId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/organizations/${encodeURIComponent(organizationId)}/billing`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Add or update an organization member */
+  writeMember<T = unknown>(organizationId: string, userId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/organizations/${encodeURIComponent(organizationId)}/members/${encodeURIComponent(userId)}`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Remove an organization member */
+  removeMember<T = unknown>(organizationId: string, userId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/organizations/${encodeURIComponent(organizationId)}/members/${encodeURIComponent(userId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List storage locations shared with an organization */
+  listSharedStorageLocations<T = unknown>(organizationId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/organizations/${encodeURIComponent(organizationId)}/storage_locations`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Share a storage location with an organization */
+  shareStorageLocation<T = unknown>(organizationId: string, storageLocationId: string, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/organizations/${encodeURIComponent(organizationId)}/storage_locations/${encodeURIComponent(storageLocationId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Stop sharing a storage location with an organization */
+  unshareStorageLocation<T = unknown>(organizationId: string, storageLocationId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/organizations/${encodeURIComponent(organizationId)}/storage_locations/${encodeURIComponent(storageLocationId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Add a team to an organization */
+  addTeam<T = unknown>(organizationId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/organizations/${encodeURIComponent(organizationId)}/teams`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Remove a team from an organization */
+  removeTeam<T = unknown>(organizationId: string, teamId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/organizations/${encodeURIComponent(organizationId)}/teams/${encodeURIComponent(teamId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Get an organization's usage */
+  getOrganizationUsage<T = unknown>(organizationId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/organizations/${encodeURIComponent(organizationId)}/usage`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Get a Postman collection of the API */
+  getPostmanCollection<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/postman_collection.json`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List global conversion profiles */
+  listGlobalProfiles<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/global/conversion_profiles`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create a global conversion profile */
+  newGlobalProfile<T = unknown>(body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/global/conversion_profiles`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Create a global conversion profile from a template */
+  newGlobalProfileFromTemplate<T = unknown>(template: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/global/conversion_profiles/from_template/${encodeURIComponent(template)}`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get a global conversion profile */
+  getGlobalProfile<T = unknown>(conversionProfileId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/global/conversion_profiles/${encodeURIComponent(conversionProfileId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Update a global conversion profile */
+  writeGlobalProfile<T = unknown>(conversionProfileId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/projects/global/conversion_profiles/${encodeURIComponent(conversionProfileId)}`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Disable a global conversion profile */
+  disableGlobalProfile<T = unknown>(conversionProfileId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/projects/global/conversion_profiles/${encodeURIComponent(conversionProfileId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Preview a global conversion profile on an image */
+  previewGlobalProfile(conversionProfileId: string, body: BodyInit, options?: RequestOptions): Promise<Blob> {
+    return this.request('POST', `/projects/global/conversion_profiles/${encodeURIComponent(conversionProfileId)}/preview`, { binary: body }, true, options) as Promise<Blob>;
+  }
+
+  /** Convert stale images again */
+  rerenderGlobalStaleImages<T = unknown>(conversionProfileId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/global/conversion_profiles/${encodeURIComponent(conversionProfileId)}/rerender`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** List images converted with an older version of a global profile */
+  listGlobalStaleImages<T = unknown>(conversionProfileId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/global/conversion_profiles/${encodeURIComponent(conversionProfileId)}/stale_images`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List global storage locations */
+  listGlobalLocations<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/global/storage_locations`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create a global storage location */
+  newGlobalLocation<T = unknown>(body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/global/storage_locations`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get a global storage location */
+  getGlobalLocation<T = unknown>(storageLocationId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/global/storage_locations/${encodeURIComponent(storageLocationId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Update a global storage location */
+  writeGlobalLocation<T = unknown>(storageLocationId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/projects/global/storage_locations/${encodeURIComponent(storageLocationId)}`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Disable a global storage location */
+  disableGlobalLocation<T = unknown>(storageLocationId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/projects/global/storage_locations/${encodeURIComponent(storageLocationId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Test a global storage location's connection */
+  testGlobalLocation<T = unknown>(storageLocationId: string, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/global/storage_locations/${encodeURIComponent(storageLocationId)}/test`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List global transformation presets */
+  listGlobalPresets<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/global/transformation_presets`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create a global transformation preset */
+  newGlobalPreset<T = unknown>(body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/global/transformation_presets`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get a global transformation preset */
+  getGlobalPreset<T = unknown>(transformationPresetId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/global/transformation_presets/${encodeURIComponent(transformationPresetId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Update a global transformation preset */
+  writeGlobalPreset<T = unknown>(transformationPresetId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/projects/global/transformation_presets/${encodeURIComponent(transformationPresetId)}`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Disable a global transformation preset */
+  disableGlobalPreset<T = unknown>(transformationPresetId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/projects/global/transformation_presets/${encodeURIComponent(transformationPresetId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List a project's access tokens */
+  listAccessTokens<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/access_tokens`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create an access token */
+  newAccessToken<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/access_tokens`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Revoke an access token */
+  revokeAccessToken<T = unknown>(projectId: string, tokenId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/projects/${encodeURIComponent(projectId)}/access_tokens/${encodeURIComponent(tokenId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Get a project's request analytics */
+  getProjectAnalytics<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/analytics`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List a project's conversion profiles */
+  listProjectProfiles<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/conversion_profiles`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create a conversion profile */
+  newProjectProfile<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/conversion_profiles`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Create a conversion profile from a template */
+  newProjectProfileFromTemplate<T = unknown>(projectId: string, template: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/conversion_profiles/from_template/${encodeURIComponent(template)}`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get a conversion profile */
+  getProjectProfile<T = unknown>(projectId: string, conversionProfileId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/conversion_profiles/${encodeURIComponent(conversionProfileId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Update a conversion profile */
+  writeProjectProfile<T = unknown>(projectId: string, conversionProfileId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/projects/${encodeURIComponent(projectId)}/conversion_profiles/${encodeURIComponent(conversionProfileId)}`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Disable a conversion profile */
+  disableProjectProfile<T = unknown>(projectId: string, conversionProfileId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/projects/${encodeURIComponent(projectId)}/conversion_profiles/${encodeURIComponent(conversionProfileId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Preview a conversion profile on an image */
+  previewProjectProfile(projectId: string, conversionProfileId: string, body: BodyInit, options?: RequestOptions): Promise<Blob> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/conversion_profiles/${encodeURIComponent(conversionProfileId)}/preview`, { binary: body }, true, options) as Promise<Blob>;
+  }
+
+  /** Convert stale images again */
+  rerenderProjectStaleImages<T = unknown>(projectId: string, conversionProfileId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/conversion_profiles/${encodeURIComponent(conversionProfileId)}/rerender`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** List images converted with an older version of a profile */
+  listProjectStaleImages<T = unknown>(projectId: string, conversionProfileId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/conversion_profiles/${encodeURIComponent(conversionProfileId)}/stale_images`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Get a project's CORS settings */
+  getCorsSettings<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/cors`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Set a project's CORS settings */
+  setCorsSettings<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/projects/${encodeURIComponent(projectId)}/cors`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** List a project's delivery domains */
+  listDeliveryDomains<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/delivery_domains`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Add a delivery domain */
+  addDeliveryDomain<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/delivery_domains`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Remove a delivery domain */
+  removeDeliveryDomain<T = unknown>(projectId: string, hostname: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/projects/${encodeURIComponent(projectId)}/delivery_domains/${encodeURIComponent(hostname)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create a gallery access link */
+  createGalleryAccess<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/gallery_access`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** List a project's grants to other teams */
+  listProjectGrants<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/grants`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Grant another team access to a project or collection */
+  newProjectGrant<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/grants`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Revoke a grant */
+  revokeProjectGrant<T = unknown>(projectId: string, grantId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/projects/${encodeURIComponent(projectId)}/grants/${encodeURIComponent(grantId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List a grant's events */
+  listProjectGrantEvents<T = unknown>(projectId: string, grantId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/grants/${encodeURIComponent(grantId)}/events`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Import references to a project's images */
+  importReferences<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/image_references`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Crawl a site for references to a project's images */
+  startCrawl<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/image_references/crawl`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get a crawl's progress */
+  getCrawl<T = unknown>(projectId: string, crawlId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/image_references/crawl/${encodeURIComponent(crawlId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Preview the images that a bulk delete would remove */
+  previewBulkDelete<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/images/bulk_delete/preview`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get a bulk delete */
+  getBulkDelete<T = unknown>(projectId: string, bulkDeletionId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/images/bulk_delete/${encodeURIComponent(bulkDeletionId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Run a previewed bulk delete */
+  confirmBulkDelete<T = unknown>(projectId: string, bulkDeletionId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/images/bulk_delete/${encodeURIComponent(bulkDeletionId)}/confirm`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Export a project's images */
+  exportImages<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/images/export`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Export a project's images as CSV */
+  exportImagesCsv(projectId: string, options?: RequestOptions): Promise<Blob> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/images/export.csv`, undefined, true, options) as Promise<Blob>;
+  }
+
+  /** Search a project's images */
+  searchImages<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/images/search`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Get a project's labels */
+  getProjectLabels<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/labels`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Set a project's labels */
+  setProjectLabels<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/projects/${encodeURIComponent(projectId)}/labels`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get a project's broken link report */
+  getLinkCheckReport<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/link_checks`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Make a project private or public */
+  setProjectPrivate<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/projects/${encodeURIComponent(projectId)}/private`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get a project's allowed referers */
+  getRefererSettings<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/referers`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Set a project's allowed referers */
+  setRefererSettings<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/projects/${encodeURIComponent(projectId)}/referers`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** List a project's share links */
+  listShareLinks<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/share_links`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create a share link for a collection */
+  newShareLink<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/share_links`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Revoke a share link */
+  revokeShareLink<T = unknown>(projectId: string, shareLinkId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/projects/${encodeURIComponent(projectId)}/share_links/${encodeURIComponent(shareLinkId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List a project's storage locations */
+  listProjectLocations<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/storage_locations`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create a storage location */
+  newProjectLocation<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/storage_locations`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get a storage location */
+  getProjectLocation<T = unknown>(projectId: string, storageLocationId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/storage_locations/${encodeURIComponent(storageLocationId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Update a storage location */
+  writeProjectLocation<T = unknown>(projectId: string, storageLocationId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/projects/${encodeURIComponent(projectId)}/storage_locations/${encodeURIComponent(storageLocationId)}`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Disable a storage location */
+  disableProjectLocation<T = unknown>(projectId: string, storageLocationId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/projects/${encodeURIComponent(projectId)}/storage_locations/${encodeURIComponent(storageLocationId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Test a storage location's connection */
+  testProjectLocation<T = unknown>(projectId: string, storageLocationId: string, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/storage_locations/${encodeURIComponent(storageLocationId)}/test`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List a project's tagging rules */
+  listTaggingRules<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/tagging_rules`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create a tagging rule */
+  newTaggingRule<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/tagging_rules`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get a tagging rule */
+  getTaggingRule<T = unknown>(projectId: string, taggingRuleId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/tagging_rules/${encodeURIComponent(taggingRuleId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Update a tagging rule */
+  writeTaggingRule<T = unknown>(projectId: string, taggingRuleId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/projects/${encodeURIComponent(projectId)}/tagging_rules/${encodeURIComponent(taggingRuleId)}`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Disable a tagging rule */
+  disableTaggingRule<T = unknown>(projectId: string, taggingRuleId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/projects/${encodeURIComponent(projectId)}/tagging_rules/${encodeURIComponent(taggingRuleId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List a project's transformation presets */
+  listProjectPresets<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/transformation_presets`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create a transformation preset */
+  newProjectPreset<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/transformation_presets`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get a transformation preset */
+  getProjectPreset<T = unknown>(projectId: string, transformationPresetId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/transformation_presets/${encodeURIComponent(transformationPresetId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Update a transformation preset */
+  writeProjectPreset<T = unknown>(projectId: string, transformationPresetId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/projects/${encodeURIComponent(projectId)}/transformation_presets/${encodeURIComponent(transformationPresetId)}`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Disable a transformation preset */
+  disableProjectPreset<T = unknown>(projectId: string, transformationPresetId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/projects/${encodeURIComponent(projectId)}/transformation_presets/${encodeURIComponent(transformationPresetId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List a project's upload profiles */
+  listProjectUploadProfiles<T = unknown>(projectId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/upload_profiles`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create an upload profile */
+  newProjectUploadProfile<T = unknown>(projectId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/upload_profiles`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get an upload profile */
+  getProjectUploadProfile<T = unknown>(projectId: string, uploadProfileId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/projects/${encodeURIComponent(projectId)}/upload_profiles/${encodeURIComponent(uploadProfileId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Update an upload profile */
+  writeProjectUploadProfile<T = unknown>(projectId: string, uploadProfileId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/projects/${encodeURIComponent(projectId)}/upload_profiles/${encodeURIComponent(uploadProfileId)}`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Disable an upload profile */
+  disableProjectUploadProfile<T = unknown>(projectId: string, uploadProfileId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/projects/${encodeURIComponent(projectId)}/upload_profiles/${encodeURIComponent(uploadProfileId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Ingest images from a storage bucket notification */
+  ingestBucketNotification<T = unknown>(projectId: string, uploadProfileId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/upload_profiles/${encodeURIComponent(uploadProfileId)}/ingest`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Check whether an upload would be accepted */
+  validateProjectUpload<T = unknown>(projectId: string, uploadProfileId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/projects/${encodeURIComponent(projectId)}/upload_profiles/${encodeURIComponent(uploadProfileId)}/validate`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get the status of the server's dependencies */
+  getStatus<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/status`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Get the server's version */
+  version<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/version`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List webhooks */
+  listWebhooks<T = unknown>(options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/webhooks`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Create a webhook */
+  newWebhook<T = unknown>(body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/webhooks`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Get a webhook */
+  getWebhook<T = unknown>(webhookId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/webhooks/${encodeURIComponent(webhookId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Update a webhook */
+  writeWebhook<T = unknown>(webhookId: string, body: unknown, options?: RequestOptions): Promise<T> {
+    return this.request('PUT', `/webhooks/${encodeURIComponent(webhookId)}`, { json: body }, false, options) as Promise<T>;
+  }
+
+  /** Delete a webhook */
+  deleteWebhook<T = unknown>(webhookId: string, options?: RequestOptions): Promise<T> {
+    return this.request('DELETE', `/webhooks/${encodeURIComponent(webhookId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** List a webhook's deliveries */
+  listDeliveries<T = unknown>(webhookId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/webhooks/${encodeURIComponent(webhookId)}/deliveries`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Get a delivery and its attempts */
+  getDelivery<T = unknown>(webhookId: string, deliveryId: string, options?: RequestOptions): Promise<T> {
+    return this.request('GET', `/webhooks/${encodeURIComponent(webhookId)}/deliveries/${encodeURIComponent(deliveryId)}`, undefined, false, options) as Promise<T>;
+  }
+
+  /** Send a delivery again */
+  redeliver<T = unknown>(webhookId: string, deliveryId: string, options?: RequestOptions): Promise<T> {
+    return this.request('POST', `/webhooks/${encodeURIComponent(webhookId)}/deliveries/${encodeURIComponent(deliveryId)}/redeliver`, undefined, false, options) as Promise<T>;
+  }
+}
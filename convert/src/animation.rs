@@ -0,0 +1,353 @@
+//! Animated images. The frames of animated GIF, PNG, and WebP images can be read and converted
+//! to an animated WebP.
+
+use std::io::Cursor;
+
+use image::{
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+    AnimationDecoder, DynamicImage, ImageFormat,
+};
+
+use crate::{
+    limits::BYTES_PER_PIXEL, operations, resize::resize_image, write_format, ConvertResult,
+    DecodeLimits, EncodeError, Error, ImageSizeTransform, LimitError, Operation,
+};
+
+/// A single frame of an animation.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub image: DynamicImage,
+    /// How long the frame is shown, in milliseconds.
+    pub delay_ms: u32,
+}
+
+/// Which frames of an animation to keep, to limit the size of the output and the time taken to
+/// encode it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameSelection {
+    /// Keep at most this many frames. The animation is cut off after them.
+    pub max_frames: Option<u32>,
+    /// Keep only every Nth frame, starting with the first.
+    pub frame_step: Option<u32>,
+}
+
+impl FrameSelection {
+    fn step(&self) -> usize {
+        self.frame_step.unwrap_or(1).max(1) as usize
+    }
+
+    /// Choose the frames to keep. Each skipped frame's delay is added to the kept frame before
+    /// it, so the animation plays at the same speed. No more frames are read once
+    /// `max_frames` have been kept.
+    pub fn select<E>(
+        &self,
+        frames: impl IntoIterator<Item = Result<Frame, E>>,
+    ) -> Result<Vec<Frame>, E> {
+        let step = self.step();
+        let max_frames = self
+            .max_frames
+            .map(|max| max as usize)
+            .unwrap_or(usize::MAX);
+
+        let mut selected: Vec<Frame> = Vec::new();
+        for (index, frame) in frames.into_iter().enumerate() {
+            if index % step == 0 {
+                if selected.len() >= max_frames {
+                    break;
+                }
+                selected.push(frame?);
+            } else if let Some(last) = selected.last_mut() {
+                last.delay_ms = last.delay_ms.saturating_add(frame?.delay_ms);
+            }
+        }
+
+        Ok(selected)
+    }
+}
+
+/// Read the frames of an animated image, keeping only the frames chosen by `selection`.
+/// Returns `None` if the image is not animated, or is in a format whose animations can't be
+/// read.
+pub fn decode_frames(
+    bytes: &[u8],
+    limits: &DecodeLimits,
+    selection: &FrameSelection,
+) -> Result<Option<Vec<Frame>>, Error> {
+    let read_error = |e: image::ImageError| match e {
+        image::ImageError::Limits(e) => Error::TooLarge(LimitError::Decoder(e.to_string())),
+        e => Error::read_error(None, e),
+    };
+
+    let frames = match image::guess_format(bytes) {
+        Ok(ImageFormat::Gif) => GifDecoder::with_limits(Cursor::new(bytes), limits.image_limits())
+            .map_err(read_error)?
+            .into_frames(),
+        Ok(ImageFormat::Png) => {
+            let decoder = PngDecoder::with_limits(Cursor::new(bytes), limits.image_limits())
+                .map_err(read_error)?;
+            if !decoder.is_apng() {
+                return Ok(None);
+            }
+            decoder.apng().into_frames()
+        }
+        Ok(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(Cursor::new(bytes)).map_err(read_error)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        }
+        _ => return Ok(None),
+    };
+
+    // The kept frames are all held in memory, so they count against the memory limit
+    // together.
+    let step = selection.step();
+    let mut decoded_bytes = 0u64;
+    let frames = frames.enumerate().map(|(index, frame)| {
+        let frame = frame.map_err(read_error)?;
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        let buffer = frame.into_buffer();
+
+        if index % step == 0 {
+            decoded_bytes += buffer.width() as u64 * buffer.height() as u64 * BYTES_PER_PIXEL;
+        }
+        if let Some(max) = limits.max_decoded_bytes {
+            if decoded_bytes > max {
+                return Err(Error::TooLarge(LimitError::Memory {
+                    bytes: decoded_bytes,
+                    max,
+                }));
+            }
+        }
+
+        Ok(Frame {
+            image: DynamicImage::ImageRgba8(buffer),
+            delay_ms: numer / denom.max(1),
+        })
+    });
+
+    let frames = selection.select(frames)?;
+    if frames.len() < 2 {
+        return Ok(None);
+    }
+
+    Ok(Some(frames))
+}
+
+/// Resize and transform each frame, and encode the frames as an animated image. WebP is the
+/// only output format that supports animation.
+pub fn convert_animation(
+    frames: &[Frame],
+    format: ImageFormat,
+    quality: Option<f32>,
+    effort: Option<u8>,
+    size: &ImageSizeTransform,
+    operations: &[Operation],
+) -> Result<ConvertResult, EncodeError> {
+    if format != ImageFormat::WebP {
+        return Err(EncodeError::UnsupportedFormat(format));
+    }
+
+    let frames = frames
+        .iter()
+        .map(|frame| {
+            let resized = resize_image(&frame.image, size);
+            let resized = resized.as_ref().unwrap_or(&frame.image);
+            let transformed = operations::apply_operations(resized, operations)?;
+            let image = transformed.as_ref().unwrap_or(resized).to_rgba8();
+            Ok((image, frame.delay_ms))
+        })
+        .collect::<Result<Vec<_>, EncodeError>>()?;
+
+    let (width, height) = frames
+        .first()
+        .map(|(image, _)| image.dimensions())
+        .unwrap_or_default();
+    let image = write_format::write_animated_webp(&frames, quality, effort)?;
+
+    Ok(ConvertResult {
+        width,
+        height,
+        image,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{codecs::gif::GifEncoder, Delay, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn frame(index: u32) -> Frame {
+        Frame {
+            image: DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+                4,
+                4,
+                Rgba([index as u8, 0, 0, 255]),
+            )),
+            delay_ms: 100,
+        }
+    }
+
+    fn frames(count: u32) -> impl Iterator<Item = Result<Frame, ()>> {
+        (0..count).map(|index| Ok(frame(index)))
+    }
+
+    fn first_pixels(frames: &[Frame]) -> Vec<u8> {
+        frames
+            .iter()
+            .map(|frame| frame.image.to_rgba8().get_pixel(0, 0).0[0])
+            .collect()
+    }
+
+    #[test]
+    fn keeps_every_frame_by_default() {
+        let selected = FrameSelection::default().select(frames(5)).unwrap();
+        assert_eq!(first_pixels(&selected), vec![0, 1, 2, 3, 4]);
+        assert!(selected.iter().all(|frame| frame.delay_ms == 100));
+    }
+
+    #[test]
+    fn max_frames() {
+        let selection = FrameSelection {
+            max_frames: Some(3),
+            frame_step: None,
+        };
+        let selected = selection.select(frames(10)).unwrap();
+        assert_eq!(first_pixels(&selected), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn frame_step_keeps_timing() {
+        let selection = FrameSelection {
+            max_frames: None,
+            frame_step: Some(3),
+        };
+        let selected = selection.select(frames(8)).unwrap();
+        assert_eq!(first_pixels(&selected), vec![0, 3, 6]);
+        assert_eq!(
+            selected.iter().map(|f| f.delay_ms).collect::<Vec<_>>(),
+            vec![300, 300, 200]
+        );
+    }
+
+    #[test]
+    fn step_and_max_frames() {
+        let selection = FrameSelection {
+            max_frames: Some(2),
+            frame_step: Some(2),
+        };
+        let selected = selection.select(frames(10)).unwrap();
+        assert_eq!(first_pixels(&selected), vec![0, 2]);
+        assert_eq!(
+            selected.iter().map(|f| f.delay_ms).collect::<Vec<_>>(),
+            vec![200, 200]
+        );
+    }
+
+    #[test]
+    fn stops_reading_after_max_frames() {
+        let selection = FrameSelection {
+            max_frames: Some(2),
+            frame_step: None,
+        };
+        let frames = (0..5).map(|index| {
+            if index < 3 {
+                Ok(frame(index))
+            } else {
+                Err(index)
+            }
+        });
+        assert_eq!(selection.select(frames).unwrap().len(), 2);
+    }
+
+    fn animated_gif(count: u32) -> Vec<u8> {
+        let mut output = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut output);
+            for index in 0..count {
+                let frame = image::Frame::from_parts(
+                    RgbaImage::from_pixel(8, 6, Rgba([index as u8 * 40, 0, 0, 255])),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(50, 1),
+                );
+                encoder.encode_frame(frame).unwrap();
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn decodes_gif_frames() {
+        let selection = FrameSelection {
+            max_frames: Some(2),
+            frame_step: Some(2),
+        };
+        let frames = decode_frames(&animated_gif(6), &DecodeLimits::default(), &selection)
+            .unwrap()
+            .expect("animated");
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].delay_ms, 100);
+        assert_eq!(frames[0].image.width(), 8);
+    }
+
+    #[test]
+    fn still_images_are_not_animated() {
+        let frames = decode_frames(
+            &animated_gif(1),
+            &DecodeLimits::default(),
+            &FrameSelection::default(),
+        )
+        .unwrap();
+        assert!(frames.is_none());
+    }
+
+    #[test]
+    fn animation_memory_limit() {
+        let limits = DecodeLimits {
+            max_decoded_bytes: Some(8 * 6 * 4 * 3),
+            ..Default::default()
+        };
+        let err = decode_frames(&animated_gif(4), &limits, &FrameSelection::default()).unwrap_err();
+        assert!(
+            matches!(err, Error::TooLarge(LimitError::Memory { .. })),
+            "{err:?}"
+        );
+
+        let selection = FrameSelection {
+            max_frames: Some(3),
+            frame_step: None,
+        };
+        assert!(decode_frames(&animated_gif(4), &limits, &selection).is_ok());
+    }
+
+    #[test]
+    fn converts_to_animated_webp() {
+        let frames = decode_frames(
+            &animated_gif(3),
+            &DecodeLimits::default(),
+            &FrameSelection::default(),
+        )
+        .unwrap()
+        .unwrap();
+        let size = ImageSizeTransform {
+            width: Some(4),
+            height: None,
+            preserve_aspect_ratio: true,
+        };
+        let result =
+            convert_animation(&frames, ImageFormat::WebP, Some(80.0), None, &size, &[]).unwrap();
+        assert_eq!((result.width, result.height), (4, 3));
+
+        let decoder = WebPDecoder::new(Cursor::new(result.image)).unwrap();
+        assert!(decoder.has_animation());
+        assert_eq!(decoder.into_frames().count(), 3);
+
+        assert!(matches!(
+            convert_animation(&frames, ImageFormat::Png, None, None, &size, &[]),
+            Err(EncodeError::UnsupportedFormat(ImageFormat::Png))
+        ));
+    }
+}
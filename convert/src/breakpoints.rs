@@ -0,0 +1,205 @@
+use image::DynamicImage;
+
+use crate::{convert, EncodeError, ImageSizeTransform};
+
+/// Settings for automatically choosing responsive image widths.
+#[derive(Debug, Clone)]
+pub struct BreakpointSettings {
+    /// The smallest width to generate.
+    pub min_width: u32,
+    /// The largest width to generate. This is clamped to the width of the source image.
+    pub max_width: u32,
+    /// The approximate difference in file size between adjacent widths.
+    pub byte_step: u32,
+    /// The maximum number of widths to generate, including the minimum and maximum. With a
+    /// limit of 1, only the maximum width is generated.
+    pub max_sizes: Option<u32>,
+}
+
+impl BreakpointSettings {
+    /// Return true if only the maximum width is generated, so there is nothing to choose.
+    fn single_width(&self) -> bool {
+        self.min_width >= self.max_width || self.max_sizes.is_some_and(|max| max <= 1)
+    }
+}
+
+/// Calculate the widths to generate, given the encoded size of the image at the minimum and
+/// maximum widths.
+///
+/// Encoded size is assumed to scale linearly with pixel area, which is not exact but close enough
+/// to space the sizes out evenly.
+pub fn calculate_breakpoints(
+    settings: &BreakpointSettings,
+    min_bytes: usize,
+    max_bytes: usize,
+) -> Vec<u32> {
+    let min_width = settings.min_width.min(settings.max_width);
+    let max_width = settings.max_width;
+
+    if settings.single_width() || max_bytes <= min_bytes || settings.byte_step == 0 {
+        return vec![max_width];
+    }
+
+    let byte_range = (max_bytes - min_bytes) as f64;
+    let mut steps = (byte_range / settings.byte_step as f64).ceil() as u32;
+    if let Some(max_sizes) = settings.max_sizes {
+        steps = steps.min(max_sizes - 1);
+    }
+
+    let min_area = (min_width as f64).powi(2);
+    let area_range = (max_width as f64).powi(2) - min_area;
+
+    let mut widths = Vec::with_capacity(steps as usize + 1);
+    widths.push(min_width);
+    for step in 1..steps {
+        let fraction = step as f64 / steps as f64;
+        let width = (min_area + fraction * area_range).sqrt().round() as u32;
+        if widths.last().copied() != Some(width) {
+            widths.push(width);
+        }
+    }
+
+    if widths.last().copied() != Some(max_width) {
+        widths.push(max_width);
+    }
+
+    widths
+}
+
+/// Choose the widths at which to generate an image by encoding it at the minimum and maximum
+/// widths and then interpolating between them.
+pub fn choose_breakpoints(
+    image: &DynamicImage,
+    format: image::ImageFormat,
+    quality: Option<f32>,
+    settings: &BreakpointSettings,
+) -> Result<Vec<u32>, EncodeError> {
+    let settings = BreakpointSettings {
+        max_width: settings.max_width.min(image.width()),
+        ..settings.clone()
+    };
+
+    if settings.single_width() {
+        return Ok(vec![settings.max_width]);
+    }
+
+    let encoded_size = |width: u32| {
+        let size = ImageSizeTransform {
+            width: Some(width),
+            height: None,
+            preserve_aspect_ratio: true,
+        };
+
+        convert(image, format, quality, &size, &[]).map(|result| result.image.len())
+    };
+
+    let min_bytes = encoded_size(settings.min_width)?;
+    let max_bytes = encoded_size(settings.max_width)?;
+
+    Ok(calculate_breakpoints(&settings, min_bytes, max_bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(min_width: u32, max_width: u32, byte_step: u32) -> BreakpointSettings {
+        BreakpointSettings {
+            min_width,
+            max_width,
+            byte_step,
+            max_sizes: None,
+        }
+    }
+
+    #[test]
+    fn evenly_spaced_by_area() {
+        let widths = calculate_breakpoints(&settings(100, 500, 20_000), 10_000, 90_000);
+        assert_eq!(widths, vec![100, 265, 361, 436, 500]);
+    }
+
+    #[test]
+    fn single_step() {
+        let widths = calculate_breakpoints(&settings(100, 500, 100_000), 10_000, 90_000);
+        assert_eq!(widths, vec![100, 500]);
+    }
+
+    #[test]
+    fn max_sizes() {
+        let widths = calculate_breakpoints(
+            &BreakpointSettings {
+                max_sizes: Some(3),
+                ..settings(100, 500, 1_000)
+            },
+            10_000,
+            90_000,
+        );
+        assert_eq!(widths, vec![100, 361, 500]);
+    }
+
+    #[test]
+    fn max_sizes_of_one_or_less() {
+        for max_sizes in [0, 1] {
+            let widths = calculate_breakpoints(
+                &BreakpointSettings {
+                    max_sizes: Some(max_sizes),
+                    ..settings(100, 500, 1_000)
+                },
+                10_000,
+                90_000,
+            );
+            assert_eq!(widths, vec![500], "max_sizes {max_sizes}");
+        }
+
+        let widths = calculate_breakpoints(
+            &BreakpointSettings {
+                max_sizes: Some(2),
+                ..settings(100, 500, 1_000)
+            },
+            10_000,
+            90_000,
+        );
+        assert_eq!(widths, vec![100, 500]);
+    }
+
+    #[test]
+    fn min_equals_max() {
+        let widths = calculate_breakpoints(&settings(500, 500, 20_000), 10_000, 90_000);
+        assert_eq!(widths, vec![500]);
+    }
+
+    #[test]
+    fn no_growth_in_size() {
+        let widths = calculate_breakpoints(&settings(100, 500, 20_000), 10_000, 10_000);
+        assert_eq!(widths, vec![500]);
+    }
+
+    #[test]
+    fn choose_clamps_to_image_width() {
+        let image = DynamicImage::new_rgb8(300, 200);
+        let widths = choose_breakpoints(
+            &image,
+            image::ImageFormat::Png,
+            None,
+            &settings(400, 1000, 10),
+        )
+        .unwrap();
+        assert_eq!(widths, vec![300]);
+    }
+
+    #[test]
+    fn choose_single_size() {
+        let image = DynamicImage::new_rgb8(300, 200);
+        let widths = choose_breakpoints(
+            &image,
+            image::ImageFormat::Png,
+            None,
+            &BreakpointSettings {
+                max_sizes: Some(1),
+                ..settings(100, 1000, 10)
+            },
+        )
+        .unwrap();
+        assert_eq!(widths, vec![300]);
+    }
+}
@@ -0,0 +1,37 @@
+//! Find and decode QR codes and barcodes in images.
+
+use image::DynamicImage;
+
+/// A code found in an image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedCode {
+    /// The type of code, such as `qrcode` or `ean 13`.
+    pub format: String,
+    pub payload: String,
+}
+
+/// Decode every code in the image.
+pub fn detect_codes(image: &DynamicImage) -> Vec<DecodedCode> {
+    let luma = image.to_luma8();
+    let (width, height) = luma.dimensions();
+
+    // rxing returns an error when it doesn't find any codes.
+    let Ok(results) = rxing::helpers::detect_multiple_in_luma(luma.into_raw(), width, height)
+    else {
+        return Vec::new();
+    };
+
+    let mut codes: Vec<DecodedCode> = Vec::with_capacity(results.len());
+    for result in results {
+        let code = DecodedCode {
+            format: result.getBarcodeFormat().to_string(),
+            payload: result.getText().to_string(),
+        };
+
+        if !code.payload.is_empty() && !codes.contains(&code) {
+            codes.push(code);
+        }
+    }
+
+    codes
+}
@@ -1,6 +1,6 @@
 use thiserror::Error;
 
-use crate::EncodeError;
+use crate::{EncodeError, LimitError};
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -11,6 +11,8 @@ pub enum Error {
     },
     #[error(transparent)]
     Encode(#[from] EncodeError),
+    #[error(transparent)]
+    TooLarge(#[from] LimitError),
 }
 
 impl Error {
@@ -0,0 +1,59 @@
+//! Read the EXIF fields that tagging rules can match on.
+
+use ::exif::{Exif, In, Reader, Tag, Value};
+
+/// EXIF fields from an image. Fields that are missing from the image are `None`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExifFields {
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub lens_model: Option<String>,
+    /// When the photo was taken, in the EXIF `YYYY:MM:DD HH:MM:SS` format.
+    pub captured: Option<String>,
+}
+
+fn ascii_field(exif: &Exif, tag: Tag) -> Option<String> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+    let Value::Ascii(values) = &field.value else {
+        return None;
+    };
+
+    values
+        .first()
+        .map(|v| {
+            String::from_utf8_lossy(v)
+                .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+                .to_string()
+        })
+        .filter(|v| !v.is_empty())
+}
+
+/// Read the EXIF fields from an encoded image. Returns `None` if the image has no EXIF data.
+pub fn read_exif(bytes: &[u8]) -> Option<ExifFields> {
+    let exif = Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(bytes))
+        .ok()?;
+
+    Some(ExifFields {
+        camera_make: ascii_field(&exif, Tag::Make),
+        camera_model: ascii_field(&exif, Tag::Model),
+        lens_model: ascii_field(&exif, Tag::LensModel),
+        captured: ascii_field(&exif, Tag::DateTimeOriginal)
+            .or_else(|| ascii_field(&exif, Tag::DateTime)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_exif() {
+        let bytes = std::fs::read(
+            std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../fixtures/test-input.png"),
+        )
+        .unwrap();
+        assert_eq!(read_exif(&bytes).and_then(|e| e.camera_model), None);
+        assert_eq!(read_exif(b"not an image"), None);
+    }
+}
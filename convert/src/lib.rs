@@ -4,14 +4,33 @@ use image::{
     error::DecodingError, flat::SampleLayout, DynamicImage, FlatSamples, ImageBuffer, ImageError,
     Rgb, Rgba,
 };
+pub use limits::{DecodeLimits, LimitError};
+pub use operations::{Operation, WatermarkImage, WatermarkPosition};
 use resize::resize_image;
 pub use resize::ImageSizeTransform;
 pub use write_format::EncodeError;
 
+pub mod animation;
+pub mod breakpoints;
+#[cfg(feature = "barcodes")]
+pub mod codes;
 mod error;
+pub mod exif;
+pub mod limits;
+#[cfg(feature = "ocr")]
+pub mod ocr;
+pub mod operations;
 pub mod resize;
 pub mod write_format;
 
+/// The cargo features this crate was built with.
+pub const ENABLED_FEATURES: &[&str] = &[
+    #[cfg(feature = "codec-dav1d")]
+    "codec-dav1d",
+    #[cfg(feature = "codec-aom")]
+    "codec-aom",
+];
+
 fn load_avif(bytes: &[u8]) -> eyre::Result<DynamicImage> {
     let pixels = libavif::decode_rgb(bytes).map_err(|e| {
         ImageError::Decoding(DecodingError::new(image::ImageFormat::Avif.into(), e))
@@ -102,22 +121,45 @@ fn load_heic(bytes: &[u8]) -> eyre::Result<DynamicImage> {
     Ok(output)
 }
 
-pub fn image_from_bytes(bytes: &[u8]) -> Result<DynamicImage, Error> {
-    let format = imageinfo::ImageInfo::from_raw_data(bytes).map(|i| i.format);
+/// Decode an image, after checking that it fits within `limits`.
+pub fn image_from_bytes(bytes: &[u8], limits: &DecodeLimits) -> Result<DynamicImage, Error> {
+    let info = imageinfo::ImageInfo::from_raw_data(bytes);
+    if let Ok(info) = &info {
+        let width = u32::try_from(info.size.width).unwrap_or(u32::MAX);
+        let height = u32::try_from(info.size.height).unwrap_or(u32::MAX);
+        limits.check(width, height)?;
+    }
+
+    let format = info.map(|i| i.format);
     let result = match format {
         // Some AVIF format files don't parse well using the image crate, so we
         // use libavif instead.
         Ok(imageinfo::ImageFormat::AVIF) => load_avif(bytes),
         Ok(imageinfo::ImageFormat::HEIC) => load_heic(bytes),
-        _ => image::load_from_memory(bytes).map_err(eyre::Report::from),
+        _ => load_with_image_crate(bytes, limits),
     };
 
-    result.map_err(|error| Error::Read {
-        format: format.ok(),
-        error,
+    result.map_err(|error| match error.downcast::<ImageError>() {
+        Ok(ImageError::Limits(e)) => Error::TooLarge(LimitError::Decoder(e.to_string())),
+        Ok(e) => Error::Read {
+            format: format.ok(),
+            error: e.into(),
+        },
+        Err(error) => Error::Read {
+            format: format.ok(),
+            error,
+        },
     })
 }
 
+/// Decode the image using the `image` crate, which enforces the limits itself in case the
+/// header couldn't be read ahead of time.
+fn load_with_image_crate(bytes: &[u8], limits: &DecodeLimits) -> eyre::Result<DynamicImage> {
+    let mut reader = image::io::Reader::new(std::io::Cursor::new(bytes)).with_guessed_format()?;
+    reader.limits(limits.image_limits());
+    Ok(reader.decode()?)
+}
+
 pub struct ConvertResult {
     pub width: u32,
     pub height: u32,
@@ -129,16 +171,32 @@ pub fn convert(
     format: image::ImageFormat,
     quality: Option<f32>,
     size: &ImageSizeTransform,
+    operations: &[Operation],
+) -> Result<ConvertResult, EncodeError> {
+    convert_with_effort(image, format, quality, None, size, operations)
+}
+
+/// Like [convert], but limits the effort that the encoder spends. See
+/// [write_format::write_image_with_effort].
+pub fn convert_with_effort(
+    image: &DynamicImage,
+    format: image::ImageFormat,
+    quality: Option<f32>,
+    effort: Option<u8>,
+    size: &ImageSizeTransform,
+    operations: &[Operation],
 ) -> Result<ConvertResult, EncodeError> {
     let resized = resize_image(image, size);
+    let resized = resized.as_ref().unwrap_or(image);
+    let transformed = operations::apply_operations(resized, operations)?;
     let mut output = Vec::new();
 
-    let convert_input = resized.as_ref().unwrap_or(image);
+    let convert_input = transformed.as_ref().unwrap_or(resized);
 
     let width = convert_input.width();
     let height = convert_input.height();
 
-    write_format::write_image(convert_input, format, quality, &mut output)?;
+    write_format::write_image_with_effort(convert_input, format, quality, effort, &mut output)?;
     Ok(ConvertResult {
         width,
         height,
@@ -146,22 +204,65 @@ pub fn convert(
     })
 }
 
+/// Encode a full size copy of the image, normalized to 8-bit RGB or RGBA so that images from
+/// unusual formats and bit depths end up in a consistent form. `format` should be a lossless
+/// format.
+pub fn archival_copy(
+    image: &DynamicImage,
+    format: image::ImageFormat,
+) -> Result<ConvertResult, EncodeError> {
+    let normalized = if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(image.to_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+    };
+
+    let mut output = Vec::new();
+    // The WebP encoder is lossless at quality 100, and PNG ignores the quality.
+    write_format::write_image(&normalized, format, Some(100.0), &mut output)?;
+    Ok(ConvertResult {
+        width: normalized.width(),
+        height: normalized.height(),
+        image: output,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use std::{io::Read, path::PathBuf};
 
     use image::DynamicImage;
 
-    use crate::write_format::write_image;
+    use crate::{write_format::write_image, DecodeLimits, Error, LimitError};
 
-    fn read_test_image(filename: &str) -> DynamicImage {
+    fn read_test_file(filename: &str) -> Vec<u8> {
         let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
             .join("../fixtures")
             .join(filename);
         let mut file = std::fs::File::open(&path).expect("opening file");
         let mut buffer = Vec::new();
         file.read_to_end(&mut buffer).expect("reading file");
-        super::image_from_bytes(buffer.as_slice()).expect("parsing file")
+        buffer
+    }
+
+    fn read_test_image(filename: &str) -> DynamicImage {
+        let buffer = read_test_file(filename);
+        super::image_from_bytes(buffer.as_slice(), &Default::default()).expect("parsing file")
+    }
+
+    #[test]
+    fn rejects_images_over_limits() {
+        let buffer = read_test_file("test-input.png");
+        let limits = DecodeLimits {
+            max_pixels: Some(1000),
+            ..Default::default()
+        };
+
+        let err = super::image_from_bytes(buffer.as_slice(), &limits).unwrap_err();
+        assert!(
+            matches!(err, Error::TooLarge(LimitError::Pixels { .. })),
+            "{err:?}"
+        );
     }
 
     #[test]
@@ -203,6 +304,21 @@ mod tests {
         assert_eq!(image.height(), 445);
     }
 
+    #[test]
+    fn archival_webp_is_lossless() {
+        let image = read_test_image("test-input.png");
+        let result = super::archival_copy(&image, image::ImageFormat::WebP).unwrap();
+        assert_eq!((result.width, result.height), (667, 445));
+
+        let decoded = super::image_from_bytes(&result.image, &Default::default()).unwrap();
+        let expected = if image.color().has_alpha() {
+            DynamicImage::ImageRgba8(image.to_rgba8())
+        } else {
+            DynamicImage::ImageRgb8(image.to_rgb8())
+        };
+        assert_eq!(decoded.to_rgba8(), expected.to_rgba8());
+    }
+
     #[test]
     fn read_webp() {
         let image = read_test_image("test-input.webp");
@@ -0,0 +1,136 @@
+use thiserror::Error;
+
+/// Decoded images are assumed to take this many bytes per pixel, which matches 8-bit RGBA.
+pub(crate) const BYTES_PER_PIXEL: u64 = 4;
+
+/// Limits on the images that will be decoded. These guard against images which are small when
+/// compressed but would take huge amounts of memory once decoded.
+#[derive(Debug, Clone, Default)]
+pub struct DecodeLimits {
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    /// The maximum value of width * height.
+    pub max_pixels: Option<u64>,
+    /// The maximum amount of memory that the decoded image may use.
+    pub max_decoded_bytes: Option<u64>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LimitError {
+    #[error("Image width {width} is larger than the maximum of {max}")]
+    Width { width: u32, max: u32 },
+    #[error("Image height {height} is larger than the maximum of {max}")]
+    Height { height: u32, max: u32 },
+    #[error("Image has {pixels} pixels, more than the maximum of {max}")]
+    Pixels { pixels: u64, max: u64 },
+    #[error("Decoding the image would use {bytes} bytes, more than the maximum of {max}")]
+    Memory { bytes: u64, max: u64 },
+    #[error("Image exceeded decoding limits: {0}")]
+    Decoder(String),
+}
+
+impl DecodeLimits {
+    /// Check the dimensions of an image, as read from its header, against the limits.
+    pub fn check(&self, width: u32, height: u32) -> Result<(), LimitError> {
+        if let Some(max) = self.max_width {
+            if width > max {
+                return Err(LimitError::Width { width, max });
+            }
+        }
+
+        if let Some(max) = self.max_height {
+            if height > max {
+                return Err(LimitError::Height { height, max });
+            }
+        }
+
+        let pixels = width as u64 * height as u64;
+        if let Some(max) = self.max_pixels {
+            if pixels > max {
+                return Err(LimitError::Pixels { pixels, max });
+            }
+        }
+
+        let bytes = pixels * BYTES_PER_PIXEL;
+        if let Some(max) = self.max_decoded_bytes {
+            if bytes > max {
+                return Err(LimitError::Memory { bytes, max });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The equivalent limits for the decoders in the `image` crate.
+    pub(crate) fn image_limits(&self) -> image::io::Limits {
+        let mut limits = image::io::Limits::no_limits();
+        limits.max_image_width = self.max_width;
+        limits.max_image_height = self.max_height;
+        limits.max_alloc = self.max_decoded_bytes;
+        limits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> DecodeLimits {
+        DecodeLimits {
+            max_width: Some(1000),
+            max_height: Some(2000),
+            max_pixels: Some(1_000_000),
+            max_decoded_bytes: Some(3_000_000),
+        }
+    }
+
+    #[test]
+    fn within_limits() {
+        assert_eq!(limits().check(500, 1000), Ok(()));
+        assert_eq!(DecodeLimits::default().check(100_000, 100_000), Ok(()));
+    }
+
+    #[test]
+    fn too_wide() {
+        assert_eq!(
+            limits().check(1001, 10),
+            Err(LimitError::Width {
+                width: 1001,
+                max: 1000
+            })
+        );
+    }
+
+    #[test]
+    fn too_tall() {
+        assert_eq!(
+            limits().check(10, 2001),
+            Err(LimitError::Height {
+                height: 2001,
+                max: 2000
+            })
+        );
+    }
+
+    #[test]
+    fn too_many_pixels() {
+        assert_eq!(
+            limits().check(1000, 1001),
+            Err(LimitError::Pixels {
+                pixels: 1_001_000,
+                max: 1_000_000
+            })
+        );
+    }
+
+    #[test]
+    fn too_much_memory() {
+        assert_eq!(
+            limits().check(1000, 800),
+            Err(LimitError::Memory {
+                bytes: 3_200_000,
+                max: 3_000_000
+            })
+        );
+    }
+}
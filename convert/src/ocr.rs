@@ -0,0 +1,18 @@
+//! Extract text from images with tesseract, so that screenshots and scans can be searched.
+
+use image::DynamicImage;
+use tesseract::Tesseract;
+
+/// Extract the text in an image. `language` is a tesseract language code such as `eng`, or
+/// several joined with `+`. Returns `None` when no text was found.
+pub fn extract_text(image: &DynamicImage, language: &str) -> Result<Option<String>, eyre::Report> {
+    let gray = image.to_luma8();
+    let (width, height) = (gray.width() as i32, gray.height() as i32);
+
+    let text = Tesseract::new(None, Some(language))?
+        .set_frame(gray.as_raw(), width, height, 1, width)?
+        .get_text()?;
+
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    Ok(Some(text).filter(|t| !t.is_empty()))
+}
@@ -0,0 +1,261 @@
+use std::sync::{Arc, OnceLock};
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+
+use crate::EncodeError;
+
+/// Where a watermark is drawn on the image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center,
+}
+
+impl WatermarkPosition {
+    /// The top left corner of a watermark of size `mark` on an image of size `image`, keeping
+    /// `margin` pixels from the edges that it is placed against.
+    fn offset(&self, image: (u32, u32), mark: (u32, u32), margin: u32) -> (i64, i64) {
+        let start = margin as i64;
+        let end = |image: u32, mark: u32| image as i64 - mark as i64 - margin as i64;
+        let center = |image: u32, mark: u32| (image as i64 - mark as i64) / 2;
+
+        match self {
+            Self::TopLeft => (start, start),
+            Self::TopRight => (end(image.0, mark.0), start),
+            Self::BottomLeft => (start, end(image.1, mark.1)),
+            Self::BottomRight => (end(image.0, mark.0), end(image.1, mark.1)),
+            Self::Center => (center(image.0, mark.0), center(image.1, mark.1)),
+        }
+    }
+}
+
+/// The image drawn by a watermark operation. It is decoded the first time that it is drawn, so
+/// that loading the operations for a request that doesn't convert anything stays cheap.
+#[derive(Debug)]
+pub struct WatermarkImage {
+    bytes: Vec<u8>,
+    decoded: OnceLock<Result<DynamicImage, String>>,
+}
+
+impl WatermarkImage {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            decoded: OnceLock::new(),
+        }
+    }
+
+    fn image(&self) -> Result<&DynamicImage, EncodeError> {
+        self.decoded
+            .get_or_init(|| image::load_from_memory(&self.bytes).map_err(|e| e.to_string()))
+            .as_ref()
+            .map_err(|e| EncodeError::StringError(format!("Reading watermark: {e}")))
+    }
+}
+
+/// An image transformation to run after the image is resized.
+#[derive(Debug, Clone)]
+pub enum Operation {
+    Sharpen {
+        sigma: f32,
+        threshold: i32,
+    },
+    Blur {
+        sigma: f32,
+    },
+    Brighten {
+        value: i32,
+    },
+    Contrast {
+        value: f32,
+    },
+    Grayscale,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipHorizontal,
+    FlipVertical,
+    /// Draw an image, such as a logo, over the output.
+    Watermark {
+        image: Arc<WatermarkImage>,
+        position: WatermarkPosition,
+        /// From 0 for invisible to 1 for fully opaque.
+        opacity: f32,
+        /// The width of the watermark, as a fraction of the output's width.
+        scale: f32,
+    },
+}
+
+impl Operation {
+    pub fn apply(&self, image: &DynamicImage) -> Result<DynamicImage, EncodeError> {
+        let output = match self {
+            Self::Sharpen { sigma, threshold } => image.unsharpen(*sigma, *threshold),
+            Self::Blur { sigma } => image.blur(*sigma),
+            Self::Brighten { value } => image.brighten(*value),
+            Self::Contrast { value } => image.adjust_contrast(*value),
+            Self::Grayscale => image.grayscale(),
+            Self::Rotate90 => image.rotate90(),
+            Self::Rotate180 => image.rotate180(),
+            Self::Rotate270 => image.rotate270(),
+            Self::FlipHorizontal => image.fliph(),
+            Self::FlipVertical => image.flipv(),
+            Self::Watermark {
+                image: mark,
+                position,
+                opacity,
+                scale,
+            } => watermark(image, mark.image()?, *position, *opacity, *scale),
+        };
+
+        Ok(output)
+    }
+}
+
+fn watermark(
+    image: &DynamicImage,
+    mark: &DynamicImage,
+    position: WatermarkPosition,
+    opacity: f32,
+    scale: f32,
+) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    let mark_width = ((width as f32 * scale.clamp(0.0, 1.0)).round() as u32).clamp(1, width);
+    let mut mark = mark
+        .resize(mark_width, height, FilterType::Lanczos3)
+        .to_rgba8();
+
+    let opacity = opacity.clamp(0.0, 1.0);
+    if opacity < 1.0 {
+        for pixel in mark.pixels_mut() {
+            pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+        }
+    }
+
+    let margin = width.min(height) / 50;
+    let (x, y) = position.offset((width, height), mark.dimensions(), margin);
+
+    let mut output = image.clone();
+    image::imageops::overlay(&mut output, &mark, x, y);
+    output
+}
+
+/// Run each operation in order. Returns None if there are no operations to run.
+pub fn apply_operations(
+    image: &DynamicImage,
+    operations: &[Operation],
+) -> Result<Option<DynamicImage>, EncodeError> {
+    let Some((first, rest)) = operations.split_first() else {
+        return Ok(None);
+    };
+
+    let mut output = first.apply(image)?;
+    for op in rest {
+        output = op.apply(&output)?;
+    }
+    Ok(Some(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{ImageFormat, RgbImage, Rgba, RgbaImage};
+
+    use super::*;
+
+    #[test]
+    fn no_operations() {
+        let image = DynamicImage::new_rgb8(100, 50);
+        assert!(apply_operations(&image, &[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn operations_run_in_order() {
+        let image = DynamicImage::new_rgb8(100, 50);
+        let output = apply_operations(
+            &image,
+            &[
+                Operation::Rotate90,
+                Operation::Sharpen {
+                    sigma: 1.0,
+                    threshold: 2,
+                },
+            ],
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(output.dimensions(), (50, 100));
+    }
+
+    fn png(image: RgbaImage) -> Vec<u8> {
+        let mut output = Vec::new();
+        DynamicImage::ImageRgba8(image)
+            .write_to(&mut Cursor::new(&mut output), ImageFormat::Png)
+            .unwrap();
+        output
+    }
+
+    fn watermark_op(position: WatermarkPosition, opacity: f32) -> Operation {
+        Operation::Watermark {
+            image: Arc::new(WatermarkImage::new(png(RgbaImage::from_pixel(
+                10,
+                10,
+                Rgba([255, 255, 255, 255]),
+            )))),
+            position,
+            opacity,
+            scale: 0.1,
+        }
+    }
+
+    #[test]
+    fn watermark_positions() {
+        let image = DynamicImage::ImageRgb8(RgbImage::new(200, 100));
+
+        let output = watermark_op(WatermarkPosition::BottomRight, 1.0)
+            .apply(&image)
+            .unwrap();
+        assert_eq!(output.color(), image.color());
+        // The watermark is 20 pixels wide, with a 2 pixel margin.
+        assert_eq!(output.get_pixel(190, 90), Rgba([255, 255, 255, 255]));
+        assert_eq!(output.get_pixel(199, 99), Rgba([0, 0, 0, 255]));
+        assert_eq!(output.get_pixel(10, 10), Rgba([0, 0, 0, 255]));
+
+        let output = watermark_op(WatermarkPosition::TopLeft, 1.0)
+            .apply(&image)
+            .unwrap();
+        assert_eq!(output.get_pixel(2, 2), Rgba([255, 255, 255, 255]));
+        assert_eq!(output.get_pixel(190, 90), Rgba([0, 0, 0, 255]));
+
+        let output = watermark_op(WatermarkPosition::Center, 1.0)
+            .apply(&image)
+            .unwrap();
+        assert_eq!(output.get_pixel(100, 50), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn watermark_opacity() {
+        let image = DynamicImage::ImageRgb8(RgbImage::new(200, 100));
+        let output = watermark_op(WatermarkPosition::Center, 0.5)
+            .apply(&image)
+            .unwrap();
+        let pixel = output.get_pixel(100, 50);
+        assert!((120..=135).contains(&pixel[0]), "{pixel:?}");
+    }
+
+    #[test]
+    fn unreadable_watermark() {
+        let op = Operation::Watermark {
+            image: Arc::new(WatermarkImage::new(b"not an image".to_vec())),
+            position: WatermarkPosition::default(),
+            opacity: 1.0,
+            scale: 0.1,
+        };
+        assert!(op.apply(&DynamicImage::new_rgb8(100, 50)).is_err());
+    }
+}
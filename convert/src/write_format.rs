@@ -1,6 +1,6 @@
 use std::{borrow::Cow, io::Write};
 
-use image::{DynamicImage, GenericImageView, ImageEncoder, ImageFormat};
+use image::{DynamicImage, GenericImageView, ImageEncoder, ImageFormat, RgbaImage};
 use rgb::FromSlice;
 use thiserror::Error;
 
@@ -13,10 +13,22 @@ fn to_8bit(image: &'_ DynamicImage) -> Cow<'_, DynamicImage> {
     }
 }
 
-fn write_png(image: &DynamicImage, writer: impl Write) -> Result<(), image::ImageError> {
+/// The highest encode effort. Higher efforts give smaller files but take longer to encode.
+pub const MAX_EFFORT: u8 = 10;
+
+fn write_png(
+    image: &DynamicImage,
+    effort: Option<u8>,
+    writer: impl Write,
+) -> Result<(), image::ImageError> {
+    let compression = match effort {
+        Some(0..=3) => image::codecs::png::CompressionType::Fast,
+        Some(4..=6) => image::codecs::png::CompressionType::Default,
+        _ => image::codecs::png::CompressionType::Best,
+    };
     let encoder = image::codecs::png::PngEncoder::new_with_quality(
         writer,
-        image::codecs::png::CompressionType::Best,
+        compression,
         image::codecs::png::FilterType::Adaptive,
     );
 
@@ -28,8 +40,9 @@ fn write_png(image: &DynamicImage, writer: impl Write) -> Result<(), image::Imag
 fn write_webp(
     image: &DynamicImage,
     quality: Option<f32>,
+    effort: Option<u8>,
     mut writer: impl Write,
-) -> Result<(), std::io::Error> {
+) -> Result<(), EncodeError> {
     let format = if image.color().has_alpha() {
         webp::PixelLayout::Rgba
     } else {
@@ -40,13 +53,60 @@ fn write_webp(
     let (width, height) = image.dimensions();
     let quality = quality.unwrap_or(70.0);
     let encoder = webp::Encoder::new(image.as_bytes(), format, width, height);
-    let output = if quality < 100.0 {
-        encoder.encode(quality)
-    } else {
-        encoder.encode_lossless()
+    let output = match effort {
+        Some(effort) => encoder
+            .encode_advanced(&webp_config(quality, Some(effort))?)
+            .map_err(|e| EncodeError::StringError(format!("WebP encoding failed: {e:?}")))?,
+        None if quality < 100.0 => encoder.encode(quality),
+        None => encoder.encode_lossless(),
     };
 
-    writer.write_all(&output)
+    writer.write_all(&output)?;
+    Ok(())
+}
+
+/// The WebP encoder settings for a quality and effort.
+fn webp_config(quality: f32, effort: Option<u8>) -> Result<webp::WebPConfig, EncodeError> {
+    let mut config = webp::WebPConfig::new()
+        .map_err(|_| EncodeError::StringError("Invalid WebP config".to_string()))?;
+    config.quality = quality;
+    config.lossless = i32::from(quality >= 100.0);
+    if let Some(effort) = effort {
+        // libwebp's method goes from 0 (fastest) to 6 (slowest).
+        config.method = i32::from(effort.min(MAX_EFFORT)) * 6 / i32::from(MAX_EFFORT);
+    }
+    Ok(config)
+}
+
+/// Encode frames, each with its delay in milliseconds, as an animated WebP. The frames must all
+/// be the same size.
+pub(crate) fn write_animated_webp(
+    frames: &[(RgbaImage, u32)],
+    quality: Option<f32>,
+    effort: Option<u8>,
+) -> Result<Vec<u8>, EncodeError> {
+    let (width, height) = frames
+        .first()
+        .map(|(image, _)| image.dimensions())
+        .ok_or_else(|| EncodeError::StringError("An animation needs frames".to_string()))?;
+    let config = webp_config(quality.unwrap_or(70.0), effort)?;
+
+    let mut encoder = webp::AnimEncoder::new(width, height, &config);
+    let mut timestamp = 0i32;
+    for (image, delay_ms) in frames {
+        encoder.add_frame(webp::AnimFrame::from_rgba(
+            image.as_raw(),
+            width,
+            height,
+            timestamp,
+        ));
+        timestamp = timestamp.saturating_add(i32::try_from(*delay_ms).unwrap_or(i32::MAX));
+    }
+
+    let output = encoder
+        .try_encode()
+        .map_err(|e| EncodeError::StringError(format!("WebP encoding failed: {e:?}")))?;
+    Ok(output.to_vec())
 }
 
 fn write_jpeg(
@@ -65,6 +125,7 @@ fn write_jpeg(
 fn write_avif(
     image: &DynamicImage,
     quality: Option<f32>,
+    effort: Option<u8>,
     mut writer: impl Write,
 ) -> Result<(), EncodeError> {
     let quality = quality.unwrap_or(60.0);
@@ -76,7 +137,7 @@ fn write_avif(
     let encoder = ravif::Encoder::new()
         .with_quality(quality)
         .with_alpha_quality(alpha_quality)
-        .with_speed(4);
+        .with_speed(avif_speed(effort));
 
     let image = to_8bit(image);
 
@@ -95,16 +156,36 @@ fn write_avif(
     Ok(())
 }
 
+/// Convert an effort to a ravif speed, which goes from 1 (slowest) to 10 (fastest).
+fn avif_speed(effort: Option<u8>) -> u8 {
+    match effort {
+        Some(effort) => (MAX_EFFORT + 1).saturating_sub(effort).clamp(1, 10),
+        None => 4,
+    }
+}
+
 pub fn write_image(
     image: &DynamicImage,
     output_format: ImageFormat,
     quality: Option<f32>,
     writer: impl Write,
+) -> Result<(), EncodeError> {
+    write_image_with_effort(image, output_format, quality, None, writer)
+}
+
+/// Write an image, spending at most the given effort on the encode. The effort goes from 0 to
+/// [MAX_EFFORT], and the encoder's default is used if it is `None`. JPEG encoding ignores it.
+pub fn write_image_with_effort(
+    image: &DynamicImage,
+    output_format: ImageFormat,
+    quality: Option<f32>,
+    effort: Option<u8>,
+    writer: impl Write,
 ) -> Result<(), EncodeError> {
     match output_format {
-        ImageFormat::Png => write_png(image, writer)?,
-        ImageFormat::WebP => write_webp(image, quality, writer)?,
-        ImageFormat::Avif => write_avif(image, quality, writer)?,
+        ImageFormat::Png => write_png(image, effort, writer)?,
+        ImageFormat::WebP => write_webp(image, quality, effort, writer)?,
+        ImageFormat::Avif => write_avif(image, quality, effort, writer)?,
         ImageFormat::Jpeg => write_jpeg(image, quality, writer)?,
         _ => Err(EncodeError::UnsupportedFormat(output_format))?,
     };
@@ -199,6 +280,31 @@ mod tests {
         assert_eq!(info.size.height as u32, image.height());
     }
 
+    #[test]
+    fn avif_speeds() {
+        assert_eq!(super::avif_speed(None), 4);
+        assert_eq!(super::avif_speed(Some(10)), 1);
+        assert_eq!(super::avif_speed(Some(1)), 10);
+        assert_eq!(super::avif_speed(Some(0)), 10);
+    }
+
+    #[test]
+    fn write_webp_with_effort() {
+        let image = read_test_image("test-input.png");
+        let mut output = Vec::new();
+        super::write_image_with_effort(
+            &image,
+            image::ImageFormat::WebP,
+            Some(80.0),
+            Some(2),
+            &mut output,
+        )
+        .unwrap();
+
+        let info = imageinfo::ImageInfo::from_raw_data(&output).expect("Reading image");
+        assert_eq!(info.format, imageinfo::ImageFormat::WEBP);
+    }
+
     #[test]
     fn write_jpeg() {
         let image = read_test_image("test-input.png");
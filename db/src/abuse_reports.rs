@@ -0,0 +1,39 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+pub use crate::schema::abuse_reports::*;
+use crate::{
+    enums::AbuseReportStatus,
+    object_id::{AbuseReportId, BaseImageId, TeamId, UserId},
+    schema::*,
+};
+
+/// A report from the public that a delivered image is abusive or illegal.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable)]
+pub struct AbuseReport {
+    pub id: AbuseReportId,
+    /// The URL that was reported.
+    pub url: String,
+    pub reason: String,
+    pub reporter_email: Option<String>,
+    /// The team that owns the reported image, if the URL could be matched to one.
+    pub team_id: Option<TeamId>,
+    pub base_image_id: Option<BaseImageId>,
+    pub status: AbuseReportStatus,
+    pub created: DateTime<Utc>,
+    pub resolved: Option<DateTime<Utc>>,
+    /// The instance admin who resolved the report.
+    pub resolved_by: Option<UserId>,
+    pub resolution_note: Option<String>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = abuse_reports)]
+pub struct NewAbuseReport {
+    pub id: AbuseReportId,
+    pub url: String,
+    pub reason: String,
+    pub reporter_email: Option<String>,
+    pub team_id: Option<TeamId>,
+    pub base_image_id: Option<BaseImageId>,
+}
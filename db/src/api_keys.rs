@@ -3,7 +3,7 @@ use diesel::prelude::*;
 use uuid::Uuid;
 
 use crate::{
-    object_id::{ProjectId, TeamId, UserId},
+    object_id::{ProjectId, TeamId, UploadProfileId, UserId},
     schema::*,
     Permission,
 };
@@ -21,6 +21,8 @@ pub struct ApiKey {
     pub inherits_user_permissions: bool,
     pub created: DateTime<Utc>,
     pub expires: DateTime<Utc>,
+    /// Only allow this key to upload images through this upload profile.
+    pub bound_upload_profile_id: Option<UploadProfileId>,
 }
 
 #[derive(Clone, Debug, Queryable, Insertable)]
@@ -0,0 +1,45 @@
+use chrono::{DateTime, Utc};
+use diesel::{prelude::*, upsert::excluded};
+use uuid::Uuid;
+
+pub use crate::schema::api_usage::*;
+use crate::{
+    object_id::{ProjectId, TeamId},
+    schema::*,
+};
+
+/// The API key ID recorded for requests that were authenticated with a session.
+pub const SESSION_KEY_ID: Uuid = Uuid::nil();
+
+/// The number of API requests made with one key to one project during an hour.
+#[derive(Clone, Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = api_usage)]
+pub struct ApiUsage {
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub api_key_id: Uuid,
+    pub hour: DateTime<Utc>,
+    pub requests: i64,
+    pub client_errors: i64,
+    pub server_errors: i64,
+}
+
+/// Add request counts to the rollups.
+pub fn add_usage(conn: &mut PgConnection, usage: &[ApiUsage]) -> QueryResult<usize> {
+    diesel::insert_into(api_usage::table)
+        .values(usage)
+        .on_conflict((
+            api_usage::project_id,
+            api_usage::hour,
+            api_usage::api_key_id,
+        ))
+        .do_update()
+        .set((
+            api_usage::requests.eq(api_usage::requests + excluded(api_usage::requests)),
+            api_usage::client_errors
+                .eq(api_usage::client_errors + excluded(api_usage::client_errors)),
+            api_usage::server_errors
+                .eq(api_usage::server_errors + excluded(api_usage::server_errors)),
+        ))
+        .execute(conn)
+}
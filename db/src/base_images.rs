@@ -1,9 +1,14 @@
-use diesel::prelude::*;
+use diesel::{prelude::*, sql_types::Jsonb};
+use serde::{Deserialize, Serialize};
 
 pub use crate::schema::base_images::*;
 use crate::{
+    diesel_jsonb,
     enums::{BaseImageStatus, ImageFormat},
-    object_id::{BaseImageId, ProjectId, TeamId, UploadProfileId, UserId},
+    object_id::{
+        BaseImageId, ConversionProfileId, ProjectId, StorageLocationId, TeamId, UploadProfileId,
+        UserId,
+    },
     schema::*,
 };
 
@@ -34,8 +39,65 @@ pub struct BaseImage {
 
     pub updated: chrono::DateTime<chrono::Utc>,
     pub deleted: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Why the image was rejected, if it was.
+    pub error: Option<String>,
+
+    /// The conversion profile and version that the current output images were generated with.
+    pub conversion_profile_id: Option<ConversionProfileId>,
+    pub conversion_profile_version: Option<i32>,
+
+    /// Tags added by the project's tagging rules.
+    pub tags: Vec<String>,
+    /// The collection that a tagging rule placed the image in.
+    pub collection: Option<String>,
+
+    /// The alt text was generated by the captioning service and still needs to be reviewed.
+    pub alt_text_machine_generated: bool,
+
+    /// Text found in the image by OCR.
+    pub ocr_text: Option<String>,
+
+    /// QR codes and barcodes found in the image.
+    pub codes: DetectedCodes,
+
+    /// The key prefix for the output images, rendered from the output storage location's
+    /// template when the image was created.
+    pub output_key_prefix: Option<String>,
+
+    /// When the output images were last generated.
+    pub converted: Option<chrono::DateTime<chrono::Utc>>,
+    /// When the upload profile's retention rules removed the original. Images without an
+    /// original can't be downloaded in full or converted again.
+    pub original_removed: Option<chrono::DateTime<chrono::Utc>>,
+    /// The storage location that the original was archived to, if it was archived instead of
+    /// deleted. The original has the same location in the archive.
+    pub original_archive_location_id: Option<StorageLocationId>,
+    pub created: chrono::DateTime<chrono::Utc>,
+    /// Pinned images are protected from bulk deletions and other bulk operations until they are
+    /// unpinned.
+    pub pinned: bool,
+    /// The SHA-256 checksum of the original, in hex.
+    pub sha256: Option<String>,
+}
+
+/// A QR code or barcode found in an image.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DetectedCode {
+    /// The type of code, such as `qrcode` or `ean 13`.
+    pub format: String,
+    pub payload: String,
 }
 
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow,
+)]
+#[diesel(sql_type = Jsonb)]
+#[serde(transparent)]
+pub struct DetectedCodes(pub Vec<DetectedCode>);
+
+diesel_jsonb!(DetectedCodes);
+
 #[derive(Debug, Insertable)]
 #[diesel(table_name = base_images)]
 pub struct NewBaseImage {
@@ -60,4 +122,5 @@ pub struct NewBaseImage {
     pub status: BaseImageStatus,
     pub alt_text: String,
     pub placeholder: String,
+    pub output_key_prefix: Option<String>,
 }
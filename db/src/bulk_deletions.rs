@@ -0,0 +1,172 @@
+use chrono::{DateTime, Utc};
+use diesel::{pg::Pg, prelude::*, sql_types::Jsonb};
+use serde::{Deserialize, Serialize};
+
+pub use crate::schema::bulk_deletions::*;
+use crate::{
+    diesel_jsonb,
+    enums::{BaseImageStatus, BulkDeletionStatus},
+    object_id::{BulkDeletionId, ProjectId, TeamId, UserId},
+    schema::*,
+};
+
+/// The statuses of images that a bulk deletion can remove. Images that are still converting are
+/// skipped, and images that were taken down are kept as a record of the takedown.
+const DELETABLE_STATUSES: [BaseImageStatus; 3] = [
+    BaseImageStatus::AwaitingUpload,
+    BaseImageStatus::Ready,
+    BaseImageStatus::Rejected,
+];
+
+/// A deletion of every image in a project that matches a filter.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable)]
+pub struct BulkDeletion {
+    pub id: BulkDeletionId,
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    /// The user who previewed the deletion.
+    pub user_id: UserId,
+    pub filter: BulkDeletionFilter,
+    pub status: BulkDeletionStatus,
+    /// The hash of the token that confirms the deletion.
+    pub confirmation_hash: Vec<u8>,
+    /// When the confirmation token stops working.
+    pub expires: DateTime<Utc>,
+    /// The number of images that matched when the deletion was previewed.
+    pub matched: i64,
+    pub deleted: i64,
+    pub failed: i64,
+    /// The error that stopped the deletion, or the last image that failed to delete.
+    pub error: Option<String>,
+    /// Only images created before this time are deleted, so that images uploaded after the
+    /// preview are never included.
+    pub created: DateTime<Utc>,
+    pub started: Option<DateTime<Utc>>,
+    pub finished: Option<DateTime<Utc>>,
+}
+
+/// The images to delete. Every condition that is set must match.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow,
+)]
+#[diesel(sql_type = Jsonb)]
+pub struct BulkDeletionFilter {
+    /// Matches images that have all of these tags.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Matches images created at or after this time.
+    #[serde(default)]
+    pub created_after: Option<DateTime<Utc>>,
+    /// Matches images created before this time.
+    #[serde(default)]
+    pub created_before: Option<DateTime<Utc>>,
+    /// Matches images whose location starts with this text.
+    #[serde(default)]
+    pub prefix: Option<String>,
+}
+
+diesel_jsonb!(BulkDeletionFilter);
+
+impl BulkDeletionFilter {
+    pub fn is_empty(&self) -> bool {
+        self == &BulkDeletionFilter::default()
+    }
+
+    /// The images in the project that match the filter and were created before `as_of`. Pinned
+    /// images are never included.
+    pub fn matching_images(
+        &self,
+        project: ProjectId,
+        as_of: DateTime<Utc>,
+    ) -> base_images::BoxedQuery<'static, Pg> {
+        self.matching(project, as_of)
+            .filter(base_images::pinned.eq(false))
+    }
+
+    /// The pinned images that would otherwise match the filter.
+    pub fn pinned_images(
+        &self,
+        project: ProjectId,
+        as_of: DateTime<Utc>,
+    ) -> base_images::BoxedQuery<'static, Pg> {
+        self.matching(project, as_of)
+            .filter(base_images::pinned.eq(true))
+    }
+
+    fn matching(
+        &self,
+        project: ProjectId,
+        as_of: DateTime<Utc>,
+    ) -> base_images::BoxedQuery<'static, Pg> {
+        let mut q = base_images::table
+            .filter(base_images::project_id.eq(project))
+            .filter(base_images::deleted.is_null())
+            .filter(base_images::status.eq_any(DELETABLE_STATUSES))
+            .filter(base_images::created.lt(as_of))
+            .into_boxed();
+
+        if !self.tags.is_empty() {
+            q = q.filter(base_images::tags.contains(self.tags.clone()));
+        }
+
+        if let Some(after) = self.created_after {
+            q = q.filter(base_images::created.ge(after));
+        }
+
+        if let Some(before) = self.created_before {
+            q = q.filter(base_images::created.lt(before));
+        }
+
+        if let Some(prefix) = &self.prefix {
+            q = q.filter(base_images::location.like(like_prefix(prefix)));
+        }
+
+        q
+    }
+}
+
+/// A LIKE pattern that matches text starting with `prefix`.
+fn like_prefix(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = bulk_deletions)]
+pub struct NewBulkDeletion {
+    pub id: BulkDeletionId,
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub user_id: UserId,
+    pub filter: BulkDeletionFilter,
+    pub confirmation_hash: Vec<u8>,
+    pub expires: DateTime<Utc>,
+    pub matched: i64,
+    pub created: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn like_prefix_escapes_wildcards() {
+        assert_eq!(like_prefix("photos/"), "photos/%");
+        assert_eq!(like_prefix("100%_a\\b"), "100\\%\\_a\\\\b%");
+        assert_eq!(like_prefix(""), "%");
+    }
+
+    #[test]
+    fn empty_filter() {
+        assert!(BulkDeletionFilter::default().is_empty());
+        let parsed: BulkDeletionFilter = serde_json::from_str(r#"{"tags": ["old"]}"#).unwrap();
+        assert!(!parsed.is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+use chrono::{DateTime, Utc};
+use diesel::{prelude::*, upsert::excluded};
+
+pub use crate::schema::conversion_pauses::*;
+use crate::{
+    enums::BaseImageStatus,
+    object_id::{BaseImageId, TeamId},
+    schema::*,
+};
+
+/// A pause on conversions for one team, or for every team.
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = conversion_pauses)]
+pub struct ConversionPause {
+    /// The nil ID for a pause that applies to every team.
+    pub team_id: TeamId,
+    pub reason: Option<String>,
+    pub created: DateTime<Utc>,
+}
+
+impl ConversionPause {
+    pub fn is_global(&self) -> bool {
+        self.team_id == TeamId::nil()
+    }
+}
+
+/// A conversion job that was held back while conversions were paused.
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = held_conversions)]
+pub struct HeldConversion {
+    pub base_image_id: BaseImageId,
+    pub team_id: TeamId,
+    /// The payload of the conversion job.
+    pub payload: serde_json::Value,
+    pub created: DateTime<Utc>,
+}
+
+/// The ID that a pause is stored under, with `None` meaning every team.
+fn pause_id(team: Option<TeamId>) -> TeamId {
+    team.unwrap_or_else(TeamId::nil)
+}
+
+/// Whether conversions are paused for the team, either for the team itself or for everyone.
+pub fn is_paused(conn: &mut PgConnection, team: TeamId) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        conversion_pauses::table.filter(conversion_pauses::team_id.eq_any([team, TeamId::nil()])),
+    ))
+    .get_result(conn)
+}
+
+/// Pause conversions for a team, or for every team when `team` is `None`. Pausing again
+/// replaces the reason.
+pub fn pause(
+    conn: &mut PgConnection,
+    team: Option<TeamId>,
+    pause_reason: Option<String>,
+) -> QueryResult<ConversionPause> {
+    diesel::insert_into(conversion_pauses::table)
+        .values((
+            conversion_pauses::team_id.eq(pause_id(team)),
+            conversion_pauses::reason.eq(pause_reason),
+        ))
+        .on_conflict(conversion_pauses::team_id)
+        .do_update()
+        .set(conversion_pauses::reason.eq(excluded(conversion_pauses::reason)))
+        .returning(ConversionPause::as_select())
+        .get_result(conn)
+}
+
+/// Lift a pause. Returns false if there was no such pause.
+pub fn resume(conn: &mut PgConnection, team: Option<TeamId>) -> QueryResult<bool> {
+    let deleted = diesel::delete(conversion_pauses::table)
+        .filter(conversion_pauses::team_id.eq(pause_id(team)))
+        .execute(conn)?;
+    Ok(deleted > 0)
+}
+
+/// Hold a conversion job until conversions are resumed. A newer job for the same image replaces
+/// the one that was already held.
+pub fn hold(
+    conn: &mut PgConnection,
+    base_image_id: BaseImageId,
+    team: TeamId,
+    payload: serde_json::Value,
+) -> QueryResult<()> {
+    diesel::insert_into(held_conversions::table)
+        .values((
+            held_conversions::base_image_id.eq(base_image_id),
+            held_conversions::team_id.eq(team),
+            held_conversions::payload.eq(payload),
+        ))
+        .on_conflict(held_conversions::base_image_id)
+        .do_update()
+        .set((
+            held_conversions::payload.eq(excluded(held_conversions::payload)),
+            held_conversions::created.eq(diesel::dsl::now),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Remove and return up to `limit` held conversions for teams that are no longer paused.
+pub fn take_released(conn: &mut PgConnection, limit: i64) -> QueryResult<Vec<HeldConversion>> {
+    let globally_paused = diesel::select(diesel::dsl::exists(
+        conversion_pauses::table.filter(conversion_pauses::team_id.eq(TeamId::nil())),
+    ))
+    .get_result::<bool>(conn)?;
+    if globally_paused {
+        return Ok(Vec::new());
+    }
+
+    let released = held_conversions::table
+        .filter(
+            held_conversions::team_id
+                .ne_all(conversion_pauses::table.select(conversion_pauses::team_id)),
+        )
+        .select(held_conversions::base_image_id)
+        .order(held_conversions::created.asc())
+        .limit(limit)
+        .for_update()
+        .skip_locked()
+        .load::<BaseImageId>(conn)?;
+
+    diesel::delete(held_conversions::table)
+        .filter(held_conversions::base_image_id.eq_any(released))
+        .get_results(conn)
+}
+
+/// The number of held conversions for each team.
+pub fn held_counts(conn: &mut PgConnection) -> QueryResult<Vec<(TeamId, i64)>> {
+    held_conversions::table
+        .group_by(held_conversions::team_id)
+        .select((held_conversions::team_id, diesel::dsl::count_star()))
+        .load(conn)
+}
+
+/// The number of images waiting for conversion, and when the longest-waiting one was queued.
+/// Conversions held by a pause are waiting on purpose, so they are left out.
+pub fn waiting_conversions(conn: &mut PgConnection) -> QueryResult<(i64, Option<DateTime<Utc>>)> {
+    base_images::table
+        .filter(base_images::status.eq(BaseImageStatus::Converting))
+        .filter(base_images::deleted.is_null())
+        .filter(diesel::dsl::not(diesel::dsl::exists(
+            held_conversions::table.filter(held_conversions::base_image_id.eq(base_images::id)),
+        )))
+        .select((
+            diesel::dsl::count_star(),
+            diesel::dsl::min(base_images::updated),
+        ))
+        .first(conn)
+}
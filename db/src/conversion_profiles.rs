@@ -1,6 +1,7 @@
 use diesel::{prelude::*, sql_types};
 use serde::{Deserialize, Serialize};
 
+pub use crate::schema::conversion_profile_versions;
 pub use crate::schema::conversion_profiles::*;
 use crate::{
     diesel_jsonb,
@@ -9,8 +10,9 @@ use crate::{
     ImageFormat,
 };
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, AsExpression, FromSqlRow)]
 #[diesel(sql_type = sql_types::Jsonb)]
+#[serde(deny_unknown_fields)]
 pub struct ConversionSize {
     pub width: Option<u32>,
     pub height: Option<u32>,
@@ -21,9 +23,12 @@ pub struct ConversionSize {
 diesel_jsonb!(ConversionSize);
 
 // This will eventually contain more details such as format-specific quality settings.
-#[derive(Debug, Clone, Serialize, Deserialize, AsExpression, FromSqlRow)]
+//
+// The conversion types reject unknown fields themselves. They are internally tagged, so serde
+// buffers their contents and the API's strict JSON mode can't see typos inside them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, AsExpression, FromSqlRow)]
 #[diesel(sql_type = sql_types::Jsonb)]
-#[serde(tag = "format", rename_all = "lowercase")]
+#[serde(tag = "format", rename_all = "lowercase", deny_unknown_fields)]
 pub enum ConversionFormat {
     Png {
         #[serde(skip_serializing_if = "Option::is_none")]
@@ -51,8 +56,8 @@ pub enum ConversionFormat {
 
 diesel_jsonb!(ConversionFormat);
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
 pub enum FormatConversionCondition {
     Must { formats: Vec<ImageFormat> },
     MustNot { formats: Vec<ImageFormat> },
@@ -124,18 +129,138 @@ impl From<&ConversionFormat> for image::ImageFormat {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, AsExpression, FromSqlRow)]
 #[diesel(sql_type = sql_types::Jsonb)]
-#[serde(tag = "type", rename_all = "lowercase")]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
 pub enum ConversionOutput {
     Cross {
         formats: Vec<ConversionFormat>,
         sizes: Vec<ConversionSize>,
+        /// The name of a transformation preset to apply to each output image.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        preset: Option<String>,
+        /// Also create an archival master in this format.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        archival: Option<ArchivalFormat>,
+        /// Keep animated inputs animated in WebP outputs.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        animation: Option<AnimationSettings>,
+    },
+    /// Let the conversion worker choose the widths, spacing them so that each size is
+    /// approximately `byte_step` bytes larger than the previous one.
+    Auto {
+        formats: Vec<ConversionFormat>,
+        min_width: u32,
+        max_width: u32,
+        byte_step: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max_sizes: Option<u32>,
+        /// The name of a transformation preset to apply to each output image.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        preset: Option<String>,
+        /// Also create an archival master in this format.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        archival: Option<ArchivalFormat>,
+        /// Keep animated inputs animated in WebP outputs.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        animation: Option<AnimationSettings>,
+    },
+    /// Each format is generated at its own set of sizes.
+    #[serde(rename = "per_format")]
+    PerFormat {
+        outputs: Vec<FormatOutput>,
+        /// The name of a transformation preset to apply to each output image.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        preset: Option<String>,
+        /// Also create an archival master in this format.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        archival: Option<ArchivalFormat>,
+        /// Keep animated inputs animated in WebP outputs.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        animation: Option<AnimationSettings>,
     },
 }
 
 diesel_jsonb!(ConversionOutput);
 
+/// How to convert animated inputs. Without these settings, only the first frame of an animation
+/// is converted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnimationSettings {
+    /// Keep at most this many frames.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_frames: Option<u32>,
+    /// Keep every Nth frame, starting with the first. The skipped frames' time is given to the
+    /// frame before them, so the animation plays at the same speed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_step: Option<u32>,
+}
+
+/// The format of an archival master, a full size lossless copy of the original image
+/// normalized to 8-bit sRGB, so that originals in unusual formats have a preserved copy that
+/// is easy to read. Archival masters ignore the transformation preset and are not served to
+/// clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchivalFormat {
+    /// Lossless WebP
+    Webp,
+    Png,
+}
+
+impl ArchivalFormat {
+    pub fn conversion_format(&self) -> ConversionFormat {
+        match self {
+            // The WebP encoder switches to lossless mode at quality 100.
+            Self::Webp => ConversionFormat::Webp {
+                quality: Some(100.0),
+                condition: None,
+            },
+            Self::Png => ConversionFormat::Png { condition: None },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormatOutput {
+    #[serde(flatten)]
+    pub format: ConversionFormat,
+    pub sizes: Vec<ConversionSize>,
+}
+
+impl ConversionOutput {
+    /// Return true if the output sizes are chosen when the image is converted, rather than
+    /// being listed in the profile.
+    pub fn has_automatic_sizes(&self) -> bool {
+        matches!(self, Self::Auto { .. })
+    }
+
+    pub fn preset(&self) -> Option<&str> {
+        match self {
+            Self::Cross { preset, .. } => preset.as_deref(),
+            Self::Auto { preset, .. } => preset.as_deref(),
+            Self::PerFormat { preset, .. } => preset.as_deref(),
+        }
+    }
+
+    pub fn archival(&self) -> Option<ArchivalFormat> {
+        match self {
+            Self::Cross { archival, .. } => *archival,
+            Self::Auto { archival, .. } => *archival,
+            Self::PerFormat { archival, .. } => *archival,
+        }
+    }
+
+    pub fn animation(&self) -> Option<AnimationSettings> {
+        match self {
+            Self::Cross { animation, .. } => *animation,
+            Self::Auto { animation, .. } => *animation,
+            Self::PerFormat { animation, .. } => *animation,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Queryable, Identifiable)]
 pub struct ConversionProfile {
     pub id: ConversionProfileId,
@@ -147,6 +272,135 @@ pub struct ConversionProfile {
 
     pub updated: chrono::DateTime<chrono::Utc>,
     pub deleted: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// A template for the storage keys of output images, used instead of the default layout.
+    pub output_key_template: Option<String>,
+
+    /// The profile that this profile inherits its settings from. When this is set, `output`
+    /// and `output_key_template` hold the result of applying `overrides` to the parent's
+    /// settings.
+    pub extends: Option<ConversionProfileId>,
+    /// A JSON merge patch applied to the parent's [ConversionProfileSettings].
+    pub overrides: Option<serde_json::Value>,
+
+    /// Incremented every time the settings change.
+    pub version: i32,
+}
+
+impl ConversionProfile {
+    pub fn settings(&self) -> ConversionProfileSettings {
+        ConversionProfileSettings {
+            output: self.output.clone(),
+            output_key_template: self.output_key_template.clone(),
+        }
+    }
+}
+
+/// The parts of a conversion profile that can be inherited by other profiles.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ConversionProfileSettings {
+    pub output: ConversionOutput,
+    #[serde(default)]
+    pub output_key_template: Option<String>,
+}
+
+impl ConversionProfileSettings {
+    /// Apply a JSON merge patch (RFC 7386) to these settings. Objects in the patch are
+    /// merged into the settings, while all other values, including arrays, replace the
+    /// existing value. A `null` removes the value.
+    pub fn with_overrides(
+        &self,
+        patch: &serde_json::Value,
+    ) -> Result<ConversionProfileSettings, serde_json::Error> {
+        let mut value = serde_json::to_value(self)?;
+        merge_patch(&mut value, patch);
+        serde_json::from_value(value)
+    }
+}
+
+fn merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = serde_json::Value::Object(serde_json::Map::new());
+    }
+
+    let target = target.as_object_mut().unwrap();
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(
+                target
+                    .entry(key.as_str())
+                    .or_insert(serde_json::Value::Null),
+                value,
+            );
+        }
+    }
+}
+
+/// The settings of a conversion profile at a particular version.
+#[derive(Clone, Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = conversion_profile_versions)]
+pub struct ConversionProfileVersion {
+    pub conversion_profile_id: ConversionProfileId,
+    pub version: i32,
+    pub output: ConversionOutput,
+    pub output_key_template: Option<String>,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
+
+/// Record the current settings of a profile as a new version, if they have changed since the
+/// last recorded version. Returns the profile's current version.
+pub fn record_version(
+    conn: &mut PgConnection,
+    profile_id: ConversionProfileId,
+) -> QueryResult<i32> {
+    let profile = conversion_profiles::table
+        .filter(conversion_profiles::id.eq(profile_id))
+        .first::<ConversionProfile>(conn)?;
+
+    let latest = conversion_profile_versions::table
+        .filter(conversion_profile_versions::conversion_profile_id.eq(profile_id))
+        .order(conversion_profile_versions::version.desc())
+        .select(ConversionProfileVersion::as_select())
+        .first(conn)
+        .optional()?;
+
+    let settings = profile.settings();
+    let next_version = match latest {
+        Some(latest)
+            if ConversionProfileSettings {
+                output: latest.output.clone(),
+                output_key_template: latest.output_key_template.clone(),
+            } == settings =>
+        {
+            return Ok(latest.version);
+        }
+        Some(latest) => latest.version + 1,
+        None => profile.version,
+    };
+
+    diesel::insert_into(conversion_profile_versions::table)
+        .values(ConversionProfileVersion {
+            conversion_profile_id: profile_id,
+            version: next_version,
+            output: settings.output,
+            output_key_template: settings.output_key_template,
+            created: chrono::Utc::now(),
+        })
+        .execute(conn)?;
+
+    diesel::update(conversion_profiles::table)
+        .filter(conversion_profiles::id.eq(profile_id))
+        .set(conversion_profiles::version.eq(next_version))
+        .execute(conn)?;
+
+    Ok(next_version)
 }
 
 #[derive(Debug, Deserialize, Insertable)]
@@ -158,4 +412,159 @@ pub struct NewConversionProfile {
     pub name: String,
 
     pub output: ConversionOutput,
+    #[serde(default)]
+    pub output_key_template: Option<String>,
+    #[serde(default)]
+    pub extends: Option<ConversionProfileId>,
+    #[serde(default)]
+    pub overrides: Option<serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn per_format_output() {
+        let parsed: ConversionOutput = serde_json::from_value(serde_json::json!({
+            "type": "per_format",
+            "outputs": [
+                {
+                    "format": "avif",
+                    "quality": 50.0,
+                    "sizes": [{ "width": 800 }, { "width": 1600 }]
+                },
+                {
+                    "format": "jpg",
+                    "sizes": [{ "width": 400 }, { "width": 800 }, { "width": 1600 }]
+                }
+            ],
+            "animation": { "max_frames": 50, "frame_step": 2 }
+        }))
+        .unwrap();
+
+        let ConversionOutput::PerFormat {
+            outputs,
+            preset,
+            archival,
+            animation,
+        } = parsed
+        else {
+            panic!("Expected per_format output, got {parsed:?}");
+        };
+
+        assert!(preset.is_none());
+        assert!(archival.is_none());
+        assert_eq!(
+            animation,
+            Some(AnimationSettings {
+                max_frames: Some(50),
+                frame_step: Some(2),
+            })
+        );
+        assert_eq!(outputs.len(), 2);
+        assert!(matches!(
+            outputs[0].format,
+            ConversionFormat::Avif {
+                quality: Some(q),
+                ..
+            } if q == 50.0
+        ));
+        assert_eq!(outputs[0].sizes.len(), 2);
+        assert!(matches!(outputs[1].format, ConversionFormat::Jpg { .. }));
+        assert_eq!(outputs[1].sizes.len(), 3);
+    }
+
+    #[test]
+    fn settings_overrides() {
+        let parent = ConversionProfileSettings {
+            output: ConversionOutput::Cross {
+                formats: vec![
+                    ConversionFormat::Avif {
+                        quality: Some(60.0),
+                        condition: None,
+                    },
+                    ConversionFormat::Jpg {
+                        quality: None,
+                        condition: None,
+                    },
+                ],
+                sizes: vec![ConversionSize {
+                    width: Some(800),
+                    ..Default::default()
+                }],
+                preset: Some("sharpen".to_string()),
+                archival: Some(ArchivalFormat::Webp),
+                animation: Some(AnimationSettings {
+                    max_frames: Some(100),
+                    frame_step: None,
+                }),
+            },
+            output_key_template: Some("{image_id}/{width}.{ext}".to_string()),
+        };
+
+        let child = parent
+            .with_overrides(&serde_json::json!({
+                "output": {
+                    "sizes": [{ "width": 400 }, { "width": 1200 }],
+                    "preset": null,
+                    "animation": { "frame_step": 2 }
+                },
+                "output_key_template": null
+            }))
+            .unwrap();
+
+        let ConversionOutput::Cross {
+            formats,
+            sizes,
+            preset,
+            archival,
+            animation,
+        } = &child.output
+        else {
+            panic!("Expected cross output, got {:?}", child.output);
+        };
+
+        assert_eq!(formats.len(), 2, "formats are inherited");
+        assert_eq!(
+            sizes.iter().map(|s| s.width).collect::<Vec<_>>(),
+            vec![Some(400), Some(1200)],
+            "sizes are replaced"
+        );
+        assert!(preset.is_none(), "null removes the preset");
+        assert_eq!(
+            *archival,
+            Some(ArchivalFormat::Webp),
+            "archival is inherited"
+        );
+        assert_eq!(
+            *animation,
+            Some(AnimationSettings {
+                max_frames: Some(100),
+                frame_step: Some(2),
+            }),
+            "animation settings are merged"
+        );
+        assert!(child.output_key_template.is_none());
+    }
+
+    #[test]
+    fn invalid_overrides() {
+        let parent = ConversionProfileSettings {
+            output: ConversionOutput::Cross {
+                formats: Vec::new(),
+                sizes: Vec::new(),
+                preset: None,
+                archival: None,
+                animation: None,
+            },
+            output_key_template: None,
+        };
+
+        let result = parent.with_overrides(&serde_json::json!({
+            "output": { "type": "auto" }
+        }));
+
+        assert!(result.is_err(), "auto output is missing required fields");
+    }
 }
@@ -0,0 +1,199 @@
+//! Envelope encryption for the credentials in storage location settings, including the API
+//! credentials of their CDN purge settings.
+//!
+//! Each value is encrypted with its own random data key, and the data key is encrypted with the
+//! master key. Encrypted values look like `enc:v1:<wrapped data key>:<ciphertext>`, and values
+//! without the prefix are treated as plaintext, so existing locations keep working until the
+//! `reencrypt-credentials` admin command encrypts them.
+
+use std::sync::OnceLock;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use thiserror::Error;
+
+const PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+static MASTER_KEY: OnceLock<MasterKey> = OnceLock::new();
+
+#[derive(Debug, Error)]
+pub enum CredentialError {
+    #[error("The master key must be 32 bytes, encoded as base64")]
+    InvalidMasterKey,
+
+    #[error("An encrypted credential was found, but no master key is configured")]
+    MissingMasterKey,
+
+    #[error("Failed to decrypt credential")]
+    DecryptFailure,
+
+    #[error("Failed to encrypt credential")]
+    EncryptFailure,
+}
+
+#[derive(Clone)]
+pub struct MasterKey(Key);
+
+impl std::fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MasterKey")
+    }
+}
+
+impl MasterKey {
+    pub fn from_base64(value: &str) -> Result<Self, CredentialError> {
+        let bytes = STANDARD
+            .decode(value.trim())
+            .map_err(|_| CredentialError::InvalidMasterKey)?;
+        if bytes.len() != 32 {
+            return Err(CredentialError::InvalidMasterKey);
+        }
+
+        Ok(MasterKey(*Key::from_slice(&bytes)))
+    }
+
+    /// Generate a new random master key.
+    pub fn generate() -> Self {
+        MasterKey(ChaCha20Poly1305::generate_key(&mut OsRng))
+    }
+
+    pub fn to_base64(&self) -> String {
+        STANDARD.encode(self.0.as_slice())
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, CredentialError> {
+        let data_key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        let wrapped_key = seal(&self.0, data_key.as_slice())?;
+        let ciphertext = seal(&data_key, plaintext.as_bytes())?;
+
+        Ok(format!(
+            "{PREFIX}{}:{}",
+            STANDARD.encode(wrapped_key),
+            STANDARD.encode(ciphertext)
+        ))
+    }
+
+    /// Decrypt a value. Values that aren't encrypted are returned unchanged.
+    pub fn decrypt(&self, value: &str) -> Result<String, CredentialError> {
+        let Some(encrypted) = value.strip_prefix(PREFIX) else {
+            return Ok(value.to_string());
+        };
+
+        let (wrapped_key, ciphertext) = encrypted
+            .split_once(':')
+            .ok_or(CredentialError::DecryptFailure)?;
+        let decode = |s: &str| {
+            STANDARD
+                .decode(s)
+                .map_err(|_| CredentialError::DecryptFailure)
+        };
+
+        let data_key = open(&self.0, &decode(wrapped_key)?)?;
+        if data_key.len() != 32 {
+            return Err(CredentialError::DecryptFailure);
+        }
+
+        let plaintext = open(Key::from_slice(&data_key), &decode(ciphertext)?)?;
+        String::from_utf8(plaintext).map_err(|_| CredentialError::DecryptFailure)
+    }
+}
+
+/// Encrypt with a random nonce, which is prepended to the ciphertext.
+fn seal(key: &Key, plaintext: &[u8]) -> Result<Vec<u8>, CredentialError> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = ChaCha20Poly1305::new(key)
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| CredentialError::EncryptFailure)?;
+
+    let mut output = nonce.to_vec();
+    output.extend_from_slice(&ciphertext);
+    Ok(output)
+}
+
+fn open(key: &Key, data: &[u8]) -> Result<Vec<u8>, CredentialError> {
+    if data.len() < NONCE_LEN {
+        return Err(CredentialError::DecryptFailure);
+    }
+
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    ChaCha20Poly1305::new(key)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| CredentialError::DecryptFailure)
+}
+
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// Set the master key for the process. Only the first call has any effect.
+pub fn set_master_key(key: MasterKey) {
+    MASTER_KEY.get_or_init(|| key);
+}
+
+pub fn master_key() -> Option<&'static MasterKey> {
+    MASTER_KEY.get()
+}
+
+/// Encrypt a value with the process's master key, or leave it as plaintext if there is no key.
+pub fn encrypt(value: &str) -> Result<String, CredentialError> {
+    match master_key() {
+        Some(key) if !is_encrypted(value) => key.encrypt(value),
+        _ => Ok(value.to_string()),
+    }
+}
+
+/// Decrypt a value with the process's master key.
+pub fn decrypt(value: &str) -> Result<String, CredentialError> {
+    match master_key() {
+        Some(key) => key.decrypt(value),
+        None if is_encrypted(value) => Err(CredentialError::MissingMasterKey),
+        None => Ok(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let key = MasterKey::generate();
+        let encrypted = key.encrypt("secret-key").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("secret-key"));
+        assert_eq!(key.decrypt(&encrypted).unwrap(), "secret-key");
+
+        // Each value gets its own data key and nonce.
+        assert_ne!(key.encrypt("secret-key").unwrap(), encrypted);
+    }
+
+    #[test]
+    fn plaintext_passes_through() {
+        let key = MasterKey::generate();
+        assert_eq!(key.decrypt("plain").unwrap(), "plain");
+    }
+
+    #[test]
+    fn wrong_key() {
+        let encrypted = MasterKey::generate().encrypt("secret-key").unwrap();
+        assert!(MasterKey::generate().decrypt(&encrypted).is_err());
+        assert!(MasterKey::generate()
+            .decrypt(&format!("{encrypted}x"))
+            .is_err());
+    }
+
+    #[test]
+    fn parse_master_key() {
+        let key = MasterKey::generate();
+        let parsed = MasterKey::from_base64(&key.to_base64()).unwrap();
+        let encrypted = key.encrypt("value").unwrap();
+        assert_eq!(parsed.decrypt(&encrypted).unwrap(), "value");
+
+        assert!(MasterKey::from_base64("c2hvcnQ=").is_err());
+        assert!(MasterKey::from_base64("not base64!").is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+pub use crate::schema::delivery_domains::*;
+use crate::{
+    object_id::{ProjectId, TeamId, UserId},
+    schema::*,
+};
+
+/// A hostname that serves the images of a single project.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable)]
+#[diesel(primary_key(hostname))]
+pub struct DeliveryDomain {
+    pub hostname: String,
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub created_by: UserId,
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = delivery_domains)]
+pub struct NewDeliveryDomain {
+    pub hostname: String,
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub created_by: UserId,
+}
+
+/// Convert a hostname or a Host header value into the form stored in the database, without a
+/// port and in lowercase. This returns None if the value is not a valid DNS hostname.
+pub fn normalize_hostname(host: &str) -> Option<String> {
+    let host = host.trim();
+    // Strip the port, but leave IPv6 literals alone since they can't be delivery domains anyway.
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !name.contains(':') && port.chars().all(|c| c.is_ascii_digit()) => {
+            name
+        }
+        _ => host,
+    };
+    let host = host.strip_suffix('.').unwrap_or(host).to_ascii_lowercase();
+
+    let valid = !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+
+    valid.then_some(host)
+}
+
+/// Find the project that a hostname serves, if it is a delivery domain.
+pub fn project_for_host(conn: &mut PgConnection, host: &str) -> QueryResult<Option<ProjectId>> {
+    delivery_domains::table
+        .filter(delivery_domains::hostname.eq(host))
+        .select(delivery_domains::project_id)
+        .first(conn)
+        .optional()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_host_header() {
+        assert_eq!(
+            normalize_hostname("Images.Example.com:8443").as_deref(),
+            Some("images.example.com")
+        );
+        assert_eq!(
+            normalize_hostname("images.example.com.").as_deref(),
+            Some("images.example.com")
+        );
+        assert_eq!(
+            normalize_hostname("localhost").as_deref(),
+            Some("localhost")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_hostnames() {
+        assert_eq!(normalize_hostname(""), None);
+        assert_eq!(normalize_hostname("bad host.com"), None);
+        assert_eq!(normalize_hostname("-bad.example.com"), None);
+        assert_eq!(normalize_hostname("a..example.com"), None);
+        assert_eq!(normalize_hostname("[::1]:8080"), None);
+        assert_eq!(normalize_hostname("example.com/path"), None);
+    }
+}
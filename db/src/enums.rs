@@ -34,6 +34,10 @@ pub enum BaseImageStatus {
     QueuedForDelete,
     Deleting,
     Deleted,
+    /// The image could not be processed. The reason is in the image's `error` field.
+    Rejected,
+    /// The image was removed in response to an abuse report.
+    TakenDown,
 }
 
 impl Default for BaseImageStatus {
@@ -59,7 +63,23 @@ impl Default for OutputImageStatus {
     }
 }
 
-#[derive(Copy, Clone, Debug, DbEnum, Deserialize)]
+#[derive(PartialEq, Eq, Copy, Clone, Debug, DbEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[ExistingTypePath = "crate::schema::sql_types::OutputMirrorStatus"]
+pub enum OutputMirrorStatus {
+    /// The mirror has a verified copy of the output image.
+    Ready,
+    /// Copying the output image to the mirror failed.
+    Failed,
+}
+
+impl Default for OutputMirrorStatus {
+    fn default() -> Self {
+        Self::Ready
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug, DbEnum, Serialize, Deserialize)]
 #[ExistingTypePath = "crate::schema::sql_types::Permission"]
 pub enum Permission {
     #[db_rename = "team:admin"]
@@ -89,6 +109,12 @@ pub enum Permission {
     #[db_rename = "storage_location:write"]
     #[serde(rename = "storage_location:write")]
     StorageLocationWrite,
+    #[db_rename = "upload_profile:read"]
+    #[serde(rename = "upload_profile:read")]
+    UploadProfileRead,
+    #[db_rename = "upload_profile:write"]
+    #[serde(rename = "upload_profile:write")]
+    UploadProfileWrite,
 }
 
 impl std::fmt::Display for Permission {
@@ -103,6 +129,8 @@ impl std::fmt::Display for Permission {
             Self::ImageEdit => "image:edit",
             Self::ConversionProfileWrite => "conversion_profile:write",
             Self::StorageLocationWrite => "storage_location:write",
+            Self::UploadProfileRead => "upload_profile:read",
+            Self::UploadProfileWrite => "upload_profile:write",
         };
 
         f.write_str(desc)
@@ -110,6 +138,20 @@ impl std::fmt::Display for Permission {
 }
 
 impl Permission {
+    pub const ALL: [Permission; 11] = [
+        Self::TeamAdmin,
+        Self::TeamWrite,
+        Self::ProjectCreate,
+        Self::ProjectWrite,
+        Self::ProjectRead,
+        Self::ImageEdit,
+        Self::ImageCreate,
+        Self::ConversionProfileWrite,
+        Self::StorageLocationWrite,
+        Self::UploadProfileRead,
+        Self::UploadProfileWrite,
+    ];
+
     /** Return true if this permission is linked to a project */
     pub fn requires_project(&self) -> bool {
         match self {
@@ -122,6 +164,164 @@ impl Permission {
             Self::ImageCreate => true,
             Self::ConversionProfileWrite => true,
             Self::StorageLocationWrite => true,
+            Self::UploadProfileRead => true,
+            Self::UploadProfileWrite => true,
         }
     }
 }
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, DbEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[ExistingTypePath = "crate::schema::sql_types::TeamStatus"]
+pub enum TeamStatus {
+    Active,
+    /// The team can read its data but can not make any changes.
+    ReadOnly,
+    /// The team can not use the API at all.
+    Suspended,
+}
+
+impl Default for TeamStatus {
+    fn default() -> Self {
+        Self::Active
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, DbEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[ExistingTypePath = "crate::schema::sql_types::AbuseReportStatus"]
+pub enum AbuseReportStatus {
+    /// The report has not been reviewed yet.
+    Pending,
+    /// The report was reviewed and no action was taken.
+    Dismissed,
+    /// The reported image was taken down.
+    TakenDown,
+}
+
+impl Default for AbuseReportStatus {
+    fn default() -> Self {
+        Self::Pending
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, DbEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[ExistingTypePath = "crate::schema::sql_types::ProjectGrantEventType"]
+pub enum ProjectGrantEventType {
+    /// The grant was created.
+    Created,
+    /// The grant was revoked.
+    Revoked,
+    /// A member of the grantee team read an image through the grant.
+    Accessed,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, DbEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[ExistingTypePath = "crate::schema::sql_types::StorageServeMode"]
+pub enum StorageServeMode {
+    /// The server reads images from storage and streams them in the response.
+    Proxy,
+    /// The server redirects to the image at the storage location's public URL.
+    Redirect,
+}
+
+impl Default for StorageServeMode {
+    fn default() -> Self {
+        Self::Proxy
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, DbEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[ExistingTypePath = "crate::schema::sql_types::OrganizationRole"]
+pub enum OrganizationRole {
+    /// Manage the organization, its members, its teams, and its shared storage locations.
+    Admin,
+    /// View the usage of every team in the organization, and manage the billing details.
+    Billing,
+    /// View the organization and its teams.
+    Member,
+}
+
+impl OrganizationRole {
+    /// Whether this role can do everything that `required` can.
+    pub fn includes(self, required: OrganizationRole) -> bool {
+        match self {
+            Self::Admin => true,
+            Self::Billing => matches!(required, Self::Billing | Self::Member),
+            Self::Member => required == Self::Member,
+        }
+    }
+}
+
+impl std::fmt::Display for OrganizationRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let desc = match self {
+            Self::Admin => "admin",
+            Self::Billing => "billing",
+            Self::Member => "member",
+        };
+
+        f.write_str(desc)
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, DbEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[ExistingTypePath = "crate::schema::sql_types::BulkDeletionStatus"]
+pub enum BulkDeletionStatus {
+    /// The deletion has been previewed and is waiting for confirmation.
+    Previewed,
+    /// The deletion was confirmed and images are being deleted.
+    Running,
+    /// Every matching image was processed. Some may have failed to delete.
+    Complete,
+    /// The deletion stopped because of an error.
+    Failed,
+}
+
+impl Default for BulkDeletionStatus {
+    fn default() -> Self {
+        Self::Previewed
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, DbEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[ExistingTypePath = "crate::schema::sql_types::ImageReferenceSource"]
+pub enum ImageReferenceSource {
+    /// Found by crawling the site's sitemap.
+    Crawl,
+    /// Uploaded in a list of references.
+    Import,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, DbEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[ExistingTypePath = "crate::schema::sql_types::ReferenceCrawlStatus"]
+pub enum ReferenceCrawlStatus {
+    Running,
+    /// Every page in the sitemap was scanned, up to the crawl's page limit.
+    Complete,
+    /// The crawl stopped because of an error.
+    Failed,
+}
+
+impl Default for ReferenceCrawlStatus {
+    fn default() -> Self {
+        Self::Running
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, DbEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[ExistingTypePath = "crate::schema::sql_types::WebhookDeliveryStatus"]
+pub enum WebhookDeliveryStatus {
+    /// Waiting to be sent, or to be retried after a failed attempt.
+    Pending,
+    Delivered,
+    /// Every attempt failed.
+    Failed,
+}
@@ -0,0 +1,74 @@
+use chrono::{DateTime, Utc};
+use diesel::{prelude::*, upsert::excluded};
+use serde::Serialize;
+
+pub use crate::schema::image_references::*;
+use crate::{
+    enums::ImageReferenceSource,
+    object_id::{BaseImageId, ProjectId, TeamId},
+    schema::*,
+};
+
+/// A site page that uses an image.
+#[derive(Clone, Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = image_references)]
+pub struct ImageReference {
+    pub base_image_id: BaseImageId,
+    pub page_url: String,
+    pub source: ImageReferenceSource,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = image_references)]
+pub struct NewImageReference {
+    pub base_image_id: BaseImageId,
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub page_url: String,
+    pub source: ImageReferenceSource,
+}
+
+/// Save references, updating when the existing ones were last seen.
+pub fn record(conn: &mut PgConnection, references: &[NewImageReference]) -> QueryResult<usize> {
+    if references.is_empty() {
+        return Ok(0);
+    }
+
+    diesel::insert_into(image_references::table)
+        .values(references)
+        .on_conflict((image_references::base_image_id, image_references::page_url))
+        .do_update()
+        .set((
+            image_references::source.eq(excluded(image_references::source)),
+            image_references::last_seen.eq(diesel::dsl::now),
+        ))
+        .execute(conn)
+}
+
+/// Remove the crawled references in a project that a crawl starting at `crawl_started` didn't
+/// find again, since the pages no longer use those images.
+pub fn remove_stale_crawled(
+    conn: &mut PgConnection,
+    project: ProjectId,
+    crawl_started: DateTime<Utc>,
+) -> QueryResult<usize> {
+    diesel::delete(image_references::table)
+        .filter(image_references::project_id.eq(project))
+        .filter(image_references::source.eq(ImageReferenceSource::Crawl))
+        .filter(image_references::last_seen.lt(crawl_started))
+        .execute(conn)
+}
+
+/// The pages that use an image, most recently seen first.
+pub fn for_image(
+    conn: &mut PgConnection,
+    image_id: BaseImageId,
+) -> QueryResult<Vec<ImageReference>> {
+    image_references::table
+        .filter(image_references::base_image_id.eq(image_id))
+        .select(ImageReference::as_select())
+        .order(image_references::last_seen.desc())
+        .load(conn)
+}
@@ -0,0 +1,44 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+pub use crate::schema::impersonation_events;
+pub use crate::schema::impersonations::*;
+use crate::{
+    object_id::{ImpersonationId, TeamId, UserId},
+    schema::*,
+};
+
+/// A time-limited grant allowing an instance admin to act as a member of another team.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable)]
+pub struct Impersonation {
+    pub id: ImpersonationId,
+    pub admin_user_id: UserId,
+    pub team_id: TeamId,
+    /// Why the admin needed to impersonate the team.
+    pub reason: String,
+    pub created: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+    /// Set when the impersonation is ended before it expires.
+    pub ended: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = impersonations)]
+pub struct NewImpersonation {
+    pub id: ImpersonationId,
+    pub admin_user_id: UserId,
+    pub team_id: TeamId,
+    pub reason: String,
+    pub expires: DateTime<Utc>,
+}
+
+/// A record of a single request made while impersonating a team.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable, Insertable)]
+pub struct ImpersonationEvent {
+    pub id: Uuid,
+    pub impersonation_id: ImpersonationId,
+    pub method: String,
+    pub path: String,
+    pub created: DateTime<Utc>,
+}
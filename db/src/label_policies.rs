@@ -0,0 +1,85 @@
+use chrono::{DateTime, Utc};
+use diesel::{prelude::*, sql_types::Jsonb};
+use serde::{Deserialize, Serialize};
+
+pub use crate::schema::label_policies::*;
+use crate::{
+    diesel_jsonb,
+    object_id::{LabelPolicyId, StorageLocationId, TeamId},
+    schema::*,
+};
+
+/// A rule for images uploaded to a project or upload profile with the policy's label.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Jsonb)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LabelPolicyRule {
+    /// Images must be stored in one of these storage locations.
+    RequireStorageLocation {
+        storage_location_ids: Vec<StorageLocationId>,
+    },
+    /// Images must be stored in storage locations that have this label.
+    RequireStorageLabel {
+        #[serde(rename = "label")]
+        storage_label: String,
+    },
+    /// Images can't be uploaded at all.
+    DenyUpload,
+}
+
+diesel_jsonb!(LabelPolicyRule);
+
+impl LabelPolicyRule {
+    /// Whether an image can be stored in the storage location.
+    pub fn allows_location(
+        &self,
+        location_id: StorageLocationId,
+        location_labels: &[String],
+    ) -> bool {
+        match self {
+            Self::RequireStorageLocation {
+                storage_location_ids,
+            } => storage_location_ids.contains(&location_id),
+            Self::RequireStorageLabel { storage_label } => location_labels.contains(storage_label),
+            Self::DenyUpload => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = label_policies)]
+pub struct LabelPolicy {
+    pub id: LabelPolicyId,
+    pub team_id: TeamId,
+    pub label: String,
+    pub rule: LabelPolicyRule,
+    pub updated: DateTime<Utc>,
+    pub deleted: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = label_policies)]
+pub struct NewLabelPolicy {
+    pub id: LabelPolicyId,
+    pub team_id: TeamId,
+    pub label: String,
+    pub rule: LabelPolicyRule,
+}
+
+/// Load the team's policies for any of the labels.
+pub fn policies_for_labels(
+    conn: &mut PgConnection,
+    team: TeamId,
+    labels: &[String],
+) -> QueryResult<Vec<LabelPolicy>> {
+    if labels.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    label_policies::table
+        .filter(label_policies::team_id.eq(team))
+        .filter(label_policies::label.eq_any(labels))
+        .filter(label_policies::deleted.is_null())
+        .select(LabelPolicy::as_select())
+        .load(conn)
+}
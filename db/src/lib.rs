@@ -5,32 +5,66 @@ mod enums;
 mod json;
 mod schema;
 
+pub mod abuse_reports;
 pub mod api_keys;
+pub mod api_usage;
 pub mod base_images;
+pub mod bulk_deletions;
+pub mod conversion_pauses;
 pub mod conversion_profiles;
+pub mod credentials;
+pub mod delivery_domains;
+pub mod image_references;
+pub mod impersonations;
+pub mod label_policies;
+pub mod link_checks;
+pub mod metering;
 pub mod object_id;
+pub mod organizations;
+pub mod output_image_mirrors;
 pub mod output_images;
 pub mod permissions;
+pub mod project_access_tokens;
+pub mod project_grants;
 pub mod projects;
+pub mod reference_crawls;
 pub mod role_permissions;
 pub mod roles;
 pub mod sessions;
+pub mod share_links;
 pub mod storage_locations;
+pub mod tagging_rules;
 pub mod teams;
 pub mod test;
+pub mod transformation_presets;
 pub mod upload_profiles;
+pub mod upload_sessions;
 pub mod user_roles;
 pub mod users;
+pub mod webhook_allowlists;
+pub mod webhooks;
 
 use std::borrow::Cow;
 
 use async_trait::async_trait;
-use diesel::{sql_types, Connection, PgConnection};
+use diesel::{migration::Migration, pg::Pg, sql_types, Connection, PgConnection};
+use diesel_migrations::{EmbeddedMigrations, MigrationHarness};
 pub use enums::*;
 pub use json::*;
 
 pub type Pool = deadpool_diesel::postgres::Pool;
 
+pub const MIGRATIONS: EmbeddedMigrations = diesel_migrations::embed_migrations!();
+
+/// Return the names of the migrations which have not been run on the database.
+pub fn pending_migrations(conn: &mut PgConnection) -> Result<Vec<String>, eyre::Report> {
+    let pending: Vec<Box<dyn Migration<Pg>>> = conn
+        .pending_migrations(MIGRATIONS)
+        .map_err(|e| eyre::eyre!("{e}"))?;
+
+    Ok(pending.iter().map(|m| m.name().to_string()).collect())
+}
+
 pub fn connect(conn_str: &str, max_connections: usize) -> Result<Pool, impl std::error::Error> {
     let manager =
         deadpool_diesel::postgres::Manager::new(conn_str, deadpool_diesel::Runtime::Tokio1);
@@ -80,11 +114,11 @@ where
 #[macro_export]
 macro_rules! with_project_or_global {
     ($query: expr,  $project_id: expr) => {
-        if let Some(project_id) = $project_id {
+        if let Some(project) = $project_id {
             $query.filter(
                 dsl::project_id
                     .is_null()
-                    .or(dsl::project_id.is_not_distinct_from(project_id)),
+                    .or(dsl::project_id.is_not_distinct_from(project)),
             )
         } else {
             $query.filter(dsl::project_id.is_null())
@@ -106,6 +140,10 @@ sql_function! {
     fn bool_or(x: sql_types::Bool) -> sql_types::Bool
 }
 
+sql_function! {
+    fn random() -> sql_types::Double
+}
+
 pub fn image_path(
     storage_location_path: &str,
     project_base_path: &str,
@@ -0,0 +1,32 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+pub use crate::schema::link_checks::*;
+use crate::{
+    object_id::{BaseImageId, ProjectId, TeamId},
+    schema::*,
+};
+
+/// The result of requesting one of a project's delivery URLs through its public path.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable, Insertable, Serialize)]
+pub struct LinkCheck {
+    pub id: Uuid,
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub base_image_id: BaseImageId,
+    pub url: String,
+    pub ok: bool,
+    /// The response status, or None if the request didn't get a response.
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub checked: DateTime<Utc>,
+}
+
+/// Delete the results of checks made before `before`.
+pub fn prune(conn: &mut PgConnection, before: DateTime<Utc>) -> QueryResult<usize> {
+    diesel::delete(link_checks::table)
+        .filter(link_checks::checked.lt(before))
+        .execute(conn)
+}
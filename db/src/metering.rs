@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use diesel::{dsl::count_star, prelude::*, upsert::excluded};
+
+pub use crate::schema::metering::*;
+use crate::{
+    enums::OutputImageStatus,
+    object_id::{ProjectId, TeamId},
+    schema::*,
+};
+
+/// The storage and traffic of one project during a day.
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = metering)]
+pub struct Metering {
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub day: NaiveDate,
+    /// The size of the originals uploaded.
+    pub bytes_uploaded: i64,
+    /// The size of the images sent by the serve routes. Images served by redirecting to the
+    /// storage location are not included.
+    pub bytes_served: i64,
+    pub images_served: i64,
+    /// The size of the originals and output images at the day's last snapshot.
+    pub bytes_stored: Option<i64>,
+    pub objects_stored: Option<i64>,
+}
+
+/// Uploads and served images to add to a project's daily totals.
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = metering)]
+pub struct MeteredTraffic {
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub day: NaiveDate,
+    pub bytes_uploaded: i64,
+    pub bytes_served: i64,
+    pub images_served: i64,
+}
+
+/// Add traffic to the daily totals.
+pub fn add_traffic(conn: &mut PgConnection, traffic: &[MeteredTraffic]) -> QueryResult<usize> {
+    diesel::insert_into(metering::table)
+        .values(traffic)
+        .on_conflict((metering::project_id, metering::day))
+        .do_update()
+        .set((
+            metering::bytes_uploaded
+                .eq(metering::bytes_uploaded + excluded(metering::bytes_uploaded)),
+            metering::bytes_served.eq(metering::bytes_served + excluded(metering::bytes_served)),
+            metering::images_served.eq(metering::images_served + excluded(metering::images_served)),
+        ))
+        .execute(conn)
+}
+
+/// Record the current size of every project's stored images as its storage for `snapshot_day`.
+pub fn snapshot_storage(conn: &mut PgConnection, snapshot_day: NaiveDate) -> QueryResult<usize> {
+    let originals = base_images::table
+        .filter(base_images::deleted.is_null())
+        .filter(base_images::original_removed.is_null())
+        .group_by((base_images::team_id, base_images::project_id))
+        .select((
+            base_images::team_id,
+            base_images::project_id,
+            count_star(),
+            diesel::dsl::sum(base_images::file_size),
+        ))
+        .load::<(TeamId, ProjectId, i64, Option<i64>)>(conn)?;
+
+    let outputs = output_images::table
+        .inner_join(base_images::table)
+        .filter(output_images::status.eq(OutputImageStatus::Ready))
+        .group_by((base_images::team_id, base_images::project_id))
+        .select((
+            base_images::team_id,
+            base_images::project_id,
+            count_star(),
+            diesel::dsl::sum(output_images::file_size),
+        ))
+        .load::<(TeamId, ProjectId, i64, Option<i64>)>(conn)?;
+
+    let mut stored: HashMap<(TeamId, ProjectId), (i64, i64)> = HashMap::new();
+    for (team, project, count, bytes) in originals.into_iter().chain(outputs) {
+        let totals = stored.entry((team, project)).or_default();
+        totals.0 += count;
+        totals.1 += bytes.unwrap_or(0);
+    }
+
+    let rows = stored
+        .into_iter()
+        .map(|((team, project), (objects, bytes))| {
+            (
+                metering::team_id.eq(team),
+                metering::project_id.eq(project),
+                metering::day.eq(snapshot_day),
+                metering::objects_stored.eq(Some(objects)),
+                metering::bytes_stored.eq(Some(bytes)),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    diesel::insert_into(metering::table)
+        .values(rows)
+        .on_conflict((metering::project_id, metering::day))
+        .do_update()
+        .set((
+            metering::objects_stored.eq(excluded(metering::objects_stored)),
+            metering::bytes_stored.eq(excluded(metering::bytes_stored)),
+        ))
+        .execute(conn)
+}
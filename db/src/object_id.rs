@@ -31,6 +31,19 @@ pub type StorageLocationId = ObjectId<6>;
 pub type UploadProfileId = ObjectId<7>;
 pub type BaseImageId = ObjectId<8>;
 pub type OutputImageId = ObjectId<9>;
+pub type TransformationPresetId = ObjectId<10>;
+pub type ImpersonationId = ObjectId<11>;
+pub type AbuseReportId = ObjectId<12>;
+pub type ProjectGrantId = ObjectId<13>;
+pub type ProjectAccessTokenId = ObjectId<14>;
+pub type OrganizationId = ObjectId<15>;
+pub type LabelPolicyId = ObjectId<16>;
+pub type TaggingRuleId = ObjectId<17>;
+pub type BulkDeletionId = ObjectId<18>;
+pub type ReferenceCrawlId = ObjectId<19>;
+pub type WebhookId = ObjectId<20>;
+pub type WebhookDeliveryId = ObjectId<21>;
+pub type ShareLinkId = ObjectId<22>;
 
 impl<const PREFIX: usize> ObjectId<PREFIX> {
     /// Once const generics supports strings, this can go away, but for now we
@@ -48,6 +61,19 @@ impl<const PREFIX: usize> ObjectId<PREFIX> {
             7 => "upl",
             8 => "bim",
             9 => "oim",
+            10 => "tpr",
+            11 => "imp",
+            12 => "abr",
+            13 => "pgr",
+            14 => "pat",
+            15 => "org",
+            16 => "lbp",
+            17 => "tgr",
+            18 => "bdl",
+            19 => "rcr",
+            20 => "whk",
+            21 => "whd",
+            22 => "shl",
             _ => "",
         }
     }
@@ -0,0 +1,56 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+pub use crate::schema::organization_members;
+pub use crate::schema::organizations::*;
+use crate::{
+    object_id::{OrganizationId, UserId},
+    schema::*,
+    OrganizationRole,
+};
+
+/// A group of teams with shared billing and storage locations, for customers that split their
+/// work into several teams.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable)]
+pub struct Organization {
+    pub id: OrganizationId,
+    pub name: String,
+    /// Where invoices for every team in the organization are sent.
+    pub billing_email: Option<String>,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    pub deleted: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = organizations)]
+pub struct NewOrganization {
+    pub id: OrganizationId,
+    pub name: String,
+    pub billing_email: Option<String>,
+}
+
+#[derive(Clone, Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = organization_members)]
+pub struct OrganizationMember {
+    pub organization_id: OrganizationId,
+    pub user_id: UserId,
+    pub role: OrganizationRole,
+    pub added: DateTime<Utc>,
+}
+
+/// Get the user's role in an organization, if they are a member of it.
+pub fn member_role(
+    conn: &mut PgConnection,
+    organization_id: OrganizationId,
+    user_id: UserId,
+) -> QueryResult<Option<OrganizationRole>> {
+    organization_members::table
+        .inner_join(organizations::table)
+        .filter(organization_members::organization_id.eq(organization_id))
+        .filter(organization_members::user_id.eq(user_id))
+        .filter(organizations::deleted.is_null())
+        .select(organization_members::role)
+        .first(conn)
+        .optional()
+}
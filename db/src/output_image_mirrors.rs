@@ -0,0 +1,66 @@
+use chrono::{DateTime, Utc};
+use diesel::{prelude::*, upsert::excluded};
+use serde::Serialize;
+
+pub use crate::schema::output_image_mirrors::*;
+use crate::{
+    enums::OutputMirrorStatus,
+    object_id::{OutputImageId, StorageLocationId, TeamId},
+    schema::*,
+};
+
+/// The copy of an output image in one of its upload profile's mirror storage locations.
+#[derive(Clone, Debug, Queryable, Selectable, Insertable, Serialize)]
+#[diesel(table_name = output_image_mirrors)]
+pub struct OutputImageMirror {
+    pub output_image_id: OutputImageId,
+    pub storage_location_id: StorageLocationId,
+    pub team_id: TeamId,
+    pub status: OutputMirrorStatus,
+    /// The SHA-256 checksum of the copy, in hex.
+    pub sha256: Option<String>,
+    /// Why the copy failed.
+    pub error: Option<String>,
+    pub updated: DateTime<Utc>,
+}
+
+/// Save the result of copying an output image to a mirror.
+pub fn record(conn: &mut PgConnection, mirror: &OutputImageMirror) -> QueryResult<usize> {
+    diesel::insert_into(output_image_mirrors::table)
+        .values(mirror)
+        .on_conflict((
+            output_image_mirrors::output_image_id,
+            output_image_mirrors::storage_location_id,
+        ))
+        .do_update()
+        .set((
+            output_image_mirrors::status.eq(excluded(output_image_mirrors::status)),
+            output_image_mirrors::sha256.eq(excluded(output_image_mirrors::sha256)),
+            output_image_mirrors::error.eq(excluded(output_image_mirrors::error)),
+            output_image_mirrors::updated.eq(excluded(output_image_mirrors::updated)),
+        ))
+        .execute(conn)
+}
+
+/// The mirrors that have a good copy of an output image.
+pub fn ready_locations(
+    conn: &mut PgConnection,
+    output_image: OutputImageId,
+) -> QueryResult<Vec<StorageLocationId>> {
+    output_image_mirrors::table
+        .filter(output_image_mirrors::output_image_id.eq(output_image))
+        .filter(output_image_mirrors::status.eq(OutputMirrorStatus::Ready))
+        .select(output_image_mirrors::storage_location_id)
+        .load(conn)
+}
+
+/// The mirror copies of a list of output images.
+pub fn for_outputs(
+    conn: &mut PgConnection,
+    output_image_ids: &[OutputImageId],
+) -> QueryResult<Vec<OutputImageMirror>> {
+    output_image_mirrors::table
+        .filter(output_image_mirrors::output_image_id.eq_any(output_image_ids))
+        .select(OutputImageMirror::as_select())
+        .load(conn)
+}
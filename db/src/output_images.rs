@@ -4,7 +4,7 @@ pub use crate::schema::output_images::*;
 use crate::{
     conversion_profiles::{ConversionFormat, ConversionSize},
     enums::OutputImageStatus,
-    object_id::{BaseImageId, OutputImageId, TeamId},
+    object_id::{BaseImageId, ConversionProfileId, OutputImageId, TeamId},
     schema::*,
 };
 
@@ -24,6 +24,15 @@ pub struct OutputImage {
 
     pub updated: chrono::DateTime<chrono::Utc>,
     pub deleted: Option<chrono::DateTime<chrono::Utc>>,
+    /// A hash of the image contents, for HTTP caching.
+    pub etag: Option<String>,
+    /// The conversion profile, and its version, that the image was rendered with.
+    pub conversion_profile_id: Option<ConversionProfileId>,
+    pub conversion_profile_version: Option<i32>,
+    /// This is an archival master rather than an output for delivery.
+    pub archival: bool,
+    /// The SHA-256 checksum of the image, in hex.
+    pub sha256: Option<String>,
 }
 
 #[derive(Debug, Insertable)]
@@ -39,4 +48,5 @@ pub struct NewOutputImage {
     pub format: ConversionFormat,
 
     pub status: OutputImageStatus,
+    pub archival: bool,
 }
@@ -30,6 +30,8 @@ pub enum ProjectPermission {
     ImageCreate,
     ConversionProfileWrite,
     StorageLocationWrite,
+    UploadProfileRead,
+    UploadProfileWrite,
 }
 
 impl From<ProjectPermission> for crate::Permission {
@@ -41,6 +43,8 @@ impl From<ProjectPermission> for crate::Permission {
             ProjectPermission::ImageCreate => Permission::ImageCreate,
             ProjectPermission::ConversionProfileWrite => Permission::ConversionProfileWrite,
             ProjectPermission::StorageLocationWrite => Permission::StorageLocationWrite,
+            ProjectPermission::UploadProfileRead => Permission::UploadProfileRead,
+            ProjectPermission::UploadProfileWrite => Permission::UploadProfileWrite,
         }
     }
 }
@@ -65,6 +69,27 @@ macro_rules! obj_allowed {
     };
 }
 
+/// Check if another team has given a team read access to the object's project, or to the
+/// collection that the object is in.
+#[macro_export]
+macro_rules! obj_granted {
+    ($team_id: expr, $obj_project_field: expr, $obj_collection_field: expr) => {
+        diesel::dsl::exists(
+            $crate::project_grants::table.filter(
+                $crate::project_grants::project_id
+                    .eq($obj_project_field)
+                    .and($crate::project_grants::grantee_team_id.eq($team_id))
+                    .and($crate::project_grants::revoked.is_null())
+                    .and(
+                        $crate::project_grants::collection
+                            .is_null()
+                            .or($crate::project_grants::collection.eq($obj_collection_field)),
+                    ),
+            ),
+        )
+    };
+}
+
 #[macro_export]
 macro_rules! obj_allowed_or_projectless {
     ($team_id: expr, $roles: expr, $obj_project_field: expr, $permission: expr) => {
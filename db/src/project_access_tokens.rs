@@ -0,0 +1,57 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+pub use crate::schema::project_access_tokens::*;
+use crate::{
+    object_id::{ProjectAccessTokenId, ProjectId, TeamId, UserId},
+    schema::*,
+};
+
+/// A token that allows reading the images in a private project.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable)]
+pub struct ProjectAccessToken {
+    pub id: ProjectAccessTokenId,
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub name: String,
+    pub hash: Vec<u8>,
+    pub created_by: UserId,
+    pub created: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+    pub revoked: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = project_access_tokens)]
+pub struct NewProjectAccessToken {
+    pub id: ProjectAccessTokenId,
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub name: String,
+    pub hash: Vec<u8>,
+    pub created_by: UserId,
+    pub expires: Option<DateTime<Utc>>,
+}
+
+/// Check whether a token with the given hash can currently be used to read images from the
+/// project.
+pub fn is_valid(
+    conn: &mut PgConnection,
+    token_id: ProjectAccessTokenId,
+    token_hash: &[u8],
+    project: ProjectId,
+) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        project_access_tokens::table
+            .filter(project_access_tokens::id.eq(token_id))
+            .filter(project_access_tokens::hash.eq(token_hash))
+            .filter(project_access_tokens::project_id.eq(project))
+            .filter(project_access_tokens::revoked.is_null())
+            .filter(
+                project_access_tokens::expires
+                    .is_null()
+                    .or(project_access_tokens::expires.gt(diesel::dsl::now)),
+            ),
+    ))
+    .get_result(conn)
+}
@@ -0,0 +1,156 @@
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+pub use crate::schema::project_grant_events;
+pub use crate::schema::project_grants::*;
+use crate::{
+    enums::ProjectGrantEventType,
+    object_id::{BaseImageId, ProjectGrantId, ProjectId, TeamId, UserId},
+    schema::*,
+};
+
+/// How often a user's reads of an image through a grant are recorded. Reads within this time
+/// of the last recorded one aren't recorded again.
+pub const ACCESS_RECORD_INTERVAL_HOURS: i64 = 24;
+
+/// Read access to a project, or to one collection in it, given by the team that owns it to
+/// another team.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable)]
+pub struct ProjectGrant {
+    pub id: ProjectGrantId,
+    /// The team that owns the project.
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    /// The team that was given access.
+    pub grantee_team_id: TeamId,
+    pub created_by: UserId,
+    pub created: DateTime<Utc>,
+    pub revoked: Option<DateTime<Utc>>,
+    pub revoked_by: Option<UserId>,
+    /// When set, the grant only covers the images in this collection.
+    pub collection: Option<String>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = project_grants)]
+pub struct NewProjectGrant {
+    pub id: ProjectGrantId,
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub grantee_team_id: TeamId,
+    pub created_by: UserId,
+    pub collection: Option<String>,
+}
+
+/// A record of a change to a grant, or of an image read through it.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable, Insertable)]
+pub struct ProjectGrantEvent {
+    pub id: Uuid,
+    pub grant_id: ProjectGrantId,
+    pub event_type: ProjectGrantEventType,
+    /// The team of the user who caused the event.
+    pub team_id: TeamId,
+    pub user_id: UserId,
+    pub base_image_id: Option<BaseImageId>,
+    pub created: DateTime<Utc>,
+}
+
+/// Find the active grant that gives a team access to a project, or to a collection in it when
+/// `grant_collection` is set.
+pub fn find_active(
+    conn: &mut PgConnection,
+    project: ProjectId,
+    grantee: TeamId,
+    grant_collection: Option<&str>,
+) -> QueryResult<Option<ProjectGrant>> {
+    let query = project_grants::table
+        .filter(project_grants::project_id.eq(project))
+        .filter(project_grants::grantee_team_id.eq(grantee))
+        .filter(project_grants::revoked.is_null())
+        .select(ProjectGrant::as_select())
+        .into_boxed();
+
+    let query = match grant_collection {
+        Some(c) => query.filter(project_grants::collection.eq(c)),
+        None => query.filter(project_grants::collection.is_null()),
+    };
+
+    query.first(conn).optional()
+}
+
+/// Find an active grant that gives a team access to an image in the given collection of a
+/// project. A grant for the whole project is preferred over one for the collection.
+pub fn find_covering(
+    conn: &mut PgConnection,
+    project: ProjectId,
+    grantee: TeamId,
+    image_collection: Option<&str>,
+) -> QueryResult<Option<ProjectGrant>> {
+    project_grants::table
+        .filter(project_grants::project_id.eq(project))
+        .filter(project_grants::grantee_team_id.eq(grantee))
+        .filter(project_grants::revoked.is_null())
+        .filter(
+            project_grants::collection
+                .is_null()
+                .or(project_grants::collection.eq(image_collection)),
+        )
+        .order(project_grants::collection.is_not_null())
+        .select(ProjectGrant::as_select())
+        .first(conn)
+        .optional()
+}
+
+pub fn record_event(
+    conn: &mut PgConnection,
+    grant_id: ProjectGrantId,
+    event_type: ProjectGrantEventType,
+    (team, user): (TeamId, UserId),
+    base_image_id: Option<BaseImageId>,
+) -> QueryResult<()> {
+    diesel::insert_into(project_grant_events::table)
+        .values(ProjectGrantEvent {
+            id: crate::new_uuid(),
+            grant_id,
+            event_type,
+            team_id: team,
+            user_id: user,
+            base_image_id,
+            created: Utc::now(),
+        })
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Record that a user read an image through a grant, unless the same read was recorded within
+/// the last [ACCESS_RECORD_INTERVAL_HOURS], so that repeated reads don't each add an event.
+pub fn record_access(
+    conn: &mut PgConnection,
+    grant_id: ProjectGrantId,
+    (team, user): (TeamId, UserId),
+    base_image_id: BaseImageId,
+) -> QueryResult<()> {
+    let since = Utc::now() - Duration::hours(ACCESS_RECORD_INTERVAL_HOURS);
+    let recorded = diesel::select(diesel::dsl::exists(
+        project_grant_events::table
+            .filter(project_grant_events::grant_id.eq(grant_id))
+            .filter(project_grant_events::user_id.eq(user))
+            .filter(project_grant_events::base_image_id.eq(base_image_id))
+            .filter(project_grant_events::event_type.eq(ProjectGrantEventType::Accessed))
+            .filter(project_grant_events::created.gt(since)),
+    ))
+    .get_result::<bool>(conn)?;
+
+    if recorded {
+        return Ok(());
+    }
+
+    record_event(
+        conn,
+        grant_id,
+        ProjectGrantEventType::Accessed,
+        (team, user),
+        Some(base_image_id),
+    )
+}
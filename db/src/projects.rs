@@ -1,7 +1,8 @@
-use diesel::prelude::*;
-use serde::Deserialize;
+use diesel::{prelude::*, sql_types::Jsonb};
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    diesel_jsonb,
     object_id::{ProjectId, TeamId},
     schema::*,
 };
@@ -17,8 +18,41 @@ pub struct Project {
 
     pub updated: chrono::DateTime<chrono::Utc>,
     pub deleted: Option<chrono::DateTime<chrono::Utc>>,
+    /// Private projects can only be served with an access token or a signed URL.
+    pub private: bool,
+    /// The sites that may embed the project's images. Any site may embed them if this is None.
+    pub allowed_referers: Option<Vec<String>>,
+    /// Refuse requests without a Referer or Origin header when `allowed_referers` is set.
+    pub block_empty_referer: bool,
+    /// CORS settings for the serve and upload routes. No CORS headers are sent if this is None.
+    pub cors: Option<CorsSettings>,
+    /// Labels for the team's label policies, which apply to every image in the project.
+    pub labels: Vec<String>,
 }
 
+/// The cross-origin requests that browsers may make to a project's images.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow,
+)]
+#[diesel(sql_type = Jsonb)]
+pub struct CorsSettings {
+    /// Origins such as `https://example.com`, or `*` to allow any origin.
+    pub allowed_origins: Vec<String>,
+    /// Methods allowed in addition to the simple methods that don't need a preflight request.
+    #[serde(default)]
+    pub allowed_methods: Vec<String>,
+    /// Request headers allowed in addition to the CORS-safelisted headers.
+    #[serde(default)]
+    pub allowed_headers: Vec<String>,
+    /// How long browsers may cache the response to a preflight request, in seconds.
+    #[serde(default)]
+    pub max_age: Option<u32>,
+    #[serde(default)]
+    pub allow_credentials: bool,
+}
+
+diesel_jsonb!(CorsSettings);
+
 #[derive(Clone, Debug, Deserialize, Insertable)]
 #[diesel(table_name = projects)]
 pub struct NewProject {
@@ -0,0 +1,39 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+pub use crate::schema::reference_crawls::*;
+use crate::{
+    enums::ReferenceCrawlStatus,
+    object_id::{ProjectId, ReferenceCrawlId, TeamId, UserId},
+    schema::*,
+};
+
+/// A crawl of a site's sitemap for the pages that use a project's images.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable)]
+pub struct ReferenceCrawl {
+    pub id: ReferenceCrawlId,
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    /// The user who started the crawl.
+    pub user_id: UserId,
+    pub sitemap_url: String,
+    /// The most pages to scan.
+    pub max_pages: i32,
+    pub status: ReferenceCrawlStatus,
+    pub pages_scanned: i32,
+    pub references_found: i32,
+    pub error: Option<String>,
+    pub created: DateTime<Utc>,
+    pub finished: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = reference_crawls)]
+pub struct NewReferenceCrawl {
+    pub id: ReferenceCrawlId,
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub user_id: UserId,
+    pub sitemap_url: String,
+    pub max_pages: i32,
+}
@@ -1,21 +1,81 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "abuse_report_status"))]
+    pub struct AbuseReportStatus;
+
     #[derive(diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "base_image_status"))]
     pub struct BaseImageStatus;
 
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "bulk_deletion_status"))]
+    pub struct BulkDeletionStatus;
+
     #[derive(diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "image_format"))]
     pub struct ImageFormat;
 
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "image_reference_source"))]
+    pub struct ImageReferenceSource;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "organization_role"))]
+    pub struct OrganizationRole;
+
     #[derive(diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "output_image_status"))]
     pub struct OutputImageStatus;
 
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "output_mirror_status"))]
+    pub struct OutputMirrorStatus;
+
     #[derive(diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "permission"))]
     pub struct Permission;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "project_grant_event_type"))]
+    pub struct ProjectGrantEventType;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "reference_crawl_status"))]
+    pub struct ReferenceCrawlStatus;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "storage_serve_mode"))]
+    pub struct StorageServeMode;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "team_status"))]
+    pub struct TeamStatus;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "webhook_delivery_status"))]
+    pub struct WebhookDeliveryStatus;
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+    use super::sql_types::AbuseReportStatus;
+
+    abuse_reports (id) {
+        id -> Uuid,
+        url -> Text,
+        reason -> Text,
+        reporter_email -> Nullable<Text>,
+        team_id -> Nullable<Uuid>,
+        base_image_id -> Nullable<Uuid>,
+        status -> AbuseReportStatus,
+        created -> Timestamptz,
+        resolved -> Nullable<Timestamptz>,
+        resolved_by -> Nullable<Uuid>,
+        resolution_note -> Nullable<Text>,
+    }
 }
 
 diesel::table! {
@@ -46,6 +106,22 @@ diesel::table! {
         inherits_user_permissions -> Bool,
         created -> Timestamptz,
         expires -> Nullable<Timestamptz>,
+        bound_upload_profile_id -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    api_usage (project_id, hour, api_key_id) {
+        team_id -> Uuid,
+        project_id -> Uuid,
+        api_key_id -> Uuid,
+        hour -> Timestamptz,
+        requests -> Int8,
+        client_errors -> Int8,
+        server_errors -> Int8,
     }
 }
 
@@ -73,6 +149,69 @@ diesel::table! {
         updated -> Timestamptz,
         deleted -> Nullable<Timestamptz>,
         file_size -> Int4,
+        error -> Nullable<Text>,
+        conversion_profile_id -> Nullable<Uuid>,
+        conversion_profile_version -> Nullable<Int4>,
+        tags -> Array<Text>,
+        collection -> Nullable<Text>,
+        alt_text_machine_generated -> Bool,
+        ocr_text -> Nullable<Text>,
+        codes -> Jsonb,
+        output_key_prefix -> Nullable<Text>,
+        converted -> Nullable<Timestamptz>,
+        original_removed -> Nullable<Timestamptz>,
+        original_archive_location_id -> Nullable<Uuid>,
+        created -> Timestamptz,
+        pinned -> Bool,
+        sha256 -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+    use super::sql_types::BulkDeletionStatus;
+
+    bulk_deletions (id) {
+        id -> Uuid,
+        team_id -> Uuid,
+        project_id -> Uuid,
+        user_id -> Uuid,
+        filter -> Jsonb,
+        status -> BulkDeletionStatus,
+        confirmation_hash -> Bytea,
+        expires -> Timestamptz,
+        matched -> Int8,
+        deleted -> Int8,
+        failed -> Int8,
+        error -> Nullable<Text>,
+        created -> Timestamptz,
+        started -> Nullable<Timestamptz>,
+        finished -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    conversion_pauses (team_id) {
+        team_id -> Uuid,
+        reason -> Nullable<Text>,
+        created -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    conversion_profile_versions (conversion_profile_id, version) {
+        conversion_profile_id -> Uuid,
+        version -> Int4,
+        output -> Jsonb,
+        output_key_template -> Nullable<Text>,
+        created -> Timestamptz,
     }
 }
 
@@ -88,6 +227,153 @@ diesel::table! {
         output -> Jsonb,
         updated -> Timestamptz,
         deleted -> Nullable<Timestamptz>,
+        output_key_template -> Nullable<Text>,
+        extends -> Nullable<Uuid>,
+        overrides -> Nullable<Jsonb>,
+        version -> Int4,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    delivery_domains (hostname) {
+        hostname -> Text,
+        team_id -> Uuid,
+        project_id -> Uuid,
+        created_by -> Uuid,
+        created -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    held_conversions (base_image_id) {
+        base_image_id -> Uuid,
+        team_id -> Uuid,
+        payload -> Jsonb,
+        created -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+    use super::sql_types::ImageReferenceSource;
+
+    image_references (base_image_id, page_url) {
+        base_image_id -> Uuid,
+        team_id -> Uuid,
+        project_id -> Uuid,
+        page_url -> Text,
+        source -> ImageReferenceSource,
+        first_seen -> Timestamptz,
+        last_seen -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    impersonation_events (id) {
+        id -> Uuid,
+        impersonation_id -> Uuid,
+        method -> Text,
+        path -> Text,
+        created -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    impersonations (id) {
+        id -> Uuid,
+        admin_user_id -> Uuid,
+        team_id -> Uuid,
+        reason -> Text,
+        created -> Timestamptz,
+        expires -> Timestamptz,
+        ended -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    label_policies (id) {
+        id -> Uuid,
+        team_id -> Uuid,
+        label -> Text,
+        rule -> Jsonb,
+        updated -> Timestamptz,
+        deleted -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    link_checks (id) {
+        id -> Uuid,
+        team_id -> Uuid,
+        project_id -> Uuid,
+        base_image_id -> Uuid,
+        url -> Text,
+        ok -> Bool,
+        status_code -> Nullable<Int4>,
+        error -> Nullable<Text>,
+        checked -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    metering (project_id, day) {
+        team_id -> Uuid,
+        project_id -> Uuid,
+        day -> Date,
+        bytes_uploaded -> Int8,
+        bytes_served -> Int8,
+        images_served -> Int8,
+        bytes_stored -> Nullable<Int8>,
+        objects_stored -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+    use super::sql_types::OrganizationRole;
+
+    organization_members (organization_id, user_id) {
+        organization_id -> Uuid,
+        user_id -> Uuid,
+        role -> OrganizationRole,
+        added -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    organizations (id) {
+        id -> Uuid,
+        name -> Text,
+        billing_email -> Nullable<Text>,
+        created -> Timestamptz,
+        updated -> Timestamptz,
+        deleted -> Nullable<Timestamptz>,
     }
 }
 
@@ -109,6 +395,77 @@ diesel::table! {
         updated -> Timestamptz,
         deleted -> Nullable<Timestamptz>,
         file_size -> Int4,
+        etag -> Nullable<Text>,
+        conversion_profile_id -> Nullable<Uuid>,
+        conversion_profile_version -> Nullable<Int4>,
+        archival -> Bool,
+        sha256 -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+    use super::sql_types::OutputMirrorStatus;
+
+    output_image_mirrors (output_image_id, storage_location_id) {
+        output_image_id -> Uuid,
+        storage_location_id -> Uuid,
+        team_id -> Uuid,
+        status -> OutputMirrorStatus,
+        sha256 -> Nullable<Text>,
+        error -> Nullable<Text>,
+        updated -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    project_access_tokens (id) {
+        id -> Uuid,
+        team_id -> Uuid,
+        project_id -> Uuid,
+        name -> Text,
+        hash -> Bytea,
+        created_by -> Uuid,
+        created -> Timestamptz,
+        expires -> Nullable<Timestamptz>,
+        revoked -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+    use super::sql_types::ProjectGrantEventType;
+
+    project_grant_events (id) {
+        id -> Uuid,
+        grant_id -> Uuid,
+        event_type -> ProjectGrantEventType,
+        team_id -> Uuid,
+        user_id -> Uuid,
+        base_image_id -> Nullable<Uuid>,
+        created -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    project_grants (id) {
+        id -> Uuid,
+        team_id -> Uuid,
+        project_id -> Uuid,
+        grantee_team_id -> Uuid,
+        created_by -> Uuid,
+        created -> Timestamptz,
+        revoked -> Nullable<Timestamptz>,
+        revoked_by -> Nullable<Uuid>,
+        collection -> Nullable<Text>,
     }
 }
 
@@ -123,6 +480,32 @@ diesel::table! {
         base_location -> Text,
         updated -> Timestamptz,
         deleted -> Nullable<Timestamptz>,
+        private -> Bool,
+        allowed_referers -> Nullable<Array<Text>>,
+        block_empty_referer -> Bool,
+        cors -> Nullable<Jsonb>,
+        labels -> Array<Text>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+    use super::sql_types::ReferenceCrawlStatus;
+
+    reference_crawls (id) {
+        id -> Uuid,
+        team_id -> Uuid,
+        project_id -> Uuid,
+        user_id -> Uuid,
+        sitemap_url -> Text,
+        max_pages -> Int4,
+        status -> ReferenceCrawlStatus,
+        pages_scanned -> Int4,
+        references_found -> Int4,
+        error -> Nullable<Text>,
+        created -> Timestamptz,
+        finished -> Nullable<Timestamptz>,
     }
 }
 
@@ -167,6 +550,26 @@ diesel::table! {
     use diesel::sql_types::*;
     use crate::enums::*;
 
+    share_links (id) {
+        id -> Uuid,
+        team_id -> Uuid,
+        project_id -> Uuid,
+        collection -> Text,
+        title -> Nullable<Text>,
+        hash -> Bytea,
+        password_hash -> Nullable<Text>,
+        created_by -> Uuid,
+        created -> Timestamptz,
+        expires -> Timestamptz,
+        revoked -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+    use super::sql_types::StorageServeMode;
+
     storage_locations (id) {
         id -> Uuid,
         team_id -> Uuid,
@@ -177,17 +580,71 @@ diesel::table! {
         public_url_base -> Text,
         updated -> Timestamptz,
         deleted -> Nullable<Timestamptz>,
+        serve_mode -> StorageServeMode,
+        cdn_purge -> Nullable<Jsonb>,
+        organization_id -> Nullable<Uuid>,
+        labels -> Array<Text>,
+        key_prefix_template -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    tagging_rules (id) {
+        id -> Uuid,
+        team_id -> Uuid,
+        project_id -> Uuid,
+        name -> Text,
+        priority -> Int4,
+        conditions -> Jsonb,
+        tags -> Array<Text>,
+        collection -> Nullable<Text>,
+        updated -> Timestamptz,
+        deleted -> Nullable<Timestamptz>,
     }
 }
 
 diesel::table! {
     use diesel::sql_types::*;
     use crate::enums::*;
+    use super::sql_types::TeamStatus;
 
     teams (id) {
         id -> Uuid,
         name -> Text,
         deleted -> Nullable<Timestamptz>,
+        status -> TeamStatus,
+        organization_id -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    transformation_presets (id) {
+        id -> Uuid,
+        team_id -> Uuid,
+        project_id -> Nullable<Uuid>,
+        name -> Text,
+        operations -> Jsonb,
+        updated -> Timestamptz,
+        deleted -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    upload_chunks (upload_session_id, chunk_index) {
+        upload_session_id -> Uuid,
+        chunk_index -> Int4,
+        size -> Int4,
+        sha256 -> Text,
+        uploaded -> Timestamptz,
     }
 }
 
@@ -208,6 +665,35 @@ diesel::table! {
         deleted -> Nullable<Timestamptz>,
         base_storage_location_path -> Nullable<Text>,
         output_storage_location_path -> Nullable<Text>,
+        require_signed_urls -> Bool,
+        signed_url_ttl_seconds -> Nullable<Int4>,
+        allowed_countries -> Nullable<Array<Text>>,
+        blocked_countries -> Nullable<Array<Text>>,
+        cache_control -> Nullable<Text>,
+        save_data_enabled -> Bool,
+        save_data_quality -> Nullable<Int4>,
+        format_fallbacks -> Nullable<Jsonb>,
+        labels -> Array<Text>,
+        constraints -> Nullable<Jsonb>,
+        original_retention -> Nullable<Jsonb>,
+        output_mirror_location_ids -> Array<Uuid>,
+        post_process_callback -> Nullable<Jsonb>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    upload_sessions (id) {
+        id -> Uuid,
+        team_id -> Uuid,
+        base_image_id -> Uuid,
+        total_size -> Int8,
+        chunk_size -> Int4,
+        chunk_count -> Int4,
+        created -> Timestamptz,
+        expires -> Timestamptz,
     }
 }
 
@@ -235,50 +721,205 @@ diesel::table! {
         default_upload_profile_id -> Nullable<Uuid>,
         updated -> Timestamptz,
         deleted -> Nullable<Timestamptz>,
+        instance_admin -> Bool,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    webhook_allowlists (team_id) {
+        team_id -> Uuid,
+        hosts -> Array<Text>,
+        updated -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+    use super::sql_types::WebhookDeliveryStatus;
+
+    webhook_deliveries (id) {
+        id -> Uuid,
+        team_id -> Uuid,
+        webhook_id -> Uuid,
+        project_id -> Nullable<Uuid>,
+        event_type -> Text,
+        payload -> Jsonb,
+        status -> WebhookDeliveryStatus,
+        attempts -> Int4,
+        next_attempt -> Timestamptz,
+        response_status -> Nullable<Int4>,
+        error -> Nullable<Text>,
+        created -> Timestamptz,
+        delivered -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    webhook_delivery_attempts (delivery_id, attempt) {
+        delivery_id -> Uuid,
+        attempt -> Int4,
+        response_status -> Nullable<Int4>,
+        error -> Nullable<Text>,
+        created -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::enums::*;
+
+    webhooks (id) {
+        id -> Uuid,
+        team_id -> Uuid,
+        url -> Text,
+        secret -> Text,
+        description -> Nullable<Text>,
+        updated -> Timestamptz,
+        deleted -> Nullable<Timestamptz>,
+        event_types -> Array<Text>,
+        project_ids -> Array<Uuid>,
+        payload_template -> Nullable<Text>,
     }
 }
 
+diesel::joinable!(abuse_reports -> base_images (base_image_id));
+diesel::joinable!(abuse_reports -> teams (team_id));
+diesel::joinable!(abuse_reports -> users (resolved_by));
 diesel::joinable!(api_key_permissions -> api_keys (api_key_id));
 diesel::joinable!(api_key_permissions -> teams (team_id));
 diesel::joinable!(api_keys -> teams (team_id));
-diesel::joinable!(api_keys -> upload_profiles (default_upload_profile_id));
 diesel::joinable!(api_keys -> users (user_id));
+diesel::joinable!(api_usage -> projects (project_id));
+diesel::joinable!(api_usage -> teams (team_id));
+diesel::joinable!(base_images -> conversion_profiles (conversion_profile_id));
 diesel::joinable!(base_images -> projects (project_id));
+diesel::joinable!(base_images -> storage_locations (original_archive_location_id));
 diesel::joinable!(base_images -> teams (team_id));
 diesel::joinable!(base_images -> upload_profiles (upload_profile_id));
 diesel::joinable!(base_images -> users (user_id));
+diesel::joinable!(bulk_deletions -> projects (project_id));
+diesel::joinable!(bulk_deletions -> teams (team_id));
+diesel::joinable!(bulk_deletions -> users (user_id));
+diesel::joinable!(conversion_profile_versions -> conversion_profiles (conversion_profile_id));
 diesel::joinable!(conversion_profiles -> projects (project_id));
 diesel::joinable!(conversion_profiles -> teams (team_id));
+diesel::joinable!(delivery_domains -> projects (project_id));
+diesel::joinable!(delivery_domains -> teams (team_id));
+diesel::joinable!(delivery_domains -> users (created_by));
+diesel::joinable!(impersonation_events -> impersonations (impersonation_id));
+diesel::joinable!(held_conversions -> base_images (base_image_id));
+diesel::joinable!(held_conversions -> teams (team_id));
+diesel::joinable!(image_references -> base_images (base_image_id));
+diesel::joinable!(image_references -> projects (project_id));
+diesel::joinable!(image_references -> teams (team_id));
+diesel::joinable!(impersonations -> teams (team_id));
+diesel::joinable!(impersonations -> users (admin_user_id));
+diesel::joinable!(label_policies -> teams (team_id));
+diesel::joinable!(link_checks -> base_images (base_image_id));
+diesel::joinable!(link_checks -> projects (project_id));
+diesel::joinable!(link_checks -> teams (team_id));
+diesel::joinable!(metering -> projects (project_id));
+diesel::joinable!(metering -> teams (team_id));
+diesel::joinable!(organization_members -> organizations (organization_id));
+diesel::joinable!(organization_members -> users (user_id));
+diesel::joinable!(output_image_mirrors -> output_images (output_image_id));
+diesel::joinable!(output_image_mirrors -> storage_locations (storage_location_id));
+diesel::joinable!(output_image_mirrors -> teams (team_id));
 diesel::joinable!(output_images -> base_images (base_image_id));
+diesel::joinable!(output_images -> conversion_profiles (conversion_profile_id));
 diesel::joinable!(output_images -> teams (team_id));
+diesel::joinable!(project_access_tokens -> projects (project_id));
+diesel::joinable!(project_access_tokens -> teams (team_id));
+diesel::joinable!(project_access_tokens -> users (created_by));
+diesel::joinable!(project_grant_events -> base_images (base_image_id));
+diesel::joinable!(project_grant_events -> project_grants (grant_id));
+diesel::joinable!(project_grant_events -> teams (team_id));
+diesel::joinable!(project_grant_events -> users (user_id));
+diesel::joinable!(project_grants -> projects (project_id));
 diesel::joinable!(projects -> teams (team_id));
+diesel::joinable!(reference_crawls -> projects (project_id));
+diesel::joinable!(reference_crawls -> teams (team_id));
+diesel::joinable!(reference_crawls -> users (user_id));
 diesel::joinable!(role_permissions -> roles (role_id));
 diesel::joinable!(role_permissions -> teams (team_id));
 diesel::joinable!(roles -> teams (team_id));
 diesel::joinable!(sessions -> users (user_id));
+diesel::joinable!(share_links -> projects (project_id));
+diesel::joinable!(share_links -> teams (team_id));
+diesel::joinable!(share_links -> users (created_by));
+diesel::joinable!(storage_locations -> organizations (organization_id));
 diesel::joinable!(storage_locations -> projects (project_id));
 diesel::joinable!(storage_locations -> teams (team_id));
+diesel::joinable!(tagging_rules -> projects (project_id));
+diesel::joinable!(tagging_rules -> teams (team_id));
+diesel::joinable!(teams -> organizations (organization_id));
+diesel::joinable!(transformation_presets -> projects (project_id));
+diesel::joinable!(transformation_presets -> teams (team_id));
+diesel::joinable!(upload_chunks -> upload_sessions (upload_session_id));
 diesel::joinable!(upload_profiles -> conversion_profiles (conversion_profile_id));
 diesel::joinable!(upload_profiles -> projects (project_id));
 diesel::joinable!(upload_profiles -> teams (team_id));
+diesel::joinable!(upload_sessions -> base_images (base_image_id));
+diesel::joinable!(upload_sessions -> teams (team_id));
 diesel::joinable!(user_roles -> roles (role_id));
 diesel::joinable!(user_roles -> users (user_id));
 diesel::joinable!(users -> teams (team_id));
 diesel::joinable!(users -> upload_profiles (default_upload_profile_id));
+diesel::joinable!(webhook_deliveries -> projects (project_id));
+diesel::joinable!(webhook_deliveries -> teams (team_id));
+diesel::joinable!(webhook_deliveries -> webhooks (webhook_id));
+diesel::joinable!(webhook_delivery_attempts -> webhook_deliveries (delivery_id));
+diesel::joinable!(webhooks -> teams (team_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    abuse_reports,
     api_key_permissions,
     api_keys,
+    api_usage,
     base_images,
+    bulk_deletions,
+    conversion_pauses,
+    conversion_profile_versions,
     conversion_profiles,
+    delivery_domains,
+    held_conversions,
+    image_references,
+    impersonation_events,
+    impersonations,
+    label_policies,
+    link_checks,
+    metering,
+    organization_members,
+    organizations,
+    output_image_mirrors,
     output_images,
+    project_access_tokens,
+    project_grant_events,
+    project_grants,
     projects,
+    reference_crawls,
     role_permissions,
     roles,
     sessions,
+    share_links,
     storage_locations,
+    tagging_rules,
     teams,
+    transformation_presets,
+    upload_chunks,
     upload_profiles,
+    upload_sessions,
     user_roles,
     users,
+    webhook_allowlists,
+    webhook_deliveries,
+    webhook_delivery_attempts,
+    webhooks,
 );
@@ -0,0 +1,55 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+pub use crate::schema::share_links::*;
+use crate::{
+    object_id::{ProjectId, ShareLinkId, TeamId, UserId},
+    schema::*,
+};
+
+/// A link that shows the images in a collection to anyone who has it.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable)]
+pub struct ShareLink {
+    pub id: ShareLinkId,
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub collection: String,
+    pub title: Option<String>,
+    pub hash: Vec<u8>,
+    pub password_hash: Option<String>,
+    pub created_by: UserId,
+    pub created: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+    pub revoked: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = share_links)]
+pub struct NewShareLink {
+    pub id: ShareLinkId,
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub collection: String,
+    pub title: Option<String>,
+    pub hash: Vec<u8>,
+    pub password_hash: Option<String>,
+    pub created_by: UserId,
+    pub expires: DateTime<Utc>,
+}
+
+/// Look up a share link by its ID and token hash. Links that have expired or been revoked are
+/// not returned.
+pub fn find_active(
+    conn: &mut PgConnection,
+    link_id: ShareLinkId,
+    token_hash: &[u8],
+) -> QueryResult<Option<ShareLink>> {
+    share_links::table
+        .filter(share_links::id.eq(link_id))
+        .filter(share_links::hash.eq(token_hash))
+        .filter(share_links::revoked.is_null())
+        .filter(share_links::expires.gt(diesel::dsl::now))
+        .select(ShareLink::as_select())
+        .first(conn)
+        .optional()
+}
@@ -3,14 +3,16 @@ use diesel::sql_types::Jsonb;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    credentials::{self, CredentialError},
     diesel_jsonb,
-    object_id::{ProjectId, StorageLocationId, TeamId},
+    enums::StorageServeMode,
+    object_id::{OrganizationId, ProjectId, StorageLocationId, TeamId},
     schema::*,
 };
 
 pub use crate::schema::storage_locations::*;
 
-#[derive(Debug, Clone, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[derive(Clone, Serialize, Deserialize, AsExpression, FromSqlRow)]
 #[diesel(sql_type = Jsonb)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum Provider {
@@ -18,21 +20,361 @@ pub enum Provider {
     Local,
     /// S3 or compatible storage
     S3 {
+        /// The endpoint of an S3-compatible service such as MinIO, R2, or Wasabi. AWS is used
+        /// when this is not set. `http://` endpoints are allowed for local services.
         endpoint: Option<String>,
+        /// The bucket's region. Some services need a specific value here, such as `auto` for R2.
         region: Option<String>,
         access_key_id: Option<String>,
         secret_key: Option<String>,
+        /// Put the bucket name in the host instead of the path. Path-style addressing is used
+        /// when this is not set, which works with most S3-compatible services.
         virtual_host_style: Option<bool>,
+        /// Accept any TLS certificate from the endpoint, for services with self-signed
+        /// certificates.
+        #[serde(default)]
+        skip_tls_verify: Option<bool>,
+    },
+    /// Google Cloud Storage
+    Gcs {
+        /// The JSON key of a service account. When this is not set, the server's own credentials
+        /// are used, such as a workload identity.
+        service_account_key: Option<String>,
+    },
+    /// Azure Blob Storage
+    Azure {
+        /// The storage account name. This can be left out when the connection string has one.
+        account: Option<String>,
+        /// A connection string from the Azure portal. When this is not set, the server's managed
+        /// identity is used.
+        connection_string: Option<String>,
+        /// The client ID of a user-assigned managed identity. The system-assigned identity is
+        /// used when this is not set.
+        client_id: Option<String>,
+    },
+    /// Backblaze B2, through its S3-compatible API.
+    B2 {
+        /// The region in the bucket's S3 endpoint, such as `us-west-004`.
+        region: String,
+        /// The ID of an application key with access to the bucket.
+        key_id: String,
+        application_key: String,
+    },
+    /// A WebDAV server, for hosting that can't use any of the object storage providers.
+    #[serde(rename = "webdav")]
+    WebDav {
+        /// The root URL of the server. Base locations are paths inside it.
+        url: String,
+        /// The username and password for basic authentication.
+        username: Option<String>,
+        password: Option<String>,
+    },
+    /// An SFTP server, for hosting that only allows uploads over SSH. Base locations are
+    /// directories on the server, and relative paths are inside the user's home directory.
+    Sftp {
+        host: String,
+        /// Defaults to 22.
+        port: Option<u16>,
+        username: String,
+        password: Option<String>,
+        /// A private key in PEM format, used instead of the password when both are set.
+        private_key: Option<String>,
+        /// The SHA-256 fingerprint of the server's host key, as `ssh-keygen -l` prints it, such
+        /// as `SHA256:uNiVztksCsDhcc0u9e8BujQXVUpKZIDTMczCvj3tD2s`. Connections to a server
+        /// with a different key are refused.
+        host_key_fingerprint: String,
     },
 }
 
 diesel_jsonb!(Provider);
 
+impl Provider {
+    /// The public URL for a storage location, for providers that have a standard one.
+    pub fn default_public_url_base(&self, bucket_path: &str) -> Option<String> {
+        match self {
+            Self::Gcs { .. } => Some(format!(
+                "https://storage.googleapis.com/{}",
+                bucket_path.trim_matches('/')
+            )),
+            Self::Azure { .. } => self.azure_account().map(|account| {
+                format!(
+                    "https://{account}.blob.core.windows.net/{}",
+                    bucket_path.trim_matches('/')
+                )
+            }),
+            // Public B2 buckets can also be downloaded through their `f000.backblazeb2.com`
+            // style URL, but that host depends on the account, so it has to be set by hand.
+            Self::B2 { region, .. } => Some(format!(
+                "https://s3.{region}.backblazeb2.com/{}",
+                bucket_path.trim_matches('/')
+            )),
+            Self::Local | Self::S3 { .. } | Self::WebDav { .. } | Self::Sftp { .. } => None,
+        }
+    }
+
+    /// Apply a function to each of the secret fields, such as keys and passwords.
+    pub fn map_credentials<E>(
+        self,
+        mut f: impl FnMut(String) -> Result<String, E>,
+    ) -> Result<Self, E> {
+        let mut map_opt = |value: Option<String>| value.map(&mut f).transpose();
+
+        let mapped = match self {
+            Self::Local => Self::Local,
+            Self::S3 {
+                endpoint,
+                region,
+                access_key_id,
+                secret_key,
+                virtual_host_style,
+                skip_tls_verify,
+            } => Self::S3 {
+                endpoint,
+                region,
+                access_key_id,
+                secret_key: map_opt(secret_key)?,
+                virtual_host_style,
+                skip_tls_verify,
+            },
+            Self::Gcs {
+                service_account_key,
+            } => Self::Gcs {
+                service_account_key: map_opt(service_account_key)?,
+            },
+            Self::Azure {
+                account,
+                connection_string,
+                client_id,
+            } => Self::Azure {
+                account,
+                connection_string: map_opt(connection_string)?,
+                client_id,
+            },
+            Self::B2 {
+                region,
+                key_id,
+                application_key,
+            } => Self::B2 {
+                region,
+                key_id,
+                application_key: f(application_key)?,
+            },
+            Self::WebDav {
+                url,
+                username,
+                password,
+            } => Self::WebDav {
+                url,
+                username,
+                password: map_opt(password)?,
+            },
+            Self::Sftp {
+                host,
+                port,
+                username,
+                password,
+                private_key,
+                host_key_fingerprint,
+            } => Self::Sftp {
+                host,
+                port,
+                username,
+                password: map_opt(password)?,
+                private_key: map_opt(private_key)?,
+                host_key_fingerprint,
+            },
+        };
+
+        Ok(mapped)
+    }
+
+    /// Encrypt the secret fields with the master key, if one is set.
+    pub fn encrypt_credentials(self) -> Result<Self, CredentialError> {
+        self.map_credentials(|value| credentials::encrypt(&value))
+    }
+
+    /// Decrypt the secret fields with the master key.
+    pub fn decrypt_credentials(self) -> Result<Self, CredentialError> {
+        self.map_credentials(|value| credentials::decrypt(&value))
+    }
+
+    /// The storage account of an Azure location, from either the account field or the
+    /// connection string.
+    pub fn azure_account(&self) -> Option<&str> {
+        match self {
+            Self::Azure {
+                account,
+                connection_string,
+                ..
+            } => account.as_deref().or_else(|| {
+                connection_string
+                    .as_deref()
+                    .and_then(|s| connection_string_value(s, "AccountName"))
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Look up a value in an Azure connection string, which looks like
+/// `AccountName=name;AccountKey=key;EndpointSuffix=core.windows.net`.
+pub fn connection_string_value<'a>(connection_string: &'a str, key: &str) -> Option<&'a str> {
+    connection_string
+        .split(';')
+        .filter_map(|part| part.trim().split_once('='))
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value)
+}
+
+impl std::fmt::Debug for Provider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Local => f.write_str("Local"),
+            Self::S3 {
+                endpoint,
+                region,
+                virtual_host_style,
+                skip_tls_verify,
+                ..
+            } => f
+                .debug_struct("S3")
+                .field("endpoint", endpoint)
+                .field("region", region)
+                .field("virtual_host_style", virtual_host_style)
+                .field("skip_tls_verify", skip_tls_verify)
+                .finish_non_exhaustive(),
+            Self::Gcs { .. } => f.debug_struct("Gcs").finish_non_exhaustive(),
+            Self::Azure {
+                account, client_id, ..
+            } => f
+                .debug_struct("Azure")
+                .field("account", account)
+                .field("client_id", client_id)
+                .finish_non_exhaustive(),
+            Self::B2 { region, key_id, .. } => f
+                .debug_struct("B2")
+                .field("region", region)
+                .field("key_id", key_id)
+                .finish_non_exhaustive(),
+            Self::WebDav { url, username, .. } => f
+                .debug_struct("WebDav")
+                .field("url", url)
+                .field("username", username)
+                .finish_non_exhaustive(),
+            Self::Sftp {
+                host,
+                port,
+                username,
+                host_key_fingerprint,
+                ..
+            } => f
+                .debug_struct("Sftp")
+                .field("host", host)
+                .field("port", port)
+                .field("username", username)
+                .field("host_key_fingerprint", host_key_fingerprint)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+/// A CDN in front of a storage location's public URL, whose cache is purged when images are
+/// replaced or deleted.
+#[derive(Clone, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Jsonb)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum CdnPurge {
+    Cloudflare {
+        zone_id: String,
+        api_token: String,
+    },
+    Fastly {
+        service_id: String,
+        api_token: String,
+    },
+    CloudFront {
+        distribution_id: String,
+        access_key_id: String,
+        secret_key: String,
+    },
+}
+
+diesel_jsonb!(CdnPurge);
+
+impl CdnPurge {
+    /// Apply a function to each of the secret fields, such as API tokens.
+    pub fn map_credentials<E>(
+        self,
+        mut f: impl FnMut(String) -> Result<String, E>,
+    ) -> Result<Self, E> {
+        let mapped = match self {
+            Self::Cloudflare { zone_id, api_token } => Self::Cloudflare {
+                zone_id,
+                api_token: f(api_token)?,
+            },
+            Self::Fastly {
+                service_id,
+                api_token,
+            } => Self::Fastly {
+                service_id,
+                api_token: f(api_token)?,
+            },
+            Self::CloudFront {
+                distribution_id,
+                access_key_id,
+                secret_key,
+            } => Self::CloudFront {
+                distribution_id,
+                access_key_id,
+                secret_key: f(secret_key)?,
+            },
+        };
+
+        Ok(mapped)
+    }
+
+    /// Encrypt the secret fields with the master key, if one is set.
+    pub fn encrypt_credentials(self) -> Result<Self, CredentialError> {
+        self.map_credentials(|value| credentials::encrypt(&value))
+    }
+
+    /// Decrypt the secret fields with the master key.
+    pub fn decrypt_credentials(self) -> Result<Self, CredentialError> {
+        self.map_credentials(|value| credentials::decrypt(&value))
+    }
+}
+
+impl std::fmt::Debug for CdnPurge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cloudflare { zone_id, .. } => f
+                .debug_struct("Cloudflare")
+                .field("zone_id", zone_id)
+                .finish_non_exhaustive(),
+            Self::Fastly { service_id, .. } => f
+                .debug_struct("Fastly")
+                .field("service_id", service_id)
+                .finish_non_exhaustive(),
+            Self::CloudFront {
+                distribution_id, ..
+            } => f
+                .debug_struct("CloudFront")
+                .field("distribution_id", distribution_id)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
 impl std::fmt::Display for Provider {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let desc = match self {
             Self::Local => "local",
             Self::S3 { .. } => "s3",
+            Self::Gcs { .. } => "gcs",
+            Self::Azure { .. } => "azure",
+            Self::B2 { .. } => "b2",
+            Self::WebDav { .. } => "webdav",
+            Self::Sftp { .. } => "sftp",
         };
 
         f.write_str(desc)
@@ -54,6 +396,22 @@ pub struct StorageLocation {
 
     pub updated: chrono::DateTime<chrono::Utc>,
     pub deleted: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Whether the serve route redirects to `public_url_base` or proxies the image itself.
+    pub serve_mode: StorageServeMode,
+
+    /// The CDN to purge when images at `public_url_base` change.
+    pub cdn_purge: Option<CdnPurge>,
+
+    /// Set when the location is shared with every team in this organization.
+    pub organization_id: Option<OrganizationId>,
+
+    /// Labels that label policies can require images to be stored under.
+    pub labels: Vec<String>,
+
+    /// A template for a prefix added to the keys of the images written to this location, like
+    /// `{team}/{project}/{year}/{month}`.
+    pub key_prefix_template: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Insertable)]
@@ -69,4 +427,13 @@ pub struct NewStorageLocation {
 
     /// The base URL at which images in this StorageLocation can be accessed on the web.
     pub public_url_base: String,
+
+    #[serde(default)]
+    pub serve_mode: StorageServeMode,
+    #[serde(default)]
+    pub cdn_purge: Option<CdnPurge>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub key_prefix_template: Option<String>,
 }
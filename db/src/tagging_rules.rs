@@ -0,0 +1,73 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use diesel::{prelude::*, sql_types::Jsonb};
+use serde::{Deserialize, Serialize};
+
+pub use crate::schema::tagging_rules::*;
+use crate::{
+    diesel_jsonb,
+    object_id::{ProjectId, TaggingRuleId, TeamId},
+    schema::*,
+};
+
+/// A rule that tags images, or places them in a collection, based on their EXIF data.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable)]
+pub struct TaggingRule {
+    pub id: TaggingRuleId,
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub name: String,
+    /// Rules are applied in ascending order of priority. When several matching rules set a
+    /// collection, the first one wins.
+    pub priority: i32,
+    pub conditions: ExifConditions,
+    /// Tags to add to matching images.
+    pub tags: Vec<String>,
+    /// The collection to place matching images in.
+    pub collection: Option<String>,
+    pub updated: DateTime<Utc>,
+    pub deleted: Option<DateTime<Utc>>,
+}
+
+/// The EXIF fields that an image must match. Every condition that is set must match.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow,
+)]
+#[diesel(sql_type = Jsonb)]
+pub struct ExifConditions {
+    /// Matches when the camera make contains this text, ignoring case.
+    #[serde(default)]
+    pub camera_make: Option<String>,
+    /// Matches when the camera model contains this text, ignoring case.
+    #[serde(default)]
+    pub camera_model: Option<String>,
+    /// Matches when the lens model contains this text, ignoring case.
+    #[serde(default)]
+    pub lens_model: Option<String>,
+    /// Matches images captured on or after this date.
+    #[serde(default)]
+    pub captured_after: Option<NaiveDate>,
+    /// Matches images captured before this date.
+    #[serde(default)]
+    pub captured_before: Option<NaiveDate>,
+}
+
+diesel_jsonb!(ExifConditions);
+
+impl ExifConditions {
+    pub fn is_empty(&self) -> bool {
+        self == &ExifConditions::default()
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = tagging_rules)]
+pub struct NewTaggingRule {
+    pub id: TaggingRuleId,
+    pub team_id: TeamId,
+    pub project_id: ProjectId,
+    pub name: String,
+    pub priority: i32,
+    pub conditions: ExifConditions,
+    pub tags: Vec<String>,
+    pub collection: Option<String>,
+}
@@ -2,13 +2,20 @@ use diesel::prelude::*;
 use serde::Deserialize;
 
 pub use crate::schema::teams::*;
-use crate::{object_id::TeamId, schema::*};
+use crate::{
+    object_id::{OrganizationId, TeamId},
+    schema::*,
+    TeamStatus,
+};
 
 #[derive(Clone, Debug, Queryable, Insertable, Identifiable)]
 pub struct Team {
     pub id: TeamId,
     pub name: String,
     pub deleted: Option<chrono::DateTime<chrono::Utc>>,
+    pub status: TeamStatus,
+    /// The organization that the team belongs to, if any.
+    pub organization_id: Option<OrganizationId>,
 }
 
 #[derive(Debug, Deserialize, Insertable)]
@@ -2,7 +2,7 @@ use std::str::FromStr;
 
 use deadpool_diesel::Manager;
 use diesel::{pg::PgConnection, prelude::*, Connection};
-use diesel_migrations::{EmbeddedMigrations, MigrationHarness};
+use diesel_migrations::MigrationHarness;
 use eyre::{eyre, Result};
 use futures::Future;
 use lazy_static::lazy_static;
@@ -21,7 +21,7 @@ use crate::{
     upload_profiles::NewUploadProfile,
     user_roles::UserAndRole,
     users::NewUser,
-    Permission, Pool, PoolExt,
+    Permission, Pool, PoolExt, StorageServeMode,
 };
 
 #[derive(Clone)]
@@ -62,8 +62,6 @@ where
     database.drop_db().expect("Cleaning up");
 }
 
-const MIGRATIONS: EmbeddedMigrations = diesel_migrations::embed_migrations!();
-
 pub async fn create_database() -> Result<(TestDatabase, DatabaseInfo)> {
     dotenv::dotenv().ok();
     let host = std::env::var("TEST_DATABASE_HOST")
@@ -109,7 +107,7 @@ END; $$;
 
     let db_info = pool
         .interact(|conn| {
-            conn.run_pending_migrations(MIGRATIONS).unwrap();
+            conn.run_pending_migrations(crate::MIGRATIONS).unwrap();
             let admin_user = populate_database(conn)?;
             Ok::<_, eyre::Report>(admin_user)
         })
@@ -145,6 +143,7 @@ pub struct DatabaseInfo {
     pub base_storage_location_id: StorageLocationId,
     pub output_storage_location_id: StorageLocationId,
     pub conversion_profile_id: ConversionProfileId,
+    pub upload_profile_id: UploadProfileId,
 }
 
 fn populate_database(conn: &mut PgConnection) -> Result<DatabaseInfo, eyre::Report> {
@@ -231,7 +230,13 @@ fn populate_database(conn: &mut PgConnection) -> Result<DatabaseInfo, eyre::Repo
                         ..Default::default()
                     },
                 ],
+                preset: None,
+                archival: None,
+                animation: None,
             },
+            output_key_template: None,
+            extends: None,
+            overrides: None,
         })
         .execute(conn)?;
 
@@ -247,6 +252,10 @@ fn populate_database(conn: &mut PgConnection) -> Result<DatabaseInfo, eyre::Repo
                 provider: crate::storage_locations::Provider::Local,
                 base_location: "TODO".to_string(),
                 public_url_base: "https://my.images/orig_image/".to_string(),
+                serve_mode: StorageServeMode::Proxy,
+                cdn_purge: None,
+                labels: Vec::new(),
+                key_prefix_template: None,
             },
             NewStorageLocation {
                 id: output_storage_location_id,
@@ -256,6 +265,10 @@ fn populate_database(conn: &mut PgConnection) -> Result<DatabaseInfo, eyre::Repo
                 provider: crate::storage_locations::Provider::Local,
                 base_location: "TODO".to_string(),
                 public_url_base: "https://my.images/image/".to_string(),
+                serve_mode: StorageServeMode::Proxy,
+                cdn_purge: None,
+                labels: Vec::new(),
+                key_prefix_template: None,
             },
         ])
         .execute(conn)?;
@@ -274,6 +287,19 @@ fn populate_database(conn: &mut PgConnection) -> Result<DatabaseInfo, eyre::Repo
             base_storage_location_path: None,
             output_storage_location_id,
             output_storage_location_path: None,
+            require_signed_urls: false,
+            signed_url_ttl_seconds: None,
+            allowed_countries: None,
+            blocked_countries: None,
+            cache_control: None,
+            save_data_enabled: true,
+            save_data_quality: None,
+            format_fallbacks: None,
+            labels: Vec::new(),
+            constraints: None,
+            original_retention: None,
+            output_mirror_location_ids: Vec::new(),
+            post_process_callback: None,
         })
         .execute(conn)?;
 
@@ -285,6 +311,7 @@ fn populate_database(conn: &mut PgConnection) -> Result<DatabaseInfo, eyre::Repo
             email: "user@example.com".to_string(),
             password_hash: Some(PASSWORD_HASH.to_string()),
             default_upload_profile_id: Some(upload_profile_id),
+            instance_admin: false,
         })
         .execute(conn)?;
 
@@ -303,6 +330,7 @@ fn populate_database(conn: &mut PgConnection) -> Result<DatabaseInfo, eyre::Repo
         base_storage_location_id,
         output_storage_location_id,
         conversion_profile_id,
+        upload_profile_id,
         admin_user: DatabaseUser {
             user_id,
             team_id,
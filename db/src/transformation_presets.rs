@@ -0,0 +1,116 @@
+use diesel::{prelude::*, sql_types};
+use serde::{Deserialize, Serialize};
+
+pub use crate::schema::transformation_presets::*;
+use crate::{
+    diesel_jsonb,
+    object_id::{ProjectId, TeamId, TransformationPresetId},
+    schema::*,
+};
+
+/// A transformation to apply to an image after it has been resized.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum TransformationOperation {
+    Sharpen {
+        sigma: f32,
+        #[serde(default)]
+        threshold: i32,
+    },
+    Blur {
+        sigma: f32,
+    },
+    Brighten {
+        value: i32,
+    },
+    Contrast {
+        value: f32,
+    },
+    Grayscale,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipHorizontal,
+    FlipVertical,
+    /// Draw an image, such as a logo, over the output.
+    Watermark {
+        /// The base64-encoded image to draw, in any format that can be uploaded.
+        image: String,
+        #[serde(default)]
+        position: WatermarkPosition,
+        /// From 0 for invisible to 1 for fully opaque. Defaults to 1.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        opacity: Option<f32>,
+        /// The width of the watermark, as a fraction of the output's width. Defaults to 0.2.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        scale: Option<f32>,
+    },
+}
+
+/// Where a watermark is drawn on the image. Watermarks against an edge are kept a small margin
+/// away from it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = sql_types::Jsonb)]
+#[serde(transparent)]
+pub struct TransformationOperations(pub Vec<TransformationOperation>);
+
+diesel_jsonb!(TransformationOperations);
+
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable)]
+pub struct TransformationPreset {
+    pub id: TransformationPresetId,
+    pub team_id: TeamId,
+    pub project_id: Option<ProjectId>,
+    pub name: String,
+
+    /// The operations to run, in order.
+    pub operations: TransformationOperations,
+
+    pub updated: chrono::DateTime<chrono::Utc>,
+    pub deleted: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Deserialize, Insertable)]
+#[diesel(table_name = transformation_presets)]
+pub struct NewTransformationPreset {
+    pub id: TransformationPresetId,
+    pub team_id: TeamId,
+    pub project_id: Option<ProjectId>,
+    pub name: String,
+
+    pub operations: TransformationOperations,
+}
+
+/// Look up a preset by name. A preset in the given project takes precedence over a team-wide
+/// preset with the same name.
+pub fn find_by_name(
+    conn: &mut PgConnection,
+    team: TeamId,
+    project: Option<ProjectId>,
+    preset_name: &str,
+) -> Result<Option<TransformationPreset>, diesel::result::Error> {
+    use crate::schema::transformation_presets::dsl;
+
+    let query = transformation_presets::table
+        .select(TransformationPreset::as_select())
+        .filter(dsl::team_id.eq(team))
+        .filter(dsl::name.eq(preset_name))
+        .filter(dsl::deleted.is_null())
+        .order(dsl::project_id.desc().nulls_last())
+        .into_boxed();
+
+    crate::with_project_or_global!(query, project)
+        .first(conn)
+        .optional()
+}
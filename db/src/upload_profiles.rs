@@ -1,10 +1,13 @@
-use diesel::prelude::*;
-use serde::Deserialize;
+use chrono::{DateTime, Duration, Utc};
+use diesel::{prelude::*, sql_types::Jsonb};
+use serde::{Deserialize, Serialize};
 
 pub use crate::schema::upload_profiles::*;
 use crate::{
+    diesel_jsonb,
     object_id::{ConversionProfileId, ProjectId, StorageLocationId, TeamId, UploadProfileId},
     schema::*,
+    ImageFormat,
 };
 
 #[derive(Clone, Debug, Queryable, Insertable, Identifiable)]
@@ -29,6 +32,275 @@ pub struct UploadProfile {
 
     pub updated: chrono::DateTime<chrono::Utc>,
     pub deleted: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Only serve images through URLs signed by the API.
+    pub require_signed_urls: bool,
+    /// How long signed URLs last. Uses the server default if not set.
+    pub signed_url_ttl_seconds: Option<i32>,
+
+    /// If set, only serve images to these countries. ISO 3166-1 alpha-2 codes.
+    pub allowed_countries: Option<Vec<String>>,
+    /// Never serve images to these countries. ISO 3166-1 alpha-2 codes.
+    pub blocked_countries: Option<Vec<String>>,
+
+    /// The Cache-Control header to send with this profile's images. Uses the server default if
+    /// not set.
+    pub cache_control: Option<String>,
+
+    /// Serve lower quality images to clients that ask to save data or have a slow connection.
+    pub save_data_enabled: bool,
+    /// The quality to use for those clients. Uses the server default if not set.
+    pub save_data_quality: Option<i32>,
+
+    /// The formats to serve, in order of preference.
+    pub format_fallbacks: Option<FormatFallbacks>,
+
+    /// Labels for the team's label policies, which apply to every image uploaded with the
+    /// profile.
+    pub labels: Vec<String>,
+
+    /// Rules that uploaded images must follow.
+    pub constraints: Option<ImageConstraints>,
+
+    /// What to do with the originals once the output images have been generated.
+    pub original_retention: Option<OriginalRetention>,
+
+    /// Other storage locations that output images are copied to, in the order that delivery
+    /// tries them when the output storage location fails.
+    pub output_mirror_location_ids: Vec<StorageLocationId>,
+
+    /// A service to call after the output images are generated, before the image is published.
+    pub post_process_callback: Option<PostProcessCallback>,
+}
+
+/// An ordered list of formats, such as AVIF, then WebP, then JPEG. Clients get the first format
+/// in the list that they accept, or the last one if they don't accept any of them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Jsonb)]
+#[serde(transparent)]
+pub struct FormatFallbacks(pub Vec<FormatFallback>);
+
+diesel_jsonb!(FormatFallbacks);
+
+impl FormatFallbacks {
+    pub fn formats(&self) -> impl Iterator<Item = ImageFormat> + '_ {
+        self.0.iter().map(|f| f.format)
+    }
+
+    /// The most effort to spend encoding the format, if the list limits it.
+    pub fn max_effort(&self, format: ImageFormat) -> Option<u8> {
+        self.0
+            .iter()
+            .find(|f| f.format == format)
+            .and_then(|f| f.max_effort)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FormatFallback {
+    pub format: ImageFormat,
+    /// The most effort to spend encoding this format, from 0 to 10. Higher efforts give smaller
+    /// files but take longer. Uses the encoder's default if not set.
+    #[serde(default)]
+    pub max_effort: Option<u8>,
+}
+
+/// Rules that uploaded images must follow, so that images which would break a layout are
+/// rejected when they are uploaded.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Jsonb)]
+pub struct ImageConstraints {
+    #[serde(default)]
+    pub min_width: Option<u32>,
+    #[serde(default)]
+    pub max_width: Option<u32>,
+    #[serde(default)]
+    pub min_height: Option<u32>,
+    #[serde(default)]
+    pub max_height: Option<u32>,
+    /// If not empty, the image must have one of these aspect ratios.
+    #[serde(default)]
+    pub aspect_ratios: Vec<AspectRatio>,
+    /// How far an image's aspect ratio can be from an allowed one, as a fraction of the allowed
+    /// ratio. Defaults to 1%.
+    #[serde(default)]
+    pub aspect_ratio_tolerance: Option<f64>,
+    #[serde(default)]
+    pub orientation: Option<Orientation>,
+}
+
+diesel_jsonb!(ImageConstraints);
+
+/// An aspect ratio, like 16:9.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AspectRatio {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AspectRatio {
+    pub fn ratio(&self) -> f64 {
+        f64::from(self.width) / f64::from(self.height)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+    Square,
+}
+
+impl Orientation {
+    pub fn of(width: u32, height: u32) -> Orientation {
+        match width.cmp(&height) {
+            std::cmp::Ordering::Greater => Orientation::Landscape,
+            std::cmp::Ordering::Less => Orientation::Portrait,
+            std::cmp::Ordering::Equal => Orientation::Square,
+        }
+    }
+}
+
+/// What to do with an image's original some time after its output images are generated, for
+/// profiles that don't need to keep the originals around.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Jsonb)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum OriginalRetention {
+    /// Delete the original.
+    Delete { after_days: u32 },
+    /// Move the original to another storage location, such as a cold storage bucket.
+    Archive {
+        after_days: u32,
+        storage_location_id: StorageLocationId,
+    },
+}
+
+diesel_jsonb!(OriginalRetention);
+
+impl OriginalRetention {
+    pub fn after_days(&self) -> u32 {
+        match self {
+            Self::Delete { after_days } | Self::Archive { after_days, .. } => *after_days,
+        }
+    }
+
+    /// The storage location to archive originals to, if they are archived.
+    pub fn archive_location(&self) -> Option<StorageLocationId> {
+        match self {
+            Self::Delete { .. } => None,
+            Self::Archive {
+                storage_location_id,
+                ..
+            } => Some(*storage_location_id),
+        }
+    }
+
+    /// Originals of images converted before this time are due to be removed.
+    pub fn cutoff(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now - Duration::days(i64::from(self.after_days()))
+    }
+}
+
+/// A service that checks each image after its output images are generated, for teams that
+/// validate images in another system before they go live. The service can add metadata to the
+/// image, or reject it so that it is never published.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Jsonb)]
+pub struct PostProcessCallback {
+    /// The URL to `POST` the image details to.
+    pub url: String,
+    /// How long to wait for the service to respond.
+    #[serde(default = "default_callback_timeout_seconds")]
+    pub timeout_seconds: u32,
+    /// Publish the image when the service fails or doesn't respond in time. Otherwise the
+    /// conversion job fails and is retried.
+    #[serde(default)]
+    pub publish_on_failure: bool,
+}
+
+diesel_jsonb!(PostProcessCallback);
+
+fn default_callback_timeout_seconds() -> u32 {
+    10
+}
+
+/// A constraint that an image did not meet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ConstraintViolation {
+    /// The name of the constraint, such as `min_width`.
+    pub constraint: &'static str,
+    pub message: String,
+}
+
+const DEFAULT_ASPECT_RATIO_TOLERANCE: f64 = 0.01;
+
+impl ImageConstraints {
+    pub fn is_empty(&self) -> bool {
+        self == &ImageConstraints::default()
+    }
+
+    /// Check an image's dimensions against the constraints, and return the constraints that it
+    /// does not meet.
+    pub fn check(&self, width: u32, height: u32) -> Vec<ConstraintViolation> {
+        let mut violations = Vec::new();
+        let mut violation = |constraint, message| {
+            violations.push(ConstraintViolation {
+                constraint,
+                message,
+            })
+        };
+
+        if let Some(min) = self.min_width.filter(|min| width < *min) {
+            violation("min_width", format!("width {width} is less than {min}"));
+        }
+        if let Some(max) = self.max_width.filter(|max| width > *max) {
+            violation("max_width", format!("width {width} is more than {max}"));
+        }
+        if let Some(min) = self.min_height.filter(|min| height < *min) {
+            violation("min_height", format!("height {height} is less than {min}"));
+        }
+        if let Some(max) = self.max_height.filter(|max| height > *max) {
+            violation("max_height", format!("height {height} is more than {max}"));
+        }
+
+        if !self.aspect_ratios.is_empty() {
+            let tolerance = self
+                .aspect_ratio_tolerance
+                .unwrap_or(DEFAULT_ASPECT_RATIO_TOLERANCE);
+            let ratio = f64::from(width) / f64::from(height.max(1));
+            let matches = self.aspect_ratios.iter().any(|allowed| {
+                let allowed = allowed.ratio();
+                (ratio - allowed).abs() <= allowed * tolerance
+            });
+
+            if !matches {
+                let allowed = self
+                    .aspect_ratios
+                    .iter()
+                    .map(|r| format!("{}:{}", r.width, r.height))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                violation(
+                    "aspect_ratios",
+                    format!("aspect ratio {width}:{height} is not one of {allowed}"),
+                );
+            }
+        }
+
+        if let Some(orientation) = self.orientation {
+            let actual = Orientation::of(width, height);
+            if actual != orientation {
+                violation(
+                    "orientation",
+                    format!("image is {actual:?}, not {orientation:?}").to_lowercase(),
+                );
+            }
+        }
+
+        violations
+    }
 }
 
 #[derive(Debug, Deserialize, Insertable)]
@@ -51,4 +323,148 @@ pub struct NewUploadProfile {
     /// A path within the output storage location where the output images will be stored.
     pub output_storage_location_path: Option<String>,
     pub conversion_profile_id: ConversionProfileId,
+
+    /// Only serve images through URLs signed by the API.
+    #[serde(default)]
+    pub require_signed_urls: bool,
+    /// How long signed URLs last. Uses the server default if not set.
+    #[serde(default)]
+    pub signed_url_ttl_seconds: Option<i32>,
+
+    /// If set, only serve images to these countries. ISO 3166-1 alpha-2 codes.
+    #[serde(default)]
+    pub allowed_countries: Option<Vec<String>>,
+    /// Never serve images to these countries. ISO 3166-1 alpha-2 codes.
+    #[serde(default)]
+    pub blocked_countries: Option<Vec<String>>,
+
+    /// The Cache-Control header to send with this profile's images. Uses the server default if
+    /// not set.
+    #[serde(default)]
+    pub cache_control: Option<String>,
+
+    /// Serve lower quality images to clients that ask to save data or have a slow connection.
+    #[serde(default = "default_save_data_enabled")]
+    pub save_data_enabled: bool,
+    /// The quality to use for those clients. Uses the server default if not set.
+    #[serde(default)]
+    pub save_data_quality: Option<i32>,
+
+    /// The formats to serve, in order of preference.
+    #[serde(default)]
+    pub format_fallbacks: Option<FormatFallbacks>,
+
+    #[serde(default)]
+    pub labels: Vec<String>,
+
+    #[serde(default)]
+    pub constraints: Option<ImageConstraints>,
+
+    #[serde(default)]
+    pub original_retention: Option<OriginalRetention>,
+
+    #[serde(default)]
+    pub output_mirror_location_ids: Vec<StorageLocationId>,
+
+    #[serde(default)]
+    pub post_process_callback: Option<PostProcessCallback>,
+}
+
+fn default_save_data_enabled() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn violated(c: &ImageConstraints, width: u32, height: u32) -> Vec<&'static str> {
+        c.check(width, height)
+            .into_iter()
+            .map(|v| v.constraint)
+            .collect()
+    }
+
+    #[test]
+    fn dimensions() {
+        let c = ImageConstraints {
+            min_width: Some(800),
+            max_width: Some(4000),
+            max_height: Some(3000),
+            ..Default::default()
+        };
+
+        assert!(violated(&c, 1200, 800).is_empty());
+        assert_eq!(violated(&c, 640, 480), ["min_width"]);
+        assert_eq!(violated(&c, 5000, 4000), ["max_width", "max_height"]);
+        assert!(ImageConstraints::default().check(1, 1).is_empty());
+    }
+
+    #[test]
+    fn aspect_ratios() {
+        let c = ImageConstraints {
+            aspect_ratios: vec![
+                AspectRatio {
+                    width: 16,
+                    height: 9,
+                },
+                AspectRatio {
+                    width: 1,
+                    height: 1,
+                },
+            ],
+            ..Default::default()
+        };
+
+        assert!(violated(&c, 1920, 1080).is_empty());
+        // Within the default 1% tolerance
+        assert!(violated(&c, 1918, 1080).is_empty());
+        assert!(violated(&c, 500, 500).is_empty());
+        assert_eq!(violated(&c, 1200, 800), ["aspect_ratios"]);
+
+        let loose = ImageConstraints {
+            aspect_ratio_tolerance: Some(0.2),
+            ..c
+        };
+        assert!(violated(&loose, 1200, 800).is_empty());
+    }
+
+    #[test]
+    fn orientation() {
+        let c = ImageConstraints {
+            orientation: Some(Orientation::Portrait),
+            ..Default::default()
+        };
+
+        assert!(violated(&c, 800, 1200).is_empty());
+        assert_eq!(violated(&c, 1200, 800), ["orientation"]);
+        assert_eq!(
+            c.check(1000, 1000)[0].message,
+            "image is square, not portrait"
+        );
+    }
+
+    #[test]
+    fn original_retention() {
+        let location_id = StorageLocationId::new();
+        let retention: OriginalRetention = serde_json::from_value(serde_json::json!({
+            "action": "archive",
+            "after_days": 30,
+            "storage_location_id": location_id,
+        }))
+        .unwrap();
+        assert_eq!(
+            retention,
+            OriginalRetention::Archive {
+                after_days: 30,
+                storage_location_id: location_id,
+            }
+        );
+        assert_eq!(retention.archive_location(), Some(location_id));
+
+        let now = Utc::now();
+        let delete = OriginalRetention::Delete { after_days: 7 };
+        assert_eq!(delete.cutoff(now), now - Duration::days(7));
+        assert_eq!(delete.archive_location(), None);
+    }
 }
@@ -0,0 +1,91 @@
+use chrono::{DateTime, Utc};
+use diesel::{prelude::*, upsert::excluded};
+use serde::Serialize;
+use uuid::Uuid;
+
+pub use crate::schema::upload_chunks;
+pub use crate::schema::upload_sessions::*;
+use crate::{
+    object_id::{BaseImageId, TeamId},
+    schema::*,
+};
+
+/// An upload of an original in chunks. Each chunk is stored separately until the upload is
+/// completed, when they are joined into the original.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable, Insertable, Serialize)]
+pub struct UploadSession {
+    pub id: Uuid,
+    pub team_id: TeamId,
+    pub base_image_id: BaseImageId,
+    /// The size of the whole original.
+    pub total_size: i64,
+    /// The size of every chunk except the last one, which holds the rest of the file.
+    pub chunk_size: i32,
+    pub chunk_count: i32,
+    pub created: DateTime<Utc>,
+    /// The session and its chunks are removed if it isn't completed by this time.
+    pub expires: DateTime<Utc>,
+}
+
+impl UploadSession {
+    /// The size that the chunk at `index` must be.
+    pub fn expected_chunk_size(&self, index: i32) -> Option<i64> {
+        if index < 0 || index >= self.chunk_count {
+            return None;
+        }
+
+        let start = index as i64 * self.chunk_size as i64;
+        Some((self.total_size - start).min(self.chunk_size as i64))
+    }
+
+    /// Where a chunk is stored, relative to the original's storage location.
+    pub fn chunk_location(&self, image_location: &str, index: i32) -> String {
+        format!("{image_location}.chunks/{}/{index}", self.id)
+    }
+}
+
+#[derive(Clone, Debug, Queryable, Selectable, Insertable, Serialize)]
+#[diesel(table_name = upload_chunks)]
+pub struct UploadChunk {
+    #[serde(skip)]
+    pub upload_session_id: Uuid,
+    pub chunk_index: i32,
+    pub size: i32,
+    /// The SHA-256 checksum of the chunk, in hex.
+    pub sha256: String,
+    pub uploaded: DateTime<Utc>,
+}
+
+/// Save a chunk, replacing any earlier upload of the same chunk.
+pub fn record_chunk(conn: &mut PgConnection, chunk: &UploadChunk) -> QueryResult<usize> {
+    diesel::insert_into(upload_chunks::table)
+        .values(chunk)
+        .on_conflict((upload_chunks::upload_session_id, upload_chunks::chunk_index))
+        .do_update()
+        .set((
+            upload_chunks::size.eq(excluded(upload_chunks::size)),
+            upload_chunks::sha256.eq(excluded(upload_chunks::sha256)),
+            upload_chunks::uploaded.eq(excluded(upload_chunks::uploaded)),
+        ))
+        .execute(conn)
+}
+
+/// The chunks of a session that have been uploaded, in order.
+pub fn chunks(conn: &mut PgConnection, session_id: Uuid) -> QueryResult<Vec<UploadChunk>> {
+    upload_chunks::table
+        .filter(upload_chunks::upload_session_id.eq(session_id))
+        .order(upload_chunks::chunk_index.asc())
+        .select(UploadChunk::as_select())
+        .load(conn)
+}
+
+/// Remove a session and its chunk records. The stored chunks must be deleted separately.
+pub fn remove(conn: &mut PgConnection, session_id: Uuid) -> QueryResult<()> {
+    diesel::delete(upload_chunks::table)
+        .filter(upload_chunks::upload_session_id.eq(session_id))
+        .execute(conn)?;
+    diesel::delete(upload_sessions::table)
+        .filter(upload_sessions::id.eq(session_id))
+        .execute(conn)?;
+    Ok(())
+}
@@ -18,6 +18,8 @@ pub struct User {
     pub default_upload_profile_id: Option<UploadProfileId>,
     pub updated: chrono::DateTime<chrono::Utc>,
     pub deleted: Option<chrono::DateTime<chrono::Utc>>,
+    /// Instance admins manage the whole installation, across teams.
+    pub instance_admin: bool,
 }
 
 #[derive(Debug, Deserialize, Insertable)]
@@ -29,4 +31,6 @@ pub struct NewUser {
     pub name: String,
     pub password_hash: Option<String>,
     pub default_upload_profile_id: Option<UploadProfileId>,
+    #[serde(default)]
+    pub instance_admin: bool,
 }
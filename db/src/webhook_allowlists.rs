@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use diesel::{prelude::*, upsert::excluded};
+
+pub use crate::schema::webhook_allowlists::*;
+use crate::{object_id::TeamId, schema::*};
+
+/// The hosts that webhooks may send to, for one team or for every team.
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = webhook_allowlists)]
+pub struct WebhookAllowlist {
+    /// The nil ID for the list that applies to every team.
+    pub team_id: TeamId,
+    /// Host names, or patterns like `*.example.com` that match any subdomain.
+    pub hosts: Vec<String>,
+    pub updated: DateTime<Utc>,
+}
+
+impl WebhookAllowlist {
+    pub fn is_global(&self) -> bool {
+        self.team_id == TeamId::nil()
+    }
+
+    /// Whether a host is on the list.
+    pub fn allows(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.hosts
+            .iter()
+            .any(|pattern| match pattern.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .is_some_and(|prefix| prefix.ends_with('.') && prefix.len() > 1),
+                None => *pattern == host,
+            })
+    }
+}
+
+/// The ID that a list is stored under, with `None` meaning every team.
+fn list_id(team: Option<TeamId>) -> TeamId {
+    team.unwrap_or_else(TeamId::nil)
+}
+
+/// The list that applies to the team: its own list if it has one, otherwise the global list.
+/// Returns `None` when the team's webhooks can send anywhere.
+pub fn for_team(conn: &mut PgConnection, team: TeamId) -> QueryResult<Option<WebhookAllowlist>> {
+    let mut lists = webhook_allowlists::table
+        .filter(webhook_allowlists::team_id.eq_any([team, TeamId::nil()]))
+        .select(WebhookAllowlist::as_select())
+        .load(conn)?;
+
+    lists.sort_by_key(|list| list.is_global());
+    Ok(lists.into_iter().next())
+}
+
+/// Set the list for a team, or for every team when `team` is `None`.
+pub fn set(
+    conn: &mut PgConnection,
+    team: Option<TeamId>,
+    allowed_hosts: Vec<String>,
+) -> QueryResult<WebhookAllowlist> {
+    diesel::insert_into(webhook_allowlists::table)
+        .values((
+            webhook_allowlists::team_id.eq(list_id(team)),
+            webhook_allowlists::hosts.eq(allowed_hosts),
+        ))
+        .on_conflict(webhook_allowlists::team_id)
+        .do_update()
+        .set((
+            webhook_allowlists::hosts.eq(excluded(webhook_allowlists::hosts)),
+            webhook_allowlists::updated.eq(diesel::dsl::now),
+        ))
+        .returning(WebhookAllowlist::as_select())
+        .get_result(conn)
+}
+
+/// Remove a list. Returns false if there was no such list.
+pub fn remove(conn: &mut PgConnection, team: Option<TeamId>) -> QueryResult<bool> {
+    let deleted = diesel::delete(webhook_allowlists::table)
+        .filter(webhook_allowlists::team_id.eq(list_id(team)))
+        .execute(conn)?;
+    Ok(deleted > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_patterns() {
+        let list = WebhookAllowlist {
+            team_id: TeamId::nil(),
+            hosts: vec!["hooks.example.com".to_string(), "*.corp.test".to_string()],
+            updated: Utc::now(),
+        };
+
+        assert!(list.allows("hooks.example.com"));
+        assert!(list.allows("HOOKS.example.com."));
+        assert!(!list.allows("example.com"));
+        assert!(!list.allows("evilhooks.example.com"));
+        assert!(list.allows("a.corp.test"));
+        assert!(list.allows("a.b.corp.test"));
+        assert!(!list.allows("corp.test"));
+        assert!(!list.allows("evilcorp.test"));
+    }
+}
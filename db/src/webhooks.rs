@@ -0,0 +1,243 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+pub use crate::schema::webhooks::*;
+pub use crate::schema::{webhook_deliveries, webhook_delivery_attempts};
+use crate::{
+    enums::WebhookDeliveryStatus,
+    object_id::{ProjectId, TeamId, WebhookDeliveryId, WebhookId},
+    schema::*,
+};
+
+/// An endpoint that receives signed notifications of a team's events.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable)]
+pub struct Webhook {
+    pub id: WebhookId,
+    pub team_id: TeamId,
+    pub url: String,
+    /// The signing secret, encrypted with [crate::credentials::encrypt].
+    pub secret: String,
+    pub description: Option<String>,
+    pub updated: DateTime<Utc>,
+    pub deleted: Option<DateTime<Utc>>,
+    /// The event types to send. An empty list sends every type.
+    pub event_types: Vec<String>,
+    /// Only send events from these projects. An empty list sends events from every project.
+    pub project_ids: Vec<ProjectId>,
+    /// A liquid template that renders the body, instead of the standard event.
+    pub payload_template: Option<String>,
+}
+
+impl Webhook {
+    /// Whether the webhook should receive an event.
+    pub fn wants(&self, event_type: &str, project_id: Option<ProjectId>) -> bool {
+        let type_matches =
+            self.event_types.is_empty() || self.event_types.iter().any(|t| t == event_type);
+        // Events that don't belong to a project go to every webhook.
+        let project_matches = match project_id {
+            Some(project) => self.project_ids.is_empty() || self.project_ids.contains(&project),
+            None => true,
+        };
+        type_matches && project_matches
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = webhooks)]
+pub struct NewWebhook {
+    pub id: WebhookId,
+    pub team_id: TeamId,
+    pub url: String,
+    pub secret: String,
+    pub description: Option<String>,
+    pub event_types: Vec<String>,
+    pub project_ids: Vec<ProjectId>,
+    pub payload_template: Option<String>,
+}
+
+/// An event sent, or waiting to be sent, to a webhook.
+#[derive(Clone, Debug, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct WebhookDelivery {
+    pub id: WebhookDeliveryId,
+    pub team_id: TeamId,
+    pub webhook_id: WebhookId,
+    pub project_id: Option<ProjectId>,
+    pub event_type: String,
+    /// The body of the request.
+    pub payload: serde_json::Value,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i32,
+    /// When the delivery will be tried next, while it is pending.
+    pub next_attempt: DateTime<Utc>,
+    /// The status code from the most recent attempt.
+    pub response_status: Option<i32>,
+    /// Why the most recent attempt failed.
+    pub error: Option<String>,
+    pub created: DateTime<Utc>,
+    pub delivered: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct NewWebhookDelivery {
+    pub id: WebhookDeliveryId,
+    pub team_id: TeamId,
+    pub webhook_id: WebhookId,
+    pub project_id: Option<ProjectId>,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+}
+
+/// A single attempt to send a delivery.
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = webhook_delivery_attempts)]
+pub struct WebhookDeliveryAttempt {
+    pub delivery_id: WebhookDeliveryId,
+    /// The attempt's number, starting from 1.
+    pub attempt: i32,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = webhook_delivery_attempts)]
+pub struct NewWebhookDeliveryAttempt {
+    pub delivery_id: WebhookDeliveryId,
+    pub attempt: i32,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+}
+
+/// Load the team's active webhooks.
+pub fn active_for_team(conn: &mut PgConnection, team: TeamId) -> QueryResult<Vec<Webhook>> {
+    webhooks::table
+        .filter(webhooks::team_id.eq(team))
+        .filter(webhooks::deleted.is_null())
+        .select(Webhook::as_select())
+        .load(conn)
+}
+
+pub fn add_deliveries(
+    conn: &mut PgConnection,
+    deliveries: &[NewWebhookDelivery],
+) -> QueryResult<usize> {
+    diesel::insert_into(webhook_deliveries::table)
+        .values(deliveries)
+        .execute(conn)
+}
+
+/// Lease up to `limit` pending deliveries that are due, along with their webhooks. The leased
+/// deliveries aren't due again until `lease_until`, so that other workers skip them while they
+/// are being sent. This should run in a transaction.
+pub fn claim_due(
+    conn: &mut PgConnection,
+    limit: i64,
+    lease_until: DateTime<Utc>,
+) -> QueryResult<Vec<(WebhookDelivery, Webhook)>> {
+    let ids = webhook_deliveries::table
+        .filter(webhook_deliveries::status.eq(WebhookDeliveryStatus::Pending))
+        .filter(webhook_deliveries::next_attempt.le(Utc::now()))
+        .select(webhook_deliveries::id)
+        .order(webhook_deliveries::next_attempt.asc())
+        .limit(limit)
+        .for_update()
+        .skip_locked()
+        .load::<WebhookDeliveryId>(conn)?;
+
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    diesel::update(webhook_deliveries::table)
+        .filter(webhook_deliveries::id.eq_any(&ids))
+        .set(webhook_deliveries::next_attempt.eq(lease_until))
+        .execute(conn)?;
+
+    webhook_deliveries::table
+        .inner_join(webhooks::table)
+        .filter(webhook_deliveries::id.eq_any(&ids))
+        .select((WebhookDelivery::as_select(), Webhook::as_select()))
+        .load(conn)
+}
+
+/// Save the result of an attempt to send a delivery. `next_attempt` is when to try again after a
+/// failure, or `None` if the delivery has failed for good. This should run in a transaction.
+pub fn record_attempt(
+    conn: &mut PgConnection,
+    delivery_id: WebhookDeliveryId,
+    response_status: Option<i32>,
+    error: Option<String>,
+    next_attempt: Option<DateTime<Utc>>,
+) -> QueryResult<()> {
+    let now = Utc::now();
+    let status = match (&error, next_attempt) {
+        (None, _) => WebhookDeliveryStatus::Delivered,
+        (Some(_), Some(_)) => WebhookDeliveryStatus::Pending,
+        (Some(_), None) => WebhookDeliveryStatus::Failed,
+    };
+
+    let attempt = diesel::update(webhook_deliveries::table)
+        .filter(webhook_deliveries::id.eq(delivery_id))
+        .set((
+            webhook_deliveries::status.eq(status),
+            webhook_deliveries::attempts.eq(webhook_deliveries::attempts + 1),
+            webhook_deliveries::next_attempt.eq(next_attempt.unwrap_or(now)),
+            webhook_deliveries::response_status.eq(response_status),
+            webhook_deliveries::error.eq(&error),
+            webhook_deliveries::delivered
+                .eq((status == WebhookDeliveryStatus::Delivered).then_some(now)),
+        ))
+        .returning(webhook_deliveries::attempts)
+        .get_result::<i32>(conn)?;
+
+    diesel::insert_into(webhook_delivery_attempts::table)
+        .values(NewWebhookDeliveryAttempt {
+            delivery_id,
+            attempt,
+            response_status,
+            error,
+        })
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Send a delivery again as soon as possible. Returns `None` if the delivery doesn't exist or its
+/// webhook was deleted.
+pub fn redeliver(
+    conn: &mut PgConnection,
+    team: TeamId,
+    webhook: WebhookId,
+    delivery_id: WebhookDeliveryId,
+) -> QueryResult<Option<WebhookDelivery>> {
+    let active = diesel::select(diesel::dsl::exists(
+        webhooks::table
+            .filter(webhooks::id.eq(webhook))
+            .filter(webhooks::team_id.eq(team))
+            .filter(webhooks::deleted.is_null()),
+    ))
+    .get_result::<bool>(conn)?;
+    if !active {
+        return Ok(None);
+    }
+
+    diesel::update(webhook_deliveries::table)
+        .filter(webhook_deliveries::id.eq(delivery_id))
+        .filter(webhook_deliveries::webhook_id.eq(webhook))
+        .filter(webhook_deliveries::team_id.eq(team))
+        .set((
+            webhook_deliveries::status.eq(WebhookDeliveryStatus::Pending),
+            webhook_deliveries::next_attempt.eq(Utc::now()),
+        ))
+        .returning(WebhookDelivery::as_select())
+        .get_result(conn)
+        .optional()
+}
+
+/// Remove deliveries created before `before`, whatever their status.
+pub fn prune(conn: &mut PgConnection, before: DateTime<Utc>) -> QueryResult<usize> {
+    diesel::delete(webhook_deliveries::table)
+        .filter(webhook_deliveries::created.lt(before))
+        .execute(conn)
+}
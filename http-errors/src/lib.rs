@@ -11,6 +11,19 @@ pub struct ErrorResponseData {
 struct ErrorDetails {
     kind: Cow<'static, str>,
     message: Cow<'static, str>,
+    /// An ID that can be used to find the error in the logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    /// Errors for individual fields of the request.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<FieldError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+    /// The path to the field, such as `output.formats[0].quality`.
+    pub field: String,
+    pub message: Cow<'static, str>,
 }
 
 impl ErrorResponseData {
@@ -22,6 +35,8 @@ impl ErrorResponseData {
             error: ErrorDetails {
                 kind: kind.into(),
                 message: message.into(),
+                id: None,
+                fields: Vec::new(),
             },
         };
 
@@ -29,4 +44,31 @@ impl ErrorResponseData {
 
         ret
     }
+
+    /// Create an error response with an ID, so that a user can report the error and an operator
+    /// can find it in the logs.
+    pub fn with_id(
+        kind: impl Into<Cow<'static, str>>,
+        message: impl Into<Cow<'static, str>>,
+        id: impl Into<String>,
+    ) -> ErrorResponseData {
+        let ret = ErrorResponseData {
+            error: ErrorDetails {
+                kind: kind.into(),
+                message: message.into(),
+                id: Some(id.into()),
+                fields: Vec::new(),
+            },
+        };
+
+        event!(Level::ERROR, kind=%ret.error.kind, message=%ret.error.message, error_id=ret.error.id.as_deref());
+
+        ret
+    }
+
+    /// Add errors for individual fields.
+    pub fn with_fields(mut self, fields: Vec<FieldError>) -> ErrorResponseData {
+        self.error.fields = fields;
+        self
+    }
 }
@@ -0,0 +1 @@
+DROP TABLE transformation_presets;
@@ -0,0 +1,14 @@
+CREATE TABLE transformation_presets (
+  id uuid primary key,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  project_id uuid references projects(id) DEFERRABLE INITIALLY IMMEDIATE,
+  name text not null,
+  operations jsonb not null,
+  updated timestamptz not null default now(),
+  deleted timestamptz
+);
+
+CREATE INDEX transformation_presets_team_id ON transformation_presets(team_id);
+CREATE UNIQUE INDEX transformation_presets_team_id_project_id_name
+  ON transformation_presets(team_id, coalesce(project_id, uuid_nil()), name)
+  WHERE deleted IS NULL;
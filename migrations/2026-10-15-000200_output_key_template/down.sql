@@ -0,0 +1 @@
+ALTER TABLE conversion_profiles DROP COLUMN output_key_template;
@@ -0,0 +1 @@
+ALTER TABLE conversion_profiles ADD COLUMN output_key_template text;
@@ -0,0 +1,3 @@
+DROP TABLE impersonation_events;
+DROP TABLE impersonations;
+ALTER TABLE users DROP COLUMN instance_admin;
@@ -0,0 +1,24 @@
+ALTER TABLE users ADD COLUMN instance_admin boolean not null default false;
+
+CREATE TABLE impersonations (
+  id uuid primary key,
+  admin_user_id uuid not null references users(id) DEFERRABLE INITIALLY IMMEDIATE,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  reason text not null,
+  created timestamptz not null default now(),
+  expires timestamptz not null,
+  ended timestamptz
+);
+
+CREATE INDEX impersonations_admin_user_id ON impersonations(admin_user_id);
+CREATE INDEX impersonations_team_id ON impersonations(team_id);
+
+CREATE TABLE impersonation_events (
+  id uuid primary key,
+  impersonation_id uuid not null references impersonations(id) DEFERRABLE INITIALLY IMMEDIATE,
+  method text not null,
+  path text not null,
+  created timestamptz not null default now()
+);
+
+CREATE INDEX impersonation_events_impersonation_id ON impersonation_events(impersonation_id);
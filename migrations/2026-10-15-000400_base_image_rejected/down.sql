@@ -0,0 +1,15 @@
+ALTER TABLE base_images DROP COLUMN error;
+
+-- Postgres can't remove a value from an enum, so recreate it.
+UPDATE base_images SET status = 'deleted' WHERE status = 'rejected';
+ALTER TYPE base_image_status RENAME TO base_image_status_old;
+CREATE TYPE base_image_status AS ENUM (
+  'awaiting_upload',
+  'converting',
+  'ready',
+  'queued_for_delete',
+  'deleting',
+  'deleted'
+);
+ALTER TABLE base_images ALTER COLUMN status TYPE base_image_status USING status::text::base_image_status;
+DROP TYPE base_image_status_old;
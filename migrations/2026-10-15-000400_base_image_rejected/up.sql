@@ -0,0 +1,2 @@
+ALTER TYPE base_image_status ADD VALUE 'rejected';
+ALTER TABLE base_images ADD COLUMN error text;
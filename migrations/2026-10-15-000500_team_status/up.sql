@@ -0,0 +1,6 @@
+CREATE TYPE team_status AS ENUM (
+  'active',
+  'suspended'
+);
+
+ALTER TABLE teams ADD COLUMN status team_status not null default 'active';
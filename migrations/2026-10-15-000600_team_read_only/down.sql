@@ -0,0 +1,11 @@
+-- Postgres can't remove a value from an enum, so recreate it.
+ALTER TABLE teams ALTER COLUMN status DROP DEFAULT;
+UPDATE teams SET status = 'suspended' WHERE status = 'read_only';
+ALTER TYPE team_status RENAME TO team_status_old;
+CREATE TYPE team_status AS ENUM (
+  'active',
+  'suspended'
+);
+ALTER TABLE teams ALTER COLUMN status TYPE team_status USING status::text::team_status;
+ALTER TABLE teams ALTER COLUMN status SET DEFAULT 'active';
+DROP TYPE team_status_old;
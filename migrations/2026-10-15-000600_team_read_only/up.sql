@@ -0,0 +1 @@
+ALTER TYPE team_status ADD VALUE 'read_only' AFTER 'active';
@@ -0,0 +1,25 @@
+ALTER TYPE base_image_status ADD VALUE 'taken_down';
+
+CREATE TYPE abuse_report_status AS ENUM (
+  'pending',
+  'dismissed',
+  'taken_down'
+);
+
+CREATE TABLE abuse_reports (
+  id uuid primary key,
+  url text not null,
+  reason text not null,
+  reporter_email text,
+  team_id uuid references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  base_image_id uuid references base_images(id) DEFERRABLE INITIALLY IMMEDIATE,
+  status abuse_report_status not null default 'pending',
+  created timestamptz not null default now(),
+  resolved timestamptz,
+  resolved_by uuid references users(id) DEFERRABLE INITIALLY IMMEDIATE,
+  resolution_note text
+);
+
+CREATE INDEX abuse_reports_status ON abuse_reports(status);
+CREATE INDEX abuse_reports_team_id ON abuse_reports(team_id);
+CREATE INDEX abuse_reports_base_image_id ON abuse_reports(base_image_id);
@@ -0,0 +1,3 @@
+ALTER TABLE conversion_profiles
+  DROP COLUMN extends,
+  DROP COLUMN overrides;
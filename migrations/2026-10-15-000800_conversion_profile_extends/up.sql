@@ -0,0 +1,5 @@
+ALTER TABLE conversion_profiles
+  ADD COLUMN extends uuid references conversion_profiles(id) DEFERRABLE INITIALLY IMMEDIATE,
+  ADD COLUMN overrides jsonb;
+
+CREATE INDEX conversion_profiles_extends ON conversion_profiles(extends);
@@ -0,0 +1,7 @@
+ALTER TABLE base_images
+  DROP COLUMN conversion_profile_id,
+  DROP COLUMN conversion_profile_version;
+
+DROP TABLE conversion_profile_versions;
+
+ALTER TABLE conversion_profiles DROP COLUMN version;
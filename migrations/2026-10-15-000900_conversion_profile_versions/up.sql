@@ -0,0 +1,28 @@
+ALTER TABLE conversion_profiles ADD COLUMN version int not null default 1;
+
+CREATE TABLE conversion_profile_versions (
+  conversion_profile_id uuid not null references conversion_profiles(id) DEFERRABLE INITIALLY IMMEDIATE,
+  version int not null,
+  output jsonb not null,
+  output_key_template text,
+  created timestamptz not null default now(),
+  primary key (conversion_profile_id, version)
+);
+
+INSERT INTO conversion_profile_versions (conversion_profile_id, version, output, output_key_template, created)
+  SELECT id, version, output, output_key_template, updated FROM conversion_profiles;
+
+-- The profile and version used to generate the current output images.
+ALTER TABLE base_images
+  ADD COLUMN conversion_profile_id uuid references conversion_profiles(id) DEFERRABLE INITIALLY IMMEDIATE,
+  ADD COLUMN conversion_profile_version int;
+
+CREATE INDEX base_images_conversion_profile_id ON base_images(conversion_profile_id);
+
+-- Assume that existing images are up to date with their profile.
+UPDATE base_images
+  SET conversion_profile_id = upload_profiles.conversion_profile_id,
+    conversion_profile_version = 1
+  FROM upload_profiles
+  WHERE upload_profiles.id = base_images.upload_profile_id
+    AND base_images.status = 'ready';
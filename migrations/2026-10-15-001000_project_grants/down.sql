@@ -0,0 +1,3 @@
+DROP TABLE project_grant_events;
+DROP TYPE project_grant_event_type;
+DROP TABLE project_grants;
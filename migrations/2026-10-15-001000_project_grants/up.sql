@@ -0,0 +1,32 @@
+CREATE TABLE project_grants (
+  id uuid primary key,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  project_id uuid not null references projects(id) DEFERRABLE INITIALLY IMMEDIATE,
+  grantee_team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  created_by uuid not null references users(id) DEFERRABLE INITIALLY IMMEDIATE,
+  created timestamptz not null default now(),
+  revoked timestamptz,
+  revoked_by uuid references users(id) DEFERRABLE INITIALLY IMMEDIATE
+);
+
+CREATE UNIQUE INDEX project_grants_active ON project_grants(project_id, grantee_team_id)
+  WHERE revoked IS NULL;
+CREATE INDEX project_grants_grantee_team_id ON project_grants(grantee_team_id);
+
+CREATE TYPE project_grant_event_type AS ENUM (
+  'created',
+  'revoked',
+  'accessed'
+);
+
+CREATE TABLE project_grant_events (
+  id uuid primary key,
+  grant_id uuid not null references project_grants(id) DEFERRABLE INITIALLY IMMEDIATE,
+  event_type project_grant_event_type not null,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  user_id uuid not null references users(id) DEFERRABLE INITIALLY IMMEDIATE,
+  base_image_id uuid references base_images(id) DEFERRABLE INITIALLY IMMEDIATE,
+  created timestamptz not null default now()
+);
+
+CREATE INDEX project_grant_events_grant_id ON project_grant_events(grant_id);
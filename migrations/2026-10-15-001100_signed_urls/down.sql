@@ -0,0 +1,3 @@
+ALTER TABLE upload_profiles
+  DROP COLUMN require_signed_urls,
+  DROP COLUMN signed_url_ttl_seconds;
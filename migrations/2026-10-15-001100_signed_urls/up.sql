@@ -0,0 +1,3 @@
+ALTER TABLE upload_profiles
+  ADD COLUMN require_signed_urls boolean not null default false,
+  ADD COLUMN signed_url_ttl_seconds int;
@@ -0,0 +1,2 @@
+DROP TABLE project_access_tokens;
+ALTER TABLE projects DROP COLUMN private;
@@ -0,0 +1,15 @@
+ALTER TABLE projects ADD COLUMN private boolean not null default false;
+
+CREATE TABLE project_access_tokens (
+  id uuid primary key,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  project_id uuid not null references projects(id) DEFERRABLE INITIALLY IMMEDIATE,
+  name text not null,
+  hash bytea not null,
+  created_by uuid not null references users(id) DEFERRABLE INITIALLY IMMEDIATE,
+  created timestamptz not null default now(),
+  expires timestamptz,
+  revoked timestamptz
+);
+
+CREATE INDEX project_access_tokens_project_id ON project_access_tokens(project_id);
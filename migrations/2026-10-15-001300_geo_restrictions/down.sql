@@ -0,0 +1,3 @@
+ALTER TABLE upload_profiles
+  DROP COLUMN allowed_countries,
+  DROP COLUMN blocked_countries;
@@ -0,0 +1,3 @@
+ALTER TABLE upload_profiles
+  ADD COLUMN allowed_countries text[],
+  ADD COLUMN blocked_countries text[];
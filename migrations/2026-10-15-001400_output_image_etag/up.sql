@@ -0,0 +1 @@
+ALTER TABLE output_images ADD COLUMN etag text;
@@ -0,0 +1 @@
+ALTER TABLE upload_profiles DROP COLUMN cache_control;
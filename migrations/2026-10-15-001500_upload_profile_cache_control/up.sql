@@ -0,0 +1 @@
+ALTER TABLE upload_profiles ADD COLUMN cache_control text;
@@ -0,0 +1,2 @@
+ALTER TABLE api_keys
+  ADD COLUMN bound_upload_profile_id uuid references upload_profiles(id) DEFERRABLE INITIALLY IMMEDIATE;
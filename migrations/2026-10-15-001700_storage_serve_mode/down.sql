@@ -0,0 +1,2 @@
+ALTER TABLE storage_locations DROP COLUMN serve_mode;
+DROP TYPE storage_serve_mode;
@@ -0,0 +1,6 @@
+CREATE TYPE storage_serve_mode AS ENUM (
+  'proxy',
+  'redirect'
+);
+
+ALTER TABLE storage_locations ADD COLUMN serve_mode storage_serve_mode not null default 'proxy';
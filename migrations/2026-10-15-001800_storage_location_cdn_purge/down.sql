@@ -0,0 +1 @@
+ALTER TABLE storage_locations DROP COLUMN cdn_purge;
@@ -0,0 +1 @@
+ALTER TABLE storage_locations ADD COLUMN cdn_purge jsonb;
@@ -0,0 +1,9 @@
+CREATE TABLE delivery_domains (
+  hostname text primary key,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  project_id uuid not null references projects(id) DEFERRABLE INITIALLY IMMEDIATE,
+  created_by uuid not null references users(id) DEFERRABLE INITIALLY IMMEDIATE,
+  created timestamptz not null default now()
+);
+
+CREATE INDEX delivery_domains_project_id ON delivery_domains(project_id);
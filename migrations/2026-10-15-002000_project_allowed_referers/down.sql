@@ -0,0 +1,3 @@
+ALTER TABLE projects
+  DROP COLUMN allowed_referers,
+  DROP COLUMN block_empty_referer;
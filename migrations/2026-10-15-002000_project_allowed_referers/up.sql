@@ -0,0 +1,3 @@
+ALTER TABLE projects
+  ADD COLUMN allowed_referers text[],
+  ADD COLUMN block_empty_referer bool not null default false;
@@ -0,0 +1,12 @@
+-- Hourly rollups of API requests for each project and API key.
+CREATE TABLE api_usage (
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  project_id uuid not null references projects(id) DEFERRABLE INITIALLY IMMEDIATE,
+  -- The nil UUID for requests that were authenticated with a session instead of an API key.
+  api_key_id uuid not null,
+  hour timestamptz not null,
+  requests bigint not null default 0,
+  client_errors bigint not null default 0,
+  server_errors bigint not null default 0,
+  primary key (project_id, hour, api_key_id)
+);
@@ -0,0 +1,3 @@
+ALTER TABLE output_images
+  DROP COLUMN conversion_profile_id,
+  DROP COLUMN conversion_profile_version;
@@ -0,0 +1,5 @@
+-- The profile and version that an output image generated on demand was rendered with. Output
+-- images generated from the profile's outputs use the version recorded on the base image.
+ALTER TABLE output_images
+  ADD COLUMN conversion_profile_id uuid references conversion_profiles(id) DEFERRABLE INITIALLY IMMEDIATE,
+  ADD COLUMN conversion_profile_version int;
@@ -0,0 +1,3 @@
+ALTER TABLE upload_profiles
+  DROP COLUMN save_data_enabled,
+  DROP COLUMN save_data_quality;
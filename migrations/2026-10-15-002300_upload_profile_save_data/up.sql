@@ -0,0 +1,3 @@
+ALTER TABLE upload_profiles
+  ADD COLUMN save_data_enabled bool not null default true,
+  ADD COLUMN save_data_quality int;
@@ -0,0 +1 @@
+ALTER TABLE upload_profiles DROP COLUMN format_fallbacks;
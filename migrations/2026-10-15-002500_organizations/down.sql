@@ -0,0 +1,5 @@
+ALTER TABLE storage_locations DROP COLUMN organization_id;
+ALTER TABLE teams DROP COLUMN organization_id;
+DROP TABLE organization_members;
+DROP TYPE organization_role;
+DROP TABLE organizations;
@@ -0,0 +1,35 @@
+CREATE TABLE organizations (
+  id uuid primary key,
+  name text not null,
+  billing_email text,
+  created timestamptz not null default now(),
+  updated timestamptz not null default now(),
+  deleted timestamptz
+);
+
+CREATE TYPE organization_role AS ENUM (
+  'admin',
+  'billing',
+  'member'
+);
+
+CREATE TABLE organization_members (
+  organization_id uuid not null references organizations(id) DEFERRABLE INITIALLY IMMEDIATE,
+  user_id uuid not null references users(id) DEFERRABLE INITIALLY IMMEDIATE,
+  role organization_role not null,
+  added timestamptz not null default now(),
+  primary key (organization_id, user_id)
+);
+
+CREATE INDEX organization_members_user_id ON organization_members(user_id);
+
+ALTER TABLE teams
+  ADD COLUMN organization_id uuid references organizations(id) DEFERRABLE INITIALLY IMMEDIATE;
+
+CREATE INDEX teams_organization_id ON teams(organization_id);
+
+-- Storage locations shared with every team in the organization.
+ALTER TABLE storage_locations
+  ADD COLUMN organization_id uuid references organizations(id) DEFERRABLE INITIALLY IMMEDIATE;
+
+CREATE INDEX storage_locations_organization_id ON storage_locations(organization_id);
@@ -0,0 +1,4 @@
+DROP TABLE label_policies;
+ALTER TABLE storage_locations DROP COLUMN labels;
+ALTER TABLE upload_profiles DROP COLUMN labels;
+ALTER TABLE projects DROP COLUMN labels;
@@ -0,0 +1,15 @@
+ALTER TABLE projects ADD COLUMN labels text[] not null default '{}';
+ALTER TABLE upload_profiles ADD COLUMN labels text[] not null default '{}';
+ALTER TABLE storage_locations ADD COLUMN labels text[] not null default '{}';
+
+-- Rules that apply to images uploaded to projects or upload profiles with the label.
+CREATE TABLE label_policies (
+  id uuid primary key,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  label text not null,
+  rule jsonb not null,
+  updated timestamptz not null default now(),
+  deleted timestamptz
+);
+
+CREATE INDEX label_policies_team_id_label ON label_policies(team_id, label) WHERE deleted IS NULL;
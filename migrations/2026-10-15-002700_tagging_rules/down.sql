@@ -0,0 +1,3 @@
+DROP TABLE tagging_rules;
+ALTER TABLE base_images DROP COLUMN collection;
+ALTER TABLE base_images DROP COLUMN tags;
@@ -0,0 +1,18 @@
+ALTER TABLE base_images ADD COLUMN tags text[] not null default '{}';
+ALTER TABLE base_images ADD COLUMN collection text;
+
+-- Rules that tag images based on their EXIF data when they are uploaded.
+CREATE TABLE tagging_rules (
+  id uuid primary key,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  project_id uuid not null references projects(id) DEFERRABLE INITIALLY IMMEDIATE,
+  name text not null,
+  priority int not null default 0,
+  conditions jsonb not null,
+  tags text[] not null default '{}',
+  collection text,
+  updated timestamptz not null default now(),
+  deleted timestamptz
+);
+
+CREATE INDEX tagging_rules_project_id ON tagging_rules(project_id) WHERE deleted IS NULL;
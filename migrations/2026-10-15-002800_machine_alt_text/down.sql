@@ -0,0 +1 @@
+ALTER TABLE base_images DROP COLUMN alt_text_machine_generated;
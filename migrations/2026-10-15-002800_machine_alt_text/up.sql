@@ -0,0 +1,2 @@
+-- Set when the alt text came from the captioning service and hasn't been reviewed yet.
+ALTER TABLE base_images ADD COLUMN alt_text_machine_generated boolean not null default false;
@@ -0,0 +1,2 @@
+DROP INDEX base_images_search;
+ALTER TABLE base_images DROP COLUMN ocr_text;
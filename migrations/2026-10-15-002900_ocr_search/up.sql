@@ -0,0 +1,7 @@
+-- Text found in the image by OCR.
+ALTER TABLE base_images ADD COLUMN ocr_text text;
+
+-- Image searches must use this same expression to use the index.
+CREATE INDEX base_images_search ON base_images USING gin(
+  to_tsvector('simple', filename || ' ' || alt_text || ' ' || coalesce(ocr_text, ''))
+);
@@ -0,0 +1,2 @@
+DROP INDEX base_images_codes;
+ALTER TABLE base_images DROP COLUMN codes;
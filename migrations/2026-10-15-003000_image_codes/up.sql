@@ -0,0 +1,4 @@
+-- QR codes and barcodes found in the image, like [{"format": "qrcode", "payload": "..."}].
+ALTER TABLE base_images ADD COLUMN codes jsonb not null default '[]';
+
+CREATE INDEX base_images_codes ON base_images USING gin(codes jsonb_path_ops);
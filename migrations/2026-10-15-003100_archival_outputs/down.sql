@@ -0,0 +1 @@
+ALTER TABLE output_images DROP COLUMN archival;
@@ -0,0 +1,2 @@
+-- Archival masters are kept alongside the delivery outputs but never served.
+ALTER TABLE output_images ADD COLUMN archival boolean not null default false;
@@ -0,0 +1,2 @@
+-- Dimension, aspect ratio, and orientation rules for uploaded images.
+ALTER TABLE upload_profiles ADD COLUMN constraints jsonb;
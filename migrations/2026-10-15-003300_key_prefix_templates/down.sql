@@ -0,0 +1,2 @@
+ALTER TABLE base_images DROP COLUMN output_key_prefix;
+ALTER TABLE storage_locations DROP COLUMN key_prefix_template;
@@ -0,0 +1,6 @@
+-- A template for a prefix added to the keys of every object written to the location.
+ALTER TABLE storage_locations ADD COLUMN key_prefix_template text;
+
+-- The rendered key prefix of the output storage location, which is chosen when the image is
+-- created so that its outputs stay in the same place when they are regenerated.
+ALTER TABLE base_images ADD COLUMN output_key_prefix text;
@@ -0,0 +1,4 @@
+ALTER TABLE base_images DROP COLUMN original_archive_location_id;
+ALTER TABLE base_images DROP COLUMN original_removed;
+ALTER TABLE base_images DROP COLUMN converted;
+ALTER TABLE upload_profiles DROP COLUMN original_retention;
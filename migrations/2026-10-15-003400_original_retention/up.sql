@@ -0,0 +1,12 @@
+-- When to delete or archive the originals of an upload profile's images.
+ALTER TABLE upload_profiles ADD COLUMN original_retention jsonb;
+
+-- When the image's output images were last generated.
+ALTER TABLE base_images ADD COLUMN converted timestamptz;
+UPDATE base_images SET converted = updated WHERE status = 'ready';
+
+-- Set when the original was removed by the retention rules. The original can no longer be
+-- downloaded or used to create new output images.
+ALTER TABLE base_images ADD COLUMN original_removed timestamptz;
+-- The storage location that the original was archived to, if it was archived instead of deleted.
+ALTER TABLE base_images ADD COLUMN original_archive_location_id uuid REFERENCES storage_locations (id);
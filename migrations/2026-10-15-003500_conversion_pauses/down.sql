@@ -0,0 +1,2 @@
+DROP TABLE held_conversions;
+DROP TABLE conversion_pauses;
@@ -0,0 +1,18 @@
+-- Conversions that have been paused by an instance admin, for every team or for one team.
+CREATE TABLE conversion_pauses (
+  -- The nil UUID for a pause that applies to every team.
+  team_id uuid primary key,
+  reason text,
+  created timestamptz not null default now()
+);
+
+-- Conversion jobs that were held back while conversions were paused. They are queued again when
+-- the pause is lifted.
+CREATE TABLE held_conversions (
+  base_image_id uuid primary key references base_images(id) DEFERRABLE INITIALLY IMMEDIATE,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  payload jsonb not null,
+  created timestamptz not null default now()
+);
+
+CREATE INDEX held_conversions_team_id ON held_conversions(team_id);
@@ -0,0 +1,3 @@
+DROP TABLE bulk_deletions;
+DROP TYPE bulk_deletion_status;
+ALTER TABLE base_images DROP COLUMN created;
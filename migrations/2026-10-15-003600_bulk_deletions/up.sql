@@ -0,0 +1,35 @@
+-- When the image was created, so that images can be selected by date.
+ALTER TABLE base_images ADD COLUMN created timestamptz not null default now();
+UPDATE base_images SET created = updated;
+
+CREATE TYPE bulk_deletion_status AS ENUM (
+  'previewed',
+  'running',
+  'complete',
+  'failed'
+);
+
+-- Deletions of every image in a project that matches a filter. A deletion is created by the
+-- preview, and only runs once it is confirmed with the token returned by the preview.
+CREATE TABLE bulk_deletions (
+  id uuid primary key,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  project_id uuid not null references projects(id) DEFERRABLE INITIALLY IMMEDIATE,
+  user_id uuid not null references users(id) DEFERRABLE INITIALLY IMMEDIATE,
+  filter jsonb not null,
+  status bulk_deletion_status not null default 'previewed',
+  -- The hash of the confirmation token.
+  confirmation_hash bytea not null,
+  -- The confirmation token can't be used after this time.
+  expires timestamptz not null,
+  -- The number of images that matched the filter when it was previewed.
+  matched bigint not null,
+  deleted bigint not null default 0,
+  failed bigint not null default 0,
+  error text,
+  created timestamptz not null default now(),
+  started timestamptz,
+  finished timestamptz
+);
+
+CREATE INDEX bulk_deletions_project_id ON bulk_deletions(project_id);
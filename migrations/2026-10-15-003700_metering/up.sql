@@ -0,0 +1,17 @@
+-- Daily metering of the bytes stored, uploaded, and served for each project, for billing and
+-- for spotting heavy tenants.
+CREATE TABLE metering (
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  project_id uuid not null references projects(id) DEFERRABLE INITIALLY IMMEDIATE,
+  day date not null,
+  bytes_uploaded bigint not null default 0,
+  bytes_served bigint not null default 0,
+  images_served bigint not null default 0,
+  -- The size of the project's originals and output images at the last snapshot of the day. Null
+  -- until a snapshot has been taken.
+  bytes_stored bigint,
+  objects_stored bigint,
+  primary key (project_id, day)
+);
+
+CREATE INDEX metering_team_id_day ON metering(team_id, day);
@@ -0,0 +1,3 @@
+-- Pinned images are skipped by bulk deletions, bulk re-renders, and the original retention
+-- rules until they are unpinned.
+ALTER TABLE base_images ADD COLUMN pinned boolean not null default false;
@@ -0,0 +1,2 @@
+ALTER TABLE output_images DROP COLUMN sha256;
+ALTER TABLE base_images DROP COLUMN sha256;
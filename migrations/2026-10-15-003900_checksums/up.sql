@@ -0,0 +1,4 @@
+-- SHA-256 checksums of the stored files, verified after each write, so that other systems can
+-- check the files they download. Files stored before this are null.
+ALTER TABLE base_images ADD COLUMN sha256 text;
+ALTER TABLE output_images ADD COLUMN sha256 text;
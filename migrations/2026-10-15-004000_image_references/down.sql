@@ -0,0 +1,4 @@
+DROP TABLE reference_crawls;
+DROP TYPE reference_crawl_status;
+DROP TABLE image_references;
+DROP TYPE image_reference_source;
@@ -0,0 +1,43 @@
+CREATE TYPE image_reference_source AS ENUM (
+  'crawl',
+  'import'
+);
+
+-- The site pages that use each image, so that users can see what will break before they
+-- delete an image. References come from sitemap crawls or from uploaded lists.
+CREATE TABLE image_references (
+  base_image_id uuid not null references base_images(id) DEFERRABLE INITIALLY IMMEDIATE,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  project_id uuid not null references projects(id) DEFERRABLE INITIALLY IMMEDIATE,
+  page_url text not null,
+  source image_reference_source not null,
+  first_seen timestamptz not null default now(),
+  last_seen timestamptz not null default now(),
+  primary key (base_image_id, page_url)
+);
+
+CREATE INDEX image_references_project_id ON image_references(project_id);
+
+CREATE TYPE reference_crawl_status AS ENUM (
+  'running',
+  'complete',
+  'failed'
+);
+
+-- Crawls of a site's sitemap to find the pages that use the project's images.
+CREATE TABLE reference_crawls (
+  id uuid primary key,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  project_id uuid not null references projects(id) DEFERRABLE INITIALLY IMMEDIATE,
+  user_id uuid not null references users(id) DEFERRABLE INITIALLY IMMEDIATE,
+  sitemap_url text not null,
+  max_pages int not null,
+  status reference_crawl_status not null default 'running',
+  pages_scanned int not null default 0,
+  references_found int not null default 0,
+  error text,
+  created timestamptz not null default now(),
+  finished timestamptz
+);
+
+CREATE INDEX reference_crawls_project_id ON reference_crawls(project_id);
@@ -0,0 +1,16 @@
+-- The results of requesting a sample of each project's delivery URLs through their public path,
+-- to catch misconfigured buckets or CDN rules before users notice broken images.
+CREATE TABLE link_checks (
+  id uuid primary key,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  project_id uuid not null references projects(id) DEFERRABLE INITIALLY IMMEDIATE,
+  base_image_id uuid not null references base_images(id) DEFERRABLE INITIALLY IMMEDIATE,
+  url text not null,
+  ok boolean not null,
+  -- Null when the request didn't get a response.
+  status_code int,
+  error text,
+  checked timestamptz not null default now()
+);
+
+CREATE INDEX link_checks_project_id_checked ON link_checks(project_id, checked);
@@ -0,0 +1,3 @@
+DROP TABLE output_image_mirrors;
+DROP TYPE output_mirror_status;
+ALTER TABLE upload_profiles DROP COLUMN output_mirror_location_ids;
@@ -0,0 +1,20 @@
+-- Extra storage locations that the conversion worker copies each output image to, in the
+-- order that delivery tries them when the primary output location fails.
+ALTER TABLE upload_profiles ADD COLUMN output_mirror_location_ids uuid[] not null default '{}';
+
+CREATE TYPE output_mirror_status AS ENUM (
+  'ready',
+  'failed'
+);
+
+-- Whether each output image was copied to each of its upload profile's mirrors.
+CREATE TABLE output_image_mirrors (
+  output_image_id uuid not null references output_images(id) DEFERRABLE INITIALLY IMMEDIATE,
+  storage_location_id uuid not null references storage_locations(id) DEFERRABLE INITIALLY IMMEDIATE,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  status output_mirror_status not null,
+  sha256 text,
+  error text,
+  updated timestamptz not null default now(),
+  primary key (output_image_id, storage_location_id)
+);
@@ -0,0 +1,25 @@
+-- Uploads of an original in separately uploaded chunks, for clients that can't stream the
+-- whole file in one request. The chunks are stored next to the original until the upload is
+-- completed, and sessions that are never completed are removed once they expire.
+CREATE TABLE upload_sessions (
+  id uuid primary key,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  base_image_id uuid not null references base_images(id) DEFERRABLE INITIALLY IMMEDIATE,
+  total_size bigint not null,
+  chunk_size int not null,
+  chunk_count int not null,
+  created timestamptz not null default now(),
+  expires timestamptz not null
+);
+
+CREATE INDEX upload_sessions_base_image_id ON upload_sessions (base_image_id);
+CREATE INDEX upload_sessions_expires ON upload_sessions (expires);
+
+CREATE TABLE upload_chunks (
+  upload_session_id uuid not null references upload_sessions(id) DEFERRABLE INITIALLY IMMEDIATE,
+  chunk_index int not null,
+  size int not null,
+  sha256 text not null,
+  uploaded timestamptz not null default now(),
+  primary key (upload_session_id, chunk_index)
+);
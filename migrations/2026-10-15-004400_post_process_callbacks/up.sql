@@ -0,0 +1,3 @@
+-- A service that is called after an image's outputs are generated, which can add metadata to
+-- the image or stop it from being published.
+ALTER TABLE upload_profiles ADD COLUMN post_process_callback jsonb;
@@ -0,0 +1,18 @@
+-- Postgres can't remove a value from an enum, so recreate it.
+DELETE FROM role_permissions WHERE permission::text IN ('upload_profile:read', 'upload_profile:write');
+DELETE FROM api_key_permissions WHERE permission::text IN ('upload_profile:read', 'upload_profile:write');
+ALTER TYPE permission RENAME TO permission_old;
+CREATE TYPE permission AS ENUM (
+  'team:admin',
+  'team:write',
+  'project:create',
+  'project:write',
+  'project:read',
+  'image:edit',
+  'image:create',
+  'conversion_profile:write',
+  'storage_location:write'
+);
+ALTER TABLE role_permissions ALTER COLUMN permission TYPE permission USING permission::text::permission;
+ALTER TABLE api_key_permissions ALTER COLUMN permission TYPE permission USING permission::text::permission;
+DROP TYPE permission_old;
@@ -0,0 +1,3 @@
+-- Upload profiles had been covered by project:read and project:write.
+ALTER TYPE permission ADD VALUE 'upload_profile:read';
+ALTER TYPE permission ADD VALUE 'upload_profile:write';
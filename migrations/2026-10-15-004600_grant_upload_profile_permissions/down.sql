@@ -0,0 +1,2 @@
+DELETE FROM role_permissions WHERE permission IN ('upload_profile:read', 'upload_profile:write');
+DELETE FROM api_key_permissions WHERE permission IN ('upload_profile:read', 'upload_profile:write');
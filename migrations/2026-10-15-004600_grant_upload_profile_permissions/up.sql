@@ -0,0 +1,25 @@
+-- Keep existing roles and keys able to do what they could before the upload profile
+-- permissions were split out of the project permissions.
+INSERT INTO role_permissions (team_id, role_id, project_id, permission)
+SELECT team_id, role_id, project_id, 'upload_profile:read'
+FROM role_permissions
+WHERE permission = 'project:read'
+ON CONFLICT DO NOTHING;
+
+INSERT INTO role_permissions (team_id, role_id, project_id, permission)
+SELECT team_id, role_id, project_id, 'upload_profile:write'
+FROM role_permissions
+WHERE permission = 'project:write'
+ON CONFLICT DO NOTHING;
+
+INSERT INTO api_key_permissions (team_id, api_key_id, project_id, permission)
+SELECT team_id, api_key_id, project_id, 'upload_profile:read'
+FROM api_key_permissions
+WHERE permission = 'project:read'
+ON CONFLICT DO NOTHING;
+
+INSERT INTO api_key_permissions (team_id, api_key_id, project_id, permission)
+SELECT team_id, api_key_id, project_id, 'upload_profile:write'
+FROM api_key_permissions
+WHERE permission = 'project:write'
+ON CONFLICT DO NOTHING;
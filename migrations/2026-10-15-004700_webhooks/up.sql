@@ -0,0 +1,41 @@
+-- Endpoints that receive signed notifications of a team's events.
+CREATE TABLE webhooks (
+  id uuid primary key,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  url text not null,
+  -- The signing secret, encrypted like storage location credentials.
+  secret text not null,
+  description text,
+  updated timestamptz not null default now(),
+  deleted timestamptz
+);
+
+CREATE INDEX webhooks_team_id ON webhooks(team_id) WHERE deleted IS NULL;
+
+CREATE TYPE webhook_delivery_status AS ENUM (
+  'pending',
+  'delivered',
+  'failed'
+);
+
+-- Each event sent to each webhook. Deliveries are added in the same transaction as the change
+-- that caused the event, and sent by a background job.
+CREATE TABLE webhook_deliveries (
+  id uuid primary key,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  webhook_id uuid not null references webhooks(id) DEFERRABLE INITIALLY IMMEDIATE,
+  project_id uuid references projects(id) DEFERRABLE INITIALLY IMMEDIATE,
+  event_type text not null,
+  payload jsonb not null,
+  status webhook_delivery_status not null default 'pending',
+  attempts int not null default 0,
+  next_attempt timestamptz not null default now(),
+  response_status int,
+  error text,
+  created timestamptz not null default now(),
+  delivered timestamptz
+);
+
+CREATE INDEX webhook_deliveries_pending ON webhook_deliveries(next_attempt) WHERE status = 'pending';
+CREATE INDEX webhook_deliveries_webhook_id ON webhook_deliveries(webhook_id, created);
+CREATE INDEX webhook_deliveries_created ON webhook_deliveries(created);
@@ -0,0 +1,3 @@
+ALTER TABLE webhooks DROP COLUMN payload_template;
+ALTER TABLE webhooks DROP COLUMN project_ids;
+ALTER TABLE webhooks DROP COLUMN event_types;
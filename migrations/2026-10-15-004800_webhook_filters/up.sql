@@ -0,0 +1,6 @@
+-- The event types and projects that a webhook receives. Empty lists mean every event type or
+-- every project.
+ALTER TABLE webhooks ADD COLUMN event_types text[] not null default '{}';
+ALTER TABLE webhooks ADD COLUMN project_ids uuid[] not null default '{}';
+-- A liquid template that renders the body sent to the webhook, instead of the standard event.
+ALTER TABLE webhooks ADD COLUMN payload_template text;
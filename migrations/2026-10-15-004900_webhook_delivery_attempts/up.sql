@@ -0,0 +1,9 @@
+-- Each attempt to send a webhook delivery, for debugging a receiver's failures.
+CREATE TABLE webhook_delivery_attempts (
+  delivery_id uuid not null references webhook_deliveries(id) on delete cascade DEFERRABLE INITIALLY IMMEDIATE,
+  attempt int not null,
+  response_status int,
+  error text,
+  created timestamptz not null default now(),
+  primary key (delivery_id, attempt)
+);
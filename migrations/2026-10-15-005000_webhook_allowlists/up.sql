@@ -0,0 +1,9 @@
+-- The hosts that webhooks may send to, set by instance admins for one team or for every team. A
+-- team's own list replaces the global list, and teams without either can send anywhere.
+CREATE TABLE webhook_allowlists (
+  -- The nil UUID for the list that applies to every team.
+  team_id uuid primary key,
+  -- Host names, or patterns like `*.example.com` that match any subdomain.
+  hosts text[] not null,
+  updated timestamptz not null default now()
+);
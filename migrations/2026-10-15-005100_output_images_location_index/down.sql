@@ -0,0 +1 @@
+DROP INDEX output_images_location;
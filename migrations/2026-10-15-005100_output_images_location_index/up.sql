@@ -0,0 +1,2 @@
+-- For matching abuse reports to the output image at the reported URL.
+CREATE INDEX output_images_location ON output_images(location) WHERE deleted IS NULL;
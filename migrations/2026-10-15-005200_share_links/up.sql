@@ -0,0 +1,18 @@
+-- Links that show a collection's images on a gallery page to anyone holding the link, until it
+-- expires or is revoked.
+CREATE TABLE share_links (
+  id uuid primary key,
+  team_id uuid not null references teams(id) DEFERRABLE INITIALLY IMMEDIATE,
+  project_id uuid not null references projects(id) DEFERRABLE INITIALLY IMMEDIATE,
+  collection text not null,
+  title text,
+  hash bytea not null,
+  -- When set, viewers must enter the password before the gallery is shown.
+  password_hash text,
+  created_by uuid not null references users(id) DEFERRABLE INITIALLY IMMEDIATE,
+  created timestamptz not null default now(),
+  expires timestamptz not null,
+  revoked timestamptz
+);
+
+CREATE INDEX share_links_project_id ON share_links(project_id);
@@ -0,0 +1,6 @@
+DROP INDEX project_grant_events_accessed;
+DROP INDEX project_grants_active;
+UPDATE project_grants SET revoked = now() WHERE collection IS NOT NULL AND revoked IS NULL;
+CREATE UNIQUE INDEX project_grants_active ON project_grants(project_id, grantee_team_id)
+  WHERE revoked IS NULL;
+ALTER TABLE project_grants DROP COLUMN collection;
@@ -0,0 +1,12 @@
+-- When set, a grant only gives access to the images in this collection of the project.
+ALTER TABLE project_grants ADD COLUMN collection text;
+
+DROP INDEX project_grants_active;
+CREATE UNIQUE INDEX project_grants_active
+  ON project_grants(project_id, grantee_team_id, coalesce(collection, ''))
+  WHERE revoked IS NULL;
+
+-- For checking whether an image read through a grant was recorded recently.
+CREATE INDEX project_grant_events_accessed
+  ON project_grant_events(grant_id, user_id, base_image_id, created)
+  WHERE event_type = 'accessed';
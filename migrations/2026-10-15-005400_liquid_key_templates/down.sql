@@ -0,0 +1,7 @@
+UPDATE conversion_profiles
+  SET output_key_template = regexp_replace(output_key_template, '\{\{\s*(\w+)\s*\}\}', '{\1}', 'g')
+  WHERE output_key_template IS NOT NULL;
+
+UPDATE conversion_profile_versions
+  SET output_key_template = regexp_replace(output_key_template, '\{\{\s*(\w+)\s*\}\}', '{\1}', 'g')
+  WHERE output_key_template IS NOT NULL;
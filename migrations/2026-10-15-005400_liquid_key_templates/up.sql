@@ -0,0 +1,8 @@
+-- Output key templates are liquid templates now, with variables in double braces.
+UPDATE conversion_profiles
+  SET output_key_template = regexp_replace(output_key_template, '\{\s*(\w+)\s*\}', '{{ \1 }}', 'g')
+  WHERE output_key_template IS NOT NULL;
+
+UPDATE conversion_profile_versions
+  SET output_key_template = regexp_replace(output_key_template, '\{\s*(\w+)\s*\}', '{{ \1 }}', 'g')
+  WHERE output_key_template IS NOT NULL;
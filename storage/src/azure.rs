@@ -0,0 +1,81 @@
+use object_store::azure::{MicrosoftAzure, MicrosoftAzureBuilder};
+use pic_store_db::storage_locations::connection_string_value;
+
+#[derive(Clone)]
+pub struct AzureProviderConfig {
+    pub account: Option<String>,
+    /// A connection string with an account key. Shared access signatures are not supported.
+    pub connection_string: Option<String>,
+    /// The client ID of a user-assigned managed identity.
+    pub client_id: Option<String>,
+}
+
+impl std::fmt::Debug for AzureProviderConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AzureProviderConfig")
+            .field("account", &self.account)
+            .field("has_connection_string", &self.connection_string.is_some())
+            .field("client_id", &self.client_id)
+            .finish()
+    }
+}
+
+fn builder_from_connection_string(
+    connection_string: &str,
+) -> Result<MicrosoftAzureBuilder, eyre::Report> {
+    let value = |key| connection_string_value(connection_string, key);
+
+    if value("UseDevelopmentStorage") == Some("true") {
+        return Ok(MicrosoftAzureBuilder::new().with_use_emulator(true));
+    }
+
+    if value("BlobEndpoint").is_some() {
+        return Err(eyre::eyre!(
+            "Custom blob endpoints in connection strings are not supported"
+        ));
+    }
+
+    let key = value("AccountKey")
+        .ok_or_else(|| eyre::eyre!("The connection string must contain an AccountKey"))?;
+    let mut builder = MicrosoftAzureBuilder::new().with_access_key(key);
+    if let Some(account) = value("AccountName") {
+        builder = builder.with_account(account);
+    }
+
+    Ok(builder)
+}
+
+pub(crate) fn create_store<'a>(
+    config: &AzureProviderConfig,
+    base_location: &'a str,
+) -> Result<(MicrosoftAzure, &'a str), eyre::Report> {
+    if base_location.is_empty() {
+        return Err(eyre::eyre!("base_location is required"));
+    }
+
+    let (container, base_path) = match base_location.find('/') {
+        Some(slash_pos) => base_location.split_at(slash_pos),
+        None => (base_location, ""),
+    };
+
+    let mut builder = match config.connection_string.as_deref() {
+        Some(connection_string) => builder_from_connection_string(connection_string)?,
+        // Without a connection string, object_store falls back to the managed identity from
+        // the instance metadata service.
+        None => {
+            let builder = MicrosoftAzureBuilder::new();
+            match config.client_id.as_deref() {
+                Some(client_id) => builder.with_client_id(client_id),
+                None => builder,
+            }
+        }
+    };
+
+    if let Some(account) = config.account.as_deref() {
+        builder = builder.with_account(account);
+    }
+
+    let store = builder.with_container_name(container).build()?;
+
+    Ok((store, base_path))
+}
@@ -20,9 +20,37 @@ pub enum Error {
     #[error("URI must have a path")]
     UriMissingPath,
 
+    #[error("Invalid B2 region {0}")]
+    InvalidB2Region(String),
+
+    #[error(transparent)]
+    Credentials(#[from] pic_store_db::credentials::CredentialError),
+
     #[error("Missing field {0}")]
     MissingField(&'static str),
 
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
     #[error("Operator error {0}")]
     OperatorError(#[from] object_store::Error),
+
+    #[error("Read back different contents than were written")]
+    ContentMismatch,
+
+    #[error("Read {actual} bytes from range {range:?}")]
+    ShortRead {
+        range: std::ops::Range<usize>,
+        actual: usize,
+    },
+}
+
+impl Error {
+    /// Whether the file does not exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            Error::OperatorError(object_store::Error::NotFound { .. })
+        )
+    }
 }
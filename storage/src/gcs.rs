@@ -0,0 +1,43 @@
+use object_store::gcp::{GoogleCloudStorage, GoogleCloudStorageBuilder};
+
+#[derive(Clone)]
+pub struct GcsProviderConfig {
+    /// A service account key, in JSON format.
+    pub service_account_key: Option<String>,
+}
+
+impl std::fmt::Debug for GcsProviderConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GcsProviderConfig")
+            .field(
+                "has_service_account_key",
+                &self.service_account_key.is_some(),
+            )
+            .finish()
+    }
+}
+
+pub(crate) fn create_store<'a>(
+    config: &GcsProviderConfig,
+    base_location: &'a str,
+) -> Result<(GoogleCloudStorage, &'a str), eyre::Report> {
+    if base_location.is_empty() {
+        return Err(eyre::eyre!("base_location is required"));
+    }
+
+    let (bucket, base_path) = match base_location.find('/') {
+        Some(slash_pos) => base_location.split_at(slash_pos),
+        None => (base_location, ""),
+    };
+
+    let builder = match config.service_account_key.as_ref() {
+        Some(key) => GoogleCloudStorageBuilder::new().with_service_account_key(key.as_str()),
+        // Without a key, use the credentials that the environment provides for the server, such
+        // as a workload identity or the GOOGLE_SERVICE_ACCOUNT variables.
+        None => GoogleCloudStorageBuilder::from_env(),
+    };
+
+    let store = builder.with_bucket_name(bucket).build()?;
+
+    Ok((store, base_path))
+}
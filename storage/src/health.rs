@@ -0,0 +1,93 @@
+use std::time::Instant;
+
+use bytes::Bytes;
+use serde::Serialize;
+
+use crate::{Error, Operator, Result};
+
+/// The contents written by the health check.
+const PROBE_CONTENTS: &[u8] = b"pic-store";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthCheckStage {
+    Write,
+    Read,
+    Delete,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthCheckStep {
+    pub stage: HealthCheckStage,
+    pub latency_ms: u64,
+    /// Why the step failed, if it did.
+    pub error: Option<String>,
+}
+
+/// The results of writing, reading, and deleting a small file in a storage location.
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthCheck {
+    pub ok: bool,
+    pub steps: Vec<HealthCheckStep>,
+}
+
+impl HealthCheck {
+    /// A description of the first step that failed.
+    pub fn error(&self) -> Option<String> {
+        self.steps.iter().find_map(|step| {
+            step.error.as_ref().map(|error| {
+                let stage = match step.stage {
+                    HealthCheckStage::Write => "write",
+                    HealthCheckStage::Read => "read",
+                    HealthCheckStage::Delete => "delete",
+                };
+                format!("{stage} failed: {error}")
+            })
+        })
+    }
+}
+
+impl Operator {
+    /// Write, read, and delete a small file at `location` to make sure that the storage is
+    /// usable. The file is still deleted when reading it back fails.
+    pub async fn health_check(&self, location: &str) -> HealthCheck {
+        let contents = Bytes::from_static(PROBE_CONTENTS);
+        let mut steps = Vec::with_capacity(3);
+
+        let start = Instant::now();
+        let error = self.put(location, contents.clone()).await.err();
+        let write_failed = error.is_some();
+        steps.push(step(HealthCheckStage::Write, start, error));
+
+        if !write_failed {
+            let start = Instant::now();
+            let error = match self.read_all(location).await {
+                Ok(read) if read != contents => Some(Error::ContentMismatch),
+                Ok(_) => None,
+                Err(e) => Some(e),
+            };
+            steps.push(step(HealthCheckStage::Read, start, error));
+
+            let start = Instant::now();
+            let error = self.delete(location).await.err();
+            steps.push(step(HealthCheckStage::Delete, start, error));
+        }
+
+        HealthCheck {
+            ok: steps.iter().all(|step| step.error.is_none()),
+            steps,
+        }
+    }
+
+    async fn read_all(&self, location: &str) -> Result<Bytes> {
+        self.get(location).await?.bytes().await.map_err(Error::from)
+    }
+}
+
+fn step(stage: HealthCheckStage, start: Instant, error: Option<Error>) -> HealthCheckStep {
+    HealthCheckStep {
+        stage,
+        latency_ms: start.elapsed().as_millis() as u64,
+        error: error.map(|e| e.to_string()),
+    }
+}
@@ -1,8 +1,17 @@
+mod azure;
 mod error;
+mod gcs;
+mod health;
 mod operator;
 mod provider;
 mod s3;
+mod sftp;
+mod upload;
+mod webdav;
 
 pub use error::*;
+pub use health::*;
+pub use object_store::{GetResult, ObjectMeta};
 pub use operator::*;
 pub use provider::*;
+pub use upload::*;
@@ -1,15 +1,51 @@
-use bytes::Bytes;
-use object_store::{path::Path, GetResult, MultipartId, ObjectStore};
+use std::{ops::Range, sync::Arc};
+
+use bytes::{Bytes, BytesMut};
+use futures::{StreamExt, TryStreamExt};
+use object_store::{
+    path::Path, signer::Signer, GetOptions, GetResult, MultipartId, ObjectMeta, ObjectStore,
+};
 use tokio::io::AsyncWrite;
 use tracing::instrument;
 
-use crate::error::{Error, Result};
+use crate::{
+    error::{Error, Result},
+    upload::Upload,
+};
+
+/// How to split up a download into concurrent range requests.
+#[derive(Debug, Clone, Copy)]
+pub struct ParallelGet {
+    /// The size of each range request. Files no larger than this are read in one request.
+    pub part_size: usize,
+    /// The maximum number of range requests to run at once.
+    pub concurrency: usize,
+}
+
+impl Default for ParallelGet {
+    fn default() -> Self {
+        Self {
+            part_size: 8 * 1024 * 1024,
+            concurrency: 8,
+        }
+    }
+}
+
+/// Split a file into ranges of at most `part_size` bytes.
+fn part_ranges(size: usize, part_size: usize) -> impl Iterator<Item = Range<usize>> {
+    let part_size = part_size.max(1);
+    (0..size)
+        .step_by(part_size)
+        .map(move |start| start..(start + part_size).min(size))
+}
 
 pub struct Operator {
-    pub operator: Box<dyn ObjectStore>,
+    pub operator: Arc<dyn ObjectStore>,
     pub base_location: String,
     pub supports_multipart: bool,
     pub path_prefix: Option<Path>,
+    /// Creates presigned URLs, for the providers that support them.
+    pub signer: Option<Arc<dyn Signer>>,
 }
 
 impl Operator {
@@ -26,6 +62,99 @@ impl Operator {
         self.operator.get(&p).await.map_err(Error::from)
     }
 
+    /// Read part of a file.
+    #[instrument(skip(self), fields(base=%self.base_location, path_prefix=?self.path_prefix))]
+    pub async fn get_range(&self, location: &str, range: Range<usize>) -> Result<Bytes> {
+        let p = self.make_full_path(location);
+        self.operator
+            .get_range(&p, range)
+            .await
+            .map_err(Error::from)
+    }
+
+    /// Read part of a file as a stream, so that a large range doesn't have to fit in memory.
+    #[instrument(skip(self), fields(base=%self.base_location, path_prefix=?self.path_prefix))]
+    pub async fn get_range_stream(&self, location: &str, range: Range<usize>) -> Result<GetResult> {
+        let p = self.make_full_path(location);
+        let options = GetOptions {
+            range: Some(range),
+            ..Default::default()
+        };
+        self.operator
+            .get_opts(&p, options)
+            .await
+            .map_err(Error::from)
+    }
+
+    /// Read a whole file. Large files are downloaded as concurrent range requests, which is
+    /// much faster than a single request when the storage has high latency.
+    #[instrument(skip(self), fields(base=%self.base_location, path_prefix=?self.path_prefix))]
+    pub async fn get_parallel(&self, location: &str, settings: &ParallelGet) -> Result<Bytes> {
+        let size = self.head(location).await?.size;
+        if size <= settings.part_size {
+            let result = self.get(location).await?;
+            return result.bytes().await.map_err(Error::from);
+        }
+
+        let parts = futures::stream::iter(part_ranges(size, settings.part_size))
+            .map(|range| async move {
+                let bytes = self.get_range(location, range.clone()).await?;
+                if bytes.len() != range.len() {
+                    return Err(Error::ShortRead {
+                        range,
+                        actual: bytes.len(),
+                    });
+                }
+                Ok(bytes)
+            })
+            .buffered(settings.concurrency.max(1))
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let mut output = BytesMut::with_capacity(size);
+        for part in parts {
+            output.extend_from_slice(&part);
+        }
+
+        Ok(output.freeze())
+    }
+
+    #[instrument(skip(self), fields(base=%self.base_location, path_prefix=?self.path_prefix))]
+    pub async fn head(&self, location: &str) -> Result<ObjectMeta> {
+        let p = self.make_full_path(location);
+        self.operator.head(&p).await.map_err(Error::from)
+    }
+
+    /// List every file under the base location. The returned locations are relative to the base
+    /// location, like the locations that the other methods take.
+    #[instrument(skip(self), fields(base=%self.base_location, path_prefix=?self.path_prefix))]
+    pub async fn list(&self) -> Result<Vec<(String, ObjectMeta)>> {
+        let prefix = self.path_prefix.as_ref();
+        let objects = self
+            .operator
+            .list(prefix)
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let objects = objects
+            .into_iter()
+            .filter_map(|meta| {
+                let location = match prefix {
+                    Some(prefix) => meta
+                        .location
+                        .prefix_match(prefix)?
+                        .map(|part| part.as_ref().to_string())
+                        .collect::<Vec<_>>()
+                        .join("/"),
+                    None => meta.location.to_string(),
+                };
+                Some((location, meta))
+            })
+            .collect();
+
+        Ok(objects)
+    }
+
     #[instrument(skip(self, bytes), fields(base=%self.base_location, path_prefix=?self.path_prefix))]
     pub async fn put(&self, location: &str, bytes: Bytes) -> Result<()> {
         let p = self.make_full_path(location);
@@ -33,6 +162,26 @@ impl Operator {
         Ok(())
     }
 
+    /// Create a URL that a client can PUT a file to directly, without sending it through the
+    /// server. Returns `None` if the provider doesn't support presigned URLs.
+    #[instrument(skip(self), fields(base=%self.base_location, path_prefix=?self.path_prefix))]
+    pub async fn presigned_put(
+        &self,
+        location: &str,
+        expires_in: std::time::Duration,
+    ) -> Result<Option<String>> {
+        let Some(signer) = self.signer.as_ref() else {
+            return Ok(None);
+        };
+
+        let p = self.make_full_path(location);
+        let url = signer
+            .signed_url(http::Method::PUT, &p, expires_in)
+            .await
+            .map_err(Error::from)?;
+        Ok(Some(url.to_string()))
+    }
+
     #[instrument(skip(self), fields(base=%self.base_location, path_prefix=?self.path_prefix))]
     pub async fn put_multipart(
         &self,
@@ -42,6 +191,24 @@ impl Operator {
         self.operator.put_multipart(&p).await.map_err(Error::from)
     }
 
+    /// Start a streaming upload, which is sent in parts of about `part_size` bytes when the store
+    /// supports multipart uploads.
+    #[instrument(skip(self), fields(base=%self.base_location, path_prefix=?self.path_prefix))]
+    pub async fn start_upload(&self, location: &str, part_size: usize) -> Result<Upload> {
+        let p = self.make_full_path(location);
+        Upload::new(self.operator.clone(), p, self.supports_multipart, part_size).await
+    }
+
+    /// Delete a file. Deleting a file that does not exist is not an error.
+    #[instrument(skip(self), fields(base=%self.base_location, path_prefix=?self.path_prefix))]
+    pub async fn delete(&self, location: &str) -> Result<()> {
+        let p = self.make_full_path(location);
+        match self.operator.delete(&p).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
     #[instrument(skip(self))]
     pub async fn abort_multipart(&self, location: &str, id: &MultipartId) -> Result<()> {
         let p = self.make_full_path(location);
@@ -1,13 +1,20 @@
 use std::sync::Arc;
 
-use object_store::{local::LocalFileSystem, ObjectStore};
+use object_store::{local::LocalFileSystem, signer::Signer, ObjectStore};
 use pic_store_db as db;
 
-use crate::{error::Error, s3::S3ProviderConfig, Operator};
+use crate::{
+    azure::AzureProviderConfig, error::Error, gcs::GcsProviderConfig, s3::S3ProviderConfig,
+    sftp::SftpProviderConfig, webdav::WebDavProviderConfig, Operator,
+};
 
 #[derive(Debug, Clone)]
 pub enum ProviderConfig {
     S3(S3ProviderConfig),
+    Gcs(GcsProviderConfig),
+    Azure(AzureProviderConfig),
+    WebDav(WebDavProviderConfig),
+    Sftp(SftpProviderConfig),
     Local,
 }
 
@@ -15,13 +22,14 @@ impl ProviderConfig {
     pub fn from_db(
         provider_type: db::storage_locations::Provider,
     ) -> Result<ProviderConfig, Error> {
-        match provider_type {
+        match provider_type.decrypt_credentials()? {
             db::storage_locations::Provider::S3 {
                 endpoint,
                 region,
                 access_key_id,
                 secret_key,
                 virtual_host_style,
+                skip_tls_verify,
             } => {
                 let uri = endpoint.map(|ep| ep.parse::<http::Uri>()).transpose()?;
 
@@ -31,8 +39,72 @@ impl ProviderConfig {
                     access_key_id,
                     secret_key,
                     virtual_host_style,
+                    skip_tls_verify: skip_tls_verify.unwrap_or(false),
                 }))
             }
+            db::storage_locations::Provider::Gcs {
+                service_account_key,
+            } => Ok(ProviderConfig::Gcs(GcsProviderConfig {
+                service_account_key,
+            })),
+            db::storage_locations::Provider::Azure {
+                account,
+                connection_string,
+                client_id,
+            } => Ok(ProviderConfig::Azure(AzureProviderConfig {
+                account,
+                connection_string,
+                client_id,
+            })),
+            db::storage_locations::Provider::B2 {
+                region,
+                key_id,
+                application_key,
+            } => {
+                if region.is_empty()
+                    || !region
+                        .bytes()
+                        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+                {
+                    return Err(Error::InvalidB2Region(region));
+                }
+
+                // B2 buckets work with the S3 provider, with the application key in place of
+                // the access key.
+                let endpoint = format!("https://s3.{region}.backblazeb2.com").parse()?;
+                Ok(ProviderConfig::S3(S3ProviderConfig {
+                    endpoint: Some(endpoint),
+                    region: Some(region),
+                    access_key_id: Some(key_id),
+                    secret_key: Some(application_key),
+                    virtual_host_style: Some(false),
+                    skip_tls_verify: false,
+                }))
+            }
+            db::storage_locations::Provider::WebDav {
+                url,
+                username,
+                password,
+            } => Ok(ProviderConfig::WebDav(WebDavProviderConfig {
+                url,
+                username,
+                password,
+            })),
+            db::storage_locations::Provider::Sftp {
+                host,
+                port,
+                username,
+                password,
+                private_key,
+                host_key_fingerprint,
+            } => Ok(ProviderConfig::Sftp(SftpProviderConfig {
+                host,
+                port,
+                username,
+                password,
+                private_key,
+                host_key_fingerprint,
+            })),
             db::storage_locations::Provider::Local => Ok(Self::Local),
         }
     }
@@ -41,6 +113,10 @@ impl ProviderConfig {
 #[derive(Debug)]
 pub enum Provider {
     S3 { config: S3ProviderConfig },
+    Gcs { config: GcsProviderConfig },
+    Azure { config: AzureProviderConfig },
+    WebDav { config: WebDavProviderConfig },
+    Sftp { config: SftpProviderConfig },
     Local,
 }
 
@@ -48,6 +124,10 @@ impl Provider {
     pub fn new(config: ProviderConfig) -> Self {
         match config {
             ProviderConfig::S3(config) => Provider::S3 { config },
+            ProviderConfig::Gcs(config) => Provider::Gcs { config },
+            ProviderConfig::Azure(config) => Provider::Azure { config },
+            ProviderConfig::WebDav(config) => Provider::WebDav { config },
+            ProviderConfig::Sftp(config) => Provider::Sftp { config },
             ProviderConfig::Local => Provider::Local,
         }
     }
@@ -58,14 +138,39 @@ impl Provider {
     }
 
     pub async fn create_operator(&self, base_location: &str) -> Result<Operator, eyre::Report> {
-        let (operator, supports_multipart, manual_prefix): (Box<dyn ObjectStore>, bool, &str) =
+        let mut signer: Option<Arc<dyn Signer>> = None;
+        let (operator, supports_multipart, manual_prefix): (Arc<dyn ObjectStore>, bool, &str) =
             match self {
                 Self::S3 { config, .. } => {
                     let (store, base_path) = crate::s3::create_store(config, base_location)?;
-                    (Box::new(store), true, base_path)
+                    let store = Arc::new(store);
+                    signer = Some(store.clone());
+                    (store, true, base_path)
+                }
+                Self::Gcs { config } => {
+                    let (store, base_path) = crate::gcs::create_store(config, base_location)?;
+                    (Arc::new(store), true, base_path)
+                }
+                Self::Azure { config } => {
+                    let (store, base_path) = crate::azure::create_store(config, base_location)?;
+                    (Arc::new(store), true, base_path)
+                }
+                Self::WebDav { config } => {
+                    // WebDAV has no multipart uploads, so files are uploaded in a single request.
+                    let (store, base_path) = crate::webdav::create_store(config, base_location)?;
+                    (Arc::new(store), false, base_path)
+                }
+                Self::Sftp { config } => {
+                    // The store adds the base location itself, and uploads each file in one
+                    // request since SFTP has no multipart uploads.
+                    let store = crate::sftp::create_store(config, base_location)?;
+                    (Arc::new(store), false, "")
                 }
                 Self::Local => {
                     let store = if !base_location.is_empty() {
+                        // A new location's directory may not exist yet, and it must exist
+                        // before the path can be canonicalized.
+                        tokio::fs::create_dir_all(base_location).await?;
                         let path = std::path::PathBuf::from(base_location);
                         if !path.is_absolute() {
                             let full_path = path.canonicalize()?;
@@ -77,7 +182,7 @@ impl Provider {
                         LocalFileSystem::new()
                     };
 
-                    (Box::new(store), false, "")
+                    (Arc::new(store), true, "")
                 }
             };
 
@@ -92,6 +197,7 @@ impl Provider {
             supports_multipart,
             base_location: base_location.to_string(),
             path_prefix,
+            signer,
         })
     }
 }
@@ -2,16 +2,28 @@ use http::{
     uri::{Authority, Scheme},
     Uri,
 };
-use object_store::aws::AmazonS3;
+use object_store::{aws::AmazonS3, ClientOptions};
 use tracing::{event, Level};
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct S3ProviderConfig {
     pub endpoint: Option<Uri>,
     pub region: Option<String>,
     pub access_key_id: Option<String>,
     pub secret_key: Option<String>,
     pub virtual_host_style: Option<bool>,
+    pub skip_tls_verify: bool,
+}
+
+impl std::fmt::Debug for S3ProviderConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3ProviderConfig")
+            .field("endpoint", &self.endpoint)
+            .field("region", &self.region)
+            .field("virtual_host_style", &self.virtual_host_style)
+            .field("skip_tls_verify", &self.skip_tls_verify)
+            .finish_non_exhaustive()
+    }
 }
 
 pub(crate) fn create_store<'a>(
@@ -19,6 +31,7 @@ pub(crate) fn create_store<'a>(
     base_location: &'a str,
 ) -> Result<(AmazonS3, &'a str), eyre::Report> {
     let virtual_host_style = config.virtual_host_style.unwrap_or(false);
+    let mut client_options = ClientOptions::new();
 
     if base_location.is_empty() {
         return Err(eyre::eyre!("base_location is required"));
@@ -50,6 +63,10 @@ pub(crate) fn create_store<'a>(
     if let Some(endpoint) = config.endpoint.as_ref() {
         event!(Level::DEBUG, ?endpoint);
         let needs_scheme = endpoint.scheme().is_none();
+        if endpoint.scheme() == Some(&Scheme::HTTP) {
+            // Local services like MinIO often don't use TLS.
+            client_options = client_options.with_allow_http(true);
+        }
 
         let e = if virtual_host_style {
             // When using virtual host style, object_store requires us to prepend the bucket name
@@ -77,7 +94,15 @@ pub(crate) fn create_store<'a>(
         builder = builder.with_region(region.as_str());
     }
 
-    let acc = builder.build()?;
+    if config.skip_tls_verify {
+        event!(
+            Level::WARN,
+            "Skipping TLS certificate verification for S3 provider"
+        );
+        client_options = client_options.with_allow_invalid_certificates(true);
+    }
+
+    let acc = builder.with_client_options(client_options).build()?;
 
     Ok((acc, base_path))
 }
@@ -0,0 +1,683 @@
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    net::TcpStream,
+    path::{Path as FsPath, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use base64::Engine;
+use bytes::Bytes;
+use chrono::{DateTime, TimeZone, Utc};
+use futures::{stream::BoxStream, StreamExt};
+use object_store::{
+    path::Path, GetOptions, GetResult, GetResultPayload, ListResult, MultipartId, ObjectMeta,
+    ObjectStore, PutMode, PutOptions, PutResult,
+};
+use ssh2::{ErrorCode, FileStat, HashType, OpenFlags, OpenType, Session, Sftp};
+use tokio::io::AsyncWrite;
+
+const STORE: &str = "SFTP";
+const DEFAULT_PORT: u16 = 22;
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+const FX_NO_SUCH_FILE: i32 = 2;
+const FX_FAILURE: i32 = 4;
+const FX_FILE_ALREADY_EXISTS: i32 = 11;
+
+#[derive(Clone)]
+pub struct SftpProviderConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub username: String,
+    pub password: Option<String>,
+    /// A private key in PEM format. It is used instead of the password when both are set.
+    pub private_key: Option<String>,
+    /// The SHA-256 fingerprint of the server's host key, as `ssh-keygen -l` prints it.
+    pub host_key_fingerprint: String,
+}
+
+impl std::fmt::Debug for SftpProviderConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SftpProviderConfig")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("username", &self.username)
+            .field("host_key_fingerprint", &self.host_key_fingerprint)
+            .finish_non_exhaustive()
+    }
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+fn generic(source: impl Into<BoxError>) -> object_store::Error {
+    object_store::Error::Generic {
+        store: STORE,
+        source: source.into(),
+    }
+}
+
+/// Convert an SFTP error into the matching object store error. Some servers report a generic
+/// failure instead of "already exists" for exclusive creates, so `exclusive` marks the calls
+/// where that is the likely meaning.
+fn map_error(error: ssh2::Error, path: &Path, exclusive: bool) -> object_store::Error {
+    match error.code() {
+        ErrorCode::SFTP(FX_NO_SUCH_FILE) => object_store::Error::NotFound {
+            path: path.to_string(),
+            source: Box::new(error),
+        },
+        ErrorCode::SFTP(FX_FILE_ALREADY_EXISTS) => object_store::Error::AlreadyExists {
+            path: path.to_string(),
+            source: Box::new(error),
+        },
+        ErrorCode::SFTP(FX_FAILURE) if exclusive => object_store::Error::AlreadyExists {
+            path: path.to_string(),
+            source: Box::new(error),
+        },
+        _ => generic(error),
+    }
+}
+
+/// Fingerprints are compared without the `SHA256:` prefix and base64 padding, so that they can
+/// be copied from `ssh-keygen -l` or from the known hosts prompt.
+fn normalize_fingerprint(fingerprint: &str) -> &str {
+    let fingerprint = fingerprint.trim();
+    fingerprint
+        .strip_prefix("SHA256:")
+        .unwrap_or(fingerprint)
+        .trim_end_matches('=')
+}
+
+fn check_host_key(hash: &[u8], expected: &str) -> Result<(), BoxError> {
+    let actual = base64::engine::general_purpose::STANDARD_NO_PAD.encode(hash);
+    if actual != normalize_fingerprint(expected) {
+        return Err(format!("host key fingerprint SHA256:{actual} does not match").into());
+    }
+
+    Ok(())
+}
+
+fn connect(config: &SftpProviderConfig) -> Result<Sftp, BoxError> {
+    let tcp = TcpStream::connect((config.host.as_str(), config.port.unwrap_or(DEFAULT_PORT)))?;
+    tcp.set_read_timeout(Some(TIMEOUT))?;
+    tcp.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut session = Session::new()?;
+    session.set_timeout(TIMEOUT.as_millis() as u32);
+    session.set_tcp_stream(tcp);
+    session.handshake()?;
+
+    let host_key = session
+        .host_key_hash(HashType::Sha256)
+        .ok_or("server did not send a host key")?;
+    check_host_key(host_key, &config.host_key_fingerprint)?;
+
+    match (&config.private_key, &config.password) {
+        (Some(key), _) => session.userauth_pubkey_memory(&config.username, None, key, None)?,
+        (None, Some(password)) => session.userauth_password(&config.username, password)?,
+        (None, None) => return Err("a password or private key is required".into()),
+    }
+
+    if !session.authenticated() {
+        return Err("authentication failed".into());
+    }
+
+    // The SFTP channel keeps the session alive.
+    Ok(session.sftp()?)
+}
+
+fn meta_from_stat(location: Path, stat: &FileStat) -> ObjectMeta {
+    let last_modified = stat
+        .mtime
+        .and_then(|mtime| Utc.timestamp_opt(mtime as i64, 0).single())
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let size = stat.size.unwrap_or(0) as usize;
+    ObjectMeta {
+        e_tag: Some(format!("{:x}-{size:x}", last_modified.timestamp())),
+        location,
+        last_modified,
+        size,
+        version: None,
+    }
+}
+
+fn read_range(sftp: &Sftp, path: &FsPath, start: usize, len: usize) -> Result<Vec<u8>, BoxError> {
+    let mut file = sftp.open(path)?;
+    file.seek(SeekFrom::Start(start as u64))?;
+    let mut data = Vec::with_capacity(len);
+    file.take(len as u64).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Create a directory and its parents, like `mkdir -p`.
+fn create_dir_all(sftp: &Sftp, dir: &FsPath) -> Result<(), ssh2::Error> {
+    if dir.as_os_str().is_empty() || sftp.stat(dir).map(|s| s.is_dir()).unwrap_or(false) {
+        return Ok(());
+    }
+
+    if let Some(parent) = dir.parent() {
+        create_dir_all(sftp, parent)?;
+    }
+
+    match sftp.mkdir(dir, 0o755) {
+        Ok(()) => Ok(()),
+        // Another upload may have created it in the meantime.
+        Err(_) if sftp.stat(dir).map(|s| s.is_dir()).unwrap_or(false) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_file(
+    sftp: &Sftp,
+    path: &FsPath,
+    location: &Path,
+    bytes: &[u8],
+    exclusive: bool,
+) -> object_store::Result<()> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(sftp, parent).map_err(generic)?;
+    }
+
+    let flags = if exclusive {
+        OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::EXCLUSIVE
+    } else {
+        OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE
+    };
+    let mut file = sftp
+        .open_mode(path, flags, 0o644, OpenType::File)
+        .map_err(|e| map_error(e, location, exclusive))?;
+    file.write_all(bytes).map_err(generic)?;
+    Ok(())
+}
+
+/// An object store on an SFTP server. libssh2 is blocking, so each operation runs on the
+/// blocking thread pool. The operations share one connection, which is opened when it is first
+/// needed and again after an error.
+pub(crate) struct SftpStore {
+    config: SftpProviderConfig,
+    /// The directory on the server that holds the location's files.
+    root: PathBuf,
+    connection: Arc<Mutex<Option<Sftp>>>,
+}
+
+impl SftpStore {
+    fn path(&self, location: &Path) -> PathBuf {
+        full_path(&self.root, location)
+    }
+
+    async fn run<T, F>(&self, op: F) -> object_store::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Sftp) -> object_store::Result<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        let config = self.config.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection.lock().unwrap_or_else(PoisonError::into_inner);
+            let sftp = match connection.take() {
+                Some(sftp) => sftp,
+                None => connect(&config).map_err(generic)?,
+            };
+
+            let result = op(&sftp);
+            // A generic error may mean that the connection was lost, so a new one is opened for
+            // the next operation.
+            if !matches!(result, Err(object_store::Error::Generic { .. })) {
+                *connection = Some(sftp);
+            }
+            result
+        })
+        .await?
+    }
+}
+
+fn full_path(root: &FsPath, location: &Path) -> PathBuf {
+    let mut path = root.to_path_buf();
+    path.extend(location.parts().map(|part| part.as_ref().to_string()));
+    path
+}
+
+/// Join an object path and a file name from a directory listing.
+fn child_location(parent: Option<&Path>, name: &str) -> Path {
+    match parent {
+        Some(parent) => parent.child(name),
+        None => Path::from(name),
+    }
+}
+
+fn list_dir(
+    sftp: &Sftp,
+    root: &FsPath,
+    prefix: Option<&Path>,
+) -> object_store::Result<Vec<(Path, FileStat)>> {
+    let dir = prefix
+        .map(|prefix| full_path(root, prefix))
+        .unwrap_or_else(|| root.to_path_buf());
+    let entries = match sftp.readdir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.code() == ErrorCode::SFTP(FX_NO_SUCH_FILE) => return Ok(Vec::new()),
+        Err(e) => return Err(generic(e)),
+    };
+
+    Ok(entries
+        .into_iter()
+        .filter_map(|(path, stat)| {
+            let name = path.file_name()?.to_str()?;
+            Some((child_location(prefix, name), stat))
+        })
+        .collect())
+}
+
+fn walk(
+    sftp: &Sftp,
+    root: &FsPath,
+    prefix: Option<&Path>,
+    output: &mut Vec<ObjectMeta>,
+) -> object_store::Result<()> {
+    for (location, stat) in list_dir(sftp, root, prefix)? {
+        if stat.is_dir() {
+            walk(sftp, root, Some(&location), output)?;
+        } else {
+            output.push(meta_from_stat(location, &stat));
+        }
+    }
+
+    Ok(())
+}
+
+impl std::fmt::Debug for SftpStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SftpStore")
+            .field("config", &self.config)
+            .field("root", &self.root)
+            .finish_non_exhaustive()
+    }
+}
+
+impl std::fmt::Display for SftpStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SftpStore({}:{}{})",
+            self.config.host,
+            self.config.port.unwrap_or(DEFAULT_PORT),
+            self.root.display()
+        )
+    }
+}
+
+#[async_trait]
+impl ObjectStore for SftpStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        opts: PutOptions,
+    ) -> object_store::Result<PutResult> {
+        let exclusive = match opts.mode {
+            PutMode::Overwrite => false,
+            PutMode::Create => true,
+            PutMode::Update(_) => return Err(object_store::Error::NotImplemented),
+        };
+
+        let path = self.path(location);
+        let location = location.clone();
+        self.run(move |sftp| {
+            write_file(sftp, &path, &location, &bytes, exclusive)?;
+            let stat = sftp
+                .stat(&path)
+                .map_err(|e| map_error(e, &location, false))?;
+            Ok(PutResult {
+                e_tag: meta_from_stat(location, &stat).e_tag,
+                version: None,
+            })
+        })
+        .await
+    }
+
+    /// SFTP has no multipart uploads, so files are uploaded in a single request.
+    async fn put_multipart(
+        &self,
+        _location: &Path,
+    ) -> object_store::Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        Err(object_store::Error::NotImplemented)
+    }
+
+    async fn abort_multipart(
+        &self,
+        _location: &Path,
+        _multipart_id: &MultipartId,
+    ) -> object_store::Result<()> {
+        Ok(())
+    }
+
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        if options.if_match.is_some()
+            || options.if_none_match.is_some()
+            || options.if_modified_since.is_some()
+            || options.if_unmodified_since.is_some()
+            || options.version.is_some()
+        {
+            return Err(object_store::Error::NotSupported {
+                source: "SFTP does not support conditional reads".into(),
+            });
+        }
+
+        let path = self.path(location);
+        let location = location.clone();
+        let (meta, range, data) = self
+            .run(move |sftp| {
+                let stat = sftp
+                    .stat(&path)
+                    .map_err(|e| map_error(e, &location, false))?;
+                if stat.is_dir() {
+                    return Err(object_store::Error::NotFound {
+                        path: location.to_string(),
+                        source: "path is a directory".into(),
+                    });
+                }
+
+                let meta = meta_from_stat(location, &stat);
+                let range = options.range.unwrap_or(0..meta.size);
+                let range = range.start.min(meta.size)..range.end.min(meta.size);
+                let data = if options.head {
+                    Vec::new()
+                } else {
+                    read_range(sftp, &path, range.start, range.len()).map_err(generic)?
+                };
+                Ok((meta, range, data))
+            })
+            .await?;
+
+        let data = Bytes::from(data);
+        Ok(GetResult {
+            payload: GetResultPayload::Stream(
+                futures::stream::once(async move { Ok(data) }).boxed(),
+            ),
+            meta,
+            range,
+        })
+    }
+
+    async fn delete(&self, location: &Path) -> object_store::Result<()> {
+        let path = self.path(location);
+        let location = location.clone();
+        self.run(move |sftp| {
+            sftp.unlink(&path)
+                .map_err(|e| map_error(e, &location, false))
+        })
+        .await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+        let root = self.root.clone();
+        let prefix = prefix.cloned();
+        futures::stream::once(self.run(move |sftp| {
+            let mut objects = Vec::new();
+            walk(sftp, &root, prefix.as_ref(), &mut objects)?;
+            Ok(objects)
+        }))
+        .map(|result| match result {
+            Ok(objects) => futures::stream::iter(objects.into_iter().map(Ok)).boxed(),
+            Err(e) => futures::stream::once(async move { Err(e) }).boxed(),
+        })
+        .flatten()
+        .boxed()
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+        let root = self.root.clone();
+        let prefix = prefix.cloned();
+        self.run(move |sftp| {
+            let mut result = ListResult {
+                common_prefixes: Vec::new(),
+                objects: Vec::new(),
+            };
+            for (location, stat) in list_dir(sftp, &root, prefix.as_ref())? {
+                if stat.is_dir() {
+                    result.common_prefixes.push(location);
+                } else {
+                    result.objects.push(meta_from_stat(location, &stat));
+                }
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    /// SFTP can't copy files on the server, so the file is downloaded and uploaded again.
+    async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.copy_file(from, to, false).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.copy_file(from, to, true).await
+    }
+}
+
+impl SftpStore {
+    async fn copy_file(&self, from: &Path, to: &Path, exclusive: bool) -> object_store::Result<()> {
+        let from_path = self.path(from);
+        let to_path = self.path(to);
+        let from = from.clone();
+        let to = to.clone();
+        self.run(move |sftp| {
+            let mut data = Vec::new();
+            sftp.open(&from_path)
+                .map_err(|e| map_error(e, &from, false))?
+                .read_to_end(&mut data)
+                .map_err(generic)?;
+            write_file(sftp, &to_path, &to, &data, exclusive)
+        })
+        .await
+    }
+}
+
+pub(crate) fn create_store(
+    config: &SftpProviderConfig,
+    base_location: &str,
+) -> Result<SftpStore, eyre::Report> {
+    if config.private_key.is_none() && config.password.is_none() {
+        return Err(eyre::eyre!("SFTP requires a password or private key"));
+    }
+
+    if normalize_fingerprint(&config.host_key_fingerprint).is_empty() {
+        return Err(eyre::eyre!(
+            "SFTP requires the server's host key fingerprint"
+        ));
+    }
+
+    // Relative base locations are inside the user's home directory.
+    let root = base_location.trim_end_matches('/');
+    Ok(SftpStore {
+        config: config.clone(),
+        root: PathBuf::from(root),
+        connection: Arc::new(Mutex::new(None)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    fn config() -> SftpProviderConfig {
+        SftpProviderConfig {
+            host: "sftp.example.com".to_string(),
+            port: None,
+            username: "images".to_string(),
+            password: Some("secret".to_string()),
+            private_key: None,
+            host_key_fingerprint: "SHA256:abc".to_string(),
+        }
+    }
+
+    #[test]
+    fn fingerprints() {
+        let hash = [1u8, 2, 3, 4, 5];
+        let encoded = base64::engine::general_purpose::STANDARD_NO_PAD.encode(hash);
+
+        check_host_key(&hash, &encoded).unwrap();
+        check_host_key(&hash, &format!("SHA256:{encoded}")).unwrap();
+        check_host_key(&hash, &format!(" SHA256:{encoded}= \n")).unwrap();
+        check_host_key(&hash, "SHA256:AQIDBAY").unwrap_err();
+    }
+
+    #[test]
+    fn paths() {
+        let location = Path::from("outputs/abc/image.webp");
+        assert_eq!(
+            full_path(FsPath::new("/srv/www"), &location),
+            PathBuf::from("/srv/www/outputs/abc/image.webp")
+        );
+        assert_eq!(
+            full_path(FsPath::new("public_html"), &location),
+            PathBuf::from("public_html/outputs/abc/image.webp")
+        );
+        assert_eq!(
+            full_path(FsPath::new(""), &location),
+            PathBuf::from("outputs/abc/image.webp")
+        );
+
+        assert_eq!(
+            child_location(Some(&Path::from("outputs")), "a.png"),
+            Path::from("outputs/a.png")
+        );
+        assert_eq!(child_location(None, "a.png"), Path::from("a.png"));
+    }
+
+    #[test]
+    fn errors() {
+        let location = Path::from("a.png");
+        let error = |code| ssh2::Error::new(ErrorCode::SFTP(code), "error");
+
+        assert!(matches!(
+            map_error(error(FX_NO_SUCH_FILE), &location, false),
+            object_store::Error::NotFound { .. }
+        ));
+        assert!(matches!(
+            map_error(error(FX_FILE_ALREADY_EXISTS), &location, false),
+            object_store::Error::AlreadyExists { .. }
+        ));
+        assert!(matches!(
+            map_error(error(FX_FAILURE), &location, true),
+            object_store::Error::AlreadyExists { .. }
+        ));
+        assert!(matches!(
+            map_error(error(FX_FAILURE), &location, false),
+            object_store::Error::Generic { .. }
+        ));
+    }
+
+    #[test]
+    fn metadata() {
+        let stat = FileStat {
+            size: Some(1234),
+            uid: None,
+            gid: None,
+            perm: Some(0o100644),
+            atime: None,
+            mtime: Some(1_700_000_000),
+        };
+        let meta = meta_from_stat(Path::from("a.png"), &stat);
+        assert_eq!(meta.size, 1234);
+        assert_eq!(meta.last_modified.timestamp(), 1_700_000_000);
+        assert_eq!(meta.e_tag.as_deref(), Some("6553f100-4d2"));
+    }
+
+    #[test]
+    fn store_config() {
+        let store = create_store(&config(), "/srv/www/").unwrap();
+        assert_eq!(store.root, PathBuf::from("/srv/www"));
+        assert_eq!(store.to_string(), "SftpStore(sftp.example.com:22/srv/www)");
+        assert!(!format!("{store:?}").contains("secret"));
+
+        let no_auth = SftpProviderConfig {
+            password: None,
+            ..config()
+        };
+        assert!(create_store(&no_auth, "").is_err());
+
+        let no_fingerprint = SftpProviderConfig {
+            host_key_fingerprint: "SHA256:".to_string(),
+            ..config()
+        };
+        assert!(create_store(&no_fingerprint, "").is_err());
+    }
+
+    /// Run the store against a real server when `TEST_SFTP_HOST` is set. The other settings
+    /// come from `TEST_SFTP_PORT`, `TEST_SFTP_USER`, `TEST_SFTP_PASSWORD`,
+    /// `TEST_SFTP_HOST_KEY`, and `TEST_SFTP_ROOT`.
+    #[tokio::test]
+    async fn server() {
+        let Ok(host) = std::env::var("TEST_SFTP_HOST") else {
+            return;
+        };
+        let env = |name: &str| std::env::var(name).ok();
+        let config = SftpProviderConfig {
+            host,
+            port: env("TEST_SFTP_PORT").and_then(|port| port.parse().ok()),
+            username: env("TEST_SFTP_USER").unwrap_or_else(|| "test".to_string()),
+            password: env("TEST_SFTP_PASSWORD"),
+            private_key: None,
+            host_key_fingerprint: env("TEST_SFTP_HOST_KEY").unwrap_or_default(),
+        };
+        let root = format!(
+            "{}/pic-store-test-{}",
+            env("TEST_SFTP_ROOT").unwrap_or_else(|| "upload".to_string()),
+            Utc::now().timestamp_millis()
+        );
+        let store = create_store(&config, &root).unwrap();
+
+        let location = Path::from("a/b/image.txt");
+        store
+            .put(&location, Bytes::from_static(b"hello world"))
+            .await
+            .unwrap();
+
+        let result = store.get(&location).await.unwrap();
+        assert_eq!(result.meta.size, 11);
+        assert_eq!(result.bytes().await.unwrap().as_ref(), b"hello world");
+        assert_eq!(
+            store.get_range(&location, 6..11).await.unwrap().as_ref(),
+            b"world"
+        );
+
+        let create = store
+            .put_opts(&location, Bytes::from_static(b"x"), PutMode::Create.into())
+            .await;
+        assert!(matches!(
+            create,
+            Err(object_store::Error::AlreadyExists { .. })
+        ));
+
+        let copy = Path::from("c.txt");
+        store.copy(&location, &copy).await.unwrap();
+
+        let mut listed = store
+            .list(None)
+            .map_ok(|meta| meta.location.to_string())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        listed.sort();
+        assert_eq!(listed, vec!["a/b/image.txt", "c.txt"]);
+
+        let top = store.list_with_delimiter(None).await.unwrap();
+        assert_eq!(top.common_prefixes, vec![Path::from("a")]);
+        assert_eq!(top.objects.len(), 1);
+
+        store.delete(&location).await.unwrap();
+        store.delete(&copy).await.unwrap();
+        assert!(matches!(
+            store.head(&location).await,
+            Err(object_store::Error::NotFound { .. })
+        ));
+    }
+}
@@ -0,0 +1,117 @@
+//! Streaming uploads of large files.
+
+use std::sync::Arc;
+
+use bytes::BytesMut;
+use object_store::{path::Path, MultipartId, ObjectStore};
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
+use tracing::{event, Level};
+
+use crate::error::{Error, Result};
+
+/// S3 rejects parts smaller than this, except for the last one.
+pub const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+enum UploadState {
+    Multipart {
+        id: MultipartId,
+        writer: BufWriter<Box<dyn AsyncWrite + Unpin + Send>>,
+    },
+    /// Stores without multipart uploads get the whole file at the end.
+    Buffered(BytesMut),
+    Done,
+}
+
+/// A file being uploaded in parts. Multipart uploads that are dropped before `finish` is called
+/// are aborted in the background, so that incomplete uploads don't keep using storage.
+pub struct Upload {
+    store: Arc<dyn ObjectStore>,
+    path: Path,
+    state: UploadState,
+}
+
+impl Upload {
+    pub(crate) async fn new(
+        store: Arc<dyn ObjectStore>,
+        path: Path,
+        supports_multipart: bool,
+        part_size: usize,
+    ) -> Result<Self> {
+        let state = if supports_multipart {
+            let (id, writer) = store.put_multipart(&path).await?;
+            UploadState::Multipart {
+                id,
+                writer: BufWriter::with_capacity(part_size.max(MIN_PART_SIZE), writer),
+            }
+        } else {
+            UploadState::Buffered(BytesMut::new())
+        };
+
+        Ok(Upload { store, path, state })
+    }
+
+    pub async fn write(&mut self, data: &[u8]) -> Result<()> {
+        match &mut self.state {
+            UploadState::Multipart { writer, .. } => writer.write_all(data).await?,
+            UploadState::Buffered(buffer) => buffer.extend_from_slice(data),
+            UploadState::Done => {}
+        }
+
+        Ok(())
+    }
+
+    /// Upload the rest of the file and complete the upload.
+    pub async fn finish(mut self) -> Result<()> {
+        match std::mem::replace(&mut self.state, UploadState::Done) {
+            UploadState::Multipart { id, mut writer } => {
+                if let Err(e) = writer.shutdown().await {
+                    self.store.abort_multipart(&self.path, &id).await.ok();
+                    return Err(e.into());
+                }
+            }
+            UploadState::Buffered(buffer) => {
+                self.store.put(&self.path, buffer.freeze()).await?;
+            }
+            UploadState::Done => {}
+        }
+
+        Ok(())
+    }
+
+    /// Cancel the upload and remove any parts that were already uploaded.
+    pub async fn abort(mut self) -> Result<()> {
+        if let UploadState::Multipart { id, .. } =
+            std::mem::replace(&mut self.state, UploadState::Done)
+        {
+            self.store
+                .abort_multipart(&self.path, &id)
+                .await
+                .map_err(Error::from)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        let UploadState::Multipart { id, .. } =
+            std::mem::replace(&mut self.state, UploadState::Done)
+        else {
+            return;
+        };
+
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            event!(Level::WARN, path=%self.path, "Could not abort incomplete upload");
+            return;
+        };
+
+        let store = self.store.clone();
+        let path = self.path.clone();
+        runtime.spawn(async move {
+            if let Err(e) = store.abort_multipart(&path, &id).await {
+                event!(Level::WARN, %path, error=?e, "Failed to abort incomplete upload");
+            }
+        });
+    }
+}
@@ -0,0 +1,63 @@
+use base64::Engine;
+use http::{header::AUTHORIZATION, HeaderMap, HeaderValue};
+use object_store::{
+    http::{HttpBuilder, HttpStore},
+    ClientOptions,
+};
+
+#[derive(Clone)]
+pub struct WebDavProviderConfig {
+    /// The root URL of the WebDAV server.
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl std::fmt::Debug for WebDavProviderConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebDavProviderConfig")
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+fn basic_auth(username: &str, password: &str) -> Result<HeaderValue, eyre::Report> {
+    let credentials = base64::engine::general_purpose::STANDARD
+        .encode(format!("{username}:{password}").as_bytes());
+    let mut value = HeaderValue::from_str(&format!("Basic {credentials}"))?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+pub(crate) fn create_store<'a>(
+    config: &WebDavProviderConfig,
+    base_location: &'a str,
+) -> Result<(HttpStore, &'a str), eyre::Report> {
+    let mut client_options = ClientOptions::new();
+
+    if config.url.starts_with("http://") {
+        client_options = client_options.with_allow_http(true);
+    }
+
+    match (config.username.as_deref(), config.password.as_deref()) {
+        (Some(username), password) => {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                AUTHORIZATION,
+                basic_auth(username, password.unwrap_or_default())?,
+            );
+            client_options = client_options.with_default_headers(headers);
+        }
+        (None, Some(_)) => return Err(eyre::eyre!("password requires a username")),
+        (None, None) => {}
+    }
+
+    let store = HttpBuilder::new()
+        .with_url(config.url.as_str())
+        .with_client_options(client_options)
+        .build()?;
+
+    // The server's URL is the root of the store, so the base location is just a path inside it.
+    Ok((store, base_location.trim_matches('/')))
+}